@@ -3,6 +3,7 @@ mod utils;
 
 use crate::tests::lifecycle::{ContainerCreate, ContainerLifecycle};
 use crate::tests::linux_ns_itype::get_ns_itype_tests;
+use crate::tests::mounts_recursive::get_mounts_recursive_test;
 use crate::tests::pidfile::get_pidfile_test;
 use crate::tests::seccomp_notify::get_seccomp_notify_test;
 use crate::tests::tlb::get_tlb_test;
@@ -72,6 +73,7 @@ fn main() -> Result<()> {
     let huge_tlb = get_tlb_test();
     let pidfile = get_pidfile_test();
     let ns_itype = get_ns_itype_tests();
+    let mounts_recursive = get_mounts_recursive_test();
     let cgroup_v1_pids = cgroups::pids::get_test_group();
     let cgroup_v1_cpu = cgroups::cpu::v1::get_test_group();
     let cgroup_v2_cpu = cgroups::cpu::v2::get_test_group();
@@ -85,6 +87,7 @@ fn main() -> Result<()> {
     tm.add_test_group(&huge_tlb);
     tm.add_test_group(&pidfile);
     tm.add_test_group(&ns_itype);
+    tm.add_test_group(&mounts_recursive);
     tm.add_test_group(&cgroup_v1_pids);
     tm.add_test_group(&cgroup_v1_cpu);
     tm.add_test_group(&cgroup_v2_cpu);
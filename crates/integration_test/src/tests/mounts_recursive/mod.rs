@@ -0,0 +1,173 @@
+use crate::utils::{create_temp_dir, generate_uuid, test_outside_container, TempDir};
+use anyhow::{anyhow, bail, Context, Result};
+use nix::mount::{mount, umount2, MntFlags, MsFlags};
+use oci_spec::runtime::{Mount, MountBuilder, Spec, SpecBuilder};
+use procfs::process::{MountInfo, Process};
+use std::fs;
+use std::path::{Path, PathBuf};
+use test_framework::{Test, TestGroup, TestResult};
+
+/// A tmpfs on the host with another tmpfs mounted in its nested directory
+struct NestedMounts {
+    dir: TempDir,
+}
+
+impl NestedMounts {
+    fn new() -> Result<Self> {
+        let dir = create_temp_dir(&generate_uuid())?;
+        mount_tmpfs(dir.path())?;
+        let nested = dir.path().join("nested");
+        fs::create_dir(&nested)?;
+        mount_tmpfs(&nested)?;
+        fs::write(nested.join("file"), "nested")?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self) -> &Path {
+        self.dir.path()
+    }
+}
+
+impl Drop for NestedMounts {
+    fn drop(&mut self) {
+        let _ = umount2(&self.path().join("nested"), MntFlags::MNT_DETACH);
+        let _ = umount2(self.path(), MntFlags::MNT_DETACH);
+    }
+}
+
+fn mount_tmpfs(path: &Path) -> Result<()> {
+    mount(
+        Some("tmpfs"),
+        path,
+        Some("tmpfs"),
+        MsFlags::empty(),
+        None::<&str>,
+    )
+    .with_context(|| format!("failed to mount tmpfs at {:?}", path))
+}
+
+fn bind_mount(source: &Path, destination: &str, options: &[&str]) -> Mount {
+    MountBuilder::default()
+        .destination(PathBuf::from(destination))
+        .typ("bind")
+        .source(source)
+        .options(options.iter().map(|o| o.to_string()).collect::<Vec<_>>())
+        .build()
+        .unwrap()
+}
+
+fn get_spec(mounts: Vec<Mount>) -> Spec {
+    let mut spec = SpecBuilder::default().build().unwrap();
+    let mut all_mounts = spec.mounts().clone().unwrap_or_default();
+    all_mounts.extend(mounts);
+    spec.set_mounts(Some(all_mounts));
+    spec
+}
+
+fn find_mount<'a>(mounts: &'a [MountInfo], mount_point: &str) -> Option<&'a MountInfo> {
+    mounts
+        .iter()
+        .find(|m| m.mount_point == Path::new(mount_point))
+}
+
+fn is_read_only(mount: &MountInfo) -> bool {
+    mount.mount_options.contains_key("ro")
+}
+
+/// Runs the check against the mount tree seen by the created container
+fn test_mount_tree(mounts: Vec<Mount>, check: fn(&[MountInfo]) -> Result<()>) -> TestResult {
+    test_outside_container(get_spec(mounts), &move |data| {
+        let pid = match data.state.and_then(|s| s.pid) {
+            Some(pid) => pid,
+            None => return TestResult::Failed(anyhow!("container has no pid")),
+        };
+
+        let mounts = match Process::new(pid).and_then(|p| p.mountinfo()) {
+            Ok(mounts) => mounts,
+            Err(e) => return TestResult::Failed(anyhow!("failed to read mountinfo: {}", e)),
+        };
+
+        match check(&mounts) {
+            Ok(()) => TestResult::Passed,
+            Err(e) => TestResult::Failed(e),
+        }
+    })
+}
+
+fn with_nested_mounts(test: fn(&Path) -> TestResult) -> TestResult {
+    match NestedMounts::new() {
+        Ok(host) => test(host.path()),
+        Err(e) => TestResult::Failed(e),
+    }
+}
+
+// rbind carries the nested mount into the container, bind does not
+fn check_rbind(source: &Path) -> TestResult {
+    let mounts = vec![
+        bind_mount(source, "/rbind", &["rbind"]),
+        bind_mount(source, "/bind", &["bind"]),
+    ];
+    test_mount_tree(mounts, |mounts| {
+        if find_mount(mounts, "/rbind").is_none() {
+            bail!("/rbind is not mounted");
+        }
+        if find_mount(mounts, "/rbind/nested").is_none() {
+            bail!("submount /rbind/nested has not been carried over by rbind");
+        }
+        if find_mount(mounts, "/bind/nested").is_some() {
+            bail!("submount /bind/nested has been carried over by a non recursive bind");
+        }
+        Ok(())
+    })
+}
+
+// ro only applies to the top mount, like in runc
+fn check_rbind_ro(source: &Path) -> TestResult {
+    let mounts = vec![bind_mount(source, "/data", &["rbind", "ro"])];
+    test_mount_tree(mounts, |mounts| {
+        let top = find_mount(mounts, "/data").context("/data is not mounted")?;
+        let nested = find_mount(mounts, "/data/nested").context("/data/nested is not mounted")?;
+        if !is_read_only(top) {
+            bail!("/data is not read-only");
+        }
+        if is_read_only(nested) {
+            bail!("ro has been applied to the submount /data/nested");
+        }
+        Ok(())
+    })
+}
+
+// rro applies to the whole mount tree
+fn check_rbind_rro(source: &Path) -> TestResult {
+    let mounts = vec![bind_mount(source, "/data", &["rbind", "rro", "rnosuid"])];
+    test_mount_tree(mounts, |mounts| {
+        for mount_point in ["/data", "/data/nested"] {
+            let mount = find_mount(mounts, mount_point)
+                .with_context(|| format!("{} is not mounted", mount_point))?;
+            if !is_read_only(mount) {
+                bail!("{} is not read-only", mount_point);
+            }
+            if !mount.mount_options.contains_key("nosuid") {
+                bail!("{} is not nosuid", mount_point);
+            }
+        }
+        Ok(())
+    })
+}
+
+pub fn get_mounts_recursive_test<'a>() -> TestGroup<'a> {
+    let rbind = Test::new("rbind", Box::new(|| with_nested_mounts(check_rbind)));
+    let rbind_ro = Test::new("rbind_ro", Box::new(|| with_nested_mounts(check_rbind_ro)));
+    let rbind_rro = Test::new(
+        "rbind_rro",
+        Box::new(|| with_nested_mounts(check_rbind_rro)),
+    );
+
+    let mut tg = TestGroup::new("mounts_recursive");
+    tg.add(vec![
+        Box::new(rbind),
+        Box::new(rbind_ro),
+        Box::new(rbind_rro),
+    ]);
+    tg
+}
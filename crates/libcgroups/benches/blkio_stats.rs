@@ -0,0 +1,28 @@
+//! Benchmarks the parsing of blkio statistics on a host with many block
+//! devices, e.g. a CI machine with hundreds of loop devices
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use libcgroups::stats::parse_blkio_data;
+use std::path::Path;
+
+const DEVICES: u64 = 500;
+
+fn blkio_data() -> String {
+    let mut data = String::new();
+    for minor in 0..DEVICES {
+        for op in ["Read", "Write", "Sync", "Async", "Discard", "Total"] {
+            data.push_str(&format!("7:{} {} {}\n", minor, op, minor * 4096));
+        }
+    }
+    data.push_str("Total 0\n");
+    data
+}
+
+fn bench_parse_blkio_data(c: &mut Criterion) {
+    let data = blkio_data();
+    c.bench_function("parse_blkio_data 500 devices", |b| {
+        b.iter(|| parse_blkio_data(Path::new("blkio.io_serviced"), black_box(&data)).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_parse_blkio_data);
+criterion_main!(benches);
@@ -0,0 +1,7 @@
+//! Run with `cargo +nightly fuzz run mountinfo` from crates/libcgroups
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = libcgroups::mountinfo::parse_mountinfo(data);
+});
@@ -1,4 +1,6 @@
 use std::{
+    borrow::Cow,
+    collections::HashMap,
     fmt::{Debug, Display},
     fs::{self, File},
     io::{BufRead, BufReader, Write},
@@ -8,14 +10,22 @@ use std::{
 
 use anyhow::{bail, Context, Result};
 use nix::{
-    sys::statfs::{statfs, CGROUP2_SUPER_MAGIC, TMPFS_MAGIC},
+    errno::Errno,
+    sys::{
+        signal::{self, Signal},
+        statfs::{statfs, CGROUP2_SUPER_MAGIC, TMPFS_MAGIC},
+    },
     unistd::Pid,
 };
+use serde::{Deserialize, Serialize};
+
 use oci_spec::runtime::{
     LinuxDevice, LinuxDeviceBuilder, LinuxDeviceCgroup, LinuxDeviceCgroupBuilder, LinuxDeviceType,
-    LinuxResources,
+    LinuxRdma, LinuxResources,
 };
 
+#[cfg(all(feature = "v1", feature = "v2"))]
+use super::hybrid;
 #[cfg(feature = "systemd")]
 use super::systemd;
 #[cfg(feature = "v1")]
@@ -23,29 +33,117 @@ use super::v1;
 #[cfg(feature = "v2")]
 use super::v2;
 
-use super::stats::Stats;
+use super::stats::{Stats, StatsFilter};
+use super::{batch, dry_run, freezer};
 
 pub const CGROUP_PROCS: &str = "cgroup.procs";
 pub const DEFAULT_CGROUP_ROOT: &str = "/sys/fs/cgroup";
+/// Mount point of the unified hierarchy on hybrid hosts
+pub const UNIFIED_MOUNT_POINT: &str = "/sys/fs/cgroup/unified";
 
 pub trait CgroupManager {
     /// Adds a task specified by its pid to the cgroup
     fn add_task(&self, pid: Pid) -> Result<()>;
 
+    /// Creates the cgroup without adding a task to it and returns its path,
+    /// so that a process can be spawned into it directly with clone3(2) and
+    /// CLONE_INTO_CGROUP instead of being added afterwards. Returns None if
+    /// the tasks have to be added with `add_task`, e.g. because the cgroup
+    /// spans several hierarchies or is created by systemd.
+    fn prepare_cgroup(&self) -> Result<Option<PathBuf>> {
+        Ok(None)
+    }
+
     /// Applies resource restrictions to the cgroup
     fn apply(&self, controller_opt: &ControllerOpt) -> Result<()>;
 
-    /// Removes the cgroup
+    /// Removes the cgroup together with the child cgroups created by the
+    /// workload. Removal is retried with a growing delay while the kernel
+    /// reports the cgroup as busy, and processes that are still in the
+    /// cgroup are moved to its parent in between.
     fn remove(&self) -> Result<()>;
 
     // Sets the freezer cgroup to the specified state
     fn freeze(&self, state: FreezerState) -> Result<()>;
 
     /// Retrieve statistics for the cgroup
-    fn stats(&self) -> Result<Stats>;
+    fn stats(&self) -> Result<Stats> {
+        self.stats_with(&StatsFilter::default())
+    }
+
+    /// Retrieve the statistics for the cgroup that are selected by the filter
+    fn stats_with(&self, filter: &StatsFilter) -> Result<Stats>;
+
+    /// Retrieve statistics for the cgroup, including the usage of child
+    /// cgroups, e.g. created by the workload, for counters that do not
+    /// include it. All counters of cgroup v2 are hierarchical, so by default
+    /// these are the same as the statistics for the cgroup.
+    fn stats_recursive(&self) -> Result<Stats> {
+        self.stats()
+    }
+
+    /// Returns the paths of the cgroup, keyed by the subsystem or named
+    /// hierarchy on v1 and by "unified" on v2. Managers that do not know the
+    /// paths of their cgroup return none by default.
+    fn get_cgroup_paths(&self) -> HashMap<String, PathBuf> {
+        HashMap::new()
+    }
 
     // Gets the PIDs inside the cgroup
     fn get_all_pids(&self) -> Result<Vec<Pid>>;
+
+    /// Sends the signal to all processes of the cgroup. By default the
+    /// cgroup is frozen while the signal is sent, so that processes which
+    /// are forked in between do not escape it.
+    fn kill_all(&self, signal: Signal) -> Result<()> {
+        signal_frozen(self, signal)
+    }
+}
+
+/// Sends the signal to each process of the cgroup while the cgroup is
+/// frozen. If the cgroup cannot be frozen, e.g. because the freezer
+/// controller is not available, the signal is sent nevertheless. The cgroup
+/// is only thawed again if it was frozen for sending the signal, so that a
+/// paused container stays paused. SIGKILL is the exception: frozen tasks do
+/// not act on it on cgroup v1, so the cgroup is always thawed afterwards.
+pub fn signal_frozen<M: CgroupManager + ?Sized>(manager: &M, signal: Signal) -> Result<()> {
+    let already_frozen = manager
+        .get_cgroup_paths()
+        .iter()
+        .filter(|(name, _)| matches!(name.as_str(), "freezer" | "unified"))
+        .any(|(_, path)| freezer::is_frozen(path).unwrap_or(false));
+
+    let frozen = if already_frozen {
+        false
+    } else {
+        match manager.freeze(FreezerState::Frozen) {
+            Ok(()) => true,
+            Err(err) => {
+                log::warn!(
+                    "failed to freeze cgroup before sending {}: {:?}",
+                    signal,
+                    err
+                );
+                false
+            }
+        }
+    };
+
+    let result = manager.get_all_pids().and_then(|pids| {
+        for pid in pids {
+            match signal::kill(pid, signal) {
+                // the process has exited in between
+                Ok(()) | Err(Errno::ESRCH) => {}
+                Err(err) => bail!("failed to send {} to {}: {}", signal, pid, err),
+            }
+        }
+        Ok(())
+    });
+
+    if frozen || (already_frozen && signal == Signal::SIGKILL) {
+        manager.freeze(FreezerState::Thawed)?;
+    }
+    result
 }
 
 #[derive(Debug)]
@@ -68,12 +166,16 @@ impl Display for CgroupSetup {
 }
 
 /// FreezerState is given freezer contoller
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum FreezerState {
     /// Tasks in cgroup are undefined
     Undefined,
     /// Tasks in cgroup are suspended.
     Frozen,
+    /// Tasks in cgroup are being suspended. This is only reported by the
+    /// freezer and cannot be applied.
+    Freezing,
     /// Tasks in cgroup are resuming.
     Thawed,
 }
@@ -91,31 +193,50 @@ pub struct ControllerOpt<'a> {
     pub freezer_state: Option<FreezerState>,
 }
 
+/// Writes the data to the cgroup file. Within a batch, a failure is reported
+/// by `batch::batched` instead, see the `batch` module.
 #[inline]
 pub fn write_cgroup_file_str<P: AsRef<Path>>(path: P, data: &str) -> Result<()> {
-    fs::OpenOptions::new()
-        .create(false)
-        .write(true)
-        .truncate(false)
-        .open(path.as_ref())
-        .with_context(|| format!("failed to open {:?}", path.as_ref()))?
-        .write_all(data.as_bytes())
-        .with_context(|| format!("failed to write {} to {:?}", data, path.as_ref()))?;
+    if dry_run::record_file(path.as_ref(), data) || batch::write(path.as_ref(), data) {
+        return Ok(());
+    }
 
-    Ok(())
+    write_file(path.as_ref(), data)
 }
 
 #[inline]
 pub fn write_cgroup_file<P: AsRef<Path>, T: ToString>(path: P, data: T) -> Result<()> {
     let data = data.to_string();
+    if dry_run::record_file(path.as_ref(), &data) || batch::write(path.as_ref(), &data) {
+        return Ok(());
+    }
+
+    write_file(path.as_ref(), &data)
+}
+
+/// Writes the data immediately, also while a batch is active, so that the
+/// caller gets the result of the write. A batch only reports failures once it
+/// is done, so writes whose result decides what is done next, e.g. enabling
+/// a controller in cgroup.subtree_control or changing the freezer state, are
+/// made with this function.
+pub fn write_cgroup_file_unbatched<P: AsRef<Path>, T: ToString>(path: P, data: T) -> Result<()> {
+    let data = data.to_string();
+    if dry_run::record_file(path.as_ref(), &data) {
+        return Ok(());
+    }
+
+    write_file(path.as_ref(), &data)
+}
+
+fn write_file(path: &Path, data: &str) -> Result<()> {
     fs::OpenOptions::new()
         .create(false)
         .write(true)
         .truncate(false)
-        .open(path.as_ref())
-        .with_context(|| format!("failed to open {:?}", path.as_ref()))?
+        .open(path)
+        .with_context(|| format!("failed to open {:?}", path))?
         .write_all(data.as_bytes())
-        .with_context(|| format!("failed to write {} to {:?}", data, path.as_ref()))?;
+        .with_context(|| format!("failed to write {} to {:?}", data, path))?;
 
     Ok(())
 }
@@ -126,14 +247,56 @@ pub fn read_cgroup_file<P: AsRef<Path>>(path: P) -> Result<String> {
     fs::read_to_string(path).with_context(|| format!("failed to open {:?}", path))
 }
 
+/// How the resources of the spec depend on a controller. Before the
+/// resources are applied, the managers skip the resources of controllers that
+/// are not available, with a warning if they are configured, and only fail if
+/// they are required, so that minimal systems can run containers that do not
+/// use e.g. the hugetlb or rdma controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControllerUsage {
+    /// The resources do not mention the controller
+    Unused,
+    /// The resources mention the controller, but do not restrict anything,
+    /// e.g. an empty list of hugepage limits or a pids limit of 0
+    Configured,
+    /// The resources restrict something that only the controller can enforce
+    Required,
+}
+
+/// Skips the resources of a controller that is not available on this system,
+/// with a warning if the resources mention the controller, or fails if the
+/// resources require the controller
+pub(crate) fn skip_unavailable_controller<C: Display>(
+    controller: C,
+    usage: ControllerUsage,
+) -> Result<()> {
+    match usage {
+        ControllerUsage::Unused => {
+            log::debug!("cgroup {} is not supported by this system", controller)
+        }
+        ControllerUsage::Configured => log::warn!(
+            "skipping resources of unsupported cgroup: controller={} usage={:?}",
+            controller,
+            usage
+        ),
+        ControllerUsage::Required => bail!(
+            "cgroup {} is required to fullfill the request, but is not supported by this system",
+            controller
+        ),
+    }
+
+    Ok(())
+}
+
 /// Determines the cgroup setup of the system. Systems typically have one of
 /// three setups:
 /// - Unified: Pure cgroup v2 system.
 /// - Legacy: Pure cgroup v1 system.
 /// - Hybrid: Hybrid is basically a cgroup v1 system, except for
-///   an additional unified hierarchy which doesn't have any
-///   controllers attached. Resource control can purely be achieved
-///   through the cgroup v1 hierarchy, not through the cgroup v2 hierarchy.
+///   an additional unified hierarchy which usually doesn't have any
+///   controllers attached. Controllers that are not mounted as a cgroup v1
+///   hierarchy are attached to the unified hierarchy instead, which the
+///   hybrid cgroup manager takes into account.
 pub fn get_cgroup_setup() -> Result<CgroupSetup> {
     let default_root = Path::new(DEFAULT_CGROUP_ROOT);
     match default_root.exists() {
@@ -153,7 +316,7 @@ pub fn get_cgroup_setup() -> Result<CgroupSetup> {
             }
 
             if stat.filesystem_type() == TMPFS_MAGIC {
-                let unified = Path::new("/sys/fs/cgroup/unified");
+                let unified = Path::new(UNIFIED_MOUNT_POINT);
                 if Path::new(unified).exists() {
                     let stat = statfs(unified)
                         .with_context(|| format!("failed to stat {}", unified.display()))?;
@@ -176,32 +339,190 @@ pub fn create_cgroup_manager<P: Into<PathBuf>>(
     systemd_cgroup: bool,
     container_name: &str,
 ) -> Result<Box<dyn CgroupManager>> {
-    let cgroup_setup = get_cgroup_setup()?;
-    let cgroup_path = cgroup_path.into();
-
-    match cgroup_setup {
-        CgroupSetup::Legacy | CgroupSetup::Hybrid => create_v1_cgroup_manager(cgroup_path),
-        CgroupSetup::Unified => {
-            if systemd_cgroup {
-                return create_systemd_cgroup_manager(cgroup_path, container_name);
+    // the processes of cgroups that are managed like systemd units should be
+    // tracked by systemd as well
+    CgroupDiscovery::new()?
+        .with_systemd_hierarchy(systemd_cgroup)
+        .create_cgroup_manager(cgroup_path, systemd_cgroup, container_name)
+}
+
+/// Holds the result of detecting the cgroup setup and the mount points of the
+/// cgroup subsystems. Discovery requires parsing the mountinfo of the process,
+/// so it should be done once when many cgroup managers need to be created,
+/// e.g. when collecting statistics for all containers.
+#[derive(Debug)]
+pub struct CgroupDiscovery {
+    setup: CgroupSetup,
+    #[cfg(feature = "v1")]
+    v1_mount_points: HashMap<v1::ControllerType, PathBuf>,
+    #[cfg(feature = "v1")]
+    v1_named_hierarchies: HashMap<String, PathBuf>,
+    /// Whether cgroup v1 managers join the named hierarchies
+    #[cfg(feature = "v1")]
+    join_named_hierarchies: bool,
+    /// Controllers that are bound to the unified hierarchy of a hybrid host
+    #[cfg(feature = "v2")]
+    unified_controllers: Vec<v2::controller_type::ControllerType>,
+}
+
+impl CgroupDiscovery {
+    pub fn new() -> Result<Self> {
+        let setup = get_cgroup_setup()?;
+
+        #[cfg(feature = "v1")]
+        let v1_mount_points = match setup {
+            CgroupSetup::Legacy | CgroupSetup::Hybrid => v1::util::list_supported_mount_points()?,
+            CgroupSetup::Unified => HashMap::new(),
+        };
+
+        // processes are tracked by systemd through the name=systemd hierarchy,
+        // which is only joined if requested
+        #[cfg(feature = "v1")]
+        let v1_named_hierarchies = match setup {
+            CgroupSetup::Legacy | CgroupSetup::Hybrid => v1::util::list_named_hierarchies()?
+                .into_iter()
+                .filter(|(name, _)| name == v1::manager::SYSTEMD_HIERARCHY)
+                .collect(),
+            CgroupSetup::Unified => HashMap::new(),
+        };
+
+        // usually all controllers are mounted as v1 hierarchies on hybrid hosts,
+        // but the ones that are not are available on the unified hierarchy
+        #[cfg(feature = "v2")]
+        let unified_controllers = match setup {
+            CgroupSetup::Hybrid => v2::util::get_available_controllers(UNIFIED_MOUNT_POINT)
+                .unwrap_or_else(|err| {
+                    log::warn!(
+                        "failed to list the controllers of the unified hierarchy: {:?}",
+                        err
+                    );
+                    Vec::new()
+                }),
+            CgroupSetup::Legacy | CgroupSetup::Unified => Vec::new(),
+        };
+
+        Ok(Self {
+            setup,
+            #[cfg(feature = "v1")]
+            v1_mount_points,
+            #[cfg(feature = "v1")]
+            v1_named_hierarchies,
+            #[cfg(feature = "v1")]
+            join_named_hierarchies: false,
+            #[cfg(feature = "v2")]
+            unified_controllers,
+        })
+    }
+
+    /// Controls if cgroup v1 managers join the name=systemd hierarchy, which
+    /// they do not by default. Named hierarchies are never removed together
+    /// with the cgroup.
+    pub fn with_systemd_hierarchy(self, join: bool) -> Self {
+        #[cfg(not(feature = "v1"))]
+        let _ = join;
+        Self {
+            #[cfg(feature = "v1")]
+            join_named_hierarchies: join,
+            ..self
+        }
+    }
+
+    #[cfg(feature = "v1")]
+    fn named_hierarchies(&self) -> HashMap<String, PathBuf> {
+        if self.join_named_hierarchies {
+            self.v1_named_hierarchies.clone()
+        } else {
+            HashMap::new()
+        }
+    }
+
+    pub fn setup(&self) -> &CgroupSetup {
+        &self.setup
+    }
+
+    /// Creates a cgroup manager without rediscovering the cgroup setup of the system
+    pub fn create_cgroup_manager<P: Into<PathBuf>>(
+        &self,
+        cgroup_path: P,
+        systemd_cgroup: bool,
+        container_name: &str,
+    ) -> Result<Box<dyn CgroupManager>> {
+        let cgroup_path = cgroup_path.into();
+        // the cgroups of v1 hosts are created by the v1 manager, but with the
+        // systemd driver in the place where systemd would create the unit
+        let cgroup_path = match self.setup {
+            CgroupSetup::Legacy | CgroupSetup::Hybrid if systemd_cgroup => {
+                expand_systemd_cgroups_path(&cgroup_path)?
             }
+            _ => cgroup_path,
+        };
+
+        match self.setup {
+            CgroupSetup::Legacy => create_v1_cgroup_manager(self, cgroup_path),
+            CgroupSetup::Hybrid => create_hybrid_cgroup_manager(self, cgroup_path),
+            CgroupSetup::Unified => {
+                if systemd_cgroup {
+                    return create_systemd_cgroup_manager(cgroup_path, container_name);
+                }
 
-            create_v2_cgroup_manager(cgroup_path)
+                create_v2_cgroup_manager(cgroup_path)
+            }
         }
     }
 }
 
 #[cfg(feature = "v1")]
-fn create_v1_cgroup_manager(cgroup_path: PathBuf) -> Result<Box<dyn CgroupManager>> {
+fn create_v1_cgroup_manager(
+    discovery: &CgroupDiscovery,
+    cgroup_path: PathBuf,
+) -> Result<Box<dyn CgroupManager>> {
     log::info!("cgroup manager V1 will be used");
-    Ok(Box::new(v1::manager::Manager::new(cgroup_path)?))
+    let manager =
+        v1::manager::Manager::with_mount_points(cgroup_path.clone(), &discovery.v1_mount_points)?
+            .join_named_hierarchies(&cgroup_path, &discovery.named_hierarchies())?;
+    Ok(Box::new(manager))
 }
 
 #[cfg(not(feature = "v1"))]
-fn create_v1_cgroup_manager(_cgroup_path: PathBuf) -> Result<Box<dyn CgroupManager>> {
+fn create_v1_cgroup_manager(
+    _discovery: &CgroupDiscovery,
+    _cgroup_path: PathBuf,
+) -> Result<Box<dyn CgroupManager>> {
     bail!("cgroup v1 feature is required, but was not enabled during compile time");
 }
 
+#[cfg(all(feature = "v1", feature = "v2"))]
+fn create_hybrid_cgroup_manager(
+    discovery: &CgroupDiscovery,
+    cgroup_path: PathBuf,
+) -> Result<Box<dyn CgroupManager>> {
+    if discovery.unified_controllers.is_empty() {
+        return create_v1_cgroup_manager(discovery, cgroup_path);
+    }
+
+    log::info!(
+        "hybrid cgroup manager will be used, {:?} on the unified hierarchy",
+        discovery.unified_controllers
+    );
+    let v1 =
+        v1::manager::Manager::with_mount_points(cgroup_path.clone(), &discovery.v1_mount_points)?
+            .join_named_hierarchies(&cgroup_path, &discovery.named_hierarchies())?;
+    let v2 = v2::manager::Manager::new(UNIFIED_MOUNT_POINT.into(), cgroup_path)?;
+    Ok(Box::new(hybrid::Manager::new(
+        v1,
+        v2,
+        discovery.unified_controllers.clone(),
+    )))
+}
+
+#[cfg(not(all(feature = "v1", feature = "v2")))]
+fn create_hybrid_cgroup_manager(
+    discovery: &CgroupDiscovery,
+    cgroup_path: PathBuf,
+) -> Result<Box<dyn CgroupManager>> {
+    create_v1_cgroup_manager(discovery, cgroup_path)
+}
+
 #[cfg(feature = "v2")]
 fn create_v2_cgroup_manager(cgroup_path: PathBuf) -> Result<Box<dyn CgroupManager>> {
     log::info!("cgroup manager V2 will be used");
@@ -241,6 +562,16 @@ fn create_systemd_cgroup_manager(
     )?))
 }
 
+#[cfg(feature = "systemd")]
+fn expand_systemd_cgroups_path(cgroup_path: &Path) -> Result<PathBuf> {
+    systemd::manager::expand_cgroups_path(cgroup_path, nix::unistd::geteuid().is_root())
+}
+
+#[cfg(not(feature = "systemd"))]
+fn expand_systemd_cgroups_path(_cgroup_path: &Path) -> Result<PathBuf> {
+    bail!("systemd cgroup feature is required, but was not enabled during compile time");
+}
+
 #[cfg(not(feature = "systemd"))]
 fn create_systemd_cgroup_manager(
     _cgroup_path: PathBuf,
@@ -299,6 +630,52 @@ impl PathBufExt for PathBuf {
     }
 }
 
+/// Writes the limits of the RDMA devices to rdma.max, which has the same format on
+/// both cgroup versions, e.g. "mlx4_0 hca_handle=2 hca_object=2000". The kernel reads
+/// the limits of a single device per write, so the devices are written one by one.
+pub(crate) fn write_rdma_limits(
+    cgroup_path: &Path,
+    limits: &HashMap<String, LinuxRdma>,
+) -> Result<()> {
+    let mut devices: Vec<&String> = limits.keys().collect();
+    devices.sort();
+
+    for device in devices {
+        let limit = &limits[device];
+        let mut value = device.clone();
+        if let Some(hca_handles) = limit.hca_handles() {
+            value.push_str(&format!(" hca_handle={}", hca_handles));
+        }
+        if let Some(hca_objects) = limit.hca_objects() {
+            value.push_str(&format!(" hca_object={}", hca_objects));
+        }
+        // a device without limits is left unlimited
+        if value.len() == device.len() {
+            continue;
+        }
+
+        write_cgroup_file_str(cgroup_path.join("rdma.max"), &value)
+            .with_context(|| format!("failed to limit rdma device {}", device))?;
+    }
+
+    Ok(())
+}
+
+/// Returns the resources with an empty list of device rules if they have
+/// none. The managers only change the device rules of a cgroup if the
+/// resources list rules, so that an update of other resources keeps the rules
+/// of the container. A new container is restricted to the
+/// default devices with this, also if its spec has no device rules.
+pub fn with_device_rules(resources: &LinuxResources) -> Cow<LinuxResources> {
+    if resources.devices().is_some() {
+        return Cow::Borrowed(resources);
+    }
+
+    let mut resources = resources.clone();
+    resources.set_devices(Some(Vec::new()));
+    Cow::Owned(resources)
+}
+
 pub(crate) fn default_allow_devices() -> Vec<LinuxDeviceCgroup> {
     vec![
         LinuxDeviceCgroupBuilder::default()
@@ -403,7 +780,11 @@ pub(crate) fn default_devices() -> Vec<LinuxDevice> {
     ]
 }
 
-/// Attempts to delete the path the requested number of times.
+/// Attempts to delete the cgroup and its descendants the requested number of
+/// times. Removal fails with EBUSY for a short time after the last process has
+/// exited, e.g. while the kernel memory of the processes is uncharged on v1, so
+/// the delay between the attempts is doubled up to the limit. Processes that
+/// are still in the cgroup are moved to its parent before the next attempt.
 pub(crate) fn delete_with_retry<P: AsRef<Path>, L: Into<Option<Duration>>>(
     path: P,
     retries: u32,
@@ -414,18 +795,90 @@ pub(crate) fn delete_with_retry<P: AsRef<Path>, L: Into<Option<Duration>>>(
     let path = path.as_ref();
     let limit = limit_backoff.into().unwrap_or(Duration::MAX);
 
-    while attempts < retries {
-        if fs::remove_dir(path).is_ok() {
-            return Ok(());
+    loop {
+        attempts += 1;
+        match remove_cgroup_tree(path) {
+            Ok(()) => return Ok(()),
+            Err(err) if attempts >= retries => {
+                return Err(err).with_context(|| {
+                    format!("could not delete {:?} after {} attempts", path, attempts)
+                })
+            }
+            Err(err) => log::debug!(
+                "attempt {} to delete {:?} failed: {:?}",
+                attempts,
+                path,
+                err
+            ),
         }
 
+        drain_procs(path);
         std::thread::sleep(delay);
-        attempts += attempts;
-        delay *= attempts;
-        if delay > limit {
-            delay = limit;
+        delay = (delay * 2).min(limit);
+    }
+}
+
+/// Removes the child cgroups, e.g. created by the workload, before the cgroup
+/// itself, as a cgroup with children cannot be removed
+fn remove_cgroup_tree(path: &Path) -> Result<()> {
+    // a previous attempt may have removed it already
+    if !path.exists() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            remove_cgroup_tree(&entry.path())?;
+        }
+    }
+
+    fs::remove_dir(path).with_context(|| format!("failed to remove {:?}", path))
+}
+
+/// Moves the processes that are still in the cgroup or its descendants to the
+/// parent of the cgroup. Failures are ignored, as the processes may exit in
+/// between and the parent may not accept processes on cgroup v2 due to the
+/// no internal processes rule.
+fn drain_procs(path: &Path) {
+    let parent_procs = match path.parent() {
+        Some(parent) => parent.join(CGROUP_PROCS),
+        None => return,
+    };
+
+    for pid in get_all_pids(path).unwrap_or_default() {
+        if let Err(err) = write_cgroup_file_unbatched(&parent_procs, pid) {
+            log::debug!("failed to move {} out of {:?}: {:?}", pid, path, err);
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{create_temp_dir, set_fixture};
+
+    #[test]
+    fn test_delete_with_retry_removes_child_cgroups() -> Result<()> {
+        let tmp = create_temp_dir("test_delete_with_retry_removes_child_cgroups")?;
+        let cgroup = tmp.join("container");
+        fs::create_dir_all(cgroup.join("workload").join("worker"))?;
+
+        delete_with_retry(&cgroup, 3, Duration::from_millis(10))?;
+        assert!(!cgroup.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_with_retry_gives_up() -> Result<()> {
+        let tmp = create_temp_dir("test_delete_with_retry_gives_up")?;
+        let cgroup = tmp.join("container");
+        fs::create_dir(&cgroup)?;
+        // unlike on cgroupfs, the directory cannot be removed with a file in it
+        set_fixture(&cgroup, CGROUP_PROCS, "")?;
 
-    bail!("could not delete {:?}", path)
+        assert!(delete_with_retry(&cgroup, 3, Duration::from_millis(10)).is_err());
+        assert!(cgroup.exists());
+        Ok(())
+    }
 }
@@ -0,0 +1,15 @@
+//! Conversion of the statistics into the metrics that containerd expects from
+//! the shims of its runtimes. The structs mirror the protobuf messages
+//! `io.containerd.cgroups.v1.Metrics` and `io.containerd.cgroups.v2.Metrics`
+//! field by field, including the field names, so that a shim can map them onto
+//! the generated protobuf types without collecting the statistics itself.
+//! Which of them containerd expects depends on the cgroup version of the host.
+pub mod v1;
+pub mod v2;
+
+use std::collections::HashMap;
+
+/// Returns the value of an entry of memory.stat, or 0 if it does not exist
+fn raw(stats: &HashMap<String, u64>, key: &str) -> u64 {
+    stats.get(key).copied().unwrap_or_default()
+}
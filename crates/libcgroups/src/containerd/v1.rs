@@ -0,0 +1,347 @@
+//! Metrics of `io.containerd.cgroups.v1`
+use serde::{Deserialize, Serialize};
+
+use super::raw;
+use crate::stats::{self, BlkioDeviceStat, Stats};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metrics {
+    pub hugetlb: Vec<HugetlbStat>,
+    pub pids: PidsStat,
+    pub cpu: CpuStat,
+    pub memory: MemoryStat,
+    pub blkio: BlkIoStat,
+    pub rdma: RdmaStat,
+    pub network: Vec<NetworkStat>,
+    pub memory_oom_control: MemoryOomControl,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HugetlbStat {
+    pub usage: u64,
+    pub max: u64,
+    pub failcnt: u64,
+    pub pagesize: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PidsStat {
+    pub current: u64,
+    pub limit: u64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CpuStat {
+    pub usage: CpuUsage,
+    pub throttling: Throttle,
+}
+
+/// Cpu usage in nanoseconds
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CpuUsage {
+    pub total: u64,
+    pub kernel: u64,
+    pub user: u64,
+    pub per_cpu: Vec<u64>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Throttle {
+    pub periods: u64,
+    pub throttled_periods: u64,
+    /// Throttled time in nanoseconds
+    pub throttled_time: u64,
+}
+
+/// Memory statistics in bytes. Apart from the usage entries, the values are
+/// the entries of memory.stat.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryStat {
+    pub cache: u64,
+    pub rss: u64,
+    pub rss_huge: u64,
+    pub mapped_file: u64,
+    pub dirty: u64,
+    pub writeback: u64,
+    pub pg_pg_in: u64,
+    pub pg_pg_out: u64,
+    pub pg_fault: u64,
+    pub pg_maj_fault: u64,
+    pub inactive_anon: u64,
+    pub active_anon: u64,
+    pub inactive_file: u64,
+    pub active_file: u64,
+    pub unevictable: u64,
+    pub hierarchical_memory_limit: u64,
+    pub hierarchical_swap_limit: u64,
+    pub total_cache: u64,
+    pub total_rss: u64,
+    pub total_rss_huge: u64,
+    pub total_mapped_file: u64,
+    pub total_dirty: u64,
+    pub total_writeback: u64,
+    pub total_pg_pg_in: u64,
+    pub total_pg_pg_out: u64,
+    pub total_pg_fault: u64,
+    pub total_pg_maj_fault: u64,
+    pub total_inactive_anon: u64,
+    pub total_active_anon: u64,
+    pub total_inactive_file: u64,
+    pub total_active_file: u64,
+    pub total_unevictable: u64,
+    pub usage: MemoryEntry,
+    pub swap: MemoryEntry,
+    pub kernel: MemoryEntry,
+    pub kernel_tcp: MemoryEntry,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryEntry {
+    pub limit: u64,
+    pub usage: u64,
+    pub max: u64,
+    pub failcnt: u64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryOomControl {
+    pub oom_kill_disable: u64,
+    pub under_oom: u64,
+    pub oom_kill: u64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlkIoStat {
+    pub io_service_bytes_recursive: Vec<BlkIoEntry>,
+    pub io_serviced_recursive: Vec<BlkIoEntry>,
+    pub io_queued_recursive: Vec<BlkIoEntry>,
+    pub io_service_time_recursive: Vec<BlkIoEntry>,
+    pub io_wait_time_recursive: Vec<BlkIoEntry>,
+    pub io_merged_recursive: Vec<BlkIoEntry>,
+    pub io_time_recursive: Vec<BlkIoEntry>,
+    pub sectors_recursive: Vec<BlkIoEntry>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlkIoEntry {
+    pub op: String,
+    pub device: String,
+    pub major: u64,
+    pub minor: u64,
+    pub value: u64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RdmaStat {
+    pub current: Vec<RdmaEntry>,
+    pub limit: Vec<RdmaEntry>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RdmaEntry {
+    pub device: String,
+    pub hca_handles: u32,
+    pub hca_objects: u32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkStat {
+    pub name: String,
+    pub rx_bytes: u64,
+    pub rx_packets: u64,
+    pub rx_errors: u64,
+    pub rx_dropped: u64,
+    pub tx_bytes: u64,
+    pub tx_packets: u64,
+    pub tx_errors: u64,
+    pub tx_dropped: u64,
+}
+
+impl From<&Stats> for Metrics {
+    fn from(stats: &Stats) -> Self {
+        let mut hugetlb: Vec<HugetlbStat> = stats
+            .hugetlb
+            .iter()
+            .map(|(pagesize, hugetlb)| HugetlbStat {
+                usage: hugetlb.usage.0,
+                max: hugetlb.max_usage.0,
+                failcnt: hugetlb.fail_count,
+                pagesize: pagesize.clone(),
+            })
+            .collect();
+        hugetlb.sort_by(|a, b| a.pagesize.cmp(&b.pagesize));
+
+        let usage = &stats.cpu.usage;
+        let throttling = &stats.cpu.throttling;
+        let blkio = &stats.blkio;
+        let rdma = stats.rdma.clone().unwrap_or_default();
+
+        Self {
+            hugetlb,
+            pids: PidsStat {
+                current: stats.pids.current,
+                limit: stats.pids.limit,
+            },
+            cpu: CpuStat {
+                usage: CpuUsage {
+                    total: usage.usage_total.0,
+                    kernel: usage.usage_kernel.0,
+                    user: usage.usage_user.0,
+                    per_cpu: usage.per_core_usage_total.iter().map(|n| n.0).collect(),
+                },
+                throttling: Throttle {
+                    periods: throttling.periods,
+                    throttled_periods: throttling.throttled_periods,
+                    throttled_time: throttling.throttled_time.0,
+                },
+            },
+            memory: memory_stat(&stats.memory),
+            blkio: BlkIoStat {
+                io_service_bytes_recursive: blkio_entries(&blkio.service_bytes),
+                io_serviced_recursive: blkio_entries(&blkio.serviced),
+                io_queued_recursive: blkio_entries(&blkio.queued),
+                io_service_time_recursive: blkio_entries(&blkio.service_time),
+                io_wait_time_recursive: blkio_entries(&blkio.wait_time),
+                io_merged_recursive: blkio_entries(&blkio.merged),
+                io_time_recursive: blkio_entries(&blkio.time),
+                sectors_recursive: blkio_entries(&blkio.sectors),
+            },
+            rdma: RdmaStat {
+                current: rdma_entries(&rdma.current),
+                limit: rdma_entries(&rdma.limit),
+            },
+            network: stats
+                .network_interfaces
+                .iter()
+                .map(|iface| NetworkStat {
+                    name: iface.name.clone(),
+                    rx_bytes: iface.rx_bytes,
+                    rx_packets: iface.rx_packets,
+                    rx_errors: iface.rx_errors,
+                    rx_dropped: iface.rx_dropped,
+                    tx_bytes: iface.tx_bytes,
+                    tx_packets: iface.tx_packets,
+                    tx_errors: iface.tx_errors,
+                    tx_dropped: iface.tx_dropped,
+                })
+                .collect(),
+            memory_oom_control: MemoryOomControl {
+                oom_kill: stats.memory.events.oom_kill,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+fn memory_stat(memory: &stats::MemoryStats) -> MemoryStat {
+    let get = |key: &str| raw(&memory.stats, key);
+    let entry = |data: &stats::MemoryData| MemoryEntry {
+        limit: data.limit.0,
+        usage: data.usage.0,
+        max: data.max_usage.0,
+        failcnt: data.fail_count,
+    };
+
+    MemoryStat {
+        cache: memory.cache.0,
+        rss: get("rss"),
+        rss_huge: get("rss_huge"),
+        mapped_file: get("mapped_file"),
+        dirty: get("dirty"),
+        writeback: get("writeback"),
+        pg_pg_in: get("pgpgin"),
+        pg_pg_out: get("pgpgout"),
+        pg_fault: get("pgfault"),
+        pg_maj_fault: get("pgmajfault"),
+        inactive_anon: get("inactive_anon"),
+        active_anon: get("active_anon"),
+        inactive_file: get("inactive_file"),
+        active_file: get("active_file"),
+        unevictable: get("unevictable"),
+        hierarchical_memory_limit: get("hierarchical_memory_limit"),
+        hierarchical_swap_limit: get("hierarchical_memsw_limit"),
+        total_cache: get("total_cache"),
+        total_rss: get("total_rss"),
+        total_rss_huge: get("total_rss_huge"),
+        total_mapped_file: get("total_mapped_file"),
+        total_dirty: get("total_dirty"),
+        total_writeback: get("total_writeback"),
+        total_pg_pg_in: get("total_pgpgin"),
+        total_pg_pg_out: get("total_pgpgout"),
+        total_pg_fault: get("total_pgfault"),
+        total_pg_maj_fault: get("total_pgmajfault"),
+        total_inactive_anon: get("total_inactive_anon"),
+        total_active_anon: get("total_active_anon"),
+        total_inactive_file: get("total_inactive_file"),
+        total_active_file: get("total_active_file"),
+        total_unevictable: get("total_unevictable"),
+        usage: entry(&memory.memory),
+        swap: entry(&memory.memswap),
+        kernel: entry(&memory.kernel),
+        kernel_tcp: entry(&memory.kernel_tcp),
+    }
+}
+
+fn blkio_entries(device_stats: &[BlkioDeviceStat]) -> Vec<BlkIoEntry> {
+    device_stats
+        .iter()
+        .map(|stat| BlkIoEntry {
+            op: stat.op_type.as_deref().unwrap_or_default().to_owned(),
+            device: stat.device_name.clone().unwrap_or_default(),
+            major: stat.major,
+            minor: stat.minor,
+            value: stat.value,
+        })
+        .collect()
+}
+
+fn rdma_entries(entries: &[stats::RdmaEntry]) -> Vec<RdmaEntry> {
+    entries
+        .iter()
+        .map(|entry| RdmaEntry {
+            device: entry.device.clone(),
+            hca_handles: entry.hca_handles,
+            hca_objects: entry.hca_objects,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::{Bytes, Nanoseconds};
+
+    #[test]
+    fn test_convert_stats() {
+        let mut stats = Stats::default();
+        stats.cpu.usage.usage_total = Nanoseconds(1_000);
+        stats.cpu.usage.per_core_usage_total = vec![Nanoseconds(400), Nanoseconds(600)];
+        stats.memory.memory.usage = Bytes(4096);
+        stats.memory.stats.insert("total_rss".to_owned(), 2048);
+        stats.memory.events.oom_kill = 1;
+        stats.blkio.service_bytes = vec![BlkioDeviceStat {
+            major: 8,
+            minor: 0,
+            op_type: Some("Read".into()),
+            value: 512,
+            device_name: Some("sda".to_owned()),
+        }];
+
+        let metrics = Metrics::from(&stats);
+        assert_eq!(metrics.cpu.usage.total, 1_000);
+        assert_eq!(metrics.cpu.usage.per_cpu, vec![400, 600]);
+        assert_eq!(metrics.memory.usage.usage, 4096);
+        assert_eq!(metrics.memory.total_rss, 2048);
+        assert_eq!(metrics.memory_oom_control.oom_kill, 1);
+        assert_eq!(
+            metrics.blkio.io_service_bytes_recursive,
+            vec![BlkIoEntry {
+                op: "Read".to_owned(),
+                device: "sda".to_owned(),
+                major: 8,
+                minor: 0,
+                value: 512,
+            }]
+        );
+    }
+}
@@ -0,0 +1,304 @@
+//! Metrics of `io.containerd.cgroups.v2`
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::raw;
+use crate::stats::{self, Stats};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metrics {
+    pub pids: PidsStat,
+    pub cpu: CpuStat,
+    pub memory: MemoryStat,
+    pub rdma: RdmaStat,
+    pub io: IoStat,
+    pub hugetlb: Vec<HugeTlbStat>,
+    pub memory_events: MemoryEvents,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PidsStat {
+    pub current: u64,
+    pub limit: u64,
+}
+
+/// Cpu statistics of cpu.stat, with times in microseconds
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CpuStat {
+    pub usage_usec: u64,
+    pub user_usec: u64,
+    pub system_usec: u64,
+    pub nr_periods: u64,
+    pub nr_throttled: u64,
+    pub throttled_usec: u64,
+}
+
+/// Memory statistics. Apart from the usage and limits, the values are the
+/// entries of memory.stat.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryStat {
+    pub anon: u64,
+    pub file: u64,
+    pub kernel_stack: u64,
+    pub slab: u64,
+    pub sock: u64,
+    pub shmem: u64,
+    pub file_mapped: u64,
+    pub file_dirty: u64,
+    pub file_writeback: u64,
+    pub anon_thp: u64,
+    pub inactive_anon: u64,
+    pub active_anon: u64,
+    pub inactive_file: u64,
+    pub active_file: u64,
+    pub unevictable: u64,
+    pub slab_reclaimable: u64,
+    pub slab_unreclaimable: u64,
+    pub pgfault: u64,
+    pub pgmajfault: u64,
+    pub workingset_refault: u64,
+    pub workingset_activate: u64,
+    pub workingset_nodereclaim: u64,
+    pub pgrefill: u64,
+    pub pgscan: u64,
+    pub pgsteal: u64,
+    pub pgactivate: u64,
+    pub pgdeactivate: u64,
+    pub pglazyfree: u64,
+    pub pglazyfreed: u64,
+    pub thp_fault_alloc: u64,
+    pub thp_collapse_alloc: u64,
+    pub usage: u64,
+    pub usage_limit: u64,
+    pub swap_usage: u64,
+    pub swap_limit: u64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryEvents {
+    pub low: u64,
+    pub high: u64,
+    pub max: u64,
+    pub oom: u64,
+    pub oom_kill: u64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RdmaStat {
+    pub current: Vec<RdmaEntry>,
+    pub limit: Vec<RdmaEntry>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RdmaEntry {
+    pub device: String,
+    pub hca_handles: u32,
+    pub hca_objects: u32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IoStat {
+    pub usage: Vec<IoEntry>,
+}
+
+/// Io of a device as reported by io.stat
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IoEntry {
+    pub major: u64,
+    pub minor: u64,
+    pub rbytes: u64,
+    pub wbytes: u64,
+    pub rios: u64,
+    pub wios: u64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HugeTlbStat {
+    pub current: u64,
+    pub max: u64,
+    pub pagesize: String,
+}
+
+impl From<&Stats> for Metrics {
+    fn from(stats: &Stats) -> Self {
+        let usage = &stats.cpu.usage;
+        let throttling = &stats.cpu.throttling;
+        let events = &stats.memory.events;
+        let rdma = stats.rdma.clone().unwrap_or_default();
+
+        let mut hugetlb: Vec<HugeTlbStat> = stats
+            .hugetlb
+            .iter()
+            .map(|(pagesize, hugetlb)| HugeTlbStat {
+                current: hugetlb.usage.0,
+                // containerd reports the limit, which is not part of the stats
+                max: 0,
+                pagesize: pagesize.clone(),
+            })
+            .collect();
+        hugetlb.sort_by(|a, b| a.pagesize.cmp(&b.pagesize));
+
+        Self {
+            pids: PidsStat {
+                current: stats.pids.current,
+                limit: stats.pids.limit,
+            },
+            cpu: CpuStat {
+                usage_usec: usage.usage_total.0 / 1000,
+                user_usec: usage.usage_user.0 / 1000,
+                system_usec: usage.usage_kernel.0 / 1000,
+                nr_periods: throttling.periods,
+                nr_throttled: throttling.throttled_periods,
+                throttled_usec: throttling.throttled_time.0 / 1000,
+            },
+            memory: memory_stat(&stats.memory),
+            rdma: RdmaStat {
+                current: rdma_entries(&rdma.current),
+                limit: rdma_entries(&rdma.limit),
+            },
+            io: IoStat {
+                usage: io_entries(&stats.blkio),
+            },
+            hugetlb,
+            memory_events: MemoryEvents {
+                low: events.low,
+                high: events.high,
+                max: events.max,
+                oom: events.oom,
+                oom_kill: events.oom_kill,
+            },
+        }
+    }
+}
+
+fn memory_stat(memory: &stats::MemoryStats) -> MemoryStat {
+    let get = |key: &str| raw(&memory.stats, key);
+
+    MemoryStat {
+        anon: get("anon"),
+        file: get("file"),
+        kernel_stack: get("kernel_stack"),
+        slab: get("slab"),
+        sock: get("sock"),
+        shmem: get("shmem"),
+        file_mapped: get("file_mapped"),
+        file_dirty: get("file_dirty"),
+        file_writeback: get("file_writeback"),
+        anon_thp: get("anon_thp"),
+        inactive_anon: get("inactive_anon"),
+        active_anon: get("active_anon"),
+        inactive_file: get("inactive_file"),
+        active_file: get("active_file"),
+        unevictable: get("unevictable"),
+        slab_reclaimable: get("slab_reclaimable"),
+        slab_unreclaimable: get("slab_unreclaimable"),
+        pgfault: get("pgfault"),
+        pgmajfault: get("pgmajfault"),
+        workingset_refault: get("workingset_refault"),
+        workingset_activate: get("workingset_activate"),
+        workingset_nodereclaim: get("workingset_nodereclaim"),
+        pgrefill: get("pgrefill"),
+        pgscan: get("pgscan"),
+        pgsteal: get("pgsteal"),
+        pgactivate: get("pgactivate"),
+        pgdeactivate: get("pgdeactivate"),
+        pglazyfree: get("pglazyfree"),
+        pglazyfreed: get("pglazyfreed"),
+        thp_fault_alloc: get("thp_fault_alloc"),
+        thp_collapse_alloc: get("thp_collapse_alloc"),
+        usage: memory.memory.usage.0,
+        usage_limit: memory.memory.limit.0,
+        // on cgroup v2 the swap is accounted separately from the memory
+        swap_usage: memory.memswap.usage.0,
+        swap_limit: memory.memswap.limit.0,
+    }
+}
+
+/// Merges the bytes and operations per device and direction into one entry per device
+fn io_entries(blkio: &stats::BlkioStats) -> Vec<IoEntry> {
+    let mut entries: BTreeMap<(u64, u64), IoEntry> = BTreeMap::new();
+    let mut add = |device_stats: &[stats::BlkioDeviceStat], bytes: bool| {
+        for stat in device_stats {
+            let entry = entries
+                .entry((stat.major, stat.minor))
+                .or_insert_with(|| IoEntry {
+                    major: stat.major,
+                    minor: stat.minor,
+                    ..Default::default()
+                });
+            // cgroup v1 reports the operation types capitalized, cgroup v2 in lowercase
+            let op = stat.op_type.as_deref().map(str::to_lowercase);
+            match (op.as_deref(), bytes) {
+                (Some("read"), true) => entry.rbytes += stat.value,
+                (Some("write"), true) => entry.wbytes += stat.value,
+                (Some("read"), false) => entry.rios += stat.value,
+                (Some("write"), false) => entry.wios += stat.value,
+                _ => continue,
+            }
+        }
+    };
+    add(&blkio.service_bytes, true);
+    add(&blkio.serviced, false);
+
+    entries.into_values().collect()
+}
+
+fn rdma_entries(entries: &[stats::RdmaEntry]) -> Vec<RdmaEntry> {
+    entries
+        .iter()
+        .map(|entry| RdmaEntry {
+            device: entry.device.clone(),
+            hca_handles: entry.hca_handles,
+            hca_objects: entry.hca_objects,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::{BlkioDeviceStat, Bytes, Nanoseconds};
+
+    fn device_stat(op_type: &str, value: u64) -> BlkioDeviceStat {
+        BlkioDeviceStat {
+            major: 8,
+            minor: 0,
+            op_type: Some(op_type.to_owned().into()),
+            value,
+            device_name: None,
+        }
+    }
+
+    #[test]
+    fn test_convert_stats() {
+        let mut stats = Stats::default();
+        stats.cpu.usage.usage_total = Nanoseconds::from_micros(7730);
+        stats.cpu.throttling.throttled_time = Nanoseconds::from_micros(150);
+        stats.memory.memory.usage = Bytes(4096);
+        stats.memory.memswap.limit = Bytes(8192);
+        stats.memory.stats.insert("anon".to_owned(), 1024);
+        stats.memory.events.oom = 2;
+        stats.blkio.service_bytes = vec![device_stat("read", 512), device_stat("write", 256)];
+        stats.blkio.serviced = vec![device_stat("read", 4), device_stat("write", 2)];
+
+        let metrics = Metrics::from(&stats);
+        assert_eq!(metrics.cpu.usage_usec, 7730);
+        assert_eq!(metrics.cpu.throttled_usec, 150);
+        assert_eq!(metrics.memory.usage, 4096);
+        assert_eq!(metrics.memory.swap_limit, 8192);
+        assert_eq!(metrics.memory.anon, 1024);
+        assert_eq!(metrics.memory_events.oom, 2);
+        assert_eq!(
+            metrics.io.usage,
+            vec![IoEntry {
+                major: 8,
+                minor: 0,
+                rbytes: 512,
+                wbytes: 256,
+                rios: 4,
+                wios: 2,
+            }]
+        );
+    }
+}
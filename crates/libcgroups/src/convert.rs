@@ -0,0 +1,95 @@
+//! Conversion of the resource values of the runtime spec, which has been
+//! designed for cgroup v1, to their cgroup v2 equivalents. The formulas are
+//! the ones that runc and crun use, so that a spec with v1 style values
+//! behaves the same on unified hosts, independent of the runtime.
+
+/// Smallest cpu shares value the kernel accepts on cgroup v1
+pub const MIN_CPU_SHARES: u64 = 2;
+/// Largest cpu shares value the kernel accepts on cgroup v1
+pub const MAX_CPU_SHARES: u64 = 262144;
+/// Largest cpu weight of cgroup v2
+pub const MAX_CPU_WEIGHT: u64 = 10000;
+/// Smallest blkio weight of cgroup v1
+pub const MIN_BLKIO_WEIGHT: u16 = 10;
+/// Largest blkio weight of cgroup v1
+pub const MAX_BLKIO_WEIGHT: u16 = 1000;
+/// Largest io weight of cgroup v2
+pub const MAX_IO_WEIGHT: u64 = 10000;
+/// Value of cpu.max for a quota that is not restricted
+pub const UNRESTRICTED_QUOTA: &str = "max";
+
+/// Converts cpu shares, which range from 2 to 262144, linearly to a cpu
+/// weight, which ranges from 1 to 10000. Shares of 0 are not set and are
+/// converted to 0, other shares out of range are clamped like the kernel
+/// does on cgroup v1.
+pub fn cpu_shares_to_weight(shares: u64) -> u64 {
+    if shares == 0 {
+        return 0;
+    }
+
+    let shares = shares.clamp(MIN_CPU_SHARES, MAX_CPU_SHARES);
+    1 + ((shares - MIN_CPU_SHARES) * (MAX_CPU_WEIGHT - 1)) / (MAX_CPU_SHARES - MIN_CPU_SHARES)
+}
+
+/// Converts the cpu quota and period, both in microseconds, to the content
+/// of cpu.max, which is "$QUOTA $PERIOD". A quota that is not positive is
+/// not restricted. Without a period only the quota is written, which keeps
+/// the period of the cgroup.
+pub fn cpu_quota_to_max(quota: i64, period: Option<u64>) -> String {
+    let quota = if quota > 0 {
+        quota.to_string()
+    } else {
+        UNRESTRICTED_QUOTA.to_owned()
+    };
+
+    match period {
+        Some(period) => format!("{} {}", quota, period),
+        None => quota,
+    }
+}
+
+/// Converts a blkio weight, which ranges from 10 to 1000, linearly to an io
+/// weight, which ranges from 1 to 10000. A weight of 0 is not set and is
+/// converted to 0, other weights out of range are clamped.
+pub fn blkio_weight_to_io_weight(weight: u16) -> u64 {
+    if weight == 0 {
+        return 0;
+    }
+
+    let weight = weight.clamp(MIN_BLKIO_WEIGHT, MAX_BLKIO_WEIGHT) - MIN_BLKIO_WEIGHT;
+    1 + (weight as u64 * (MAX_IO_WEIGHT - 1)) / (MAX_BLKIO_WEIGHT - MIN_BLKIO_WEIGHT) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpu_shares_to_weight() {
+        assert_eq!(cpu_shares_to_weight(0), 0);
+        assert_eq!(cpu_shares_to_weight(1), 1);
+        assert_eq!(cpu_shares_to_weight(2), 1);
+        assert_eq!(cpu_shares_to_weight(1024), 39);
+        assert_eq!(cpu_shares_to_weight(22000), 840);
+        assert_eq!(cpu_shares_to_weight(262144), 10000);
+        assert_eq!(cpu_shares_to_weight(u64::MAX), 10000);
+    }
+
+    #[test]
+    fn test_cpu_quota_to_max() {
+        assert_eq!(cpu_quota_to_max(50000, Some(100000)), "50000 100000");
+        assert_eq!(cpu_quota_to_max(-1, Some(250000)), "max 250000");
+        assert_eq!(cpu_quota_to_max(0, None), "max");
+        assert_eq!(cpu_quota_to_max(20000, None), "20000");
+    }
+
+    #[test]
+    fn test_blkio_weight_to_io_weight() {
+        assert_eq!(blkio_weight_to_io_weight(0), 0);
+        assert_eq!(blkio_weight_to_io_weight(5), 1);
+        assert_eq!(blkio_weight_to_io_weight(10), 1);
+        assert_eq!(blkio_weight_to_io_weight(500), 4950);
+        assert_eq!(blkio_weight_to_io_weight(1000), 10000);
+        assert_eq!(blkio_weight_to_io_weight(u16::MAX), 10000);
+    }
+}
@@ -0,0 +1,134 @@
+//! Dry runs compute the changes that applying resource restrictions would make
+//! without making them. While a dry run is active on the current thread, the
+//! cgroup managers record the values they would write to cgroup files, the
+//! properties they would set on systemd units and the device filters they
+//! would attach, instead of applying them. Files are still read, e.g. to
+//! respect the order in which the kernel accepts memory limits.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use libcgroups::common::{create_cgroup_manager, ControllerOpt};
+//! use libcgroups::dry_run;
+//! use oci_spec::runtime::LinuxResources;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let manager = create_cgroup_manager("/youki/container", false, "container")?;
+//! let resources = LinuxResources::default();
+//! let writes = dry_run::dry_run(|| {
+//!     manager.apply(&ControllerOpt {
+//!         resources: &resources,
+//!         disable_oom_killer: false,
+//!         oom_score_adj: None,
+//!         freezer_state: None,
+//!     })
+//! })?;
+//! for write in writes {
+//!     println!("{}", write);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+    cell::RefCell,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+thread_local! {
+    static RECORDED: RefCell<Option<Vec<CgroupWrite>>> = RefCell::new(None);
+}
+
+/// A change that a cgroup manager would have made
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum CgroupWrite {
+    /// A value written to a cgroup file
+    File { path: PathBuf, value: String },
+    /// A property set on a systemd unit
+    UnitProperty {
+        unit: String,
+        name: String,
+        value: String,
+    },
+    /// An eBPF device filter attached to a cgroup
+    DeviceFilter { path: PathBuf, rules: usize },
+}
+
+impl Display for CgroupWrite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CgroupWrite::File { path, value } => write!(f, "{} = {}", path.display(), value),
+            CgroupWrite::UnitProperty { unit, name, value } => {
+                write!(f, "{} {} = {}", unit, name, value)
+            }
+            CgroupWrite::DeviceFilter { path, rules } => {
+                write!(f, "{} device filter with {} rules", path.display(), rules)
+            }
+        }
+    }
+}
+
+/// Runs the function as a dry run and returns the changes it would have made
+/// in the order they would have been made
+pub fn dry_run<F: FnOnce() -> Result<()>>(f: F) -> Result<Vec<CgroupWrite>> {
+    RECORDED.with(|recorded| *recorded.borrow_mut() = Some(Vec::new()));
+    let result = f();
+    let writes = RECORDED.with(|recorded| recorded.borrow_mut().take().unwrap_or_default());
+    result.map(|_| writes)
+}
+
+/// Returns true if a dry run is active on the current thread
+pub fn is_dry_run() -> bool {
+    RECORDED.with(|recorded| recorded.borrow().is_some())
+}
+
+/// Records the change if a dry run is active. Returns true if it was
+/// recorded, in which case the caller must not apply it.
+pub(crate) fn record(write: CgroupWrite) -> bool {
+    RECORDED.with(|recorded| match recorded.borrow_mut().as_mut() {
+        Some(writes) => {
+            writes.push(write);
+            true
+        }
+        None => false,
+    })
+}
+
+pub(crate) fn record_file(path: &Path, value: &str) -> bool {
+    is_dry_run()
+        && record(CgroupWrite::File {
+            path: path.to_owned(),
+            value: value.to_owned(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common,
+        test::{create_temp_dir, set_fixture},
+    };
+
+    #[test]
+    fn test_dry_run() -> Result<()> {
+        let tmp = create_temp_dir("test_dry_run")?;
+        set_fixture(&tmp, "pids.max", "max")?;
+
+        let writes = dry_run(|| common::write_cgroup_file(tmp.join("pids.max"), 10))?;
+        assert_eq!(
+            writes,
+            vec![CgroupWrite::File {
+                path: tmp.join("pids.max"),
+                value: "10".to_owned()
+            }]
+        );
+        assert_eq!(common::read_cgroup_file(tmp.join("pids.max"))?, "max");
+        assert!(!is_dry_run());
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+//! Extensions allow embedders to manage controllers that libcgroups has no
+//! built in support for, e.g. out of tree GPU or network controllers, without
+//! patching the cgroup managers. Registered extensions are invoked by all
+//! cgroup managers that are created after the registration.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use anyhow::Result;
+//! use libcgroups::common::ControllerOpt;
+//! use libcgroups::extension::{register_extension, ControllerExtension};
+//! use std::{collections::HashMap, path::Path, sync::Arc};
+//!
+//! struct Gpu;
+//!
+//! impl ControllerExtension for Gpu {
+//!     fn name(&self) -> &str {
+//!         "gpu"
+//!     }
+//!
+//!     fn apply(&self, controller_opt: &ControllerOpt, cgroup_path: &Path) -> Result<()> {
+//!         if let Some(limit) = controller_opt
+//!             .resources
+//!             .unified()
+//!             .as_ref()
+//!             .and_then(|unified| unified.get("gpu.max"))
+//!         {
+//!             libcgroups::common::write_cgroup_file_str(cgroup_path.join("gpu.max"), limit)?;
+//!         }
+//!         Ok(())
+//!     }
+//!
+//!     fn stats(&self, cgroup_path: &Path) -> Result<HashMap<String, u64>> {
+//!         let usage = libcgroups::common::read_cgroup_file(cgroup_path.join("gpu.current"))?;
+//!         Ok(HashMap::from([("current".to_owned(), usage.trim().parse()?)]))
+//!     }
+//! }
+//!
+//! register_extension(Arc::new(Gpu));
+//! ```
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+
+use crate::{common::ControllerOpt, stats::Stats};
+
+static EXTENSIONS: Lazy<RwLock<Vec<Arc<dyn ControllerExtension>>>> =
+    Lazy::new(|| RwLock::new(Vec::new()));
+
+/// Handler for a controller that is not supported by libcgroups itself
+pub trait ControllerExtension: Send + Sync {
+    /// Name of the controller. On cgroup v1 the controller is expected to be
+    /// mounted as a hierarchy of the same name, on cgroup v2 its files are
+    /// part of the unified hierarchy.
+    fn name(&self) -> &str;
+
+    /// Applies the resource restrictions of the controller to the cgroup.
+    /// Called after the built in controllers have been applied.
+    fn apply(&self, controller_opt: &ControllerOpt, cgroup_path: &Path) -> Result<()>;
+
+    /// Collects the statistics of the controller, which are reported under
+    /// the name of the controller
+    fn stats(&self, _cgroup_path: &Path) -> Result<HashMap<String, u64>> {
+        Ok(HashMap::new())
+    }
+}
+
+/// Registers an extension. Extensions with the name of an already registered
+/// extension replace it.
+pub fn register_extension(extension: Arc<dyn ControllerExtension>) {
+    let mut extensions = EXTENSIONS.write().unwrap();
+    extensions.retain(|e| e.name() != extension.name());
+    log::debug!(
+        "registered cgroup controller extension {}",
+        extension.name()
+    );
+    extensions.push(extension);
+}
+
+/// Removes the extension with the given name
+pub fn unregister_extension(name: &str) {
+    EXTENSIONS.write().unwrap().retain(|e| e.name() != name);
+}
+
+/// Returns the currently registered extensions
+pub fn extensions() -> Vec<Arc<dyn ControllerExtension>> {
+    EXTENSIONS.read().unwrap().clone()
+}
+
+/// Adds the statistics of the extension to the statistics of the cgroup. Like
+/// for the built in controllers, a failure is recorded instead of failing the
+/// collection.
+pub(crate) fn collect_stats(stats: &mut Stats, extension: &dyn ControllerExtension, path: &Path) {
+    if let Some(ext_stats) = stats.check_available(extension.name(), extension.stats(path)) {
+        stats
+            .extensions
+            .insert(extension.name().to_owned(), ext_stats);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Dummy(&'static str);
+
+    impl ControllerExtension for Dummy {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn apply(&self, _: &ControllerOpt, _: &Path) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_register_extension() {
+        register_extension(Arc::new(Dummy("test_register_a")));
+        register_extension(Arc::new(Dummy("test_register_a")));
+        register_extension(Arc::new(Dummy("test_register_b")));
+
+        let names = |prefix: &str| -> Vec<String> {
+            extensions()
+                .iter()
+                .map(|e| e.name().to_owned())
+                .filter(|n| n.starts_with(prefix))
+                .collect()
+        };
+        assert_eq!(
+            names("test_register_"),
+            vec!["test_register_a", "test_register_b"]
+        );
+
+        unregister_extension("test_register_a");
+        unregister_extension("test_register_b");
+        assert!(names("test_register_").is_empty());
+    }
+}
@@ -0,0 +1,254 @@
+//! Freezes and thaws the processes of a cgroup on both hierarchies, through
+//! freezer.state of the freezer controller on cgroup v1 and through
+//! cgroup.freeze on cgroup v2. Freezing does not complete immediately, so the
+//! state of the cgroup is polled until all of its processes are frozen. If
+//! that does not happen within the timeout, the cgroup is thawed again rather
+//! than being left partially frozen.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use libcgroups::freezer;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let cgroup = Path::new("/sys/fs/cgroup/youki/container");
+//! freezer::freeze(cgroup, freezer::DEFAULT_TIMEOUT)?;
+//! // e.g. checkpoint the processes of the container
+//! freezer::thaw(cgroup)?;
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    batch,
+    common::{self, FreezerState},
+    dry_run,
+};
+
+pub(crate) const CGROUP_FREEZER_STATE: &str = "freezer.state";
+pub(crate) const FREEZER_STATE_THAWED: &str = "THAWED";
+pub(crate) const FREEZER_STATE_FROZEN: &str = "FROZEN";
+pub(crate) const FREEZER_STATE_FREEZING: &str = "FREEZING";
+
+pub(crate) const CGROUP_FREEZE: &str = "cgroup.freeze";
+pub(crate) const CGROUP_EVENTS: &str = "cgroup.events";
+
+/// Time that the managers wait for the processes of a cgroup to be frozen
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Freezes the processes of the cgroup and waits until all of them are
+/// frozen. The cgroup may be part of the freezer hierarchy of cgroup v1 or
+/// of the unified hierarchy of cgroup v2.
+pub fn freeze(cgroup_path: &Path, timeout: Duration) -> Result<()> {
+    if is_unified(cgroup_path) {
+        freeze_v2(cgroup_path, timeout)
+    } else {
+        freeze_v1(cgroup_path, timeout)
+    }
+}
+
+/// Thaws the processes of the cgroup
+pub fn thaw(cgroup_path: &Path) -> Result<()> {
+    if is_unified(cgroup_path) {
+        thaw_v2(cgroup_path)
+    } else {
+        thaw_v1(cgroup_path)
+    }
+}
+
+/// Freezes or thaws the processes of the cgroup depending on the state
+pub fn apply(cgroup_path: &Path, state: FreezerState, timeout: Duration) -> Result<()> {
+    match state {
+        FreezerState::Undefined => Ok(()),
+        FreezerState::Freezing => bail!("freezing is a transitional state and cannot be applied"),
+        FreezerState::Frozen => freeze(cgroup_path, timeout),
+        FreezerState::Thawed => thaw(cgroup_path),
+    }
+}
+
+/// Returns if the processes of the cgroup are frozen or being frozen
+pub fn is_frozen(cgroup_path: &Path) -> Result<bool> {
+    if is_unified(cgroup_path) {
+        let freeze = common::read_cgroup_file(cgroup_path.join(CGROUP_FREEZE))?;
+        Ok(freeze.trim() == "1")
+    } else {
+        let state = common::read_cgroup_file(cgroup_path.join(CGROUP_FREEZER_STATE))?;
+        Ok(matches!(
+            state.trim(),
+            FREEZER_STATE_FROZEN | FREEZER_STATE_FREEZING
+        ))
+    }
+}
+
+fn is_unified(cgroup_path: &Path) -> bool {
+    cgroup_path.join(CGROUP_FREEZE).exists()
+}
+
+pub(crate) fn freeze_v1(cgroup_path: &Path, timeout: Duration) -> Result<()> {
+    let state_path = cgroup_path.join(CGROUP_FREEZER_STATE);
+    let result = poll(timeout, |attempt| {
+        // We should do our best to retry if FREEZING is seen until it becomes FROZEN.
+        // Thawing in between occasionally helped when system is extremely slow.
+        // see:
+        // https://github.com/opencontainers/runc/blob/b9ee9c6314599f1b4a7f497e1f1f856fe433d3b7/libcontainer/cgroups/fs/freezer.go#L42
+        if attempt % 50 == 49 {
+            let _ = common::write_cgroup_file_unbatched(&state_path, FREEZER_STATE_THAWED);
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        common::write_cgroup_file_unbatched(&state_path, FREEZER_STATE_FROZEN)?;
+        if !is_observable() {
+            return Ok(true);
+        }
+
+        let state = common::read_cgroup_file(&state_path)?;
+        match state.trim() {
+            FREEZER_STATE_FREEZING => Ok(false),
+            FREEZER_STATE_FROZEN => Ok(true),
+            state => bail!("unexpected state {} while freezing", state),
+        }
+    });
+
+    if result.is_err() {
+        // it is bad and dangerous to leave the cgroup in FROZEN or FREEZING
+        let _ = common::write_cgroup_file_unbatched(&state_path, FREEZER_STATE_THAWED);
+    }
+    result.with_context(|| format!("failed to freeze {}", cgroup_path.display()))
+}
+
+pub(crate) fn thaw_v1(cgroup_path: &Path) -> Result<()> {
+    common::write_cgroup_file_unbatched(
+        cgroup_path.join(CGROUP_FREEZER_STATE),
+        FREEZER_STATE_THAWED,
+    )
+}
+
+pub(crate) fn freeze_v2(cgroup_path: &Path, timeout: Duration) -> Result<()> {
+    let freeze_path = cgroup_path.join(CGROUP_FREEZE);
+    let events_path = cgroup_path.join(CGROUP_EVENTS);
+    common::write_cgroup_file_unbatched(&freeze_path, "1")?;
+    if !is_observable() {
+        return Ok(());
+    }
+
+    let result = poll(timeout, |_| {
+        let events = common::read_cgroup_file(&events_path)?;
+        match events.lines().find_map(|line| line.strip_prefix("frozen ")) {
+            Some(frozen) => Ok(frozen.trim() == "1"),
+            None => bail!("{} does not report the frozen state", events_path.display()),
+        }
+    });
+
+    if result.is_err() {
+        let _ = common::write_cgroup_file_unbatched(&freeze_path, "0");
+    }
+    result.with_context(|| format!("failed to freeze {}", cgroup_path.display()))
+}
+
+pub(crate) fn thaw_v2(cgroup_path: &Path) -> Result<()> {
+    common::write_cgroup_file_unbatched(cgroup_path.join(CGROUP_FREEZE), "0")
+}
+
+/// The state cannot be observed while the writes are only recorded
+fn is_observable() -> bool {
+    !dry_run::is_dry_run() && !batch::is_batched()
+}
+
+/// Calls the check until it reports that the cgroup is frozen or fails, or
+/// until the timeout has passed
+fn poll<F: FnMut(u32) -> Result<bool>>(timeout: Duration, mut frozen: F) -> Result<()> {
+    let deadline = Instant::now() + timeout;
+    let mut attempt = 0;
+    loop {
+        if frozen(attempt)? {
+            if attempt > 1 {
+                log::debug!("frozen after {} retries", attempt);
+            }
+            return Ok(());
+        }
+
+        if Instant::now() >= deadline {
+            bail!(
+                "timeout of {} ms reached waiting for the cgroup to freeze",
+                timeout.as_millis()
+            );
+        }
+        attempt += 1;
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{create_temp_dir, set_fixture};
+
+    #[test]
+    fn test_freeze_v2() {
+        let tmp = create_temp_dir("test_freeze_v2").expect("create temp directory for test");
+        set_fixture(&tmp, CGROUP_FREEZE, "0").expect("set fixture for cgroup.freeze");
+        set_fixture(&tmp, CGROUP_EVENTS, "populated 1\nfrozen 1\n")
+            .expect("set fixture for cgroup.events");
+
+        freeze(&tmp, DEFAULT_TIMEOUT).expect("freeze cgroup");
+        let content = std::fs::read_to_string(tmp.join(CGROUP_FREEZE)).expect("read to string");
+        assert_eq!(content, "1");
+
+        thaw(&tmp).expect("thaw cgroup");
+        let content = std::fs::read_to_string(tmp.join(CGROUP_FREEZE)).expect("read to string");
+        assert_eq!(content, "0");
+    }
+
+    #[test]
+    fn test_freeze_timeout() {
+        let tmp = create_temp_dir("test_freeze_timeout").expect("create temp directory for test");
+        set_fixture(&tmp, CGROUP_FREEZE, "0").expect("set fixture for cgroup.freeze");
+        set_fixture(&tmp, CGROUP_EVENTS, "populated 1\nfrozen 0\n")
+            .expect("set fixture for cgroup.events");
+
+        let result = freeze(&tmp, Duration::from_millis(50));
+        assert!(result.is_err());
+        // the cgroup is thawed again
+        let content = std::fs::read_to_string(tmp.join(CGROUP_FREEZE)).expect("read to string");
+        assert_eq!(content, "0");
+    }
+
+    #[test]
+    fn test_freeze_v1() {
+        let tmp = create_temp_dir("test_freeze_v1").expect("create temp directory for test");
+        set_fixture(&tmp, CGROUP_FREEZER_STATE, "").expect("set fixture for freezer state");
+
+        freeze(&tmp, DEFAULT_TIMEOUT).expect("freeze cgroup");
+        let content =
+            std::fs::read_to_string(tmp.join(CGROUP_FREEZER_STATE)).expect("read to string");
+        assert_eq!(content, FREEZER_STATE_FROZEN);
+
+        thaw(&tmp).expect("thaw cgroup");
+        let content =
+            std::fs::read_to_string(tmp.join(CGROUP_FREEZER_STATE)).expect("read to string");
+        assert_eq!(content, FREEZER_STATE_THAWED);
+    }
+
+    #[test]
+    fn test_is_frozen() {
+        let tmp = create_temp_dir("test_is_frozen").expect("create temp directory for test");
+        set_fixture(&tmp, CGROUP_FREEZER_STATE, FREEZER_STATE_THAWED)
+            .expect("set fixture for freezer state");
+        assert!(!is_frozen(&tmp).expect("read freezer state"));
+        set_fixture(&tmp, CGROUP_FREEZER_STATE, FREEZER_STATE_FREEZING)
+            .expect("set fixture for freezer state");
+        assert!(is_frozen(&tmp).expect("read freezer state"));
+
+        set_fixture(&tmp, CGROUP_FREEZE, "1").expect("set fixture for cgroup.freeze");
+        assert!(is_frozen(&tmp).expect("read cgroup.freeze"));
+    }
+}
@@ -0,0 +1,233 @@
+//! Cgroup manager for hybrid hosts. On these most controllers are mounted as
+//! cgroup v1 hierarchies, while the unified hierarchy is mounted at
+//! /sys/fs/cgroup/unified. Controllers that are not mounted as a v1 hierarchy,
+//! e.g. because the kernel has been booted with `cgroup_no_v1=memory`, are
+//! bound to the unified hierarchy instead. The manager routes the resources
+//! and statistics of each controller to the hierarchy it is available on.
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::Result;
+use nix::unistd::Pid;
+
+use crate::{
+    common::{CgroupManager, ControllerOpt, FreezerState},
+    stats::{Stats, StatsFilter},
+    v1::{self, ControllerType as V1ControllerType},
+    v2::{self, controller_type::ControllerType as V2ControllerType},
+};
+
+pub struct Manager {
+    v1: v1::manager::Manager,
+    v2: v2::manager::Manager,
+    /// Controllers that are bound to the unified hierarchy
+    unified_controllers: Vec<V2ControllerType>,
+}
+
+impl Manager {
+    /// Combines the managers of the same cgroup on the v1 hierarchies and on
+    /// the unified hierarchy. The unified controllers are the ones listed in
+    /// cgroup.controllers of the unified mount point, the v1 manager leaves
+    /// their resources to the v2 manager.
+    pub fn new(
+        v1: v1::manager::Manager,
+        v2: v2::manager::Manager,
+        unified_controllers: Vec<V2ControllerType>,
+    ) -> Self {
+        let excluded: Vec<V1ControllerType> = unified_controllers
+            .iter()
+            .flat_map(|controller| v1_controller_types(*controller))
+            .cloned()
+            .collect();
+
+        Self {
+            v1: v1.without_controllers(&excluded),
+            v2,
+            unified_controllers,
+        }
+    }
+
+    fn is_unified(&self, controller: V2ControllerType) -> bool {
+        self.unified_controllers.contains(&controller)
+    }
+
+    /// Splits the filter into the statistics collected from the v1
+    /// hierarchies and the ones collected from the unified hierarchy
+    fn split_filter(&self, filter: &StatsFilter) -> (StatsFilter, StatsFilter) {
+        let mut v1 = *filter;
+        let mut unified = StatsFilter::none();
+        let selections = [
+            (&mut v1.cpu, &mut unified.cpu, V2ControllerType::Cpu),
+            (
+                &mut v1.memory,
+                &mut unified.memory,
+                V2ControllerType::Memory,
+            ),
+            (&mut v1.pids, &mut unified.pids, V2ControllerType::Pids),
+            (&mut v1.blkio, &mut unified.blkio, V2ControllerType::Io),
+            (
+                &mut v1.hugetlb,
+                &mut unified.hugetlb,
+                V2ControllerType::HugeTlb,
+            ),
+            (
+                &mut v1.cpuset,
+                &mut unified.cpuset,
+                V2ControllerType::CpuSet,
+            ),
+            (&mut v1.rdma, &mut unified.rdma, V2ControllerType::Rdma),
+            (&mut v1.misc, &mut unified.misc, V2ControllerType::Misc),
+        ];
+        for (v1_selected, unified_selected, controller) in selections {
+            if *v1_selected && self.is_unified(controller) {
+                *v1_selected = false;
+                *unified_selected = true;
+            }
+        }
+
+        (v1, unified)
+    }
+
+    fn stats_from_both(&self, filter: &StatsFilter, recursive: bool) -> Result<Stats> {
+        let (v1_filter, unified_filter) = self.split_filter(filter);
+        let mut stats = if recursive {
+            // the recursive statistics are only needed for the v1 counters,
+            // so all v1 statistics are collected
+            self.v1.stats_recursive()?
+        } else {
+            self.v1.stats_with(&v1_filter)?
+        };
+
+        if unified_filter != StatsFilter::none() {
+            let unified = self.v2.stats_with(&unified_filter)?;
+            merge_stats(&mut stats, unified, &unified_filter);
+        }
+
+        Ok(stats)
+    }
+}
+
+/// Returns the v1 controllers whose resources are handled by the v2 controller
+pub(crate) fn v1_controller_types(controller: V2ControllerType) -> &'static [V1ControllerType] {
+    match controller {
+        V2ControllerType::Cpu => &[V1ControllerType::Cpu, V1ControllerType::CpuAcct],
+        V2ControllerType::CpuSet => &[V1ControllerType::CpuSet],
+        V2ControllerType::Io => &[V1ControllerType::Blkio],
+        V2ControllerType::Memory => &[V1ControllerType::Memory],
+        V2ControllerType::HugeTlb => &[V1ControllerType::HugeTlb],
+        V2ControllerType::Pids => &[V1ControllerType::Pids],
+        V2ControllerType::Rdma => &[V1ControllerType::Rdma],
+        V2ControllerType::Misc => &[],
+    }
+}
+
+/// Replaces the statistics that are selected by the filter with the ones of
+/// the unified hierarchy
+fn merge_stats(stats: &mut Stats, unified: Stats, filter: &StatsFilter) {
+    if filter.cpu {
+        stats.cpu = unified.cpu;
+    }
+    if filter.memory {
+        stats.memory = unified.memory;
+    }
+    if filter.pids {
+        stats.pids = unified.pids;
+    }
+    if filter.blkio {
+        stats.blkio = unified.blkio;
+    }
+    if filter.hugetlb {
+        stats.hugetlb = unified.hugetlb;
+    }
+    if filter.cpuset {
+        stats.cpuset = unified.cpuset;
+    }
+    if filter.rdma {
+        stats.rdma = unified.rdma;
+    }
+    if filter.misc {
+        stats.misc = unified.misc;
+    }
+    stats.unavailable.extend(unified.unavailable);
+    stats.diagnostics.extend(unified.diagnostics);
+}
+
+impl CgroupManager for Manager {
+    fn add_task(&self, pid: Pid) -> Result<()> {
+        self.v1.add_task(pid)?;
+        self.v2.add_task(pid)
+    }
+
+    fn apply(&self, controller_opt: &ControllerOpt) -> Result<()> {
+        self.v1.apply(controller_opt)?;
+        self.v2
+            .apply_controllers(controller_opt, &self.unified_controllers)
+    }
+
+    fn remove(&self) -> Result<()> {
+        self.v1.remove()?;
+        self.v2.remove()
+    }
+
+    fn freeze(&self, state: FreezerState) -> Result<()> {
+        self.v1.freeze(state)
+    }
+
+    fn stats_with(&self, filter: &StatsFilter) -> Result<Stats> {
+        self.stats_from_both(filter, false)
+    }
+
+    fn stats_recursive(&self) -> Result<Stats> {
+        self.stats_from_both(&StatsFilter::default(), true)
+    }
+
+    fn get_cgroup_paths(&self) -> HashMap<String, PathBuf> {
+        let mut paths = self.v1.get_cgroup_paths();
+        paths.extend(self.v2.get_cgroup_paths());
+        paths
+    }
+
+    fn get_all_pids(&self) -> Result<Vec<Pid>> {
+        self.v1.get_all_pids()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::Bytes;
+
+    #[test]
+    fn test_v1_controller_types() {
+        assert_eq!(
+            v1_controller_types(V2ControllerType::Cpu),
+            &[V1ControllerType::Cpu, V1ControllerType::CpuAcct]
+        );
+        assert_eq!(
+            v1_controller_types(V2ControllerType::Io),
+            &[V1ControllerType::Blkio]
+        );
+        assert!(v1_controller_types(V2ControllerType::Misc).is_empty());
+    }
+
+    #[test]
+    fn test_merge_stats() {
+        let mut stats = Stats::default();
+        stats.pids.current = 3;
+        stats.memory.memory.usage = Bytes(100);
+
+        let mut unified = Stats::default();
+        unified.pids.current = 5;
+        unified.memory.memory.usage = Bytes(200);
+        unified.unavailable.push("memory".to_owned());
+
+        let filter = StatsFilter {
+            memory: true,
+            ..StatsFilter::none()
+        };
+        merge_stats(&mut stats, unified, &filter);
+
+        assert_eq!(stats.pids.current, 3);
+        assert_eq!(stats.memory.memory.usage.0, 200);
+        assert_eq!(stats.unavailable, vec!["memory".to_owned()]);
+    }
+}
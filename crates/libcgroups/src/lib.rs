@@ -6,8 +6,22 @@
 extern crate quickcheck;
 mod test;
 
+pub mod batch;
 pub mod common;
+pub mod containerd;
+pub mod convert;
+pub mod dry_run;
+pub mod extension;
+pub mod freezer;
+#[cfg(all(feature = "v1", feature = "v2"))]
+pub mod hybrid;
+pub mod mountinfo;
+pub mod oom;
+pub mod process_stats;
+pub mod reader;
 pub mod stats;
+pub mod stats_cache;
+pub mod stats_delta;
 #[cfg(feature = "systemd")]
 pub mod systemd;
 pub mod test_manager;
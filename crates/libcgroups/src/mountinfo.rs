@@ -0,0 +1,465 @@
+//! Parsers for /proc/[pid]/mountinfo and /proc/[pid]/cgroup.
+//!
+//! Fields are decoded the same way the kernel encodes them, i.e. paths may
+//! contain octal escapes for whitespace and backslashes, the number of optional
+//! fields varies and superblock options can carry arbitrary values. Malformed
+//! lines result in an error that points to the offending line instead of a panic.
+use std::{
+    ffi::OsString,
+    fs,
+    os::unix::ffi::OsStringExt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+/// A line of a mountinfo file, see proc(5)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MountInfo {
+    /// Unique id of the mount
+    pub mount_id: u32,
+    /// Id of the parent mount
+    pub parent_id: u32,
+    /// Major device number of the filesystem
+    pub major: u32,
+    /// Minor device number of the filesystem
+    pub minor: u32,
+    /// Directory of the filesystem that forms the root of the mount
+    pub root: PathBuf,
+    /// Mount point relative to the root of the process
+    pub mount_point: PathBuf,
+    /// Per mount options
+    pub mount_options: Vec<String>,
+    /// Optional fields like shared:X or master:X
+    pub optional_fields: Vec<String>,
+    /// Type of the filesystem, including the subtype for fuse
+    pub fs_type: String,
+    /// Filesystem specific source, if there is one
+    pub mount_source: Option<String>,
+    /// Per superblock options, with the value if the option has one
+    pub super_options: Vec<(String, Option<String>)>,
+}
+
+impl MountInfo {
+    /// Checks if the superblock options contain the option
+    pub fn has_super_option(&self, option: &str) -> bool {
+        self.super_options.iter().any(|(key, _)| key == option)
+    }
+
+    /// Returns the value of a superblock option like name=systemd
+    pub fn super_option_value(&self, option: &str) -> Option<&str> {
+        self.super_options
+            .iter()
+            .find(|(key, _)| key == option)
+            .and_then(|(_, value)| value.as_deref())
+    }
+}
+
+/// A line of a /proc/[pid]/cgroup file, see cgroups(7)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessCgroup {
+    /// Id of the hierarchy, which is 0 for cgroup v2
+    pub hierarchy_id: u32,
+    /// Controllers or named hierarchies bound to the hierarchy. Empty for cgroup v2.
+    pub controllers: Vec<String>,
+    /// Path of the cgroup relative to the root of the hierarchy
+    pub path: PathBuf,
+}
+
+/// Reads and parses the mountinfo file of the current process
+pub fn mountinfo() -> Result<Vec<MountInfo>> {
+    read_mountinfo("/proc/self/mountinfo")
+}
+
+/// Reads and parses a mountinfo file
+pub fn read_mountinfo<P: AsRef<Path>>(path: P) -> Result<Vec<MountInfo>> {
+    let path = path.as_ref();
+    let content = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    parse_mountinfo(&content).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+/// Parses the content of a mountinfo file. The content is not required to be
+/// valid utf-8, as paths can contain arbitrary bytes.
+pub fn parse_mountinfo(content: &[u8]) -> Result<Vec<MountInfo>> {
+    content
+        .split(|b| *b == b'\n')
+        .enumerate()
+        .filter(|(_, line)| !line.iter().all(u8::is_ascii_whitespace))
+        .map(|(index, line)| {
+            parse_mountinfo_line(line).with_context(|| {
+                format!(
+                    "invalid line {}: {:?}",
+                    index + 1,
+                    String::from_utf8_lossy(line)
+                )
+            })
+        })
+        .collect()
+}
+
+/// Parses a single line of a mountinfo file, e.g.
+/// `36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue`
+pub fn parse_mountinfo_line(line: &[u8]) -> Result<MountInfo> {
+    let mut fields = line
+        .split(u8::is_ascii_whitespace)
+        .filter(|field| !field.is_empty());
+    let mut next = |name: &str| {
+        fields
+            .next()
+            .with_context(|| format!("missing field {}", name))
+    };
+
+    let mount_id = text(next("mount id")?)?
+        .parse()
+        .context("invalid mount id")?;
+    let parent_id = text(next("parent id")?)?
+        .parse()
+        .context("invalid parent id")?;
+    let (major, minor) = text(next("major:minor")?)?
+        .split_once(':')
+        .context("invalid major:minor")?;
+    let major = major.parse().context("invalid major device number")?;
+    let minor = minor.parse().context("invalid minor device number")?;
+    let root = unescape_path(next("root")?);
+    let mount_point = unescape_path(next("mount point")?);
+    let mount_options = split_options(next("mount options")?);
+
+    // the optional fields are terminated by a single hyphen
+    let mut optional_fields = Vec::new();
+    loop {
+        match next("separator")? {
+            b"-" => break,
+            field => optional_fields.push(String::from_utf8_lossy(field).into_owned()),
+        }
+    }
+
+    let fs_type = unescape(next("filesystem type")?);
+    // the source is missing on some older kernels if it is empty
+    let remaining: Vec<&[u8]> = fields.collect();
+    let (mount_source, super_options) = match remaining[..] {
+        [source, options] => (Some(unescape(source)), options),
+        [options] => (None, options),
+        [] => bail!("missing field super options"),
+        _ => bail!(
+            "unexpected trailing fields {:?}",
+            remaining[2..]
+                .iter()
+                .map(|field| String::from_utf8_lossy(field))
+                .collect::<Vec<_>>()
+        ),
+    };
+    let super_options = split_options(super_options)
+        .into_iter()
+        .map(|option| match option.split_once('=') {
+            Some((key, value)) => (key.to_owned(), Some(value.to_owned())),
+            None => (option, None),
+        })
+        .collect();
+
+    Ok(MountInfo {
+        mount_id,
+        parent_id,
+        major,
+        minor,
+        root,
+        mount_point,
+        mount_options,
+        optional_fields,
+        fs_type,
+        mount_source,
+        super_options,
+    })
+}
+
+/// Reads and parses the cgroup file of the current process
+pub fn process_cgroups() -> Result<Vec<ProcessCgroup>> {
+    read_process_cgroups("/proc/self/cgroup")
+}
+
+/// Reads and parses a /proc/[pid]/cgroup file
+pub fn read_process_cgroups<P: AsRef<Path>>(path: P) -> Result<Vec<ProcessCgroup>> {
+    let path = path.as_ref();
+    let content = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    parse_process_cgroups(&content).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+/// Parses the content of a /proc/[pid]/cgroup file
+pub fn parse_process_cgroups(content: &[u8]) -> Result<Vec<ProcessCgroup>> {
+    content
+        .split(|b| *b == b'\n')
+        .enumerate()
+        .filter(|(_, line)| !line.iter().all(u8::is_ascii_whitespace))
+        .map(|(index, line)| {
+            parse_process_cgroup_line(line).with_context(|| {
+                format!(
+                    "invalid line {}: {:?}",
+                    index + 1,
+                    String::from_utf8_lossy(line)
+                )
+            })
+        })
+        .collect()
+}
+
+/// Parses a single line of a /proc/[pid]/cgroup file, e.g. `4:cpu,cpuacct:/user.slice`
+pub fn parse_process_cgroup_line(line: &[u8]) -> Result<ProcessCgroup> {
+    // the path is the last field and may itself contain colons
+    let mut fields = line.splitn(3, |b| *b == b':');
+    let hierarchy_id = text(fields.next().context("missing hierarchy id")?)?
+        .parse()
+        .context("invalid hierarchy id")?;
+    let controllers = String::from_utf8_lossy(fields.next().context("missing controllers")?)
+        .split(',')
+        .filter(|c| !c.is_empty())
+        .map(|c| c.to_owned())
+        .collect();
+    let path = PathBuf::from(OsString::from_vec(
+        fields.next().context("missing cgroup path")?.to_vec(),
+    ));
+    if !path.is_absolute() {
+        bail!("cgroup path {} is not absolute", path.display());
+    }
+
+    Ok(ProcessCgroup {
+        hierarchy_id,
+        controllers,
+        path,
+    })
+}
+
+/// Returns a field that has to be valid utf-8, like a number
+fn text(field: &[u8]) -> Result<&str> {
+    std::str::from_utf8(field)
+        .with_context(|| format!("invalid field {:?}", String::from_utf8_lossy(field)))
+}
+
+fn split_options(options: &[u8]) -> Vec<String> {
+    // escaped commas are encoded as \054, so splitting before unescaping is safe
+    options
+        .split(|b| *b == b',')
+        .filter(|option| !option.is_empty())
+        .map(unescape)
+        .collect()
+}
+
+fn unescape(field: &[u8]) -> String {
+    String::from_utf8_lossy(&unescape_bytes(field)).into_owned()
+}
+
+fn unescape_path(field: &[u8]) -> PathBuf {
+    // paths are not necessarily valid utf-8
+    PathBuf::from(OsString::from_vec(unescape_bytes(field)))
+}
+
+/// Decodes the octal escapes (e.g. \040 for a space) the kernel uses for
+/// whitespace and backslashes. Anything that is not a valid escape is kept as is.
+fn unescape_bytes(bytes: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 3 < bytes.len() && is_octal_escape(&bytes[i + 1..i + 4]) {
+            let value = bytes[i + 1..i + 4]
+                .iter()
+                .fold(0u32, |acc, digit| acc * 8 + u32::from(digit - b'0'));
+            result.push(value as u8);
+            i += 4;
+        } else {
+            result.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    result
+}
+
+fn is_octal_escape(digits: &[u8]) -> bool {
+    // values above \377 do not fit into a byte
+    digits.len() == 3 && digits[0] <= b'3' && digits.iter().all(|d| (b'0'..=b'7').contains(d))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mountinfo_line() -> Result<()> {
+        let info = parse_mountinfo_line(
+            b"36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue",
+        )?;
+        assert_eq!(
+            info,
+            MountInfo {
+                mount_id: 36,
+                parent_id: 35,
+                major: 98,
+                minor: 0,
+                root: PathBuf::from("/mnt1"),
+                mount_point: PathBuf::from("/mnt2"),
+                mount_options: vec!["rw".to_owned(), "noatime".to_owned()],
+                optional_fields: vec!["master:1".to_owned()],
+                fs_type: "ext3".to_owned(),
+                mount_source: Some("/dev/root".to_owned()),
+                super_options: vec![
+                    ("rw".to_owned(), None),
+                    ("errors".to_owned(), Some("continue".to_owned()))
+                ],
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_mountinfo_optional_fields() -> Result<()> {
+        let none = parse_mountinfo_line(b"25 1 0:22 / /sys rw - sysfs sysfs rw")?;
+        assert!(none.optional_fields.is_empty());
+
+        let many = parse_mountinfo_line(
+            b"30 25 0:26 / /sys/fs/cgroup/systemd rw shared:9 master:2 propagate_from:1 unbindable - \
+             cgroup cgroup rw,xattr,name=systemd",
+        )?;
+        assert_eq!(
+            many.optional_fields,
+            vec!["shared:9", "master:2", "propagate_from:1", "unbindable"]
+        );
+        assert_eq!(many.super_option_value("name"), Some("systemd"));
+        assert!(many.has_super_option("xattr"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_mountinfo_escapes() -> Result<()> {
+        let info = parse_mountinfo_line(
+            br"40 25 8:1 /dir\040with\011tab /mnt/a\134b\040c rw - ext4 /dev/my\040disk rw,opt=a\054b",
+        )?;
+        assert_eq!(info.root, PathBuf::from("/dir with\ttab"));
+        assert_eq!(info.mount_point, PathBuf::from(r"/mnt/a\b c"));
+        assert_eq!(info.mount_source.as_deref(), Some("/dev/my disk"));
+        assert_eq!(info.super_option_value("opt"), Some("a,b"));
+
+        // invalid escapes are kept
+        let info = parse_mountinfo_line(br"40 25 8:1 / /mnt\9\40 rw - ext4 /dev/sda rw")?;
+        assert_eq!(info.mount_point, PathBuf::from(r"/mnt\9\40"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_mountinfo_exotic_super_options() -> Result<()> {
+        let info = parse_mountinfo_line(
+            b"50 25 0:45 / /merged rw,relatime - overlay overlay \
+             rw,lowerdir=/l1:/l2,upperdir=/u,workdir=/w,redirect_dir=on,,index=off",
+        )?;
+        assert_eq!(info.super_option_value("lowerdir"), Some("/l1:/l2"));
+        assert_eq!(info.super_option_value("index"), Some("off"));
+        assert_eq!(info.super_options.len(), 6);
+
+        let fuse = parse_mountinfo_line(
+            b"60 25 0:50 / /home/user/mnt rw,nosuid - fuse.sshfs user@host:/path rw,user_id=1000,key==value",
+        )?;
+        assert_eq!(fuse.fs_type, "fuse.sshfs");
+        assert_eq!(fuse.super_option_value("key"), Some("=value"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_mountinfo_non_utf8() -> Result<()> {
+        let info = parse_mountinfo_line(b"80 25 8:1 /caf\xe9 /mnt/\xff rw - ext4 /dev/sda rw")?;
+        assert_eq!(
+            info.root,
+            PathBuf::from(OsString::from_vec(b"/caf\xe9".to_vec()))
+        );
+        assert_eq!(
+            info.mount_point,
+            PathBuf::from(OsString::from_vec(b"/mnt/\xff".to_vec()))
+        );
+
+        let cgroup = parse_process_cgroup_line(b"0::/caf\xe9.slice")?;
+        assert_eq!(
+            cgroup.path,
+            PathBuf::from(OsString::from_vec(b"/caf\xe9.slice".to_vec()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_mountinfo_missing_source() -> Result<()> {
+        let info = parse_mountinfo_line(b"70 25 0:60 / /mnt rw - tmpfs rw,size=64k")?;
+        assert_eq!(info.mount_source, None);
+        assert_eq!(info.super_option_value("size"), Some("64k"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_mountinfo_malformed() {
+        for line in [
+            "",
+            "36",
+            "x 35 98:0 / /mnt rw - ext3 /dev/root rw",
+            "36 35 98 / /mnt rw - ext3 /dev/root rw",
+            "36 35 98:0 / /mnt rw master:1 ext3 /dev/root rw",
+            "36 35 98:0 / /mnt rw -",
+            "36 35 98:0 / /mnt rw - ext3 /dev/root rw extra",
+        ] {
+            assert!(parse_mountinfo_line(line.as_bytes()).is_err(), "{:?}", line);
+        }
+
+        let err = parse_mountinfo(b"25 1 0:22 / /sys rw - sysfs sysfs rw\ngarbage\n").unwrap_err();
+        assert!(format!("{:?}", err).contains("invalid line 2"));
+    }
+
+    #[test]
+    fn test_parse_process_cgroups() -> Result<()> {
+        let cgroups = parse_process_cgroups(
+            b"12:cpu,cpuacct:/user.slice\n1:name=systemd:/user.slice/session-1.scope\n0::/a:b\n",
+        )?;
+        assert_eq!(
+            cgroups,
+            vec![
+                ProcessCgroup {
+                    hierarchy_id: 12,
+                    controllers: vec!["cpu".to_owned(), "cpuacct".to_owned()],
+                    path: PathBuf::from("/user.slice"),
+                },
+                ProcessCgroup {
+                    hierarchy_id: 1,
+                    controllers: vec!["name=systemd".to_owned()],
+                    path: PathBuf::from("/user.slice/session-1.scope"),
+                },
+                ProcessCgroup {
+                    hierarchy_id: 0,
+                    controllers: vec![],
+                    path: PathBuf::from("/a:b"),
+                },
+            ]
+        );
+
+        assert!(parse_process_cgroup_line(b"x:cpu:/").is_err());
+        assert!(parse_process_cgroup_line(b"1:cpu").is_err());
+        assert!(parse_process_cgroup_line(b"1:cpu:relative").is_err());
+        Ok(())
+    }
+
+    quickcheck! {
+        fn property_test_parse_mountinfo_never_panics(content: String) -> bool {
+            let _ = parse_mountinfo(content.as_bytes());
+            let _ = parse_process_cgroups(content.as_bytes());
+            true
+        }
+
+        fn property_test_parse_mountinfo_bytes_never_panics(content: Vec<u8>) -> bool {
+            let _ = parse_mountinfo(&content);
+            let _ = parse_process_cgroups(&content);
+            true
+        }
+
+        fn property_test_unescape_roundtrip(path: String) -> bool {
+            let escaped: String = path
+                .chars()
+                .map(|c| match c {
+                    ' ' | '\t' | '\n' | '\\' => format!("\\{:03o}", c as u32),
+                    c => c.to_string(),
+                })
+                .collect();
+            unescape(escaped.as_bytes()) == path
+        }
+    }
+}
@@ -0,0 +1,350 @@
+//! Notifies about the oom killer being invoked for a cgroup. On cgroup v1 an
+//! eventfd is registered for memory.oom_control through cgroup.event_control,
+//! which the kernel signals on every oom and once the cgroup is removed. On
+//! cgroup v2 memory.events is watched with inotify, as the kernel notifies
+//! about modifications of the file whenever one of its counters changes, and
+//! the oom_kill counter is compared to its previous value.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use libcgroups::oom::{OomNotification, OomNotifier};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let mut notifier = OomNotifier::v2(Path::new("/sys/fs/cgroup/youki/container"))?;
+//! while notifier.wait(None)? == OomNotification::Oom {
+//!     println!("the oom killer has been invoked");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    os::unix::io::{AsRawFd, FromRawFd, RawFd},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use nix::{
+    errno::Errno,
+    poll::{poll, PollFd, PollFlags},
+    sys::{
+        eventfd::{eventfd, EfdFlags},
+        inotify::{AddWatchFlags, InitFlags, Inotify},
+    },
+};
+
+use crate::stats::{self, MemoryEvents};
+
+const CGROUP_EVENT_CONTROL: &str = "cgroup.event_control";
+const CGROUP_MEMORY_OOM_CONTROL: &str = "memory.oom_control";
+const CGROUP_MEMORY_EVENTS: &str = "memory.events";
+
+/// Result of waiting for an oom notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OomNotification {
+    /// The oom killer has been invoked for the cgroup
+    Oom,
+    /// The cgroup has been removed, there will be no further notifications
+    Removed,
+    /// Nothing happened before the timeout expired
+    Timeout,
+}
+
+enum Source {
+    V1 {
+        event_fd: File,
+        // the kernel only signals the eventfd as long as the file is open
+        _oom_control: File,
+        cgroup_path: PathBuf,
+    },
+    V2 {
+        inotify: Inotify,
+        events_path: PathBuf,
+        oom_kills: u64,
+    },
+}
+
+pub struct OomNotifier {
+    source: Source,
+}
+
+impl OomNotifier {
+    /// Registers for the oom notifications of the memory cgroup among the
+    /// paths returned by `CgroupManager::get_cgroup_paths`. The memory
+    /// hierarchy of cgroup v1 is preferred, so that the memory cgroup of
+    /// hybrid hosts is found on the hierarchy it is attached to.
+    pub fn new(cgroup_paths: &HashMap<String, PathBuf>) -> Result<Self> {
+        if let Some(path) = cgroup_paths.get("memory") {
+            return Self::v1(path);
+        }
+        if let Some(path) = cgroup_paths.get("unified") {
+            return Self::v2(path);
+        }
+
+        bail!("no memory cgroup to receive oom notifications from")
+    }
+
+    /// Registers an eventfd for the oom notifications of a cgroup v1 memory
+    /// cgroup
+    pub fn v1(cgroup_path: &Path) -> Result<Self> {
+        let oom_control_path = cgroup_path.join(CGROUP_MEMORY_OOM_CONTROL);
+        let oom_control = File::open(&oom_control_path)
+            .with_context(|| format!("failed to open {}", oom_control_path.display()))?;
+        let event_fd = eventfd(0, EfdFlags::EFD_CLOEXEC | EfdFlags::EFD_NONBLOCK)
+            .context("failed to create eventfd")?;
+        // the file closes the eventfd, also if the registration fails
+        let event_fd = unsafe { File::from_raw_fd(event_fd) };
+
+        let event_control_path = cgroup_path.join(CGROUP_EVENT_CONTROL);
+        OpenOptions::new()
+            .write(true)
+            .open(&event_control_path)
+            .and_then(|mut file| {
+                file.write_all(
+                    format!("{} {}", event_fd.as_raw_fd(), oom_control.as_raw_fd()).as_bytes(),
+                )
+            })
+            .with_context(|| {
+                format!(
+                    "failed to register for oom notifications in {}",
+                    event_control_path.display()
+                )
+            })?;
+
+        Ok(Self {
+            source: Source::V1 {
+                event_fd,
+                _oom_control: oom_control,
+                cgroup_path: cgroup_path.to_path_buf(),
+            },
+        })
+    }
+
+    /// Watches memory.events of a cgroup v2 cgroup for oom kills. Only the
+    /// kills after the registration are notified.
+    pub fn v2(cgroup_path: &Path) -> Result<Self> {
+        let events_path = cgroup_path.join(CGROUP_MEMORY_EVENTS);
+        // the watch is added before the counter is read, so that no kill
+        // between the two is missed
+        let inotify = Inotify::init(InitFlags::IN_CLOEXEC | InitFlags::IN_NONBLOCK)?;
+        inotify
+            .add_watch(
+                &events_path,
+                AddWatchFlags::IN_MODIFY | AddWatchFlags::IN_DELETE_SELF,
+            )
+            .with_context(|| format!("failed to watch {}", events_path.display()))?;
+        let oom_kills = read_oom_kills(&events_path)?;
+
+        Ok(Self {
+            source: Source::V2 {
+                inotify,
+                events_path,
+                oom_kills,
+            },
+        })
+    }
+
+    /// Blocks until the oom killer is invoked for the cgroup, the cgroup is
+    /// removed or the timeout expires. Without a timeout it blocks until one
+    /// of the first two happens. Changes of other memory events on cgroup v2
+    /// end the wait early with `OomNotification::Timeout` as well.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> Result<OomNotification> {
+        let timeout = timeout.map_or(-1, |t| t.as_millis().min(i32::MAX as u128) as i32);
+        let mut fds = [PollFd::new(self.as_raw_fd(), PollFlags::POLLIN)];
+        loop {
+            match poll(&mut fds, timeout) {
+                Ok(0) => return Ok(OomNotification::Timeout),
+                Ok(_) => break,
+                Err(Errno::EINTR) => continue,
+                Err(err) => return Err(err).context("failed to wait for oom notifications"),
+            }
+        }
+
+        match &mut self.source {
+            Source::V1 {
+                event_fd,
+                cgroup_path,
+                ..
+            } => {
+                let mut counter = [0; 8];
+                match event_fd.read_exact(&mut counter) {
+                    Ok(()) => {}
+                    Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                        return Ok(OomNotification::Timeout)
+                    }
+                    Err(err) => return Err(err).context("failed to read oom eventfd"),
+                }
+                // the eventfd is signaled as well when the cgroup is removed
+                if !cgroup_path.join(CGROUP_EVENT_CONTROL).exists() {
+                    return Ok(OomNotification::Removed);
+                }
+                Ok(OomNotification::Oom)
+            }
+            Source::V2 {
+                inotify,
+                events_path,
+                oom_kills,
+            } => {
+                let events = match inotify.read_events() {
+                    Ok(events) => events,
+                    Err(Errno::EAGAIN) => return Ok(OomNotification::Timeout),
+                    Err(err) => return Err(err).context("failed to read inotify events"),
+                };
+                if events.iter().any(|event| {
+                    event
+                        .mask
+                        .intersects(AddWatchFlags::IN_DELETE_SELF | AddWatchFlags::IN_IGNORED)
+                }) || !events_path.exists()
+                {
+                    return Ok(OomNotification::Removed);
+                }
+
+                // memory.events is modified on changes of every counter
+                let current = read_oom_kills(events_path)?;
+                if current > *oom_kills {
+                    *oom_kills = current;
+                    return Ok(OomNotification::Oom);
+                }
+                Ok(OomNotification::Timeout)
+            }
+        }
+    }
+}
+
+impl AsRawFd for OomNotifier {
+    /// Returns the file descriptor that becomes readable on notifications,
+    /// so that the notifier can be polled together with other descriptors
+    fn as_raw_fd(&self) -> RawFd {
+        match &self.source {
+            Source::V1 { event_fd, .. } => event_fd.as_raw_fd(),
+            Source::V2 { inotify, .. } => inotify.as_raw_fd(),
+        }
+    }
+}
+
+/// Returns how often the oom killer has killed a process of the memory cgroup
+/// among the paths returned by `CgroupManager::get_cgroup_paths`, which needs
+/// neither a notifier nor a running monitor. The counter is only reported
+/// since kernel 4.13 on cgroup v1.
+pub fn oom_kills(cgroup_paths: &HashMap<String, PathBuf>) -> Result<u64> {
+    if let Some(path) = cgroup_paths.get("memory") {
+        return read_oom_kills(&path.join(CGROUP_MEMORY_OOM_CONTROL));
+    }
+    if let Some(path) = cgroup_paths.get("unified") {
+        return read_oom_kills(&path.join(CGROUP_MEMORY_EVENTS));
+    }
+
+    bail!("no memory cgroup to read oom kills from")
+}
+
+/// Reads the oom_kill counter of memory.events or memory.oom_control, which
+/// are both flat keyed
+fn read_oom_kills(events_path: &Path) -> Result<u64> {
+    let events = stats::parse_flat_keyed_data(events_path)
+        .with_context(|| format!("failed to read {}", events_path.display()))?;
+    Ok(MemoryEvents::from(&events).oom_kill)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{create_temp_dir, set_fixture};
+
+    #[test]
+    fn test_register_v1() -> Result<()> {
+        let tmp = create_temp_dir("test_register_oom_v1")?;
+        set_fixture(
+            &tmp,
+            CGROUP_MEMORY_OOM_CONTROL,
+            "oom_kill_disable 0\nunder_oom 0\n",
+        )?;
+        set_fixture(&tmp, CGROUP_EVENT_CONTROL, "")?;
+
+        let mut notifier = OomNotifier::v1(&tmp)?;
+        let registration = std::fs::read_to_string(tmp.join(CGROUP_EVENT_CONTROL))?;
+        let fds: Vec<RawFd> = registration
+            .split(' ')
+            .map(|fd| fd.parse())
+            .collect::<Result<_, _>>()?;
+        assert_eq!(fds.len(), 2);
+        assert_eq!(fds[0], notifier.as_raw_fd());
+
+        assert_eq!(
+            notifier.wait(Some(Duration::ZERO))?,
+            OomNotification::Timeout
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_notify_v2() -> Result<()> {
+        let tmp = create_temp_dir("test_notify_oom_v2")?;
+        set_fixture(
+            &tmp,
+            CGROUP_MEMORY_EVENTS,
+            "low 0\nhigh 0\nmax 1\noom 1\noom_kill 1\n",
+        )?;
+
+        let mut notifier = OomNotifier::v2(&tmp)?;
+        assert_eq!(
+            notifier.wait(Some(Duration::ZERO))?,
+            OomNotification::Timeout
+        );
+
+        // changes of other counters are not notified
+        set_fixture(
+            &tmp,
+            CGROUP_MEMORY_EVENTS,
+            "low 0\nhigh 5\nmax 1\noom 1\noom_kill 1\n",
+        )?;
+        assert_eq!(
+            notifier.wait(Some(Duration::from_secs(1)))?,
+            OomNotification::Timeout
+        );
+
+        set_fixture(
+            &tmp,
+            CGROUP_MEMORY_EVENTS,
+            "low 0\nhigh 5\nmax 2\noom 2\noom_kill 2\n",
+        )?;
+        assert_eq!(
+            notifier.wait(Some(Duration::from_secs(1)))?,
+            OomNotification::Oom
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_oom_kills() -> Result<()> {
+        let v1 = create_temp_dir("test_oom_kills_v1")?;
+        set_fixture(
+            &v1,
+            CGROUP_MEMORY_OOM_CONTROL,
+            "oom_kill_disable 0\nunder_oom 0\noom_kill 2\n",
+        )?;
+        let v2 = create_temp_dir("test_oom_kills_v2")?;
+        set_fixture(
+            &v2,
+            CGROUP_MEMORY_EVENTS,
+            "low 0\nhigh 0\nmax 1\noom 1\noom_kill 1\n",
+        )?;
+
+        let paths = HashMap::from([("memory".to_owned(), v1.to_path_buf())]);
+        assert_eq!(oom_kills(&paths)?, 2);
+        let paths = HashMap::from([("unified".to_owned(), v2.to_path_buf())]);
+        assert_eq!(oom_kills(&paths)?, 1);
+        assert!(oom_kills(&HashMap::new()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_new_without_memory_cgroup() {
+        let paths = HashMap::from([("pids".to_owned(), PathBuf::from("/sys/fs/cgroup/pids"))]);
+        assert!(OomNotifier::new(&paths).is_err());
+    }
+}
@@ -0,0 +1,212 @@
+//! Resource usage of the individual processes of a cgroup. The controllers
+//! only account the usage of the cgroup as a whole, so the processes are
+//! enumerated from cgroup.procs and their usage is read from /proc, which is
+//! what ps like tooling needs to find the process that is responsible for
+//! the usage of a container.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use libcgroups::{process_stats::Processes, stats::StatsProvider};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! for process in Processes::stats(Path::new("/sys/fs/cgroup/youki/container"))? {
+//!     println!("{} {} {}", process.pid, process.name, process.rss);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use nix::unistd::{sysconf, SysconfVar};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::CGROUP_PROCS,
+    reader::CgroupReader,
+    stats::{self, Bytes, Nanoseconds, StatsError, StatsProvider},
+};
+
+const PROC_ROOT: &str = "/proc";
+
+/// Reports the resource usage of a process of a cgroup
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessStats {
+    /// Id of the process
+    pub pid: i32,
+    /// Id of the parent process
+    pub ppid: i32,
+    /// Name of the executable, which the kernel truncates to 15 characters
+    pub name: String,
+    /// State of the process, e.g. R for running or S for sleeping
+    pub state: String,
+    /// Number of threads of the process
+    pub threads: u64,
+    /// Cpu time consumed in user mode
+    pub user_time: Nanoseconds,
+    /// Cpu time consumed in kernel mode
+    pub system_time: Nanoseconds,
+    /// Time after boot at which the process has been started
+    pub start_time: Nanoseconds,
+    /// Resident set size, which is zero for kernel threads
+    pub rss: Bytes,
+    /// Peak resident set size
+    pub rss_peak: Bytes,
+    /// Size of the virtual address space
+    pub virtual_memory: Bytes,
+}
+
+/// Provides the statistics of the processes of a cgroup. Processes that exit
+/// while their statistics are read are left out.
+pub struct Processes {}
+
+impl StatsProvider for Processes {
+    type Stats = Vec<ProcessStats>;
+
+    fn stats_from(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<Self::Stats> {
+        let ticks_per_second = sysconf(SysconfVar::CLK_TCK)?
+            .ok_or_else(|| anyhow!("failed to determine clock ticks per second"))?
+            as u64;
+
+        let procs_path = cgroup_path.join(CGROUP_PROCS);
+        let mut processes = Vec::new();
+        for line in reader.read(&procs_path)?.lines() {
+            let pid = line
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid pid {} in {}", line, procs_path.display()))?;
+            match process_stats(reader, pid, ticks_per_second) {
+                Ok(process) => processes.push(process),
+                Err(err)
+                    if matches!(
+                        stats::stats_error(&err),
+                        Some(StatsError::MissingFile { .. })
+                    ) =>
+                {
+                    log::debug!("process {} exited while reading its stats", pid);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(processes)
+    }
+}
+
+fn proc_path(pid: i32, file: &str) -> PathBuf {
+    Path::new(PROC_ROOT).join(pid.to_string()).join(file)
+}
+
+/// Reads the statistics of a process from /proc/<pid>/stat and /proc/<pid>/status
+fn process_stats(
+    reader: &dyn CgroupReader,
+    pid: i32,
+    ticks_per_second: u64,
+) -> Result<ProcessStats> {
+    let stat_path = proc_path(pid, "stat");
+    let mut process = parse_proc_stat(&reader.read(&stat_path)?, ticks_per_second)
+        .with_context(|| format!("failed to parse {}", stat_path.display()))?;
+
+    let status_path = proc_path(pid, "status");
+    let status = reader.read(&status_path)?;
+    for line in status.lines() {
+        let (key, value) = match line.split_once(':') {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let field = match key {
+            "VmRSS" => &mut process.rss,
+            "VmHWM" => &mut process.rss_peak,
+            "VmSize" => &mut process.virtual_memory,
+            _ => continue,
+        };
+        // the sizes are reported in kB
+        let size = value.trim().trim_end_matches("kB").trim();
+        *field = Bytes(
+            stats::parse_value(size)
+                .with_context(|| format!("invalid {} in {}", key, status_path.display()))?
+                * 1024,
+        );
+    }
+
+    Ok(process)
+}
+
+/// Parses the content of /proc/<pid>/stat. The name of the executable is
+/// enclosed in parentheses and may contain spaces and parentheses itself, so
+/// the remaining fields are split after the last closing parenthesis.
+fn parse_proc_stat(content: &str, ticks_per_second: u64) -> Result<ProcessStats> {
+    let (pid, rest) = content
+        .split_once(" (")
+        .ok_or_else(|| anyhow!("missing name of the process"))?;
+    let (name, rest) = rest
+        .rsplit_once(") ")
+        .ok_or_else(|| anyhow!("missing end of the name of the process"))?;
+    // the fields are indexed from the state, which is the third field
+    let fields: Vec<&str> = rest.split_ascii_whitespace().collect();
+    if fields.len() < 20 {
+        bail!("expected at least 22 fields, found {}", fields.len() + 2);
+    }
+    let ticks = |index: usize| -> Result<Nanoseconds> {
+        let ticks = stats::parse_value(fields[index])?;
+        Ok(Nanoseconds(
+            ticks.saturating_mul(1_000_000_000) / ticks_per_second,
+        ))
+    };
+
+    Ok(ProcessStats {
+        pid: pid.trim().parse().context("invalid pid")?,
+        ppid: fields[1].parse().context("invalid ppid")?,
+        name: name.to_owned(),
+        state: fields[0].to_owned(),
+        threads: stats::parse_value(fields[17])?,
+        user_time: ticks(11)?,
+        system_time: ticks(12)?,
+        start_time: ticks(19)?,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::FakeReader;
+
+    const STAT: &str = "42 (my (app)) S 1 42 42 0 -1 4194560 1000 0 0 0 250 50 0 0 20 0 3 0 12000 10485760 512 18446744073709551615 1 1 0 0 0 0 0 0 0 0 0 0 17 0 0 0 0 0 0";
+
+    #[test]
+    fn test_parse_proc_stat() {
+        let process = parse_proc_stat(STAT, 100).unwrap();
+        assert_eq!(process.pid, 42);
+        assert_eq!(process.ppid, 1);
+        assert_eq!(process.name, "my (app)");
+        assert_eq!(process.state, "S");
+        assert_eq!(process.threads, 3);
+        assert_eq!(process.user_time, Nanoseconds(2_500_000_000));
+        assert_eq!(process.system_time, Nanoseconds(500_000_000));
+        assert_eq!(process.start_time, Nanoseconds(120_000_000_000));
+
+        assert!(parse_proc_stat("42 my app S 1", 100).is_err());
+    }
+
+    #[test]
+    fn test_process_stats() {
+        let reader = FakeReader::default()
+            .with_file("/sys/fs/cgroup/test/cgroup.procs", "42\n43\n")
+            .with_file("/proc/42/stat", STAT)
+            .with_file(
+                "/proc/42/status",
+                "Name:\tmy (app)\nVmPeak:\t   20480 kB\nVmSize:\t   10240 kB\nVmHWM:\t    4096 kB\nVmRSS:\t    2048 kB\nThreads:\t3\n",
+            );
+
+        // process 43 has exited in between
+        let processes = Processes::stats_from(&reader, Path::new("/sys/fs/cgroup/test")).unwrap();
+        assert_eq!(processes.len(), 1);
+        assert_eq!(processes[0].rss, Bytes(2048 * 1024));
+        assert_eq!(processes[0].rss_peak, Bytes(4096 * 1024));
+        assert_eq!(processes[0].virtual_memory, Bytes(10240 * 1024));
+    }
+}
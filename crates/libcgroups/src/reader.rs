@@ -0,0 +1,227 @@
+//! Reading of cgroup files by the stats providers. The providers read all
+//! files through a `CgroupReader`, so that their parsing can be tested with
+//! the contents of the files kept in memory by a `FakeReader`, without a
+//! cgroup hierarchy.
+//!
+//! # Example
+//!
+//! ```
+//! use std::path::Path;
+//! use libcgroups::{reader::FakeReader, stats};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let reader = FakeReader::default()
+//!     .with_file("/sys/fs/cgroup/youki/pids.current", "5\n")
+//!     .with_file("/sys/fs/cgroup/youki/pids.max", "max\n");
+//! let pids = stats::pid_stats(&reader, Path::new("/sys/fs/cgroup/youki"))?;
+//! assert_eq!(pids.current, 5);
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::stats::StatsError;
+
+/// Provides the contents of cgroup files
+pub trait CgroupReader {
+    /// Reads the content of a file
+    fn read(&self, path: &Path) -> Result<String, StatsError>;
+
+    /// Checks if a file or directory exists
+    fn exists(&self, path: &Path) -> bool;
+
+    /// Returns the names of the entries of a directory
+    fn read_dir(&self, path: &Path) -> Result<Vec<String>, StatsError>;
+
+    /// Parses a single valued file to an u64, where max is reported as u64::MAX
+    fn parse_single_value(&self, file_path: &Path) -> Result<u64, StatsError> {
+        let value = self.read(file_path)?;
+        let value = value.trim();
+        if value == "max" {
+            return Ok(u64::MAX);
+        }
+
+        value
+            .parse()
+            .map_err(|_| StatsError::parse_error(file_path, 1, format!("invalid value {}", value)))
+    }
+
+    /// Parses a file that is structured according to the flat keyed format
+    fn parse_flat_keyed_data(&self, file_path: &Path) -> Result<HashMap<String, u64>, StatsError> {
+        let mut stats = HashMap::new();
+        let keyed_data = self.read(file_path)?;
+        for (index, entry) in keyed_data.lines().enumerate() {
+            let entry_fields: Vec<&str> = entry.split_ascii_whitespace().collect();
+            if entry_fields.len() != 2 {
+                return Err(StatsError::parse_error(
+                    file_path,
+                    index + 1,
+                    "entry does not conform to 'key value'",
+                ));
+            }
+
+            let value = entry_fields[1].parse().map_err(|_| {
+                StatsError::parse_error(
+                    file_path,
+                    index + 1,
+                    format!("invalid value of {}", entry_fields[0]),
+                )
+            })?;
+            stats.insert(entry_fields[0].to_owned(), value);
+        }
+
+        Ok(stats)
+    }
+
+    /// Parses a file that is structured according to the nested keyed format
+    fn parse_nested_keyed_data(
+        &self,
+        file_path: &Path,
+    ) -> Result<HashMap<String, Vec<String>>, StatsError> {
+        let mut stats: HashMap<String, Vec<String>> = HashMap::new();
+        let keyed_data = self.read(file_path)?;
+        for (index, entry) in keyed_data.lines().enumerate() {
+            let entry_fields: Vec<&str> = entry.split_ascii_whitespace().collect();
+            if entry_fields.len() < 2 || !entry_fields[1..].iter().all(|p| p.contains('=')) {
+                return Err(StatsError::parse_error(
+                    file_path,
+                    index + 1,
+                    "entry does not conform to the nested key format",
+                ));
+            }
+
+            stats.insert(
+                entry_fields[0].to_owned(),
+                entry_fields[1..]
+                    .iter()
+                    .copied()
+                    .map(|p| p.to_owned())
+                    .collect(),
+            );
+        }
+
+        Ok(stats)
+    }
+}
+
+/// Reads the files from the file system
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FsReader;
+
+impl FsReader {
+    fn error(path: &Path, err: io::Error) -> StatsError {
+        match err.kind() {
+            io::ErrorKind::NotFound => StatsError::MissingFile {
+                path: path.to_owned(),
+            },
+            _ => StatsError::Io {
+                path: path.to_owned(),
+                source: err,
+            },
+        }
+    }
+}
+
+impl CgroupReader for FsReader {
+    fn read(&self, path: &Path) -> Result<String, StatsError> {
+        fs::read_to_string(path).map_err(|err| Self::error(path, err))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<String>, StatsError> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(path).map_err(|err| Self::error(path, err))? {
+            let entry = entry.map_err(|err| Self::error(path, err))?;
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+
+        Ok(names)
+    }
+}
+
+/// Keeps the contents of files in memory. Directories exist implicitly if
+/// they contain a file.
+#[derive(Debug, Default, Clone)]
+pub struct FakeReader {
+    files: HashMap<PathBuf, String>,
+}
+
+impl FakeReader {
+    /// Adds a file with the content
+    pub fn with_file<P: Into<PathBuf>, C: Into<String>>(mut self, path: P, content: C) -> Self {
+        self.insert(path, content);
+        self
+    }
+
+    /// Adds a file with the content, replacing the previous content
+    pub fn insert<P: Into<PathBuf>, C: Into<String>>(&mut self, path: P, content: C) {
+        self.files.insert(path.into(), content.into());
+    }
+}
+
+impl CgroupReader for FakeReader {
+    fn read(&self, path: &Path) -> Result<String, StatsError> {
+        self.files
+            .get(path)
+            .cloned()
+            .ok_or_else(|| StatsError::MissingFile {
+                path: path.to_owned(),
+            })
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.files.keys().any(|file| file.starts_with(path))
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<String>, StatsError> {
+        let names: BTreeSet<String> = self
+            .files
+            .keys()
+            .filter_map(|file| file.strip_prefix(path).ok()?.components().next())
+            .map(|name| name.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        if names.is_empty() {
+            return Err(StatsError::MissingFile {
+                path: path.to_owned(),
+            });
+        }
+
+        Ok(names.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fake_reader() {
+        let reader = FakeReader::default()
+            .with_file("/sys/fs/cgroup/test/memory.current", "4096\n")
+            .with_file("/sys/fs/cgroup/test/child/memory.current", "1024\n");
+
+        assert_eq!(
+            reader
+                .parse_single_value(Path::new("/sys/fs/cgroup/test/memory.current"))
+                .unwrap(),
+            4096
+        );
+        assert!(reader.exists(Path::new("/sys/fs/cgroup/test/child")));
+        assert!(!reader.exists(Path::new("/sys/fs/cgroup/test/memory.max")));
+        assert!(matches!(
+            reader.read(Path::new("/sys/fs/cgroup/test/memory.max")),
+            Err(StatsError::MissingFile { .. })
+        ));
+        assert_eq!(
+            reader.read_dir(Path::new("/sys/fs/cgroup/test")).unwrap(),
+            vec!["child".to_owned(), "memory.current".to_owned()]
+        );
+    }
+}
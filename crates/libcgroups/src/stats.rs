@@ -1,17 +1,39 @@
 use anyhow::{bail, Context, Result};
-use serde::Serialize;
-use std::{collections::HashMap, fmt::Display, fs, path::Path};
-
-use super::common;
+use nix::errno::Errno;
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt::Display,
+    fs, io,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use super::common::{self, FreezerState, CGROUP_PROCS};
+use crate::reader::{CgroupReader, FsReader};
+
+const PROC_PARTITIONS: &str = "/proc/partitions";
+const HUGEPAGES_DIR: &str = "/sys/kernel/mm/hugepages";
 
 pub trait StatsProvider {
     type Stats;
 
-    fn stats(cgroup_path: &Path) -> Result<Self::Stats>;
+    /// Returns the statistics of the cgroup, read from the cgroup filesystem
+    fn stats(cgroup_path: &Path) -> Result<Self::Stats> {
+        Self::stats_from(&FsReader, cgroup_path)
+    }
+
+    /// Returns the statistics of the cgroup, read with the reader
+    fn stats_from(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<Self::Stats>;
 }
 
-/// Reports the statistics for a cgroup
-#[derive(Debug, Serialize, Default)]
+/// Reports the statistics for a cgroup. The serialized field names match the
+/// ones in the events of runc, so that consumers of `runc events` can read them.
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Stats {
     /// Cpu statistics for the cgroup
     pub cpu: CpuStats,
@@ -23,79 +45,480 @@ pub struct Stats {
     pub blkio: BlkioStats,
     /// Memory statistics for the cgroup
     pub memory: MemoryStats,
+    /// State of the freezer of the cgroup, which tells paused containers
+    /// apart from ones that do not make progress for other reasons
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub freezer: Option<FreezerState>,
+    /// Cpus and memory nodes the cgroup is configured with and allowed to use
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpuset: Option<CpuSetStats>,
+    /// Rdma statistics for the cgroup
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rdma: Option<RdmaStats>,
+    /// Statistics of the resources of the misc controller, e.g. the address
+    /// space ids of encrypted virtual machines, keyed by the resource name
+    /// (cgroup v2 only)
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub misc: HashMap<String, MiscStats>,
+    /// Statistics of the registered controller extensions, keyed by the
+    /// name of the controller
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub extensions: HashMap<String, HashMap<String, u64>>,
+    /// Statistics of the network interfaces in the network namespace of the
+    /// container. They are not part of a cgroup and therefore only filled
+    /// in by callers that know the container process.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub network_interfaces: Vec<NetworkInterface>,
+    /// Controllers whose statistics could not be read due to insufficient permissions
+    /// or because they are not enabled
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unavailable: Vec<String>,
+    /// Errors that occurred while collecting the statistics of individual controllers
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub diagnostics: Vec<StatsDiagnostic>,
+}
+
+impl Stats {
+    /// Collects the statistics of the cgroup with the path relative to the
+    /// root of the cgroup hierarchy, including the usage of the child cgroups
+    /// that is not accounted to the cgroup itself, e.g. the io of child
+    /// cgroups with the throttling policy on cgroup v1. See
+    /// `CgroupManager::stats_recursive`.
+    pub fn collect_recursive<P: Into<PathBuf>>(cgroup_path: P) -> Result<Stats> {
+        common::create_cgroup_manager(cgroup_path, false, "")?.stats_recursive()
+    }
+
+    /// Collects the statistics selected by the filter of the cgroup with the
+    /// path relative to the root of the cgroup hierarchy
+    pub fn collect_with<P: Into<PathBuf>>(cgroup_path: P, filter: &StatsFilter) -> Result<Stats> {
+        common::create_cgroup_manager(cgroup_path, false, "")?.stats_with(filter)
+    }
+
+    /// Checks the result of collecting the statistics of a controller. A failing
+    /// controller does not fail the collection of the remaining statistics. If the
+    /// statistics could not be read because the caller lacks the required permissions,
+    /// which is common for rootless containers in nested setups, or because the
+    /// controller is not enabled for the cgroup, the controller is recorded as
+    /// unavailable. Any other error is recorded as a diagnostic.
+    pub fn check_available<T, C: Display>(
+        &mut self,
+        controller: C,
+        result: Result<T>,
+    ) -> Option<T> {
+        match result {
+            Ok(stats) => Some(stats),
+            Err(err) if is_permission_denied(&err) || is_not_enabled(&err) => {
+                log::debug!("stats of {} are not readable: {:?}", controller, err);
+                self.unavailable.push(controller.to_string());
+                None
+            }
+            Err(err) => {
+                log::warn!("failed to collect stats of {}: {:?}", controller, err);
+                self.diagnostics.push(StatsDiagnostic {
+                    controller: controller.to_string(),
+                    error: format!("{:#}", err),
+                });
+                None
+            }
+        }
+    }
+}
+
+/// Selects the statistics that are collected, so that callers that only need
+/// some of them, e.g. to chart the cpu and memory usage, do not read the files
+/// of the other controllers on every poll. The statistics that are not
+/// selected keep their default values. By default all statistics are selected.
+///
+/// ```
+/// use libcgroups::stats::StatsFilter;
+///
+/// let filter = StatsFilter {
+///     blkio: false,
+///     hugetlb: false,
+///     ..Default::default()
+/// };
+/// assert!(filter.cpu);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatsFilter {
+    /// Cpu usage and throttling
+    pub cpu: bool,
+    /// Memory usage
+    pub memory: bool,
+    /// Number of pids
+    pub pids: bool,
+    /// Block io of the blkio controller on v1 and the io controller on v2
+    pub blkio: bool,
+    /// Hugetlb usage
+    pub hugetlb: bool,
+    /// Cpus and memory nodes of the cpuset controller
+    pub cpuset: bool,
+    /// State of the freezer
+    pub freezer: bool,
+    /// Rdma usage
+    pub rdma: bool,
+    /// Resources of the misc controller (cgroup v2 only)
+    pub misc: bool,
+    /// Statistics of the registered controller extensions
+    pub extensions: bool,
+    /// Statistics of the network interfaces of the container, which are
+    /// only collected by callers that know the container process
+    pub network: bool,
+}
+
+impl Default for StatsFilter {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl StatsFilter {
+    /// Selects all statistics
+    pub fn all() -> Self {
+        Self {
+            cpu: true,
+            memory: true,
+            pids: true,
+            blkio: true,
+            hugetlb: true,
+            cpuset: true,
+            freezer: true,
+            rdma: true,
+            misc: true,
+            extensions: true,
+            network: true,
+        }
+    }
+
+    /// Selects no statistics, so that the needed ones can be enabled one by one
+    pub fn none() -> Self {
+        Self {
+            cpu: false,
+            memory: false,
+            pids: false,
+            blkio: false,
+            hugetlb: false,
+            cpuset: false,
+            freezer: false,
+            rdma: false,
+            misc: false,
+            extensions: false,
+            network: false,
+        }
+    }
+}
+
+/// Reports an error that occurred while collecting the statistics of a controller
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct StatsDiagnostic {
+    /// Controller whose statistics are missing or incomplete
+    pub controller: String,
+    /// Description of the error
+    pub error: String,
+}
+
+/// Stores the collected statistics of a controller in the stats
+type StatsUpdate = Box<dyn FnOnce(&mut Stats)>;
+
+/// Collects the statistics of several controllers one after the other. The
+/// controllers only read a few small cgroup files each, which is not worth a
+/// thread per controller, or per container for callers like the metrics
+/// server. The errors of the controllers are isolated from each other as
+/// with `check_available`, and a controller that panics only loses its own
+/// statistics.
+#[derive(Default)]
+pub(crate) struct StatsCollector {
+    collected: Vec<(String, Option<StatsUpdate>)>,
+}
+
+impl StatsCollector {
+    /// Collects the statistics of a controller with `read`, which are stored
+    /// with `store` when the collector is joined
+    pub fn collect<T, C>(
+        &mut self,
+        controller: C,
+        cgroup_path: &Path,
+        read: fn(&Path) -> Result<T>,
+        store: fn(&mut Stats, T),
+    ) where
+        T: 'static,
+        C: Display,
+    {
+        let controller = controller.to_string();
+        let name = controller.clone();
+        let update = panic::catch_unwind(AssertUnwindSafe(|| read(cgroup_path)))
+            .ok()
+            .map(|result| -> StatsUpdate {
+                Box::new(move |stats: &mut Stats| {
+                    if let Some(controller_stats) = stats.check_available(name, result) {
+                        store(stats, controller_stats);
+                    }
+                })
+            });
+        self.collected.push((controller, update));
+    }
+
+    /// Stores the statistics of all controllers in the order in which they
+    /// have been collected
+    pub fn join(self, stats: &mut Stats) {
+        for (controller, update) in self.collected {
+            match update {
+                Some(update) => update(stats),
+                None => stats.diagnostics.push(StatsDiagnostic {
+                    controller,
+                    error: "collection of the stats panicked".to_owned(),
+                }),
+            }
+        }
+    }
+}
+
+/// Checks if the error has been caused by missing permissions to access a file
+pub fn is_permission_denied(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        matches!(cause.downcast_ref::<io::Error>(), Some(io_err) if io_err.kind() == io::ErrorKind::PermissionDenied)
+    })
+}
+
+/// Error that occurred while reading the statistics of a controller. It allows
+/// callers to tell a controller that is not enabled apart from a cgroup file
+/// that cannot be parsed, e.g. with `stats_error`.
+#[derive(Debug)]
+pub enum StatsError {
+    /// The cgroup file does not exist, usually because its controller is not
+    /// enabled or the kernel is too old to provide it
+    MissingFile { path: PathBuf },
+    /// The controller is not enabled for the cgroup
+    UnsupportedController { controller: String },
+    /// The content of the cgroup file does not have the expected format
+    ParseError {
+        path: PathBuf,
+        /// Line of the file the error occurred in, starting at 1
+        line: usize,
+        message: String,
+    },
+    /// The cgroup file could not be read for any other reason
+    Io { path: PathBuf, source: io::Error },
+}
+
+impl StatsError {
+    pub(crate) fn parse_error(path: &Path, line: usize, message: impl Into<String>) -> Self {
+        StatsError::ParseError {
+            path: path.to_owned(),
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for StatsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StatsError::MissingFile { path } => write!(f, "{} does not exist", path.display()),
+            StatsError::UnsupportedController { controller } => {
+                write!(f, "controller {} is not enabled", controller)
+            }
+            StatsError::ParseError {
+                path,
+                line,
+                message,
+            } => write!(
+                f,
+                "failed to parse {}:{}: {}",
+                path.display(),
+                line,
+                message
+            ),
+            StatsError::Io { path, .. } => write!(f, "failed to read {}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for StatsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StatsError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the stats error the error has been caused by, if any
+/// # Example
+/// ```no_run
+/// use std::path::Path;
+/// use libcgroups::{reader::FsReader, stats::{self, StatsError}};
+///
+/// if let Err(err) = stats::pid_stats(&FsReader, Path::new("/sys/fs/cgroup/youki")) {
+///     match stats::stats_error(&err) {
+///         Some(StatsError::MissingFile { .. }) => println!("pids controller is not enabled"),
+///         _ => eprintln!("failed to read pids stats: {:?}", err),
+///     }
+/// }
+/// ```
+pub fn stats_error(err: &anyhow::Error) -> Option<&StatsError> {
+    err.chain()
+        .find_map(|cause| cause.downcast_ref::<StatsError>())
+}
+
+/// Checks if the error has been caused by a controller that is not enabled
+fn is_not_enabled(err: &anyhow::Error) -> bool {
+    matches!(
+        stats_error(err),
+        Some(StatsError::MissingFile { .. } | StatsError::UnsupportedController { .. })
+    )
 }
 
 /// Reports the cpu statistics for a cgroup
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CpuStats {
     /// Cpu usage statistics for the cgroup
     pub usage: CpuUsage,
     /// Cpu Throttling statistics for the cgroup
     pub throttling: CpuThrottling,
+    /// Pressure stall information for cpu (cgroup v2 only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub psi: Option<PsiStats>,
 }
 
 /// Reports the cpu usage for a cgroup
-#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CpuUsage {
     /// Cpu time consumed by tasks in total
-    pub usage_total: u64,
+    #[serde(rename = "total")]
+    pub usage_total: Nanoseconds,
     /// Cpu time consumed by tasks in user mode
-    pub usage_user: u64,
+    #[serde(rename = "user")]
+    pub usage_user: Nanoseconds,
     /// Cpu time consumed by tasks in kernel mode
-    pub usage_kernel: u64,
+    #[serde(rename = "kernel")]
+    pub usage_kernel: Nanoseconds,
     /// Cpu time consumed by tasks itemized per core
-    pub per_core_usage_total: Vec<u64>,
+    #[serde(rename = "percpu")]
+    pub per_core_usage_total: Vec<Nanoseconds>,
     /// Cpu time consumed by tasks in user mode itemized per core
-    pub per_core_usage_user: Vec<u64>,
+    #[serde(rename = "percpu_user")]
+    pub per_core_usage_user: Vec<Nanoseconds>,
     /// Cpu time consumed by tasks in kernel mode itemized per core
-    pub per_core_usage_kernel: Vec<u64>,
+    #[serde(rename = "percpu_kernel")]
+    pub per_core_usage_kernel: Vec<Nanoseconds>,
 }
 
 /// Reports the cpu throttling for a cgroup
-#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CpuThrottling {
     /// Number of period intervals (as specified in cpu.cfs_period_us) that have elapsed
     pub periods: u64,
     /// Number of period intervals where tasks have been throttled because they exhausted their quota
     pub throttled_periods: u64,
     /// Total time duration for which tasks have been throttled
-    pub throttled_time: u64,
+    pub throttled_time: Nanoseconds,
+    /// Number of period intervals in which tasks have used more than their
+    /// quota by bursting (as configured with cpu.cfs_burst_us on v1 and
+    /// cpu.max.burst on v2)
+    pub bursts: u64,
+    /// Total time duration that tasks have run in excess of their quota by bursting
+    pub burst_time: Nanoseconds,
 }
 
 /// Reports memory stats for a cgroup
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MemoryStats {
     /// Usage of memory
+    #[serde(rename = "usage")]
     pub memory: MemoryData,
     /// Usage of memory and swap
+    #[serde(rename = "swap")]
     pub memswap: MemoryData,
     /// Usage of kernel memory
     pub kernel: MemoryData,
     /// Usage of kernel tcp memory
+    #[serde(rename = "kernelTCP")]
     pub kernel_tcp: MemoryData,
     /// Page cache in bytes
-    pub cache: u64,
+    pub cache: Bytes,
     /// Returns true if hierarchical accounting is enabled
     pub hierarchy: bool,
     /// Various memory statistics
+    #[serde(rename = "raw")]
     pub stats: HashMap<String, u64>,
+    /// Number of times the memory limits of the cgroup have been hit
+    pub events: MemoryEvents,
+    /// Memory usage per NUMA node, keyed by the id of the node
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub numa: HashMap<u32, NumaNodeMemory>,
+    /// Pressure stall information for memory (cgroup v2 only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub psi: Option<PsiStats>,
+}
+
+/// Reports how often the memory of a cgroup has been constrained. Cgroup v1
+/// only reports oom kills.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MemoryEvents {
+    /// Number of times the usage has been below the low boundary while
+    /// memory was reclaimed under memory pressure
+    pub low: u64,
+    /// Number of times the processes have been throttled and routed to
+    /// direct reclaim because the usage exceeded the high boundary
+    pub high: u64,
+    /// Number of times the usage was about to exceed the limit
+    pub max: u64,
+    /// Number of times the usage hit the limit and allocations failed
+    pub oom: u64,
+    /// Number of processes that have been killed by the oom killer
+    pub oom_kill: u64,
+}
+
+impl From<&HashMap<String, u64>> for MemoryEvents {
+    fn from(events: &HashMap<String, u64>) -> Self {
+        let get = |key: &str| events.get(key).copied().unwrap_or_default();
+        Self {
+            low: get("low"),
+            high: get("high"),
+            max: get("max"),
+            oom: get("oom"),
+            oom_kill: get("oom_kill"),
+        }
+    }
+}
+
+/// Reports the memory of a cgroup that has been allocated on a NUMA node
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NumaNodeMemory {
+    /// Anonymous memory in bytes
+    pub anon: Bytes,
+    /// File backed memory in bytes
+    pub file: Bytes,
+    /// Memory that cannot be reclaimed in bytes
+    pub unevictable: Bytes,
 }
 
 /// Reports memory stats for one type of memory
-#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MemoryData {
     /// Usage in bytes
-    pub usage: u64,
+    pub usage: Bytes,
     /// Maximum recorded usage in bytes
-    pub max_usage: u64,
+    #[serde(rename = "max")]
+    pub max_usage: Bytes,
     /// Number of times memory usage hit limits
+    #[serde(rename = "failcnt")]
     pub fail_count: u64,
     /// Memory usage limit
-    pub limit: u64,
+    pub limit: Bytes,
 }
 
 /// Reports pid stats for a cgroup
-#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PidStats {
     /// Current number of active pids
     pub current: u64,
@@ -104,37 +527,238 @@ pub struct PidStats {
 }
 
 /// Reports block io stats for a cgroup
-#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct BlkioStats {
     // Number of bytes transfered to/from a device by the cgroup
+    #[serde(rename = "ioServiceBytesRecursive")]
     pub service_bytes: Vec<BlkioDeviceStat>,
     // Number of I/O operations performed on a device by the cgroup
+    #[serde(rename = "ioServicedRecursive")]
     pub serviced: Vec<BlkioDeviceStat>,
     // Time in milliseconds that the cgroup had access to a device
+    #[serde(rename = "ioTimeRecursive")]
     pub time: Vec<BlkioDeviceStat>,
     // Number of sectors transferred to/from a device by the cgroup
+    #[serde(rename = "sectorsRecursive")]
     pub sectors: Vec<BlkioDeviceStat>,
     // Total time between request dispatch and request completion
+    #[serde(rename = "ioServiceTimeRecursive")]
     pub service_time: Vec<BlkioDeviceStat>,
     // Total time spend waiting in the scheduler queues for service
+    #[serde(rename = "ioWaitTimeRecursive")]
     pub wait_time: Vec<BlkioDeviceStat>,
     // Number of requests queued for I/O operations
+    #[serde(rename = "ioQueueRecursive")]
     pub queued: Vec<BlkioDeviceStat>,
     // Number of requests merged into requests for I/O operations
+    #[serde(rename = "ioMergedRecursive")]
     pub merged: Vec<BlkioDeviceStat>,
+    /// Pressure stall information for io (cgroup v2 only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub psi: Option<PsiStats>,
+    /// Files from which the statistics have been read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<BlkioSource>,
+    /// Statistics and settings of the io.latency and io.cost controllers
+    /// (cgroup v2 only), which are missing if neither is in use
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qos: Option<IoQosStats>,
+}
+
+impl BlkioStats {
+    /// Fills in the names of the devices from /proc/partitions, so that the
+    /// stats do not have to be read by their device numbers. Devices that are
+    /// not listed there, e.g. because they have been removed, keep no name.
+    pub fn resolve_device_names(&mut self) -> Result<()> {
+        let content = fs::read_to_string(PROC_PARTITIONS)
+            .with_context(|| format!("failed to read {}", PROC_PARTITIONS))?;
+        self.set_device_names(&parse_partitions(&content));
+        Ok(())
+    }
+
+    /// Fills in the names of the devices from a map of major and minor
+    /// device numbers to names
+    pub fn set_device_names(&mut self, names: &HashMap<(u64, u64), String>) {
+        let device_stats = [
+            &mut self.service_bytes,
+            &mut self.serviced,
+            &mut self.time,
+            &mut self.sectors,
+            &mut self.service_time,
+            &mut self.wait_time,
+            &mut self.queued,
+            &mut self.merged,
+        ];
+        for stat in device_stats.into_iter().flat_map(|stats| stats.iter_mut()) {
+            stat.device_name = names.get(&(stat.major, stat.minor)).cloned();
+        }
+    }
+
+    /// Adds the counters of another cgroup, e.g. of a child cgroup whose io
+    /// is not included in the counters of its parent
+    pub fn add(&mut self, other: &BlkioStats) {
+        add_device_stats(&mut self.service_bytes, &other.service_bytes);
+        add_device_stats(&mut self.serviced, &other.serviced);
+        add_device_stats(&mut self.time, &other.time);
+        add_device_stats(&mut self.sectors, &other.sectors);
+        add_device_stats(&mut self.service_time, &other.service_time);
+        add_device_stats(&mut self.wait_time, &other.wait_time);
+        add_device_stats(&mut self.queued, &other.queued);
+        add_device_stats(&mut self.merged, &other.merged);
+    }
+}
+
+/// Adds the values of the device stats to the ones with the same device and
+/// operation type, appending the ones that do not exist yet
+fn add_device_stats(stats: &mut Vec<BlkioDeviceStat>, other: &[BlkioDeviceStat]) {
+    for other_stat in other {
+        match stats.iter_mut().find(|stat| {
+            stat.major == other_stat.major
+                && stat.minor == other_stat.minor
+                && stat.op_type == other_stat.op_type
+        }) {
+            Some(stat) => stat.value += other_stat.value,
+            None => stats.push(other_stat.clone()),
+        }
+    }
+}
+
+/// Reports which files of the kernel the block io statistics come from. The
+/// throttling policy counters are the only ones that are filled on cgroup v1
+/// if neither the CFQ nor the BFQ scheduler is in use, but they do not
+/// include the io of child cgroups.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlkioSource {
+    /// Recursive counters of the CFQ scheduler on cgroup v1
+    Cfq,
+    /// Recursive counters of the BFQ scheduler on cgroup v1
+    Bfq,
+    /// Counters of the throttling policy on cgroup v1
+    Throttling,
+    /// io.stat on cgroup v2
+    IoStat,
+}
+
+/// Reports the cpus and memory nodes of a cgroup. The requested ones may differ
+/// from the effective ones, as a cgroup can only use the cpus and memory nodes
+/// that its parent is allowed to use and that are online.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CpuSetStats {
+    /// Requested cpus (empty if the cgroup uses the cpus of its parent)
+    pub cpus: Vec<u32>,
+    /// Requested memory nodes (empty if the cgroup uses the nodes of its parent)
+    pub mems: Vec<u32>,
+    /// Cpus the tasks of the cgroup are allowed to run on
+    pub effective_cpus: Vec<u32>,
+    /// Memory nodes the tasks of the cgroup are allowed to allocate memory on
+    pub effective_mems: Vec<u32>,
+}
+
+/// Reports the usage and limits of the RDMA resources of a cgroup per device
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RdmaStats {
+    /// Resources in use
+    #[serde(rename = "rdma_current")]
+    pub current: Vec<RdmaEntry>,
+    /// Configured limits
+    #[serde(rename = "rdma_limit")]
+    pub limit: Vec<RdmaEntry>,
+}
+
+/// Reports the RDMA resources of a device, i.e. of a host channel adapter
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RdmaEntry {
+    /// Name of the device, e.g. mlx4_0
+    pub device: String,
+    /// Number of HCA handles (u32::MAX means no limit)
+    pub hca_handles: u32,
+    /// Number of HCA objects (u32::MAX means no limit)
+    pub hca_objects: u32,
+}
+
+/// Reports the usage of a scalar resource of the misc controller, e.g. sev
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MiscStats {
+    /// Current usage
+    pub usage: u64,
+    /// Usage limit (u64::MAX means no limit)
+    pub limit: u64,
+    /// Number of times the usage was about to exceed the limit
+    #[serde(rename = "failcnt")]
+    pub fail_count: u64,
+}
+
+/// Reports the traffic of a network interface
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkInterface {
+    /// Name of the interface
+    pub name: String,
+    /// Number of received bytes
+    pub rx_bytes: u64,
+    /// Number of received packets
+    pub rx_packets: u64,
+    /// Number of receive errors
+    pub rx_errors: u64,
+    /// Number of received packets that have been dropped
+    pub rx_dropped: u64,
+    /// Number of transmitted bytes
+    pub tx_bytes: u64,
+    /// Number of transmitted packets
+    pub tx_packets: u64,
+    /// Number of transmit errors
+    pub tx_errors: u64,
+    /// Number of packets that have been dropped before transmission
+    pub tx_dropped: u64,
+}
+
+/// Reports the pressure stall information of a resource, i.e. the share of
+/// time in which tasks have been stalled waiting for it
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PsiStats {
+    /// Time in which at least some tasks have been stalled
+    pub some: PsiData,
+    /// Time in which all non idle tasks have been stalled at the same time
+    pub full: PsiData,
+}
+
+/// Reports the pressure of a resource over several time windows
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PsiData {
+    /// Percentage of stalled time in the last 10 seconds
+    pub avg10: f64,
+    /// Percentage of stalled time in the last 60 seconds
+    pub avg60: f64,
+    /// Percentage of stalled time in the last 300 seconds
+    pub avg300: f64,
+    /// Total stalled time in microseconds
+    pub total: u64,
 }
 
 /// Reports single stat value for a specific device
-#[derive(Debug, PartialEq, Eq, Clone, Serialize, PartialOrd, Ord)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, PartialOrd, Ord)]
 pub struct BlkioDeviceStat {
     /// Major device number
     pub major: u64,
     /// Minor device number
     pub minor: u64,
     /// Operation type
-    pub op_type: Option<String>,
+    #[serde(rename = "op", default, skip_serializing_if = "Option::is_none")]
+    pub op_type: Option<Cow<'static, str>>,
     /// Stat value
     pub value: u64,
+    /// Name of the device, e.g. sda, if it has been resolved with
+    /// `BlkioStats::resolve_device_names`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_name: Option<String>,
 }
 
 impl Display for BlkioDeviceStat {
@@ -151,51 +775,293 @@ impl Display for BlkioDeviceStat {
     }
 }
 
+/// Reports the statistics and settings of the io quality of service
+/// controllers of cgroup v2, so that it can be verified that the latency
+/// targets and cost model are taking effect
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IoQosStats {
+    /// Per device statistics of io.latency and io.cost from io.stat
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub devices: Vec<IoQosDeviceStats>,
+    /// Quality of service parameters of io.cost, which are configured in the
+    /// root cgroup for all cgroups
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cost_qos: Vec<IoCostQos>,
+    /// Cost models of io.cost, which are configured in the root cgroup for
+    /// all cgroups
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cost_model: Vec<IoCostModel>,
+}
+
+/// Reports the io.latency and io.cost statistics of a device. Each value is
+/// only present if the corresponding controller is active for the device.
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IoQosDeviceStats {
+    /// Major device number
+    pub major: u64,
+    /// Minor device number
+    pub minor: u64,
+    /// Latency target of io.latency in microseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_target: Option<u64>,
+    /// Average completion latency in microseconds over the last sampling window
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_latency: Option<u64>,
+    /// Length of the sampling window in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window: Option<u64>,
+    /// Time in nanoseconds the cgroup has been delayed in favor of cgroups
+    /// with lower latency targets
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delay: Option<u64>,
+    /// Rate of the device relative to its cost model in percent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_vrate: Option<f64>,
+    /// Device time in microseconds used by the cgroup according to io.cost
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_usage: Option<u64>,
+    /// Time in microseconds the cgroup has waited for its share of io.cost
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_wait: Option<u64>,
+    /// Time in microseconds the cgroup has been in debt to io.cost
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_indebt: Option<u64>,
+    /// Time in microseconds the cgroup has been delayed to pay off its debt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_indelay: Option<u64>,
+}
+
+/// Reports the quality of service parameters of io.cost for a device
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IoCostQos {
+    /// Major device number
+    pub major: u64,
+    /// Minor device number
+    pub minor: u64,
+    /// Returns true if io.cost is enabled for the device
+    pub enable: bool,
+    /// Whether the parameters are set by the user or the kernel ("user" or "auto")
+    pub ctrl: String,
+    /// Percentile of read latencies that has to stay below `read_latency`
+    pub read_percentile: f64,
+    /// Read latency target in microseconds
+    pub read_latency: u64,
+    /// Percentile of write latencies that has to stay below `write_latency`
+    pub write_percentile: f64,
+    /// Write latency target in microseconds
+    pub write_latency: u64,
+    /// Lower bound of the rate relative to the cost model in percent
+    pub min: f64,
+    /// Upper bound of the rate relative to the cost model in percent
+    pub max: f64,
+}
+
+/// Reports the cost model of io.cost for a device
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IoCostModel {
+    /// Major device number
+    pub major: u64,
+    /// Minor device number
+    pub minor: u64,
+    /// Whether the model is set by the user or the kernel ("user" or "auto")
+    pub ctrl: String,
+    /// Name of the model, e.g. "linear"
+    pub model: String,
+    /// Parameters of the model, e.g. rbps or wrandiops, keyed by their name
+    pub parameters: HashMap<String, u64>,
+}
+
 /// Reports hugetlb stats for a cgroup
-#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct HugeTlbStats {
     /// Current usage in bytes
-    pub usage: u64,
+    pub usage: Bytes,
     /// Maximum recorded usage in bytes
-    pub max_usage: u64,
+    #[serde(rename = "max")]
+    pub max_usage: Bytes,
     /// Number of allocation failures due to HugeTlb usage limit
+    #[serde(rename = "failcnt")]
     pub fail_count: u64,
+    /// Reserved and allocated huge pages in bytes, if the kernel supports
+    /// reservation accounting
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rsvd_usage: Option<Bytes>,
+}
+
+/// Amount of memory in bytes. Serializes as a plain number and displays in
+/// binary units, e.g. 1.50 GiB.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Bytes(pub u64);
+
+impl From<u64> for Bytes {
+    fn from(bytes: u64) -> Self {
+        Self(bytes)
+    }
+}
+
+impl Display for Bytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+        // unlimited values are reported as max by the kernel
+        if self.0 == u64::MAX {
+            return write!(f, "max");
+        }
+
+        let mut value = self.0 as f64;
+        let mut unit = 0;
+        while value >= 1024.0 && unit < UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+
+        if unit == 0 {
+            write!(f, "{} {}", self.0, UNITS[unit])
+        } else {
+            write!(f, "{:.2} {}", value, UNITS[unit])
+        }
+    }
+}
+
+/// Duration in nanoseconds. Serializes as a plain number and displays in the
+/// largest fitting unit, e.g. 1.50s.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Nanoseconds(pub u64);
+
+impl Nanoseconds {
+    pub fn from_micros(micros: u64) -> Self {
+        Self(micros.saturating_mul(1_000))
+    }
+
+    pub fn as_duration(&self) -> Duration {
+        Duration::from_nanos(self.0)
+    }
+}
+
+impl From<u64> for Nanoseconds {
+    fn from(nanos: u64) -> Self {
+        Self(nanos)
+    }
+}
+
+impl Display for Nanoseconds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let nanos = self.0 as f64;
+        if self.0 >= 1_000_000_000 {
+            write!(f, "{:.2}s", nanos / 1e9)
+        } else if self.0 >= 1_000_000 {
+            write!(f, "{:.2}ms", nanos / 1e6)
+        } else if self.0 >= 1_000 {
+            write!(f, "{:.2}µs", nanos / 1e3)
+        } else {
+            write!(f, "{}ns", self.0)
+        }
+    }
+}
+
+/// Size of a hugepage together with its moniker, e.g. 2MB, which names the
+/// page size in the files of the hugetlb controller and in the hugepage
+/// limits of the runtime spec
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct HugePageSize {
+    /// Size of the page in bytes
+    pub bytes: u64,
+    /// Size of the page in the largest unit that divides it, as the kernel
+    /// names it
+    pub moniker: String,
+}
+
+impl HugePageSize {
+    /// Creates the page size from its size in bytes
+    pub fn from_bytes(bytes: u64) -> Self {
+        let moniker = if bytes >= (1 << 30) && bytes % (1 << 30) == 0 {
+            format!("{}GB", bytes >> 30)
+        } else if bytes >= (1 << 20) && bytes % (1 << 20) == 0 {
+            format!("{}MB", bytes >> 20)
+        } else if bytes % (1 << 10) == 0 {
+            format!("{}KB", bytes >> 10)
+        } else {
+            format!("{}B", bytes)
+        };
+
+        Self { bytes, moniker }
+    }
+}
+
+impl FromStr for HugePageSize {
+    type Err = anyhow::Error;
+
+    /// Parses a page size such as 2MB or 1GB. As hugepages are a power of two
+    /// in size, any other size is rejected.
+    fn from_str(size: &str) -> Result<Self> {
+        let unit_start = size
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(size.len());
+        let (value, unit) = size.split_at(unit_start);
+        let shift = match unit {
+            "KB" | "kB" => 10,
+            "MB" => 20,
+            "GB" => 30,
+            _ => bail!("invalid unit of page size {}", size),
+        };
+        let value: u64 = value
+            .parse()
+            .with_context(|| format!("invalid page size {}", size))?;
+        if !value.is_power_of_two() {
+            bail!("page size {} must be in the format of 2^(integer)", size);
+        }
+
+        Ok(Self::from_bytes(value << shift))
+    }
+}
+
+impl Display for HugePageSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.moniker)
+    }
 }
 
 /// Reports which hugepage sizes are supported by the system
-pub fn supported_page_sizes() -> Result<Vec<String>> {
+pub fn supported_page_sizes(reader: &dyn CgroupReader) -> Result<Vec<HugePageSize>> {
     let mut sizes = Vec::new();
-    for hugetlb_entry in fs::read_dir("/sys/kernel/mm/hugepages")? {
-        let hugetlb_entry = hugetlb_entry?;
-        if !hugetlb_entry.path().is_dir() {
+    for dir_name in reader.read_dir(Path::new(HUGEPAGES_DIR))? {
+        if !dir_name.starts_with("hugepages-") {
             continue;
         }
 
-        let dir_name = hugetlb_entry.file_name();
-        let dir_name = dir_name.to_str().unwrap();
-
-        sizes.push(extract_page_size(dir_name)?);
+        sizes.push(extract_page_size(&dir_name)?);
     }
 
     Ok(sizes)
 }
 
-fn extract_page_size(dir_name: &str) -> Result<String> {
+/// Reports the monikers of the hugepage sizes that are supported by the
+/// system, e.g. 2MB, for callers that expect the monikers only
+pub fn supported_page_size_monikers(reader: &dyn CgroupReader) -> Result<Vec<String>> {
+    Ok(supported_page_sizes(reader)?
+        .into_iter()
+        .map(|size| size.moniker)
+        .collect())
+}
+
+fn extract_page_size(dir_name: &str) -> Result<HugePageSize> {
     if let Some(size) = dir_name
         .strip_prefix("hugepages-")
         .and_then(|name_stripped| name_stripped.strip_suffix("kB"))
     {
         let size: u64 = parse_value(size)?;
-
-        let size_moniker = if size >= (1 << 20) {
-            (size >> 20).to_string() + "GB"
-        } else if size >= (1 << 10) {
-            (size >> 10).to_string() + "MB"
-        } else {
-            size.to_string() + "KB"
-        };
-
-        return Ok(size_moniker);
+        return Ok(HugePageSize::from_bytes(size << 10));
     }
 
     bail!("failed to determine page size from {}", dir_name);
@@ -224,131 +1090,481 @@ pub fn parse_value(value: &str) -> Result<u64> {
 /// let value = parse_single_value(&Path::new("memory.current")).unwrap();
 /// assert_eq!(value, 32);
 /// ```
-pub fn parse_single_value(file_path: &Path) -> Result<u64> {
-    let value = common::read_cgroup_file(file_path)?;
-    let value = value.trim();
-    if value == "max" {
-        return Ok(u64::MAX);
-    }
-
-    value.parse().with_context(|| {
-        format!(
-            "failed to parse value {} from {}",
-            value,
-            file_path.display()
-        )
-    })
+pub fn parse_single_value(file_path: &Path) -> Result<u64, StatsError> {
+    FsReader.parse_single_value(file_path)
 }
 
 /// Parses a file that is structed according to the flat keyed format
-pub fn parse_flat_keyed_data(file_path: &Path) -> Result<HashMap<String, u64>> {
-    let mut stats = HashMap::new();
-    let keyed_data = common::read_cgroup_file(file_path)?;
-    for entry in keyed_data.lines() {
-        let entry_fields: Vec<&str> = entry.split_ascii_whitespace().collect();
-        if entry_fields.len() != 2 {
-            bail!(
-                "flat keyed data at {} contains entries that do not conform to 'key value'",
-                &file_path.display()
-            );
-        }
+pub fn parse_flat_keyed_data(file_path: &Path) -> Result<HashMap<String, u64>, StatsError> {
+    FsReader.parse_flat_keyed_data(file_path)
+}
 
-        stats.insert(
-            entry_fields[0].to_owned(),
-            entry_fields[1].parse().with_context(|| {
-                format!(
-                    "failed to parse value {} from {}",
-                    entry_fields[0],
-                    file_path.display()
-                )
-            })?,
-        );
+/// Parses a file that is structed according to the nested keyed format
+pub fn parse_nested_keyed_data(
+    file_path: &Path,
+) -> Result<HashMap<String, Vec<String>>, StatsError> {
+    FsReader.parse_nested_keyed_data(file_path)
+}
+
+/// Parses a file that is structed according to the nested keyed format
+/// # Example
+/// ```
+/// use libcgroups::stats::parse_device_number;
+///
+/// let (major, minor) = parse_device_number("8:0").unwrap();
+/// assert_eq!((major, minor), (8, 0));
+/// ```
+pub fn parse_device_number(device: &str) -> Result<(u64, u64)> {
+    let (major, minor) = device
+        .split_once(':')
+        .with_context(|| format!("failed to parse device number {}", device))?;
+    Ok((major.parse()?, minor.parse()?))
+}
+
+/// Parses a number found on the given line of a cgroup file
+fn parse_number<T: FromStr>(path: &Path, line: usize, value: &str) -> Result<T, StatsError> {
+    value
+        .parse()
+        .map_err(|_| StatsError::parse_error(path, line, format!("invalid number {}", value)))
+}
+
+/// Parses the per device statistics of a blkio file, which are either
+/// `major:minor operation value` or `major:minor value` entries. Summary
+/// entries like `Total 20` are skipped. Hosts can have hundreds of block
+/// devices, so the entries are parsed in place and only the result is
+/// allocated.
+/// # Example
+/// ```
+/// use std::path::Path;
+/// use libcgroups::stats::parse_blkio_data;
+///
+/// let data = "8:0 Read 20\n8:16 Read 10\nTotal 30";
+/// let stats = parse_blkio_data(Path::new("blkio.throttle.io_serviced"), data).unwrap();
+/// assert_eq!(stats.len(), 2);
+/// assert_eq!(stats[1].op_type.as_deref(), Some("Read"));
+/// ```
+pub fn parse_blkio_data(path: &Path, data: &str) -> Result<Vec<BlkioDeviceStat>, StatsError> {
+    let mut stats = Vec::with_capacity(data.bytes().filter(|&b| b == b'\n').count() + 1);
+    for (index, entry) in data.lines().enumerate() {
+        let mut fields = entry.split_ascii_whitespace();
+        let (device, first, second) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(device), Some(first), second) if device.contains(':') => (device, first, second),
+            _ => continue,
+        };
+
+        let line = index + 1;
+        let (major, minor) = device.split_once(':').unwrap_or_default();
+        let (op_type, value) = match second {
+            Some(value) => (Some(op_type(first)), value),
+            None => (None, first),
+        };
+        stats.push(BlkioDeviceStat {
+            major: parse_number(path, line, major)?,
+            minor: parse_number(path, line, minor)?,
+            op_type,
+            value: parse_number(path, line, value)?,
+            device_name: None,
+        });
     }
 
     Ok(stats)
 }
 
-/// Parses a file that is structed according to the nested keyed format
-pub fn parse_nested_keyed_data(file_path: &Path) -> Result<HashMap<String, Vec<String>>> {
-    let mut stats: HashMap<String, Vec<String>> = HashMap::new();
-    let keyed_data = common::read_cgroup_file(file_path)?;
-    for entry in keyed_data.lines() {
-        let entry_fields: Vec<&str> = entry.split_ascii_whitespace().collect();
-        if entry_fields.len() < 2 || !entry_fields[1..].iter().all(|p| p.contains('=')) {
-            bail!("nested key data at {} contains entries that do not conform to the nested key format", file_path.display());
-        }
+/// Returns the name of an operation without allocating it for the
+/// operations the kernel reports
+pub(crate) fn op_type(op: &str) -> Cow<'static, str> {
+    const KNOWN_OPS: [&str; 8] = [
+        "Read", "Write", "Sync", "Async", "Discard", "Total", "read", "write",
+    ];
+    match KNOWN_OPS.iter().find(|&&known| known == op) {
+        Some(&known) => Cow::Borrowed(known),
+        None => Cow::Owned(op.to_owned()),
+    }
+}
 
-        stats.insert(
-            entry_fields[0].to_owned(),
-            entry_fields[1..]
-                .iter()
-                .copied()
-                .map(|p| p.to_owned())
-                .collect(),
-        );
+/// Returns cgroup rdma statistics, which are reported the same way on cgroup
+/// v1 and v2
+pub fn rdma_stats(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<RdmaStats> {
+    let parse = |file: &str| -> Result<Vec<RdmaEntry>> {
+        let path = cgroup_path.join(file);
+        let content = reader.read(&path)?;
+        Ok(parse_rdma_entries(&path, &content)?)
+    };
+
+    Ok(RdmaStats {
+        current: parse("rdma.current")?,
+        limit: parse("rdma.max")?,
+    })
+}
+
+/// Parses the entries of rdma.current or rdma.max, e.g.
+/// `mlx4_0 hca_handle=2 hca_object=max`, one per device
+pub fn parse_rdma_entries(path: &Path, content: &str) -> Result<Vec<RdmaEntry>, StatsError> {
+    let mut entries = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let mut fields = line.split_ascii_whitespace();
+        let device = match fields.next() {
+            Some(device) => device,
+            None => continue,
+        };
+
+        let mut entry = RdmaEntry {
+            device: device.to_owned(),
+            ..Default::default()
+        };
+        for field in fields {
+            let (key, value) = field.split_once('=').ok_or_else(|| {
+                StatsError::parse_error(path, index + 1, format!("invalid entry {}", field))
+            })?;
+            let value = if value == "max" {
+                u32::MAX
+            } else {
+                parse_number(path, index + 1, value)?
+            };
+            match key {
+                "hca_handle" => entry.hca_handles = value,
+                "hca_object" => entry.hca_objects = value,
+                _ => continue,
+            }
+        }
+        entries.push(entry);
     }
 
-    Ok(stats)
+    Ok(entries)
 }
 
-/// Parses a file that is structed according to the nested keyed format
+/// Returns the cpuset statistics of a cgroup. The files holding the effective cpus
+/// and memory nodes are named differently on cgroup v1 and v2.
+pub(crate) fn cpuset_stats(
+    reader: &dyn CgroupReader,
+    cgroup_path: &Path,
+    effective_cpus_file: &str,
+    effective_mems_file: &str,
+) -> Result<CpuSetStats> {
+    let parse = |file: &str| -> Result<Vec<u32>> {
+        let path = cgroup_path.join(file);
+        let content = reader.read(&path)?;
+        Ok(parse_cpuset_list(&path, &content)?)
+    };
+
+    Ok(CpuSetStats {
+        cpus: parse("cpuset.cpus")?,
+        mems: parse("cpuset.mems")?,
+        effective_cpus: parse(effective_cpus_file)?,
+        effective_mems: parse(effective_mems_file)?,
+    })
+}
+
+/// Parses a list of cpus or memory nodes in the list format of the kernel, which
+/// consists of comma separated ids and ranges of ids
 /// # Example
 /// ```
-/// use libcgroups::stats::parse_device_number;
+/// use std::path::Path;
+/// use libcgroups::stats::parse_cpuset_list;
 ///
-/// let (major, minor) = parse_device_number("8:0").unwrap();
-/// assert_eq!((major, minor), (8, 0));
+/// let cpus = parse_cpuset_list(Path::new("cpuset.cpus"), "0-2,4\n").unwrap();
+/// assert_eq!(cpus, vec![0, 1, 2, 4]);
 /// ```
-pub fn parse_device_number(device: &str) -> Result<(u64, u64)> {
-    let numbers: Vec<&str> = device.split_terminator(':').collect();
-    if numbers.len() != 2 {
-        bail!("failed to parse device number {}", device);
+pub fn parse_cpuset_list(path: &Path, content: &str) -> Result<Vec<u32>, StatsError> {
+    // the list is a single line
+    let mut ids = Vec::new();
+    for item in content.trim().split(',').filter(|item| !item.is_empty()) {
+        match item.split_once('-') {
+            Some((start, end)) => {
+                let start: u32 = parse_number(path, 1, start)?;
+                let end: u32 = parse_number(path, 1, end)?;
+                if start > end {
+                    return Err(StatsError::parse_error(
+                        path,
+                        1,
+                        format!("invalid range {}", item),
+                    ));
+                }
+                ids.extend(start..=end);
+            }
+            None => ids.push(parse_number(path, 1, item)?),
+        }
+    }
+
+    Ok(ids)
+}
+
+/// Returns the paths of all descendants of a cgroup, parents before their
+/// children. Directories without a cgroup.procs file are not cgroups.
+pub fn descendant_cgroups(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<Vec<PathBuf>> {
+    let mut descendants = Vec::new();
+    let mut pending = vec![cgroup_path.to_owned()];
+    while let Some(path) = pending.pop() {
+        for name in reader.read_dir(&path)? {
+            let child = path.join(name);
+            if reader.exists(&child.join(CGROUP_PROCS)) {
+                descendants.push(child.clone());
+                pending.push(child);
+            }
+        }
     }
 
-    Ok((numbers[0].parse()?, numbers[1].parse()?))
+    Ok(descendants)
 }
 
 /// Returns cgroup pid statistics
-pub fn pid_stats(cgroup_path: &Path) -> Result<PidStats> {
+pub fn pid_stats(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<PidStats> {
     let mut stats = PidStats::default();
 
-    let current = common::read_cgroup_file(cgroup_path.join("pids.current"))?;
+    let current_path = cgroup_path.join("pids.current");
+    let current = reader.read(&current_path)?;
     stats.current = current
         .trim()
         .parse()
-        .context("failed to parse current pids")?;
+        .map_err(|_| StatsError::parse_error(&current_path, 1, "invalid number of pids"))?;
 
-    let limit =
-        common::read_cgroup_file(cgroup_path.join("pids.max")).map(|l| l.trim().to_owned())?;
+    let limit_path = cgroup_path.join("pids.max");
+    let limit = reader.read(&limit_path)?;
+    let limit = limit.trim();
     if limit != "max" {
-        stats.limit = limit.parse().context("failed to parse pids limit")?;
+        stats.limit = limit
+            .parse()
+            .map_err(|_| StatsError::parse_error(&limit_path, 1, "invalid pids limit"))?;
     }
 
     Ok(stats)
 }
 
+/// Parses the statistics of the network interfaces in the format of
+/// /proc/<pid>/net/dev. The loopback interface is skipped, as its traffic
+/// never leaves the container.
+/// # Example
+/// ```
+/// use std::path::Path;
+/// use libcgroups::stats::parse_net_dev;
+///
+/// let content = "Inter-|   Receive                            |  Transmit
+///  face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+///   eth0:    1500      10    0    1    0     0          0         0      900       8    0    0    0     0       0          0";
+/// let interfaces = parse_net_dev(Path::new("/proc/1/net/dev"), content).unwrap();
+/// assert_eq!(interfaces[0].name, "eth0");
+/// assert_eq!(interfaces[0].rx_bytes, 1500);
+/// ```
+pub fn parse_net_dev(path: &Path, content: &str) -> Result<Vec<NetworkInterface>, StatsError> {
+    let mut interfaces = Vec::new();
+    // the first two lines are the header
+    for (index, line) in content.lines().enumerate().skip(2) {
+        let (name, counters) = match line.split_once(':') {
+            Some((name, counters)) => (name.trim(), counters),
+            None => continue,
+        };
+        if name == "lo" {
+            continue;
+        }
+
+        let counters = counters
+            .split_ascii_whitespace()
+            .map(|counter| parse_number(path, index + 1, counter))
+            .collect::<Result<Vec<u64>, StatsError>>()?;
+        // receive: bytes packets errs drop fifo frame compressed multicast
+        // transmit: bytes packets errs drop fifo colls carrier compressed
+        if counters.len() < 12 {
+            return Err(StatsError::parse_error(
+                path,
+                index + 1,
+                format!("missing statistics of interface {}", name),
+            ));
+        }
+
+        interfaces.push(NetworkInterface {
+            name: name.to_owned(),
+            rx_bytes: counters[0],
+            rx_packets: counters[1],
+            rx_errors: counters[2],
+            rx_dropped: counters[3],
+            tx_bytes: counters[8],
+            tx_packets: counters[9],
+            tx_errors: counters[10],
+            tx_dropped: counters[11],
+        });
+    }
+
+    Ok(interfaces)
+}
+
+/// Reads memory.numa_stat. The values are multiplied with the unit, as cgroup
+/// v1 reports them in pages and cgroup v2 in bytes. Returns an empty map if the
+/// file does not exist, which is the case on kernels without NUMA support.
+pub fn numa_stats(
+    reader: &dyn CgroupReader,
+    file_path: &Path,
+    unit: u64,
+) -> Result<HashMap<u32, NumaNodeMemory>> {
+    match reader.read(file_path) {
+        Ok(content) => Ok(parse_numa_stat(file_path, &content, unit)?),
+        Err(StatsError::MissingFile { .. }) => Ok(HashMap::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Parses the content of memory.numa_stat. On cgroup v1 each line starts
+/// with the type of memory and its total, e.g. `anon=183 N0=17 N1=166`, on
+/// cgroup v2 only with the type, e.g. `anon N0=69632 N1=679936`. The
+/// hierarchical entries of cgroup v1 are skipped, as they include the memory
+/// of the child cgroups.
+/// # Example
+/// ```
+/// use std::path::Path;
+/// use libcgroups::stats::{parse_numa_stat, Bytes};
+///
+/// let content = "anon=3 N0=1 N1=2\nfile=4 N0=4 N1=0";
+/// let numa = parse_numa_stat(Path::new("memory.numa_stat"), content, 4096).unwrap();
+/// assert_eq!(numa[&1].anon, Bytes(8192));
+/// assert_eq!(numa[&0].file, Bytes(16384));
+/// ```
+pub fn parse_numa_stat(
+    path: &Path,
+    content: &str,
+    unit: u64,
+) -> Result<HashMap<u32, NumaNodeMemory>, StatsError> {
+    let mut numa: HashMap<u32, NumaNodeMemory> = HashMap::new();
+    for (index, line) in content.lines().enumerate() {
+        let mut fields = line.split_ascii_whitespace();
+        let key = match fields.next() {
+            Some(key) => key.split_once('=').map_or(key, |(key, _)| key),
+            None => continue,
+        };
+
+        for field in fields {
+            let (node, value) = field
+                .strip_prefix('N')
+                .and_then(|field| field.split_once('='))
+                .ok_or_else(|| {
+                    StatsError::parse_error(path, index + 1, format!("invalid entry {}", field))
+                })?;
+            let node: u32 = parse_number(path, index + 1, node)?;
+            let value: u64 = parse_number(path, index + 1, value)?;
+            let value = Bytes(value.saturating_mul(unit));
+
+            let memory = numa.entry(node).or_default();
+            match key {
+                "anon" => memory.anon = value,
+                "file" => memory.file = value,
+                "unevictable" => memory.unevictable = value,
+                _ => continue,
+            }
+        }
+    }
+
+    Ok(numa)
+}
+
+/// Parses the block devices listed in /proc/partitions into a map of their
+/// major and minor device numbers to their names
+/// # Example
+/// ```
+/// use libcgroups::stats::parse_partitions;
+///
+/// let content = "major minor  #blocks  name
+///
+///    8        0  488386584 sda
+///    8        1     524288 sda1";
+/// let names = parse_partitions(content);
+/// assert_eq!(names[&(8, 1)], "sda1");
+/// ```
+pub fn parse_partitions(content: &str) -> HashMap<(u64, u64), String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_ascii_whitespace().collect();
+            match fields[..] {
+                [major, minor, _, name] => {
+                    Some(((major.parse().ok()?, minor.parse().ok()?), name.to_owned()))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Parses a pressure file like cpu.pressure. Returns None if the file does
+/// not exist, which is the case if the kernel has been built or booted without
+/// PSI support.
+pub fn psi_stats(reader: &dyn CgroupReader, psi_path: &Path) -> Result<Option<PsiStats>> {
+    let content = match reader.read(psi_path) {
+        Ok(content) => content,
+        Err(StatsError::MissingFile { .. }) => return Ok(None),
+        Err(StatsError::Io { source, .. })
+            if source.raw_os_error() == Some(Errno::EOPNOTSUPP as i32) =>
+        {
+            return Ok(None);
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut stats = PsiStats::default();
+    for line in content.lines() {
+        let mut fields = line.split_ascii_whitespace();
+        let data = match fields.next() {
+            Some("some") => &mut stats.some,
+            Some("full") => &mut stats.full,
+            _ => continue,
+        };
+
+        for field in fields {
+            let (key, value) = field
+                .split_once('=')
+                .with_context(|| format!("invalid entry {} in {}", field, psi_path.display()))?;
+            let parse_err = || format!("failed to parse {} in {}", field, psi_path.display());
+            match key {
+                "avg10" => data.avg10 = value.parse().with_context(parse_err)?,
+                "avg60" => data.avg60 = value.parse().with_context(parse_err)?,
+                "avg300" => data.avg300 = value.parse().with_context(parse_err)?,
+                "total" => data.total = value.parse().with_context(parse_err)?,
+                _ => continue,
+            }
+        }
+    }
+
+    Ok(Some(stats))
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::test::{create_temp_dir, set_fixture};
+    use crate::{
+        reader::FakeReader,
+        test::{create_temp_dir, set_fixture},
+    };
 
     use super::*;
 
     #[test]
     fn test_supported_page_sizes_gigabyte() {
         let page_size = extract_page_size("hugepages-1048576kB").unwrap();
-        assert_eq!(page_size, "1GB");
+        assert_eq!(page_size.moniker, "1GB");
+        assert_eq!(page_size.bytes, 1 << 30);
     }
 
     #[test]
     fn test_supported_page_sizes_megabyte() {
         let page_size = extract_page_size("hugepages-2048kB").unwrap();
-        assert_eq!(page_size, "2MB");
+        assert_eq!(page_size.moniker, "2MB");
     }
 
     #[test]
     fn test_supported_page_sizes_kilobyte() {
         let page_size = extract_page_size("hugepages-512kB").unwrap();
-        assert_eq!(page_size, "512KB");
+        assert_eq!(page_size.moniker, "512KB");
+    }
+
+    #[test]
+    fn test_parse_hugepage_size() {
+        let page_size: HugePageSize = "2MB".parse().unwrap();
+        assert_eq!(page_size, HugePageSize::from_bytes(2 << 20));
+        assert_eq!(page_size.moniker, "2MB");
+
+        // the moniker is normalized to the one the kernel uses
+        let page_size: HugePageSize = "2048KB".parse().unwrap();
+        assert_eq!(page_size.moniker, "2MB");
+
+        assert!("3MB".parse::<HugePageSize>().is_err());
+        assert!("2XB".parse::<HugePageSize>().is_err());
+        assert!("MB".parse::<HugePageSize>().is_err());
     }
 
     #[test]
@@ -509,15 +1725,426 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_parse_missing_file() {
+        let tmp = create_temp_dir("test_parse_missing_file").unwrap();
+        let file_path = tmp.join("memory.current");
+
+        let result = parse_single_value(&file_path);
+        assert!(matches!(result, Err(StatsError::MissingFile { path }) if path == file_path));
+    }
+
+    #[test]
+    fn test_parse_error_context() {
+        let tmp = create_temp_dir("test_parse_error_context").unwrap();
+        let file_content = ["key1 1", "key2 2", "key3 c"].join("\n");
+        let file_path = set_fixture(&tmp, "flat_keyed_data", &file_content).unwrap();
+
+        let err = parse_flat_keyed_data(&file_path).unwrap_err();
+        assert!(matches!(&err, StatsError::ParseError { path, line: 3, .. } if path == &file_path));
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "failed to parse {}:3: invalid value of key3",
+                file_path.display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_check_available_not_enabled() {
+        let mut stats = Stats::default();
+        let err = StatsError::UnsupportedController {
+            controller: "hugetlb".to_owned(),
+        };
+        let err = Err::<u64, _>(anyhow::Error::from(err)).context("failed to get hugetlb stats");
+        assert!(matches!(
+            stats_error(err.as_ref().unwrap_err()),
+            Some(StatsError::UnsupportedController { .. })
+        ));
+
+        let result = stats.check_available("hugetlb", err);
+        assert_eq!(result, None);
+        assert_eq!(stats.unavailable, vec!["hugetlb".to_owned()]);
+        assert!(stats.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_check_available_permission_denied() {
+        let mut stats = Stats::default();
+        let err = Err::<u64, _>(io::Error::from(io::ErrorKind::PermissionDenied))
+            .context("failed to open memory.stat")
+            .context("failed to get memory stats");
+
+        let result = stats.check_available("memory", err);
+        assert_eq!(result, None);
+        assert_eq!(stats.unavailable, vec!["memory".to_owned()]);
+        assert!(stats.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_check_available_other_error() {
+        let mut stats = Stats::default();
+        let err =
+            Err::<u64, _>(anyhow::anyhow!("unexpected token")).context("failed to parse blkio");
+
+        let result = stats.check_available("blkio", err);
+        assert_eq!(result, None);
+        assert!(stats.unavailable.is_empty());
+        assert_eq!(
+            stats.diagnostics,
+            vec![StatsDiagnostic {
+                controller: "blkio".to_owned(),
+                error: "failed to parse blkio: unexpected token".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_check_available_success() {
+        let mut stats = Stats::default();
+        let result = stats.check_available("pids", Ok(32));
+        assert_eq!(result, Some(32));
+        assert!(stats.unavailable.is_empty());
+        assert!(stats.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_display_bytes() {
+        assert_eq!(Bytes(512).to_string(), "512 B");
+        assert_eq!(Bytes(1536).to_string(), "1.50 KiB");
+        assert_eq!(Bytes(256 * 1024 * 1024).to_string(), "256.00 MiB");
+        assert_eq!(Bytes(3 * 1024 * 1024 * 1024).to_string(), "3.00 GiB");
+        assert_eq!(Bytes(u64::MAX).to_string(), "max");
+    }
+
+    #[test]
+    fn test_display_nanoseconds() {
+        assert_eq!(Nanoseconds(800).to_string(), "800ns");
+        assert_eq!(Nanoseconds::from_micros(350).to_string(), "350.00µs");
+        assert_eq!(Nanoseconds(12_500_000).to_string(), "12.50ms");
+        assert_eq!(Nanoseconds(1_500_000_000).to_string(), "1.50s");
+    }
+
+    #[test]
+    fn test_serialize_as_plain_numbers() {
+        let data = MemoryData {
+            usage: Bytes(1024),
+            limit: Bytes(2048),
+            ..Default::default()
+        };
+
+        let json = serde_json::to_string(&data).unwrap();
+        assert_eq!(json, r#"{"usage":1024,"max":0,"failcnt":0,"limit":2048}"#);
+        assert_eq!(serde_json::to_string(&Nanoseconds(42)).unwrap(), "42");
+    }
+
+    #[test]
+    fn test_roundtrip_runc_field_names() {
+        let mut stats = Stats::default();
+        stats.cpu.usage.usage_total = Nanoseconds(100);
+        stats.cpu.throttling.throttled_periods = 3;
+        stats.memory.memory.max_usage = Bytes(4096);
+        stats.memory.stats.insert("rss".to_owned(), 1024);
+        stats.blkio.service_bytes.push(BlkioDeviceStat {
+            major: 8,
+            minor: 0,
+            op_type: Some("Read".into()),
+            value: 512,
+            device_name: None,
+        });
+        stats.blkio.serviced.push(BlkioDeviceStat {
+            major: 8,
+            minor: 0,
+            op_type: None,
+            value: 2,
+            device_name: None,
+        });
+
+        let json: serde_json::Value = serde_json::to_value(&stats).unwrap();
+        assert_eq!(json["cpu"]["usage"]["total"], 100);
+        assert_eq!(json["cpu"]["throttling"]["throttled_periods"], 3);
+        assert_eq!(json["memory"]["usage"]["max"], 4096);
+        assert_eq!(json["memory"]["raw"]["rss"], 1024);
+        assert_eq!(json["blkio"]["ioServiceBytesRecursive"][0]["op"], "Read");
+        assert!(json["blkio"]["ioServicedRecursive"][0].get("op").is_none());
+
+        let parsed: Stats = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.cpu.usage, stats.cpu.usage);
+        assert_eq!(parsed.memory.memory, stats.memory.memory);
+        assert_eq!(parsed.blkio, stats.blkio);
+
+        // fields that runc omits are left at their defaults
+        let parsed: Stats = serde_json::from_str(r#"{"pids":{"current":4}}"#).unwrap();
+        assert_eq!(parsed.pids.current, 4);
+        assert_eq!(parsed.pids.limit, 0);
+    }
+
     #[test]
     fn test_parse_device_number() {
         let (major, minor) = parse_device_number("8:0").unwrap();
         assert_eq!((major, minor), (8, 0));
     }
 
+    #[test]
+    fn test_parse_blkio_data() {
+        let data = "8:0 Read 20\n8:0 Custom 5\n253:1 1500\nTotal 25\n";
+        let stats = parse_blkio_data(Path::new("blkio.io_serviced"), data).unwrap();
+        assert_eq!(
+            stats,
+            vec![
+                BlkioDeviceStat {
+                    major: 8,
+                    minor: 0,
+                    op_type: Some(Cow::Borrowed("Read")),
+                    value: 20,
+                    device_name: None,
+                },
+                BlkioDeviceStat {
+                    major: 8,
+                    minor: 0,
+                    op_type: Some(Cow::Owned("Custom".to_owned())),
+                    value: 5,
+                    device_name: None,
+                },
+                BlkioDeviceStat {
+                    major: 253,
+                    minor: 1,
+                    op_type: None,
+                    value: 1500,
+                    device_name: None,
+                },
+            ]
+        );
+        assert!(matches!(stats[0].op_type, Some(Cow::Borrowed(_))));
+        let err =
+            parse_blkio_data(Path::new("blkio.io_serviced"), "8:0 Read 1\n8:0 Read x").unwrap_err();
+        assert!(matches!(err, StatsError::ParseError { line: 2, .. }));
+        assert!(parse_blkio_data(Path::new("blkio.io_serviced"), "a:b Read 1").is_err());
+    }
+
     #[test]
     fn test_parse_invalid_device_number() {
         let result = parse_device_number("a:b");
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_psi_stats() {
+        let content = [
+            "some avg10=1.50 avg60=0.75 avg300=0.10 total=123456",
+            "full avg10=0.00 avg60=0.25 avg300=0.05 total=4242",
+        ]
+        .join("\n");
+        let reader = FakeReader::default().with_file("/test/memory.pressure", content);
+
+        let actual = psi_stats(&reader, Path::new("/test/memory.pressure"))
+            .expect("parse psi stats")
+            .expect("psi stats are present");
+        assert_eq!(
+            actual.some,
+            PsiData {
+                avg10: 1.5,
+                avg60: 0.75,
+                avg300: 0.1,
+                total: 123456,
+            }
+        );
+        assert_eq!(actual.full.total, 4242);
+        assert_eq!(actual.full.avg60, 0.25);
+
+        assert!(psi_stats(&reader, Path::new("/test/io.pressure"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_parse_net_dev() {
+        let content = [
+            "Inter-|   Receive                                                |  Transmit",
+            " face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed",
+            "    lo:    2048      20    0    0    0     0          0         0     2048      20    0    0    0     0       0          0",
+            "  eth0:12345678   9000    1    2    0     0          0         3  8765432    7000    4    5    0     0       0          0",
+        ]
+        .join("\n");
+
+        let path = Path::new("/proc/1/net/dev");
+        let interfaces = parse_net_dev(path, &content).expect("parse net dev");
+        assert_eq!(
+            interfaces,
+            vec![NetworkInterface {
+                name: "eth0".to_owned(),
+                rx_bytes: 12345678,
+                rx_packets: 9000,
+                rx_errors: 1,
+                rx_dropped: 2,
+                tx_bytes: 8765432,
+                tx_packets: 7000,
+                tx_errors: 4,
+                tx_dropped: 5,
+            }]
+        );
+
+        let err = parse_net_dev(path, "header\nheader\n  eth0: 1 2 3\n").unwrap_err();
+        assert!(matches!(err, StatsError::ParseError { line: 3, .. }));
+    }
+
+    #[test]
+    fn test_parse_numa_stat() {
+        let v1 = [
+            "total=44611 N0=32631 N1=11980",
+            "file=44428 N0=32614 N1=11814",
+            "anon=183 N0=17 N1=166",
+            "unevictable=0 N0=0 N1=0",
+            "hierarchical_anon=500 N0=300 N1=200",
+        ]
+        .join("\n");
+        let path = Path::new("memory.numa_stat");
+        let numa = parse_numa_stat(path, &v1, 4096).expect("parse v1 numa stat");
+        assert_eq!(
+            numa[&1],
+            NumaNodeMemory {
+                anon: Bytes(166 * 4096),
+                file: Bytes(11814 * 4096),
+                unevictable: Bytes(0),
+            }
+        );
+
+        let v2 = [
+            "anon N0=69632 N1=679936",
+            "file N0=1204224 N1=0",
+            "kernel_stack N0=16384 N1=0",
+            "unevictable N0=0 N1=4096",
+        ]
+        .join("\n");
+        let numa = parse_numa_stat(path, &v2, 1).expect("parse v2 numa stat");
+        assert_eq!(numa.len(), 2);
+        assert_eq!(numa[&0].anon, Bytes(69632));
+        assert_eq!(numa[&0].file, Bytes(1204224));
+        assert_eq!(numa[&1].unevictable, Bytes(4096));
+
+        assert!(parse_numa_stat(path, "anon X0=1", 1).is_err());
+    }
+
+    #[test]
+    fn test_set_device_names() {
+        let content = [
+            "major minor  #blocks  name",
+            "",
+            "   8        0  488386584 sda",
+            " 253        1   41943040 dm-1",
+        ]
+        .join("\n");
+        let names = parse_partitions(&content);
+        assert_eq!(names.len(), 2);
+
+        let mut blkio = BlkioStats::default();
+        for (major, minor) in [(8, 0), (8, 16)] {
+            blkio.serviced.push(BlkioDeviceStat {
+                major,
+                minor,
+                op_type: None,
+                value: 1,
+                device_name: None,
+            });
+        }
+        blkio.set_device_names(&names);
+        assert_eq!(blkio.serviced[0].device_name.as_deref(), Some("sda"));
+        assert_eq!(blkio.serviced[1].device_name, None);
+    }
+
+    #[test]
+    fn test_rdma_stats() {
+        let reader = FakeReader::default()
+            .with_file("/test/rdma.current", "mlx4_0 hca_handle=1 hca_object=20\n")
+            .with_file(
+                "/test/rdma.max",
+                "mlx4_0 hca_handle=2 hca_object=2000\nocrdma1 hca_handle=3 hca_object=max\n",
+            );
+
+        let stats = rdma_stats(&reader, Path::new("/test")).expect("get rdma stats");
+        assert_eq!(
+            stats.current,
+            vec![RdmaEntry {
+                device: "mlx4_0".to_owned(),
+                hca_handles: 1,
+                hca_objects: 20,
+            }]
+        );
+        assert_eq!(stats.limit.len(), 2);
+        assert_eq!(stats.limit[1].device, "ocrdma1");
+        assert_eq!(stats.limit[1].hca_handles, 3);
+        assert_eq!(stats.limit[1].hca_objects, u32::MAX);
+
+        let err = parse_rdma_entries(Path::new("rdma.max"), "mlx4_0 hca_handle").unwrap_err();
+        assert!(matches!(err, StatsError::ParseError { line: 1, .. }));
+    }
+
+    #[test]
+    fn test_parse_cpuset_list() {
+        let path = Path::new("cpuset.cpus");
+        assert_eq!(parse_cpuset_list(path, "").unwrap(), Vec::<u32>::new());
+        assert_eq!(parse_cpuset_list(path, "0\n").unwrap(), vec![0]);
+        assert_eq!(
+            parse_cpuset_list(path, "0-3,8,10-11").unwrap(),
+            vec![0, 1, 2, 3, 8, 10, 11]
+        );
+        assert!(parse_cpuset_list(path, "3-1").is_err());
+        assert!(matches!(
+            parse_cpuset_list(path, "a"),
+            Err(StatsError::ParseError { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_stats_collector() {
+        let tmp = create_temp_dir("test_stats_collector").expect("create test directory");
+        set_fixture(&tmp, "pids.current", "5\n").unwrap();
+        set_fixture(&tmp, "pids.max", "max\n").unwrap();
+        set_fixture(&tmp, "rdma.current", "mlx4_0 hca_handle\n").unwrap();
+
+        let mut collector = StatsCollector::default();
+        collector.collect(
+            "pids",
+            &tmp,
+            |path| pid_stats(&FsReader, path),
+            |stats, pids| stats.pids = pids,
+        );
+        collector.collect(
+            "rdma",
+            &tmp,
+            |path| rdma_stats(&FsReader, path),
+            |stats, rdma| stats.rdma = Some(rdma),
+        );
+        collector.collect("panic", &tmp, |_| -> Result<u64> { panic!() }, |_, _| {});
+        let mut stats = Stats::default();
+        collector.join(&mut stats);
+
+        assert_eq!(stats.pids.current, 5);
+        assert!(stats.rdma.is_none());
+        assert_eq!(stats.diagnostics.len(), 2);
+        assert_eq!(stats.diagnostics[0].controller, "rdma");
+        assert_eq!(stats.diagnostics[1].controller, "panic");
+    }
+
+    #[test]
+    fn test_descendant_cgroups() {
+        let reader = FakeReader::default()
+            .with_file("/test/cgroup.procs", "")
+            .with_file("/test/a/cgroup.procs", "")
+            .with_file("/test/a/b/cgroup.procs", "")
+            .with_file("/test/c/cgroup.procs", "")
+            .with_file("/test/not_a_cgroup/file", "");
+
+        let mut descendants = descendant_cgroups(&reader, Path::new("/test")).unwrap();
+        descendants.sort();
+        assert_eq!(
+            descendants,
+            vec![
+                PathBuf::from("/test/a"),
+                PathBuf::from("/test/a/b"),
+                PathBuf::from("/test/c")
+            ]
+        );
+    }
 }
@@ -0,0 +1,164 @@
+//! Caching of statistics for callers that read them in bursts, e.g. a metrics
+//! endpoint that is polled by several scrapers, so that the cgroup files are
+//! read at most once per time to live. The caller passes a stamp along, e.g.
+//! the modification time of the container state, which changes when another
+//! process pauses or resumes the container. Cached statistics with a
+//! different stamp are collected again before their time to live has passed.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::{fs, path::Path, time::Duration};
+//! use libcgroups::{reader::FsReader, stats, stats_cache::StatsCache};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let cache = StatsCache::new(Duration::from_secs(1));
+//! let stamp = fs::metadata("/run/youki/container/state.json")?.modified().ok();
+//! let pids = cache.get(Path::new("/sys/fs/cgroup/youki"), stamp, |path| {
+//!     stats::pid_stats(&FsReader, path)
+//! })?;
+//! println!("{}", pids.current);
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::Result;
+
+use crate::stats::StatsProvider;
+
+struct Entry<T> {
+    collected: Instant,
+    stamp: Option<SystemTime>,
+    stats: Arc<T>,
+}
+
+/// Cache of statistics keyed by the path of the cgroup they belong to
+pub struct StatsCache<T> {
+    ttl: Duration,
+    entries: Mutex<HashMap<PathBuf, Entry<T>>>,
+}
+
+impl<T> StatsCache<T> {
+    /// Creates a cache whose entries are collected again once they are older than `ttl`
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the cached statistics of the cgroup or collects them with `collect`
+    /// if they are missing, expired or have been cached with another stamp.
+    /// Errors are not cached.
+    pub fn get<F>(
+        &self,
+        cgroup_path: &Path,
+        stamp: Option<SystemTime>,
+        collect: F,
+    ) -> Result<Arc<T>>
+    where
+        F: FnOnce(&Path) -> Result<T>,
+    {
+        if let Some(entry) = self.entries.lock().unwrap().get(cgroup_path) {
+            if entry.stamp == stamp && entry.collected.elapsed() < self.ttl {
+                return Ok(Arc::clone(&entry.stats));
+            }
+        }
+
+        // the lock is not held while collecting, so that callers asking for
+        // other cgroups are not blocked by slow reads
+        let stats = Arc::new(collect(cgroup_path)?);
+        self.entries.lock().unwrap().insert(
+            cgroup_path.to_owned(),
+            Entry {
+                collected: Instant::now(),
+                stamp,
+                stats: Arc::clone(&stats),
+            },
+        );
+
+        Ok(stats)
+    }
+
+    /// Returns the cached statistics of a controller for the cgroup, see `get`
+    pub fn stats<P>(&self, cgroup_path: &Path, stamp: Option<SystemTime>) -> Result<Arc<T>>
+    where
+        P: StatsProvider<Stats = T>,
+    {
+        self.get(cgroup_path, stamp, P::stats)
+    }
+
+    /// Drops the cached statistics of the cgroup and of its descendants
+    pub fn invalidate(&self, cgroup_path: &Path) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|path, _| !path.starts_with(cgroup_path));
+    }
+
+    /// Drops all cached statistics
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_cache_hit_and_expiry() -> Result<()> {
+        let collected = Cell::new(0);
+        let collect = |_: &Path| -> Result<u64> {
+            collected.set(collected.get() + 1);
+            Ok(collected.get())
+        };
+        let path = Path::new("/sys/fs/cgroup/test");
+
+        let stamp = Some(SystemTime::UNIX_EPOCH);
+
+        let cache = StatsCache::new(Duration::from_secs(60));
+        assert_eq!(*cache.get(path, stamp, collect)?, 1);
+        assert_eq!(*cache.get(path, stamp, collect)?, 1);
+        cache.invalidate(Path::new("/sys/fs/cgroup"));
+        assert_eq!(*cache.get(path, stamp, collect)?, 2);
+
+        let cache = StatsCache::new(Duration::ZERO);
+        assert_eq!(*cache.get(path, stamp, collect)?, 3);
+        assert_eq!(*cache.get(path, stamp, collect)?, 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_changed_stamp() -> Result<()> {
+        let path = Path::new("/sys/fs/cgroup/test");
+        let cache = StatsCache::new(Duration::from_secs(60));
+        let paused = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1));
+        assert_eq!(
+            *cache.get(path, Some(SystemTime::UNIX_EPOCH), |_| Ok(1))?,
+            1
+        );
+        // e.g. another process paused the container in the meantime
+        assert_eq!(*cache.get(path, paused, |_| Ok(2))?, 2);
+        assert_eq!(*cache.get(path, paused, |_| Ok(3))?, 2);
+        assert_eq!(*cache.get(path, None, |_| Ok(4))?, 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors_are_not_cached() {
+        let path = Path::new("/sys/fs/cgroup/test");
+        let cache: StatsCache<u64> = StatsCache::new(Duration::from_secs(60));
+        assert!(cache
+            .get(path, None, |_| anyhow::bail!("unreadable"))
+            .is_err());
+        assert_eq!(*cache.get(path, None, |_| Ok(1)).unwrap(), 1);
+    }
+}
@@ -0,0 +1,265 @@
+//! Rates derived from two snapshots of the statistics of a cgroup, e.g. the
+//! cpu utilization, as the kernel only reports cumulative counters and the
+//! current memory usage.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::{thread, time::Duration};
+//! use libcgroups::{common::CgroupManager, stats_delta::StatsSampler};
+//!
+//! # fn rates(manager: &dyn CgroupManager) -> anyhow::Result<()> {
+//! let mut sampler = StatsSampler::default();
+//! loop {
+//!     if let Some(delta) = sampler.sample(&manager.stats()?) {
+//!         println!("cpu {:.1}%", delta.cpu_percent);
+//!     }
+//!     thread::sleep(Duration::from_secs(1));
+//! }
+//! # }
+//! ```
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::stats::{BlkioDeviceStat, Stats};
+
+/// Rates of change between two snapshots of the statistics of a cgroup. The
+/// percentages are relative to a single cpu, i.e. a container that keeps two
+/// cpus busy has a cpu utilization of 200%. Counters that have been reset in
+/// between, e.g. because the cgroup has been recreated, count as zero.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatsDelta {
+    /// Wall clock time between the snapshots
+    pub elapsed: Duration,
+    /// Cpu time consumed per wall clock time in percent
+    pub cpu_percent: f64,
+    /// Cpu time consumed in user mode per wall clock time in percent
+    pub cpu_user_percent: f64,
+    /// Cpu time consumed in kernel mode per wall clock time in percent
+    pub cpu_system_percent: f64,
+    /// Enforcement periods of the cpu quota in which the cgroup has been
+    /// throttled, in percent of the elapsed periods
+    pub throttled_percent: f64,
+    /// Bytes read from block devices per second
+    pub io_read_bytes_per_second: f64,
+    /// Bytes written to block devices per second
+    pub io_write_bytes_per_second: f64,
+    /// Read operations on block devices per second
+    pub io_read_ops_per_second: f64,
+    /// Write operations on block devices per second
+    pub io_write_ops_per_second: f64,
+    /// Change of the memory usage per second, which is negative if the usage shrinks
+    pub memory_growth_bytes_per_second: f64,
+}
+
+impl StatsDelta {
+    /// Computes the rates between the previous and the current snapshot, which
+    /// have been taken at the given times
+    pub fn new(
+        previous: &Stats,
+        previous_time: SystemTime,
+        current: &Stats,
+        current_time: SystemTime,
+    ) -> Result<Self> {
+        let elapsed = current_time
+            .duration_since(previous_time)
+            .context("the current snapshot has been taken before the previous one")?;
+        Ok(Counters::from(current).delta(&Counters::from(previous), elapsed))
+    }
+}
+
+/// Computes the rates between consecutive snapshots. Only the counters of the
+/// previous snapshot are kept, not the snapshot itself.
+#[derive(Debug, Default)]
+pub struct StatsSampler {
+    previous: Option<(Counters, SystemTime)>,
+}
+
+impl StatsSampler {
+    /// Records a snapshot taken now and returns the rates since the previous
+    /// one, or None for the first snapshot
+    pub fn sample(&mut self, stats: &Stats) -> Option<StatsDelta> {
+        self.sample_at(stats, SystemTime::now())
+    }
+
+    /// Records a snapshot taken at the given time and returns the rates since
+    /// the previous one. If the wall clock has been set back in between, no
+    /// rates are returned and the snapshot becomes the new starting point.
+    pub fn sample_at(&mut self, stats: &Stats, timestamp: SystemTime) -> Option<StatsDelta> {
+        let current = Counters::from(stats);
+        let delta = self
+            .previous
+            .as_ref()
+            .and_then(|(previous, previous_time)| {
+                let elapsed = timestamp.duration_since(*previous_time).ok()?;
+                Some(current.delta(previous, elapsed))
+            });
+        self.previous = Some((current, timestamp));
+        delta
+    }
+}
+
+/// Counters of a snapshot that the rates are computed from
+#[derive(Debug, Default, Clone, Copy)]
+struct Counters {
+    cpu_total: u64,
+    cpu_user: u64,
+    cpu_kernel: u64,
+    periods: u64,
+    throttled_periods: u64,
+    io_read_bytes: u64,
+    io_write_bytes: u64,
+    io_read_ops: u64,
+    io_write_ops: u64,
+    memory_usage: u64,
+}
+
+impl From<&Stats> for Counters {
+    fn from(stats: &Stats) -> Self {
+        let (io_read_bytes, io_write_bytes) = io_totals(&stats.blkio.service_bytes);
+        let (io_read_ops, io_write_ops) = io_totals(&stats.blkio.serviced);
+        Self {
+            cpu_total: stats.cpu.usage.usage_total.0,
+            cpu_user: stats.cpu.usage.usage_user.0,
+            cpu_kernel: stats.cpu.usage.usage_kernel.0,
+            periods: stats.cpu.throttling.periods,
+            throttled_periods: stats.cpu.throttling.throttled_periods,
+            io_read_bytes,
+            io_write_bytes,
+            io_read_ops,
+            io_write_ops,
+            memory_usage: stats.memory.memory.usage.0,
+        }
+    }
+}
+
+impl Counters {
+    fn delta(&self, previous: &Counters, elapsed: Duration) -> StatsDelta {
+        let seconds = elapsed.as_secs_f64();
+        let per_second = |current: u64, previous: u64| {
+            if seconds == 0.0 {
+                return 0.0;
+            }
+            current.saturating_sub(previous) as f64 / seconds
+        };
+        // the cpu times are in nanoseconds
+        let cpu_percent = |current: u64, previous: u64| per_second(current, previous) / 1e9 * 100.0;
+
+        let periods = self.periods.saturating_sub(previous.periods);
+        let throttled_percent = if periods != 0 {
+            self.throttled_periods
+                .saturating_sub(previous.throttled_periods) as f64
+                / periods as f64
+                * 100.0
+        } else {
+            0.0
+        };
+
+        let memory_growth_bytes_per_second = if seconds != 0.0 {
+            (self.memory_usage as f64 - previous.memory_usage as f64) / seconds
+        } else {
+            0.0
+        };
+
+        StatsDelta {
+            elapsed,
+            cpu_percent: cpu_percent(self.cpu_total, previous.cpu_total),
+            cpu_user_percent: cpu_percent(self.cpu_user, previous.cpu_user),
+            cpu_system_percent: cpu_percent(self.cpu_kernel, previous.cpu_kernel),
+            throttled_percent,
+            io_read_bytes_per_second: per_second(self.io_read_bytes, previous.io_read_bytes),
+            io_write_bytes_per_second: per_second(self.io_write_bytes, previous.io_write_bytes),
+            io_read_ops_per_second: per_second(self.io_read_ops, previous.io_read_ops),
+            io_write_ops_per_second: per_second(self.io_write_ops, previous.io_write_ops),
+            memory_growth_bytes_per_second,
+        }
+    }
+}
+
+/// Sums up the reads and writes of all devices. cgroup v1 reports the
+/// operation types capitalized, cgroup v2 in lowercase.
+fn io_totals(device_stats: &[BlkioDeviceStat]) -> (u64, u64) {
+    let mut totals = (0, 0);
+    for stat in device_stats {
+        match stat.op_type.as_deref() {
+            Some("Read" | "read") => totals.0 += stat.value,
+            Some("Write" | "write") => totals.1 += stat.value,
+            _ => continue,
+        }
+    }
+
+    totals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::{Bytes, Nanoseconds};
+
+    fn snapshot(cpu_total: u64, read: u64, memory: u64) -> Stats {
+        let mut stats = Stats::default();
+        stats.cpu.usage.usage_total = Nanoseconds(cpu_total);
+        stats.cpu.throttling.periods = cpu_total / 100_000_000;
+        stats.cpu.throttling.throttled_periods = cpu_total / 400_000_000;
+        stats.blkio.service_bytes = vec![
+            BlkioDeviceStat {
+                major: 8,
+                minor: 0,
+                op_type: Some("Read".into()),
+                value: read,
+                device_name: None,
+            },
+            BlkioDeviceStat {
+                major: 8,
+                minor: 0,
+                op_type: Some("Total".into()),
+                value: read,
+                device_name: None,
+            },
+        ];
+        stats.memory.memory.usage = Bytes(memory);
+        stats
+    }
+
+    #[test]
+    fn test_stats_delta() -> Result<()> {
+        let start = SystemTime::UNIX_EPOCH;
+        let previous = snapshot(1_000_000_000, 4096, 8192);
+        let current = snapshot(3_000_000_000, 12288, 4096);
+
+        let delta = StatsDelta::new(&previous, start, &current, start + Duration::from_secs(2))?;
+        assert_eq!(delta.cpu_percent, 100.0);
+        assert_eq!(delta.throttled_percent, 25.0);
+        assert_eq!(delta.io_read_bytes_per_second, 4096.0);
+        assert_eq!(delta.io_write_bytes_per_second, 0.0);
+        assert_eq!(delta.memory_growth_bytes_per_second, -2048.0);
+
+        assert!(
+            StatsDelta::new(&current, start + Duration::from_secs(2), &previous, start).is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_sampler() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(10);
+        let mut sampler = StatsSampler::default();
+        assert!(sampler.sample_at(&snapshot(0, 0, 0), start).is_none());
+
+        let delta = sampler
+            .sample_at(&snapshot(500_000_000, 0, 0), start + Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(delta.cpu_percent, 50.0);
+
+        // a counter that has been reset does not result in a negative rate
+        let delta = sampler
+            .sample_at(&snapshot(0, 0, 0), start + Duration::from_secs(2))
+            .unwrap();
+        assert_eq!(delta.cpu_percent, 0.0);
+
+        // the wall clock has been set back
+        assert!(sampler.sample_at(&snapshot(0, 0, 0), start).is_none());
+    }
+}
@@ -5,7 +5,7 @@ use dbus::arg::RefArg;
 use oci_spec::runtime::LinuxCpu;
 
 use super::controller::Controller;
-use crate::common::ControllerOpt;
+use crate::{common::ControllerOpt, convert};
 
 pub const CPU_WEIGHT: &str = "CPUWeight";
 pub const CPU_QUOTA: &str = "CPUQuotaPerSecUSec";
@@ -36,10 +36,10 @@ impl Cpu {
             bail!("realtime is not supported on systemd v2 yet");
         }
 
-        if let Some(mut shares) = cpu.shares() {
-            shares = convert_shares_to_cgroup2(shares);
-            if shares != 0 {
-                properties.insert(CPU_WEIGHT, Box::new(shares));
+        if let Some(shares) = cpu.shares() {
+            let weight = convert::cpu_shares_to_weight(shares);
+            if weight != 0 {
+                properties.insert(CPU_WEIGHT, Box::new(weight));
             }
         }
 
@@ -72,14 +72,6 @@ impl Cpu {
     }
 }
 
-pub fn convert_shares_to_cgroup2(shares: u64) -> u64 {
-    if shares == 0 {
-        return 0;
-    }
-
-    1 + ((shares - 2) * 9999) / 262142
-}
-
 #[cfg(test)]
 mod tests {
     use dbus::arg::ArgType;
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use dbus::arg::RefArg;
+use oci_spec::runtime::{LinuxBlockIo, LinuxThrottleDevice};
+
+use super::controller::Controller;
+use crate::{common::ControllerOpt, convert};
+
+pub const IO_WEIGHT: &str = "IOWeight";
+pub const IO_DEVICE_WEIGHT: &str = "IODeviceWeight";
+pub const IO_READ_BANDWIDTH_MAX: &str = "IOReadBandwidthMax";
+pub const IO_WRITE_BANDWIDTH_MAX: &str = "IOWriteBandwidthMax";
+pub const IO_READ_IOPS_MAX: &str = "IOReadIOPSMax";
+pub const IO_WRITE_IOPS_MAX: &str = "IOWriteIOPSMax";
+
+pub(crate) struct Io {}
+
+impl Controller for Io {
+    fn apply(
+        options: &ControllerOpt,
+        _: u32,
+        properties: &mut HashMap<&str, Box<dyn RefArg>>,
+    ) -> Result<()> {
+        if let Some(io) = options.resources.block_io() {
+            log::debug!("Applying io resource restrictions");
+            return Self::apply(io, properties).context("could not apply io resource restrictions");
+        }
+
+        Ok(())
+    }
+}
+
+impl Io {
+    fn apply(io: &LinuxBlockIo, properties: &mut HashMap<&str, Box<dyn RefArg>>) -> Result<()> {
+        if let Some(leaf_weight) = io.leaf_weight() {
+            if leaf_weight > 0 {
+                bail!("cannot set leaf_weight with cgroupv2");
+            }
+        }
+
+        if let Some(weight) = io.weight() {
+            let weight = convert::blkio_weight_to_io_weight(weight);
+            if weight != 0 {
+                properties.insert(IO_WEIGHT, Box::new(weight));
+            }
+        }
+
+        if let Some(weight_devices) = io.weight_device() {
+            // systemd identifies the devices by their path
+            let weights: Vec<(String, u64)> = weight_devices
+                .iter()
+                .filter_map(|device| {
+                    let weight = convert::blkio_weight_to_io_weight(device.weight()?);
+                    Some((device_path(device.major(), device.minor()), weight))
+                })
+                .filter(|(_, weight)| *weight != 0)
+                .collect();
+            if !weights.is_empty() {
+                properties.insert(IO_DEVICE_WEIGHT, Box::new(weights));
+            }
+        }
+
+        let throttles = [
+            (IO_READ_BANDWIDTH_MAX, io.throttle_read_bps_device()),
+            (IO_WRITE_BANDWIDTH_MAX, io.throttle_write_bps_device()),
+            (IO_READ_IOPS_MAX, io.throttle_read_iops_device()),
+            (IO_WRITE_IOPS_MAX, io.throttle_write_iops_device()),
+        ];
+        for (property, devices) in throttles {
+            if let Some(devices) = devices {
+                properties.insert(property, Box::new(device_limits(devices)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn device_path(major: i64, minor: i64) -> String {
+    format!("/dev/block/{}:{}", major, minor)
+}
+
+fn device_limits(devices: &[LinuxThrottleDevice]) -> Vec<(String, u64)> {
+    devices
+        .iter()
+        .map(|device| (device_path(device.major(), device.minor()), device.rate()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use dbus::arg::ArgType;
+    use oci_spec::runtime::{
+        LinuxBlockIoBuilder, LinuxThrottleDeviceBuilder, LinuxWeightDeviceBuilder,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_set_io_weight() -> Result<()> {
+        let io = LinuxBlockIoBuilder::default()
+            .weight(1000u16)
+            .weight_device(vec![LinuxWeightDeviceBuilder::default()
+                .major(8)
+                .minor(0)
+                .weight(10u16)
+                .build()?])
+            .build()?;
+        let mut properties: HashMap<&str, Box<dyn RefArg>> = HashMap::new();
+
+        Io::apply(&io, &mut properties).context("apply io")?;
+
+        assert_eq!(properties.len(), 2);
+        let weight = &properties[IO_WEIGHT];
+        assert_eq!(weight.arg_type(), ArgType::UInt64);
+        assert_eq!(weight.as_u64().unwrap(), 10000);
+        let device_weight = &properties[IO_DEVICE_WEIGHT];
+        assert_eq!(device_weight.arg_type(), ArgType::Array);
+        assert_eq!(device_weight.signature().to_string(), "a(st)");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_io_throttling() -> Result<()> {
+        let io = LinuxBlockIoBuilder::default()
+            .throttle_read_bps_device(vec![LinuxThrottleDeviceBuilder::default()
+                .major(8)
+                .minor(0)
+                .rate(102400u64)
+                .build()?])
+            .throttle_write_iops_device(vec![LinuxThrottleDeviceBuilder::default()
+                .major(8)
+                .minor(16)
+                .rate(100u64)
+                .build()?])
+            .build()?;
+        let mut properties: HashMap<&str, Box<dyn RefArg>> = HashMap::new();
+
+        Io::apply(&io, &mut properties).context("apply io")?;
+
+        assert_eq!(properties.len(), 2);
+        assert!(properties.contains_key(IO_READ_BANDWIDTH_MAX));
+        assert!(properties.contains_key(IO_WRITE_IOPS_MAX));
+        assert_eq!(
+            device_limits(io.throttle_write_iops_device().as_ref().unwrap()),
+            vec![("/dev/block/8:16".to_owned(), 100)]
+        );
+
+        Ok(())
+    }
+}
@@ -7,7 +7,7 @@ use std::{
 
 use anyhow::{anyhow, bail, Context, Result};
 use dbus::arg::RefArg;
-use nix::unistd::Pid;
+use nix::{sys::signal::Signal, unistd::Pid};
 use std::path::{Path, PathBuf};
 
 use super::{
@@ -16,14 +16,20 @@ use super::{
     cpu::Cpu,
     cpuset::CpuSet,
     dbus::client::{Client, SystemdClient},
+    io::Io,
     memory::Memory,
     pids::Pids,
 };
 use crate::{
     common::{self, CgroupManager, ControllerOpt, FreezerState, PathBufExt},
+    dry_run::{self, CgroupWrite},
+    extension,
     systemd::unified::Unified,
 };
-use crate::{stats::Stats, v2::manager::Manager as FsManager};
+use crate::{
+    stats::{Stats, StatsFilter},
+    v2::manager::Manager as FsManager,
+};
 
 const CGROUP_CONTROLLERS: &str = "cgroup.controllers";
 const CGROUP_SUBTREE_CONTROL: &str = "cgroup.subtree_control";
@@ -65,28 +71,30 @@ impl TryFrom<&Path> for CgroupsPath {
     type Error = anyhow::Error;
 
     fn try_from(cgroups_path: &Path) -> Result<Self, Self::Error> {
-        // cgroups path may never be empty as it is defaulted to `/youki`
+        // cgroups path may never be empty as it is defaulted to the container id
         // see 'get_cgroup_path' under utils.rs.
         // if cgroups_path was provided it should be of the form [slice]:[prefix]:[name],
-        // for example: "system.slice:docker:1234".
-        let mut parent = "";
-        let prefix;
-        let name;
-        if cgroups_path.starts_with("/youki") {
-            prefix = "youki";
-            name = cgroups_path
-                .strip_prefix("/youki/")?
-                .to_str()
-                .ok_or_else(|| anyhow!("failed to parse cgroups path {:?}", cgroups_path))?;
-        } else {
-            let parts = cgroups_path
-                .to_str()
-                .ok_or_else(|| anyhow!("failed to parse cgroups path {:?}", cgroups_path))?
-                .split(':')
-                .collect::<Vec<&str>>();
-            parent = parts[0];
-            prefix = parts[1];
-            name = parts[2];
+        // for example: "system.slice:docker:1234" or, as used by kubernetes,
+        // "kubepods-burstable-pod1234.slice:cri-containerd:5678".
+        let path = cgroups_path
+            .to_str()
+            .ok_or_else(|| anyhow!("failed to parse cgroups path {:?}", cgroups_path))?;
+        let parts = path.split(':').collect::<Vec<&str>>();
+        let (parent, prefix, name) = match parts[..] {
+            [parent, prefix, name] => (parent, prefix, name),
+            // the default cgroups path of youki, which is either the container id
+            // or /youki/<container id>
+            [path] if !path.trim_start_matches("/youki/").contains('/') => {
+                ("", "youki", path.trim_start_matches("/youki/"))
+            }
+            _ => bail!(
+                "expected cgroups path {:?} to be of the form [slice]:[prefix]:[name]",
+                cgroups_path
+            ),
+        };
+
+        if name.is_empty() {
+            bail!("cgroups path {:?} does not contain a name", cgroups_path);
         }
 
         Ok(CgroupsPath {
@@ -103,6 +111,18 @@ impl Display for CgroupsPath {
     }
 }
 
+/// Expands a cgroups path of the form [slice]:[prefix]:[name] into the path of
+/// the unit relative to the root of the cgroup hierarchy, e.g.
+/// kubepods-burstable.slice:crio:1234 into /kubepods.slice/kubepods-burstable.slice/crio-1234.scope.
+/// Used on cgroup v1 hosts, where the cgroups are managed by the v1 manager,
+/// so that they are placed where systemd would place the unit.
+pub fn expand_cgroups_path(cgroups_path: &Path, system: bool) -> Result<PathBuf> {
+    let destructured_path: CgroupsPath = cgroups_path
+        .try_into()
+        .with_context(|| format!("failed to destructure cgroups path {:?}", cgroups_path))?;
+    Manager::unit_path(&destructured_path, system)
+}
+
 // custom debug impl as Manager contains fields that do not implement Debug
 // and therefore Debug cannot be derived
 impl Debug for Manager {
@@ -170,7 +190,20 @@ impl Manager {
         cgroups_path: &CgroupsPath,
         client: &dyn SystemdClient,
     ) -> Result<(PathBuf, PathBuf)> {
-        let mut parent = match client.is_system() {
+        let systemd_root = client.control_cgroup_root()?;
+        let unit_path = Self::unit_path(cgroups_path, client.is_system())?;
+
+        let cgroups_path = systemd_root
+            .join_safely(&unit_path)
+            .with_context(|| format!("failed to join {:?} with {:?}", systemd_root, unit_path))?;
+        Ok((cgroups_path, systemd_root))
+    }
+
+    /// Returns the path of the unit relative to the control group of systemd,
+    /// e.g. /kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod1234.slice/cri-containerd-5678.scope
+    /// for kubepods-burstable-pod1234.slice:cri-containerd:5678
+    fn unit_path(cgroups_path: &CgroupsPath, system: bool) -> Result<PathBuf> {
+        let mut parent = match system {
             true => PathBuf::from("/system.slice"),
             false => PathBuf::from("/user.slice"),
         };
@@ -181,15 +214,10 @@ impl Manager {
             parent = Self::expand_slice(&cgroups_path.parent)?;
         }
 
-        let systemd_root = client.control_cgroup_root()?;
         let unit_name = Self::get_unit_name(cgroups_path);
-
-        let cgroups_path = systemd_root
-            .join_safely(&parent)
-            .with_context(|| format!("failed to join {:?} with {:?}", systemd_root, parent))?
+        parent
             .join_safely(&unit_name)
-            .with_context(|| format!("failed to join {:?} with {:?}", parent, unit_name))?;
-        Ok((cgroups_path, systemd_root))
+            .with_context(|| format!("failed to join {:?} with {:?}", parent, unit_name))
     }
 
     // systemd represents slice hierarchy using `-`, so we need to follow suit when
@@ -212,7 +240,7 @@ impl Manager {
         }
         for component in slice_name.split('-') {
             if component.is_empty() {
-                anyhow!("invalid slice name: {}", slice);
+                bail!("invalid slice name: {}", slice);
             }
             // Append the component to the path and to the prefix.
             path = format!("{}/{}{}{}", path, prefix, component, suffix);
@@ -222,17 +250,16 @@ impl Manager {
     }
 
     /// ensures that each level in the downward path from the delegation boundary down to
-    /// the scope or slice of the transient unit has all available controllers enabled
-    fn ensure_controllers_attached(&self) -> Result<()> {
+    /// the scope or slice of the transient unit has all available controllers enabled.
+    /// Returns the controllers that are available to the transient unit. For rootless
+    /// containers these are only the controllers that have been delegated to the user,
+    /// e.g. systemd delegates memory and pids but not cpu to user@.service by default.
+    fn ensure_controllers_attached(&self) -> Result<Vec<ControllerType>> {
         let full_boundary_path = self.root_path.join_safely(&self.delegation_boundary)?;
+        let rootless = !self.client.is_system();
 
-        let controllers: Vec<String> = self
-            .get_available_controllers(&full_boundary_path)?
-            .into_iter()
-            .map(|c| format!("{}{}", "+", c.to_string()))
-            .collect();
-
-        Self::write_controllers(&full_boundary_path, &controllers)?;
+        let controllers = self.get_available_controllers(&full_boundary_path)?;
+        let mut controllers = Self::write_controllers(&full_boundary_path, &controllers, rootless)?;
 
         let mut current_path = full_boundary_path;
         let mut components = self
@@ -251,17 +278,17 @@ impl Manager {
                     "{:?} does not exist. Resource restrictions might not work correctly",
                     current_path
                 );
-                return Ok(());
+                return Ok(controllers);
             }
 
             // last component cannot have subtree_control enabled due to internal process constraint
             // if this were set, writing to the cgroups.procs file will fail with Erno 16 (device or resource busy)
             if components.peek().is_some() {
-                Self::write_controllers(&current_path, &controllers)?;
+                controllers = Self::write_controllers(&current_path, &controllers, rootless)?;
             }
         }
 
-        Ok(())
+        Ok(controllers)
     }
 
     fn get_available_controllers<P: AsRef<Path>>(
@@ -280,6 +307,8 @@ impl Manager {
         for controller in fs::read_to_string(&controllers_path)?.split_whitespace() {
             match controller {
                 "cpu" => controllers.push(ControllerType::Cpu),
+                "cpuset" => controllers.push(ControllerType::CpuSet),
+                "io" => controllers.push(ControllerType::Io),
                 "memory" => controllers.push(ControllerType::Memory),
                 "pids" => controllers.push(ControllerType::Pids),
                 _ => continue,
@@ -289,12 +318,43 @@ impl Manager {
         Ok(controllers)
     }
 
-    fn write_controllers(path: &Path, controllers: &[String]) -> Result<()> {
+    /// Enables the controllers for the children of the cgroup and returns the
+    /// controllers that are enabled afterwards. Controllers that are enabled
+    /// already are not written again. For rootless containers a controller
+    /// that cannot be enabled, because it has not been delegated, is skipped
+    /// instead of failing with EPERM.
+    fn write_controllers(
+        path: &Path,
+        controllers: &[ControllerType],
+        rootless: bool,
+    ) -> Result<Vec<ControllerType>> {
+        let subtree_control = path.join(CGROUP_SUBTREE_CONTROL);
+        let enabled = common::read_cgroup_file(&subtree_control).unwrap_or_default();
+        let enabled: Vec<&str> = enabled.split_whitespace().collect();
+
+        let mut result = Vec::with_capacity(controllers.len());
         for controller in controllers {
-            common::write_cgroup_file_str(path.join(CGROUP_SUBTREE_CONTROL), controller)?;
+            if enabled.contains(&controller.as_ref()) {
+                result.push(*controller);
+                continue;
+            }
+
+            // written outside of a batch, which would only report the failure
+            // once it is done and the controller has been used already
+            match common::write_cgroup_file_unbatched(&subtree_control, format!("+{}", controller))
+            {
+                Ok(()) => result.push(*controller),
+                Err(err) if rootless => log::warn!(
+                    "controller {} is not delegated to {:?}, its resource restrictions are skipped: {:?}",
+                    controller,
+                    path,
+                    err
+                ),
+                Err(err) => return Err(err),
+            }
         }
 
-        Ok(())
+        Ok(result)
     }
 }
 
@@ -330,7 +390,9 @@ impl CgroupManager for Manager {
             .systemd_version()
             .context("could not retrieve systemd version")?;
 
+        let mut controller_properties = Vec::with_capacity(CONTROLLER_TYPES.len());
         for controller in CONTROLLER_TYPES {
+            let mut properties: HashMap<&str, Box<dyn RefArg>> = HashMap::new();
             match controller {
                 ControllerType::Cpu => {
                     Cpu::apply(controller_opt, systemd_version, &mut properties)?
@@ -346,20 +408,61 @@ impl CgroupManager for Manager {
                 ControllerType::Memory => {
                     Memory::apply(controller_opt, systemd_version, &mut properties)?
                 }
-                _ => {}
+                ControllerType::Io => Io::apply(controller_opt, systemd_version, &mut properties)?,
             };
+
+            if !properties.is_empty() {
+                controller_properties.push((*controller, properties));
+            }
         }
 
         Unified::apply(controller_opt, systemd_version, &mut properties)?;
+
+        if !controller_properties.is_empty() || !properties.is_empty() {
+            let available = self
+                .ensure_controllers_attached()
+                .context("failed to attach controllers")?;
+
+            for (controller, controller_properties) in controller_properties {
+                if available.contains(&controller) {
+                    properties.extend(controller_properties);
+                } else {
+                    // only possible for rootless containers, otherwise attaching fails
+                    log::warn!(
+                        "skipping {:?} of unit {}, the {} controller is not available",
+                        controller_properties.keys().collect::<Vec<_>>(),
+                        self.unit_name,
+                        controller
+                    );
+                }
+            }
+        }
         log::debug!("{:?}", properties);
 
         if !properties.is_empty() {
-            self.ensure_controllers_attached()
-                .context("failed to attach controllers")?;
+            if dry_run::is_dry_run() {
+                for (name, value) in &properties {
+                    dry_run::record(CgroupWrite::UnitProperty {
+                        unit: self.unit_name.clone(),
+                        name: name.to_string(),
+                        value: format!("{:?}", value),
+                    });
+                }
+            } else {
+                self.client
+                    .set_unit_properties(&self.unit_name, &properties)
+                    .context("could not apply resource restrictions")?;
+            }
+        }
 
-            self.client
-                .set_unit_properties(&self.unit_name, &properties)
-                .context("could not apply resource restrictions")?;
+        // systemd has no properties for controllers it does not know, so the
+        // files of extensions are written directly
+        for extension in extension::extensions() {
+            extension
+                .apply(controller_opt, &self.full_path)
+                .with_context(|| {
+                    format!("failed to apply cgroup extension {}", extension.name())
+                })?;
         }
 
         Ok(())
@@ -380,18 +483,27 @@ impl CgroupManager for Manager {
         self.fs_manager.freeze(state)
     }
 
-    fn stats(&self) -> Result<Stats> {
-        self.fs_manager.stats()
+    fn kill_all(&self, signal: Signal) -> Result<()> {
+        self.fs_manager.kill_all(signal)
+    }
+
+    fn stats_with(&self, filter: &StatsFilter) -> Result<Stats> {
+        self.fs_manager.stats_with(filter)
     }
 
     fn get_all_pids(&self) -> Result<Vec<Pid>> {
         common::get_all_pids(&self.full_path)
     }
+
+    fn get_cgroup_paths(&self) -> HashMap<String, PathBuf> {
+        self.fs_manager.get_cgroup_paths()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::systemd::dbus::client::SystemdClient;
+    use crate::test::{create_temp_dir, set_fixture};
 
     use super::*;
 
@@ -484,4 +596,87 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn expand_slice_rejects_empty_components() {
+        assert!(Manager::expand_slice("test--a.slice").is_err());
+        assert!(Manager::expand_slice("test/a.slice").is_err());
+        assert!(Manager::expand_slice("test").is_err());
+    }
+
+    #[test]
+    fn cgroups_path_is_destructured() -> Result<()> {
+        let cgroups_path: CgroupsPath =
+            Path::new("kubepods-burstable-pod1234.slice:cri-containerd:5678").try_into()?;
+        assert_eq!(cgroups_path.parent, "kubepods-burstable-pod1234.slice");
+        assert_eq!(cgroups_path.prefix, "cri-containerd");
+        assert_eq!(cgroups_path.name, "5678");
+
+        for default in ["5678", "/youki/5678"] {
+            let cgroups_path: CgroupsPath = Path::new(default).try_into()?;
+            assert_eq!(cgroups_path.parent, "");
+            assert_eq!(cgroups_path.prefix, "youki");
+            assert_eq!(cgroups_path.name, "5678");
+        }
+
+        for invalid in [
+            "/kubepods/burstable/5678",
+            "system.slice:5678",
+            "system.slice:youki:",
+        ] {
+            assert!(CgroupsPath::try_from(Path::new(invalid)).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn expand_cgroups_path_works_with_nested_slices() -> Result<()> {
+        assert_eq!(
+            expand_cgroups_path(
+                Path::new("kubepods-burstable-pod1234.slice:cri-containerd:5678"),
+                true
+            )?,
+            PathBuf::from("/kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod1234.slice/cri-containerd-5678.scope"),
+        );
+        assert_eq!(
+            expand_cgroups_path(Path::new("5678"), true)?,
+            PathBuf::from("/system.slice/youki-5678.scope"),
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_controllers_skips_undelegated_controllers_for_rootless() -> Result<()> {
+        let tmp = create_temp_dir("write_controllers_skips_undelegated_controllers_for_rootless")?;
+        let controllers = [ControllerType::Cpu, ControllerType::Memory];
+        // the controllers cannot be enabled without the file
+        assert!(Manager::write_controllers(&tmp, &controllers, false).is_err());
+        assert_eq!(
+            Manager::write_controllers(&tmp, &controllers, true)?,
+            vec![]
+        );
+
+        set_fixture(&tmp, CGROUP_SUBTREE_CONTROL, "memory pids")?;
+        assert_eq!(
+            Manager::write_controllers(&tmp, &controllers, true)?,
+            controllers.to_vec()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn write_controllers_skips_undelegated_controllers_in_batch() -> Result<()> {
+        let tmp = create_temp_dir("write_controllers_skips_undelegated_controllers_in_batch")?;
+        let controllers = [ControllerType::Cpu, ControllerType::Memory];
+
+        // the resources of a new container are applied in a batch
+        let mut available = None;
+        crate::batch::batched(|| {
+            available = Some(Manager::write_controllers(&tmp, &controllers, true)?);
+            Ok(())
+        })?;
+        assert_eq!(available, Some(vec![]));
+        Ok(())
+    }
 }
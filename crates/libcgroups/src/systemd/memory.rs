@@ -34,6 +34,14 @@ impl Controller for Memory {
 
 impl Memory {
     fn apply(memory: &LinuxMemory, properties: &mut HashMap<&str, Box<dyn RefArg>>) -> Result<()> {
+        // socket buffers are accounted to the memory of the unit
+        if let Some(kernel_tcp) = memory.kernel_tcp().filter(|kernel_tcp| *kernel_tcp != 0) {
+            bail!(
+                "kernel TCP memory limit ({}) is not supported by systemd, use the memory limit instead",
+                kernel_tcp
+            );
+        }
+
         if let Some(reservation) = memory.reservation() {
             match reservation {
                 1..=i64::MAX => {
@@ -4,11 +4,11 @@ use std::collections::HashMap;
 
 use super::{
     controller::Controller,
-    cpu::{self, convert_shares_to_cgroup2},
+    cpu,
     cpuset::{self, to_bitmask},
     memory, pids,
 };
-use crate::common::ControllerOpt;
+use crate::{common::ControllerOpt, convert};
 
 pub struct Unified {}
 
@@ -40,7 +40,10 @@ impl Unified {
                     let shares = value
                         .parse::<u64>()
                         .with_context(|| format!("failed to parse cpu weight: {}", value))?;
-                    properties.insert(cpu::CPU_WEIGHT, Box::new(convert_shares_to_cgroup2(shares)));
+                    properties.insert(
+                        cpu::CPU_WEIGHT,
+                        Box::new(convert::cpu_shares_to_weight(shares)),
+                    );
                 }
                 "cpu.max" => {
                     let parts: Vec<&str> = value.split_whitespace().collect();
@@ -2,16 +2,18 @@ use std::cell::RefCell;
 
 use anyhow::Result;
 use nix::unistd::Pid;
+use oci_spec::runtime::LinuxResources;
 
 use crate::{
     common::{CgroupManager, ControllerOpt, FreezerState},
-    stats::Stats,
+    stats::{Stats, StatsFilter},
 };
 
 #[derive(Debug)]
 pub struct TestManager {
     add_task_args: RefCell<Vec<Pid>>,
     pub apply_called: RefCell<bool>,
+    applied_resources: RefCell<Option<LinuxResources>>,
 }
 
 impl Default for TestManager {
@@ -19,6 +21,7 @@ impl Default for TestManager {
         Self {
             add_task_args: RefCell::new(vec![]),
             apply_called: RefCell::new(false),
+            applied_resources: RefCell::new(None),
         }
     }
 }
@@ -29,9 +32,9 @@ impl CgroupManager for TestManager {
         Ok(())
     }
 
-    // NOTE: The argument cannot be stored due to lifetime.
-    fn apply(&self, _controller_opt: &ControllerOpt) -> Result<()> {
+    fn apply(&self, controller_opt: &ControllerOpt) -> Result<()> {
         *self.apply_called.borrow_mut() = true;
+        *self.applied_resources.borrow_mut() = Some(controller_opt.resources.clone());
         Ok(())
     }
 
@@ -43,7 +46,7 @@ impl CgroupManager for TestManager {
         unimplemented!()
     }
 
-    fn stats(&self) -> anyhow::Result<Stats> {
+    fn stats_with(&self, _filter: &StatsFilter) -> anyhow::Result<Stats> {
         unimplemented!()
     }
 
@@ -60,4 +63,8 @@ impl TestManager {
     pub fn apply_called(&self) -> bool {
         *self.apply_called.borrow_mut()
     }
+
+    pub fn get_applied_resources(&self) -> Option<LinuxResources> {
+        self.applied_resources.borrow().clone()
+    }
 }
@@ -1,12 +1,13 @@
-use std::path::Path;
+use std::{borrow::Cow, path::Path};
 
 use crate::{
     common::{self, ControllerOpt},
-    stats::{self, BlkioDeviceStat, BlkioStats, StatsProvider},
+    reader::CgroupReader,
+    stats::{self, BlkioDeviceStat, BlkioSource, BlkioStats, StatsProvider},
     v1::Controller,
 };
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 use oci_spec::runtime::LinuxBlockIo;
 
 // Throttling/upper limit policy
@@ -96,12 +97,22 @@ impl Controller for Blkio {
 impl StatsProvider for Blkio {
     type Stats = BlkioStats;
 
-    fn stats(cgroup_path: &Path) -> Result<Self::Stats> {
-        if cgroup_path.join(BLKIO_WEIGHT).exists() {
-            return Self::get_weight_division_policy_stats(cgroup_path);
+    fn stats_from(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<Self::Stats> {
+        // the recursive counters are only filled by the CFQ and BFQ schedulers,
+        // with other schedulers the files exist, but are empty
+        for source in [BlkioSource::Cfq, BlkioSource::Bfq] {
+            let service_bytes = Self::stats_file(BLKIO_IO_SERVICE_BYTES, source);
+            if !reader.exists(&cgroup_path.join(service_bytes)) {
+                continue;
+            }
+
+            let stats = Self::get_weight_division_policy_stats(reader, cgroup_path, source)?;
+            if !stats.service_bytes.is_empty() || !stats.serviced.is_empty() {
+                return Ok(stats);
+            }
         }
 
-        Self::get_throttling_policy_stats(cgroup_path)
+        Self::get_throttling_policy_stats(reader, cgroup_path)
     }
 }
 
@@ -159,77 +170,86 @@ impl Blkio {
         Ok(())
     }
 
-    fn get_throttling_policy_stats(cgroup_path: &Path) -> Result<BlkioStats> {
+    /// Adds the io of the descendant cgroups, which is not included in the
+    /// counters of the throttling policy of the cgroup
+    pub(crate) fn add_descendant_stats(
+        reader: &dyn CgroupReader,
+        stats: &mut BlkioStats,
+        cgroup_path: &Path,
+    ) -> Result<()> {
+        for descendant in stats::descendant_cgroups(reader, cgroup_path)? {
+            stats.add(&Self::get_throttling_policy_stats(reader, &descendant)?);
+        }
+
+        Ok(())
+    }
+
+    fn get_throttling_policy_stats(
+        reader: &dyn CgroupReader,
+        cgroup_path: &Path,
+    ) -> Result<BlkioStats> {
         let stats = BlkioStats {
             service_bytes: Self::parse_blkio_file(
+                reader,
                 &cgroup_path.join(BLKIO_THROTTLE_IO_SERVICE_BYTES),
             )?,
-            serviced: Self::parse_blkio_file(&cgroup_path.join(BLKIO_THROTTLE_IO_SERVICED))?,
+            serviced: Self::parse_blkio_file(
+                reader,
+                &cgroup_path.join(BLKIO_THROTTLE_IO_SERVICED),
+            )?,
+            source: Some(BlkioSource::Throttling),
             ..Default::default()
         };
 
         Ok(stats)
     }
 
-    fn get_weight_division_policy_stats(cgroup_path: &Path) -> Result<BlkioStats> {
+    fn get_weight_division_policy_stats(
+        reader: &dyn CgroupReader,
+        cgroup_path: &Path,
+        source: BlkioSource,
+    ) -> Result<BlkioStats> {
+        // BFQ only provides some of the counters unless it has been built with
+        // CONFIG_BFQ_CGROUP_DEBUG
+        let parse = |file: &str| -> Result<Vec<BlkioDeviceStat>> {
+            let path = cgroup_path.join(Self::stats_file(file, source));
+            if !reader.exists(&path) {
+                return Ok(Vec::new());
+            }
+            Self::parse_blkio_file(reader, &path)
+        };
+
         let stats = BlkioStats {
-            time: Self::parse_blkio_file(&cgroup_path.join(BLKIO_TIME))?,
-            sectors: Self::parse_blkio_file(&cgroup_path.join(BLKIO_SECTORS))?,
-            service_bytes: Self::parse_blkio_file(&cgroup_path.join(BLKIO_IO_SERVICE_BYTES))?,
-            serviced: Self::parse_blkio_file(&cgroup_path.join(BLKIO_IO_SERVICED))?,
-            service_time: Self::parse_blkio_file(&cgroup_path.join(BLKIO_IO_SERVICE_TIME))?,
-            wait_time: Self::parse_blkio_file(&cgroup_path.join(BLKIO_WAIT_TIME))?,
-            queued: Self::parse_blkio_file(&cgroup_path.join(BLKIO_QUEUED))?,
-            merged: Self::parse_blkio_file(&cgroup_path.join(BLKIO_MERGED))?,
+            time: parse(BLKIO_TIME)?,
+            sectors: parse(BLKIO_SECTORS)?,
+            service_bytes: parse(BLKIO_IO_SERVICE_BYTES)?,
+            serviced: parse(BLKIO_IO_SERVICED)?,
+            service_time: parse(BLKIO_IO_SERVICE_TIME)?,
+            wait_time: parse(BLKIO_WAIT_TIME)?,
+            queued: parse(BLKIO_QUEUED)?,
+            merged: parse(BLKIO_MERGED)?,
+            source: Some(source),
+            ..Default::default()
         };
 
         Ok(stats)
     }
 
-    fn parse_blkio_file(blkio_file: &Path) -> Result<Vec<BlkioDeviceStat>> {
-        let content = common::read_cgroup_file(blkio_file)?;
-        let mut stats = Vec::new();
-        for entry in content.lines() {
-            let entry_fields: Vec<&str> = entry.split_ascii_whitespace().collect();
-            if entry_fields.len() <= 2 {
-                continue;
-            }
-
-            let (major, minor) = stats::parse_device_number(entry_fields[0])?;
-            let op_type = if entry_fields.len() == 3 {
-                Some(entry_fields[1].to_owned())
-            } else {
-                None
-            };
-            let value = if entry_fields.len() == 3 {
-                entry_fields[2].parse().with_context(|| {
-                    format!(
-                        "failed to parse device value {} in {}",
-                        entry_fields[2],
-                        blkio_file.display()
-                    )
-                })?
-            } else {
-                entry_fields[1].parse().with_context(|| {
-                    format!(
-                        "failed to parse device value {} in {}",
-                        entry_fields[1],
-                        blkio_file.display()
-                    )
-                })?
-            };
-
-            let stat = BlkioDeviceStat {
-                major,
-                minor,
-                op_type,
-                value,
-            };
-
-            stats.push(stat);
+    /// Returns the name of the file of the scheduler, e.g.
+    /// blkio.bfq.io_serviced_recursive instead of blkio.io_serviced_recursive
+    fn stats_file(file: &str, source: BlkioSource) -> Cow<str> {
+        match source {
+            BlkioSource::Bfq => file.replacen("blkio.", "blkio.bfq.", 1).into(),
+            _ => file.into(),
         }
+    }
 
-        Ok(stats)
+    fn parse_blkio_file(
+        reader: &dyn CgroupReader,
+        blkio_file: &Path,
+    ) -> Result<Vec<BlkioDeviceStat>> {
+        let content = reader.read(blkio_file)?;
+        Ok(stats::parse_blkio_data(blkio_file, &content)?)
     }
 }
 
@@ -238,6 +258,7 @@ mod tests {
     use std::fs;
 
     use super::*;
+    use crate::reader::FakeReader;
     use crate::test::{create_temp_dir, set_fixture, setup};
 
     use anyhow::Result;
@@ -366,15 +387,105 @@ mod tests {
             .map(|op| BlkioDeviceStat {
                 major: 8,
                 minor: 0,
-                op_type: Some(op.to_owned()),
+                op_type: Some(op.into()),
                 value: 20,
+                device_name: None,
             })
             .collect();
 
         expected.service_bytes = devices.clone();
         expected.serviced = devices;
+        expected.source = Some(BlkioSource::Throttling);
 
         assert_eq!(expected, actual);
         Ok(())
     }
+
+    #[test]
+    fn test_stat_fallback_to_throttling_policy() -> Result<()> {
+        let tmp = create_temp_dir("test_stat_fallback_to_throttling_policy")
+            .expect("create test directory");
+        // without CFQ or BFQ the recursive counters are empty
+        set_fixture(&tmp, BLKIO_WEIGHT, "500").unwrap();
+        set_fixture(&tmp, BLKIO_IO_SERVICE_BYTES, "Total 0\n").unwrap();
+        set_fixture(&tmp, BLKIO_IO_SERVICED, "Total 0\n").unwrap();
+        set_fixture(
+            &tmp,
+            BLKIO_THROTTLE_IO_SERVICE_BYTES,
+            "8:0 Read 4096\nTotal 4096\n",
+        )
+        .unwrap();
+        set_fixture(&tmp, BLKIO_THROTTLE_IO_SERVICED, "8:0 Read 1\nTotal 1\n").unwrap();
+
+        let actual = Blkio::stats(&tmp).expect("get cgroup stats");
+        assert_eq!(actual.source, Some(BlkioSource::Throttling));
+        assert_eq!(actual.service_bytes.len(), 1);
+        assert_eq!(actual.service_bytes[0].value, 4096);
+
+        // the bfq counters are preferred over the throttling ones
+        set_fixture(
+            &tmp,
+            "blkio.bfq.io_service_bytes_recursive",
+            "8:0 Read 8192\n",
+        )
+        .unwrap();
+        let actual = Blkio::stats(&tmp).expect("get cgroup stats");
+        assert_eq!(actual.source, Some(BlkioSource::Bfq));
+        assert_eq!(actual.service_bytes[0].value, 8192);
+        assert!(actual.serviced.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_descendant_stats() -> Result<()> {
+        let root = Path::new("/sys/fs/cgroup/blkio/youki");
+        let reader = FakeReader::default()
+            .with_file(
+                root.join(BLKIO_THROTTLE_IO_SERVICE_BYTES),
+                "8:0 Read 4096
+",
+            )
+            .with_file(
+                root.join(BLKIO_THROTTLE_IO_SERVICED),
+                "8:0 Read 1
+",
+            )
+            .with_file(root.join("child/cgroup.procs"), "")
+            .with_file(
+                root.join("child").join(BLKIO_THROTTLE_IO_SERVICE_BYTES),
+                "8:0 Read 8192
+8:16 Write 512
+",
+            )
+            .with_file(
+                root.join("child").join(BLKIO_THROTTLE_IO_SERVICED),
+                "8:0 Read 2
+",
+            )
+            .with_file(root.join("child/nested/cgroup.procs"), "")
+            .with_file(
+                root.join("child/nested")
+                    .join(BLKIO_THROTTLE_IO_SERVICE_BYTES),
+                "8:0 Read 1024
+",
+            )
+            .with_file(
+                root.join("child/nested").join(BLKIO_THROTTLE_IO_SERVICED),
+                "8:0 Read 1
+",
+            );
+
+        let mut stats = Blkio::stats_from(&reader, root)?;
+        assert_eq!(stats.source, Some(BlkioSource::Throttling));
+        Blkio::add_descendant_stats(&reader, &mut stats, root)?;
+
+        let values: Vec<(u64, u64)> = stats
+            .service_bytes
+            .iter()
+            .map(|stat| (stat.minor, stat.value))
+            .collect();
+        assert_eq!(values, vec![(0, 13312), (16, 512)]);
+        assert_eq!(stats.serviced[0].value, 4);
+        Ok(())
+    }
 }
@@ -14,6 +14,7 @@ pub enum ControllerType {
     NetworkPriority,
     NetworkClassifier,
     Freezer,
+    Rdma,
 }
 
 impl Display for ControllerType {
@@ -31,12 +32,21 @@ impl Display for ControllerType {
             Self::NetworkPriority => "net_prio",
             Self::NetworkClassifier => "net_cls",
             Self::Freezer => "freezer",
+            Self::Rdma => "rdma",
         };
 
         write!(f, "{}", print)
     }
 }
 
+impl ControllerType {
+    /// Returns true for controllers that many hosts do not mount, e.g. the
+    /// rdma controller on hosts without rdma devices
+    pub fn is_optional(&self) -> bool {
+        matches!(self, Self::Rdma)
+    }
+}
+
 impl AsRef<str> for ControllerType {
     fn as_ref(&self) -> &str {
         match *self {
@@ -52,6 +62,7 @@ impl AsRef<str> for ControllerType {
             Self::NetworkPriority => "net_prio",
             Self::NetworkClassifier => "net_cls",
             Self::Freezer => "freezer",
+            Self::Rdma => "rdma",
         }
     }
 }
@@ -69,4 +80,5 @@ pub const CONTROLLERS: &[ControllerType] = &[
     ControllerType::NetworkPriority,
     ControllerType::NetworkClassifier,
     ControllerType::Freezer,
+    ControllerType::Rdma,
 ];
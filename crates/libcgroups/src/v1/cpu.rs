@@ -5,7 +5,8 @@ use oci_spec::runtime::LinuxCpu;
 
 use crate::{
     common::{self, ControllerOpt},
-    stats::{CpuThrottling, StatsProvider},
+    reader::CgroupReader,
+    stats::{CpuThrottling, Nanoseconds, StatsProvider},
 };
 
 use super::Controller;
@@ -51,10 +52,10 @@ impl Controller for Cpu {
 impl StatsProvider for Cpu {
     type Stats = CpuThrottling;
 
-    fn stats(cgroup_path: &Path) -> Result<Self::Stats> {
+    fn stats_from(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<Self::Stats> {
         let mut stats = CpuThrottling::default();
         let stat_path = cgroup_path.join(CGROUP_CPU_STAT);
-        let stat_content = common::read_cgroup_file(&stat_path)?;
+        let stat_content = reader.read(&stat_path)?;
 
         let parts: Vec<&str> = stat_content.split_ascii_whitespace().collect();
         if parts.len() < 6 {
@@ -87,7 +88,22 @@ impl StatsProvider for Cpu {
 
         stats.periods = parts[1].parse().context("failed to parse nr_periods")?;
         stats.throttled_periods = parts[3].parse().context("failed to parse nr_throttled")?;
-        stats.throttled_time = parts[5].parse().context("failed to parse throttled time")?;
+        stats.throttled_time =
+            Nanoseconds(parts[5].parse().context("failed to parse throttled time")?);
+
+        // the burst counters are only reported since kernel 5.14
+        for entry in parts[6..].chunks_exact(2) {
+            match entry[0] {
+                "nr_bursts" => {
+                    stats.bursts = entry[1].parse().context("failed to parse nr_bursts")?
+                }
+                "burst_time" => {
+                    stats.burst_time =
+                        Nanoseconds(entry[1].parse().context("failed to parse burst time")?)
+                }
+                _ => continue,
+            }
+        }
 
         Ok(stats)
     }
@@ -238,8 +254,27 @@ mod tests {
         let expected = CpuThrottling {
             periods: 165000,
             throttled_periods: 27,
-            throttled_time: 1080,
+            throttled_time: Nanoseconds(1080),
+            ..Default::default()
         };
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_stat_cpu_burst() {
+        let tmp = create_temp_dir("test_stat_cpu_burst").expect("create test directory");
+        let stat_content = &[
+            "nr_periods 165000",
+            "nr_throttled 27",
+            "throttled_time 1080",
+            "nr_bursts 12",
+            "burst_time 340000",
+        ]
+        .join("\n");
+        set_fixture(&tmp, CGROUP_CPU_STAT, stat_content).expect("create stat file");
+
+        let actual = Cpu::stats(&tmp).expect("get cgroup stats");
+        assert_eq!(actual.bursts, 12);
+        assert_eq!(actual.burst_time, Nanoseconds(340000));
+    }
 }
@@ -1,10 +1,12 @@
 use std::path::Path;
 
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use nix::unistd::{self, SysconfVar};
 
 use crate::{
-    common::{self, ControllerOpt},
-    stats::{CpuUsage, StatsProvider},
+    common::ControllerOpt,
+    reader::CgroupReader,
+    stats::{CpuUsage, Nanoseconds, StatsProvider},
 };
 
 use super::Controller;
@@ -35,19 +37,23 @@ impl Controller for CpuAcct {
 impl StatsProvider for CpuAcct {
     type Stats = CpuUsage;
 
-    fn stats(cgroup_path: &Path) -> Result<Self::Stats> {
+    fn stats_from(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<Self::Stats> {
         let mut stats = CpuUsage::default();
-        Self::get_total_cpu_usage(cgroup_path, &mut stats)?;
-        Self::get_per_core_usage(cgroup_path, &mut stats)?;
+        Self::get_total_cpu_usage(reader, cgroup_path, &mut stats)?;
+        Self::get_per_core_usage(reader, cgroup_path, &mut stats)?;
 
         Ok(stats)
     }
 }
 
 impl CpuAcct {
-    fn get_total_cpu_usage(cgroup_path: &Path, stats: &mut CpuUsage) -> Result<()> {
+    fn get_total_cpu_usage(
+        reader: &dyn CgroupReader,
+        cgroup_path: &Path,
+        stats: &mut CpuUsage,
+    ) -> Result<()> {
         let stat_file_path = cgroup_path.join(CGROUP_CPUACCT_STAT);
-        let stat_file_content = common::read_cgroup_file(&stat_file_path)?;
+        let stat_file_content = reader.read(&stat_file_path)?;
 
         // the first two entries of the file should look like this
         // user 746908
@@ -75,51 +81,117 @@ impl CpuAcct {
             );
         }
 
-        stats.usage_user = parts[1]
-            .parse()
-            .context("failed to parse user mode cpu usage")?;
-        stats.usage_kernel = parts[3]
-            .parse()
-            .context("failed to parse kernel mode cpu usage")?;
+        // cpuacct.stat reports the usage in clock ticks (USER_HZ) instead of nanoseconds
+        let ticks_per_second = Self::ticks_per_second()?;
+        stats.usage_user = Self::ticks_to_nanos(
+            parts[1]
+                .parse()
+                .context("failed to parse user mode cpu usage")?,
+            ticks_per_second,
+        );
+        stats.usage_kernel = Self::ticks_to_nanos(
+            parts[3]
+                .parse()
+                .context("failed to parse kernel mode cpu usage")?,
+            ticks_per_second,
+        );
+
+        let total = reader.read(&cgroup_path.join(CGROUP_CPUACCT_USAGE))?;
+        stats.usage_total = Nanoseconds(
+            total
+                .trim()
+                .parse()
+                .context("failed to parse total cpu usage")?,
+        );
+
+        Ok(())
+    }
+
+    fn ticks_per_second() -> Result<u64> {
+        unistd::sysconf(SysconfVar::CLK_TCK)?
+            .filter(|ticks| *ticks > 0)
+            .map(|ticks| ticks as u64)
+            .ok_or_else(|| anyhow!("failed to determine clock ticks per second"))
+    }
+
+    fn ticks_to_nanos(ticks: u64, ticks_per_second: u64) -> Nanoseconds {
+        Nanoseconds(ticks.saturating_mul(1_000_000_000) / ticks_per_second)
+    }
+
+    fn get_per_core_usage(
+        reader: &dyn CgroupReader,
+        cgroup_path: &Path,
+        stats: &mut CpuUsage,
+    ) -> Result<()> {
+        let percpu_content = reader.read(&cgroup_path.join(CGROUP_CPUACCT_PERCPU))?;
+        stats.per_core_usage_total = percpu_content
+            .split_ascii_whitespace()
+            .map(|v| v.parse().map(Nanoseconds))
+            .collect::<Result<Vec<_>, _>>()
+            .context("failed to parse per core cpu usage")?;
+
+        // cpuacct.usage_all is only available since kernel 4.7
+        let usage_all_path = cgroup_path.join(CGROUP_CPUACCT_USAGE_ALL);
+        if !reader.exists(&usage_all_path) {
+            return Ok(());
+        }
 
-        let total = common::read_cgroup_file(cgroup_path.join(CGROUP_CPUACCT_USAGE))?;
-        stats.usage_total = total
-            .trim()
-            .parse()
-            .context("failed to parse total cpu usage")?;
+        let all_content = reader.read(&usage_all_path)?;
+        let (user, kernel) = Self::parse_usage_all(&all_content)
+            .with_context(|| format!("failed to parse {}", usage_all_path.display()))?;
+        stats.per_core_usage_user = user;
+        stats.per_core_usage_kernel = kernel;
 
         Ok(())
     }
 
-    fn get_per_core_usage(cgroup_path: &Path, stats: &mut CpuUsage) -> Result<()> {
-        let all_content = common::read_cgroup_file(cgroup_path.join(CGROUP_CPUACCT_USAGE_ALL))?;
-        // first line is header, skip it
-        for entry in all_content.lines().skip(1) {
+    /// Parses the table of cpuacct.usage_all into the user and kernel mode
+    /// usage per core. The rows are placed at the index of their cpu, so that
+    /// the usage lines up with cpuacct.usage_percpu.
+    /// ```text
+    /// cpu user system
+    /// 0 5838999815217 295316023007
+    /// 1 4139072325517 325194619244
+    /// ```
+    fn parse_usage_all(content: &str) -> Result<(Vec<Nanoseconds>, Vec<Nanoseconds>)> {
+        let mut lines = content.lines();
+        match lines.next() {
+            Some(header)
+                if header
+                    .split_ascii_whitespace()
+                    .eq(["cpu", "user", "system"]) => {}
+            header => bail!("unexpected header {:?}", header),
+        }
+
+        let mut user = Vec::new();
+        let mut kernel = Vec::new();
+        for entry in lines {
             let entry_parts: Vec<&str> = entry.split_ascii_whitespace().collect();
             if entry_parts.len() != 3 {
                 continue;
             }
 
-            stats.per_core_usage_user.push(
+            let cpu: usize = entry_parts[0]
+                .parse()
+                .with_context(|| format!("failed to parse cpu of {}", entry))?;
+            if cpu >= user.len() {
+                user.resize(cpu + 1, Nanoseconds::default());
+                kernel.resize(cpu + 1, Nanoseconds::default());
+            }
+
+            user[cpu] = Nanoseconds(
                 entry_parts[1]
                     .parse()
                     .context("failed to parse per core user mode cpu usage")?,
             );
-            stats.per_core_usage_kernel.push(
+            kernel[cpu] = Nanoseconds(
                 entry_parts[2]
                     .parse()
                     .context("failed to parse per core kernel mode cpu usage")?,
             );
         }
 
-        let percpu_content = common::read_cgroup_file(cgroup_path.join(CGROUP_CPUACCT_PERCPU))?;
-        stats.per_core_usage_total = percpu_content
-            .split_ascii_whitespace()
-            .map(|v| v.parse())
-            .collect::<Result<Vec<_>, _>>()
-            .context("failed to parse per core cpu usage")?;
-
-        Ok(())
+        Ok((user, kernel))
     }
 }
 
@@ -179,9 +251,24 @@ mod tests {
         let mut stats = CpuUsage::default();
         CpuAcct::get_total_cpu_usage(tmp.path(), &mut stats).expect("get cgroup stats");
 
-        assert_eq!(stats.usage_user, 1300888);
-        assert_eq!(stats.usage_kernel, 364592);
-        assert_eq!(stats.usage_total, 18198092369681);
+        let ticks_per_second = CpuAcct::ticks_per_second().expect("get clock ticks");
+        assert_eq!(
+            stats.usage_user,
+            CpuAcct::ticks_to_nanos(1300888, ticks_per_second)
+        );
+        assert_eq!(
+            stats.usage_kernel,
+            CpuAcct::ticks_to_nanos(364592, ticks_per_second)
+        );
+        assert_eq!(stats.usage_total, Nanoseconds(18198092369681));
+    }
+
+    #[test]
+    fn test_ticks_to_nanos() {
+        assert_eq!(
+            CpuAcct::ticks_to_nanos(150, 100),
+            Nanoseconds(1_500_000_000)
+        );
     }
 
     #[test]
@@ -206,17 +293,42 @@ mod tests {
 
         assert_eq!(
             stats.per_core_usage_user,
-            [5838999815217, 4139072325517, 4175712075766, 4021385867300]
+            [5838999815217, 4139072325517, 4175712075766, 4021385867300].map(Nanoseconds)
         );
 
         assert_eq!(
             stats.per_core_usage_kernel,
-            [295316023007, 325194619244, 323435639997, 304269989810]
+            [295316023007, 325194619244, 323435639997, 304269989810].map(Nanoseconds)
         );
 
         assert_eq!(
             stats.per_core_usage_total,
-            [989683000640, 4409567860144, 4439880333849, 4273328034121]
+            [989683000640, 4409567860144, 4439880333849, 4273328034121].map(Nanoseconds)
         );
     }
+
+    #[test]
+    fn test_stat_per_cpu_usage_without_usage_all() {
+        let tmp = create_temp_dir("test_stat_per_cpu_usage_without_usage_all")
+            .expect("create temp directory for test");
+        set_fixture(&tmp, CGROUP_CPUACCT_PERCPU, "100 200").expect("create percpu file");
+
+        let mut stats = CpuUsage::default();
+        CpuAcct::get_per_core_usage(tmp.path(), &mut stats).expect("get cgroup stats");
+
+        assert_eq!(stats.per_core_usage_total, [100, 200].map(Nanoseconds));
+        assert!(stats.per_core_usage_user.is_empty());
+        assert!(stats.per_core_usage_kernel.is_empty());
+    }
+
+    #[test]
+    fn test_parse_usage_all() {
+        // offline cpus are missing from the table
+        let content = ["cpu user system", "0 10 20", "2 30 40"].join("\n");
+        let (user, kernel) = CpuAcct::parse_usage_all(&content).expect("parse usage_all");
+        assert_eq!(user, [10, 0, 30].map(Nanoseconds));
+        assert_eq!(kernel, [20, 0, 40].map(Nanoseconds));
+
+        assert!(CpuAcct::parse_usage_all("0 10 20").is_err());
+    }
 }
@@ -5,12 +5,18 @@ use nix::unistd;
 use oci_spec::runtime::LinuxCpu;
 use unistd::Pid;
 
-use crate::common::{self, ControllerOpt, CGROUP_PROCS};
+use crate::{
+    common::{self, ControllerOpt, CGROUP_PROCS},
+    reader::CgroupReader,
+    stats::{self, CpuSetStats, StatsProvider},
+};
 
 use super::{util, Controller, ControllerType};
 
 const CGROUP_CPUSET_CPUS: &str = "cpuset.cpus";
 const CGROUP_CPUSET_MEMS: &str = "cpuset.mems";
+const CGROUP_CPUSET_EFFECTIVE_CPUS: &str = "cpuset.effective_cpus";
+const CGROUP_CPUSET_EFFECTIVE_MEMS: &str = "cpuset.effective_mems";
 
 pub struct CpuSet {}
 
@@ -20,8 +26,9 @@ impl Controller for CpuSet {
     fn add_task(pid: Pid, cgroup_path: &Path) -> Result<()> {
         fs::create_dir_all(cgroup_path)?;
 
-        Self::ensure_not_empty(cgroup_path, CGROUP_CPUSET_CPUS)?;
-        Self::ensure_not_empty(cgroup_path, CGROUP_CPUSET_MEMS)?;
+        let root = util::get_subsystem_mount_point(&ControllerType::CpuSet)?;
+        Self::init_hierarchy(&root, cgroup_path)
+            .context("failed to initialize the cpusets of the parent cgroups")?;
 
         common::write_cgroup_file(cgroup_path.join(CGROUP_PROCS), pid)?;
         Ok(())
@@ -49,6 +56,19 @@ impl Controller for CpuSet {
     }
 }
 
+impl StatsProvider for CpuSet {
+    type Stats = CpuSetStats;
+
+    fn stats_from(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<Self::Stats> {
+        stats::cpuset_stats(
+            reader,
+            cgroup_path,
+            CGROUP_CPUSET_EFFECTIVE_CPUS,
+            CGROUP_CPUSET_EFFECTIVE_MEMS,
+        )
+    }
+}
+
 impl CpuSet {
     fn apply(cgroup_path: &Path, cpuset: &LinuxCpu) -> Result<()> {
         if let Some(cpus) = &cpuset.cpus() {
@@ -63,29 +83,58 @@ impl CpuSet {
     }
 
     // if a task is moved into the cgroup and a value has not been set for cpus and mems
-    // Errno 28 (no space left on device) will be returned. Therefore we set the value from the parent if required.
-    fn ensure_not_empty(cgroup_path: &Path, interface_file: &str) -> Result<()> {
-        let mut current = util::get_subsystem_mount_point(&ControllerType::CpuSet)?;
-        let relative_cgroup_path = cgroup_path.strip_prefix(&current)?;
+    // Errno 28 (no space left on device) will be returned. Therefore the cgroups from the
+    // root of the hierarchy down to the cgroup inherit the values of their parents if required.
+    fn init_hierarchy(root: &Path, cgroup_path: &Path) -> Result<()> {
+        let relative_cgroup_path = cgroup_path.strip_prefix(root)?;
+        let mut current = root.to_path_buf();
 
         for component in relative_cgroup_path.components() {
-            let parent_value = fs::read_to_string(current.join(interface_file))?;
-            if parent_value.trim().is_empty() {
-                bail!("cpuset parent value is empty")
-            }
-
+            let parent = current.clone();
             current.push(component);
-            let child_path = current.join(interface_file);
-            let child_value = fs::read_to_string(&child_path)?;
-            // the file can contain a newline character. Need to trim it away,
-            // otherwise it is not considered empty and value will not be written
-            if child_value.trim().is_empty() {
-                common::write_cgroup_file_str(&child_path, &parent_value)?;
-            }
+            Self::inherit(
+                &parent,
+                &current,
+                CGROUP_CPUSET_CPUS,
+                CGROUP_CPUSET_EFFECTIVE_CPUS,
+            )?;
+            Self::inherit(
+                &parent,
+                &current,
+                CGROUP_CPUSET_MEMS,
+                CGROUP_CPUSET_EFFECTIVE_MEMS,
+            )?;
         }
 
         Ok(())
     }
+
+    fn inherit(
+        parent: &Path,
+        child: &Path,
+        interface_file: &str,
+        effective_file: &str,
+    ) -> Result<()> {
+        let child_path = child.join(interface_file);
+        let child_value = fs::read_to_string(&child_path)?;
+        // the file can contain a newline character. Need to trim it away,
+        // otherwise it is not considered empty and value will not be written
+        if !child_value.trim().is_empty() {
+            return Ok(());
+        }
+
+        // the effective values leave out cpus and memory nodes that are offline,
+        // which cannot be assigned to the child. Older kernels do not have them.
+        let parent_value = match fs::read_to_string(parent.join(effective_file)) {
+            Ok(value) if !value.trim().is_empty() => value,
+            _ => fs::read_to_string(parent.join(interface_file))?,
+        };
+        if parent_value.trim().is_empty() {
+            bail!("cpuset parent value of {} is empty", parent.display())
+        }
+
+        common::write_cgroup_file_str(&child_path, parent_value.trim())
+    }
 }
 
 #[cfg(test)]
@@ -93,9 +142,34 @@ mod tests {
     use std::fs;
 
     use super::*;
-    use crate::test::setup;
+    use crate::test::{create_temp_dir, set_fixture, setup};
     use oci_spec::runtime::LinuxCpuBuilder;
 
+    #[test]
+    fn test_init_hierarchy() {
+        let root = create_temp_dir("test_init_hierarchy").expect("create temp directory for test");
+        set_fixture(&root, CGROUP_CPUSET_CPUS, "0-7\n").unwrap();
+        set_fixture(&root, CGROUP_CPUSET_EFFECTIVE_CPUS, "0-3\n").unwrap();
+        set_fixture(&root, CGROUP_CPUSET_MEMS, "0-1\n").unwrap();
+
+        let parent = root.join("youki");
+        let cgroup = parent.join("container");
+        fs::create_dir_all(&cgroup).unwrap();
+        set_fixture(&parent, CGROUP_CPUSET_CPUS, "\n").unwrap();
+        set_fixture(&parent, CGROUP_CPUSET_MEMS, "1\n").unwrap();
+        set_fixture(&cgroup, CGROUP_CPUSET_CPUS, "").unwrap();
+        set_fixture(&cgroup, CGROUP_CPUSET_MEMS, "").unwrap();
+
+        CpuSet::init_hierarchy(&root, &cgroup).expect("init hierarchy");
+
+        // the effective cpus are inherited, the memory nodes that have been set are kept
+        let read = |path: &Path| fs::read_to_string(path).unwrap();
+        assert_eq!(read(&parent.join(CGROUP_CPUSET_CPUS)), "0-3");
+        assert_eq!(read(&parent.join(CGROUP_CPUSET_MEMS)), "1\n");
+        assert_eq!(read(&cgroup.join(CGROUP_CPUSET_CPUS)), "0-3");
+        assert_eq!(read(&cgroup.join(CGROUP_CPUSET_MEMS)), "1");
+    }
+
     #[test]
     fn test_set_cpus() {
         // arrange
@@ -131,4 +205,20 @@ mod tests {
             .unwrap_or_else(|_| panic!("read {} file content", CGROUP_CPUSET_MEMS));
         assert_eq!(content, "1-3");
     }
+
+    #[test]
+    fn test_stat_cpuset() {
+        let (tmp, _) = setup("test_stat_cpuset", CGROUP_CPUSET_CPUS);
+        set_fixture(&tmp, CGROUP_CPUSET_MEMS, "").unwrap();
+        set_fixture(&tmp, CGROUP_CPUSET_EFFECTIVE_CPUS, "0-3\n").unwrap();
+        set_fixture(&tmp, CGROUP_CPUSET_EFFECTIVE_MEMS, "0\n").unwrap();
+
+        let actual = CpuSet::stats(&tmp).expect("get cgroup stats");
+        let expected = CpuSetStats {
+            effective_cpus: vec![0, 1, 2, 3],
+            effective_mems: vec![0],
+            ..Default::default()
+        };
+        assert_eq!(actual, expected);
+    }
 }
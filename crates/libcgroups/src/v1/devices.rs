@@ -1,10 +1,13 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
 use std::path::Path;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 
 use super::controller::Controller;
 use crate::common::{self, default_allow_devices, default_devices, ControllerOpt};
-use oci_spec::runtime::LinuxDeviceCgroup;
+use oci_spec::runtime::{LinuxDeviceCgroup, LinuxDeviceCgroupBuilder, LinuxDeviceType};
 
 pub struct Devices {}
 
@@ -12,53 +15,330 @@ impl Controller for Devices {
     type Resource = ();
 
     fn apply(controller_opt: &ControllerOpt, cgroup_root: &Path) -> Result<()> {
+        // without rules, e.g. when other resources are updated, the rules
+        // that have been applied before are kept
+        let devices = match controller_opt.resources.devices() {
+            Some(devices) => devices,
+            None => return Ok(()),
+        };
         log::debug!("Apply Devices cgroup config");
 
-        if let Some(devices) = controller_opt.resources.devices().as_ref() {
-            for d in devices {
-                Self::apply_device(d, cgroup_root)?;
-            }
+        let current = DeviceState::read(cgroup_root)?;
+        let mut target = current.clone().unwrap_or_default();
+        // access to all devices is denied first, so that only the devices
+        // that the rules allow can be accessed from the container
+        target.add_rule(&deny_all_devices());
+        for d in devices {
+            target.add_rule(d);
         }
-
         for d in [
             default_devices().iter().map(|d| d.into()).collect(),
             default_allow_devices(),
         ]
         .concat()
         {
-            Self::apply_device(&d, cgroup_root)?;
+            target.add_rule(&d);
+        }
+
+        for (allow, rule) in target.transition_from(current.as_ref()) {
+            Self::write_rule(allow, &rule, cgroup_root)?;
         }
 
         Ok(())
     }
 
-    // always needs to be called due to default devices
-    fn needs_to_handle<'a>(_controller_opt: &'a ControllerOpt) -> Option<&'a Self::Resource> {
-        Some(&())
+    // the default devices are applied together with the rules of the
+    // resources, see `common::with_device_rules`
+    fn needs_to_handle<'a>(controller_opt: &'a ControllerOpt) -> Option<&'a Self::Resource> {
+        controller_opt.resources.devices().as_ref().map(|_| &())
     }
 }
 
 impl Devices {
-    fn apply_device(device: &LinuxDeviceCgroup, cgroup_root: &Path) -> Result<()> {
-        let path = if device.allow() {
+    fn write_rule(allow: bool, rule: &str, cgroup_root: &Path) -> Result<()> {
+        let path = if allow {
             cgroup_root.join("devices.allow")
         } else {
             cgroup_root.join("devices.deny")
         };
 
-        common::write_cgroup_file_str(path, &device.to_string())?;
+        common::write_cgroup_file_str(path, rule)?;
         Ok(())
     }
 }
 
+/// Device access of a cgroup, modelled after the devices emulator of runc.
+/// The exceptions map a device, e.g. `c 10:200`, to the access that deviates
+/// from the default.
+#[derive(Debug, Clone, PartialEq)]
+struct DeviceState {
+    default_allow: bool,
+    exceptions: BTreeMap<String, String>,
+}
+
+impl Default for DeviceState {
+    // a new cgroup inherits the access of its parent, which allows all
+    // devices on the root cgroup
+    fn default() -> Self {
+        Self {
+            default_allow: true,
+            exceptions: BTreeMap::new(),
+        }
+    }
+}
+
+impl DeviceState {
+    /// Reads the state from devices.list. None is returned if the file does
+    /// not exist.
+    fn read(cgroup_root: &Path) -> Result<Option<Self>> {
+        let path = cgroup_root.join("devices.list");
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to read {}", path.display()))
+            }
+        };
+
+        let mut state = Self {
+            default_allow: false,
+            exceptions: BTreeMap::new(),
+        };
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            state.add(true, line);
+        }
+        Ok(Some(state))
+    }
+
+    fn add_rule(&mut self, rule: &LinuxDeviceCgroup) {
+        self.add(rule.allow(), &rule.to_string());
+    }
+
+    fn add(&mut self, allow: bool, rule: &str) {
+        let mut fields = rule.split_whitespace();
+        let (typ, numbers) = match (fields.next(), fields.next()) {
+            (Some(typ), Some(numbers)) => (typ, numbers),
+            _ => return,
+        };
+        let rule_access = fields.next().unwrap_or_default();
+
+        if typ == "a" {
+            self.default_allow = allow;
+            self.exceptions.clear();
+            return;
+        }
+
+        let key = format!("{} {}", typ, numbers);
+        let current = self.exceptions.remove(&key).unwrap_or_default();
+        let access: String = "rwm"
+            .chars()
+            .filter(|c| {
+                let listed = current.contains(*c);
+                let changed = rule_access.contains(*c);
+                if allow == self.default_allow {
+                    listed && !changed
+                } else {
+                    listed || changed
+                }
+            })
+            .collect();
+        if !access.is_empty() {
+            self.exceptions.insert(key, access);
+        }
+    }
+
+    /// Returns the rules that turn the current state into this state, as
+    /// pairs of whether the rule is written to devices.allow and the rule.
+    /// Access to all devices is only reset if the state cannot be reached by
+    /// changing single devices, so that the devices of a running container
+    /// stay accessible while its rules are updated.
+    fn transition_from(&self, current: Option<&Self>) -> Vec<(bool, String)> {
+        let current = match current {
+            // the devices that are denied are not listed if all devices are
+            // allowed by default
+            Some(current) if !current.default_allow && !self.default_allow => current,
+            _ => {
+                let mut rules = vec![(self.default_allow, "a *:* rwm".to_owned())];
+                rules.extend(
+                    self.exceptions
+                        .iter()
+                        .map(|(key, access)| (!self.default_allow, format!("{} {}", key, access))),
+                );
+                return rules;
+            }
+        };
+
+        let mut rules = Vec::new();
+        for (key, access) in &current.exceptions {
+            let kept = self.exceptions.get(key).map(String::as_str).unwrap_or("");
+            let removed: String = access.chars().filter(|c| !kept.contains(*c)).collect();
+            if !removed.is_empty() {
+                rules.push((false, format!("{} {}", key, removed)));
+            }
+        }
+        for (key, access) in &self.exceptions {
+            let listed = current
+                .exceptions
+                .get(key)
+                .map(String::as_str)
+                .unwrap_or("");
+            let added: String = access.chars().filter(|c| !listed.contains(*c)).collect();
+            if !added.is_empty() {
+                rules.push((true, format!("{} {}", key, added)));
+            }
+        }
+        rules
+    }
+}
+
+fn deny_all_devices() -> LinuxDeviceCgroup {
+    LinuxDeviceCgroupBuilder::default()
+        .allow(false)
+        .typ(LinuxDeviceType::A)
+        .access("rwm")
+        .build()
+        .unwrap()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::test::create_temp_dir;
     use crate::test::set_fixture;
-    use oci_spec::runtime::{LinuxDeviceCgroupBuilder, LinuxDeviceType};
+    use oci_spec::runtime::LinuxResourcesBuilder;
     use std::fs::read_to_string;
 
+    fn apply_device(device: &LinuxDeviceCgroup, cgroup_root: &Path) -> Result<()> {
+        Devices::write_rule(device.allow(), &device.to_string(), cgroup_root)
+    }
+
+    #[test]
+    fn test_deny_all_devices_first() {
+        let tmp =
+            create_temp_dir("test_deny_all_devices_first").expect("create temp directory for test");
+        set_fixture(&tmp, "devices.allow", "").expect("create allowed devices list");
+        set_fixture(&tmp, "devices.deny", "").expect("create denied devices list");
+
+        let resources = LinuxResourcesBuilder::default()
+            .devices(vec![LinuxDeviceCgroupBuilder::default()
+                .allow(true)
+                .typ(LinuxDeviceType::C)
+                .major(10)
+                .minor(200)
+                .access("rwm")
+                .build()
+                .unwrap()])
+            .build()
+            .unwrap();
+        let controller_opt = ControllerOpt {
+            resources: &resources,
+            freezer_state: None,
+            oom_score_adj: None,
+            disable_oom_killer: false,
+        };
+
+        Devices::apply(&controller_opt, &tmp).expect("apply devices");
+        // none of the rules denies access, so the deny all rule is the last
+        // rule that has been written to devices.deny
+        let denied_content = read_to_string(tmp.join("devices.deny")).expect("read to string");
+        assert_eq!(denied_content, "a *:* rwm");
+    }
+
+    #[test]
+    fn test_update_keeps_device_rules() {
+        let tmp = create_temp_dir("test_update_keeps_device_rules")
+            .expect("create temp directory for test");
+        set_fixture(&tmp, "devices.allow", "c 10:200 rwm").expect("create allowed devices list");
+        set_fixture(&tmp, "devices.deny", "").expect("create denied devices list");
+
+        // an update of the memory limit has no device rules
+        let resources = LinuxResourcesBuilder::default()
+            .memory(
+                oci_spec::runtime::LinuxMemoryBuilder::default()
+                    .limit(1 << 30)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        let controller_opt = ControllerOpt {
+            resources: &resources,
+            freezer_state: None,
+            oom_score_adj: None,
+            disable_oom_killer: false,
+        };
+
+        assert!(Devices::needs_to_handle(&controller_opt).is_none());
+        Devices::apply(&controller_opt, &tmp).expect("apply devices");
+        assert_eq!(read_to_string(tmp.join("devices.deny")).unwrap(), "");
+        assert_eq!(
+            read_to_string(tmp.join("devices.allow")).unwrap(),
+            "c 10:200 rwm"
+        );
+
+        // a new container is restricted to the default devices
+        let resources = common::with_device_rules(&resources);
+        let controller_opt = ControllerOpt {
+            resources: &resources,
+            freezer_state: None,
+            oom_score_adj: None,
+            disable_oom_killer: false,
+        };
+        Devices::apply(&controller_opt, &tmp).expect("apply devices");
+        assert_eq!(
+            read_to_string(tmp.join("devices.deny")).unwrap(),
+            "a *:* rwm"
+        );
+    }
+
+    #[test]
+    fn test_update_only_changes_device_rules() {
+        let tmp = create_temp_dir("test_update_only_changes_device_rules")
+            .expect("create temp directory for test");
+        set_fixture(&tmp, "devices.list", "c 1:3 rwm\nc 10:200 rwm\n")
+            .expect("create devices list");
+        let current = DeviceState::read(&tmp)
+            .expect("read devices list")
+            .expect("devices list exists");
+
+        let mut target = current.clone();
+        target.add(false, "a *:* rwm");
+        target.add(true, "c 1:3 rwm");
+        target.add(true, "c 10:229 rw");
+        target.add(true, "c 10:229 m");
+
+        assert_eq!(
+            target.transition_from(Some(&current)),
+            vec![
+                (false, "c 10:200 rwm".to_owned()),
+                (true, "c 10:229 rwm".to_owned()),
+            ]
+        );
+        assert!(current.transition_from(Some(&current)).is_empty());
+    }
+
+    #[test]
+    fn test_reset_device_rules() {
+        let current = DeviceState::default();
+        let mut target = current.clone();
+        target.add(false, "a *:* rwm");
+        target.add(true, "c 1:3 rwm");
+        target.add(false, "c 1:3 w");
+
+        // the devices that are denied are unknown if all devices are allowed
+        assert_eq!(
+            target.transition_from(Some(&current)),
+            vec![
+                (false, "a *:* rwm".to_owned()),
+                (true, "c 1:3 rm".to_owned())
+            ]
+        );
+        assert_eq!(
+            target.transition_from(None),
+            target.transition_from(Some(&current))
+        );
+    }
+
     #[test]
     fn test_set_default_devices() {
         let tmp =
@@ -72,7 +352,7 @@ mod tests {
             set_fixture(&tmp, "devices.allow", "").expect("create allowed devices list");
             set_fixture(&tmp, "devices.deny", "").expect("create denied devices list");
 
-            Devices::apply_device(d, &tmp).expect("Apply default device");
+            apply_device(d, &tmp).expect("Apply default device");
             println!("Device: {}", d.to_string());
             if d.allow() {
                 let allowed_content =
@@ -124,7 +404,7 @@ mod tests {
             set_fixture(&tmp, "devices.allow", "").expect("create allowed devices list");
             set_fixture(&tmp, "devices.deny", "").expect("create denied devices list");
 
-            Devices::apply_device(d, &tmp).expect("Apply default device");
+            apply_device(d, &tmp).expect("Apply default device");
             println!("Device: {}", d.to_string());
             if d.allow() {
                 let allowed_content =
@@ -143,7 +423,7 @@ mod tests {
             let tmp = create_temp_dir("property_test_apply_device").expect("create temp directory for test");
             set_fixture(&tmp, "devices.allow", "").expect("create allowed devices list");
             set_fixture(&tmp, "devices.deny", "").expect("create denied devices list");
-            Devices::apply_device(&device, &tmp).expect("Apply default device");
+            apply_device(&device, &tmp).expect("Apply default device");
             if device.allow() {
                 let allowed_content =
                     read_to_string(tmp.join("devices.allow")).expect("read to string");
@@ -161,7 +441,7 @@ mod tests {
                 .map(|device| {
                     set_fixture(&tmp, "devices.allow", "").expect("create allowed devices list");
                     set_fixture(&tmp, "devices.deny", "").expect("create denied devices list");
-                    Devices::apply_device(device, &tmp).expect("Apply default device");
+                    apply_device(device, &tmp).expect("Apply default device");
                     if device.allow() {
                         let allowed_content =
                             read_to_string(tmp.join("devices.allow")).expect("read to string");
@@ -1,20 +1,14 @@
-use std::io::prelude::*;
-use std::{
-    fs::{create_dir_all, OpenOptions},
-    path::Path,
-    thread, time,
-};
+use std::{fs::create_dir_all, path::Path};
 
 use anyhow::{Result, *};
 
 use super::Controller;
-use crate::common;
 use crate::common::{ControllerOpt, FreezerState};
-
-const CGROUP_FREEZER_STATE: &str = "freezer.state";
-const FREEZER_STATE_THAWED: &str = "THAWED";
-const FREEZER_STATE_FROZEN: &str = "FROZEN";
-const FREEZER_STATE_FREEZING: &str = "FREEZING";
+use crate::freezer::{
+    self, CGROUP_FREEZER_STATE, FREEZER_STATE_FREEZING, FREEZER_STATE_FROZEN, FREEZER_STATE_THAWED,
+};
+use crate::reader::CgroupReader;
+use crate::stats::StatsProvider;
 
 pub struct Freezer {}
 
@@ -37,83 +31,30 @@ impl Controller for Freezer {
     }
 }
 
+impl StatsProvider for Freezer {
+    type Stats = FreezerState;
+
+    fn stats_from(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<Self::Stats> {
+        let state = reader.read(&cgroup_path.join(CGROUP_FREEZER_STATE))?;
+        match state.trim() {
+            FREEZER_STATE_THAWED => Ok(FreezerState::Thawed),
+            FREEZER_STATE_FREEZING => Ok(FreezerState::Freezing),
+            FREEZER_STATE_FROZEN => Ok(FreezerState::Frozen),
+            _ => bail!("unknown freezer state {}", state.trim()),
+        }
+    }
+}
+
 impl Freezer {
     fn apply(freezer_state: &FreezerState, cgroup_root: &Path) -> Result<()> {
         match freezer_state {
-            FreezerState::Undefined => {}
-            FreezerState::Thawed => {
-                common::write_cgroup_file(
-                    cgroup_root.join(CGROUP_FREEZER_STATE),
-                    FREEZER_STATE_THAWED,
-                )?;
-            }
-            FreezerState::Frozen => {
-                let r = || -> Result<()> {
-                    // We should do our best to retry if FREEZING is seen until it becomes FROZEN.
-                    // Add sleep between retries occasionally helped when system is extremely slow.
-                    // see:
-                    // https://github.com/opencontainers/runc/blob/b9ee9c6314599f1b4a7f497e1f1f856fe433d3b7/libcontainer/cgroups/fs/freezer.go#L42
-                    for i in 0..1000 {
-                        if i % 50 == 49 {
-                            let _ = common::write_cgroup_file(
-                                cgroup_root.join(CGROUP_FREEZER_STATE),
-                                FREEZER_STATE_THAWED,
-                            );
-                            thread::sleep(time::Duration::from_millis(10));
-                        }
-
-                        common::write_cgroup_file(
-                            cgroup_root.join(CGROUP_FREEZER_STATE),
-                            FREEZER_STATE_FROZEN,
-                        )?;
-
-                        if i % 25 == 24 {
-                            thread::sleep(time::Duration::from_millis(10));
-                        }
-
-                        let r = Self::read_freezer_state(cgroup_root)?;
-                        match r.trim() {
-                            FREEZER_STATE_FREEZING => {
-                                continue;
-                            }
-                            FREEZER_STATE_FROZEN => {
-                                if i > 1 {
-                                    log::debug!("frozen after {} retries", i)
-                                }
-                                return Ok(());
-                            }
-                            _ => {
-                                // should not reach here.
-                                bail!("unexpected state {} while freezing", r.trim());
-                            }
-                        }
-                    }
-                    bail!("unbale to freeze");
-                }();
-
-                if r.is_err() {
-                    // Freezing failed, and it is bad and dangerous to leave the cgroup in FROZEN or
-                    // FREEZING, so try to thaw it back.
-                    let _ = common::write_cgroup_file(
-                        cgroup_root.join(CGROUP_FREEZER_STATE),
-                        FREEZER_STATE_THAWED,
-                    );
-                }
-                return r;
+            FreezerState::Undefined => Ok(()),
+            FreezerState::Freezing => {
+                bail!("freezing is a transitional state and cannot be applied")
             }
+            FreezerState::Thawed => freezer::thaw_v1(cgroup_root),
+            FreezerState::Frozen => freezer::freeze_v1(cgroup_root, freezer::DEFAULT_TIMEOUT),
         }
-        Ok(())
-    }
-
-    fn read_freezer_state(cgroup_root: &Path) -> Result<String> {
-        let path = cgroup_root.join(CGROUP_FREEZER_STATE);
-        let mut content = String::new();
-        OpenOptions::new()
-            .create(false)
-            .read(true)
-            .open(path)?
-            .read_to_string(&mut content)?;
-        Ok(content)
     }
 }
 
@@ -1,10 +1,11 @@
 use std::{collections::HashMap, path::Path};
 
-use anyhow::{bail, Context, Result};
+use anyhow::{Context, Result};
 
 use crate::{
     common::{self, ControllerOpt},
-    stats::{supported_page_sizes, HugeTlbStats, StatsProvider},
+    reader::CgroupReader,
+    stats::{supported_page_sizes, Bytes, HugePageSize, HugeTlbStats, StatsProvider},
 };
 
 use super::Controller;
@@ -42,13 +43,13 @@ impl Controller for HugeTlb {
 impl StatsProvider for HugeTlb {
     type Stats = HashMap<String, HugeTlbStats>;
 
-    fn stats(cgroup_path: &Path) -> Result<Self::Stats> {
-        let page_sizes = supported_page_sizes()?;
+    fn stats_from(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<Self::Stats> {
+        let page_sizes = supported_page_sizes(reader)?;
         let mut hugetlb_stats = HashMap::with_capacity(page_sizes.len());
 
-        for page_size in &page_sizes {
-            let stats = Self::stats_for_page_size(cgroup_path, page_size)?;
-            hugetlb_stats.insert(page_size.to_owned(), stats);
+        for page_size in page_sizes {
+            let stats = Self::stats_for_page_size(reader, cgroup_path, &page_size)?;
+            hugetlb_stats.insert(page_size.moniker, stats);
         }
 
         Ok(hugetlb_stats)
@@ -57,42 +58,47 @@ impl StatsProvider for HugeTlb {
 
 impl HugeTlb {
     fn apply(root_path: &Path, hugetlb: &LinuxHugepageLimit) -> Result<()> {
-        let page_size: String = hugetlb
-            .page_size()
-            .chars()
-            .take_while(|c| c.is_digit(10))
-            .collect();
-        let page_size: u64 = page_size.parse()?;
-        if !Self::is_power_of_two(page_size) {
-            bail!("page size must be in the format of 2^(integer)");
-        }
-
+        let page_size: HugePageSize = hugetlb.page_size().parse()?;
         common::write_cgroup_file(
-            root_path.join(format!("hugetlb.{}.limit_in_bytes", hugetlb.page_size())),
+            root_path.join(format!("hugetlb.{}.limit_in_bytes", page_size)),
             hugetlb.limit(),
         )?;
-        Ok(())
-    }
 
-    fn is_power_of_two(number: u64) -> bool {
-        (number != 0) && (number & (number - 1)) == 0
+        // reservations are accounted since kernel 5.7. They are limited as
+        // well, as otherwise hugepages that are reserved by a mapping but not
+        // yet faulted in are not bounded by the limit
+        let rsvd_limit = root_path.join(format!("hugetlb.{}.rsvd.limit_in_bytes", page_size));
+        if rsvd_limit.exists() {
+            common::write_cgroup_file(rsvd_limit, hugetlb.limit())?;
+        }
+        Ok(())
     }
 
-    fn stats_for_page_size(cgroup_path: &Path, page_size: &str) -> Result<HugeTlbStats> {
+    fn stats_for_page_size(
+        reader: &dyn CgroupReader,
+        cgroup_path: &Path,
+        page_size: &HugePageSize,
+    ) -> Result<HugeTlbStats> {
         let mut stats = HugeTlbStats::default();
 
         let usage_file = format!("hugetlb.{}.usage_in_bytes", page_size);
-        let usage_content = common::read_cgroup_file(cgroup_path.join(usage_file))?;
-        stats.usage = usage_content.trim().parse()?;
+        let usage_content = reader.read(&cgroup_path.join(usage_file))?;
+        stats.usage = Bytes(usage_content.trim().parse()?);
 
         let max_file = format!("hugetlb.{}.max_usage_in_bytes", page_size);
-        let max_content = common::read_cgroup_file(cgroup_path.join(max_file))?;
-        stats.max_usage = max_content.trim().parse()?;
+        let max_content = reader.read(&cgroup_path.join(max_file))?;
+        stats.max_usage = Bytes(max_content.trim().parse()?);
 
         let failcnt_file = format!("hugetlb.{}.failcnt", page_size);
-        let failcnt_content = common::read_cgroup_file(cgroup_path.join(failcnt_file))?;
+        let failcnt_content = reader.read(&cgroup_path.join(failcnt_file))?;
         stats.fail_count = failcnt_content.trim().parse()?;
 
+        // reservations are accounted since kernel 5.7
+        let rsvd_file = cgroup_path.join(format!("hugetlb.{}.rsvd.usage_in_bytes", page_size));
+        if reader.exists(&rsvd_file) {
+            stats.rsvd_usage = Some(Bytes(reader.parse_single_value(&rsvd_file)?));
+        }
+
         Ok(stats)
     }
 }
@@ -100,6 +106,7 @@ impl HugeTlb {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::reader::FsReader;
     use crate::test::{create_temp_dir, set_fixture};
     use oci_spec::runtime::LinuxHugepageLimitBuilder;
     use std::fs::read_to_string;
@@ -121,6 +128,33 @@ mod tests {
         assert_eq!(hugetlb.limit().to_string(), content);
     }
 
+    #[test]
+    fn test_set_hugetlb_rsvd() {
+        let tmp =
+            create_temp_dir("test_set_hugetlbv1_rsvd").expect("create temp directory for test");
+        set_fixture(&tmp, "hugetlb.2MB.limit_in_bytes", "0")
+            .expect("set fixture for 2 MB page size");
+        set_fixture(&tmp, "hugetlb.1GB.limit_in_bytes", "0")
+            .expect("set fixture for 1 GB page size");
+        set_fixture(&tmp, "hugetlb.2MB.rsvd.limit_in_bytes", "0")
+            .expect("set fixture for 2 MB reservations");
+
+        for page_size in ["2MB", "1GB"] {
+            let hugetlb = LinuxHugepageLimitBuilder::default()
+                .page_size(page_size)
+                .limit(16384)
+                .build()
+                .unwrap();
+            HugeTlb::apply(&tmp, &hugetlb).expect("apply hugetlb");
+        }
+
+        let content =
+            read_to_string(tmp.join("hugetlb.2MB.rsvd.limit_in_bytes")).expect("read rsvd limit");
+        assert_eq!(content, "16384");
+        // kernels without reservation accounting only have the limit
+        assert!(!tmp.join("hugetlb.1GB.rsvd.limit_in_bytes").exists());
+    }
+
     #[test]
     fn test_set_hugetlb_with_invalid_page_size() {
         let tmp = create_temp_dir("test_set_hugetlb_with_invalid_page_size")
@@ -154,7 +188,7 @@ mod tests {
             .collect();
             let page_size: u64 = page_size.parse().expect("parse page size");
 
-            if HugeTlb::is_power_of_two(page_size) && page_size != 1 {
+            if page_size.is_power_of_two() && page_size != 1 {
                 let content =
                     read_to_string(tmp.join(page_file_name)).expect("Read hugetlb file content");
                 hugetlb.limit().to_string() == content
@@ -172,12 +206,14 @@ mod tests {
             .expect("set hugetlb max usage");
         set_fixture(&tmp, "hugetlb.2MB.failcnt", "5").expect("set hugetlb fail count");
 
-        let actual = HugeTlb::stats_for_page_size(&tmp, "2MB").expect("get cgroup stats");
+        let actual = HugeTlb::stats_for_page_size(&FsReader, &tmp, &"2MB".parse().unwrap())
+            .expect("get cgroup stats");
 
         let expected = HugeTlbStats {
-            usage: 1024,
-            max_usage: 4096,
+            usage: Bytes(1024),
+            max_usage: Bytes(4096),
             fail_count: 5,
+            rsvd_usage: None,
         };
         assert_eq!(actual, expected);
     }
@@ -1,61 +1,153 @@
 use std::fs;
 use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
 use std::{collections::HashMap, path::PathBuf};
 
 use anyhow::bail;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use nix::unistd::Pid;
 
-use procfs::process::Process;
-
 use super::ControllerType as CtrlType;
 use super::{
     blkio::Blkio, controller_type::CONTROLLERS, cpu::Cpu, cpuacct::CpuAcct, cpuset::CpuSet,
     devices::Devices, freezer::Freezer, hugetlb::HugeTlb, memory::Memory,
     network_classifier::NetworkClassifier, network_priority::NetworkPriority,
-    perf_event::PerfEvent, pids::Pids, util, Controller,
+    perf_event::PerfEvent, pids::Pids, rdma::Rdma, util, Controller,
+};
+
+use crate::common::{
+    self, CgroupManager, ControllerOpt, ControllerUsage, FreezerState, PathBufExt, CGROUP_PROCS,
 };
+use crate::extension::{self, ControllerExtension};
+use crate::mountinfo::{self, ProcessCgroup};
+use crate::reader::FsReader;
+use crate::stats::{BlkioSource, Stats, StatsCollector, StatsFilter, StatsProvider};
 
-use crate::common::{self, CgroupManager, ControllerOpt, FreezerState, PathBufExt, CGROUP_PROCS};
-use crate::stats::{Stats, StatsProvider};
+/// Name of the hierarchy systemd uses to track processes on v1 hosts
+pub const SYSTEMD_HIERARCHY: &str = "systemd";
 
 pub struct Manager {
     subsystems: HashMap<CtrlType, PathBuf>,
+    named_hierarchies: HashMap<String, PathBuf>,
+    extensions: Vec<(Arc<dyn ControllerExtension>, PathBuf)>,
+    /// Controllers that are handled on the unified hierarchy of a hybrid host
+    excluded: Vec<CtrlType>,
 }
 
 impl Manager {
     /// Constructs a new cgroup manager with cgroups_path being relative to the root of the subsystem
     pub fn new(cgroup_path: PathBuf) -> Result<Self> {
+        let mount_points = util::list_supported_mount_points()?;
+        Self::with_mount_points(cgroup_path, &mount_points)
+    }
+
+    /// Constructs a new cgroup manager from previously discovered subsystem mount points. This
+    /// avoids rescanning the mountinfo of the process when many managers need to be created
+    pub fn with_mount_points(
+        cgroup_path: PathBuf,
+        mount_points: &HashMap<CtrlType, PathBuf>,
+    ) -> Result<Self> {
+        let process_cgroups = own_cgroups(&cgroup_path)?;
         let mut subsystems = HashMap::<CtrlType, PathBuf>::new();
         for subsystem in CONTROLLERS {
-            if let Ok(subsystem_path) = Self::get_subsystem_path(&cgroup_path, subsystem) {
+            let subsystem_path = mount_points.get(subsystem).and_then(|mount_point| {
+                Self::get_subsystem_path(
+                    &cgroup_path,
+                    mount_point.clone(),
+                    subsystem,
+                    &process_cgroups,
+                )
+                .ok()
+            });
+
+            if let Some(subsystem_path) = subsystem_path {
                 subsystems.insert(subsystem.clone(), subsystem_path);
+            } else if subsystem.is_optional() {
+                log::debug!("cgroup {} not supported on this system", subsystem);
             } else {
                 log::warn!("cgroup {} not supported on this system", subsystem);
             }
         }
 
-        Ok(Manager { subsystems })
+        let mut extensions = Vec::new();
+        for extension in extension::extensions() {
+            match Self::get_extension_path(&cgroup_path, extension.name(), &process_cgroups) {
+                Ok(path) => extensions.push((extension, path)),
+                Err(err) => log::warn!(
+                    "cgroup extension {} not supported on this system: {:?}",
+                    extension.name(),
+                    err
+                ),
+            }
+        }
+
+        Ok(Manager {
+            subsystems,
+            named_hierarchies: HashMap::new(),
+            extensions,
+            excluded: Vec::new(),
+        })
     }
 
-    fn get_subsystem_path(cgroup_path: &Path, subsystem: &CtrlType) -> Result<PathBuf> {
-        log::debug!("Get path for subsystem: {}", subsystem);
-        let mount_point = util::get_subsystem_mount_point(subsystem)?;
+    /// Extensions are expected to be mounted as a hierarchy of the same name
+    fn get_extension_path(
+        cgroup_path: &Path,
+        name: &str,
+        process_cgroups: &[ProcessCgroup],
+    ) -> Result<PathBuf> {
+        let info = util::cgroup_info()?;
+        let mount_point = info
+            .mount_point(name)
+            .with_context(|| format!("could not find mountpoint for {}", name))?
+            .to_path_buf();
+        mount_point.join_safely(resolve_cgroup_path(cgroup_path, name, process_cgroups)?)
+    }
 
-        let cgroup = Process::myself()?
-            .cgroups()?
-            .into_iter()
-            .find(|c| c.controllers.contains(&subsystem.to_string()))
-            .unwrap();
+    /// Additionally places the processes of the cgroup into the given named
+    /// hierarchies, so that e.g. systemd can keep track of them. Named
+    /// hierarchies have no resource controller attached and are therefore
+    /// only joined, but never configured. They are not removed with the
+    /// cgroup either, as they are owned by whoever mounted them.
+    pub fn join_named_hierarchies(
+        mut self,
+        cgroup_path: &Path,
+        mount_points: &HashMap<String, PathBuf>,
+    ) -> Result<Self> {
+        let process_cgroups = own_cgroups(cgroup_path)?;
+        for (name, mount_point) in mount_points {
+            let hierarchy = format!("name={}", name);
+            let path = resolve_cgroup_path(cgroup_path, &hierarchy, &process_cgroups)
+                .and_then(|path| mount_point.join_safely(path))
+                .with_context(|| format!("failed to get path of named hierarchy {}", name))?;
+            self.named_hierarchies.insert(name.clone(), path);
+        }
 
-        let p = if cgroup_path.as_os_str().is_empty() {
-            mount_point.join_safely(Path::new(&cgroup.pathname))?
-        } else {
-            mount_point.join_safely(cgroup_path)?
-        };
+        Ok(self)
+    }
+
+    /// Leaves the controllers to another cgroup manager, e.g. because they
+    /// are bound to the unified hierarchy of a hybrid host instead of being
+    /// mounted as a v1 hierarchy. Resources of these controllers are not
+    /// rejected as unsupported, but skipped.
+    pub fn without_controllers(mut self, controllers: &[CtrlType]) -> Self {
+        for controller in controllers {
+            self.subsystems.remove(controller);
+        }
+        self.excluded.extend_from_slice(controllers);
+        self
+    }
 
-        Ok(p)
+    fn get_subsystem_path(
+        cgroup_path: &Path,
+        mount_point: PathBuf,
+        subsystem: &CtrlType,
+        process_cgroups: &[ProcessCgroup],
+    ) -> Result<PathBuf> {
+        log::debug!("Get path for subsystem: {}", subsystem);
+        let cgroup_path =
+            resolve_cgroup_path(cgroup_path, &subsystem.to_string(), process_cgroups)?;
+        mount_point.join_safely(cgroup_path)
     }
 
     fn get_required_controllers(
@@ -82,13 +174,14 @@ impl Manager {
                     NetworkClassifier::needs_to_handle(controller_opt).is_some()
                 }
                 CtrlType::Freezer => Freezer::needs_to_handle(controller_opt).is_some(),
+                CtrlType::Rdma => Rdma::needs_to_handle(controller_opt).is_some(),
             };
 
-            if required {
+            if required && !self.excluded.contains(controller) {
                 if let Some(subsystem_path) = self.subsystems.get(controller) {
                     required_controllers.insert(controller, subsystem_path);
                 } else {
-                    bail!("cgroup {} is required to fullfill the request, but is not supported by this system", controller);
+                    common::skip_unavailable_controller(controller, ControllerUsage::Required)?;
                 }
             }
         }
@@ -97,6 +190,41 @@ impl Manager {
     }
 }
 
+/// Reads the cgroups of the current process if they are needed to resolve an
+/// empty cgroup path. They are read anew, as the process may have been moved.
+fn own_cgroups(cgroup_path: &Path) -> Result<Vec<ProcessCgroup>> {
+    if cgroup_path.as_os_str().is_empty() {
+        mountinfo::process_cgroups()
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+/// An empty cgroup path refers to the cgroup of the current process in the
+/// hierarchy. The root of a hierarchy is rejected though, as it must never be
+/// joined or removed.
+fn resolve_cgroup_path(
+    cgroup_path: &Path,
+    hierarchy: &str,
+    process_cgroups: &[ProcessCgroup],
+) -> Result<PathBuf> {
+    if !cgroup_path.as_os_str().is_empty() {
+        return Ok(cgroup_path.to_path_buf());
+    }
+
+    let cgroup = process_cgroups
+        .iter()
+        .find(|cgroup| cgroup.controllers.iter().any(|c| c == hierarchy))
+        .with_context(|| format!("process is not part of the {} hierarchy", hierarchy))?;
+    if cgroup.path == Path::new("/") {
+        bail!(
+            "process is in the root of the {} hierarchy, which cannot be used as cgroup",
+            hierarchy
+        );
+    }
+    Ok(cgroup.path.clone())
+}
+
 impl CgroupManager for Manager {
     fn get_all_pids(&self) -> Result<Vec<Pid>> {
         let devices = self.subsystems.get(&CtrlType::Devices);
@@ -121,9 +249,20 @@ impl CgroupManager for Manager {
                 CtrlType::NetworkPriority => NetworkPriority::add_task(pid, subsys.1)?,
                 CtrlType::NetworkClassifier => NetworkClassifier::add_task(pid, subsys.1)?,
                 CtrlType::Freezer => Freezer::add_task(pid, subsys.1)?,
+                CtrlType::Rdma => Rdma::add_task(pid, subsys.1)?,
             }
         }
 
+        let paths = self
+            .named_hierarchies
+            .values()
+            .chain(self.extensions.iter().map(|(_, path)| path));
+        for path in paths {
+            fs::create_dir_all(path)
+                .with_context(|| format!("failed to create cgroup {}", path.display()))?;
+            common::write_cgroup_file(path.join(CGROUP_PROCS), pid)?;
+        }
+
         Ok(())
     }
 
@@ -142,17 +281,30 @@ impl CgroupManager for Manager {
                 CtrlType::NetworkPriority => NetworkPriority::apply(controller_opt, subsys.1)?,
                 CtrlType::NetworkClassifier => NetworkClassifier::apply(controller_opt, subsys.1)?,
                 CtrlType::Freezer => Freezer::apply(controller_opt, subsys.1)?,
+                CtrlType::Rdma => Rdma::apply(controller_opt, subsys.1)?,
             }
         }
 
+        for (extension, path) in &self.extensions {
+            extension.apply(controller_opt, path).with_context(|| {
+                format!("failed to apply cgroup extension {}", extension.name())
+            })?;
+        }
+
         Ok(())
     }
 
     fn remove(&self) -> Result<()> {
-        for cgroup_path in &self.subsystems {
-            if cgroup_path.1.exists() {
-                log::debug!("remove cgroup {:?}", cgroup_path.1);
-                let procs_path = cgroup_path.1.join(CGROUP_PROCS);
+        // named hierarchies are left alone, the processes that are tracked in
+        // them are killed through the subsystems
+        let paths = self
+            .subsystems
+            .values()
+            .chain(self.extensions.iter().map(|(_, path)| path));
+        for cgroup_path in paths {
+            if cgroup_path.exists() {
+                log::debug!("remove cgroup {:?}", cgroup_path);
+                let procs_path = cgroup_path.join(CGROUP_PROCS);
                 let procs = fs::read_to_string(&procs_path)?;
 
                 for line in procs.lines() {
@@ -160,7 +312,7 @@ impl CgroupManager for Manager {
                     let _ = nix::sys::signal::kill(Pid::from_raw(pid), nix::sys::signal::SIGKILL);
                 }
 
-                common::delete_with_retry(cgroup_path.1, 4, Duration::from_millis(100))?;
+                common::delete_with_retry(cgroup_path, 10, Duration::from_millis(100))?;
             }
         }
 
@@ -174,27 +326,214 @@ impl CgroupManager for Manager {
             oom_score_adj: None,
             disable_oom_killer: false,
         };
-        Freezer::apply(
-            &controller_opt,
-            self.subsystems.get(&CtrlType::Freezer).unwrap(),
-        )
+        let freezer_path = self
+            .subsystems
+            .get(&CtrlType::Freezer)
+            .context("cgroup freezer is not supported by this system")?;
+        Freezer::apply(&controller_opt, freezer_path)
     }
 
-    fn stats(&self) -> Result<Stats> {
+    fn get_cgroup_paths(&self) -> HashMap<String, PathBuf> {
+        let subsystems = self
+            .subsystems
+            .iter()
+            .map(|(subsystem, path)| (subsystem.to_string(), path.clone()));
+        let named_hierarchies = self
+            .named_hierarchies
+            .iter()
+            .map(|(name, path)| (format!("name={}", name), path.clone()));
+        let extensions = self
+            .extensions
+            .iter()
+            .map(|(extension, path)| (extension.name().to_owned(), path.clone()));
+        subsystems
+            .chain(named_hierarchies)
+            .chain(extensions)
+            .collect()
+    }
+
+    fn stats_with(&self, filter: &StatsFilter) -> Result<Stats> {
         let mut stats = Stats::default();
 
-        for subsystem in &self.subsystems {
-            match subsystem.0 {
-                CtrlType::Cpu => stats.cpu.throttling = Cpu::stats(subsystem.1)?,
-                CtrlType::CpuAcct => stats.cpu.usage = CpuAcct::stats(subsystem.1)?,
-                CtrlType::Pids => stats.pids = Pids::stats(subsystem.1)?,
-                CtrlType::HugeTlb => stats.hugetlb = HugeTlb::stats(subsystem.1)?,
-                CtrlType::Blkio => stats.blkio = Blkio::stats(subsystem.1)?,
-                CtrlType::Memory => stats.memory = Memory::stats(subsystem.1)?,
+        let mut collector = StatsCollector::default();
+        for (subsystem, path) in &self.subsystems {
+            let selected = match subsystem {
+                CtrlType::Cpu | CtrlType::CpuAcct => filter.cpu,
+                CtrlType::Pids => filter.pids,
+                CtrlType::HugeTlb => filter.hugetlb,
+                CtrlType::Blkio => filter.blkio,
+                CtrlType::Memory => filter.memory,
+                CtrlType::CpuSet => filter.cpuset,
+                CtrlType::Freezer => filter.freezer,
+                CtrlType::Rdma => filter.rdma,
+                _ => false,
+            };
+            if !selected {
+                continue;
+            }
+
+            match subsystem {
+                CtrlType::Cpu => collector.collect(subsystem, path, Cpu::stats, |stats, cpu| {
+                    stats.cpu.throttling = cpu
+                }),
+                CtrlType::CpuAcct => {
+                    collector.collect(subsystem, path, CpuAcct::stats, |stats, cpuacct| {
+                        stats.cpu.usage = cpuacct
+                    })
+                }
+                CtrlType::Pids => collector.collect(subsystem, path, Pids::stats, |stats, pids| {
+                    stats.pids = pids
+                }),
+                CtrlType::HugeTlb => {
+                    collector.collect(subsystem, path, HugeTlb::stats, |stats, hugetlb| {
+                        stats.hugetlb = hugetlb
+                    })
+                }
+                CtrlType::Blkio => {
+                    collector.collect(subsystem, path, Blkio::stats, |stats, blkio| {
+                        stats.blkio = blkio
+                    })
+                }
+                CtrlType::Memory => {
+                    collector.collect(subsystem, path, Memory::stats, |stats, memory| {
+                        stats.memory = memory
+                    })
+                }
+                CtrlType::CpuSet => {
+                    collector.collect(subsystem, path, CpuSet::stats, |stats, cpuset| {
+                        stats.cpuset = Some(cpuset)
+                    })
+                }
+                CtrlType::Freezer => {
+                    collector.collect(subsystem, path, Freezer::stats, |stats, freezer| {
+                        stats.freezer = Some(freezer)
+                    })
+                }
+                CtrlType::Rdma => collector.collect(subsystem, path, Rdma::stats, |stats, rdma| {
+                    stats.rdma = Some(rdma)
+                }),
                 _ => continue,
             }
         }
+        collector.join(&mut stats);
+
+        if filter.extensions {
+            for (extension, path) in &self.extensions {
+                extension::collect_stats(&mut stats, extension.as_ref(), path);
+            }
+        }
+
+        Ok(stats)
+    }
+
+    fn stats_recursive(&self) -> Result<Stats> {
+        let mut stats = self.stats()?;
+        let collected = |stats: &Stats, subsystem: &CtrlType| {
+            let controller = subsystem.to_string();
+            !stats.unavailable.contains(&controller)
+                && !stats.diagnostics.iter().any(|d| d.controller == controller)
+        };
+
+        if let Some(path) = self.subsystems.get(&CtrlType::Blkio) {
+            if collected(&stats, &CtrlType::Blkio)
+                && stats.blkio.source == Some(BlkioSource::Throttling)
+            {
+                let result = Blkio::add_descendant_stats(&FsReader, &mut stats.blkio, path);
+                stats.check_available(CtrlType::Blkio, result);
+            }
+        }
+
+        if let Some(path) = self.subsystems.get(&CtrlType::Memory) {
+            if collected(&stats, &CtrlType::Memory) && !stats.memory.hierarchy {
+                let result = Memory::add_descendant_usage(&FsReader, &mut stats.memory, path);
+                stats.check_available(CtrlType::Memory, result);
+            }
+        }
 
         Ok(stats)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::freezer::{CGROUP_FREEZER_STATE, FREEZER_STATE_FROZEN, FREEZER_STATE_THAWED};
+    use crate::test::{create_temp_dir, set_fixture};
+    use nix::sys::signal::Signal;
+
+    #[test]
+    fn test_resolve_empty_cgroup_path() -> Result<()> {
+        let process_cgroups = vec![
+            ProcessCgroup {
+                hierarchy_id: 4,
+                controllers: vec!["cpu".to_owned(), "cpuacct".to_owned()],
+                path: PathBuf::from("/user.slice"),
+            },
+            ProcessCgroup {
+                hierarchy_id: 1,
+                controllers: vec!["name=systemd".to_owned()],
+                path: PathBuf::from("/"),
+            },
+        ];
+
+        assert_eq!(
+            resolve_cgroup_path(Path::new("/youki"), "cpu", &process_cgroups)?,
+            PathBuf::from("/youki")
+        );
+        assert_eq!(
+            resolve_cgroup_path(Path::new(""), "cpuacct", &process_cgroups)?,
+            PathBuf::from("/user.slice")
+        );
+        // the root of a hierarchy is never used
+        assert!(resolve_cgroup_path(Path::new(""), "name=systemd", &process_cgroups).is_err());
+        assert!(resolve_cgroup_path(Path::new(""), "memory", &process_cgroups).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_keeps_named_hierarchies() -> Result<()> {
+        let tmp = create_temp_dir("test_remove_keeps_named_hierarchies")?;
+        let cgroup_path = PathBuf::from("/youki");
+        let named_hierarchies =
+            HashMap::from([(SYSTEMD_HIERARCHY.to_owned(), tmp.join(SYSTEMD_HIERARCHY))]);
+        let manager = Manager::with_mount_points(cgroup_path.clone(), &HashMap::new())?
+            .join_named_hierarchies(&cgroup_path, &named_hierarchies)?;
+        let systemd_cgroup = tmp.join(SYSTEMD_HIERARCHY).join("youki");
+        fs::create_dir_all(&systemd_cgroup)?;
+
+        manager.remove()?;
+        assert!(systemd_cgroup.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_kill_all_thaws_paused_cgroup() -> Result<()> {
+        let tmp = create_temp_dir("test_kill_all_thaws_paused_cgroup")?;
+        let freezer = tmp.join("freezer");
+        let devices = tmp.join("devices");
+        fs::create_dir_all(&freezer)?;
+        fs::create_dir_all(&devices)?;
+        set_fixture(&freezer, CGROUP_FREEZER_STATE, FREEZER_STATE_FROZEN)?;
+        set_fixture(&devices, CGROUP_PROCS, "")?;
+        let manager = Manager {
+            subsystems: HashMap::from([
+                (CtrlType::Freezer, freezer.clone()),
+                (CtrlType::Devices, devices),
+            ]),
+            ..Manager::with_mount_points(PathBuf::from("/youki"), &HashMap::new())?
+        };
+
+        manager.kill_all(Signal::SIGTERM)?;
+        assert_eq!(
+            common::read_cgroup_file(freezer.join(CGROUP_FREEZER_STATE))?.trim(),
+            FREEZER_STATE_FROZEN
+        );
+
+        manager.kill_all(Signal::SIGKILL)?;
+        assert_eq!(
+            common::read_cgroup_file(freezer.join(CGROUP_FREEZER_STATE))?.trim(),
+            FREEZER_STATE_THAWED
+        );
+        Ok(())
+    }
+}
@@ -4,10 +4,13 @@ use std::{fs::OpenOptions, path::Path};
 
 use anyhow::{anyhow, bail, Result};
 use nix::errno::Errno;
+use nix::unistd::{sysconf, SysconfVar};
 
 use super::Controller;
 use crate::common::{self, ControllerOpt};
-use crate::stats::{self, parse_single_value, MemoryData, MemoryStats, StatsProvider};
+use crate::dry_run;
+use crate::reader::CgroupReader;
+use crate::stats::{self, Bytes, MemoryData, MemoryStats, StatsProvider};
 
 use oci_spec::runtime::LinuxMemory;
 
@@ -24,6 +27,8 @@ const CGROUP_KERNEL_TCP_MEMORY_LIMIT: &str = "memory.kmem.tcp.limit_in_bytes";
 
 // Shows various memory statistics
 const MEMORY_STAT: &str = "memory.stat";
+// Shows the memory per numa node in pages
+const MEMORY_NUMA_STAT: &str = "memory.numa_stat";
 //
 const MEMORY_USE_HIERARCHY: &str = "memory.use_hierarchy";
 // Prefix for memory cgroup files
@@ -54,6 +59,20 @@ impl Controller for Memory {
         if let Some(memory) = &controller_opt.resources.memory() {
             let reservation = memory.reservation().unwrap_or(0);
 
+            // validate before anything is written, so that an invalid value
+            // does not leave the cgroup partially configured
+            if let Some(swappiness) = memory.swappiness() {
+                if swappiness > 100 {
+                    bail!(
+                        "invalid swappiness value: {}. Valid range is 0-100",
+                        swappiness
+                    );
+                }
+            }
+            if let Some(tcp_mem) = memory.kernel_tcp().filter(|tcp_mem| *tcp_mem < -1) {
+                bail!("invalid kernel TCP memory limit: {}", tcp_mem);
+            }
+
             Self::apply(memory, cgroup_root)?;
 
             if reservation != 0 {
@@ -63,38 +82,28 @@ impl Controller for Memory {
                 )?;
             }
 
-            if controller_opt.disable_oom_killer {
-                common::write_cgroup_file(cgroup_root.join(CGROUP_MEMORY_OOM_CONTROL), 0)?;
-            } else {
-                common::write_cgroup_file(cgroup_root.join(CGROUP_MEMORY_OOM_CONTROL), 1)?;
+            // writing 1 to memory.oom_control disables the oom killer and 0
+            // enables it again. It is left untouched if the spec does not
+            // set it, so that the setting of the parent is inherited
+            if controller_opt.disable_oom_killer || memory.disable_oom_killer() == Some(true) {
+                Self::set_optional(cgroup_root, CGROUP_MEMORY_OOM_CONTROL, 1)?;
+            } else if memory.disable_oom_killer() == Some(false) {
+                Self::set_optional(cgroup_root, CGROUP_MEMORY_OOM_CONTROL, 0)?;
             }
 
             if let Some(swappiness) = memory.swappiness() {
-                if swappiness <= 100 {
-                    common::write_cgroup_file(
-                        cgroup_root.join(CGROUP_MEMORY_SWAPPINESS),
-                        swappiness,
-                    )?;
-                } else {
-                    // invalid swappiness value
-                    return Err(anyhow!(
-                        "Invalid swappiness value: {}. Valid range is 0-100",
-                        swappiness
-                    ));
-                }
+                Self::set_optional(cgroup_root, CGROUP_MEMORY_SWAPPINESS, swappiness)?;
             }
 
-            // NOTE: Seems as though kernel and kernelTCP are both deprecated
-            // neither are implemented by runc. Tests pass without this, but
-            // kept in per the spec.
+            // NOTE: the kernel memory limit is deprecated and has been
+            // removed from recent kernels, but kept in per the spec
             if let Some(kmem) = memory.kernel() {
-                common::write_cgroup_file(cgroup_root.join(CGROUP_KERNEL_MEMORY_LIMIT), kmem)?;
+                Self::set_optional(cgroup_root, CGROUP_KERNEL_MEMORY_LIMIT, kmem)?;
             }
+            // bounds the memory of the socket buffers, which is not
+            // accounted to the memory limit on cgroup v1
             if let Some(tcp_mem) = memory.kernel_tcp() {
-                common::write_cgroup_file(
-                    cgroup_root.join(CGROUP_KERNEL_TCP_MEMORY_LIMIT),
-                    tcp_mem,
-                )?;
+                Self::set_optional(cgroup_root, CGROUP_KERNEL_TCP_MEMORY_LIMIT, tcp_mem)?;
             }
         }
 
@@ -109,39 +118,53 @@ impl Controller for Memory {
 impl StatsProvider for Memory {
     type Stats = MemoryStats;
 
-    fn stats(cgroup_path: &Path) -> Result<Self::Stats> {
-        let memory = Self::get_memory_data(cgroup_path, MEMORY_PREFIX)?;
-        let memswap = Self::get_memory_data(cgroup_path, MEMORY_AND_SWAP_PREFIX)?;
-        let kernel = Self::get_memory_data(cgroup_path, MEMORY_KERNEL_PREFIX)?;
-        let kernel_tcp = Self::get_memory_data(cgroup_path, MEMORY_KERNEL_TCP_PREFIX)?;
-        let hierarchy = Self::hierarchy_enabled(cgroup_path)?;
-        let stats = Self::get_stat_data(cgroup_path)?;
+    fn stats_from(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<Self::Stats> {
+        let memory = Self::get_memory_data(reader, cgroup_path, MEMORY_PREFIX)?;
+        let memswap = Self::get_memory_data(reader, cgroup_path, MEMORY_AND_SWAP_PREFIX)?;
+        let kernel = Self::get_memory_data(reader, cgroup_path, MEMORY_KERNEL_PREFIX)?;
+        let kernel_tcp = Self::get_memory_data(reader, cgroup_path, MEMORY_KERNEL_TCP_PREFIX)?;
+        let hierarchy = Self::hierarchy_enabled(reader, cgroup_path)?;
+        let stats = Self::get_stat_data(reader, cgroup_path)?;
+        let page_size = sysconf(SysconfVar::PAGE_SIZE)?
+            .ok_or_else(|| anyhow!("failed to determine page size"))?
+            as u64;
+        let numa = stats::numa_stats(reader, &cgroup_path.join(MEMORY_NUMA_STAT), page_size)?;
+        // oom_kill is only reported since kernel 4.13
+        let oom_control =
+            reader.parse_flat_keyed_data(&cgroup_path.join(CGROUP_MEMORY_OOM_CONTROL))?;
 
         Ok(MemoryStats {
             memory,
             memswap,
             kernel,
             kernel_tcp,
-            cache: stats["cache"],
+            cache: Bytes(stats["cache"]),
             hierarchy,
             stats,
+            events: (&oom_control).into(),
+            numa,
+            ..Default::default()
         })
     }
 }
 
 impl Memory {
-    fn get_memory_data(cgroup_path: &Path, file_prefix: &str) -> Result<MemoryData> {
+    fn get_memory_data(
+        reader: &dyn CgroupReader,
+        cgroup_path: &Path,
+        file_prefix: &str,
+    ) -> Result<MemoryData> {
         let memory_data = MemoryData {
-            usage: parse_single_value(
+            usage: Bytes(reader.parse_single_value(
                 &cgroup_path.join(format!("{}{}", file_prefix, MEMORY_USAGE_IN_BYTES)),
-            )?,
-            max_usage: parse_single_value(
+            )?),
+            max_usage: Bytes(reader.parse_single_value(
                 &cgroup_path.join(format!("{}{}", file_prefix, MEMORY_MAX_USAGE_IN_BYTES)),
-            )?,
-            limit: parse_single_value(
+            )?),
+            limit: Bytes(reader.parse_single_value(
                 &cgroup_path.join(format!("{}{}", file_prefix, MEMORY_LIMIT_IN_BYTES)),
-            )?,
-            fail_count: parse_single_value(
+            )?),
+            fail_count: reader.parse_single_value(
                 &cgroup_path.join(format!("{}{}", file_prefix, MEMORY_FAIL_COUNT)),
             )?,
         };
@@ -149,16 +172,43 @@ impl Memory {
         Ok(memory_data)
     }
 
-    fn hierarchy_enabled(cgroup_path: &Path) -> Result<bool> {
+    /// Adds the usage of the descendant cgroups, which is not included in
+    /// the usage of the cgroup if hierarchical accounting is disabled
+    pub(crate) fn add_descendant_usage(
+        reader: &dyn CgroupReader,
+        stats: &mut MemoryStats,
+        cgroup_path: &Path,
+    ) -> Result<()> {
+        for descendant in stats::descendant_cgroups(reader, cgroup_path)? {
+            let usages = [
+                (&mut stats.memory, MEMORY_PREFIX),
+                (&mut stats.memswap, MEMORY_AND_SWAP_PREFIX),
+                (&mut stats.kernel, MEMORY_KERNEL_PREFIX),
+                (&mut stats.kernel_tcp, MEMORY_KERNEL_TCP_PREFIX),
+            ];
+            for (data, file_prefix) in usages {
+                let usage_path =
+                    descendant.join(format!("{}{}", file_prefix, MEMORY_USAGE_IN_BYTES));
+                data.usage.0 += reader.parse_single_value(&usage_path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn hierarchy_enabled(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<bool> {
         let hierarchy_path = cgroup_path.join(MEMORY_USE_HIERARCHY);
-        let hierarchy = common::read_cgroup_file(hierarchy_path)?;
+        let hierarchy = reader.read(&hierarchy_path)?;
         let enabled = matches!(hierarchy.trim(), "1");
 
         Ok(enabled)
     }
 
-    fn get_stat_data(cgroup_path: &Path) -> Result<HashMap<String, u64>> {
-        stats::parse_flat_keyed_data(&cgroup_path.join(MEMORY_STAT))
+    fn get_stat_data(
+        reader: &dyn CgroupReader,
+        cgroup_path: &Path,
+    ) -> Result<HashMap<String, u64>> {
+        Ok(reader.parse_flat_keyed_data(&cgroup_path.join(MEMORY_STAT))?)
     }
 
     fn get_memory_usage(cgroup_root: &Path) -> Result<u64> {
@@ -219,6 +269,10 @@ impl Memory {
     }
 
     fn set<T: ToString>(val: T, path: &Path) -> std::io::Result<()> {
+        if dry_run::record_file(path, &val.to_string()) {
+            return Ok(());
+        }
+
         OpenOptions::new()
             .create(false)
             .write(true)
@@ -228,6 +282,23 @@ impl Memory {
         Ok(())
     }
 
+    /// Writes a file that is not provided by every memory cgroup, e.g.
+    /// memory.swappiness does not exist in the root cgroup and on kernels
+    /// without swap accounting
+    fn set_optional<T: ToString>(cgroup_root: &Path, file: &str, val: T) -> Result<()> {
+        let path = cgroup_root.join(file);
+        if !dry_run::is_dry_run() && !path.exists() {
+            bail!(
+                "cannot set {} to {}: {} does not exist",
+                file,
+                val.to_string(),
+                path.display()
+            );
+        }
+
+        common::write_cgroup_file(path, val)
+    }
+
     fn set_memory(val: i64, cgroup_root: &Path) -> Result<()> {
         if val == 0 {
             return Ok(());
@@ -290,7 +361,13 @@ impl Memory {
     fn apply(resource: &LinuxMemory, cgroup_root: &Path) -> Result<()> {
         match resource.limit() {
             Some(limit) => {
-                let current_limit = Self::get_memory_limit(cgroup_root)?;
+                // a cgroup that a dry run would create has no limit yet
+                let current_limit =
+                    if dry_run::is_dry_run() && !cgroup_root.join(CGROUP_MEMORY_LIMIT).exists() {
+                        i64::MAX
+                    } else {
+                        Self::get_memory_limit(cgroup_root)?
+                    };
                 match resource.swap() {
                     Some(swap) => {
                         let is_updated = swap == -1 || current_limit < swap;
@@ -319,6 +396,7 @@ impl Memory {
 mod tests {
     use super::*;
     use crate::common::CGROUP_PROCS;
+    use crate::reader::FsReader;
     use crate::test::{create_temp_dir, set_fixture};
     use oci_spec::runtime::{LinuxMemoryBuilder, LinuxResourcesBuilder};
 
@@ -405,6 +483,169 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_disable_oom_killer() {
+        let tmp =
+            create_temp_dir("test_disable_oom_killer").expect("create temp directory for test");
+        set_fixture(&tmp, CGROUP_MEMORY_OOM_CONTROL, "0").expect("set fixture for oom control");
+
+        // not requested, the setting of the parent is inherited
+        let linux_resources = LinuxResourcesBuilder::default()
+            .devices(vec![])
+            .hugepage_limits(vec![])
+            .memory(LinuxMemoryBuilder::default().build().unwrap())
+            .build()
+            .unwrap();
+        let controller_opt = ControllerOpt {
+            resources: &linux_resources,
+            disable_oom_killer: false,
+            oom_score_adj: None,
+            freezer_state: None,
+        };
+        <Memory as Controller>::apply(&controller_opt, &tmp).expect("apply memory");
+        let content =
+            std::fs::read_to_string(tmp.join(CGROUP_MEMORY_OOM_CONTROL)).expect("read oom control");
+        assert_eq!(content, "0");
+
+        // requested by the spec
+        let linux_resources = LinuxResourcesBuilder::default()
+            .devices(vec![])
+            .hugepage_limits(vec![])
+            .memory(
+                LinuxMemoryBuilder::default()
+                    .disable_oom_killer(true)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        let controller_opt = ControllerOpt {
+            resources: &linux_resources,
+            disable_oom_killer: false,
+            oom_score_adj: None,
+            freezer_state: None,
+        };
+        <Memory as Controller>::apply(&controller_opt, &tmp).expect("apply memory");
+        let content =
+            std::fs::read_to_string(tmp.join(CGROUP_MEMORY_OOM_CONTROL)).expect("read oom control");
+        assert_eq!(content, "1");
+
+        // enabled again by an update
+        let linux_resources = LinuxResourcesBuilder::default()
+            .devices(vec![])
+            .hugepage_limits(vec![])
+            .memory(
+                LinuxMemoryBuilder::default()
+                    .disable_oom_killer(false)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        let controller_opt = ControllerOpt {
+            resources: &linux_resources,
+            disable_oom_killer: false,
+            oom_score_adj: None,
+            freezer_state: None,
+        };
+        <Memory as Controller>::apply(&controller_opt, &tmp).expect("apply memory");
+        let content =
+            std::fs::read_to_string(tmp.join(CGROUP_MEMORY_OOM_CONTROL)).expect("read oom control");
+        assert_eq!(content, "0");
+    }
+
+    #[test]
+    fn test_swappiness_validation() {
+        let tmp =
+            create_temp_dir("test_swappiness_validation").expect("create temp directory for test");
+        set_fixture(&tmp, CGROUP_MEMORY_USAGE, "0").expect("set fixture for memory usage");
+        set_fixture(&tmp, CGROUP_MEMORY_MAX_USAGE, "0").expect("set fixture for max usage");
+        set_fixture(&tmp, CGROUP_MEMORY_LIMIT, "0").expect("set fixture for memory limit");
+        set_fixture(&tmp, CGROUP_MEMORY_SWAP_LIMIT, "0").expect("set fixture for swap limit");
+
+        let linux_resources = LinuxResourcesBuilder::default()
+            .devices(vec![])
+            .hugepage_limits(vec![])
+            .memory(
+                LinuxMemoryBuilder::default()
+                    .limit(1024)
+                    .swappiness(101u64)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        let controller_opt = ControllerOpt {
+            resources: &linux_resources,
+            disable_oom_killer: false,
+            oom_score_adj: None,
+            freezer_state: None,
+        };
+        let err = <Memory as Controller>::apply(&controller_opt, &tmp).unwrap_err();
+        assert!(err.to_string().contains("invalid swappiness value: 101"));
+        // nothing is written for an invalid configuration
+        let content =
+            std::fs::read_to_string(tmp.join(CGROUP_MEMORY_LIMIT)).expect("read memory limit");
+        assert_eq!(content, "0");
+    }
+
+    #[test]
+    fn test_set_kernel_tcp() {
+        let tmp = create_temp_dir("test_set_kernel_tcp").expect("create temp directory for test");
+        set_fixture(&tmp, CGROUP_KERNEL_TCP_MEMORY_LIMIT, "0")
+            .expect("set fixture for kernel tcp memory limit");
+
+        let linux_resources = LinuxResourcesBuilder::default()
+            .devices(vec![])
+            .hugepage_limits(vec![])
+            .memory(
+                LinuxMemoryBuilder::default()
+                    .kernel_tcp(16 * 1024 * 1024)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        let controller_opt = ControllerOpt {
+            resources: &linux_resources,
+            disable_oom_killer: false,
+            oom_score_adj: None,
+            freezer_state: None,
+        };
+        <Memory as Controller>::apply(&controller_opt, &tmp).expect("apply memory");
+        let content = std::fs::read_to_string(tmp.join(CGROUP_KERNEL_TCP_MEMORY_LIMIT))
+            .expect("read kernel tcp memory limit");
+        assert_eq!(content, (16 * 1024 * 1024).to_string());
+    }
+
+    #[test]
+    fn test_missing_swappiness_file() {
+        let tmp = create_temp_dir("test_missing_swappiness_file")
+            .expect("create temp directory for test");
+
+        let linux_resources = LinuxResourcesBuilder::default()
+            .devices(vec![])
+            .hugepage_limits(vec![])
+            .memory(
+                LinuxMemoryBuilder::default()
+                    .swappiness(60u64)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        let controller_opt = ControllerOpt {
+            resources: &linux_resources,
+            disable_oom_killer: false,
+            oom_score_adj: None,
+            freezer_state: None,
+        };
+        let err = <Memory as Controller>::apply(&controller_opt, &tmp).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("cannot set memory.swappiness to 60"));
+    }
+
     quickcheck! {
             fn property_test_set_memory(linux_memory: LinuxMemory, disable_oom_killer: bool) -> bool {
                 let tmp =
@@ -437,6 +678,10 @@ mod tests {
 
 
                 if result.is_err() {
+                    // error is expected if the kernel tcp limit is below -1
+                    if memory_limits.kernel_tcp().map_or(false, |tcp_mem| tcp_mem < -1) {
+                        return true;
+                    }
                     if let Some(swappiness) = memory_limits.swappiness() {
                         // error is expected if swappiness is greater than 100
                         if swappiness > 100 {
@@ -560,11 +805,12 @@ mod tests {
         )
         .unwrap();
 
-        let actual = Memory::get_memory_data(&tmp, MEMORY_PREFIX).expect("get cgroup stats");
+        let actual =
+            Memory::get_memory_data(&FsReader, &tmp, MEMORY_PREFIX).expect("get cgroup stats");
         let expected = MemoryData {
-            usage: 1024,
-            max_usage: 2048,
-            limit: 4096,
+            usage: Bytes(1024),
+            max_usage: Bytes(2048),
+            limit: Bytes(4096),
             fail_count: 5,
         };
 
@@ -576,7 +822,7 @@ mod tests {
         let tmp = create_temp_dir("test_stat_hierarchy_enabled").expect("create test directory");
         set_fixture(&tmp, MEMORY_USE_HIERARCHY, "1").unwrap();
 
-        let enabled = Memory::hierarchy_enabled(&tmp).expect("get cgroup stats");
+        let enabled = Memory::hierarchy_enabled(&FsReader, &tmp).expect("get cgroup stats");
         assert!(enabled)
     }
 
@@ -585,7 +831,7 @@ mod tests {
         let tmp = create_temp_dir("test_stat_hierarchy_disabled").expect("create test directory");
         set_fixture(&tmp, MEMORY_USE_HIERARCHY, "0").unwrap();
 
-        let enabled = Memory::hierarchy_enabled(&tmp).expect("get cgroup stats");
+        let enabled = Memory::hierarchy_enabled(&FsReader, &tmp).expect("get cgroup stats");
         assert!(!enabled)
     }
 
@@ -605,7 +851,7 @@ mod tests {
         .join("\n");
         set_fixture(&tmp, MEMORY_STAT, &content).unwrap();
 
-        let actual = Memory::get_stat_data(&tmp).expect("get cgroup data");
+        let actual = Memory::get_stat_data(&FsReader, &tmp).expect("get cgroup data");
         let expected: HashMap<String, u64> = [
             ("cache".to_owned(), 0),
             ("rss".to_owned(), 0),
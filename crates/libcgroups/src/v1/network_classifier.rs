@@ -23,7 +23,11 @@ impl Controller for NetworkClassifier {
     }
 
     fn needs_to_handle<'a>(controller_opt: &'a ControllerOpt) -> Option<&'a Self::Resource> {
-        controller_opt.resources.network().as_ref()
+        controller_opt
+            .resources
+            .network()
+            .as_ref()
+            .filter(|network| network.class_id().is_some())
     }
 }
 
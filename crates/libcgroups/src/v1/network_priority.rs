@@ -23,15 +23,24 @@ impl Controller for NetworkPriority {
     }
 
     fn needs_to_handle<'a>(controller_opt: &'a ControllerOpt) -> Option<&'a Self::Resource> {
-        controller_opt.resources.network().as_ref()
+        controller_opt
+            .resources
+            .network()
+            .as_ref()
+            .filter(|network| matches!(network.priorities(), Some(p) if !p.is_empty()))
     }
 }
 
 impl NetworkPriority {
     fn apply(root_path: &Path, network: &LinuxNetwork) -> Result<()> {
         if let Some(ni_priorities) = network.priorities() {
-            let priorities: String = ni_priorities.iter().map(|p| p.to_string()).collect();
-            common::write_cgroup_file_str(root_path.join("net_prio.ifpriomap"), priorities.trim())?;
+            // the kernel only reads the priority of a single interface per write
+            for priority in ni_priorities {
+                common::write_cgroup_file_str(
+                    root_path.join("net_prio.ifpriomap"),
+                    priority.to_string().trim(),
+                )?;
+            }
         }
 
         Ok(())
@@ -41,6 +50,7 @@ impl NetworkPriority {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dry_run::{self, CgroupWrite};
     use crate::test::{create_temp_dir, set_fixture};
     use oci_spec::runtime::{LinuxInterfacePriorityBuilder, LinuxNetworkBuilder};
 
@@ -61,16 +71,27 @@ mod tests {
                 .build()
                 .unwrap(),
         ];
-        let priorities_string = priorities.iter().map(|p| p.to_string()).collect::<String>();
         let network = LinuxNetworkBuilder::default()
             .priorities(priorities)
             .build()
             .unwrap();
 
-        NetworkPriority::apply(&tmp, &network).expect("apply network priorities");
+        let writes = dry_run::dry_run(|| NetworkPriority::apply(&tmp, &network))
+            .expect("apply network priorities");
 
-        let content =
-            std::fs::read_to_string(tmp.join("net_prio.ifpriomap")).expect("Read classID contents");
-        assert_eq!(priorities_string.trim(), content);
+        let priority_map = tmp.join("net_prio.ifpriomap");
+        assert_eq!(
+            writes,
+            vec![
+                CgroupWrite::File {
+                    path: priority_map.clone(),
+                    value: "a 1".to_owned(),
+                },
+                CgroupWrite::File {
+                    path: priority_map,
+                    value: "b 2".to_owned(),
+                },
+            ]
+        );
     }
 }
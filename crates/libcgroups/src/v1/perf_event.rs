@@ -3,6 +3,10 @@ use crate::common::ControllerOpt;
 use anyhow::Result;
 use std::path::Path;
 
+/// The perf_event controller has no resources to apply. Adding the processes
+/// of the container to the cgroup of the perf_event hierarchy lets them be
+/// profiled with `perf --cgroup`, which takes the path of the cgroup relative
+/// to the mount point of the hierarchy.
 pub struct PerfEvent {}
 
 impl Controller for PerfEvent {
@@ -5,6 +5,7 @@ use anyhow::{Context, Result};
 use super::Controller;
 use crate::{
     common::{self, ControllerOpt},
+    reader::CgroupReader,
     stats::{self, PidStats, StatsProvider},
 };
 use oci_spec::runtime::LinuxPids;
@@ -35,8 +36,8 @@ impl Controller for Pids {
 impl StatsProvider for Pids {
     type Stats = PidStats;
 
-    fn stats(cgroup_path: &Path) -> Result<Self::Stats> {
-        stats::pid_stats(cgroup_path)
+    fn stats_from(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<Self::Stats> {
+        stats::pid_stats(reader, cgroup_path)
     }
 }
 
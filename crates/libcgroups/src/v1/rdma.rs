@@ -0,0 +1,100 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context, Result};
+use oci_spec::runtime::LinuxRdma;
+
+use super::Controller;
+use crate::{
+    common::{self, ControllerOpt},
+    reader::CgroupReader,
+    stats::{self, RdmaStats, StatsProvider},
+};
+
+pub struct Rdma {}
+
+impl Controller for Rdma {
+    type Resource = HashMap<String, LinuxRdma>;
+
+    fn apply(controller_opt: &ControllerOpt, cgroup_root: &Path) -> Result<()> {
+        log::debug!("Apply Rdma cgroup config");
+
+        if let Some(rdma) = Self::needs_to_handle(controller_opt) {
+            common::write_rdma_limits(cgroup_root, rdma)
+                .context("failed to apply rdma resource restrictions")?;
+        }
+
+        Ok(())
+    }
+
+    fn needs_to_handle<'a>(controller_opt: &'a ControllerOpt) -> Option<&'a Self::Resource> {
+        controller_opt
+            .resources
+            .rdma()
+            .as_ref()
+            .filter(|rdma| !rdma.is_empty())
+    }
+}
+
+impl StatsProvider for Rdma {
+    type Stats = RdmaStats;
+
+    fn stats_from(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<Self::Stats> {
+        stats::rdma_stats(reader, cgroup_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dry_run::{self, CgroupWrite};
+    use oci_spec::runtime::{LinuxRdmaBuilder, LinuxResourcesBuilder};
+
+    #[test]
+    fn test_apply_rdma() {
+        let mut rdma = HashMap::new();
+        rdma.insert(
+            "mlx5_1".to_owned(),
+            LinuxRdmaBuilder::default()
+                .hca_handles(3u32)
+                .hca_objects(10000u32)
+                .build()
+                .unwrap(),
+        );
+        rdma.insert(
+            "mlx4_0".to_owned(),
+            LinuxRdmaBuilder::default()
+                .hca_objects(1000u32)
+                .build()
+                .unwrap(),
+        );
+        rdma.insert(
+            "mlx4_1".to_owned(),
+            LinuxRdmaBuilder::default().build().unwrap(),
+        );
+        let resources = LinuxResourcesBuilder::default().rdma(rdma).build().unwrap();
+        let controller_opt = ControllerOpt {
+            resources: &resources,
+            freezer_state: None,
+            oom_score_adj: None,
+            disable_oom_killer: false,
+        };
+
+        let cgroup = Path::new("/sys/fs/cgroup/rdma/youki");
+        let writes = dry_run::dry_run(|| Rdma::apply(&controller_opt, cgroup)).unwrap();
+
+        let rdma_max = cgroup.join("rdma.max");
+        assert_eq!(
+            writes,
+            vec![
+                CgroupWrite::File {
+                    path: rdma_max.clone(),
+                    value: "mlx4_0 hca_object=1000".to_owned(),
+                },
+                CgroupWrite::File {
+                    path: rdma_max,
+                    value: "mlx5_1 hca_handle=3 hca_object=10000".to_owned(),
+                },
+            ]
+        );
+    }
+}
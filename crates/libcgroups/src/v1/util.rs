@@ -1,20 +1,116 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
 
-use anyhow::{anyhow, Context, Result};
-use procfs::process::Process;
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
 
 use super::{controller_type::CONTROLLERS, ControllerType};
+use crate::mountinfo;
+
+static CGROUP_INFO: Lazy<RwLock<Option<Arc<CgroupInfo>>>> = Lazy::new(|| RwLock::new(None));
+
+/// Cgroup v1 hierarchies of the system and the cgroups of the current process
+/// in them. Collecting this requires parsing /proc/self/mountinfo, /proc/cgroups
+/// and /proc/self/cgroup, so it is done on first use and shared by all cgroup
+/// managers until [`refresh_cgroup_info`] is called.
+#[derive(Debug, Default, Clone)]
+pub struct CgroupInfo {
+    /// Mount points of all cgroup v1 hierarchies
+    mounts: Vec<PathBuf>,
+    /// Mount point per hierarchy, keyed by controller or name=<name>
+    mount_points: HashMap<String, PathBuf>,
+    /// Cgroup of the process per hierarchy, keyed like the mount points
+    process_cgroups: HashMap<String, PathBuf>,
+}
+
+impl CgroupInfo {
+    fn collect() -> Result<Self> {
+        let mounts = mountinfo::mountinfo()?
+            .into_iter()
+            .filter(|m| m.fs_type == "cgroup")
+            .map(|m| (m.mount_point, m.super_options));
+        let controllers: HashSet<String> = procfs::cgroups()
+            .context("failed to read /proc/cgroups")?
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        let process_cgroups = mountinfo::process_cgroups()?
+            .into_iter()
+            .map(|c| (c.controllers, c.path));
+
+        Ok(Self::from_parts(mounts, &controllers, process_cgroups))
+    }
+
+    fn from_parts(
+        mounts: impl IntoIterator<Item = (PathBuf, Vec<(String, Option<String>)>)>,
+        controllers: &HashSet<String>,
+        process_cgroups: impl IntoIterator<Item = (Vec<String>, PathBuf)>,
+    ) -> Self {
+        let mut info = Self::default();
+        for (mount_point, options) in mounts {
+            info.mounts.push(mount_point.clone());
+            // The super options of a cgroup mount list the attached controllers
+            // or the name of a named hierarchy. A hierarchy may be mounted more
+            // than once, in which case the first mount wins.
+            for (key, value) in options {
+                let hierarchy = match (key.as_str(), value) {
+                    ("name", Some(name)) => format!("name={}", name),
+                    (controller, None) if controllers.contains(controller) => controller.to_owned(),
+                    _ => continue,
+                };
+
+                info.mount_points
+                    .entry(hierarchy)
+                    .or_insert_with(|| mount_point.clone());
+            }
+        }
+
+        for (hierarchies, path) in process_cgroups {
+            for hierarchy in hierarchies {
+                info.process_cgroups.insert(hierarchy, path.clone());
+            }
+        }
+
+        info
+    }
+
+    /// Mount point of the hierarchy of a controller or named hierarchy
+    pub fn mount_point(&self, hierarchy: &str) -> Option<&Path> {
+        self.mount_points.get(hierarchy).map(PathBuf::as_path)
+    }
+
+    /// Cgroup of the current process in the hierarchy of a controller or named hierarchy
+    pub fn process_cgroup(&self, hierarchy: &str) -> Option<&Path> {
+        self.process_cgroups.get(hierarchy).map(PathBuf::as_path)
+    }
+}
+
+/// Returns the cgroup v1 hierarchies of the system, which are collected on first use
+pub fn cgroup_info() -> Result<Arc<CgroupInfo>> {
+    if let Some(info) = CGROUP_INFO.read().unwrap().as_ref() {
+        return Ok(Arc::clone(info));
+    }
+
+    let info = Arc::new(CgroupInfo::collect()?);
+    *CGROUP_INFO.write().unwrap() = Some(Arc::clone(&info));
+    Ok(info)
+}
+
+/// Discards the collected hierarchies, so that the next lookup collects them
+/// again. Long-lived processes, e.g. the metrics server, call this before an
+/// operation, because mounts may have changed or the process may have moved
+/// to another cgroup since the hierarchies were collected.
+pub fn refresh_cgroup_info() {
+    *CGROUP_INFO.write().unwrap() = None;
+}
 
 /// List all cgroup v1 subsystem mount points on the system. This can include unsupported
 /// subsystems, comounted controllers and named hierarchies.
 pub fn list_subsystem_mount_points() -> Result<Vec<PathBuf>> {
-    Ok(Process::myself()?
-        .mountinfo()
-        .context("failed to get mountinfo")?
-        .into_iter()
-        .filter(|m| m.fs_type == "cgroup")
-        .map(|m| m.mount_point)
-        .collect())
+    Ok(cgroup_info()?.mounts.clone())
 }
 
 /// List the mount points of all currently supported cgroup subsystems.
@@ -30,38 +126,116 @@ pub fn list_supported_mount_points() -> Result<HashMap<ControllerType, PathBuf>>
     Ok(mount_paths)
 }
 
-pub fn get_subsystem_mount_point(subsystem: &ControllerType) -> Result<PathBuf> {
-    let subsystem = subsystem.to_string();
-    Process::myself()?
-        .mountinfo()
-        .context("failed to get mountinfo")?
-        .into_iter()
-        .find(|m| {
-            if m.fs_type == "cgroup" {
-                // Some systems mount net_prio and net_cls in the same directory
-                // other systems mount them in their own diretories. This
-                // should handle both cases.
-                if subsystem == "net_cls" {
-                    return m.mount_point.ends_with("net_cls,net_prio")
-                        || m.mount_point.ends_with("net_prio,net_cls")
-                        || m.mount_point.ends_with("net_cls");
-                } else if subsystem == "net_prio" {
-                    return m.mount_point.ends_with("net_cls,net_prio")
-                        || m.mount_point.ends_with("net_prio,net_cls")
-                        || m.mount_point.ends_with("net_prio");
-                }
-
-                if subsystem == "cpu" {
-                    return m.mount_point.ends_with("cpu,cpuacct")
-                        || m.mount_point.ends_with("cpu");
-                }
-                if subsystem == "cpuacct" {
-                    return m.mount_point.ends_with("cpu,cpuacct")
-                        || m.mount_point.ends_with("cpuacct");
-                }
-            }
-            m.mount_point.ends_with(&subsystem)
+/// List the mount points of named hierarchies, which group processes without
+/// attaching a resource controller, e.g. name=systemd. They are keyed by name.
+pub fn list_named_hierarchies() -> Result<HashMap<String, PathBuf>> {
+    Ok(cgroup_info()?
+        .mount_points
+        .iter()
+        .filter_map(|(hierarchy, mount_point)| {
+            let name = hierarchy.strip_prefix("name=")?;
+            Some((name.to_owned(), mount_point.clone()))
         })
-        .map(|m| m.mount_point)
-        .ok_or_else(|| anyhow!("could not find mountpoint for {}", subsystem))
+        .collect())
+}
+
+pub fn get_subsystem_mount_point(subsystem: &ControllerType) -> Result<PathBuf> {
+    cgroup_info()?
+        .mount_point(subsystem.as_ref())
+        .map(Path::to_path_buf)
+        .with_context(|| format!("could not find mountpoint for {}", subsystem))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(options: &[&str]) -> Vec<(String, Option<String>)> {
+        options
+            .iter()
+            .map(|o| match o.split_once('=') {
+                Some((key, value)) => (key.to_string(), Some(value.to_string())),
+                None => (o.to_string(), None),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cgroup_info_from_parts() {
+        let mounts = vec![
+            (
+                PathBuf::from("/sys/fs/cgroup/systemd"),
+                options(&["rw", "xattr", "name=systemd"]),
+            ),
+            (
+                PathBuf::from("/sys/fs/cgroup/cpu,cpuacct"),
+                options(&["rw", "cpu", "cpuacct"]),
+            ),
+            (
+                PathBuf::from("/sys/fs/cgroup/net_cls,net_prio"),
+                options(&["rw", "net_cls", "net_prio"]),
+            ),
+            (
+                PathBuf::from("/sys/fs/cgroup/memory"),
+                options(&["rw", "memory"]),
+            ),
+            (
+                PathBuf::from("/run/container/memory"),
+                options(&["rw", "memory"]),
+            ),
+        ];
+        let controllers: HashSet<String> = ["cpu", "cpuacct", "net_cls", "net_prio", "memory"]
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        let process_cgroups = vec![
+            (
+                vec!["name=systemd".to_owned()],
+                PathBuf::from("/user.slice"),
+            ),
+            (
+                vec!["cpu".to_owned(), "cpuacct".to_owned()],
+                PathBuf::from("/youki"),
+            ),
+        ];
+
+        let info = CgroupInfo::from_parts(mounts, &controllers, process_cgroups);
+        assert_eq!(info.mounts.len(), 5);
+        assert_eq!(
+            info.mount_point("cpuacct"),
+            Some(Path::new("/sys/fs/cgroup/cpu,cpuacct"))
+        );
+        assert_eq!(
+            info.mount_point("net_prio"),
+            Some(Path::new("/sys/fs/cgroup/net_cls,net_prio"))
+        );
+        assert_eq!(
+            info.mount_point("memory"),
+            Some(Path::new("/sys/fs/cgroup/memory"))
+        );
+        assert_eq!(
+            info.mount_point("name=systemd"),
+            Some(Path::new("/sys/fs/cgroup/systemd"))
+        );
+        assert_eq!(info.mount_point("rw"), None);
+        assert_eq!(info.mount_point("xattr"), None);
+        assert_eq!(info.process_cgroup("cpu"), Some(Path::new("/youki")));
+        assert_eq!(
+            info.process_cgroup("name=systemd"),
+            Some(Path::new("/user.slice"))
+        );
+        assert_eq!(info.process_cgroup("memory"), None);
+    }
+
+    #[test]
+    fn test_refresh_cgroup_info() -> Result<()> {
+        let first = cgroup_info()?;
+        assert!(Arc::ptr_eq(&first, &cgroup_info()?));
+
+        refresh_cgroup_info();
+        let refreshed = cgroup_info()?;
+        assert!(!Arc::ptr_eq(&first, &refreshed));
+        assert_eq!(first.mounts, refreshed.mounts);
+        Ok(())
+    }
 }
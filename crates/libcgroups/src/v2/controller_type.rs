@@ -1,5 +1,9 @@
 use std::fmt::Display;
 
+use oci_spec::runtime::LinuxResources;
+
+use crate::common::ControllerUsage;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum ControllerType {
     Cpu,
@@ -8,6 +12,8 @@ pub enum ControllerType {
     Memory,
     HugeTlb,
     Pids,
+    Rdma,
+    Misc,
 }
 
 impl Display for ControllerType {
@@ -19,12 +25,93 @@ impl Display for ControllerType {
             Self::Memory => "memory",
             Self::HugeTlb => "hugetlb",
             Self::Pids => "pids",
+            Self::Rdma => "rdma",
+            Self::Misc => "misc",
         };
 
         write!(f, "{}", print)
     }
 }
 
+impl ControllerType {
+    /// Returns true if the controller can be used in a threaded subtree
+    pub fn is_threaded(&self) -> bool {
+        matches!(self, Self::Cpu | Self::CpuSet | Self::Pids)
+    }
+
+    /// Returns how the resources depend on the controller, which decides if
+    /// they can be skipped when the controller is not available
+    pub fn usage(&self, resources: &LinuxResources) -> ControllerUsage {
+        if self.is_required_by(resources) {
+            ControllerUsage::Required
+        } else if self.is_configured_by(resources) {
+            ControllerUsage::Configured
+        } else {
+            ControllerUsage::Unused
+        }
+    }
+
+    fn is_configured_by(&self, resources: &LinuxResources) -> bool {
+        match self {
+            Self::Cpu | Self::CpuSet => resources.cpu().is_some(),
+            Self::Io => resources.block_io().is_some(),
+            Self::Memory => resources.memory().is_some(),
+            Self::HugeTlb => resources.hugepage_limits().is_some(),
+            Self::Pids => resources.pids().is_some(),
+            Self::Rdma => resources.rdma().is_some(),
+            Self::Misc => false,
+        }
+    }
+
+    /// Returns true if the resources restrict something that only the
+    /// controller can enforce
+    fn is_required_by(&self, resources: &LinuxResources) -> bool {
+        match self {
+            Self::Cpu => resources.cpu().as_ref().map_or(false, |cpu| {
+                cpu.shares().is_some()
+                    || cpu.quota().is_some()
+                    || cpu.period().is_some()
+                    || cpu.realtime_runtime().is_some()
+                    || cpu.realtime_period().is_some()
+            }),
+            Self::CpuSet => resources
+                .cpu()
+                .as_ref()
+                .map_or(false, |cpu| cpu.cpus().is_some() || cpu.mems().is_some()),
+            Self::Io => resources.block_io().as_ref().map_or(false, |io| {
+                io.weight().map_or(false, |weight| weight > 0)
+                    || io.weight_device().as_ref().map_or(false, |d| !d.is_empty())
+                    || [
+                        io.throttle_read_bps_device(),
+                        io.throttle_write_bps_device(),
+                        io.throttle_read_iops_device(),
+                        io.throttle_write_iops_device(),
+                    ]
+                    .iter()
+                    .any(|devices| devices.as_ref().map_or(false, |d| !d.is_empty()))
+            }),
+            Self::Memory => resources.memory().as_ref().map_or(false, |memory| {
+                memory.limit().is_some()
+                    || memory.reservation().is_some()
+                    || memory.swap().is_some()
+            }),
+            Self::HugeTlb => resources
+                .hugepage_limits()
+                .as_ref()
+                .map_or(false, |limits| !limits.is_empty()),
+            Self::Pids => resources
+                .pids()
+                .as_ref()
+                .map_or(false, |pids| pids.limit() > 0),
+            Self::Rdma => resources
+                .rdma()
+                .as_ref()
+                .map_or(false, |rdma| !rdma.is_empty()),
+            Self::Misc => false,
+        }
+    }
+}
+
 pub const CONTROLLER_TYPES: &[ControllerType] = &[
     ControllerType::Cpu,
     ControllerType::CpuSet,
@@ -32,6 +119,8 @@ pub const CONTROLLER_TYPES: &[ControllerType] = &[
     ControllerType::Io,
     ControllerType::Memory,
     ControllerType::Pids,
+    ControllerType::Rdma,
+    ControllerType::Misc,
 ];
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
@@ -58,3 +147,48 @@ pub const PSEUDO_CONTROLLER_TYPES: &[PseudoControllerType] = &[
     PseudoControllerType::Freezer,
     PseudoControllerType::Unified,
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oci_spec::runtime::{LinuxHugepageLimitBuilder, LinuxPidsBuilder, LinuxResourcesBuilder};
+
+    #[test]
+    fn test_usage() {
+        let resources = LinuxResourcesBuilder::default()
+            .hugepage_limits(vec![])
+            .pids(LinuxPidsBuilder::default().limit(0).build().unwrap())
+            .build()
+            .unwrap();
+        assert_eq!(
+            ControllerType::HugeTlb.usage(&resources),
+            ControllerUsage::Configured
+        );
+        assert_eq!(
+            ControllerType::Pids.usage(&resources),
+            ControllerUsage::Configured
+        );
+        assert_eq!(
+            ControllerType::Memory.usage(&resources),
+            ControllerUsage::Unused
+        );
+
+        let resources = LinuxResourcesBuilder::default()
+            .hugepage_limits(vec![LinuxHugepageLimitBuilder::default()
+                .page_size("2MB")
+                .limit(16384)
+                .build()
+                .unwrap()])
+            .pids(LinuxPidsBuilder::default().limit(100).build().unwrap())
+            .build()
+            .unwrap();
+        assert_eq!(
+            ControllerType::HugeTlb.usage(&resources),
+            ControllerUsage::Required
+        );
+        assert_eq!(
+            ControllerType::Pids.usage(&resources),
+            ControllerUsage::Required
+        );
+    }
+}
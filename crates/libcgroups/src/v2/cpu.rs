@@ -3,7 +3,9 @@ use std::{borrow::Cow, path::Path};
 
 use crate::{
     common::{self, ControllerOpt},
-    stats::{CpuUsage, StatsProvider},
+    convert,
+    reader::CgroupReader,
+    stats::{self, CpuStats, Nanoseconds, StatsProvider},
 };
 
 use oci_spec::runtime::LinuxCpu;
@@ -12,10 +14,9 @@ use super::controller::Controller;
 
 const CGROUP_CPU_WEIGHT: &str = "cpu.weight";
 const CGROUP_CPU_MAX: &str = "cpu.max";
-const UNRESTRICTED_QUOTA: &str = "max";
-const MAX_CPU_WEIGHT: u64 = 10000;
 
 const CPU_STAT: &str = "cpu.stat";
+const CPU_PSI: &str = "cpu.pressure";
 
 pub struct Cpu {}
 
@@ -30,27 +31,37 @@ impl Controller for Cpu {
 }
 
 impl StatsProvider for Cpu {
-    type Stats = CpuUsage;
+    type Stats = CpuStats;
 
-    fn stats(cgroup_path: &Path) -> Result<Self::Stats> {
-        let mut stats = CpuUsage::default();
+    fn stats_from(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<Self::Stats> {
+        let mut stats = CpuStats::default();
 
-        let stat_content = common::read_cgroup_file(cgroup_path.join(CPU_STAT))?;
+        let stat_content = reader.read(&cgroup_path.join(CPU_STAT))?;
         for entry in stat_content.lines() {
             let parts: Vec<&str> = entry.split_ascii_whitespace().collect();
             if parts.len() != 2 {
                 continue;
             }
 
-            let value = parts[1].parse()?;
+            let value: u64 = parts[1].parse()?;
+            // cpu.stat reports times in microseconds. The throttling stats are
+            // only present if the cpu controller is enabled for the cgroup.
             match parts[0] {
-                "usage_usec" => stats.usage_total = value,
-                "user_usec" => stats.usage_user = value,
-                "system_usec" => stats.usage_kernel = value,
+                "usage_usec" => stats.usage.usage_total = Nanoseconds::from_micros(value),
+                "user_usec" => stats.usage.usage_user = Nanoseconds::from_micros(value),
+                "system_usec" => stats.usage.usage_kernel = Nanoseconds::from_micros(value),
+                "nr_periods" => stats.throttling.periods = value,
+                "nr_throttled" => stats.throttling.throttled_periods = value,
+                "throttled_usec" => {
+                    stats.throttling.throttled_time = Nanoseconds::from_micros(value)
+                }
+                "nr_bursts" => stats.throttling.bursts = value,
+                "burst_usec" => stats.throttling.burst_time = Nanoseconds::from_micros(value),
                 _ => continue,
             }
         }
 
+        stats.psi = stats::psi_stats(reader, &cgroup_path.join(CPU_PSI))?;
         Ok(stats)
     }
 }
@@ -61,26 +72,19 @@ impl Cpu {
             bail!("realtime is not supported on cgroup v2 yet");
         }
 
-        if let Some(mut shares) = cpu.shares() {
-            shares = Self::convert_shares_to_cgroup2(shares);
-            if shares != 0 {
+        if let Some(shares) = cpu.shares() {
+            let weight = convert::cpu_shares_to_weight(shares);
+            if weight != 0 {
                 // will result in Erno 34 (numerical result out of range) otherwise
-                common::write_cgroup_file(path.join(CGROUP_CPU_WEIGHT), shares)?;
+                common::write_cgroup_file(path.join(CGROUP_CPU_WEIGHT), weight)?;
             }
         }
 
         let cpu_max_file = path.join(CGROUP_CPU_MAX);
         let new_cpu_max: Option<Cow<str>> = match (cpu.quota(), cpu.period()) {
             (None, Some(period)) => Self::create_period_only_value(&cpu_max_file, period)?,
-            (Some(quota), None) if quota > 0 => Some(quota.to_string().into()),
-            (Some(quota), None) if quota <= 0 => Some(UNRESTRICTED_QUOTA.into()),
-            (Some(quota), Some(period)) if quota > 0 => {
-                Some(format!("{} {}", quota, period).into())
-            }
-            (Some(quota), Some(period)) if quota <= 0 => {
-                Some(format!("{} {}", UNRESTRICTED_QUOTA, period).into())
-            }
-            _ => None,
+            (Some(quota), period) => Some(convert::cpu_quota_to_max(quota, period).into()),
+            (None, None) => None,
         };
 
         // format is 'quota period'
@@ -94,15 +98,6 @@ impl Cpu {
         Ok(())
     }
 
-    fn convert_shares_to_cgroup2(shares: u64) -> u64 {
-        if shares == 0 {
-            return 0;
-        }
-
-        let weight = 1 + ((shares - 2) * 9999) / 262142;
-        weight.min(MAX_CPU_WEIGHT)
-    }
-
     fn is_realtime_requested(cpu: &LinuxCpu) -> bool {
         if cpu.realtime_period().is_some() {
             return true;
@@ -127,6 +122,7 @@ impl Cpu {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::stats::{CpuThrottling, CpuUsage};
     use crate::test::{create_temp_dir, set_fixture, setup};
     use oci_spec::runtime::LinuxCpuBuilder;
     use std::fs;
@@ -176,7 +172,7 @@ mod tests {
         // assert
         let content = fs::read_to_string(max)
             .unwrap_or_else(|_| panic!("read {} file content", CGROUP_CPU_MAX));
-        assert_eq!(content, UNRESTRICTED_QUOTA)
+        assert_eq!(content, convert::UNRESTRICTED_QUOTA)
     }
 
     #[test]
@@ -266,12 +262,41 @@ mod tests {
 
         let actual = Cpu::stats(&tmp).expect("get cgroup stats");
         let expected = CpuUsage {
-            usage_total: 7730,
-            usage_user: 4387,
-            usage_kernel: 3498,
+            usage_total: Nanoseconds::from_micros(7730),
+            usage_user: Nanoseconds::from_micros(4387),
+            usage_kernel: Nanoseconds::from_micros(3498),
             ..Default::default()
         };
 
-        assert_eq!(actual, expected);
+        assert_eq!(actual.usage, expected);
+        assert_eq!(actual.throttling, CpuThrottling::default());
+    }
+
+    #[test]
+    fn test_stat_throttling() {
+        let tmp = create_temp_dir("test_stat_throttling").expect("create temp directory for test");
+        let content = [
+            "usage_usec 7730",
+            "user_usec 4387",
+            "system_usec 3498",
+            "nr_periods 400",
+            "nr_throttled 20",
+            "throttled_usec 150000",
+            "nr_bursts 3",
+            "burst_usec 25000",
+        ]
+        .join("\n");
+        set_fixture(&tmp, CPU_STAT, &content).expect("create stat file");
+
+        let actual = Cpu::stats(&tmp).expect("get cgroup stats");
+        let expected = CpuThrottling {
+            periods: 400,
+            throttled_periods: 20,
+            throttled_time: Nanoseconds::from_micros(150000),
+            bursts: 3,
+            burst_time: Nanoseconds::from_micros(25000),
+        };
+
+        assert_eq!(actual.throttling, expected);
     }
 }
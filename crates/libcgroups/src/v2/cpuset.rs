@@ -1,13 +1,19 @@
 use anyhow::{Context, Result};
 use std::path::Path;
 
-use crate::common::{self, ControllerOpt};
+use crate::{
+    common::{self, ControllerOpt},
+    reader::CgroupReader,
+    stats::{self, CpuSetStats, StatsProvider},
+};
 use oci_spec::runtime::LinuxCpu;
 
 use super::controller::Controller;
 
 const CGROUP_CPUSET_CPUS: &str = "cpuset.cpus";
 const CGROUP_CPUSET_MEMS: &str = "cpuset.mems";
+const CGROUP_CPUSET_CPUS_EFFECTIVE: &str = "cpuset.cpus.effective";
+const CGROUP_CPUSET_MEMS_EFFECTIVE: &str = "cpuset.mems.effective";
 
 pub struct CpuSet {}
 
@@ -22,6 +28,19 @@ impl Controller for CpuSet {
     }
 }
 
+impl StatsProvider for CpuSet {
+    type Stats = CpuSetStats;
+
+    fn stats_from(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<Self::Stats> {
+        stats::cpuset_stats(
+            reader,
+            cgroup_path,
+            CGROUP_CPUSET_CPUS_EFFECTIVE,
+            CGROUP_CPUSET_MEMS_EFFECTIVE,
+        )
+    }
+}
+
 impl CpuSet {
     fn apply(path: &Path, cpuset: &LinuxCpu) -> Result<()> {
         if let Some(cpus) = &cpuset.cpus() {
@@ -41,7 +60,7 @@ mod tests {
     use std::fs;
 
     use super::*;
-    use crate::test::setup;
+    use crate::test::{set_fixture, setup};
     use oci_spec::runtime::LinuxCpuBuilder;
 
     #[test]
@@ -79,4 +98,22 @@ mod tests {
             .unwrap_or_else(|_| panic!("read {} file content", CGROUP_CPUSET_MEMS));
         assert_eq!(content, "1-3");
     }
+
+    #[test]
+    fn test_stat_cpuset() {
+        let (tmp, _) = setup("test_stat_cpuset", CGROUP_CPUSET_CPUS);
+        set_fixture(&tmp, CGROUP_CPUSET_CPUS, "2-3\n").unwrap();
+        set_fixture(&tmp, CGROUP_CPUSET_MEMS, "").unwrap();
+        set_fixture(&tmp, CGROUP_CPUSET_CPUS_EFFECTIVE, "2-3\n").unwrap();
+        set_fixture(&tmp, CGROUP_CPUSET_MEMS_EFFECTIVE, "0-1\n").unwrap();
+
+        let actual = CpuSet::stats(&tmp).expect("get cgroup stats");
+        let expected = CpuSetStats {
+            cpus: vec![2, 3],
+            mems: vec![],
+            effective_cpus: vec![2, 3],
+            effective_mems: vec![0, 1],
+        };
+        assert_eq!(actual, expected);
+    }
 }
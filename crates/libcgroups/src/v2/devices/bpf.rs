@@ -1,4 +1,5 @@
 use anyhow::{bail, Result};
+use std::ffi::CString;
 use std::os::unix::io::RawFd;
 use std::ptr;
 
@@ -7,13 +8,15 @@ use std::ptr;
 pub fn prog_load(license: &str, insns: &[u8]) -> Result<RawFd> {
     let insns_cnt = insns.len() / std::mem::size_of::<libbpf_sys::bpf_insn>();
     let insns = insns as *const _ as *const libbpf_sys::bpf_insn;
+    // the kernel expects a nul terminated license
+    let license = CString::new(license)?;
 
     let prog_fd = unsafe {
         libbpf_sys::bpf_load_program(
             libbpf_sys::BPF_PROG_TYPE_CGROUP_DEVICE,
             insns,
             insns_cnt as u64,
-            license as *const _ as *const i8,
+            license.as_ptr(),
             0,
             ptr::null_mut::<i8>(),
             0,
@@ -103,6 +106,14 @@ pub fn prog_attach(prog_fd: RawFd, cgroup_fd: RawFd) -> Result<()> {
     Ok(())
 }
 
+/// Closes the file descriptor of a program. The program stays loaded as long
+/// as it is attached to a cgroup.
+pub fn prog_close(prog_fd: RawFd) {
+    if let Err(err) = nix::unistd::close(prog_fd) {
+        log::debug!("failed to close bpf program {}: {}", prog_fd, err);
+    }
+}
+
 pub fn bump_memlock_rlimit() -> Result<()> {
     let rlimit = libc::rlimit {
         rlim_cur: 128 << 20,
@@ -1,14 +1,16 @@
 use std::os::unix::io::AsRawFd;
 use std::path::Path;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 
 use super::*;
+use nix::dir::Dir;
 use nix::fcntl::OFlag;
 use nix::sys::stat::Mode;
 use oci_spec::runtime::LinuxDeviceCgroup;
 
 use crate::common::{default_allow_devices, default_devices, ControllerOpt};
+use crate::dry_run::{self, CgroupWrite};
 use crate::v2::controller::Controller;
 
 const LICENSE: &str = "Apache";
@@ -20,8 +22,13 @@ impl Controller for Devices {
         #[cfg(not(feature = "cgroupsv2_devices"))]
         return Ok(());
 
+        // without rules, e.g. when other resources are updated, the program
+        // that has been attached before is kept
         #[cfg(feature = "cgroupsv2_devices")]
-        return Self::apply_devices(cgroup_root, controller_opt.resources.devices());
+        return match controller_opt.resources.devices() {
+            Some(_) => Self::apply_devices(cgroup_root, controller_opt.resources.devices()),
+            None => Ok(()),
+        };
     }
 }
 
@@ -54,13 +61,19 @@ impl Devices {
         }
 
         let prog = program::Program::from_rules(&emulator.rules, emulator.default_allow)?;
+        if dry_run::record(CgroupWrite::DeviceFilter {
+            path: cgroup_root.to_owned(),
+            rules: emulator.rules.len(),
+        }) {
+            return Ok(());
+        }
 
         // Increase `ulimit -l` limit to avoid BPF_PROG_LOAD error (#2167).
         // This limit is not inherited into the container.
         bpf::bump_memlock_rlimit()?;
         let prog_fd = bpf::prog_load(LICENSE, prog.bytecodes())?;
 
-        // FIXME: simple way to attach BPF program
+        // simple way to replace the BPF program on updates
         //  1. get list of existing attached programs
         //  2. attach this program (not use BPF_F_REPLACE, see below)
         //  3. detach all programs of 1
@@ -70,23 +83,56 @@ impl Devices {
         //   2. there is exactly one attached program
         // https://github.com/opencontainers/runc/blob/8e6871a3b14bb74e0ef358aca3b9f8f9cb80f041/libcontainer/cgroups/ebpf/ebpf_linux.go#L165
         //
-        // IMHO, this is too complicated, and in most cases, we just attach program once without
-        // already attached programs.
+        // Both programs are attached for a moment, which denies all accesses that
+        // either of them denies, so no access is allowed in between that neither
+        // the old nor the new rules allow.
+        let fd = open_cgroup(cgroup_root)?;
+        let old_progs = bpf::prog_query(fd.as_raw_fd())?;
+        let attached = bpf::prog_attach(prog_fd, fd.as_raw_fd());
+        // the program stays loaded while it is attached
+        bpf::prog_close(prog_fd);
+        attached.with_context(|| {
+            format!(
+                "failed to attach device filter to {}",
+                cgroup_root.display()
+            )
+        })?;
+
+        detach_programs(&fd, old_progs)
+    }
+
+    /// Detaches the device filters from the cgroup, which allows access to
+    /// all devices again. The kernel releases the programs of a cgroup when
+    /// it is removed, but detaching them first releases them even if the
+    /// removal fails.
+    pub fn detach(cgroup_root: &Path) -> Result<()> {
+        let fd = open_cgroup(cgroup_root)?;
+        let progs = bpf::prog_query(fd.as_raw_fd())?;
+        detach_programs(&fd, progs)
+    }
+}
 
-        let fd = nix::dir::Dir::open(
-            cgroup_root.as_os_str(),
-            OFlag::O_RDONLY | OFlag::O_DIRECTORY,
-            Mode::from_bits(0o600).unwrap(),
-        )?;
+fn open_cgroup(cgroup_root: &Path) -> Result<Dir> {
+    Dir::open(
+        cgroup_root.as_os_str(),
+        OFlag::O_RDONLY | OFlag::O_DIRECTORY,
+        Mode::from_bits(0o600).unwrap(),
+    )
+    .with_context(|| format!("failed to open cgroup {}", cgroup_root.display()))
+}
 
-        let old_progs = bpf::prog_query(fd.as_raw_fd())?;
-        bpf::prog_attach(prog_fd, fd.as_raw_fd())?;
-        for old_prog in old_progs {
-            bpf::prog_detach2(old_prog.fd, fd.as_raw_fd())?;
+fn detach_programs(cgroup: &Dir, progs: Vec<bpf::ProgramInfo>) -> Result<()> {
+    let mut result = Ok(());
+    for prog in progs {
+        log::debug!("detach device filter {} from cgroup", prog.id);
+        if let Err(err) = bpf::prog_detach2(prog.fd, cgroup.as_raw_fd()) {
+            result =
+                Err(err).with_context(|| format!("failed to detach device filter {}", prog.id));
         }
-
-        Ok(())
+        bpf::prog_close(prog.fd);
     }
+
+    result
 }
 
 // FIXME: add tests, but how to?
@@ -1,19 +1,13 @@
 use anyhow::{bail, Context, Result};
-use std::{
-    fs::OpenOptions,
-    io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
-    path::Path,
-    str, thread,
-    time::Duration,
-};
+use std::path::Path;
 
 use crate::common::{ControllerOpt, FreezerState};
+use crate::freezer::{self, CGROUP_EVENTS, CGROUP_FREEZE};
+use crate::reader::CgroupReader;
+use crate::stats::StatsProvider;
 
 use super::controller::Controller;
 
-const CGROUP_FREEZE: &str = "cgroup.freeze";
-const CGROUP_EVENTS: &str = "cgroup.events";
-
 pub struct Freezer {}
 
 impl Controller for Freezer {
@@ -26,96 +20,44 @@ impl Controller for Freezer {
     }
 }
 
-impl Freezer {
-    fn apply(freezer_state: FreezerState, path: &Path) -> Result<()> {
-        let state_str = match freezer_state {
-            FreezerState::Undefined => return Ok(()),
-            FreezerState::Frozen => "1",
-            FreezerState::Thawed => "0",
-        };
-
-        match OpenOptions::new()
-            .create(false)
-            .write(true)
-            .open(path.join(CGROUP_FREEZE))
-        {
-            Err(e) => {
-                if let FreezerState::Frozen = freezer_state {
-                    bail!("freezer not supported {}", e);
-                }
-                return Ok(());
-            }
-            Ok(mut file) => file.write_all(state_str.as_bytes())?,
-        };
-
-        // confirm that the cgroup did actually change states.
-        let actual_state = Self::read_freezer_state(path)?;
-        if !actual_state.eq(&freezer_state) {
-            bail!(
-                "expected \"cgroup.freeze\" to be in state {:?} but was in {:?}",
-                freezer_state,
-                actual_state
-            );
-        }
+impl StatsProvider for Freezer {
+    type Stats = FreezerState;
 
-        Ok(())
-    }
+    /// Unlike reading the state after applying it, this does not wait for the
+    /// cgroup to be frozen, but reports that it is still freezing
+    fn stats_from(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<Self::Stats> {
+        let freeze = reader.read(&cgroup_path.join(CGROUP_FREEZE))?;
+        if freeze.trim() != "1" {
+            return Ok(FreezerState::Thawed);
+        }
 
-    fn read_freezer_state(path: &Path) -> Result<FreezerState> {
-        let mut buf = [0; 1];
-        OpenOptions::new()
-            .create(false)
-            .read(true)
-            .open(path.join(CGROUP_FREEZE))?
-            .read_exact(&mut buf)?;
-
-        let state = str::from_utf8(&buf)?;
-        match state {
-            "0" => Ok(FreezerState::Thawed),
-            "1" => Self::wait_frozen(path),
-            _ => bail!("unknown \"cgroup.freeze\" state: {}", state),
+        let events = reader.read(&cgroup_path.join(CGROUP_EVENTS))?;
+        let frozen = events.lines().any(|line| line.trim() == "frozen 1");
+        if frozen {
+            Ok(FreezerState::Frozen)
+        } else {
+            Ok(FreezerState::Freezing)
         }
     }
+}
 
-    // wait_frozen polls cgroup.events until it sees "frozen 1" in it.
-    fn wait_frozen(path: &Path) -> Result<FreezerState> {
-        let f = OpenOptions::new()
-            .create(false)
-            .read(true)
-            .open(path.join(CGROUP_EVENTS))?;
-        let mut f = BufReader::new(f);
-
-        let wait_time = Duration::from_millis(10);
-        let max_iter = 1000;
-        let mut iter = 0;
-        let mut line = String::new();
-
-        loop {
-            if iter == max_iter {
-                bail!(
-                    "timeout of {} ms reached waiting for the cgroup to freeze",
-                    wait_time.as_millis() * max_iter
-                );
-            }
-            line.clear();
-            let num_bytes = f.read_line(&mut line)?;
-            if num_bytes == 0 {
-                break;
-            }
-            if line.starts_with("frozen ") {
-                if line.starts_with("frozen 1") {
-                    if iter > 1 {
-                        log::debug!("frozen after {} retries", iter)
-                    }
-                    return Ok(FreezerState::Frozen);
-                }
-                iter += 1;
-                thread::sleep(wait_time);
-                f.seek(SeekFrom::Start(0))?;
+impl Freezer {
+    fn apply(freezer_state: FreezerState, path: &Path) -> Result<()> {
+        if !path.join(CGROUP_FREEZE).exists() {
+            if let FreezerState::Frozen = freezer_state {
+                bail!("freezer not supported by {}", path.display());
             }
+            return Ok(());
         }
 
-        Ok(FreezerState::Undefined)
+        match freezer_state {
+            FreezerState::Undefined => Ok(()),
+            FreezerState::Freezing => {
+                bail!("freezing is a transitional state and cannot be applied")
+            }
+            FreezerState::Frozen => freezer::freeze_v2(path, freezer::DEFAULT_TIMEOUT),
+            FreezerState::Thawed => freezer::thaw_v2(path),
+        }
     }
 }
 
@@ -125,6 +67,7 @@ mod tests {
     use crate::common::FreezerState;
     use crate::test::{create_temp_dir, set_fixture};
     use std::sync::Arc;
+    use std::{thread, time::Duration};
 
     #[test]
     fn test_set_freezer_state() {
@@ -189,4 +132,20 @@ mod tests {
             assert!(r.is_err());
         }
     }
+
+    #[test]
+    fn test_freezer_stats() {
+        let tmp = create_temp_dir("test_freezer_stats").expect("create temp directory for test");
+        set_fixture(&tmp, CGROUP_FREEZE, "0\n").expect("Set fixure for freezer state");
+        set_fixture(&tmp, CGROUP_EVENTS, "populated 1\nfrozen 0\n")
+            .expect("Set fixure for freezer state");
+        assert_eq!(Freezer::stats(&tmp).unwrap(), FreezerState::Thawed);
+
+        set_fixture(&tmp, CGROUP_FREEZE, "1\n").expect("Set fixure for freezer state");
+        assert_eq!(Freezer::stats(&tmp).unwrap(), FreezerState::Freezing);
+
+        set_fixture(&tmp, CGROUP_EVENTS, "populated 1\nfrozen 1\n")
+            .expect("Set fixure for freezer state");
+        assert_eq!(Freezer::stats(&tmp).unwrap(), FreezerState::Frozen);
+    }
 }
@@ -1,10 +1,11 @@
-use anyhow::{bail, Context, Result};
+use anyhow::{Context, Result};
 use std::{collections::HashMap, path::Path};
 
 use super::controller::Controller;
 use crate::{
     common::{self, ControllerOpt},
-    stats::{parse_single_value, supported_page_sizes, HugeTlbStats, StatsProvider},
+    reader::CgroupReader,
+    stats::{supported_page_sizes, Bytes, HugePageSize, HugeTlbStats, StatsProvider},
 };
 
 use oci_spec::runtime::LinuxHugepageLimit;
@@ -27,15 +28,13 @@ impl Controller for HugeTlb {
 impl StatsProvider for HugeTlb {
     type Stats = HashMap<String, HugeTlbStats>;
 
-    fn stats(cgroup_path: &Path) -> Result<Self::Stats> {
-        let page_sizes = supported_page_sizes()?;
+    fn stats_from(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<Self::Stats> {
+        let page_sizes = supported_page_sizes(reader)?;
         let mut hugetlb_stats = HashMap::with_capacity(page_sizes.len());
 
         for page_size in page_sizes {
-            hugetlb_stats.insert(
-                page_size.clone(),
-                Self::stats_for_page_size(cgroup_path, &page_size)?,
-            );
+            let stats = Self::stats_for_page_size(reader, cgroup_path, &page_size)?;
+            hugetlb_stats.insert(page_size.moniker, stats);
         }
 
         Ok(hugetlb_stats)
@@ -44,41 +43,55 @@ impl StatsProvider for HugeTlb {
 
 impl HugeTlb {
     fn apply(root_path: &Path, hugetlb: &LinuxHugepageLimit) -> Result<()> {
-        let page_size: String = hugetlb
-            .page_size()
-            .chars()
-            .take_while(|c| c.is_digit(10))
-            .collect();
-        let page_size: u64 = page_size.parse()?;
-        if !Self::is_power_of_two(page_size) {
-            bail!("page size must be in the format of 2^(integer)");
-        }
-
+        let page_size: HugePageSize = hugetlb.page_size().parse()?;
         common::write_cgroup_file(
-            root_path.join(format!("hugetlb.{}.limit_in_bytes", hugetlb.page_size())),
+            root_path.join(format!("hugetlb.{}.max", page_size)),
             hugetlb.limit(),
         )?;
-        Ok(())
-    }
 
-    fn is_power_of_two(number: u64) -> bool {
-        (number != 0) && (number & (number - 1)) == 0
+        // reservations are accounted since kernel 5.7. They are limited as
+        // well, as otherwise hugepages that are reserved by a mapping but not
+        // yet faulted in are not bounded by the limit
+        let rsvd_limit = root_path.join(format!("hugetlb.{}.rsvd.max", page_size));
+        if rsvd_limit.exists() {
+            common::write_cgroup_file(rsvd_limit, hugetlb.limit())?;
+        }
+        Ok(())
     }
 
-    fn stats_for_page_size(cgroup_path: &Path, page_size: &str) -> Result<HugeTlbStats> {
+    fn stats_for_page_size(
+        reader: &dyn CgroupReader,
+        cgroup_path: &Path,
+        page_size: &HugePageSize,
+    ) -> Result<HugeTlbStats> {
         let events_file = format!("hugetlb.{}.events", page_size);
-        let events = common::read_cgroup_file(cgroup_path.join(&events_file))?;
+        let events = reader.read(&cgroup_path.join(&events_file))?;
+        // the max event counts the allocations that failed due to the limit
         let fail_count: u64 = events
             .lines()
-            .find(|l| l.starts_with("max"))
-            .map(|l| l[3..].trim().parse())
+            .filter_map(|l| l.split_once(' '))
+            .find(|(key, _)| *key == "max")
+            .map(|(_, value)| value.trim().parse())
             .transpose()
             .with_context(|| format!("failed to parse max value for {}", events_file))?
             .unwrap_or_default();
 
+        // reservations are accounted since kernel 5.7
+        let rsvd_file = cgroup_path.join(format!("hugetlb.{}.rsvd.current", page_size));
+        let rsvd_usage = if reader.exists(&rsvd_file) {
+            Some(Bytes(reader.parse_single_value(&rsvd_file)?))
+        } else {
+            None
+        };
+
         Ok(HugeTlbStats {
-            usage: parse_single_value(&cgroup_path.join(format!("hugetlb.{}.current", page_size)))?,
+            usage: Bytes(
+                reader.parse_single_value(
+                    &cgroup_path.join(format!("hugetlb.{}.current", page_size)),
+                )?,
+            ),
             fail_count,
+            rsvd_usage,
             ..Default::default()
         })
     }
@@ -87,13 +100,14 @@ impl HugeTlb {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::reader::{FakeReader, FsReader};
     use crate::test::{create_temp_dir, set_fixture};
     use oci_spec::runtime::LinuxHugepageLimitBuilder;
     use std::fs::read_to_string;
 
     #[test]
     fn test_set_hugetlb() {
-        let page_file_name = "hugetlb.2MB.limit_in_bytes";
+        let page_file_name = "hugetlb.2MB.max";
         let tmp = create_temp_dir("test_set_hugetlbv2").expect("create temp directory for test");
         set_fixture(&tmp, page_file_name, "0").expect("Set fixture for 2 MB page size");
 
@@ -107,6 +121,29 @@ mod tests {
         assert_eq!(hugetlb.limit().to_string(), content);
     }
 
+    #[test]
+    fn test_set_hugetlb_rsvd() {
+        let tmp =
+            create_temp_dir("test_set_hugetlbv2_rsvd").expect("create temp directory for test");
+        set_fixture(&tmp, "hugetlb.2MB.max", "0").expect("set fixture for 2 MB page size");
+        set_fixture(&tmp, "hugetlb.1GB.max", "0").expect("set fixture for 1 GB page size");
+        set_fixture(&tmp, "hugetlb.2MB.rsvd.max", "0").expect("set fixture for 2 MB reservations");
+
+        for page_size in ["2MB", "1GB"] {
+            let hugetlb = LinuxHugepageLimitBuilder::default()
+                .page_size(page_size)
+                .limit(16384)
+                .build()
+                .unwrap();
+            HugeTlb::apply(&tmp, &hugetlb).expect("apply hugetlb");
+        }
+
+        let content = read_to_string(tmp.join("hugetlb.2MB.rsvd.max")).expect("read rsvd limit");
+        assert_eq!(content, "16384");
+        // kernels without reservation accounting only have the limit
+        assert!(!tmp.join("hugetlb.1GB.rsvd.max").exists());
+    }
+
     #[test]
     fn test_set_hugetlb_with_invalid_page_size() {
         let tmp = create_temp_dir("test_set_hugetlbv2_with_invalid_page_size")
@@ -127,7 +164,7 @@ mod tests {
 
     quickcheck! {
         fn property_test_set_hugetlb(hugetlb: LinuxHugepageLimit) -> bool {
-            let page_file_name = format!("hugetlb.{:?}.limit_in_bytes", hugetlb.page_size());
+            let page_file_name = format!("hugetlb.{:?}.max", hugetlb.page_size());
             let tmp = create_temp_dir("property_test_set_hugetlbv2").expect("create temp directory for test");
             set_fixture(&tmp, &page_file_name, "0").expect("Set fixture for page size");
             let result = HugeTlb::apply(&tmp, &hugetlb);
@@ -139,7 +176,7 @@ mod tests {
             .collect();
             let page_size: u64 = page_size.parse().expect("parse page size");
 
-            if HugeTlb::is_power_of_two(page_size) && page_size != 1 {
+            if page_size.is_power_of_two() && page_size != 1 {
                 let content =
                     read_to_string(tmp.join(page_file_name)).expect("Read hugetlb file content");
                 hugetlb.limit().to_string() == content
@@ -155,13 +192,32 @@ mod tests {
         set_fixture(&tmp, "hugetlb.2MB.current", "1024\n").expect("set hugetlb current");
         set_fixture(&tmp, "hugetlb.2MB.events", "max 5\n").expect("set hugetlb events");
 
-        let actual = HugeTlb::stats_for_page_size(&tmp, "2MB").expect("get cgroup stats");
+        let actual = HugeTlb::stats_for_page_size(&FsReader, &tmp, &"2MB".parse().unwrap())
+            .expect("get cgroup stats");
 
         let expected = HugeTlbStats {
-            usage: 1024,
-            max_usage: 0,
+            usage: Bytes(1024),
+            max_usage: Bytes(0),
             fail_count: 5,
+            rsvd_usage: None,
         };
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_stat_hugetlb_rsvd() {
+        let reader = FakeReader::default()
+            .with_file(
+                "/sys/kernel/mm/hugepages/hugepages-2048kB/nr_hugepages",
+                "4\n",
+            )
+            .with_file("/test/hugetlb.2MB.current", "2097152\n")
+            .with_file("/test/hugetlb.2MB.events", "max 0\n")
+            .with_file("/test/hugetlb.2MB.rsvd.current", "4194304\n");
+
+        let actual = HugeTlb::stats_from(&reader, Path::new("/test")).expect("get cgroup stats");
+        assert_eq!(actual.len(), 1);
+        assert_eq!(actual["2MB"].usage, Bytes(2097152));
+        assert_eq!(actual["2MB"].rsvd_usage, Some(Bytes(4194304)));
+    }
 }
@@ -1,10 +1,18 @@
-use std::path::{Path, PathBuf};
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{bail, Context, Result};
 
 use crate::{
     common::{self, ControllerOpt},
-    stats::{self, BlkioDeviceStat, BlkioStats, StatsProvider},
+    convert,
+    reader::CgroupReader,
+    stats::{
+        self, BlkioDeviceStat, BlkioSource, BlkioStats, IoCostModel, IoCostQos, IoQosDeviceStats,
+        IoQosStats, StatsError, StatsProvider,
+    },
 };
 
 use super::controller::Controller;
@@ -13,6 +21,11 @@ use oci_spec::runtime::LinuxBlockIo;
 const CGROUP_BFQ_IO_WEIGHT: &str = "io.bfq.weight";
 const CGROUP_IO_WEIGHT: &str = "io.weight";
 const CGROUP_IO_STAT: &str = "io.stat";
+const CGROUP_IO_PSI: &str = "io.pressure";
+const CGROUP_IO_LATENCY: &str = "io.latency";
+// only provided by the root cgroup
+const CGROUP_IO_COST_QOS: &str = "io.cost.qos";
+const CGROUP_IO_COST_MODEL: &str = "io.cost.model";
 
 pub struct Io {}
 
@@ -29,48 +42,61 @@ impl Controller for Io {
 impl StatsProvider for Io {
     type Stats = BlkioStats;
 
-    fn stats(cgroup_path: &Path) -> Result<Self::Stats> {
-        let keyed_data = stats::parse_nested_keyed_data(&cgroup_path.join(CGROUP_IO_STAT))?;
-        let mut service_bytes = Vec::with_capacity(keyed_data.len());
-        let mut serviced = Vec::with_capacity(keyed_data.len());
-        for entry in keyed_data {
-            let (major, minor) = stats::parse_device_number(&entry.0)?;
-            for value in &entry.1 {
-                if value.starts_with("rbytes") {
-                    service_bytes.push(BlkioDeviceStat {
-                        major,
-                        minor,
-                        op_type: Some("read".to_owned()),
-                        value: stats::parse_value(&value[7..])?,
-                    });
-                } else if value.starts_with("wbytes") {
-                    service_bytes.push(BlkioDeviceStat {
-                        major,
-                        minor,
-                        op_type: Some("write".to_owned()),
-                        value: stats::parse_value(&value[7..])?,
-                    });
-                } else if value.starts_with("rios") {
-                    serviced.push(BlkioDeviceStat {
-                        major,
-                        minor,
-                        op_type: Some("read".to_owned()),
-                        value: stats::parse_value(&value[5..])?,
-                    });
-                } else if value.starts_with("wios") {
-                    serviced.push(BlkioDeviceStat {
-                        major,
-                        minor,
-                        op_type: Some("write".to_owned()),
-                        value: stats::parse_value(&value[5..])?,
-                    });
-                }
+    fn stats_from(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<Self::Stats> {
+        // io.stat has one line of nested keyed data per device, which is
+        // parsed in place instead of collecting it into owned strings first
+        let data = reader.read(&cgroup_path.join(CGROUP_IO_STAT))?;
+        let devices = data.lines().count();
+        let mut service_bytes = Vec::with_capacity(2 * devices);
+        let mut serviced = Vec::with_capacity(2 * devices);
+        let mut qos_devices = Vec::new();
+        for entry in data.lines() {
+            let mut fields = entry.split_ascii_whitespace();
+            let (major, minor) = match fields.next() {
+                Some(device) => stats::parse_device_number(device)?,
+                None => continue,
+            };
+
+            let mut qos = IoQosDeviceStats {
+                major,
+                minor,
+                ..Default::default()
+            };
+            let mut has_qos = false;
+            for field in fields {
+                let (key, value) = field
+                    .split_once('=')
+                    .with_context(|| format!("invalid entry {} in {}", field, CGROUP_IO_STAT))?;
+                let (device_stats, op_type) = match key {
+                    "rbytes" => (&mut service_bytes, "read"),
+                    "wbytes" => (&mut service_bytes, "write"),
+                    "rios" => (&mut serviced, "read"),
+                    "wios" => (&mut serviced, "write"),
+                    _ => {
+                        has_qos |= Self::set_qos_stat(&mut qos, key, value)?;
+                        continue;
+                    }
+                };
+                device_stats.push(BlkioDeviceStat {
+                    major,
+                    minor,
+                    op_type: Some(Cow::Borrowed(op_type)),
+                    value: stats::parse_value(value)?,
+                    device_name: None,
+                });
+            }
+
+            if has_qos {
+                qos_devices.push(qos);
             }
         }
 
         let stats = BlkioStats {
             service_bytes,
             serviced,
+            psi: stats::psi_stats(reader, &cgroup_path.join(CGROUP_IO_PSI))?,
+            source: Some(BlkioSource::IoStat),
+            qos: Self::qos_stats(reader, cgroup_path, qos_devices)?,
             ..Default::default()
         };
 
@@ -79,15 +105,85 @@ impl StatsProvider for Io {
 }
 
 impl Io {
-    // Since the OCI spec is designed for cgroup v1, in some cases
-    // there is need to convert from the cgroup v1 configuration to cgroup v2
-    // the formula for BlkIOWeight to IOWeight is y = (1 + (x - 10) * 9999 / 990)
-    // convert linearly from [10-1000] to [1-10000]
-    fn convert_cfq_io_weight_to_bfq(v: u16) -> u16 {
-        if v == 0 {
-            return 0;
+    /// Sets the io.latency or io.cost statistic of an entry of io.stat and
+    /// returns false if the key belongs to neither of them
+    fn set_qos_stat(qos: &mut IoQosDeviceStats, key: &str, value: &str) -> Result<bool> {
+        let parse = || stats::parse_value(value).map(Some);
+        match key {
+            "avg_lat" => qos.avg_latency = parse()?,
+            "win" => qos.window = parse()?,
+            "delay_nsec" => qos.delay = parse()?,
+            "cost.vrate" => {
+                qos.cost_vrate = Some(value.parse().with_context(|| {
+                    format!("invalid value {} of {} in {}", value, key, CGROUP_IO_STAT)
+                })?)
+            }
+            "cost.usage" => qos.cost_usage = parse()?,
+            "cost.wait" => qos.cost_wait = parse()?,
+            "cost.indebt" => qos.cost_indebt = parse()?,
+            "cost.indelay" => qos.cost_indelay = parse()?,
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    /// Combines the statistics of io.stat with the latency targets of the
+    /// cgroup and the io.cost settings of the root cgroup
+    fn qos_stats(
+        reader: &dyn CgroupReader,
+        cgroup_path: &Path,
+        mut devices: Vec<IoQosDeviceStats>,
+    ) -> Result<Option<IoQosStats>> {
+        if let Some(content) = Self::read_optional(reader, &cgroup_path.join(CGROUP_IO_LATENCY))? {
+            for ((major, minor), settings) in parse_device_settings(&content, CGROUP_IO_LATENCY)? {
+                let target = match settings.iter().find(|(key, _)| *key == "target") {
+                    Some((_, target)) => stats::parse_value(target)?,
+                    None => continue,
+                };
+                match devices
+                    .iter_mut()
+                    .find(|device| device.major == major && device.minor == minor)
+                {
+                    Some(device) => device.latency_target = Some(target),
+                    None => devices.push(IoQosDeviceStats {
+                        major,
+                        minor,
+                        latency_target: Some(target),
+                        ..Default::default()
+                    }),
+                }
+            }
+        }
+
+        let root = Path::new(common::DEFAULT_CGROUP_ROOT);
+        let cost_qos = match Self::read_optional(reader, &root.join(CGROUP_IO_COST_QOS))? {
+            Some(content) => parse_cost_qos(&content)?,
+            None => Vec::new(),
+        };
+        let cost_model = match Self::read_optional(reader, &root.join(CGROUP_IO_COST_MODEL))? {
+            Some(content) => parse_cost_model(&content)?,
+            None => Vec::new(),
+        };
+
+        if devices.is_empty() && cost_qos.is_empty() && cost_model.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(IoQosStats {
+            devices,
+            cost_qos,
+            cost_model,
+        }))
+    }
+
+    /// Reads a file that only exists if the kernel supports the controller
+    fn read_optional(reader: &dyn CgroupReader, path: &Path) -> Result<Option<String>> {
+        match reader.read(path) {
+            Ok(content) => Ok(Some(content)),
+            Err(StatsError::MissingFile { .. }) => Ok(None),
+            Err(err) => Err(err.into()),
         }
-        1 + (v - 10) * 9999 / 990
     }
 
     fn io_max_path(path: &Path) -> PathBuf {
@@ -96,77 +192,78 @@ impl Io {
 
     // linux kernel doc: https://www.kernel.org/doc/html/latest/admin-guide/cgroup-v2.html#io
     fn apply(root_path: &Path, blkio: &LinuxBlockIo) -> Result<()> {
-        if let Some(weight_device) = blkio.weight_device() {
-            for wd in weight_device {
-                common::write_cgroup_file(
-                    root_path.join(CGROUP_BFQ_IO_WEIGHT),
-                    &format!("{}:{} {}", wd.major(), wd.minor(), wd.weight().unwrap()),
-                )?;
-            }
-        }
         if let Some(leaf_weight) = blkio.leaf_weight() {
             if leaf_weight > 0 {
                 bail!("cannot set leaf_weight with cgroupv2");
             }
         }
+
         if let Some(io_weight) = blkio.weight() {
-            // be aligned with what runc does
-            // See also: https://github.com/opencontainers/runc/blob/81044ad7c902f3fc153cb8ffadaf4da62855193f/libcontainer/cgroups/fs2/io.go#L57-L69
             if io_weight > 0 {
-                let cgroup_file = root_path.join(CGROUP_BFQ_IO_WEIGHT);
-                if cgroup_file.exists() {
-                    common::write_cgroup_file(cgroup_file, io_weight)?;
-                } else {
-                    common::write_cgroup_file(
-                        root_path.join(CGROUP_IO_WEIGHT),
-                        Self::convert_cfq_io_weight_to_bfq(io_weight),
-                    )?;
-                }
+                Self::set_weight(root_path, None, io_weight)?;
             }
         }
 
-        if let Some(throttle_read_bps_device) = blkio.throttle_read_bps_device() {
-            for trbd in throttle_read_bps_device {
-                common::write_cgroup_file(
-                    Self::io_max_path(root_path),
-                    &format!("{}:{} rbps={}", trbd.major(), trbd.minor(), trbd.rate()),
-                )?;
+        if let Some(weight_device) = blkio.weight_device() {
+            for wd in weight_device {
+                // the entry may only set the leaf weight, which does not exist on cgroup v2
+                if let Some(weight) = wd.weight() {
+                    Self::set_weight(root_path, Some((wd.major(), wd.minor())), weight)?;
+                }
             }
         }
 
-        if let Some(throttle_write_bps_device) = blkio.throttle_write_bps_device() {
-            for twbd in throttle_write_bps_device {
-                common::write_cgroup_file(
-                    Self::io_max_path(root_path),
-                    format!("{}:{} wbps={}", twbd.major(), twbd.minor(), twbd.rate()),
-                )?;
+        let throttles = [
+            ("rbps", blkio.throttle_read_bps_device()),
+            ("wbps", blkio.throttle_write_bps_device()),
+            ("riops", blkio.throttle_read_iops_device()),
+            ("wiops", blkio.throttle_write_iops_device()),
+        ];
+        for (key, devices) in throttles {
+            if let Some(devices) = devices {
+                for device in devices {
+                    // a rate of 0 removes the limit, as it does on cgroup v1
+                    let rate = match device.rate() {
+                        0 => "max".to_owned(),
+                        rate => rate.to_string(),
+                    };
+                    common::write_cgroup_file_str(
+                        Self::io_max_path(root_path),
+                        &format!("{}:{} {}={}", device.major(), device.minor(), key, rate),
+                    )?;
+                }
             }
         }
 
-        if let Some(throttle_read_iops_device) = blkio.throttle_read_iops_device() {
-            for trid in throttle_read_iops_device {
-                common::write_cgroup_file(
-                    Self::io_max_path(root_path),
-                    format!("{}:{} riops={}", trid.major(), trid.minor(), trid.rate()),
-                )?;
-            }
-        }
+        Ok(())
+    }
 
-        if let Some(throttle_write_iops_device) = blkio.throttle_write_iops_device() {
-            for twid in throttle_write_iops_device {
-                common::write_cgroup_file(
-                    Self::io_max_path(root_path),
-                    format!("{}:{} wiops={}", twid.major(), twid.minor(), twid.rate()),
-                )?;
-            }
-        }
+    // be aligned with what runc does: the bfq scheduler takes the blkio weights as they
+    // are, while the io.weight of the iocost controller has a range of 1 to 10000
+    // See also: https://github.com/opencontainers/runc/blob/81044ad7c902f3fc153cb8ffadaf4da62855193f/libcontainer/cgroups/fs2/io.go#L57-L69
+    fn set_weight(root_path: &Path, device: Option<(i64, i64)>, weight: u16) -> Result<()> {
+        let bfq_weight = root_path.join(CGROUP_BFQ_IO_WEIGHT);
+        let (path, weight) = if bfq_weight.exists() {
+            (bfq_weight, weight as u64)
+        } else {
+            (
+                root_path.join(CGROUP_IO_WEIGHT),
+                convert::blkio_weight_to_io_weight(weight),
+            )
+        };
 
-        Ok(())
+        let value = match device {
+            Some((major, minor)) => format!("{}:{} {}", major, minor, weight),
+            None => weight.to_string(),
+        };
+        common::write_cgroup_file_str(path, &value)
     }
 }
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::reader::FakeReader;
     use crate::test::{create_temp_dir, set_fixture, setup};
 
     use oci_spec::runtime::{
@@ -275,6 +372,52 @@ mod test {
         assert_eq!("8:0 80", content);
     }
 
+    #[test]
+    fn test_set_ioweight_device_iocost() {
+        let (tmp, weight_file) = setup("test_set_io_weight_device_iocost", CGROUP_IO_WEIGHT);
+        let blkio = LinuxBlockIoBuilder::default()
+            .weight_device(vec![
+                LinuxWeightDeviceBuilder::default()
+                    .major(8)
+                    .minor(0)
+                    .leaf_weight(0u16)
+                    .build()
+                    .unwrap(),
+                LinuxWeightDeviceBuilder::default()
+                    .major(8)
+                    .minor(16)
+                    .weight(500u16)
+                    .build()
+                    .unwrap(),
+            ])
+            .build()
+            .unwrap();
+
+        Io::apply(&tmp, &blkio).expect("apply blkio");
+        let content = fs::read_to_string(weight_file).expect("read io weight");
+
+        assert_eq!("8:16 4950", content);
+    }
+
+    #[test]
+    fn test_remove_io_limit() {
+        let (tmp, throttle) = setup("test_remove_io_limit", "io.max");
+        let blkio = LinuxBlockIoBuilder::default()
+            .throttle_read_bps_device(vec![LinuxThrottleDeviceBuilder::default()
+                .major(8)
+                .minor(0)
+                .rate(0u64)
+                .build()
+                .unwrap()])
+            .build()
+            .unwrap();
+
+        Io::apply(&tmp, &blkio).expect("apply blkio");
+        let content = fs::read_to_string(throttle).expect("read io.max");
+
+        assert_eq!("8:0 rbps=max", content);
+    }
+
     #[test]
     fn test_set_ioweight() {
         struct TestCase {
@@ -322,54 +465,63 @@ mod test {
                 BlkioDeviceStat {
                     major: 7,
                     minor: 9,
-                    op_type: Some("read".to_owned()),
+                    op_type: Some("read".into()),
                     value: 34629632,
+                    device_name: None,
                 },
                 BlkioDeviceStat {
                     major: 7,
                     minor: 9,
-                    op_type: Some("write".to_owned()),
+                    op_type: Some("write".into()),
                     value: 274965,
+                    device_name: None,
                 },
                 BlkioDeviceStat {
                     major: 7,
                     minor: 10,
-                    op_type: Some("read".to_owned()),
+                    op_type: Some("read".into()),
                     value: 18432,
+                    device_name: None,
                 },
                 BlkioDeviceStat {
                     major: 7,
                     minor: 10,
-                    op_type: Some("write".to_owned()),
+                    op_type: Some("write".into()),
                     value: 16842,
+                    device_name: None,
                 },
             ],
             serviced: vec![
                 BlkioDeviceStat {
                     major: 7,
                     minor: 9,
-                    op_type: Some("read".to_owned()),
+                    op_type: Some("read".into()),
                     value: 1066,
+                    device_name: None,
                 },
                 BlkioDeviceStat {
                     major: 7,
                     minor: 9,
-                    op_type: Some("write".to_owned()),
+                    op_type: Some("write".into()),
                     value: 319,
+                    device_name: None,
                 },
                 BlkioDeviceStat {
                     major: 7,
                     minor: 10,
-                    op_type: Some("read".to_owned()),
+                    op_type: Some("read".into()),
                     value: 12,
+                    device_name: None,
                 },
                 BlkioDeviceStat {
                     major: 7,
                     minor: 10,
-                    op_type: Some("write".to_owned()),
+                    op_type: Some("write".into()),
                     value: 0,
+                    device_name: None,
                 },
             ],
+            source: Some(BlkioSource::IoStat),
             ..Default::default()
         };
 
@@ -378,4 +530,77 @@ mod test {
 
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_stat_io_qos() -> Result<()> {
+        let reader = FakeReader::default()
+            .with_file(
+                "/sys/fs/cgroup/test/io.stat",
+                "8:0 rbytes=4096 wbytes=0 rios=1 wios=0 dbytes=0 dios=0 use_delay=0 delay_nsec=1200 avg_lat=850 win=100 cost.vrate=97.50 cost.usage=120 cost.wait=30 cost.indebt=0 cost.indelay=0\n\
+                 8:16 rbytes=0 wbytes=0 rios=0 wios=0 dbytes=0 dios=0\n",
+            )
+            .with_file("/sys/fs/cgroup/test/io.latency", "8:0 target=500\n8:16 target=2000\n")
+            .with_file(
+                "/sys/fs/cgroup/io.cost.qos",
+                "8:0 enable=1 ctrl=user rpct=95.00 rlat=5000 wpct=90.00 wlat=10000 min=50.00 max=150.00\n",
+            )
+            .with_file(
+                "/sys/fs/cgroup/io.cost.model",
+                "8:0 ctrl=auto model=linear rbps=174019176 rseqiops=41708 rrandiops=370 wbps=178075866 wseqiops=42705 wrandiops=378\n",
+            );
+
+        let qos = Io::stats_from(&reader, Path::new("/sys/fs/cgroup/test"))?
+            .qos
+            .expect("qos stats");
+        assert_eq!(
+            qos.devices,
+            vec![
+                IoQosDeviceStats {
+                    major: 8,
+                    minor: 0,
+                    latency_target: Some(500),
+                    avg_latency: Some(850),
+                    window: Some(100),
+                    delay: Some(1200),
+                    cost_vrate: Some(97.5),
+                    cost_usage: Some(120),
+                    cost_wait: Some(30),
+                    cost_indebt: Some(0),
+                    cost_indelay: Some(0),
+                },
+                IoQosDeviceStats {
+                    major: 8,
+                    minor: 16,
+                    latency_target: Some(2000),
+                    ..Default::default()
+                },
+            ]
+        );
+        assert_eq!(
+            qos.cost_qos,
+            vec![IoCostQos {
+                major: 8,
+                minor: 0,
+                enable: true,
+                ctrl: "user".to_owned(),
+                read_percentile: 95.0,
+                read_latency: 5000,
+                write_percentile: 90.0,
+                write_latency: 10000,
+                min: 50.0,
+                max: 150.0,
+            }]
+        );
+        assert_eq!(qos.cost_model[0].model, "linear");
+        assert_eq!(qos.cost_model[0].parameters["wrandiops"], 378);
+
+        let reader = FakeReader::default().with_file(
+            "/sys/fs/cgroup/test/io.stat",
+            "8:0 rbytes=4096 wbytes=0 rios=1 wios=0 dbytes=0 dios=0\n",
+        );
+        assert!(Io::stats_from(&reader, Path::new("/sys/fs/cgroup/test"))?
+            .qos
+            .is_none());
+        Ok(())
+    }
 }
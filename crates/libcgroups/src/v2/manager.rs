@@ -1,13 +1,14 @@
 use std::{
+    collections::HashMap,
     fs::{self},
     os::unix::fs::PermissionsExt,
     path::{Component::RootDir, Path, PathBuf},
     time::Duration,
 };
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 
-use nix::unistd::Pid;
+use nix::{sys::signal::Signal, unistd::Pid};
 
 #[cfg(feature = "cgroupsv2_devices")]
 use super::devices::Devices;
@@ -22,14 +23,21 @@ use super::{
     hugetlb::HugeTlb,
     io::Io,
     memory::Memory,
+    misc::Misc,
     pids::Pids,
+    rdma::Rdma,
+    threaded,
     unified::Unified,
     util::{self, CGROUP_SUBTREE_CONTROL},
 };
 use crate::{
     common::{self, CgroupManager, ControllerOpt, FreezerState, PathBufExt, CGROUP_PROCS},
-    stats::{Stats, StatsProvider},
+    extension,
+    stats::{Stats, StatsCollector, StatsError, StatsFilter, StatsProvider},
 };
+
+const CGROUP_KILL: &str = "cgroup.kill";
+
 pub struct Manager {
     root_path: PathBuf,
     cgroup_path: PathBuf,
@@ -50,12 +58,16 @@ impl Manager {
     }
 
     fn create_unified_cgroup(&self, pid: Pid) -> Result<()> {
-        let controllers: Vec<String> = util::get_available_controllers(&self.root_path)?
-            .iter()
-            .map(|c| format!("{}{}", "+", c.to_string()))
-            .collect();
+        self.create_cgroup_dirs()?;
+        common::write_cgroup_file(&self.full_path.join(CGROUP_PROCS), pid)?;
+        Ok(())
+    }
 
-        Self::write_controllers(&self.root_path, &controllers)?;
+    /// Creates the cgroup and its ancestors with the controllers enabled
+    fn create_cgroup_dirs(&self) -> Result<()> {
+        // a cgroup can only enable the controllers for its children that its
+        // parent has enabled for it, so they are enabled level by level
+        Self::enable_controllers(&self.root_path)?;
 
         let mut current_path = self.root_path.clone();
         let mut components = self
@@ -67,37 +79,70 @@ impl Manager {
             current_path = current_path.join(component);
             if !current_path.exists() {
                 fs::create_dir(&current_path)?;
-                fs::metadata(&current_path)?.permissions().set_mode(0o755);
+                fs::set_permissions(&current_path, fs::Permissions::from_mode(0o755))?;
             }
 
             // last component cannot have subtree_control enabled due to internal process constraint
             // if this were set, writing to the cgroups.procs file will fail with Erno 16 (device or resource busy)
             if components.peek().is_some() {
-                Self::write_controllers(&current_path, &controllers)?;
+                Self::enable_controllers(&current_path)?;
             }
         }
 
-        common::write_cgroup_file(&self.full_path.join(CGROUP_PROCS), pid)?;
         Ok(())
     }
 
-    fn write_controllers(path: &Path, controllers: &[String]) -> Result<()> {
-        for controller in controllers {
-            common::write_cgroup_file_str(path.join(CGROUP_SUBTREE_CONTROL), controller)?;
+    /// Enables the controllers that are available in the cgroup for its
+    /// children. Controllers that are enabled already are not written again,
+    /// so that cgroups that have been delegated with their controllers
+    /// enabled can be used without write access to their ancestors, which is
+    /// the case for rootless containers.
+    fn enable_controllers(path: &Path) -> Result<()> {
+        let subtree_control = path.join(CGROUP_SUBTREE_CONTROL);
+        let enabled = common::read_cgroup_file(&subtree_control)?;
+        let enabled: Vec<&str> = enabled.split_whitespace().collect();
+        for controller in util::get_available_controllers(path)? {
+            let controller = controller.to_string();
+            if enabled.contains(&controller.as_str()) {
+                continue;
+            }
+
+            common::write_cgroup_file_unbatched(&subtree_control, format!("+{}", controller))
+                .with_context(|| {
+                    format!(
+                        "failed to enable the {} controller for the children of {}",
+                        controller,
+                        path.display()
+                    )
+                })?;
         }
 
         Ok(())
     }
-}
 
-impl CgroupManager for Manager {
-    fn add_task(&self, pid: Pid) -> Result<()> {
-        self.create_unified_cgroup(pid)?;
-        Ok(())
-    }
+    /// Applies the resources of the given controllers only. On hybrid hosts
+    /// the remaining controllers are handled on the v1 hierarchies.
+    pub(crate) fn apply_controllers(
+        &self,
+        controller_opt: &ControllerOpt,
+        controllers: &[ControllerType],
+    ) -> Result<()> {
+        if controllers.contains(&ControllerType::Memory) {
+            Memory::validate(controller_opt.resources)?;
+        }
+
+        // the controllers that the parent has enabled for the cgroup, which are
+        // unknown if the cgroup has not been created, e.g. in dry runs
+        let available = util::get_available_controllers(&self.full_path).ok();
+        for controller in controllers {
+            if let Some(available) = &available {
+                if !available.contains(controller) {
+                    let usage = controller.usage(controller_opt.resources);
+                    common::skip_unavailable_controller(controller, usage)?;
+                    continue;
+                }
+            }
 
-    fn apply(&self, controller_opt: &ControllerOpt) -> Result<()> {
-        for controller in CONTROLLER_TYPES {
             match controller {
                 ControllerType::Cpu => Cpu::apply(controller_opt, &self.full_path)?,
                 ControllerType::CpuSet => CpuSet::apply(controller_opt, &self.full_path)?,
@@ -105,11 +150,43 @@ impl CgroupManager for Manager {
                 ControllerType::Io => Io::apply(controller_opt, &self.full_path)?,
                 ControllerType::Memory => Memory::apply(controller_opt, &self.full_path)?,
                 ControllerType::Pids => Pids::apply(controller_opt, &self.full_path)?,
+                ControllerType::Rdma => Rdma::apply(controller_opt, &self.full_path)?,
+                ControllerType::Misc => Misc::apply(controller_opt, &self.full_path)?,
             }
         }
 
+        Ok(())
+    }
+}
+
+impl CgroupManager for Manager {
+    fn add_task(&self, pid: Pid) -> Result<()> {
+        self.create_unified_cgroup(pid)?;
+        Ok(())
+    }
+
+    fn prepare_cgroup(&self) -> Result<Option<PathBuf>> {
+        self.create_cgroup_dirs()?;
+        Ok(Some(self.full_path.clone()))
+    }
+
+    fn apply(&self, controller_opt: &ControllerOpt) -> Result<()> {
+        Unified::validate(controller_opt.resources)?;
+        // the cgroup has to be threaded before the controllers are applied,
+        // as only the threaded controllers remain available
+        if threaded::is_threaded(controller_opt.resources) {
+            threaded::validate(controller_opt.resources)?;
+            threaded::enable(
+                &self.root_path,
+                &self.full_path,
+                &util::get_available_controllers(&self.root_path)?,
+            )?;
+        }
+
+        self.apply_controllers(controller_opt, CONTROLLER_TYPES)?;
+
         #[cfg(feature = "cgroupsv2_devices")]
-        Devices::apply(controller_opt, &self.cgroup_path)?;
+        Devices::apply(controller_opt, &self.full_path)?;
 
         for pseudoctlr in PSEUDO_CONTROLLER_TYPES {
             if let PseudoControllerType::Unified = pseudoctlr {
@@ -121,21 +198,49 @@ impl CgroupManager for Manager {
             }
         }
 
+        for extension in extension::extensions() {
+            extension
+                .apply(controller_opt, &self.full_path)
+                .with_context(|| {
+                    format!("failed to apply cgroup extension {}", extension.name())
+                })?;
+        }
+
         Ok(())
     }
 
     fn remove(&self) -> Result<()> {
         if self.full_path.exists() {
             log::debug!("remove cgroup {:?}", self.full_path);
-            let procs_path = self.full_path.join(CGROUP_PROCS);
-            let procs = fs::read_to_string(&procs_path)?;
+            let kill_path = self.full_path.join(CGROUP_KILL);
+            let is_threaded = threaded::is_threaded_cgroup(&self.full_path);
+            if is_threaded {
+                self.kill_all(Signal::SIGKILL)?;
+            } else if kill_path.exists() {
+                common::write_cgroup_file(kill_path, 1)?;
+            } else {
+                let procs_path = self.full_path.join(CGROUP_PROCS);
+                let procs = fs::read_to_string(&procs_path)?;
 
-            for line in procs.lines() {
-                let pid: i32 = line.parse()?;
-                let _ = nix::sys::signal::kill(Pid::from_raw(pid), nix::sys::signal::SIGKILL);
+                for line in procs.lines() {
+                    let pid: i32 = line.parse()?;
+                    let _ = nix::sys::signal::kill(Pid::from_raw(pid), Signal::SIGKILL);
+                }
             }
 
-            common::delete_with_retry(&self.full_path, 4, Duration::from_millis(100))?;
+            #[cfg(feature = "cgroupsv2_devices")]
+            Devices::detach(&self.full_path)
+                .unwrap_or_else(|err| log::warn!("failed to detach device filters: {:?}", err));
+
+            common::delete_with_retry(&self.full_path, 10, Duration::from_millis(100))?;
+            if is_threaded {
+                threaded::restore_parent(&self.root_path, &self.full_path).unwrap_or_else(|err| {
+                    log::warn!(
+                        "failed to restore the parent of the threaded cgroup: {:?}",
+                        err
+                    )
+                });
+            }
         }
 
         Ok(())
@@ -151,24 +256,302 @@ impl CgroupManager for Manager {
         Freezer::apply(&controller_opt, &self.full_path)
     }
 
-    fn stats(&self) -> Result<Stats> {
+    /// Kills all processes at once through cgroup.kill, which kernels
+    /// since 5.14 provide, including processes that are forked while they
+    /// are killed. Other signals and older kernels fall back to sending the
+    /// signal to each process of the frozen cgroup, as do threaded cgroups,
+    /// which do not support cgroup.kill.
+    fn kill_all(&self, signal: Signal) -> Result<()> {
+        let kill_path = self.full_path.join(CGROUP_KILL);
+        if signal == Signal::SIGKILL
+            && kill_path.exists()
+            && !threaded::is_threaded_cgroup(&self.full_path)
+        {
+            log::debug!("kill cgroup {:?}", self.full_path);
+            return common::write_cgroup_file(kill_path, 1);
+        }
+
+        common::signal_frozen(self, signal)
+    }
+
+    fn stats_with(&self, filter: &StatsFilter) -> Result<Stats> {
         let mut stats = Stats::default();
 
+        let path = &self.full_path;
+        let mut collector = StatsCollector::default();
+        let enabled = util::get_available_controllers(path).ok();
         for subsystem in CONTROLLER_TYPES {
+            let selected = match subsystem {
+                ControllerType::Cpu => filter.cpu,
+                ControllerType::CpuSet => filter.cpuset,
+                ControllerType::HugeTlb => filter.hugetlb,
+                ControllerType::Pids => filter.pids,
+                ControllerType::Memory => filter.memory,
+                ControllerType::Io => filter.blkio,
+                ControllerType::Rdma => filter.rdma,
+                ControllerType::Misc => filter.misc,
+            };
+            if !selected {
+                continue;
+            }
+
+            let is_enabled = enabled
+                .as_ref()
+                .map_or(true, |enabled| enabled.contains(subsystem));
             match subsystem {
-                ControllerType::Cpu => stats.cpu.usage = Cpu::stats(&self.full_path)?,
-                ControllerType::HugeTlb => stats.hugetlb = HugeTlb::stats(&self.full_path)?,
-                ControllerType::Pids => stats.pids = Pids::stats(&self.full_path)?,
-                ControllerType::Memory => stats.memory = Memory::stats(&self.full_path)?,
-                ControllerType::Io => stats.blkio = Io::stats(&self.full_path)?,
+                // cpu.stat is provided even if the cpu controller is not enabled
+                ControllerType::HugeTlb
+                | ControllerType::Pids
+                | ControllerType::Memory
+                | ControllerType::Io
+                    if !is_enabled =>
+                {
+                    let err = StatsError::UnsupportedController {
+                        controller: subsystem.to_string(),
+                    };
+                    stats.check_available::<(), _>(subsystem, Err(err.into()));
+                }
+                ControllerType::Cpu => {
+                    collector.collect(subsystem, path, Cpu::stats, |stats, cpu| stats.cpu = cpu)
+                }
+                // the cpuset controller is not enabled by default on many systems
+                ControllerType::CpuSet if path.join("cpuset.cpus.effective").exists() => collector
+                    .collect(subsystem, path, CpuSet::stats, |stats, cpuset| {
+                        stats.cpuset = Some(cpuset)
+                    }),
+                ControllerType::HugeTlb => {
+                    collector.collect(subsystem, path, HugeTlb::stats, |stats, hugetlb| {
+                        stats.hugetlb = hugetlb
+                    })
+                }
+                ControllerType::Pids => {
+                    collector.collect(subsystem, path, Pids::stats, |stats, pids| {
+                        stats.pids = pids
+                    })
+                }
+                ControllerType::Memory => {
+                    collector.collect(subsystem, path, Memory::stats, |stats, memory| {
+                        stats.memory = memory
+                    })
+                }
+                ControllerType::Io => {
+                    collector.collect(subsystem, path, Io::stats, |stats, blkio| {
+                        stats.blkio = blkio
+                    })
+                }
+                // most systems have no rdma devices and do not enable the controller
+                ControllerType::Rdma if path.join("rdma.current").exists() => {
+                    collector.collect(subsystem, path, Rdma::stats, |stats, rdma| {
+                        stats.rdma = Some(rdma)
+                    })
+                }
+                ControllerType::Misc if path.join("misc.current").exists() => {
+                    collector.collect(subsystem, path, Misc::stats, |stats, misc| {
+                        stats.misc = misc
+                    })
+                }
                 _ => continue,
             }
         }
+        if filter.freezer {
+            collector.collect(
+                PseudoControllerType::Freezer,
+                path,
+                Freezer::stats,
+                |stats, freezer| stats.freezer = Some(freezer),
+            );
+        }
+        collector.join(&mut stats);
+
+        if filter.extensions {
+            for extension in extension::extensions() {
+                extension::collect_stats(&mut stats, extension.as_ref(), &self.full_path);
+            }
+        }
 
         Ok(stats)
     }
 
     fn get_all_pids(&self) -> Result<Vec<Pid>> {
+        if threaded::is_threaded_cgroup(&self.full_path) {
+            return threaded::get_all_pids(&self.full_path);
+        }
+
         common::get_all_pids(&self.full_path)
     }
+
+    fn get_cgroup_paths(&self) -> HashMap<String, PathBuf> {
+        HashMap::from([("unified".to_owned(), self.full_path.clone())])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{create_temp_dir, set_fixture};
+    use crate::v2::util::CGROUP_CONTROLLERS;
+    use oci_spec::runtime::{LinuxHugepageLimitBuilder, LinuxPidsBuilder, LinuxResourcesBuilder};
+
+    #[test]
+    fn test_create_unified_cgroup() -> Result<()> {
+        let root = create_temp_dir("test_create_unified_cgroup")?;
+        // the controllers of the root have been enabled already, e.g. by systemd
+        set_fixture(&root, CGROUP_CONTROLLERS, "cpu memory")?;
+        set_fixture(&root, CGROUP_SUBTREE_CONTROL, "cpu memory")?;
+        let parent = root.join("youki");
+        fs::create_dir(&parent)?;
+        set_fixture(&parent, CGROUP_CONTROLLERS, "memory")?;
+        set_fixture(&parent, CGROUP_SUBTREE_CONTROL, "")?;
+        let container = parent.join("container");
+        fs::create_dir(&container)?;
+        set_fixture(&container, CGROUP_PROCS, "")?;
+
+        let manager = Manager::new(root.to_path_buf(), PathBuf::from("/youki/container"))?;
+        manager.create_unified_cgroup(Pid::from_raw(1000))?;
+
+        assert_eq!(
+            common::read_cgroup_file(root.join(CGROUP_SUBTREE_CONTROL))?,
+            "cpu memory"
+        );
+        assert_eq!(
+            common::read_cgroup_file(parent.join(CGROUP_SUBTREE_CONTROL))?,
+            "+memory"
+        );
+        assert_eq!(
+            common::read_cgroup_file(container.join(CGROUP_PROCS))?,
+            "1000"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_kill_all_through_cgroup_kill() -> Result<()> {
+        let root = create_temp_dir("test_kill_all_through_cgroup_kill")?;
+        let container = root.join("youki");
+        fs::create_dir(&container)?;
+        set_fixture(&container, CGROUP_KILL, "0")?;
+
+        let manager = Manager::new(root.to_path_buf(), PathBuf::from("/youki"))?;
+        manager.kill_all(Signal::SIGKILL)?;
+
+        assert_eq!(common::read_cgroup_file(container.join(CGROUP_KILL))?, "1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_kill_all_threaded_cgroup() -> Result<()> {
+        let root = create_temp_dir("test_kill_all_threaded_cgroup")?;
+        let container = root.join("youki");
+        fs::create_dir(&container)?;
+        set_fixture(&container, CGROUP_KILL, "0")?;
+        set_fixture(&container, threaded::CGROUP_TYPE, "threaded")?;
+        // the thread has exited, and cgroup.procs cannot be read
+        set_fixture(&container, "cgroup.threads", "999999999\n")?;
+
+        let manager = Manager::new(root.to_path_buf(), PathBuf::from("/youki"))?;
+        manager.kill_all(Signal::SIGKILL)?;
+        assert!(manager.get_all_pids()?.is_empty());
+
+        assert_eq!(common::read_cgroup_file(container.join(CGROUP_KILL))?, "0");
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_threaded_cgroup() -> Result<()> {
+        let root = create_temp_dir("test_remove_threaded_cgroup")?;
+        let container = root.join("youki");
+        fs::create_dir(&container)?;
+        set_fixture(&container, CGROUP_KILL, "0")?;
+        set_fixture(&container, threaded::CGROUP_TYPE, "threaded")?;
+        set_fixture(&container, "cgroup.threads", "999999999\n")?;
+
+        let manager = Manager::new(root.to_path_buf(), PathBuf::from("/youki"))?;
+        // unlike on cgroupfs, the directory cannot be removed with the fixtures
+        // in it, so the removal only fails after the processes have been killed
+        let err = manager.remove().unwrap_err();
+        assert!(
+            format!("{:?}", err).contains("could not delete"),
+            "{:?}",
+            err
+        );
+
+        assert_eq!(common::read_cgroup_file(container.join(CGROUP_KILL))?, "0");
+        Ok(())
+    }
+
+    #[test]
+    fn test_prepare_cgroup() -> Result<()> {
+        let root = create_temp_dir("test_prepare_cgroup")?;
+        set_fixture(&root, CGROUP_CONTROLLERS, "")?;
+        set_fixture(&root, CGROUP_SUBTREE_CONTROL, "")?;
+
+        let manager = Manager::new(root.to_path_buf(), PathBuf::from("/youki"))?;
+        let path = manager.prepare_cgroup()?;
+
+        assert_eq!(path, Some(root.join("youki")));
+        assert!(root.join("youki").is_dir());
+        assert!(!root.join("youki").join(CGROUP_PROCS).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_kill_all_keeps_frozen_cgroup_frozen() -> Result<()> {
+        let root = create_temp_dir("test_kill_all_keeps_frozen_cgroup_frozen")?;
+        let container = root.join("youki");
+        fs::create_dir(&container)?;
+        set_fixture(&container, "cgroup.freeze", "1")?;
+        set_fixture(&container, "cgroup.events", "populated 0\nfrozen 1\n")?;
+        set_fixture(&container, CGROUP_PROCS, "")?;
+
+        let manager = Manager::new(root.to_path_buf(), PathBuf::from("/youki"))?;
+        manager.kill_all(Signal::SIGTERM)?;
+
+        assert_eq!(
+            common::read_cgroup_file(container.join("cgroup.freeze"))?,
+            "1"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_skips_unavailable_controllers() -> Result<()> {
+        let root = create_temp_dir("test_apply_skips_unavailable_controllers")?;
+        let container = root.join("youki");
+        fs::create_dir(&container)?;
+        set_fixture(&container, CGROUP_CONTROLLERS, "pids")?;
+        set_fixture(&container, "pids.max", "")?;
+        let manager = Manager::new(root.to_path_buf(), PathBuf::from("/youki"))?;
+
+        // an empty list of hugepage limits does not require the hugetlb controller
+        let resources = LinuxResourcesBuilder::default()
+            .hugepage_limits(vec![])
+            .pids(LinuxPidsBuilder::default().limit(10).build().unwrap())
+            .build()
+            .unwrap();
+        let controller_opt = ControllerOpt {
+            resources: &resources,
+            freezer_state: None,
+            oom_score_adj: None,
+            disable_oom_killer: false,
+        };
+        manager.apply_controllers(&controller_opt, CONTROLLER_TYPES)?;
+        assert_eq!(common::read_cgroup_file(container.join("pids.max"))?, "10");
+
+        let resources = LinuxResourcesBuilder::default()
+            .hugepage_limits(vec![LinuxHugepageLimitBuilder::default()
+                .page_size("2MB")
+                .limit(16384)
+                .build()
+                .unwrap()])
+            .build()
+            .unwrap();
+        let controller_opt = ControllerOpt {
+            resources: &resources,
+            ..controller_opt
+        };
+        assert!(manager
+            .apply_controllers(&controller_opt, CONTROLLER_TYPES)
+            .is_err());
+        Ok(())
+    }
 }
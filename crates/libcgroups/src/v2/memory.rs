@@ -1,11 +1,12 @@
 use anyhow::{bail, Context, Result};
 use std::path::Path;
 
-use oci_spec::runtime::LinuxMemory;
+use oci_spec::runtime::{LinuxMemory, LinuxResources};
 
 use crate::{
     common::{self, ControllerOpt},
-    stats::{self, MemoryData, MemoryStats, StatsProvider},
+    reader::CgroupReader,
+    stats::{self, Bytes, MemoryData, MemoryStats, StatsProvider},
 };
 
 use super::controller::Controller;
@@ -13,7 +14,13 @@ use super::controller::Controller;
 const CGROUP_MEMORY_SWAP: &str = "memory.swap.max";
 const CGROUP_MEMORY_MAX: &str = "memory.max";
 const CGROUP_MEMORY_LOW: &str = "memory.low";
+const CGROUP_MEMORY_MIN: &str = "memory.min";
+const CGROUP_MEMORY_HIGH: &str = "memory.high";
 const MEMORY_STAT: &str = "memory.stat";
+const MEMORY_SWAP_CURRENT: &str = "memory.swap.current";
+const MEMORY_PSI: &str = "memory.pressure";
+const MEMORY_NUMA_STAT: &str = "memory.numa_stat";
+const MEMORY_EVENTS: &str = "memory.events";
 
 pub struct Memory {}
 
@@ -31,12 +38,26 @@ impl Controller for Memory {
 impl StatsProvider for Memory {
     type Stats = MemoryStats;
 
-    fn stats(cgroup_path: &Path) -> Result<Self::Stats> {
+    fn stats_from(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<Self::Stats> {
+        // the swap files do not exist if swap accounting is disabled
+        let memswap = if reader.exists(&cgroup_path.join(MEMORY_SWAP_CURRENT)) {
+            Self::get_memory_data(reader, cgroup_path, "memory.swap", "fail")?
+        } else {
+            MemoryData::default()
+        };
+
+        let raw = reader.parse_flat_keyed_data(&cgroup_path.join(MEMORY_STAT))?;
+        let events = reader.parse_flat_keyed_data(&cgroup_path.join(MEMORY_EVENTS))?;
         let stats = MemoryStats {
-            memory: Self::get_memory_data(cgroup_path, "memory", "oom")?,
-            memswap: Self::get_memory_data(cgroup_path, "memory.swap", "fail")?,
+            memory: Self::get_memory_data(reader, cgroup_path, "memory", "oom")?,
+            memswap,
+            // memory.stat reports the page cache as file backed memory
+            cache: Bytes(raw.get("file").copied().unwrap_or_default()),
             hierarchy: true,
-            stats: stats::parse_flat_keyed_data(&cgroup_path.join(MEMORY_STAT))?,
+            stats: raw,
+            events: (&events).into(),
+            psi: stats::psi_stats(reader, &cgroup_path.join(MEMORY_PSI))?,
+            numa: stats::numa_stats(reader, &cgroup_path.join(MEMORY_NUMA_STAT), 1)?,
             ..Default::default()
         };
 
@@ -45,19 +66,95 @@ impl StatsProvider for Memory {
 }
 
 impl Memory {
+    /// Checks that the memory protections and limits, which are set by the
+    /// reservation and limit of the spec or by the unified resources, are
+    /// ordered as memory.min <= memory.low <= memory.high <= memory.max. The
+    /// kernel accepts them in any order, but e.g. a protection above the limit
+    /// does not protect more memory than the limit allows. A kernel TCP memory
+    /// limit is rejected, as cgroup v2 does not have one.
+    pub fn validate(resources: &LinuxResources) -> Result<()> {
+        let memory = resources.memory().as_ref();
+        // cgroup v2 accounts socket buffers to the memory of the cgroup, so
+        // they are bounded by memory.max and there is no separate limit
+        if let Some(kernel_tcp) = memory
+            .and_then(|memory| memory.kernel_tcp())
+            .filter(|kernel_tcp| *kernel_tcp != 0)
+        {
+            bail!(
+                "kernel TCP memory limit ({}) is not supported on cgroup v2, use the memory limit instead",
+                kernel_tcp
+            );
+        }
+
+        let levels = [
+            (CGROUP_MEMORY_MIN, None),
+            (
+                CGROUP_MEMORY_LOW,
+                memory.and_then(|memory| memory.reservation()),
+            ),
+            (CGROUP_MEMORY_HIGH, None),
+            (CGROUP_MEMORY_MAX, memory.and_then(|memory| memory.limit())),
+        ];
+
+        let mut values = Vec::with_capacity(levels.len());
+        for (file, structured) in levels {
+            let unified = resources
+                .unified()
+                .as_ref()
+                .and_then(|unified| unified.get(file));
+            let value = match (unified, structured) {
+                // memory.high of max only disables throttling, which is the default
+                (Some(value), _) if value.trim() == "max" && file == CGROUP_MEMORY_HIGH => continue,
+                (Some(value), _) if value.trim() == "max" => u64::MAX,
+                (Some(value), _) => value
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("invalid value {} for {}", value, file))?,
+                (None, Some(-1)) => u64::MAX,
+                // 0 is not written and other negative values are rejected when applied
+                (None, Some(value)) if value > 0 => value as u64,
+                _ => continue,
+            };
+            values.push((file, value));
+        }
+
+        for (i, (lower_file, lower)) in values.iter().enumerate() {
+            for (upper_file, upper) in &values[i + 1..] {
+                if lower > upper {
+                    bail!(
+                        "{} ({}) must not be larger than {} ({})",
+                        lower_file,
+                        lower,
+                        upper_file,
+                        upper
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     fn get_memory_data(
+        reader: &dyn CgroupReader,
         cgroup_path: &Path,
         file_prefix: &str,
         fail_event: &str,
     ) -> Result<MemoryData> {
-        let usage =
-            stats::parse_single_value(&cgroup_path.join(format!("{}.{}", file_prefix, "current")))?;
+        let usage = reader
+            .parse_single_value(&cgroup_path.join(format!("{}.{}", file_prefix, "current")))?;
         let limit =
-            stats::parse_single_value(&cgroup_path.join(format!("{}.{}", file_prefix, "max")))?;
+            reader.parse_single_value(&cgroup_path.join(format!("{}.{}", file_prefix, "max")))?;
+        // the peak usage is only reported by kernels since 5.19
+        let peak = cgroup_path.join(format!("{}.{}", file_prefix, "peak"));
+        let max_usage = if reader.exists(&peak) {
+            reader.parse_single_value(&peak)?
+        } else {
+            0
+        };
 
-        let events = stats::parse_flat_keyed_data(
-            &cgroup_path.join(format!("{}.{}", file_prefix, "events")),
-        )?;
+        let events = reader
+            .parse_flat_keyed_data(&cgroup_path.join(format!("{}.{}", file_prefix, "events")))?;
         let fail_count = if let Some((_, v)) = events.get_key_value(fail_event) {
             *v
         } else {
@@ -65,10 +162,10 @@ impl Memory {
         };
 
         Ok(MemoryData {
-            usage,
+            usage: Bytes(usage),
+            max_usage: Bytes(max_usage),
             fail_count,
-            limit,
-            ..Default::default()
+            limit: Bytes(limit),
         })
     }
 
@@ -145,10 +242,65 @@ impl Memory {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::reader::FakeReader;
+    use crate::stats::MemoryEvents;
     use crate::test::{create_temp_dir, set_fixture};
-    use oci_spec::runtime::LinuxMemoryBuilder;
+    use oci_spec::runtime::{LinuxMemoryBuilder, LinuxResourcesBuilder};
+    use std::collections::HashMap;
     use std::fs::read_to_string;
 
+    #[test]
+    fn test_validate_kernel_tcp() {
+        let resources = |kernel_tcp: i64| {
+            LinuxResourcesBuilder::default()
+                .memory(
+                    LinuxMemoryBuilder::default()
+                        .kernel_tcp(kernel_tcp)
+                        .build()
+                        .unwrap(),
+                )
+                .build()
+                .unwrap()
+        };
+
+        assert!(Memory::validate(&resources(0)).is_ok());
+        let err = Memory::validate(&resources(1024 * 1024)).unwrap_err();
+        assert!(err.to_string().contains("not supported on cgroup v2"));
+    }
+
+    #[test]
+    fn test_validate_memory_protection() {
+        let resources = |reservation: i64, unified: &[(&str, &str)]| {
+            LinuxResourcesBuilder::default()
+                .memory(
+                    LinuxMemoryBuilder::default()
+                        .limit(4096i64)
+                        .reservation(reservation)
+                        .build()
+                        .unwrap(),
+                )
+                .unified(
+                    unified
+                        .iter()
+                        .map(|(file, value)| (file.to_string(), value.to_string()))
+                        .collect::<HashMap<_, _>>(),
+                )
+                .build()
+                .unwrap()
+        };
+
+        let ordered = resources(2048, &[("memory.min", "1024"), ("memory.high", "3072")]);
+        assert!(Memory::validate(&ordered).is_ok());
+        let unlimited_high = resources(2048, &[("memory.high", "max")]);
+        assert!(Memory::validate(&unlimited_high).is_ok());
+        let high_above_max = resources(2048, &[("memory.high", "8192")]);
+        assert!(Memory::validate(&high_above_max).is_err());
+        let min_above_low = resources(1024, &[("memory.min", "2048")]);
+        assert!(Memory::validate(&min_above_low).is_err());
+        let invalid = resources(1024, &[("memory.high", "a lot")]);
+        assert!(Memory::validate(&invalid).is_err());
+    }
+
     #[test]
     fn test_set_memory() {
         let tmp = create_temp_dir("test_set_memory_v2").expect("create temp directory for test");
@@ -332,20 +484,53 @@ mod tests {
 
     #[test]
     fn test_get_memory_data() {
-        let tmp = create_temp_dir("test_stat_memory").expect("create test directory");
-        set_fixture(&tmp, "memory.current", "12500\n").unwrap();
-        set_fixture(&tmp, "memory.max", "25000\n").unwrap();
-        let events = ["slab 5", "anon 13", "oom 3"].join("\n");
-        set_fixture(&tmp, "memory.events", &events).unwrap();
+        let reader = FakeReader::default()
+            .with_file("/test/memory.current", "12500\n")
+            .with_file("/test/memory.max", "25000\n")
+            .with_file("/test/memory.events", "slab 5\nanon 13\noom 3");
 
-        let actual = Memory::get_memory_data(&tmp, "memory", "oom").expect("get cgroup stats");
+        let actual = Memory::get_memory_data(&reader, Path::new("/test"), "memory", "oom")
+            .expect("get cgroup stats");
         let expected = MemoryData {
-            usage: 12500,
-            limit: 25000,
+            usage: Bytes(12500),
+            limit: Bytes(25000),
             fail_count: 3,
             ..Default::default()
         };
 
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_stats_without_swap_accounting() {
+        let tmp = create_temp_dir("test_stats_without_swap").expect("create test directory");
+        set_fixture(&tmp, "memory.current", "12500\n").unwrap();
+        set_fixture(&tmp, "memory.max", "max\n").unwrap();
+        set_fixture(&tmp, "memory.peak", "20000\n").unwrap();
+        set_fixture(
+            &tmp,
+            "memory.events",
+            "low 0\nhigh 12\nmax 4\noom 1\noom_kill 1\n",
+        )
+        .unwrap();
+        set_fixture(&tmp, MEMORY_STAT, "anon 4096\nfile 8192\n").unwrap();
+
+        let actual = Memory::stats(&tmp).expect("get cgroup stats");
+        assert_eq!(actual.memory.usage, Bytes(12500));
+        assert_eq!(actual.memory.max_usage, Bytes(20000));
+        assert_eq!(actual.memory.limit, Bytes(u64::MAX));
+        assert_eq!(actual.memswap, MemoryData::default());
+        assert_eq!(actual.cache, Bytes(8192));
+        assert_eq!(actual.stats.get("anon"), Some(&4096));
+        assert_eq!(
+            actual.events,
+            MemoryEvents {
+                high: 12,
+                max: 4,
+                oom: 1,
+                oom_kill: 1,
+                ..Default::default()
+            }
+        );
+    }
 }
@@ -0,0 +1,102 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context, Result};
+
+use super::controller::Controller;
+use crate::{
+    common::ControllerOpt,
+    reader::CgroupReader,
+    stats::{self, MiscStats, StatsProvider},
+};
+
+const MISC_CURRENT: &str = "misc.current";
+const MISC_MAX: &str = "misc.max";
+const MISC_EVENTS: &str = "misc.events";
+
+/// The misc controller accounts scalar resources that do not fit into the
+/// other controllers, e.g. the address space ids of AMD SEV and SEV-ES
+/// encrypted virtual machines
+pub struct Misc {}
+
+impl Controller for Misc {
+    fn apply(_controller_opt: &ControllerOpt, _cgroup_path: &Path) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl StatsProvider for Misc {
+    type Stats = HashMap<String, MiscStats>;
+
+    fn stats_from(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<Self::Stats> {
+        let mut misc_stats: HashMap<String, MiscStats> = HashMap::new();
+        for (resource, usage) in Self::read_entries(reader, cgroup_path, MISC_CURRENT)? {
+            misc_stats.entry(resource).or_default().usage = usage;
+        }
+        for (resource, limit) in Self::read_entries(reader, cgroup_path, MISC_MAX)? {
+            misc_stats.entry(resource).or_default().limit = limit;
+        }
+
+        // misc.events is only available since kernel 5.15
+        if reader.exists(&cgroup_path.join(MISC_EVENTS)) {
+            for (event, count) in Self::read_entries(reader, cgroup_path, MISC_EVENTS)? {
+                if let Some(resource) = event.strip_suffix(".max") {
+                    misc_stats
+                        .entry(resource.to_owned())
+                        .or_default()
+                        .fail_count = count;
+                }
+            }
+        }
+
+        Ok(misc_stats)
+    }
+}
+
+impl Misc {
+    /// Reads the flat keyed entries of a misc file, in which a value of max
+    /// means that there is no limit
+    fn read_entries(
+        reader: &dyn CgroupReader,
+        cgroup_path: &Path,
+        file: &str,
+    ) -> Result<Vec<(String, u64)>> {
+        let content = reader.read(&cgroup_path.join(file))?;
+        content
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .map(|(key, value)| {
+                let value = match value.trim() {
+                    "max" => u64::MAX,
+                    value => stats::parse_value(value)
+                        .with_context(|| format!("failed to parse {} in {}", key, file))?,
+                };
+                Ok((key.to_owned(), value))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{create_temp_dir, set_fixture};
+
+    #[test]
+    fn test_stat_misc() {
+        let tmp = create_temp_dir("test_stat_misc").expect("create temp directory for test");
+        set_fixture(&tmp, MISC_CURRENT, "sev 3\nsev_es 0\n").unwrap();
+        set_fixture(&tmp, MISC_MAX, "sev 50\nsev_es max\n").unwrap();
+        set_fixture(&tmp, MISC_EVENTS, "sev.max 2\nsev_es.max 0\n").unwrap();
+
+        let actual = Misc::stats(&tmp).expect("get cgroup stats");
+        assert_eq!(
+            actual["sev"],
+            MiscStats {
+                usage: 3,
+                limit: 50,
+                fail_count: 2,
+            }
+        );
+        assert_eq!(actual["sev_es"].limit, u64::MAX);
+    }
+}
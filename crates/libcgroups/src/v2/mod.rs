@@ -9,6 +9,9 @@ mod hugetlb;
 mod io;
 pub mod manager;
 mod memory;
+mod misc;
 mod pids;
+mod rdma;
+mod threaded;
 mod unified;
 pub mod util;
@@ -4,6 +4,7 @@ use anyhow::{Context, Result};
 
 use crate::{
     common::{self, ControllerOpt},
+    reader::CgroupReader,
     stats::{self, PidStats, StatsProvider},
 };
 
@@ -25,8 +26,8 @@ impl Controller for Pids {
 impl StatsProvider for Pids {
     type Stats = PidStats;
 
-    fn stats(cgroup_path: &Path) -> Result<Self::Stats> {
-        stats::pid_stats(cgroup_path)
+    fn stats_from(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<Self::Stats> {
+        stats::pid_stats(reader, cgroup_path)
     }
 }
 
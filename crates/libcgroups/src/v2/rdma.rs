@@ -0,0 +1,32 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use super::controller::Controller;
+use crate::{
+    common::{self, ControllerOpt},
+    reader::CgroupReader,
+    stats::{self, RdmaStats, StatsProvider},
+};
+
+pub struct Rdma {}
+
+impl Controller for Rdma {
+    fn apply(controller_opt: &ControllerOpt, cgroup_path: &Path) -> Result<()> {
+        if let Some(rdma) = controller_opt.resources.rdma() {
+            log::debug!("Apply rdma cgroup config");
+            common::write_rdma_limits(cgroup_path, rdma)
+                .context("failed to apply rdma resource restrictions")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl StatsProvider for Rdma {
+    type Stats = RdmaStats;
+
+    fn stats_from(reader: &dyn CgroupReader, cgroup_path: &Path) -> Result<Self::Stats> {
+        stats::rdma_stats(reader, cgroup_path)
+    }
+}
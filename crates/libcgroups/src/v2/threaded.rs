@@ -0,0 +1,293 @@
+//! Threaded mode allows processes to distribute their threads over the
+//! cgroups of a threaded subtree, e.g. to pin the polling threads of a DPDK
+//! application with cpuset. It is requested by setting `cgroup.type` to
+//! `threaded` in the unified resources of the spec. Only the threaded
+//! controllers are available in a threaded subtree, so resources of the other
+//! controllers are rejected.
+//!
+//! A threaded cgroup supports neither `cgroup.kill` nor reading
+//! `cgroup.procs`, both fail with EOPNOTSUPP. Its processes are found through
+//! `cgroup.threads` instead.
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use nix::unistd::Pid;
+use oci_spec::runtime::LinuxResources;
+
+use super::{
+    controller_type::ControllerType,
+    util::{self, CGROUP_SUBTREE_CONTROL},
+};
+use crate::common;
+
+pub const CGROUP_TYPE: &str = "cgroup.type";
+const CGROUP_THREADS: &str = "cgroup.threads";
+const THREADED: &str = "threaded";
+
+/// Returns true if the resources request a threaded cgroup
+pub fn is_threaded(resources: &LinuxResources) -> bool {
+    resources
+        .unified()
+        .as_ref()
+        .and_then(|unified| unified.get(CGROUP_TYPE))
+        .map_or(false, |cgroup_type| cgroup_type.trim() == THREADED)
+}
+
+/// Returns true if the cgroup is a threaded cgroup
+pub fn is_threaded_cgroup(cgroup_path: &Path) -> bool {
+    fs::read_to_string(cgroup_path.join(CGROUP_TYPE))
+        .map_or(false, |cgroup_type| cgroup_type.trim() == THREADED)
+}
+
+/// Checks that the resources only restrict threaded controllers
+pub fn validate(resources: &LinuxResources) -> Result<()> {
+    let mut domain_resources = Vec::new();
+    if resources.memory().is_some() {
+        domain_resources.push("memory".to_owned());
+    }
+    if resources.block_io().is_some() {
+        domain_resources.push("blockIO".to_owned());
+    }
+    if resources
+        .hugepage_limits()
+        .as_ref()
+        .map_or(false, |limits| !limits.is_empty())
+    {
+        domain_resources.push("hugepageLimits".to_owned());
+    }
+    if let Some(unified) = resources.unified() {
+        let mut keys: Vec<&String> = unified
+            .keys()
+            .filter(|key| {
+                let controller = key.split_once('.').map_or(key.as_str(), |(c, _)| c);
+                !matches!(controller, "cgroup" | "cpu" | "cpuset" | "pids")
+            })
+            .collect();
+        keys.sort();
+        domain_resources.extend(keys.into_iter().cloned());
+    }
+
+    if !domain_resources.is_empty() {
+        bail!(
+            "a threaded cgroup only supports the cpu, cpuset and pids controllers, but {} are set",
+            domain_resources.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// Turns the cgroup into a threaded cgroup. Its parent becomes the root of
+/// the threaded subtree, which requires that no domain controllers are
+/// enabled for its children. They are disabled if the cgroup is the only
+/// child of its parent, so that other cgroups are never affected.
+pub fn enable(root_path: &Path, cgroup_path: &Path, available: &[ControllerType]) -> Result<()> {
+    let parent = cgroup_path
+        .parent()
+        .with_context(|| format!("{:?} has no parent cgroup", cgroup_path))?;
+
+    // the root cgroup can always be the root of a threaded subtree
+    if parent != root_path {
+        let domain_controllers: Vec<String> = available
+            .iter()
+            .filter(|controller| !controller.is_threaded())
+            .map(|controller| format!("-{}", controller))
+            .collect();
+        if !domain_controllers.is_empty() {
+            let children = fs::read_dir(parent)?
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_dir())
+                .count();
+            if children > 1 {
+                bail!(
+                    "cannot make {:?} threaded, because its parent {:?} has other child cgroups that may use the domain controllers",
+                    cgroup_path,
+                    parent
+                );
+            }
+
+            for controller in domain_controllers {
+                common::write_cgroup_file_unbatched(
+                    parent.join(CGROUP_SUBTREE_CONTROL),
+                    &controller,
+                )?;
+            }
+        }
+    }
+
+    common::write_cgroup_file_unbatched(cgroup_path.join(CGROUP_TYPE), THREADED)
+        .with_context(|| format!("failed to make {:?} threaded", cgroup_path))
+}
+
+/// Enables the domain controllers of the parent for its children again after
+/// the threaded cgroup has been removed, unless the parent is the root or
+/// has other children that may still be threaded
+pub fn restore_parent(root_path: &Path, cgroup_path: &Path) -> Result<()> {
+    let parent = match cgroup_path.parent() {
+        Some(parent) if parent != root_path && parent.exists() => parent,
+        _ => return Ok(()),
+    };
+    let has_children = fs::read_dir(parent)?
+        .filter_map(|entry| entry.ok())
+        .any(|entry| entry.path().is_dir());
+    if has_children {
+        return Ok(());
+    }
+
+    let enabled = common::read_cgroup_file(parent.join(CGROUP_SUBTREE_CONTROL))?;
+    let enabled: Vec<&str> = enabled.split_whitespace().collect();
+    for controller in util::get_available_controllers(parent)? {
+        let controller = controller.to_string();
+        if enabled.contains(&controller.as_str()) {
+            continue;
+        }
+
+        common::write_cgroup_file_unbatched(
+            parent.join(CGROUP_SUBTREE_CONTROL),
+            format!("+{}", controller),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Returns the processes that have threads in the threaded cgroup or its
+/// descendants
+pub fn get_all_pids(cgroup_path: &Path) -> Result<Vec<Pid>> {
+    let mut pids = Vec::new();
+    collect_pids(cgroup_path, &mut pids)?;
+    Ok(pids)
+}
+
+fn collect_pids(cgroup_path: &Path, pids: &mut Vec<Pid>) -> Result<()> {
+    let threads = common::read_cgroup_file(cgroup_path.join(CGROUP_THREADS))?;
+    for tid in threads.lines() {
+        let tid: i32 = tid
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid thread id {:?} in {:?}", tid, cgroup_path))?;
+        // the thread may have exited in between
+        let tgid = match procfs::process::Process::new(tid).and_then(|p| p.status()) {
+            Ok(status) => Pid::from_raw(status.tgid),
+            Err(_) => continue,
+        };
+        if !pids.contains(&tgid) {
+            pids.push(tgid);
+        }
+    }
+
+    for entry in fs::read_dir(cgroup_path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            collect_pids(&entry.path(), pids)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use oci_spec::runtime::{LinuxMemoryBuilder, LinuxResourcesBuilder};
+
+    use super::*;
+    use crate::test::{create_temp_dir, set_fixture};
+    use crate::v2::util::CGROUP_CONTROLLERS;
+
+    fn unified(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate() -> Result<()> {
+        let resources = LinuxResourcesBuilder::default()
+            .unified(unified(&[(CGROUP_TYPE, THREADED), ("cpu.weight", "50")]))
+            .build()?;
+        assert!(is_threaded(&resources));
+        assert!(validate(&resources).is_ok());
+
+        let resources = LinuxResourcesBuilder::default()
+            .memory(LinuxMemoryBuilder::default().limit(1024).build()?)
+            .unified(unified(&[(CGROUP_TYPE, THREADED), ("io.weight", "50")]))
+            .build()?;
+        let err = validate(&resources).unwrap_err().to_string();
+        assert!(err.contains("memory, io.weight"), "{}", err);
+
+        assert!(!is_threaded(&LinuxResources::default()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_enable() -> Result<()> {
+        let tmp = create_temp_dir("test_enable_threaded")?;
+        let parent = tmp.join("parent");
+        let cgroup = parent.join("container");
+        fs::create_dir_all(&cgroup)?;
+        set_fixture(&parent, CGROUP_SUBTREE_CONTROL, "")?;
+        set_fixture(&cgroup, CGROUP_TYPE, "")?;
+
+        enable(
+            &tmp,
+            &cgroup,
+            &[ControllerType::Cpu, ControllerType::Memory],
+        )?;
+        assert_eq!(
+            common::read_cgroup_file(parent.join(CGROUP_SUBTREE_CONTROL))?,
+            "-memory"
+        );
+        assert_eq!(
+            common::read_cgroup_file(cgroup.join(CGROUP_TYPE))?,
+            THREADED
+        );
+
+        // other children of the parent must keep their controllers
+        fs::create_dir(parent.join("sibling"))?;
+        assert!(enable(&tmp, &cgroup, &[ControllerType::Memory]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_parent() -> Result<()> {
+        let tmp = create_temp_dir("test_restore_parent")?;
+        let parent = tmp.join("parent");
+        fs::create_dir(&parent)?;
+        set_fixture(&parent, CGROUP_CONTROLLERS, "cpu memory")?;
+        set_fixture(&parent, CGROUP_SUBTREE_CONTROL, "cpu")?;
+
+        // the threaded cgroup has been removed
+        restore_parent(&tmp, &parent.join("container"))?;
+        assert_eq!(
+            common::read_cgroup_file(parent.join(CGROUP_SUBTREE_CONTROL))?,
+            "+memory"
+        );
+
+        // a sibling may still be threaded
+        set_fixture(&parent, CGROUP_SUBTREE_CONTROL, "cpu")?;
+        fs::create_dir(parent.join("sibling"))?;
+        restore_parent(&tmp, &parent.join("container"))?;
+        assert_eq!(
+            common::read_cgroup_file(parent.join(CGROUP_SUBTREE_CONTROL))?,
+            "cpu"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_all_pids() -> Result<()> {
+        let tmp = create_temp_dir("test_get_all_pids_threaded")?;
+        let pid = std::process::id() as i32;
+        // the second thread has exited
+        set_fixture(&tmp, CGROUP_THREADS, &format!("{}\n999999999\n", pid))?;
+        let child = tmp.join("worker");
+        fs::create_dir(&child)?;
+        set_fixture(&child, CGROUP_THREADS, &format!("{}\n", pid))?;
+
+        assert_eq!(get_all_pids(&tmp)?, vec![Pid::from_raw(pid)]);
+        Ok(())
+    }
+}
@@ -1,8 +1,9 @@
 use std::{collections::HashMap, path::Path};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use oci_spec::runtime::LinuxResources;
 
-use super::controller_type::ControllerType;
+use super::{controller_type::ControllerType, threaded};
 use crate::common::{self, ControllerOpt};
 
 pub struct Unified {}
@@ -21,6 +22,37 @@ impl Unified {
         Ok(())
     }
 
+    /// Checks the unified resources before any resource is applied. The keys
+    /// have to be interface files of the cgroup itself, and must not be files
+    /// that the structured resources of the spec set as well, as it would be
+    /// ambiguous which of the two values is meant.
+    pub fn validate(resources: &LinuxResources) -> Result<()> {
+        let unified = match resources.unified() {
+            Some(unified) => unified,
+            None => return Ok(()),
+        };
+
+        let structured = structured_files(resources);
+        for cgroup_file in unified.keys() {
+            if cgroup_file.contains('/') || !cgroup_file.contains('.') {
+                bail!(
+                    "unified resource {} is not a cgroup interface file",
+                    cgroup_file
+                );
+            }
+
+            if let Some((_, field)) = structured.iter().find(|(file, _)| file == cgroup_file) {
+                bail!(
+                    "unified resource {} conflicts with the {} resource",
+                    cgroup_file,
+                    field
+                );
+            }
+        }
+
+        Ok(())
+    }
+
     fn apply_impl(
         unified: &HashMap<String, String>,
         cgroup_path: &Path,
@@ -28,15 +60,20 @@ impl Unified {
     ) -> Result<()> {
         {
             log::debug!("Apply unified cgroup config");
-            for (cgroup_file, value) in unified {
+            // the files are written in a stable order, which makes failures reproducible
+            let mut entries: Vec<(&String, &String)> = unified.iter().collect();
+            entries.sort();
+            for (cgroup_file, value) in entries {
+                // the type has already been applied by the manager
+                if cgroup_file == threaded::CGROUP_TYPE {
+                    continue;
+                }
+
                 common::write_cgroup_file_str(cgroup_path.join(cgroup_file), value).map_err(
                     |e| {
-                        let (subsystem, _) = cgroup_file
-                            .split_once(".")
-                            .with_context(|| {
-                                format!("failed to split {} with {}", cgroup_file, ".")
-                            })
-                            .unwrap();
+                        let subsystem = cgroup_file
+                            .split_once('.')
+                            .map_or(cgroup_file.as_str(), |(subsystem, _)| subsystem);
                         let context = if !controllers.iter().any(|c| c.to_string() == subsystem) {
                             format!(
                                 "failed to set {} to {}: subsystem {} is not available",
@@ -56,12 +93,64 @@ impl Unified {
     }
 }
 
+/// Lists the interface files that the structured resources set, together
+/// with the resource that sets them
+fn structured_files(resources: &LinuxResources) -> Vec<(&'static str, &'static str)> {
+    let mut files = Vec::new();
+    if let Some(cpu) = resources.cpu() {
+        if cpu.shares().is_some() {
+            files.push(("cpu.weight", "cpu.shares"));
+        }
+        if cpu.quota().is_some() || cpu.period().is_some() {
+            files.push(("cpu.max", "cpu.quota"));
+        }
+        if cpu.cpus().is_some() {
+            files.push(("cpuset.cpus", "cpu.cpus"));
+        }
+        if cpu.mems().is_some() {
+            files.push(("cpuset.mems", "cpu.mems"));
+        }
+    }
+
+    if let Some(memory) = resources.memory() {
+        if memory.limit().is_some() {
+            files.push(("memory.max", "memory.limit"));
+        }
+        if memory.swap().is_some() {
+            files.push(("memory.swap.max", "memory.swap"));
+        }
+        if memory.reservation().is_some() {
+            files.push(("memory.low", "memory.reservation"));
+        }
+    }
+
+    if resources.pids().is_some() {
+        files.push(("pids.max", "pids.limit"));
+    }
+
+    if let Some(block_io) = resources.block_io() {
+        if block_io.weight().is_some() || block_io.weight_device().is_some() {
+            files.push(("io.weight", "blockIO.weight"));
+            files.push(("io.bfq.weight", "blockIO.weight"));
+        }
+        if block_io.throttle_read_bps_device().is_some()
+            || block_io.throttle_write_bps_device().is_some()
+            || block_io.throttle_read_iops_device().is_some()
+            || block_io.throttle_write_iops_device().is_some()
+        {
+            files.push(("io.max", "blockIO.throttle"));
+        }
+    }
+
+    files
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
     use std::fs;
 
-    use oci_spec::runtime::LinuxResourcesBuilder;
+    use oci_spec::runtime::{LinuxMemoryBuilder, LinuxResourcesBuilder};
 
     use crate::test::{create_temp_dir, set_fixture};
     use crate::v2::controller_type::ControllerType;
@@ -179,4 +268,39 @@ mod tests {
         // assert
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_validate_unified() {
+        let mut unified = HashMap::new();
+        unified.insert("memory.high".to_owned(), "1073741824".to_owned());
+        unified.insert("memory.min".to_owned(), "536870912".to_owned());
+        let memory = LinuxMemoryBuilder::default()
+            .limit(2147483648i64)
+            .build()
+            .unwrap();
+        let resources = LinuxResourcesBuilder::default()
+            .memory(memory.clone())
+            .unified(unified.clone())
+            .build()
+            .unwrap();
+        assert!(Unified::validate(&resources).is_ok());
+
+        // memory.max is set by the memory limit as well
+        unified.insert("memory.max".to_owned(), "1073741824".to_owned());
+        let resources = LinuxResourcesBuilder::default()
+            .memory(memory)
+            .unified(unified)
+            .build()
+            .unwrap();
+        assert!(Unified::validate(&resources).is_err());
+
+        // the keys must not leave the cgroup
+        let mut unified = HashMap::new();
+        unified.insert("../memory.max".to_owned(), "max".to_owned());
+        let resources = LinuxResourcesBuilder::default()
+            .unified(unified)
+            .build()
+            .unwrap();
+        assert!(Unified::validate(&resources).is_err());
+    }
 }
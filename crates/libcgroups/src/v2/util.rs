@@ -1,9 +1,7 @@
 use std::path::{Path, PathBuf};
 
+use crate::{common, mountinfo};
 use anyhow::{anyhow, bail, Result};
-use procfs::process::Process;
-
-use crate::common;
 
 use super::controller_type::ControllerType;
 
@@ -11,8 +9,7 @@ pub const CGROUP_CONTROLLERS: &str = "cgroup.controllers";
 pub const CGROUP_SUBTREE_CONTROL: &str = "cgroup.subtree_control";
 
 pub fn get_unified_mount_point() -> Result<PathBuf> {
-    Process::myself()?
-        .mountinfo()?
+    mountinfo::mountinfo()?
         .into_iter()
         .find(|m| m.fs_type == "cgroup2")
         .map(|m| m.mount_point)
@@ -38,6 +35,8 @@ pub fn get_available_controllers<P: AsRef<Path>>(root_path: P) -> Result<Vec<Con
             "io" => controllers.push(ControllerType::Io),
             "memory" => controllers.push(ControllerType::Memory),
             "pids" => controllers.push(ControllerType::Pids),
+            "rdma" => controllers.push(ControllerType::Rdma),
+            "misc" => controllers.push(ControllerType::Misc),
             tpe => log::warn!("Controller {} is not yet implemented.", tpe),
         }
     }
@@ -0,0 +1,96 @@
+//! Reference implementation of a seccomp agent.
+//!
+//! The agent listens on the unix socket given as first argument, which has to be
+//! specified as `linux.seccomp.listenerPath` in the runtime spec. For each container
+//! it answers the syscalls that are intercepted with `SCMP_ACT_NOTIFY`:
+//!
+//! - `mkdir` and `mkdirat` fail with EPERM
+//! - `open` and `openat` succeed, but return a fd of /dev/null injected by the agent
+//! - all other syscalls are executed by the kernel as if they had not been intercepted
+//!
+//! ```text
+//! cargo run --example seccomp_agent -- /run/seccomp-agent.sock
+//! ```
+use anyhow::{bail, Context, Result};
+use libcontainer::seccomp::notify::{self, AddFd, NotifyFd, Response};
+use nix::errno::Errno;
+use std::{
+    fs::{self, File},
+    os::unix::{io::AsRawFd, net::UnixListener},
+    thread,
+};
+
+fn deny(notify_fd: &NotifyFd, id: u64) -> Result<()> {
+    notify_fd.respond(id, Response::Errno(Errno::EPERM))
+}
+
+fn inject(notify_fd: &NotifyFd, id: u64, file: &File) -> Result<()> {
+    // the number of the injected fd is returned to the process as result of the syscall
+    notify_fd.add_fd(
+        id,
+        file.as_raw_fd(),
+        AddFd {
+            cloexec: true,
+            send: true,
+            ..Default::default()
+        },
+    )?;
+    Ok(())
+}
+
+fn handle_container(notify_fd: NotifyFd) -> Result<()> {
+    let dev_null = File::open("/dev/null").context("failed to open /dev/null")?;
+    loop {
+        let notification = notify_fd.receive()?;
+        let syscall = notification.syscall as i64;
+        println!(
+            "pid {} called syscall {} with args {:?}",
+            notification.pid, syscall, notification.args
+        );
+
+        let result = match syscall {
+            #[cfg(target_arch = "x86_64")]
+            libc::SYS_mkdir => deny(&notify_fd, notification.id),
+            libc::SYS_mkdirat => deny(&notify_fd, notification.id),
+            #[cfg(target_arch = "x86_64")]
+            libc::SYS_open => inject(&notify_fd, notification.id, &dev_null),
+            libc::SYS_openat => inject(&notify_fd, notification.id, &dev_null),
+            _ => notify_fd.respond(notification.id, Response::Continue),
+        };
+
+        // The process may have been killed while the syscall was intercepted, in
+        // which case the notification is no longer valid.
+        if let Err(err) = result {
+            eprintln!("failed to handle notification: {:?}", err);
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let socket_path = match std::env::args().nth(1) {
+        Some(path) => path,
+        None => bail!("usage: seccomp_agent <listener path>"),
+    };
+
+    let _ = fs::remove_file(&socket_path);
+    let listener = UnixListener::bind(&socket_path)
+        .with_context(|| format!("failed to listen on {}", socket_path))?;
+
+    for stream in listener.incoming() {
+        let stream = stream.context("failed to accept connection from runtime")?;
+        let (state, notify_fd) = notify::receive_listener_message(&stream)?;
+        println!(
+            "intercepting syscalls of container {} (pid {})",
+            state.state.id, state.pid
+        );
+
+        thread::spawn(move || {
+            if let Err(err) = handle_container(notify_fd) {
+                // receive fails once all processes of the container have exited
+                println!("stopped handling container {}: {:?}", state.state.id, err);
+            }
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,788 @@
+//! Attach allows to stream the stdio of a container after the runtime exited.
+//! The stdio of the container is connected to pipes of a small relay process,
+//! which serves the unix socket `attach.sock` in the container directory and
+//! lives as long as the container writes to stdout or stderr. For containers
+//! with a terminal, the relay receives the pty master instead and relays it
+//! on the stdout channel.
+//!
+//! Clients that connect to the socket exchange frames in both directions.
+//! Each frame consists of the channel (1 byte), the length of the payload
+//! (4 bytes, big endian) and the payload:
+//!
+//! ```text
+//! +---------+----------------+-------------------+
+//! | channel | length (u32be) | payload           |
+//! +---------+----------------+-------------------+
+//! ```
+//!
+//! The channels are listed in [`Channel`].
+//! The relay sends the output of the container on the stdout and stderr
+//! channels to all connected clients. Output that is written while no client
+//! is connected is discarded. Clients that fall more than
+//! [`MAX_CLIENT_BACKLOG`] bytes behind are disconnected, so that a client
+//! that does not read cannot stall the container.
+//!
+//! Clients send frames on the stdin channel, whose payload is written to the
+//! stdin of the container. Input that the container does not read right away
+//! is buffered, and a client whose input would exceed [`MAX_STDIN_BACKLOG`]
+//! bytes is disconnected. An empty stdin frame closes the stdin of the
+//! container once the buffered input has been written. Frames on the resize channel carry the rows and columns of the
+//! terminal of the client (2 bytes each, big endian) and resize the terminal
+//! of the container.
+use std::{
+    fs,
+    io::{self, Read, Write},
+    os::unix::{
+        io::{AsRawFd, FromRawFd, RawFd},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use nix::{
+    fcntl::{self, OFlag},
+    poll::{poll, PollFd, PollFlags},
+    sys::{
+        signal::{self, SigHandler, Signal},
+        socket,
+        stat::Mode,
+        uio,
+    },
+    unistd::{self, dup2},
+};
+
+use crate::process::fork;
+
+/// Name of the attach socket in the container directory
+pub const ATTACH_SOCKET: &str = "attach.sock";
+
+/// Maximum size of the payload of a frame
+pub const MAX_FRAME_SIZE: usize = 1 << 20;
+
+/// Maximum number of bytes of output that is buffered for a client which does
+/// not read fast enough
+pub const MAX_CLIENT_BACKLOG: usize = 4 << 20;
+
+/// Maximum number of bytes of input that is buffered for a container which
+/// does not read its stdin fast enough
+pub const MAX_STDIN_BACKLOG: usize = 4 << 20;
+
+const BUFFER_SIZE: usize = 32 * 1024;
+
+/// Time the relay keeps sending buffered output to clients once the
+/// container closed its stdout and stderr
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Channel {
+    Stdin = 0,
+    Stdout = 1,
+    Stderr = 2,
+    Resize = 3,
+}
+
+impl TryFrom<u8> for Channel {
+    type Error = io::Error;
+
+    fn try_from(value: u8) -> io::Result<Self> {
+        match value {
+            0 => Ok(Channel::Stdin),
+            1 => Ok(Channel::Stdout),
+            2 => Ok(Channel::Stderr),
+            3 => Ok(Channel::Resize),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown attach channel {}", value),
+            )),
+        }
+    }
+}
+
+/// Path of the attach socket of the container
+pub fn socket_path(container_root: &Path) -> PathBuf {
+    container_root.join(ATTACH_SOCKET)
+}
+
+/// Writes a frame with the payload to the channel
+pub fn write_frame<W: Write>(writer: &mut W, channel: Channel, payload: &[u8]) -> io::Result<()> {
+    if payload.len() > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("attach frame of {} bytes is too large", payload.len()),
+        ));
+    }
+
+    let mut frame = Vec::with_capacity(5 + payload.len());
+    frame.push(channel as u8);
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)
+}
+
+/// Reads the next frame. Returns None if the other side closed the connection
+/// between two frames.
+pub fn read_frame<R: Read>(reader: &mut R) -> io::Result<Option<(Channel, Vec<u8>)>> {
+    let mut header = [0u8; 5];
+    match reader.read_exact(&mut header[..1]) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    reader.read_exact(&mut header[1..])?;
+
+    let channel = Channel::try_from(header[0])?;
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("attach frame of {} bytes is too large", len),
+        ));
+    }
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload)?;
+    Ok(Some((channel, payload)))
+}
+
+/// Decodes the frame at the start of the buffer. Returns the frame and the
+/// number of bytes it takes up, or None if the buffer does not hold a complete
+/// frame yet.
+fn decode_frame(buf: &[u8]) -> io::Result<Option<(Channel, Vec<u8>, usize)>> {
+    if buf.len() < 5 {
+        return Ok(None);
+    }
+
+    let channel = Channel::try_from(buf[0])?;
+    let len = u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("attach frame of {} bytes is too large", len),
+        ));
+    }
+    if buf.len() < 5 + len {
+        return Ok(None);
+    }
+
+    Ok(Some((channel, buf[5..5 + len].to_vec(), 5 + len)))
+}
+
+/// Encodes the payload of a resize frame
+pub fn resize_payload(rows: u16, cols: u16) -> [u8; 4] {
+    let rows = rows.to_be_bytes();
+    let cols = cols.to_be_bytes();
+    [rows[0], rows[1], cols[0], cols[1]]
+}
+
+/// The ends of the stdio pipes that are connected to the container process
+#[derive(Debug, Clone, Copy)]
+pub struct AttachStdio {
+    stdin: RawFd,
+    stdout: RawFd,
+    stderr: RawFd,
+}
+
+impl AttachStdio {
+    /// Replaces the stdio of the calling process with the pipes of the relay
+    pub fn connect(&self) -> Result<()> {
+        dup2(self.stdin, libc::STDIN_FILENO).context("failed to dup attach stdin")?;
+        dup2(self.stdout, libc::STDOUT_FILENO).context("failed to dup attach stdout")?;
+        dup2(self.stderr, libc::STDERR_FILENO).context("failed to dup attach stderr")?;
+        self.close();
+        Ok(())
+    }
+
+    /// Closes the pipes in the calling process
+    pub fn close(&self) {
+        for fd in [self.stdin, self.stdout, self.stderr] {
+            let _ = unistd::close(fd);
+        }
+    }
+}
+
+/// Starts the relay process that serves the attach socket in the container
+/// directory and returns the pipes the container has to use as its stdio
+pub fn spawn_relay(container_root: &Path) -> Result<AttachStdio> {
+    let listener = bind(&socket_path(container_root))?;
+    let (stdin_r, stdin_w) = unistd::pipe2(OFlag::O_CLOEXEC)?;
+    let (stdout_r, stdout_w) = unistd::pipe2(OFlag::O_CLOEXEC)?;
+    let (stderr_r, stderr_w) = unistd::pipe2(OFlag::O_CLOEXEC)?;
+
+    let socket = socket_path(container_root);
+    fork::container_fork(|| {
+        for fd in [stdin_r, stdout_w, stderr_w] {
+            unistd::close(fd)?;
+        }
+
+        detach(&[listener.as_raw_fd(), stdin_w, stdout_r, stderr_r])?;
+
+        let relay = Relay {
+            listener,
+            stdin: Some(unsafe { fs::File::from_raw_fd(stdin_w) }),
+            stdout: Some(unsafe { fs::File::from_raw_fd(stdout_r) }),
+            stderr: Some(unsafe { fs::File::from_raw_fd(stderr_r) }),
+            stdin_pending: Vec::new(),
+            close_stdin: false,
+            terminal: false,
+            clients: Vec::new(),
+        };
+        let result = relay.run();
+        let _ = fs::remove_file(&socket);
+        result
+    })
+    .context("failed to fork attach relay")?;
+
+    for fd in [stdin_w, stdout_r, stderr_r] {
+        unistd::close(fd)?;
+    }
+
+    Ok(AttachStdio {
+        stdin: stdin_r,
+        stdout: stdout_w,
+        stderr: stderr_w,
+    })
+}
+
+/// Starts the relay process for a container with a terminal. Returns the
+/// console socket that the container process sends the pty master to.
+pub fn spawn_terminal_relay(container_root: &Path) -> Result<RawFd> {
+    let listener = bind(&socket_path(container_root))?;
+    let (console_socket, relay_socket) = socket::socketpair(
+        socket::AddressFamily::Unix,
+        socket::SockType::Stream,
+        None,
+        socket::SockFlag::SOCK_CLOEXEC,
+    )?;
+
+    let socket = socket_path(container_root);
+    fork::container_fork(|| {
+        unistd::close(console_socket)?;
+        detach(&[listener.as_raw_fd(), relay_socket])?;
+
+        let result = receive_pty_master(relay_socket).and_then(|master| {
+            let relay = Relay {
+                listener,
+                stdin: Some(unsafe { fs::File::from_raw_fd(unistd::dup(master)?) }),
+                stdout: Some(unsafe { fs::File::from_raw_fd(master) }),
+                stderr: None,
+                stdin_pending: Vec::new(),
+                close_stdin: false,
+                terminal: true,
+                clients: Vec::new(),
+            };
+            relay.run()
+        });
+        let _ = fs::remove_file(&socket);
+        result
+    })
+    .context("failed to fork attach relay")?;
+
+    unistd::close(relay_socket)?;
+    Ok(console_socket)
+}
+
+fn receive_pty_master(socket: RawFd) -> Result<RawFd> {
+    let mut buf = [0u8; 64];
+    let mut cmsg_buf = nix::cmsg_space!([RawFd; 1]);
+    let iov = [uio::IoVec::from_mut_slice(&mut buf)];
+    let msg = socket::recvmsg(
+        socket,
+        &iov,
+        Some(&mut cmsg_buf),
+        socket::MsgFlags::MSG_CMSG_CLOEXEC,
+    )
+    .context("failed to receive pty master")?;
+    let master = msg.cmsgs().find_map(|cmsg| match cmsg {
+        socket::ControlMessageOwned::ScmRights(fds) => fds.first().copied(),
+        _ => None,
+    });
+    unistd::close(socket)?;
+
+    // the container process closes the socket without sending the pty
+    // master if it failed
+    master.context("container did not send a pty master")
+}
+
+// The relay outlives the runtime, so it must neither hold the stdio or other
+// files of the runtime open nor be part of its session
+fn detach(keep: &[RawFd]) -> Result<()> {
+    unistd::setsid()?;
+    close_other_fds(keep)?;
+    let null = fcntl::open("/dev/null", OFlag::O_RDWR, Mode::empty())?;
+    for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        dup2(null, fd)?;
+    }
+    unistd::close(null)?;
+    // clients and the container may go away at any time, which must not kill
+    // the relay
+    unsafe { signal::signal(Signal::SIGPIPE, SigHandler::SigIgn) }?;
+    Ok(())
+}
+
+// Closes all file descriptors of the process, except stdio and the given ones
+fn close_other_fds(keep: &[RawFd]) -> Result<()> {
+    let fds: Vec<RawFd> = fs::read_dir("/proc/self/fd")?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .collect();
+    for fd in fds {
+        if fd > libc::STDERR_FILENO && !keep.contains(&fd) {
+            // the fd of the directory listing is already closed
+            let _ = unistd::close(fd);
+        }
+    }
+
+    Ok(())
+}
+
+// unix domain sockets have a maximum path length of 108, so the socket is
+// bound relative to the container directory, like the notify socket
+fn bind(path: &Path) -> Result<UnixListener> {
+    let dir = path.parent().context("attach socket has no parent")?;
+    let name = path.file_name().context("attach socket has no name")?;
+    let cwd = unistd::getcwd().context("failed to get cwd")?;
+    unistd::chdir(dir).with_context(|| format!("failed to chdir into {:?}", dir))?;
+    let listener = UnixListener::bind(name);
+    unistd::chdir(&cwd).with_context(|| format!("failed to chdir back to {:?}", cwd))?;
+    listener.with_context(|| format!("failed to bind {:?}", path))
+}
+
+/// A client of the relay. Its socket is non-blocking, and output that cannot
+/// be written right away is buffered.
+struct Client {
+    stream: UnixStream,
+    /// Received bytes that do not form a complete frame yet
+    input: Vec<u8>,
+    /// Frames that have not been written to the socket yet
+    output: Vec<u8>,
+}
+
+impl Client {
+    fn new(stream: UnixStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream,
+            input: Vec::new(),
+            output: Vec::new(),
+        })
+    }
+
+    /// Reads what the client has sent. Returns false if the client is gone.
+    fn receive(&mut self, buf: &mut [u8]) -> bool {
+        match self.stream.read(buf) {
+            Ok(0) => false,
+            Ok(len) => {
+                self.input.extend_from_slice(&buf[..len]);
+                true
+            }
+            Err(err) => matches!(
+                err.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+            ),
+        }
+    }
+
+    /// Takes the next complete frame from the received bytes
+    fn next_frame(&mut self) -> io::Result<Option<(Channel, Vec<u8>)>> {
+        Ok(decode_frame(&self.input)?.map(|(channel, payload, len)| {
+            self.input.drain(..len);
+            (channel, payload)
+        }))
+    }
+
+    /// Queues a frame and writes as much of the buffered output as the socket
+    /// takes. Returns false if the client is gone or too far behind.
+    fn send(&mut self, channel: Channel, payload: &[u8]) -> bool {
+        write_frame(&mut self.output, channel, payload).is_ok()
+            && self.flush()
+            && self.output.len() <= MAX_CLIENT_BACKLOG
+    }
+
+    /// Writes buffered output until the socket would block. Returns false if
+    /// the client is gone.
+    fn flush(&mut self) -> bool {
+        while !self.output.is_empty() {
+            match self.stream.write(&self.output) {
+                Ok(0) => return false,
+                Ok(len) => {
+                    self.output.drain(..len);
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => return false,
+            }
+        }
+
+        true
+    }
+}
+
+struct Relay {
+    listener: UnixListener,
+    /// Non-blocking, so that a container that does not read its stdin
+    /// cannot stall the relay
+    stdin: Option<fs::File>,
+    stdout: Option<fs::File>,
+    stderr: Option<fs::File>,
+    /// Input that has not been written to the stdin of the container yet
+    stdin_pending: Vec<u8>,
+    /// stdin is closed once the pending input has been written
+    close_stdin: bool,
+    /// stdin and stdout are the pty master of the container
+    terminal: bool,
+    clients: Vec<Client>,
+}
+
+impl Relay {
+    /// Relays until the container closed its stdout and stderr and the
+    /// buffered output has been sent, or `DRAIN_TIMEOUT` has passed
+    fn run(mut self) -> Result<()> {
+        if let Some(stdin) = &self.stdin {
+            // with a terminal, this also makes the reads of the pty master
+            // non-blocking, as both share the open file
+            let fd = stdin.as_raw_fd();
+            let flags = OFlag::from_bits_truncate(fcntl::fcntl(fd, fcntl::FcntlArg::F_GETFL)?);
+            fcntl::fcntl(fd, fcntl::FcntlArg::F_SETFL(flags | OFlag::O_NONBLOCK))?;
+        }
+
+        let mut buf = vec![0u8; BUFFER_SIZE];
+        let mut deadline = None;
+        loop {
+            let mut timeout = -1;
+            if self.stdout.is_none() && self.stderr.is_none() {
+                self.clients.retain(|client| !client.output.is_empty());
+                let deadline = *deadline.get_or_insert_with(|| Instant::now() + DRAIN_TIMEOUT);
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if self.clients.is_empty() || remaining.is_zero() {
+                    break;
+                }
+                timeout = remaining.as_millis().max(1) as libc::c_int;
+            }
+
+            let mut fds = vec![PollFd::new(self.listener.as_raw_fd(), PollFlags::POLLIN)];
+            for output in [&self.stdout, &self.stderr] {
+                // a closed output is polled as an invalid fd, which poll ignores
+                let fd = output.as_ref().map(|f| f.as_raw_fd()).unwrap_or(-1);
+                fds.push(PollFd::new(fd, PollFlags::POLLIN));
+            }
+            let stdin_fd = match &self.stdin {
+                Some(stdin) if !self.stdin_pending.is_empty() => stdin.as_raw_fd(),
+                _ => -1,
+            };
+            fds.push(PollFd::new(stdin_fd, PollFlags::POLLOUT));
+            for client in &self.clients {
+                let mut flags = PollFlags::POLLIN;
+                if !client.output.is_empty() {
+                    flags |= PollFlags::POLLOUT;
+                }
+                fds.push(PollFd::new(client.stream.as_raw_fd(), flags));
+            }
+
+            match poll(&mut fds, timeout) {
+                Ok(_) => {}
+                Err(nix::errno::Errno::EINTR) => continue,
+                Err(err) => bail!("failed to poll attach fds: {}", err),
+            }
+            let revents: Vec<PollFlags> = fds
+                .iter()
+                .map(|fd| fd.revents().unwrap_or_else(PollFlags::empty))
+                .collect();
+            let readable = |revents: PollFlags| {
+                revents.intersects(PollFlags::POLLIN | PollFlags::POLLHUP | PollFlags::POLLERR)
+            };
+
+            if revents[3].intersects(PollFlags::POLLOUT | PollFlags::POLLERR) {
+                self.flush_stdin();
+            }
+
+            // clients are handled first, so that their indices stay valid
+            for i in (0..self.clients.len()).rev() {
+                let revents = revents[4 + i];
+                let alive = (!revents.contains(PollFlags::POLLOUT) || self.clients[i].flush())
+                    && (!readable(revents) || self.handle_client(i, &mut buf));
+                if !alive {
+                    self.clients.remove(i);
+                }
+            }
+
+            if readable(revents[1]) {
+                self.forward_output(Channel::Stdout, &mut buf);
+            }
+            if readable(revents[2]) {
+                self.forward_output(Channel::Stderr, &mut buf);
+            }
+
+            if readable(revents[0]) {
+                match self
+                    .listener
+                    .accept()
+                    .and_then(|(stream, _)| Client::new(stream))
+                {
+                    Ok(client) => self.clients.push(client),
+                    Err(err) => log::warn!("failed to accept attach client: {}", err),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handles the frames of a client. Returns false if the client is gone.
+    fn handle_client(&mut self, index: usize, buf: &mut [u8]) -> bool {
+        if !self.clients[index].receive(buf) {
+            return false;
+        }
+
+        loop {
+            match self.clients[index].next_frame() {
+                Ok(Some((channel, payload))) => {
+                    if !self.handle_frame(channel, &payload) {
+                        return false;
+                    }
+                }
+                Ok(None) => return true,
+                Err(_) => return false,
+            }
+        }
+    }
+
+    /// Handles a frame of a client. Returns false if the frame is invalid.
+    fn handle_frame(&mut self, channel: Channel, payload: &[u8]) -> bool {
+        match channel {
+            Channel::Stdin if payload.is_empty() => {
+                self.close_stdin = true;
+                self.flush_stdin();
+                true
+            }
+            Channel::Stdin => {
+                if self.stdin.is_none() || self.close_stdin {
+                    return true;
+                }
+                if self.stdin_pending.len() + payload.len() > MAX_STDIN_BACKLOG {
+                    log::debug!("disconnect attach client, the container does not read its stdin");
+                    return false;
+                }
+                self.stdin_pending.extend_from_slice(payload);
+                self.flush_stdin();
+                true
+            }
+            Channel::Resize if payload.len() == 4 => {
+                if let (true, Some(master)) = (self.terminal, &self.stdout) {
+                    let size = libc::winsize {
+                        ws_row: u16::from_be_bytes([payload[0], payload[1]]),
+                        ws_col: u16::from_be_bytes([payload[2], payload[3]]),
+                        ws_xpixel: 0,
+                        ws_ypixel: 0,
+                    };
+                    if unsafe { libc::ioctl(master.as_raw_fd(), libc::TIOCSWINSZ, &size) } < 0 {
+                        log::debug!("failed to resize the terminal of the container");
+                    }
+                }
+                true
+            }
+            // clients only send on the stdin and resize channels
+            _ => false,
+        }
+    }
+
+    /// Writes pending input to the stdin of the container until it would
+    /// block, and closes stdin if requested once all input is written
+    fn flush_stdin(&mut self) {
+        let stdin = match &mut self.stdin {
+            Some(stdin) => stdin,
+            None => return,
+        };
+
+        while !self.stdin_pending.is_empty() {
+            match stdin.write(&self.stdin_pending) {
+                Ok(len) if len > 0 => {
+                    self.stdin_pending.drain(..len);
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                // the container closed its stdin
+                _ => {
+                    self.stdin_pending.clear();
+                    self.stdin = None;
+                    return;
+                }
+            }
+        }
+
+        if self.close_stdin {
+            self.stdin = None;
+        }
+    }
+
+    fn forward_output(&mut self, channel: Channel, buf: &mut [u8]) {
+        let output = match channel {
+            Channel::Stdout => &mut self.stdout,
+            _ => &mut self.stderr,
+        };
+
+        let len = match output.as_mut().map(|f| f.read(buf)) {
+            Some(Ok(len)) if len > 0 => len,
+            Some(Err(err))
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock
+                ) =>
+            {
+                return
+            }
+            _ => {
+                *output = None;
+                return;
+            }
+        };
+
+        // clients that are gone or do not keep up are disconnected
+        let mut i = 0;
+        while i < self.clients.len() {
+            if self.clients[i].send(channel, &buf[..len]) {
+                i += 1;
+            } else {
+                log::debug!("disconnect attach client");
+                self.clients.remove(i);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_frames() -> Result<()> {
+        let mut buf = Vec::new();
+        write_frame(&mut buf, Channel::Stdout, b"hello")?;
+        write_frame(&mut buf, Channel::Stdin, b"")?;
+        assert_eq!(&buf[..5], &[1, 0, 0, 0, 5]);
+
+        let mut reader = Cursor::new(buf);
+        assert_eq!(
+            read_frame(&mut reader)?,
+            Some((Channel::Stdout, b"hello".to_vec()))
+        );
+        assert_eq!(read_frame(&mut reader)?, Some((Channel::Stdin, Vec::new())));
+        assert_eq!(read_frame(&mut reader)?, None);
+
+        assert_eq!(resize_payload(24, 80), [0, 24, 0, 80]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_frames() {
+        let mut reader = Cursor::new(vec![7, 0, 0, 0, 0]);
+        assert!(read_frame(&mut reader).is_err());
+
+        let mut reader = Cursor::new(vec![1, 0xff, 0xff, 0xff, 0xff]);
+        assert!(read_frame(&mut reader).is_err());
+
+        // truncated payload
+        let mut reader = Cursor::new(vec![1, 0, 0, 0, 5, b'a']);
+        assert!(read_frame(&mut reader).is_err());
+    }
+
+    #[test]
+    fn test_decode_partial_frames() -> Result<()> {
+        let mut buf = Vec::new();
+        write_frame(&mut buf, Channel::Stdin, b"hello")?;
+        write_frame(&mut buf, Channel::Resize, &resize_payload(24, 80))?;
+
+        assert_eq!(decode_frame(&buf[..3])?, None);
+        assert_eq!(decode_frame(&buf[..7])?, None);
+        assert_eq!(
+            decode_frame(&buf)?,
+            Some((Channel::Stdin, b"hello".to_vec(), 10))
+        );
+        assert_eq!(
+            decode_frame(&buf[10..])?,
+            Some((Channel::Resize, vec![0, 24, 0, 80], 9))
+        );
+        assert!(decode_frame(&[1, 0xff, 0xff, 0xff, 0xff]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_relay_disconnects_client_that_does_not_read() -> Result<()> {
+        let tmp = create_temp_dir("test_relay_disconnects_client_that_does_not_read")?;
+        let socket = socket_path(tmp.path());
+        let listener = UnixListener::bind(&socket)?;
+        let client = UnixStream::connect(&socket)?;
+        let (stream, _) = listener.accept()?;
+        let (stdout_r, stdout_w) = unistd::pipe2(OFlag::O_CLOEXEC)?;
+        let relay = Relay {
+            listener,
+            stdin: None,
+            stdout: Some(unsafe { fs::File::from_raw_fd(stdout_r) }),
+            stderr: None,
+            stdin_pending: Vec::new(),
+            close_stdin: false,
+            terminal: false,
+            clients: vec![Client::new(stream)?],
+        };
+        let relay = std::thread::spawn(move || relay.run());
+
+        // the container is not blocked by the client, which never reads
+        let total = 2 * MAX_CLIENT_BACKLOG;
+        let mut stdout = unsafe { fs::File::from_raw_fd(stdout_w) };
+        let chunk = vec![b'x'; BUFFER_SIZE];
+        for _ in 0..total / BUFFER_SIZE {
+            stdout.write_all(&chunk)?;
+        }
+        drop(stdout);
+        relay.join().expect("relay panicked")?;
+
+        let mut received = Vec::new();
+        (&client).read_to_end(&mut received)?;
+        assert!(received.len() < total);
+        Ok(())
+    }
+
+    #[test]
+    fn test_relay_disconnects_client_when_stdin_is_not_read() -> Result<()> {
+        let tmp = create_temp_dir("test_relay_disconnects_client_when_stdin_is_not_read")?;
+        let socket = socket_path(tmp.path());
+        let listener = UnixListener::bind(&socket)?;
+        let mut client = UnixStream::connect(&socket)?;
+        let (stream, _) = listener.accept()?;
+        let (stdin_r, stdin_w) = unistd::pipe2(OFlag::O_CLOEXEC)?;
+        let (stdout_r, stdout_w) = unistd::pipe2(OFlag::O_CLOEXEC)?;
+        let relay = Relay {
+            listener,
+            stdin: Some(unsafe { fs::File::from_raw_fd(stdin_w) }),
+            stdout: Some(unsafe { fs::File::from_raw_fd(stdout_r) }),
+            stderr: None,
+            stdin_pending: Vec::new(),
+            close_stdin: false,
+            terminal: false,
+            clients: vec![Client::new(stream)?],
+        };
+        let relay = std::thread::spawn(move || relay.run());
+
+        // the container never reads its stdin, which must not stall the relay
+        let payload = vec![b'x'; MAX_FRAME_SIZE];
+        let mut disconnected = false;
+        for _ in 0..2 * MAX_STDIN_BACKLOG / MAX_FRAME_SIZE + 1 {
+            if write_frame(&mut client, Channel::Stdin, &payload).is_err() {
+                disconnected = true;
+                break;
+            }
+        }
+        // the relay still forwards the output of the container
+        let mut stdout = unsafe { fs::File::from_raw_fd(stdout_w) };
+        stdout.write_all(b"done")?;
+        drop(stdout);
+        relay.join().expect("relay panicked")?;
+
+        assert!(disconnected);
+        unistd::close(stdin_r)?;
+        Ok(())
+    }
+}
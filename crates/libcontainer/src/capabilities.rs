@@ -4,7 +4,7 @@ use caps::Capability as CapsCapability;
 use caps::*;
 
 use anyhow::Result;
-use oci_spec::runtime::{Capabilities, Capability as SpecCapability, LinuxCapabilities};
+use oci_spec::runtime::{Capabilities, Capability as SpecCapability, LinuxCapabilities, Process};
 
 /// Converts a list of capability types to capabilities has set
 fn to_set(caps: &Capabilities) -> CapsHashSet {
@@ -159,6 +159,131 @@ pub fn drop_privileges<S: Syscall + ?Sized>(cs: &LinuxCapabilities, syscall: &S)
     Ok(())
 }
 
+/// The capability sets of a process
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CapabilitySets {
+    pub bounding: CapsHashSet,
+    pub effective: CapsHashSet,
+    pub permitted: CapsHashSet,
+    pub inheritable: CapsHashSet,
+    pub ambient: CapsHashSet,
+}
+
+/// Result of [`compute_capabilities`]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CapabilityReport {
+    /// The sets youki configures for the container process right before it
+    /// executes the entrypoint
+    pub before_exec: CapabilitySets,
+    /// The sets of the entrypoint after the exec
+    pub after_exec: CapabilitySets,
+    /// Parts of the configuration that fail the create or are ignored
+    pub warnings: Vec<String>,
+}
+
+/// Computes the capability sets that the container process ends up with,
+/// without starting it. This follows what the init process does, i.e. it
+/// switches to the configured user, resets the effective capabilities and
+/// applies the capabilities of the spec, and then applies the transformation
+/// of the capabilities by execve(2), see
+/// https://man7.org/linux/man-pages/man7/capabilities.7.html. It is assumed
+/// that the runtime runs with all capabilities and nothing inheritable and
+/// that the entrypoint neither has file capabilities nor the set-user-ID bit.
+pub fn compute_capabilities(process: &Process) -> CapabilityReport {
+    let mut warnings = Vec::new();
+    let mut sets = CapabilitySets {
+        bounding: caps::all(),
+        effective: caps::all(),
+        permitted: caps::all(),
+        ..Default::default()
+    };
+
+    if let Some(cs) = process.capabilities() {
+        if let Some(bounding) = cs.bounding() {
+            sets.bounding = to_set(bounding);
+        }
+
+        if let Some(effective) = cs.effective() {
+            sets.effective = to_set(effective);
+        }
+
+        if let Some(permitted) = cs.permitted() {
+            let permitted = to_set(permitted);
+            // the effective capabilities are set first and capset(2) rejects
+            // permitted capabilities that drop an effective one
+            if !sets.effective.is_subset(&permitted) {
+                warnings.push(format!(
+                    "effective capabilities {} are not permitted, which fails the create",
+                    format_set(&sets.effective.difference(&permitted).copied().collect())
+                ));
+            }
+            sets.permitted = permitted;
+        }
+
+        if let Some(inheritable) = cs.inheritable() {
+            sets.inheritable = to_set(inheritable);
+        }
+
+        if let Some(ambient) = cs.ambient() {
+            // a capability can only be raised in the ambient set if it is
+            // permitted and inheritable. Otherwise setting the ambient
+            // capabilities fails, which is only logged.
+            let ambient = to_set(ambient);
+            let allowed: CapsHashSet = sets
+                .permitted
+                .intersection(&sets.inheritable)
+                .copied()
+                .collect();
+            if ambient.is_subset(&allowed) {
+                sets.ambient = ambient;
+            } else {
+                warnings.push(format!(
+                    "ambient capabilities {} are not permitted and inheritable, so no ambient capabilities are set",
+                    format_set(&ambient.difference(&allowed).copied().collect())
+                ));
+            }
+        }
+    }
+
+    // A process with user ID 0 is treated as if the entrypoint had all file
+    // capabilities, a process of any other user only keeps the ambient ones.
+    let mut permitted = if process.user().uid() == 0 {
+        sets.inheritable
+            .union(&sets.bounding)
+            .chain(sets.ambient.iter())
+            .copied()
+            .collect()
+    } else {
+        sets.ambient.clone()
+    };
+
+    // With no new privileges, the exec can never gain capabilities
+    if let Some(true) = process.no_new_privileges() {
+        permitted = permitted.intersection(&sets.permitted).copied().collect();
+    }
+
+    let after_exec = CapabilitySets {
+        bounding: sets.bounding.clone(),
+        effective: permitted.clone(),
+        permitted,
+        inheritable: sets.inheritable.clone(),
+        ambient: sets.ambient.clone(),
+    };
+
+    CapabilityReport {
+        before_exec: sets,
+        after_exec,
+        warnings,
+    }
+}
+
+/// Formats a set of capabilities as a sorted, comma separated list
+pub fn format_set(set: &CapsHashSet) -> String {
+    let mut names: Vec<String> = set.iter().map(|c| c.to_string()).collect();
+    names.sort();
+    names.join(",")
+}
+
 #[cfg(test)]
 mod tests {
     use oci_spec::runtime::LinuxCapabilitiesBuilder;
@@ -662,4 +787,75 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_compute_capabilities() -> anyhow::Result<()> {
+        use oci_spec::runtime::{ProcessBuilder, UserBuilder};
+
+        let caps = |list: &[SpecCapability]| -> Capabilities { list.iter().copied().collect() };
+        let capabilities = LinuxCapabilitiesBuilder::default()
+            .bounding(caps(&[SpecCapability::Chown, SpecCapability::Kill]))
+            .effective(caps(&[SpecCapability::Chown]))
+            .permitted(caps(&[SpecCapability::Chown]))
+            .inheritable(caps(&[SpecCapability::Kill]))
+            .ambient(caps(&[]))
+            .build()?;
+
+        let root = ProcessBuilder::default()
+            .capabilities(capabilities.clone())
+            .no_new_privileges(false)
+            .build()?;
+        let report = compute_capabilities(&root);
+        assert!(report.warnings.is_empty());
+        assert_eq!(
+            format_set(&report.before_exec.permitted),
+            "CAP_CHOWN".to_owned()
+        );
+        // root gains the bounding and inheritable capabilities on exec
+        assert_eq!(
+            format_set(&report.after_exec.permitted),
+            "CAP_CHOWN,CAP_KILL".to_owned()
+        );
+
+        let root_nnp = ProcessBuilder::default()
+            .capabilities(capabilities.clone())
+            .no_new_privileges(true)
+            .build()?;
+        let report = compute_capabilities(&root_nnp);
+        assert_eq!(
+            format_set(&report.after_exec.effective),
+            "CAP_CHOWN".to_owned()
+        );
+
+        let user = ProcessBuilder::default()
+            .capabilities(capabilities)
+            .user(UserBuilder::default().uid(1000u32).gid(1000u32).build()?)
+            .no_new_privileges(false)
+            .build()?;
+        let report = compute_capabilities(&user);
+        assert!(report.after_exec.permitted.is_empty());
+        assert!(report.after_exec.effective.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_compute_capabilities_warnings() -> anyhow::Result<()> {
+        use oci_spec::runtime::ProcessBuilder;
+
+        let caps = |list: &[SpecCapability]| -> Capabilities { list.iter().copied().collect() };
+        let capabilities = LinuxCapabilitiesBuilder::default()
+            .effective(caps(&[SpecCapability::Chown]))
+            .permitted(caps(&[]))
+            .inheritable(caps(&[]))
+            .ambient(caps(&[SpecCapability::NetRaw]))
+            .build()?;
+        let process = ProcessBuilder::default()
+            .capabilities(capabilities)
+            .build()?;
+
+        let report = compute_capabilities(&process);
+        assert_eq!(report.warnings.len(), 2);
+        assert!(report.before_exec.ambient.is_empty());
+        Ok(())
+    }
 }
@@ -6,11 +6,12 @@ use std::{
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 
-use oci_spec::runtime::{Hooks, Spec};
+use oci_spec::runtime::{Hooks, LinuxIntelRdt, Spec};
 
 use crate::utils;
 
 const YOUKI_CONFIG_NAME: &str = "youki_config.json";
+const RUNTIME_SPEC_NAME: &str = "config.json";
 
 /// A configuration for passing information obtained during container creation to other commands.
 /// Keeping the information to a minimum improves performance.
@@ -19,19 +20,26 @@ const YOUKI_CONFIG_NAME: &str = "youki_config.json";
 pub struct YoukiConfig {
     pub hooks: Option<Hooks>,
     pub cgroup_path: PathBuf,
+    /// Intel RDT restrictions set by updates of the container
+    #[serde(default)]
+    pub intel_rdt: Option<LinuxIntelRdt>,
+    /// If the container process has a terminal
+    #[serde(default)]
+    pub terminal: bool,
 }
 
 impl<'a> YoukiConfig {
     pub fn from_spec(spec: &'a Spec, container_id: &str) -> Result<Self> {
+        let linux = spec.linux().as_ref().context("no linux in spec")?;
         Ok(YoukiConfig {
             hooks: spec.hooks().clone(),
-            cgroup_path: utils::get_cgroup_path(
-                spec.linux()
-                    .as_ref()
-                    .context("no linux in spec")?
-                    .cgroups_path(),
-                container_id,
-            ),
+            cgroup_path: utils::get_cgroup_path(linux.cgroups_path(), container_id),
+            intel_rdt: None,
+            terminal: spec
+                .process()
+                .as_ref()
+                .and_then(|process| process.terminal())
+                .unwrap_or(false),
         })
     }
 
@@ -50,6 +58,28 @@ impl<'a> YoukiConfig {
     }
 }
 
+/// Saves the spec the container has been created with, after the rootfs has
+/// been canonicalized and the LSM labels have been resolved, to the container
+/// directory. Later commands like exec, delete or checkpoint use this copy
+/// instead of the config.json of the bundle, which may have been changed or
+/// removed since, so that they see the same configuration as the container.
+pub fn save_runtime_spec<P: AsRef<Path>>(spec: &Spec, container_root: P) -> Result<()> {
+    let container_root = container_root.as_ref();
+    // the spec is replaced atomically, so that a concurrent reader never
+    // sees a partially written file
+    let tmp_path = container_root.join(format!("{}.tmp", RUNTIME_SPEC_NAME));
+    spec.save(&tmp_path)
+        .with_context(|| format!("failed to save runtime spec to {:?}", tmp_path))?;
+    fs::rename(&tmp_path, container_root.join(RUNTIME_SPEC_NAME))?;
+    Ok(())
+}
+
+/// Loads the spec saved by [`save_runtime_spec`]
+pub fn load_runtime_spec<P: AsRef<Path>>(container_root: P) -> Result<Spec> {
+    let path = container_root.as_ref().join(RUNTIME_SPEC_NAME);
+    Spec::load(&path).with_context(|| format!("failed to load runtime spec from {:?}", path))
+}
+
 #[cfg(test)]
 mod tests {
     use crate::utils::create_temp_dir;
@@ -65,6 +95,7 @@ mod tests {
         assert_eq!(&config.hooks, spec.hooks());
         dbg!(&config.cgroup_path);
         assert_eq!(config.cgroup_path, PathBuf::from(container_id));
+        assert_eq!(config.intel_rdt, None);
         Ok(())
     }
 
@@ -79,4 +110,14 @@ mod tests {
         assert_eq!(act, config);
         Ok(())
     }
+
+    #[test]
+    fn test_runtime_spec_save_and_load() -> Result<()> {
+        let tmp = create_temp_dir("test_runtime_spec_save_and_load")?;
+        let spec = Spec::default();
+        save_runtime_spec(&spec, &tmp)?;
+        assert_eq!(load_runtime_spec(&tmp)?, spec);
+        assert!(!tmp.join("config.json.tmp").exists());
+        Ok(())
+    }
 }
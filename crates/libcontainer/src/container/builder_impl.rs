@@ -1,9 +1,13 @@
-use super::{Container, ContainerStatus};
+use super::{runtime_metrics, Container, ContainerStatus};
 use crate::{
+    attach::AttachStdio,
+    experimental::{ExperimentalFeatures, Feature},
     hooks,
     notify_socket::NotifyListener,
     process::{self, args::ContainerArgs},
+    progress::{Phase, Progress},
     rootless::Rootless,
+    seccomp::{self, BpfProgram},
     syscall::Syscall,
     utils,
 };
@@ -11,6 +15,10 @@ use anyhow::{bail, Context, Result};
 use oci_spec::runtime::Spec;
 use std::{fs, io::Write, os::unix::prelude::RawFd, path::PathBuf};
 
+/// Directory in the root directory that caches the compiled seccomp profiles.
+/// It starts with a dot, so that it is not mistaken for a container.
+const SECCOMP_CACHE_DIR: &str = ".seccomp-cache";
+
 pub(super) struct ContainerBuilderImpl<'a> {
     /// Flag indicating if an init or a tenant container should be created
     pub init: bool,
@@ -20,6 +28,8 @@ pub(super) struct ContainerBuilderImpl<'a> {
     pub use_systemd: bool,
     /// Id of the container
     pub container_id: String,
+    /// Root directory of youki, which holds the state of the containers
+    pub root_path: PathBuf,
     /// OCI complient runtime spec
     pub spec: &'a Spec,
     /// Root filesystem of the container
@@ -29,6 +39,8 @@ pub(super) struct ContainerBuilderImpl<'a> {
     pub pid_file: Option<PathBuf>,
     /// Socket to communicate the file descriptor of the ptty
     pub console_socket: Option<RawFd>,
+    /// Pipes of the attach relay to use as stdio of the container
+    pub attach_stdio: Option<AttachStdio>,
     /// Options for rootless containers
     pub rootless: Option<Rootless<'a>>,
     /// Path to the Unix Domain Socket to communicate container start
@@ -37,6 +49,10 @@ pub(super) struct ContainerBuilderImpl<'a> {
     pub container: Option<Container>,
     /// File descriptos preserved/passed to the container init process.
     pub preserve_fds: i32,
+    /// Experimental features enabled by the annotations of the spec
+    pub experimental: ExperimentalFeatures,
+    /// Reports the progress of the phases of the creation
+    pub progress: Progress,
 }
 
 impl<'a> ContainerBuilderImpl<'a> {
@@ -60,11 +76,16 @@ impl<'a> ContainerBuilderImpl<'a> {
             self.use_systemd || self.rootless.is_some(),
             &self.container_id,
         )?;
+        let cgroup_paths = cmanager.get_cgroup_paths();
         let process = self.spec.process().as_ref().context("No process in spec")?;
 
         if self.init {
             if let Some(hooks) = self.spec.hooks() {
-                hooks::run_hooks(hooks.create_runtime().as_ref(), self.container.as_ref())?
+                runtime_metrics::timed("hook.createRuntime", || {
+                    self.progress.phase(Phase::Hooks, || {
+                        hooks::run_hooks(hooks.create_runtime().as_ref(), self.container.as_ref())
+                    })
+                })?
             }
         }
 
@@ -101,6 +122,10 @@ impl<'a> ContainerBuilderImpl<'a> {
             prctl::set_dumpable(false).unwrap();
         }
 
+        let seccomp_program = self
+            .seccomp_program()
+            .context("failed to compile seccomp profile")?;
+
         // This intermediate_args will be passed to the container intermediate process,
         // therefore we will have to move all the variable by value. Since self
         // is a shared reference, we have to clone these variables here.
@@ -110,11 +135,15 @@ impl<'a> ContainerBuilderImpl<'a> {
             spec: self.spec,
             rootfs: &self.rootfs,
             console_socket: self.console_socket,
+            attach_stdio: self.attach_stdio,
             notify_socket,
             preserve_fds: self.preserve_fds,
             container: &self.container,
             rootless: &self.rootless,
             cgroup_manager: cmanager,
+            experimental: &self.experimental,
+            seccomp_program,
+            progress: self.progress,
         };
 
         let init_pid = process::container_main_process::container_main_process(&container_args)?;
@@ -130,6 +159,7 @@ impl<'a> ContainerBuilderImpl<'a> {
                 .set_status(ContainerStatus::Created)
                 .set_creator(nix::unistd::geteuid().as_raw())
                 .set_pid(init_pid.as_raw())
+                .set_cgroup_paths(cgroup_paths)
                 .save()
                 .context("Failed to save container state")?;
         }
@@ -137,6 +167,28 @@ impl<'a> ContainerBuilderImpl<'a> {
         Ok(())
     }
 
+    /// Compiles the seccomp profile with the experimental seccomp-cache
+    /// feature, while the cache in the root directory is still accessible
+    fn seccomp_program(&self) -> Result<Option<BpfProgram>> {
+        if !self.experimental.is_enabled(Feature::SeccompCache) {
+            return Ok(None);
+        }
+
+        match self
+            .spec
+            .linux()
+            .as_ref()
+            .and_then(|l| l.seccomp().as_ref())
+        {
+            Some(seccomp) => seccomp::compile_cached(
+                seccomp,
+                seccomp::is_audit(self.spec.annotations().as_ref())?,
+                &self.root_path.join(SECCOMP_CACHE_DIR),
+            ),
+            None => Ok(None),
+        }
+    }
+
     fn cleanup_container(&self) -> Result<()> {
         let linux = self.spec.linux().as_ref().context("no linux in spec")?;
         let cgroups_path = utils::get_cgroup_path(linux.cgroups_path(), &self.container_id);
@@ -3,17 +3,18 @@ use std::ffi::OsString;
 use std::fs;
 use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use chrono::DateTime;
 use nix::unistd::Pid;
 
 use chrono::Utc;
+use oci_spec::runtime::Spec;
 use procfs::process::Process;
 
-use crate::config::YoukiConfig;
+use crate::config::{self, YoukiConfig};
 use crate::syscall::syscall::create_syscall;
 
-use crate::container::{ContainerStatus, State};
+use crate::container::{runtime_metrics::RuntimeMetrics, CheckpointState, ContainerStatus, State};
 
 /// Structure representing the container data
 #[derive(Debug, Clone)]
@@ -125,6 +126,35 @@ impl Container {
         self
     }
 
+    pub fn selinux_label(&self) -> Option<&str> {
+        self.state.selinux_label.as_deref()
+    }
+
+    pub fn set_selinux_label(&mut self, label: Option<String>) -> &mut Self {
+        self.state.selinux_label = label;
+        self
+    }
+
+    pub fn cgroup_paths(&self) -> Option<&HashMap<String, PathBuf>> {
+        self.state.cgroup_paths.as_ref()
+    }
+
+    pub fn set_cgroup_paths(&mut self, paths: HashMap<String, PathBuf>) -> &mut Self {
+        self.state.cgroup_paths = Some(paths);
+        self
+    }
+
+    pub fn checkpoint_state(&self) -> Option<&CheckpointState> {
+        self.state.checkpoint.as_ref()
+    }
+
+    /// Returns whether it has been recorded that a process of the container
+    /// has been killed by the oom killer, which is done while `events` monitors
+    /// the container and whenever the container is found to have stopped
+    pub fn oom_killed(&self) -> bool {
+        self.state.oom_killed
+    }
+
     pub fn status(&self) -> ContainerStatus {
         self.state.status
     }
@@ -142,34 +172,30 @@ impl Container {
     }
 
     pub fn refresh_status(&mut self) -> Result<()> {
-        let new_status = match self.pid() {
-            Some(pid) => {
-                // Note that Process::new does not spawn a new process
-                // but instead creates a new Process structure, and fill
-                // it with information about the process with given pid
-                if let Ok(proc) = Process::new(pid.as_raw()) {
-                    use procfs::process::ProcState;
-
-                    match proc.stat.state()? {
-                        ProcState::Zombie | ProcState::Dead => ContainerStatus::Stopped,
-                        _ => match self.status() {
-                            ContainerStatus::Creating
-                            | ContainerStatus::Created
-                            | ContainerStatus::Paused => self.status(),
-                            _ => ContainerStatus::Running,
-                        },
-                    }
-                } else {
-                    ContainerStatus::Stopped
-                }
-            }
-            None => ContainerStatus::Stopped,
-        };
-
+        let new_status = current_status(self.pid(), self.status())?;
         self.set_status(new_status);
+        if new_status == ContainerStatus::Stopped {
+            self.check_oom_kills();
+        }
         Ok(())
     }
 
+    // The oom_kill counter of the cgroup outlives the container process, so
+    // reading it once the container has stopped records oom kills even if
+    // no one has monitored the container for them
+    fn check_oom_kills(&mut self) {
+        if self.state.oom_killed {
+            return;
+        }
+
+        if let Some(cgroup_paths) = self.cgroup_paths() {
+            match libcgroups::oom::oom_kills(cgroup_paths) {
+                Ok(oom_kills) => self.state.oom_killed = oom_kills > 0,
+                Err(err) => log::debug!("failed to read the oom kills of {}: {:?}", self.id(), err),
+            }
+        }
+    }
+
     pub fn refresh_state(&mut self) -> Result<&mut Self> {
         let state = State::load(&self.root)?;
         self.state = state;
@@ -177,6 +203,32 @@ impl Container {
         Ok(self)
     }
 
+    /// Checks if a directory in the root path may hold a container. Youki keeps
+    /// its own data in the root path in directories whose name starts with a
+    /// dot, e.g. the seccomp cache, which are never containers.
+    pub fn is_container_dir(path: &Path) -> bool {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| !name.starts_with('.'))
+    }
+
+    /// Checks that a container id names a directory directly in the root
+    /// path, so that it can be joined to the root path safely
+    pub fn validate_id(container_id: &str) -> Result<()> {
+        if container_id.is_empty()
+            || container_id.contains('/')
+            || container_id.contains("..")
+            || container_id.starts_with('.')
+        {
+            bail!(
+                "invalid container id {:?}, it must not be empty, contain a slash or .. or start with a dot",
+                container_id
+            );
+        }
+
+        Ok(())
+    }
+
     pub fn load(container_root: PathBuf) -> Result<Self> {
         let state = State::load(&container_root)?;
         let mut container = Self {
@@ -196,6 +248,46 @@ impl Container {
         let spec = YoukiConfig::load(&self.root)?;
         Ok(spec)
     }
+
+    /// Returns the spec the container has been created with
+    pub fn runtime_spec(&self) -> Result<Spec> {
+        config::load_runtime_spec(&self.root)
+    }
+
+    /// Returns the metrics of the runtime operations on the container
+    pub fn runtime_metrics(&self) -> Result<RuntimeMetrics> {
+        RuntimeMetrics::load(&self.root)
+    }
+}
+
+/// Determines the status of a container from its recorded status and whether
+/// its process is still alive
+pub(crate) fn current_status(pid: Option<Pid>, status: ContainerStatus) -> Result<ContainerStatus> {
+    let status = match pid {
+        Some(pid) => {
+            // Note that Process::new does not spawn a new process
+            // but instead creates a new Process structure, and fill
+            // it with information about the process with given pid
+            if let Ok(proc) = Process::new(pid.as_raw()) {
+                use procfs::process::ProcState;
+
+                match proc.stat.state()? {
+                    ProcState::Zombie | ProcState::Dead => ContainerStatus::Stopped,
+                    _ => match status {
+                        ContainerStatus::Creating
+                        | ContainerStatus::Created
+                        | ContainerStatus::Paused => status,
+                        _ => ContainerStatus::Running,
+                    },
+                }
+            } else {
+                ContainerStatus::Stopped
+            }
+        }
+        None => ContainerStatus::Stopped,
+    };
+
+    Ok(status)
 }
 
 #[cfg(test)]
@@ -214,6 +306,18 @@ mod tests {
         assert_eq!(container.pid(), Some(Pid::from_raw(1)));
     }
 
+    #[test]
+    fn test_validate_id() {
+        assert!(Container::validate_id("74f1a4cb3801").is_ok());
+        assert!(Container::validate_id("pod_1.app").is_ok());
+        assert!(Container::validate_id("").is_err());
+        assert!(Container::validate_id("..").is_err());
+        assert!(Container::validate_id(".seccomp-cache").is_err());
+        assert!(Container::validate_id("../etc").is_err());
+        assert!(Container::validate_id("a/../../etc").is_err());
+        assert!(Container::validate_id("/etc").is_err());
+    }
+
     #[test]
     fn test_basic_getter() -> Result<()> {
         let mut container = Container::new(
@@ -262,6 +366,36 @@ mod tests {
         assert_eq!(container.systemd(), Some(false));
     }
 
+    #[test]
+    fn test_get_set_selinux_label() {
+        let mut container = Container::default();
+        assert_eq!(container.selinux_label(), None);
+        container.set_selinux_label(Some("system_u:system_r:container_t:s0:c1,c2".to_owned()));
+        assert_eq!(
+            container.selinux_label(),
+            Some("system_u:system_r:container_t:s0:c1,c2")
+        );
+    }
+
+    #[test]
+    fn test_get_set_cgroup_paths() {
+        let mut container = Container::default();
+        assert_eq!(container.cgroup_paths(), None);
+
+        let paths = HashMap::from([
+            (
+                "memory".to_owned(),
+                PathBuf::from("/sys/fs/cgroup/memory/youki"),
+            ),
+            (
+                "name=systemd".to_owned(),
+                PathBuf::from("/sys/fs/cgroup/systemd/youki"),
+            ),
+        ]);
+        container.set_cgroup_paths(paths.clone());
+        assert_eq!(container.cgroup_paths(), Some(&paths));
+    }
+
     #[test]
     fn test_get_set_creator() {
         let mut container = Container::default();
@@ -295,11 +429,31 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_refresh_status_records_oom_kill() -> Result<()> {
+        let tmp_dir = create_temp_dir("test_refresh_status_records_oom_kill")?;
+        fs::write(tmp_dir.path().join("memory.events"), "oom 1\noom_kill 0\n")?;
+        let mut container = Container::default();
+        container.set_cgroup_paths(HashMap::from([(
+            "unified".to_owned(),
+            tmp_dir.path().to_path_buf(),
+        )]));
+
+        container.refresh_status()?;
+        assert!(!container.oom_killed());
+
+        fs::write(tmp_dir.path().join("memory.events"), "oom 1\noom_kill 1\n")?;
+        container.refresh_status()?;
+        assert_eq!(container.status(), ContainerStatus::Stopped);
+        assert!(container.oom_killed());
+
+        Ok(())
+    }
+
     #[test]
     #[serial]
     fn test_get_spec() -> Result<()> {
         let tmp_dir = create_temp_dir("test_get_spec")?;
-        use oci_spec::runtime::Spec;
         let spec = Spec::default();
         let config = YoukiConfig::from_spec(&spec, "123").context("convert spec to config")?;
         config.save(tmp_dir.path()).context("save config")?;
@@ -0,0 +1,396 @@
+use super::{CheckpointState, Container, ContainerStatus};
+use crate::criu::{self, CriuFeature, CRIU_BINARY};
+use crate::utils;
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use libcgroups::common::{self, CgroupSetup};
+use oci_spec::runtime::{LinuxNamespaceType, Mount, Spec};
+use std::ffi::OsString;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const DUMP_LOG_FILE: &str = "dump.log";
+pub(super) const DEFAULT_IMAGE_DIR: &str = "checkpoint";
+// Keys under which external namespaces are recorded in the images. A restore
+// has to provide a namespace for each of them, e.g. with --inherit-fd.
+const EXTERNAL_NET_NS_KEY: &str = "extRootNetNS";
+const EXTERNAL_PID_NS_KEY: &str = "extRootPidNS";
+
+/// Options of a checkpoint of a container
+#[derive(Debug, Clone, Default)]
+pub struct CheckpointOptions {
+    /// Directory the images are written to. Defaults to the checkpoint
+    /// directory in the state directory of the container.
+    pub image_path: Option<PathBuf>,
+    /// Directory for the logs and stats of criu. Defaults to the image path.
+    pub work_path: Option<PathBuf>,
+    /// Images of a previous (pre-)dump, relative to the image path. Only the
+    /// memory pages that changed since then are dumped.
+    pub parent_path: Option<PathBuf>,
+    /// Track the memory changes, so that a later dump with this one as its
+    /// parent only dumps the changed pages. Implied by the parent path.
+    pub track_mem: bool,
+    /// Keep the container running after the checkpoint has been taken
+    pub leave_running: bool,
+    /// Allow to checkpoint established tcp connections
+    pub tcp_established: bool,
+    /// Allow to checkpoint external unix sockets
+    pub ext_unix_sk: bool,
+    /// Allow to checkpoint shell jobs
+    pub shell_job: bool,
+    /// Allow to checkpoint file locks
+    pub file_locks: bool,
+}
+
+impl Container {
+    /// Checkpoints the processes of the container with criu. The location of
+    /// the images is recorded in the state of the container, so that they can
+    /// be found without specifying the image path again.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use libcontainer::container::builder::ContainerBuilder;
+    /// use libcontainer::container::CheckpointOptions;
+    /// use libcontainer::syscall::syscall::create_syscall;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut container = ContainerBuilder::new("74f1a4cb3801".to_owned(), create_syscall().as_ref())
+    /// .as_init("/var/run/docker/bundle")
+    /// .build()?;
+    ///
+    /// container.checkpoint(&CheckpointOptions::default())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn checkpoint(&mut self, opts: &CheckpointOptions) -> Result<()> {
+        self.refresh_status()
+            .context("failed to refresh container status")?;
+
+        if !matches!(
+            self.status(),
+            ContainerStatus::Running | ContainerStatus::Paused
+        ) {
+            bail!(
+                "{} could not be checkpointed because it was {:?}",
+                self.id(),
+                self.status()
+            );
+        }
+
+        let pid = self.pid().context("container has no pid")?;
+        let spec = match self.runtime_spec() {
+            Ok(spec) => spec,
+            // containers created by older versions have no saved spec
+            Err(err) => match self.load_bundle_spec()? {
+                Some(spec) => spec,
+                None => return Err(err).context("failed to load runtime spec of container"),
+            },
+        };
+        let external_namespaces = external_namespaces(&spec)?;
+
+        let features = required_features(opts, common::get_cgroup_setup()?, &external_namespaces);
+        let version = criu::check(&features)
+            .with_context(|| format!("criu cannot checkpoint container {}", self.id()))?;
+        log::debug!("using criu {} with features {:?}", version, features);
+
+        let rootfs = spec
+            .root()
+            .as_ref()
+            .context("no root in spec")?
+            .path()
+            .clone();
+
+        let image_path = opts
+            .image_path
+            .clone()
+            .unwrap_or_else(|| self.root.join(DEFAULT_IMAGE_DIR));
+        let work_path = opts.work_path.clone().unwrap_or_else(|| image_path.clone());
+        utils::create_dir_all(&image_path)?;
+        utils::create_dir_all(&work_path)?;
+
+        let args = dump_args(
+            pid.as_raw(),
+            &rootfs,
+            &image_path,
+            &work_path,
+            spec.mounts().as_deref().unwrap_or_default(),
+            &external_namespaces,
+            opts,
+        );
+        log::debug!("checkpoint container {} with criu {:?}", self.id(), args);
+        let status = Command::new(CRIU_BINARY)
+            .args(&args)
+            .status()
+            .context("failed to execute criu")?;
+        if !status.success() {
+            bail!(
+                "criu failed to checkpoint container {} ({}), see {} for details",
+                self.id(),
+                status,
+                work_path.join(DUMP_LOG_FILE).display()
+            );
+        }
+
+        self.state.checkpoint = Some(CheckpointState {
+            image_path,
+            work_path,
+            parent_path: opts.parent_path.clone(),
+            created: Utc::now(),
+            leave_running: opts.leave_running,
+        });
+        if !opts.leave_running {
+            // criu kills the processes after they have been dumped
+            self.set_status(ContainerStatus::Stopped);
+        }
+        self.save()?;
+
+        log::debug!("container {} checkpointed", self.id());
+        Ok(())
+    }
+}
+
+/// Namespaces of the container that have been joined instead of created, e.g.
+/// the network namespace of a pod sandbox. They are not part of the images
+/// and are marked as external, so that the container can be restored into
+/// namespaces that are provided at restore time.
+fn external_namespaces(spec: &Spec) -> Result<Vec<(LinuxNamespaceType, u64)>> {
+    let namespaces = spec
+        .linux()
+        .as_ref()
+        .and_then(|linux| linux.namespaces().as_ref());
+    let mut external = Vec::new();
+    for namespace in namespaces.into_iter().flatten() {
+        let path = match namespace.path() {
+            Some(path) => path,
+            None => continue,
+        };
+
+        match namespace.typ() {
+            LinuxNamespaceType::Network | LinuxNamespaceType::Pid => {
+                let inode = fs::metadata(path)
+                    .with_context(|| format!("failed to stat namespace {}", path.display()))?
+                    .ino();
+                external.push((namespace.typ(), inode));
+            }
+            typ => log::debug!(
+                "{:?} namespace {} is not marked as external",
+                typ,
+                path.display()
+            ),
+        }
+    }
+
+    Ok(external)
+}
+
+pub(super) fn external_namespace_key(
+    typ: LinuxNamespaceType,
+) -> Option<(&'static str, &'static str)> {
+    match typ {
+        LinuxNamespaceType::Network => Some(("net", EXTERNAL_NET_NS_KEY)),
+        LinuxNamespaceType::Pid => Some(("pid", EXTERNAL_PID_NS_KEY)),
+        _ => None,
+    }
+}
+
+fn required_features(
+    opts: &CheckpointOptions,
+    cgroup_setup: CgroupSetup,
+    external_namespaces: &[(LinuxNamespaceType, u64)],
+) -> Vec<CriuFeature> {
+    let mut features = Vec::new();
+    if opts.parent_path.is_some() || opts.track_mem {
+        features.push(CriuFeature::PreDump);
+    }
+    if opts.tcp_established {
+        features.push(CriuFeature::TcpEstablished);
+    }
+    if matches!(cgroup_setup, CgroupSetup::Unified) {
+        features.push(CriuFeature::CgroupV2);
+    }
+    features.extend(
+        external_namespaces
+            .iter()
+            .filter_map(|(typ, _)| external_namespace_feature(*typ)),
+    );
+
+    features
+}
+
+pub(super) fn external_namespace_feature(typ: LinuxNamespaceType) -> Option<CriuFeature> {
+    match typ {
+        LinuxNamespaceType::Network => Some(CriuFeature::ExternalNetNs),
+        LinuxNamespaceType::Pid => Some(CriuFeature::ExternalPidNs),
+        _ => None,
+    }
+}
+
+fn dump_args(
+    pid: i32,
+    rootfs: &Path,
+    image_path: &Path,
+    work_path: &Path,
+    mounts: &[Mount],
+    external_namespaces: &[(LinuxNamespaceType, u64)],
+    opts: &CheckpointOptions,
+) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec![
+        "dump".into(),
+        "--tree".into(),
+        pid.to_string().into(),
+        "--images-dir".into(),
+        image_path.into(),
+        "--work-dir".into(),
+        work_path.into(),
+        "--log-file".into(),
+        DUMP_LOG_FILE.into(),
+        "--root".into(),
+        rootfs.into(),
+        "--manage-cgroups".into(),
+    ];
+
+    // bind mounts are not visible from the mount namespace of criu, they have
+    // to be declared as external and are mounted again on restore
+    for mount in mounts.iter().filter(|m| is_bind_mount(m)) {
+        let destination = mount.destination().as_os_str();
+        let mut map = destination.to_owned();
+        map.push(":");
+        map.push(destination);
+        args.push("--ext-mount-map".into());
+        args.push(map);
+    }
+
+    for (typ, inode) in external_namespaces {
+        if let Some((name, key)) = external_namespace_key(*typ) {
+            args.push("--external".into());
+            args.push(format!("{}[{}]:{}", name, inode, key).into());
+        }
+    }
+
+    if let Some(parent_path) = &opts.parent_path {
+        args.push("--prev-images-dir".into());
+        args.push(parent_path.into());
+    }
+    if opts.parent_path.is_some() || opts.track_mem {
+        args.push("--track-mem".into());
+    }
+
+    let flags = [
+        (opts.leave_running, "--leave-running"),
+        (opts.tcp_established, "--tcp-established"),
+        (opts.ext_unix_sk, "--ext-unix-sk"),
+        (opts.shell_job, "--shell-job"),
+        (opts.file_locks, "--file-locks"),
+    ];
+    args.extend(
+        flags
+            .iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|(_, flag)| OsString::from(flag)),
+    );
+
+    args
+}
+
+pub(super) fn is_bind_mount(mount: &Mount) -> bool {
+    mount.typ().as_deref() == Some("bind")
+        || mount
+            .options()
+            .as_ref()
+            .map(|options| options.iter().any(|o| o == "bind" || o == "rbind"))
+            .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oci_spec::runtime::MountBuilder;
+
+    #[test]
+    fn test_required_features() {
+        let opts = CheckpointOptions::default();
+        assert!(required_features(&opts, CgroupSetup::Legacy, &[]).is_empty());
+        assert_eq!(
+            required_features(&opts, CgroupSetup::Unified, &[]),
+            vec![CriuFeature::CgroupV2]
+        );
+
+        let opts = CheckpointOptions {
+            parent_path: Some(PathBuf::from("../pre-dump")),
+            tcp_established: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            required_features(
+                &opts,
+                CgroupSetup::Hybrid,
+                &[(LinuxNamespaceType::Network, 4026532008)]
+            ),
+            vec![
+                CriuFeature::PreDump,
+                CriuFeature::TcpEstablished,
+                CriuFeature::ExternalNetNs
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dump_args() -> Result<()> {
+        let mounts = vec![
+            MountBuilder::default()
+                .destination("/proc")
+                .typ("proc")
+                .source("proc")
+                .build()?,
+            MountBuilder::default()
+                .destination("/data")
+                .source("/srv/data")
+                .options(vec!["rbind".to_owned(), "ro".to_owned()])
+                .build()?,
+        ];
+        let opts = CheckpointOptions {
+            parent_path: Some(PathBuf::from("../pre-dump")),
+            leave_running: true,
+            tcp_established: true,
+            ..Default::default()
+        };
+
+        let args = dump_args(
+            42,
+            Path::new("/rootfs"),
+            Path::new("/images"),
+            Path::new("/work"),
+            &mounts,
+            &[
+                (LinuxNamespaceType::Network, 4026532008),
+                (LinuxNamespaceType::Pid, 4026532010),
+            ],
+            &opts,
+        );
+        let args: Vec<&str> = args.iter().map(|a| a.to_str().unwrap()).collect();
+        assert_eq!(&args[..3], &["dump", "--tree", "42"]);
+        assert!(args.windows(2).any(|w| w == ["--images-dir", "/images"]));
+        assert!(args.windows(2).any(|w| w == ["--work-dir", "/work"]));
+        assert!(args.windows(2).any(|w| w == ["--root", "/rootfs"]));
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--ext-mount-map", "/data:/data"]));
+        assert!(!args.contains(&"/proc:/proc"));
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--external", "net[4026532008]:extRootNetNS"]));
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--external", "pid[4026532010]:extRootPidNS"]));
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--prev-images-dir", "../pre-dump"]));
+        assert!(args.contains(&"--leave-running"));
+        assert!(args.contains(&"--tcp-established"));
+        assert!(!args.contains(&"--shell-job"));
+        Ok(())
+    }
+}
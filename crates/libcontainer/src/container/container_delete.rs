@@ -1,11 +1,16 @@
-use super::{Container, ContainerStatus};
+use super::{Container, ContainerStatus, State};
 use crate::config::YoukiConfig;
 use crate::hooks;
+use crate::intel_rdt;
 use crate::utils;
 use anyhow::{bail, Context, Result};
 use libcgroups;
+use nix::mount::{umount2, MntFlags};
 use nix::sys::signal;
+use oci_spec::runtime::Spec;
+use std::cmp::Reverse;
 use std::fs;
+use std::path::PathBuf;
 
 impl Container {
     /// Deletes the container
@@ -26,52 +31,93 @@ impl Container {
     /// # }
     /// ```
     pub fn delete(&mut self, force: bool) -> Result<()> {
+        // only an interrupted create can leave mounts behind in the mount
+        // namespace of youki
+        let orphan =
+            self.status() == ContainerStatus::Creating || !State::file_path(&self.root).exists();
         self.refresh_status()
             .context("failed to refresh container status")?;
         if self.can_kill() && force {
             let sig = signal::Signal::SIGKILL;
-            log::debug!("kill signal {} to {}", sig, self.pid().unwrap());
-            signal::kill(self.pid().unwrap(), sig)?;
+            // processes that have been started with exec or forked by the
+            // init process would otherwise keep the cgroup busy
+            if let Err(err) = self.kill_all_processes(sig) {
+                log::warn!("failed to kill all processes of {}: {:?}", self.id(), err);
+                log::debug!("kill signal {} to {}", sig, self.pid().unwrap());
+                signal::kill(self.pid().unwrap(), sig)?;
+            }
             self.set_status(ContainerStatus::Stopped).save()?;
         }
         log::debug!("container status: {:?}", self.status());
         if self.can_delete() {
             if self.root.exists() {
-                let config = YoukiConfig::load(&self.root).with_context(|| {
-                    format!("failed to load runtime spec for container {}", self.id())
-                })?;
+                // The config contains the poststop hooks and has been saved to the
+                // container directory during create, so it is available even if
+                // the bundle has been removed in the meantime. If it cannot be
+                // loaded, the remaining resources are still cleaned up.
+                let config = match YoukiConfig::load(&self.root) {
+                    Ok(config) => Some(config),
+                    Err(err) => {
+                        log::warn!(
+                            "failed to load config of container {}, poststop hooks will not run: {:?}",
+                            self.id(),
+                            err
+                        );
+                        None
+                    }
+                };
                 log::debug!("config: {:?}", config);
 
+                let mut errors = Vec::new();
+                // the config is saved before the cgroup is created, without it
+                // there is no cgroup to remove
+                if let Some(config) = config.as_ref() {
+                    if let Err(err) = self.remove_cgroup(config) {
+                        errors.push(format!("{:?}", err));
+                    }
+                }
+
+                if let Some(intel_rdt) = config.as_ref().and_then(|c| c.intel_rdt.as_ref()) {
+                    if let Err(err) = intel_rdt::remove(self.id(), intel_rdt) {
+                        errors.push(format!("failed to remove intel rdt group: {:?}", err));
+                    }
+                }
+
+                if orphan {
+                    if let Err(err) = self.remove_dangling_mounts() {
+                        errors.push(format!("failed to remove dangling mounts: {:?}", err));
+                    }
+                }
+
+                if !errors.is_empty() {
+                    // keep the container directory, so that a later delete can retry
+                    bail!(
+                        "failed to delete container {}: {}",
+                        self.id(),
+                        errors.join("; ")
+                    );
+                }
+
+                // The poststop hooks only run once the resources of the container
+                // have been cleaned up, so that they do not run again when a
+                // failed delete is retried. Failing hooks must not prevent the
+                // deletion of the container, the runtime spec only requires a
+                // warning. Each hook runs even if a previous one failed.
+                if let Some(hooks) = config.as_ref().and_then(|c| c.hooks.as_ref()) {
+                    for hook in hooks.poststop().iter().flatten() {
+                        if let Err(err) = hooks::run_hooks(Some(&vec![hook.clone()]), Some(self)) {
+                            log::warn!("poststop hook {:?} failed: {:?}", hook.path(), err);
+                        }
+                    }
+                }
+
                 // remove the directory storing container state
                 log::debug!("remove dir {:?}", self.root);
                 fs::remove_dir_all(&self.root).with_context(|| {
                     format!("failed to remove container dir {}", self.root.display())
                 })?;
-
-                let cgroups_path = utils::get_cgroup_path(&Some(config.cgroup_path), self.id());
-
-                // remove the cgroup created for the container
-                // check https://man7.org/linux/man-pages/man7/cgroups.7.html
-                // creating and removing cgroups section for more information on cgroups
-                let use_systemd = self
-                    .systemd()
-                    .context("container state does not contain cgroup manager")?;
-                let cmanager = libcgroups::common::create_cgroup_manager(
-                    &cgroups_path,
-                    use_systemd,
-                    self.id(),
-                )
-                .context("failed to create cgroup manager")?;
-                cmanager.remove().with_context(|| {
-                    format!("failed to remove cgroup {}", cgroups_path.display())
-                })?;
-
-                if let Some(hooks) = config.hooks.as_ref() {
-                    hooks::run_hooks(hooks.poststop().as_ref(), Some(self))
-                        .with_context(|| "failed to run post stop hooks")?;
-                }
             }
-            std::process::exit(0)
+            Ok(())
         } else {
             bail!(
                 "{} could not be deleted because it was {:?}",
@@ -80,4 +126,124 @@ impl Container {
             )
         }
     }
+
+    /// Loads the spec from the bundle of the container, if it still exists
+    pub(super) fn load_bundle_spec(&self) -> Result<Option<Spec>> {
+        // the bundle is unknown for containers without state
+        if self.bundle().as_os_str().is_empty() {
+            return Ok(None);
+        }
+
+        let spec_path = self.bundle().join("config.json");
+        if !spec_path.exists() {
+            log::debug!("bundle of container {} has been removed", self.id());
+            return Ok(None);
+        }
+
+        let mut spec = Spec::load(&spec_path)?;
+        spec.canonicalize_rootfs(self.bundle())?;
+        Ok(Some(spec))
+    }
+
+    /// Unmounts what is still mounted below the root filesystem of the container
+    /// in the mount namespace of youki. This is the case if the creation has been
+    /// interrupted before the container switched to its own mount namespace.
+    fn remove_dangling_mounts(&self) -> Result<()> {
+        let spec = match self.runtime_spec() {
+            Ok(spec) => spec,
+            // containers created by older versions have no saved spec
+            Err(_) => match self.load_bundle_spec() {
+                Ok(Some(spec)) => spec,
+                Ok(None) => return Ok(()),
+                Err(err) => {
+                    log::warn!(
+                        "failed to load the spec of container {}, dangling mounts are not removed: {:?}",
+                        self.id(),
+                        err
+                    );
+                    return Ok(());
+                }
+            },
+        };
+        let rootfs = match spec.root() {
+            Some(root) => root.path(),
+            None => {
+                log::warn!("spec of container {} has no root", self.id());
+                return Ok(());
+            }
+        };
+
+        let mut mount_points: Vec<PathBuf> = libcgroups::mountinfo::mountinfo()
+            .context("failed to read mount info")?
+            .into_iter()
+            .map(|m| m.mount_point)
+            .filter(|m| m != rootfs && m.starts_with(rootfs))
+            .collect();
+        // nested mounts have to be unmounted first
+        mount_points.sort_by_key(|m| Reverse(m.components().count()));
+
+        for mount_point in mount_points {
+            log::debug!("unmount dangling mount {:?}", mount_point);
+            umount2(&mount_point, MntFlags::MNT_DETACH)
+                .with_context(|| format!("failed to unmount {}", mount_point.display()))?;
+        }
+
+        Ok(())
+    }
+
+    fn remove_cgroup(&self, config: &YoukiConfig) -> Result<()> {
+        let cgroups_path = utils::get_cgroup_path(&Some(config.cgroup_path.clone()), self.id());
+
+        // remove the cgroup created for the container
+        // check https://man7.org/linux/man-pages/man7/cgroups.7.html
+        // creating and removing cgroups section for more information on cgroups
+        let use_systemd = self
+            .systemd()
+            .context("container state does not contain cgroup manager")?;
+        let cmanager =
+            libcgroups::common::create_cgroup_manager(&cgroups_path, use_systemd, self.id())
+                .context("failed to create cgroup manager")?;
+        cmanager
+            .remove()
+            .with_context(|| format!("failed to remove cgroup {}", cgroups_path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+    use oci_spec::runtime::{HookBuilder, HooksBuilder};
+
+    #[test]
+    fn test_failed_delete_does_not_run_poststop_hooks() -> Result<()> {
+        let tmp = create_temp_dir("test_failed_delete_does_not_run_poststop_hooks")?;
+        let root = tmp.path().join("container");
+        let bundle = tmp.path().join("bundle");
+        fs::create_dir_all(&root)?;
+        fs::create_dir_all(&bundle)?;
+
+        let marker = tmp.path().join("poststop");
+        let hook = HookBuilder::default()
+            .path("/bin/touch")
+            .args(vec!["touch".to_owned(), marker.display().to_string()])
+            .build()?;
+        let config = YoukiConfig {
+            hooks: Some(HooksBuilder::default().poststop(vec![hook]).build()?),
+            cgroup_path: PathBuf::from("/youki-test-failed-delete"),
+            intel_rdt: None,
+            terminal: false,
+        };
+        config.save(&root)?;
+        // the cgroup cannot be removed as the state does not record the
+        // cgroup manager
+        let mut container = Container::new("test", ContainerStatus::Stopped, None, &bundle, &root)?;
+        container.save()?;
+
+        assert!(container.delete(false).is_err());
+        // the hooks run when the retried delete succeeds
+        assert!(!marker.exists());
+        assert!(root.exists());
+        Ok(())
+    }
 }
@@ -1,14 +1,76 @@
-use std::{thread, time::Duration};
+use std::io::{self, Write};
+use std::{
+    sync::mpsc::{Receiver, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
 
 use super::{Container, ContainerStatus};
 use anyhow::{bail, Context, Result};
+use libcgroups::{
+    oom::{OomNotification, OomNotifier},
+    stats::{Stats, StatsFilter},
+};
+use serde::{Deserialize, Serialize};
+
+/// Event as written by `runc events`. Every event is printed as json on a
+/// line of its own, so that consumers like containerd can read them as a
+/// stream.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Event {
+    /// Type of the event, "stats" for resource statistics or "oom" if the
+    /// oom killer has been invoked for the container
+    #[serde(rename = "type")]
+    pub event_type: String,
+    /// Id of the container
+    pub id: String,
+    /// Resource statistics of the container, only set for "stats" events
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Stats>,
+}
+
+impl Event {
+    pub fn stats(id: &str, stats: Stats) -> Self {
+        Self {
+            event_type: "stats".to_owned(),
+            id: id.to_owned(),
+            data: Some(stats),
+        }
+    }
+
+    pub fn oom(id: &str) -> Self {
+        Self {
+            event_type: "oom".to_owned(),
+            id: id.to_owned(),
+            data: None,
+        }
+    }
+
+    /// Prints the event as a line of json. Stdout is locked while the event
+    /// is written, so that events of different threads do not interleave.
+    fn print(&self) -> Result<()> {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        serde_json::to_writer(&mut stdout, self)?;
+        writeln!(stdout)?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
 
 impl Container {
-    /// Displays container events
+    /// Prints the resource statistics of the container as events in the
+    /// format of `runc events`, once if `stats` is set or otherwise every
+    /// `interval` until the container exits. In the latter case an "oom"
+    /// event is printed as well whenever the oom killer is invoked for the
+    /// container, which is recorded in its state. The loop also ends cleanly as
+    /// soon as a message is received on `shutdown` or its sender is dropped,
+    /// e.g. when the caller has been asked to terminate.
     ///
     /// # Example
     ///
     /// ```no_run
+    /// use std::{sync::mpsc, time::Duration};
     /// use libcontainer::container::builder::ContainerBuilder;
     /// use libcontainer::syscall::syscall::create_syscall;
     ///
@@ -17,36 +79,178 @@ impl Container {
     /// .as_init("/var/run/docker/bundle")
     /// .build()?;
     ///
-    /// container.events(5000, false)?;
+    /// let (_shutdown_tx, shutdown_rx) = mpsc::channel();
+    /// container.events(Duration::from_secs(5), false, &shutdown_rx)?;
     /// # Ok(())
     /// # }
     /// ```
-    pub fn events(&mut self, interval: u32, stats: bool) -> Result<()> {
+    pub fn events(
+        &mut self,
+        interval: Duration,
+        stats: bool,
+        shutdown: &Receiver<()>,
+    ) -> Result<()> {
         self.refresh_status()
             .context("failed to refresh container status")?;
         if !self.state.status.eq(&ContainerStatus::Running) {
             bail!("{} is not in running state", self.id());
         }
 
+        if !stats {
+            // oom kills before the monitoring started are only visible in
+            // the counter of the memory cgroup
+            if let Err(err) = self.refresh_oom_killed() {
+                log::debug!("failed to check oom kills of {}: {:?}", self.id(), err);
+            }
+            match self.oom_notifier() {
+                Ok(notifier) => {
+                    let container = self.clone();
+                    thread::spawn(move || monitor_oom(container, notifier));
+                }
+                Err(err) => log::warn!(
+                    "failed to register for oom notifications of {}: {:?}",
+                    self.id(),
+                    err
+                ),
+            }
+        }
+
+        loop {
+            Event::stats(self.id(), self.stats()?).print()?;
+
+            if stats {
+                return Ok(());
+            }
+
+            match shutdown.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => {}
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+
+            // the stats of a paused container can still be read, but the
+            // cgroup of a stopped container may already be gone
+            self.refresh_status()
+                .context("failed to refresh container status")?;
+            if !matches!(
+                self.status(),
+                ContainerStatus::Running | ContainerStatus::Paused
+            ) {
+                log::debug!("container {} is {}, stop events", self.id(), self.status());
+                return Ok(());
+            }
+        }
+    }
+
+    /// Records an oom kill that happened before the events are monitored.
+    /// Unless it has been recorded already, the oom_kill counter of the memory
+    /// cgroup is checked.
+    fn refresh_oom_killed(&mut self) -> Result<()> {
+        if self.state.oom_killed {
+            return Ok(());
+        }
+
         let cgroups_path = self.spec()?.cgroup_path;
         let use_systemd = self
             .systemd()
             .context("could not determine cgroup manager")?;
-
-        let cgroup_manager =
+        let cmanager =
             libcgroups::common::create_cgroup_manager(cgroups_path, use_systemd, self.id())?;
-        match stats {
-            true => {
-                let stats = cgroup_manager.stats()?;
-                println!("{}", serde_json::to_string_pretty(&stats)?);
+        let filter = StatsFilter {
+            memory: true,
+            ..StatsFilter::none()
+        };
+        let stats = cmanager
+            .stats_with(&filter)
+            .with_context(|| format!("failed to get memory stats of {}", self.id()))?;
+
+        if stats.memory.events.oom_kill > 0 {
+            self.record_oom_kill()?;
+        }
+        Ok(())
+    }
+
+    /// Records in the state that a process of the container has been killed
+    /// by the oom killer. The state is reloaded first, so that concurrent
+    /// changes of other fields are not reverted.
+    fn record_oom_kill(&mut self) -> Result<()> {
+        self.refresh_state()?.refresh_status()?;
+        self.state.oom_killed = true;
+        self.save()
+    }
+
+    fn oom_notifier(&self) -> Result<OomNotifier> {
+        match self.cgroup_paths() {
+            Some(paths) => OomNotifier::new(paths),
+            None => {
+                let cgroups_path = self.spec()?.cgroup_path;
+                let use_systemd = self
+                    .systemd()
+                    .context("could not determine cgroup manager")?;
+                let cmanager = libcgroups::common::create_cgroup_manager(
+                    cgroups_path,
+                    use_systemd,
+                    self.id(),
+                )?;
+                OomNotifier::new(&cmanager.get_cgroup_paths())
             }
-            false => loop {
-                let stats = cgroup_manager.stats()?;
-                println!("{}", serde_json::to_string_pretty(&stats)?);
-                thread::sleep(Duration::from_secs(interval as u64));
-            },
         }
+    }
+}
+
+/// Prints an "oom" event and records the oom kill in the state of the
+/// container on every oom notification, until the cgroup is removed
+fn monitor_oom(mut container: Container, mut notifier: OomNotifier) {
+    loop {
+        match notifier.wait(None) {
+            Ok(OomNotification::Oom) => {
+                if let Err(err) = container.record_oom_kill() {
+                    log::warn!("failed to record oom kill of {}: {:?}", container.id(), err);
+                }
+                if let Err(err) = Event::oom(container.id()).print() {
+                    log::warn!("failed to print oom event: {:?}", err);
+                }
+            }
+            Ok(OomNotification::Timeout) => {}
+            Ok(OomNotification::Removed) => return,
+            Err(err) => {
+                log::warn!(
+                    "failed to wait for oom notifications of {}: {:?}",
+                    container.id(),
+                    err
+                );
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_event() -> Result<()> {
+        let mut stats = Stats::default();
+        stats.pids.current = 2;
+
+        let json = serde_json::to_value(Event::stats("74f1a4cb3801", stats))?;
+        assert_eq!(json["type"], "stats");
+        assert_eq!(json["id"], "74f1a4cb3801");
+        assert_eq!(json["data"]["pids"]["current"], 2);
+
+        let event: Event = serde_json::from_value(json)?;
+        assert_eq!(event.data.map(|data| data.pids.current), Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_oom_event() -> Result<()> {
+        let json = serde_json::to_string(&Event::oom("74f1a4cb3801"))?;
+        assert_eq!(json, r#"{"type":"oom","id":"74f1a4cb3801"}"#);
 
+        let event: Event = serde_json::from_str(&json)?;
+        assert_eq!(event.event_type, "oom");
+        assert!(event.data.is_none());
         Ok(())
     }
 }
@@ -0,0 +1,306 @@
+use super::{Container, ContainerStatus, State};
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Time after which `delete` considers a container directory without state
+/// to be left over by an interrupted create instead of a create in progress
+pub const DEFAULT_ORPHAN_MIN_AGE: Duration = Duration::from_secs(60);
+
+impl Container {
+    /// Loads a container whose creation has been interrupted before its state
+    /// could be saved, e.g. because youki has been killed. Only the directory
+    /// of the container exists, which is enough for `delete` to remove the
+    /// cgroup, dangling mounts and the directory itself. As a create may be in
+    /// progress, the directory must not have been modified for `min_age`.
+    pub fn load_orphan<P: AsRef<Path>>(
+        root_path: P,
+        container_id: &str,
+        use_systemd: bool,
+        min_age: Duration,
+    ) -> Result<Self> {
+        Container::validate_id(container_id)?;
+        let root_path = fs::canonicalize(root_path.as_ref())
+            .with_context(|| format!("failed to canonicalize {}", root_path.as_ref().display()))?;
+        let container_root = root_path.join(container_id);
+        check_in_root(&root_path, &container_root)?;
+        if State::file_path(&container_root).exists() {
+            bail!(
+                "{} is not an orphan, the container state exists",
+                container_root.display()
+            );
+        }
+
+        let age = directory_age(&container_root)?
+            .with_context(|| format!("{} does not exist", container_root.display()))?;
+        if !is_orphan(None, age, min_age) {
+            bail!(
+                "{} has been modified {}s ago, its creation may still be in progress",
+                container_root.display(),
+                age.as_secs()
+            );
+        }
+
+        let mut container = Container::new(
+            container_id,
+            ContainerStatus::Stopped,
+            None,
+            Path::new(""),
+            &container_root,
+        )?;
+        container.set_systemd(use_systemd);
+
+        Ok(container)
+    }
+
+    /// Finds the containers in the root path whose creation has been
+    /// interrupted. They either have no state at all or are still in the
+    /// creating state. As a create may be in progress, only containers
+    /// whose directory has not been modified for at least `min_age` are
+    /// considered.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use libcontainer::container::Container;
+    /// use std::time::Duration;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// for mut orphan in Container::find_orphans("/run/youki", Duration::from_secs(60), true)? {
+    ///     orphan.delete(false)?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn find_orphans<P: AsRef<Path>>(
+        root_path: P,
+        min_age: Duration,
+        use_systemd: bool,
+    ) -> Result<Vec<Self>> {
+        let root_path = fs::canonicalize(root_path.as_ref())
+            .with_context(|| format!("failed to canonicalize {}", root_path.as_ref().display()))?;
+
+        let mut orphans = Vec::new();
+        for container_dir in fs::read_dir(&root_path)? {
+            let container_dir = container_dir?;
+            if !container_dir.file_type()?.is_dir() {
+                continue;
+            }
+
+            let container_id = match container_dir.file_name().into_string() {
+                Ok(container_id) if Container::validate_id(&container_id).is_ok() => container_id,
+                _ => continue,
+            };
+            let container_dir = container_dir.path();
+            if let Err(err) = check_in_root(&root_path, &container_dir) {
+                log::warn!("skipping {}: {:?}", container_dir.display(), err);
+                continue;
+            }
+            // the container may be deleted while the root path is scanned
+            let age = match directory_age(&container_dir)? {
+                Some(age) => age,
+                None => continue,
+            };
+
+            let state = if State::file_path(&container_dir).exists() {
+                match State::load(&container_dir) {
+                    Ok(state) => Some(state),
+                    Err(err) => {
+                        log::warn!(
+                            "failed to load container state from {:?}: {:?}",
+                            container_dir,
+                            err
+                        );
+                        continue;
+                    }
+                }
+            } else {
+                None
+            };
+
+            if !is_orphan(state.as_ref(), age, min_age) {
+                continue;
+            }
+
+            let container = match state {
+                Some(state) => Container {
+                    state,
+                    root: container_dir,
+                },
+                None => {
+                    match Container::load_orphan(&root_path, &container_id, use_systemd, min_age) {
+                        Ok(container) => container,
+                        Err(_) if !container_dir.exists() => continue,
+                        Err(err) => return Err(err),
+                    }
+                }
+            };
+            log::debug!("found orphaned container {}", container.id());
+            orphans.push(container);
+        }
+
+        Ok(orphans)
+    }
+}
+
+/// Checks that the container directory is a directory in the root path and
+/// not e.g. a symbolic link to a directory elsewhere, which must not be
+/// cleaned up
+fn check_in_root(root_path: &Path, container_root: &Path) -> Result<()> {
+    let canonical = match fs::canonicalize(container_root) {
+        Ok(canonical) => canonical,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("failed to canonicalize {}", container_root.display()))
+        }
+    };
+    if canonical.parent() != Some(root_path) {
+        bail!(
+            "{} resolves to {}, which is not in the root path {}",
+            container_root.display(),
+            canonical.display(),
+            root_path.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Returns the time since the directory has been modified, or None if it does
+/// not exist
+fn directory_age(path: &Path) -> Result<Option<Duration>> {
+    let modified = match fs::metadata(path) {
+        Ok(metadata) => metadata.modified()?,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err).with_context(|| format!("failed to read metadata of {:?}", path))
+        }
+    };
+
+    Ok(Some(
+        SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default(),
+    ))
+}
+
+/// A create that has been interrupted leaves a directory without state or with
+/// the creating state behind. The pid of the container is saved together with
+/// the created status, so such a container has no init process that could be
+/// running outside of its cgroup and can be deleted like a stopped container.
+fn is_orphan(state: Option<&State>, age: Duration, min_age: Duration) -> bool {
+    if age < min_age {
+        return false;
+    }
+
+    match state {
+        Some(state) => state.status == ContainerStatus::Creating,
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+
+    #[test]
+    fn test_is_orphan() {
+        let min_age = Duration::from_secs(60);
+        let old = Duration::from_secs(120);
+        let young = Duration::from_secs(5);
+
+        let creating = State::new("id", ContainerStatus::Creating, None, PathBuf::from("/"));
+        let created = State::new("id", ContainerStatus::Created, Some(1), PathBuf::from("/"));
+        let stopped = State::new("id", ContainerStatus::Stopped, Some(1), PathBuf::from("/"));
+
+        assert!(is_orphan(None, old, min_age));
+        assert!(is_orphan(Some(&creating), old, min_age));
+        assert!(!is_orphan(None, young, min_age));
+        assert!(!is_orphan(Some(&creating), young, min_age));
+        assert!(!is_orphan(Some(&created), old, min_age));
+        assert!(!is_orphan(Some(&stopped), old, min_age));
+    }
+
+    #[test]
+    fn test_find_orphans() -> Result<()> {
+        let root = create_temp_dir("test_find_orphans")?;
+
+        fs::create_dir(root.path().join("no-state"))?;
+        let creating = root.path().join("creating");
+        fs::create_dir(&creating)?;
+        Container::new(
+            "creating",
+            ContainerStatus::Creating,
+            None,
+            root.path(),
+            &creating,
+        )?
+        .save()?;
+        let created = root.path().join("created");
+        fs::create_dir(&created)?;
+        Container::new(
+            "created",
+            ContainerStatus::Created,
+            Some(1),
+            root.path(),
+            &created,
+        )?
+        .save()?;
+
+        let mut orphans: Vec<String> = Container::find_orphans(root.path(), Duration::ZERO, false)?
+            .iter()
+            .map(|c| c.id().to_owned())
+            .collect();
+        orphans.sort();
+        assert_eq!(orphans, vec!["creating", "no-state"]);
+
+        let orphans = Container::find_orphans(root.path(), Duration::from_secs(3600), false)?;
+        assert!(orphans.is_empty());
+
+        assert!(Container::load_orphan(root.path(), "created", false, Duration::ZERO).is_err());
+        // a directory that has just been created may belong to a create in progress
+        fs::create_dir(root.path().join("young"))?;
+        assert!(
+            Container::load_orphan(root.path(), "young", false, Duration::from_secs(3600)).is_err()
+        );
+        assert!(Container::load_orphan(root.path(), "young", false, Duration::ZERO).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_orphans_skips_hidden_dirs() -> Result<()> {
+        let root = create_temp_dir("test_find_orphans_skips_hidden_dirs")?;
+
+        // e.g. the seccomp cache, which has no state either
+        fs::create_dir(root.path().join(".seccomp-cache"))?;
+        fs::create_dir(root.path().join("no-state"))?;
+
+        let orphans: Vec<String> = Container::find_orphans(root.path(), Duration::ZERO, false)?
+            .iter()
+            .map(|c| c.id().to_owned())
+            .collect();
+        assert_eq!(orphans, vec!["no-state"]);
+        assert!(root.path().join(".seccomp-cache").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_orphans_outside_of_root() -> Result<()> {
+        let tmp = create_temp_dir("test_orphans_outside_of_root")?;
+        let root = tmp.path().join("root");
+        let outside = tmp.path().join("outside");
+        fs::create_dir(&root)?;
+        fs::create_dir(&outside)?;
+        std::os::unix::fs::symlink(&outside, root.join("link"))?;
+
+        assert!(Container::load_orphan(&root, "../outside", false, Duration::ZERO).is_err());
+        assert!(Container::load_orphan(&root, "link", false, Duration::ZERO).is_err());
+        assert!(Container::find_orphans(&root, Duration::ZERO, false)?.is_empty());
+        assert!(outside.exists());
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
 use super::{Container, ContainerStatus};
 use crate::signal::Signal;
 use anyhow::{bail, Context, Result};
-use nix::sys::signal::{self};
+use nix::sys::signal::{self, Signal as NixSignal};
 
 impl Container {
     /// Sends the specified signal to the container init process
@@ -30,7 +30,7 @@ impl Container {
             log::debug!("kill signal {} to {}", signal, self.pid().unwrap());
             signal::kill(self.pid().unwrap(), signal)?;
             self.set_status(ContainerStatus::Stopped).save()?;
-            std::process::exit(0)
+            Ok(())
         } else {
             bail!(
                 "{} could not be killed because it was {:?}",
@@ -39,4 +39,58 @@ impl Container {
             )
         }
     }
+
+    /// Sends the specified signal to all processes of the container, which
+    /// includes the processes started with exec or forked by the init process
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use libcontainer::container::builder::ContainerBuilder;
+    /// use libcontainer::syscall::syscall::create_syscall;
+    /// use nix::sys::signal::Signal;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut container = ContainerBuilder::new("74f1a4cb3801".to_owned(), create_syscall().as_ref())
+    /// .as_init("/var/run/docker/bundle")
+    /// .build()?;
+    ///
+    /// container.kill_all(Signal::SIGKILL)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn kill_all<S: Into<Signal>>(&mut self, signal: S) -> Result<()> {
+        let signal = signal.into().into_raw();
+        self.refresh_status()
+            .context("failed to refresh container status")?;
+        if self.can_kill() {
+            log::debug!("kill signal {} to all processes of {}", signal, self.id());
+            self.kill_all_processes(signal)?;
+            self.set_status(ContainerStatus::Stopped).save()?;
+            Ok(())
+        } else {
+            bail!(
+                "{} could not be killed because it was {:?}",
+                self.id(),
+                self.status()
+            )
+        }
+    }
+
+    /// Sends the signal to all processes in the cgroup of the container
+    pub(crate) fn kill_all_processes(&self, signal: NixSignal) -> Result<()> {
+        let cgroups_path = self.spec()?.cgroup_path;
+        let use_systemd = self
+            .systemd()
+            .context("container state does not contain cgroup manager")?;
+        let cmanager =
+            libcgroups::common::create_cgroup_manager(cgroups_path, use_systemd, self.id())?;
+        cmanager.kill_all(signal).with_context(|| {
+            format!(
+                "failed to send {} to all processes of {}",
+                signal,
+                self.id()
+            )
+        })
+    }
 }
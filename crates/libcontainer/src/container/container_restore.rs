@@ -0,0 +1,462 @@
+use super::container_checkpoint::{
+    external_namespace_feature, external_namespace_key, is_bind_mount, DEFAULT_IMAGE_DIR,
+};
+use super::{Container, ContainerStatus};
+use crate::criu::{self, CriuFeature, CRIU_BINARY};
+use crate::utils;
+use anyhow::{bail, Context, Result};
+use libcgroups::common::{self, CgroupSetup};
+use nix::unistd;
+use oci_spec::runtime::{LinuxNamespaceType, Mount, Spec};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const RESTORE_LOG_FILE: &str = "restore.log";
+const RESTORE_PID_FILE: &str = "restore.pid";
+
+/// Options of a restore of a container
+#[derive(Debug, Clone, Default)]
+pub struct RestoreOptions {
+    /// Directory the images are read from. Defaults to the image path of the
+    /// latest checkpoint of the container.
+    pub image_path: Option<PathBuf>,
+    /// Directory for the logs of criu. Defaults to the image path.
+    pub work_path: Option<PathBuf>,
+    /// Network namespace to restore the container into, e.g. the one of a pod
+    /// sandbox. Defaults to the path of the network namespace in the spec.
+    pub network_namespace: Option<PathBuf>,
+    /// Pid namespace to restore the container into. Defaults to the path of
+    /// the pid namespace in the spec.
+    pub pid_namespace: Option<PathBuf>,
+    /// Allow to restore established tcp connections
+    pub tcp_established: bool,
+    /// Allow to restore external unix sockets
+    pub ext_unix_sk: bool,
+    /// Allow to restore shell jobs
+    pub shell_job: bool,
+    /// Allow to restore file locks
+    pub file_locks: bool,
+}
+
+impl Container {
+    /// Restores the processes of a stopped container from the images of a
+    /// checkpoint with criu. Namespaces that were joined instead of created
+    /// when the container was checkpointed, like the network namespace of a
+    /// pod sandbox, are not part of the images. They are passed to criu for
+    /// the external namespaces recorded in the images, so the container can
+    /// be restored into namespaces that are owned by someone else.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use libcontainer::container::{Container, RestoreOptions};
+    /// use std::path::PathBuf;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut container = Container::load(PathBuf::from("/run/youki/74f1a4cb3801"))?;
+    /// container.restore(&RestoreOptions {
+    ///     network_namespace: Some(PathBuf::from("/var/run/netns/sandbox")),
+    ///     ..Default::default()
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn restore(&mut self, opts: &RestoreOptions) -> Result<()> {
+        self.refresh_status()
+            .context("failed to refresh container status")?;
+
+        if self.status() != ContainerStatus::Stopped {
+            bail!(
+                "{} could not be restored because it was {:?}",
+                self.id(),
+                self.status()
+            );
+        }
+
+        let spec = match self.runtime_spec() {
+            Ok(spec) => spec,
+            // containers created by older versions have no saved spec
+            Err(err) => match self.load_bundle_spec()? {
+                Some(spec) => spec,
+                None => return Err(err).context("failed to load runtime spec of container"),
+            },
+        };
+        let external_namespaces = external_namespace_paths(&spec, opts)?;
+
+        let features = required_features(opts, common::get_cgroup_setup()?, &external_namespaces);
+        let version = criu::check(&features)
+            .with_context(|| format!("criu cannot restore container {}", self.id()))?;
+        log::debug!("using criu {} with features {:?}", version, features);
+
+        let rootfs = spec
+            .root()
+            .as_ref()
+            .context("no root in spec")?
+            .path()
+            .clone();
+
+        let image_path = match (&opts.image_path, self.checkpoint_state()) {
+            (Some(image_path), _) => image_path.clone(),
+            (None, Some(checkpoint)) => checkpoint.image_path.clone(),
+            (None, None) => self.root.join(DEFAULT_IMAGE_DIR),
+        };
+        if !image_path.exists() {
+            bail!(
+                "no checkpoint of container {} in {}",
+                self.id(),
+                image_path.display()
+            );
+        }
+        let work_path = opts.work_path.clone().unwrap_or_else(|| image_path.clone());
+        utils::create_dir_all(&work_path)?;
+
+        let pid_file = self.root.join(RESTORE_PID_FILE);
+        let _ = fs::remove_file(&pid_file);
+
+        // the namespaces are inherited by criu, and the fds are closed again
+        // once it has finished
+        let inherited = inherit_namespaces(&external_namespaces)?;
+        let args = restore_args(
+            &rootfs,
+            &image_path,
+            &work_path,
+            &pid_file,
+            spec.mounts().as_deref().unwrap_or_default(),
+            &inherited
+                .iter()
+                .map(|(typ, fd)| (*typ, fd.0))
+                .collect::<Vec<_>>(),
+            opts,
+        );
+        log::debug!("restore container {} with criu {:?}", self.id(), args);
+        let status = Command::new(CRIU_BINARY)
+            .args(&args)
+            .status()
+            .context("failed to execute criu")?;
+        drop(inherited);
+        if !status.success() {
+            bail!(
+                "criu failed to restore container {} ({}), see {} for details",
+                self.id(),
+                status,
+                work_path.join(RESTORE_LOG_FILE).display()
+            );
+        }
+
+        let pid = fs::read_to_string(&pid_file)
+            .with_context(|| format!("failed to read {}", pid_file.display()))?;
+        let pid: i32 = pid
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid pid {:?} in {}", pid, pid_file.display()))?;
+        let _ = fs::remove_file(&pid_file);
+
+        self.set_pid(pid)
+            .set_status(ContainerStatus::Running)
+            .save()?;
+
+        log::debug!("container {} restored with pid {}", self.id(), pid);
+        Ok(())
+    }
+}
+
+/// Paths of the namespaces that replace the external namespaces of the
+/// checkpoint. Only the namespaces that have a path in the spec have been
+/// marked as external when the container was checkpointed.
+fn external_namespace_paths(
+    spec: &Spec,
+    opts: &RestoreOptions,
+) -> Result<Vec<(LinuxNamespaceType, PathBuf)>> {
+    let namespaces = spec
+        .linux()
+        .as_ref()
+        .and_then(|linux| linux.namespaces().as_ref());
+    let namespace_path = |typ: LinuxNamespaceType| {
+        namespaces
+            .into_iter()
+            .flatten()
+            .find(|namespace| namespace.typ() == typ)
+            .and_then(|namespace| namespace.path().clone())
+    };
+
+    let mut external = Vec::new();
+    for (typ, path) in [
+        (LinuxNamespaceType::Network, &opts.network_namespace),
+        (LinuxNamespaceType::Pid, &opts.pid_namespace),
+    ] {
+        match (path, namespace_path(typ)) {
+            (Some(path), Some(_)) => external.push((typ, path.clone())),
+            (Some(path), None) => bail!(
+                "cannot restore into {:?} namespace {}, as the spec does not join a {:?} namespace",
+                typ,
+                path.display(),
+                typ
+            ),
+            (None, Some(path)) => external.push((typ, path)),
+            (None, None) => {}
+        }
+    }
+
+    Ok(external)
+}
+
+fn required_features(
+    opts: &RestoreOptions,
+    cgroup_setup: CgroupSetup,
+    external_namespaces: &[(LinuxNamespaceType, PathBuf)],
+) -> Vec<CriuFeature> {
+    let mut features = Vec::new();
+    if opts.tcp_established {
+        features.push(CriuFeature::TcpEstablished);
+    }
+    if matches!(cgroup_setup, CgroupSetup::Unified) {
+        features.push(CriuFeature::CgroupV2);
+    }
+    features.extend(
+        external_namespaces
+            .iter()
+            .filter_map(|(typ, _)| external_namespace_feature(*typ)),
+    );
+
+    features
+}
+
+/// Fd that is inherited by the child processes and closed when dropped
+struct InheritedFd(RawFd);
+
+impl Drop for InheritedFd {
+    fn drop(&mut self) {
+        let _ = unistd::close(self.0);
+    }
+}
+
+/// Opens the namespaces without the close-on-exec flag, so that criu can
+/// inherit them
+fn inherit_namespaces(
+    namespaces: &[(LinuxNamespaceType, PathBuf)],
+) -> Result<Vec<(LinuxNamespaceType, InheritedFd)>> {
+    namespaces
+        .iter()
+        .map(|(typ, path)| {
+            let file = File::open(path)
+                .with_context(|| format!("failed to open namespace {}", path.display()))?;
+            // unlike the fd of the file, a duplicated fd is not closed on exec
+            let fd = unistd::dup(file.as_raw_fd())?;
+            Ok((*typ, InheritedFd(fd)))
+        })
+        .collect()
+}
+
+fn restore_args(
+    rootfs: &Path,
+    image_path: &Path,
+    work_path: &Path,
+    pid_file: &Path,
+    mounts: &[Mount],
+    inherited_namespaces: &[(LinuxNamespaceType, RawFd)],
+    opts: &RestoreOptions,
+) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec![
+        "restore".into(),
+        "--images-dir".into(),
+        image_path.into(),
+        "--work-dir".into(),
+        work_path.into(),
+        "--log-file".into(),
+        RESTORE_LOG_FILE.into(),
+        "--root".into(),
+        rootfs.into(),
+        "--manage-cgroups".into(),
+        "--restore-detached".into(),
+        "--pidfile".into(),
+        pid_file.into(),
+    ];
+
+    // the bind mounts have been declared as external by their destination
+    // and are mounted again from their source
+    for mount in mounts.iter().filter(|m| is_bind_mount(m)) {
+        if let Some(source) = mount.source() {
+            let mut map = mount.destination().as_os_str().to_owned();
+            map.push(":");
+            map.push(source);
+            args.push("--ext-mount-map".into());
+            args.push(map);
+        }
+    }
+
+    for (typ, fd) in inherited_namespaces {
+        if let Some((_, key)) = external_namespace_key(*typ) {
+            args.push("--inherit-fd".into());
+            args.push(format!("fd[{}]:{}", fd, key).into());
+        }
+    }
+
+    let flags = [
+        (opts.tcp_established, "--tcp-established"),
+        (opts.ext_unix_sk, "--ext-unix-sk"),
+        (opts.shell_job, "--shell-job"),
+        (opts.file_locks, "--file-locks"),
+    ];
+    args.extend(
+        flags
+            .iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|(_, flag)| OsString::from(flag)),
+    );
+
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+    use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+    use oci_spec::runtime::{LinuxBuilder, LinuxNamespaceBuilder, MountBuilder, SpecBuilder};
+
+    fn spec_with_namespaces(namespaces: &[(LinuxNamespaceType, Option<&str>)]) -> Result<Spec> {
+        let namespaces = namespaces
+            .iter()
+            .map(|(typ, path)| {
+                let mut builder = LinuxNamespaceBuilder::default().typ(*typ);
+                if let Some(path) = path {
+                    builder = builder.path(*path);
+                }
+                builder.build()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(SpecBuilder::default()
+            .linux(LinuxBuilder::default().namespaces(namespaces).build()?)
+            .build()?)
+    }
+
+    #[test]
+    fn test_external_namespace_paths() -> Result<()> {
+        let spec = spec_with_namespaces(&[
+            (LinuxNamespaceType::Network, Some("/var/run/netns/old")),
+            (LinuxNamespaceType::Pid, None),
+            (LinuxNamespaceType::Mount, None),
+        ])?;
+
+        assert_eq!(
+            external_namespace_paths(&spec, &RestoreOptions::default())?,
+            vec![(
+                LinuxNamespaceType::Network,
+                PathBuf::from("/var/run/netns/old")
+            )]
+        );
+
+        let opts = RestoreOptions {
+            network_namespace: Some(PathBuf::from("/var/run/netns/sandbox")),
+            ..Default::default()
+        };
+        assert_eq!(
+            external_namespace_paths(&spec, &opts)?,
+            vec![(
+                LinuxNamespaceType::Network,
+                PathBuf::from("/var/run/netns/sandbox")
+            )]
+        );
+
+        // the pid namespace has been part of the images
+        let opts = RestoreOptions {
+            pid_namespace: Some(PathBuf::from("/proc/1/ns/pid")),
+            ..Default::default()
+        };
+        assert!(external_namespace_paths(&spec, &opts).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_inherit_namespaces() -> Result<()> {
+        let tmp = create_temp_dir("test_inherit_namespaces")?;
+        let path = tmp.join("net");
+        fs::write(&path, "")?;
+
+        let inherited = inherit_namespaces(&[(LinuxNamespaceType::Network, path)])?;
+        assert_eq!(inherited.len(), 1);
+        let (typ, fd) = &inherited[0];
+        assert_eq!(*typ, LinuxNamespaceType::Network);
+        let flags = FdFlag::from_bits_truncate(fcntl(fd.0, FcntlArg::F_GETFD)?);
+        assert!(!flags.contains(FdFlag::FD_CLOEXEC));
+
+        let raw_fd = fd.0;
+        drop(inherited);
+        assert!(fcntl(raw_fd, FcntlArg::F_GETFD).is_err());
+
+        assert!(inherit_namespaces(&[(LinuxNamespaceType::Pid, tmp.join("missing"))]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_args() -> Result<()> {
+        let mounts = vec![
+            MountBuilder::default()
+                .destination("/proc")
+                .typ("proc")
+                .source("proc")
+                .build()?,
+            MountBuilder::default()
+                .destination("/data")
+                .source("/srv/data")
+                .options(vec!["rbind".to_owned(), "ro".to_owned()])
+                .build()?,
+        ];
+        let opts = RestoreOptions {
+            tcp_established: true,
+            ..Default::default()
+        };
+
+        let args = restore_args(
+            Path::new("/rootfs"),
+            Path::new("/images"),
+            Path::new("/work"),
+            Path::new("/run/youki/test/restore.pid"),
+            &mounts,
+            &[
+                (LinuxNamespaceType::Network, 7),
+                (LinuxNamespaceType::Pid, 8),
+            ],
+            &opts,
+        );
+        let args: Vec<&str> = args.iter().map(|a| a.to_str().unwrap()).collect();
+        assert_eq!(args[0], "restore");
+        assert!(args.windows(2).any(|w| w == ["--images-dir", "/images"]));
+        assert!(args.windows(2).any(|w| w == ["--work-dir", "/work"]));
+        assert!(args.windows(2).any(|w| w == ["--root", "/rootfs"]));
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--pidfile", "/run/youki/test/restore.pid"]));
+        assert!(args.contains(&"--restore-detached"));
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--ext-mount-map", "/data:/srv/data"]));
+        assert!(!args.contains(&"/proc:proc"));
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--inherit-fd", "fd[7]:extRootNetNS"]));
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--inherit-fd", "fd[8]:extRootPidNS"]));
+        assert!(args.contains(&"--tcp-established"));
+        assert!(!args.contains(&"--shell-job"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_required_features() {
+        let opts = RestoreOptions::default();
+        assert!(required_features(&opts, CgroupSetup::Legacy, &[]).is_empty());
+        assert_eq!(
+            required_features(
+                &opts,
+                CgroupSetup::Unified,
+                &[(LinuxNamespaceType::Pid, PathBuf::from("/proc/1/ns/pid"))]
+            ),
+            vec![CriuFeature::CgroupV2, CriuFeature::ExternalPidNs]
+        );
+    }
+}
@@ -4,9 +4,10 @@ use crate::{
     notify_socket::{NotifySocket, NOTIFY_FILE},
 };
 
-use super::{Container, ContainerStatus};
+use super::{runtime_metrics, Container, ContainerStatus};
 use anyhow::{bail, Context, Result};
 use nix::unistd;
+use std::time::Instant;
 
 impl Container {
     /// Starts a previously created container
@@ -27,6 +28,8 @@ impl Container {
     /// # }
     /// ```
     pub fn start(&mut self) -> Result<()> {
+        let start = Instant::now();
+        runtime_metrics::discard();
         self.refresh_status()
             .context("failed to refresh container status")?;
 
@@ -46,8 +49,10 @@ impl Container {
             // While prestart is marked as deprecated in the OCI spec, the docker and integration test still
             // uses it.
             #[allow(deprecated)]
-            hooks::run_hooks(hooks.prestart().as_ref(), Some(self))
-                .with_context(|| "failed to run pre start hooks")?;
+            runtime_metrics::timed("hook.prestart", || {
+                hooks::run_hooks(hooks.prestart().as_ref(), Some(self))
+            })
+            .with_context(|| "failed to run pre start hooks")?;
         }
 
         unistd::chdir(self.root.as_os_str())?;
@@ -61,10 +66,14 @@ impl Container {
         // Run post start hooks. It runs after the container process is started.
         // It is called in the runtime namespace.
         if let Some(hooks) = config.hooks.as_ref() {
-            hooks::run_hooks(hooks.poststart().as_ref(), Some(self))
-                .with_context(|| "failed to run post start hooks")?;
+            runtime_metrics::timed("hook.poststart", || {
+                hooks::run_hooks(hooks.poststart().as_ref(), Some(self))
+            })
+            .with_context(|| "failed to run post start hooks")?;
         }
 
+        runtime_metrics::observe("start", start.elapsed());
+        runtime_metrics::flush(&self.root);
         Ok(())
     }
 }
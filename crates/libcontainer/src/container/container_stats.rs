@@ -0,0 +1,204 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{Container, ContainerStatus, State};
+use anyhow::{bail, Context, Result};
+use libcgroups::{
+    common::CgroupDiscovery,
+    stats::{self, NetworkInterface, Stats, StatsFilter},
+};
+use nix::unistd::Pid;
+
+impl Container {
+    /// Retrieves the resource usage statistics of the container
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use libcontainer::container::builder::ContainerBuilder;
+    /// use libcontainer::syscall::syscall::create_syscall;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut container = ContainerBuilder::new("74f1a4cb3801".to_owned(), create_syscall().as_ref())
+    /// .as_init("/var/run/docker/bundle")
+    /// .build()?;
+    ///
+    /// let stats = container.stats()?;
+    /// println!("{:?}", stats.memory);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn stats(&mut self) -> Result<Stats> {
+        self.stats_with(&StatsFilter::default())
+    }
+
+    /// Retrieves the resource usage statistics of the container that are
+    /// selected by the filter
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use libcgroups::stats::StatsFilter;
+    /// use libcontainer::container::Container;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut container = Container::load("/run/youki/74f1a4cb3801".into())?;
+    /// let filter = StatsFilter {
+    ///     cpu: true,
+    ///     memory: true,
+    ///     ..StatsFilter::none()
+    /// };
+    /// let stats = container.stats_with(&filter)?;
+    /// println!("{:?}", stats.cpu);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn stats_with(&mut self, filter: &StatsFilter) -> Result<Stats> {
+        self.refresh_status()
+            .context("failed to refresh container status")?;
+        if !self.can_report_stats() {
+            bail!("{} is not in running or paused state", self.id());
+        }
+
+        let discovery = CgroupDiscovery::new().context("failed to discover cgroup setup")?;
+        self.stats_with_discovery(&discovery, filter)
+    }
+
+    /// Retrieves the resource usage statistics of all running or paused containers
+    /// under the root path. The cgroup setup of the system is discovered only once
+    /// and shared between all containers. Containers whose statistics cannot be
+    /// retrieved, e.g. because they have been deleted concurrently, are skipped.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use libcontainer::container::Container;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let stats = Container::stats_all("/run/youki")?;
+    /// for (id, stats) in &stats {
+    ///     println!("{}: {:?}", id, stats.pids);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn stats_all<P: AsRef<Path>>(root_path: P) -> Result<HashMap<String, Stats>> {
+        let root_path = fs::canonicalize(root_path.as_ref())
+            .with_context(|| format!("failed to canonicalize {}", root_path.as_ref().display()))?;
+        let discovery = CgroupDiscovery::new().context("failed to discover cgroup setup")?;
+
+        let mut all_stats = HashMap::new();
+        for container_dir in fs::read_dir(&root_path)? {
+            let container_dir = container_dir?.path();
+            if !Container::is_container_dir(&container_dir)
+                || !State::file_path(&container_dir).exists()
+            {
+                continue;
+            }
+
+            let container = match Container::load(container_dir.clone()) {
+                Ok(container) => container,
+                Err(err) => {
+                    log::warn!(
+                        "failed to load container from {:?}: {:?}",
+                        container_dir,
+                        err
+                    );
+                    continue;
+                }
+            };
+
+            // loading refreshes the status from the process of the container,
+            // so containers that exited since their state was written are
+            // skipped here rather than failing below
+            if !container.can_report_stats() {
+                continue;
+            }
+
+            match container.stats_with_discovery(&discovery, &StatsFilter::default()) {
+                Ok(stats) => {
+                    all_stats.insert(container.id().to_owned(), stats);
+                }
+                Err(err) => {
+                    log::warn!(
+                        "failed to get stats of container {}: {:?}",
+                        container.id(),
+                        err
+                    )
+                }
+            }
+        }
+
+        Ok(all_stats)
+    }
+
+    fn can_report_stats(&self) -> bool {
+        matches!(
+            self.status(),
+            ContainerStatus::Running | ContainerStatus::Paused
+        )
+    }
+
+    /// Collects the statistics of the container, whose status has to be
+    /// refreshed and checked by the caller
+    fn stats_with_discovery(
+        &self,
+        discovery: &CgroupDiscovery,
+        filter: &StatsFilter,
+    ) -> Result<Stats> {
+        let cgroups_path = self.spec()?.cgroup_path;
+        let use_systemd = self
+            .systemd()
+            .context("could not determine cgroup manager")?;
+
+        let cgroup_manager =
+            discovery.create_cgroup_manager(cgroups_path, use_systemd, self.id())?;
+        let mut stats = cgroup_manager
+            .stats_with(filter)
+            .with_context(|| format!("failed to get stats of container {}", self.id()))?;
+
+        if let Some(pid) = self.pid().filter(|_| filter.network) {
+            if let Some(interfaces) = stats.check_available("network", network_stats(pid)) {
+                stats.network_interfaces = interfaces;
+            }
+        }
+
+        Ok(stats)
+    }
+}
+
+/// Reads the statistics of the network interfaces in the network namespace of
+/// the process. /proc/<pid>/net always shows the namespace of the process, so
+/// the namespace does not have to be entered.
+fn network_stats(pid: Pid) -> Result<Vec<NetworkInterface>> {
+    let net_dev = PathBuf::from(format!("/proc/{}/net/dev", pid));
+    let content = fs::read_to_string(&net_dev)
+        .with_context(|| format!("failed to read {}", net_dev.display()))?;
+    Ok(stats::parse_net_dev(&net_dev, &content)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+
+    #[test]
+    fn test_stats_all_skips_containers_that_do_not_run() -> Result<()> {
+        let root = create_temp_dir("test_stats_all_skips_containers_that_do_not_run")?;
+        let save = |id: &str, status: ContainerStatus, pid: i32| -> Result<()> {
+            let container_dir = root.path().join(id);
+            fs::create_dir(&container_dir)?;
+            Container::new(id, status, Some(pid), root.path(), &container_dir)?.save()
+        };
+        // the process of the container exited after its state was written
+        save("exited", ContainerStatus::Running, i32::MAX)?;
+        // the process is alive, but has not been started yet
+        save("created", ContainerStatus::Created, Pid::this().as_raw())?;
+
+        assert!(Container::stats_all(root.path())?.is_empty());
+        Ok(())
+    }
+}
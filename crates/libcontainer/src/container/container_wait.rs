@@ -0,0 +1,153 @@
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::{Duration, Instant};
+
+use super::{Container, ContainerStatus};
+use anyhow::{anyhow, Context, Result};
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+use nix::unistd::{self, Pid};
+
+/// Interval in which the status is checked while waiting for the container
+/// process to exit on kernels without pidfd support
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+impl Container {
+    /// Blocks until the container reaches the status or the timeout expires.
+    /// Changes of the state are observed with inotify on the container
+    /// directory and the exit of the container process with a pidfd, so the
+    /// caller does not have to poll the state.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::PathBuf;
+    /// use libcontainer::container::{Container, ContainerStatus};
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut container = Container::load(PathBuf::from("/run/youki/74f1a4cb3801"))?;
+    /// container.wait_for_status(ContainerStatus::Stopped, None)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn wait_for_status(
+        &mut self,
+        status: ContainerStatus,
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        // the watch is added before the status is checked for the first time,
+        // so that no change between the check and the wait is missed
+        let inotify = Inotify::init(InitFlags::IN_CLOEXEC | InitFlags::IN_NONBLOCK)?;
+        inotify
+            .add_watch(
+                &self.root,
+                AddWatchFlags::IN_CLOSE_WRITE
+                    | AddWatchFlags::IN_MOVED_TO
+                    | AddWatchFlags::IN_DELETE_SELF,
+            )
+            .with_context(|| format!("failed to watch {:?}", self.root))?;
+
+        let mut pidfd: Option<RawFd> = None;
+        let result = loop {
+            if let Err(err) = self.refresh_state() {
+                break Err(err.context(format!("container {} has been deleted", self.id())));
+            }
+            if let Err(err) = self.refresh_status() {
+                break Err(err);
+            }
+
+            let current = self.status();
+            if current == status {
+                break Ok(());
+            }
+            if current == ContainerStatus::Stopped {
+                break Err(anyhow!(
+                    "container {} stopped before it was {}",
+                    self.id(),
+                    status
+                ));
+            }
+
+            if pidfd.is_none() {
+                pidfd = self.pid().and_then(pidfd_open);
+            }
+
+            let mut timeout =
+                deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            if timeout == Some(Duration::ZERO) {
+                break Err(anyhow!(
+                    "timed out waiting for container {} to be {}",
+                    self.id(),
+                    status
+                ));
+            }
+            // without a pidfd the exit of the process does not wake us up
+            if pidfd.is_none() && self.pid().is_some() {
+                timeout = Some(timeout.map_or(EXIT_POLL_INTERVAL, |t| t.min(EXIT_POLL_INTERVAL)));
+            }
+
+            let mut fds = vec![PollFd::new(inotify.as_raw_fd(), PollFlags::POLLIN)];
+            if let Some(pidfd) = pidfd {
+                fds.push(PollFd::new(pidfd, PollFlags::POLLIN));
+            }
+            let timeout = timeout.map_or(-1, |t| t.as_millis().max(1) as i32);
+            match poll(&mut fds, timeout) {
+                Ok(_) | Err(Errno::EINTR) => {}
+                Err(err) => break Err(err.into()),
+            }
+
+            // the events only signal that the state may have changed
+            match inotify.read_events() {
+                Ok(_) | Err(Errno::EAGAIN) => {}
+                Err(err) => break Err(err.into()),
+            }
+        };
+
+        if let Some(pidfd) = pidfd {
+            let _ = unistd::close(pidfd);
+        }
+        result
+    }
+}
+
+/// Returns a pidfd of the process, which becomes readable once it exited, or
+/// None if the kernel does not support pidfds
+fn pidfd_open(pid: Pid) -> Option<RawFd> {
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid.as_raw(), 0) };
+    if fd < 0 {
+        log::debug!(
+            "failed to open pidfd of {}: {}",
+            pid,
+            std::io::Error::last_os_error()
+        );
+        return None;
+    }
+
+    Some(fd as RawFd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::State;
+    use crate::utils::create_temp_dir;
+
+    #[test]
+    fn test_wait_for_status() -> Result<()> {
+        let tmp = create_temp_dir("test_wait_for_status")?;
+        let state = State::new("wait", ContainerStatus::Created, None, tmp.to_path_buf());
+        state.save(&tmp)?;
+
+        let mut container = Container {
+            state,
+            root: tmp.to_path_buf(),
+        };
+        // a container without a process is stopped
+        container.wait_for_status(ContainerStatus::Stopped, Some(Duration::from_secs(1)))?;
+        assert!(container
+            .wait_for_status(ContainerStatus::Running, Some(Duration::from_secs(1)))
+            .is_err());
+        Ok(())
+    }
+}
@@ -5,12 +5,22 @@ use rootless::Rootless;
 use std::{
     fs,
     path::{Path, PathBuf},
+    time::Instant,
 };
 
-use crate::{apparmor, config::YoukiConfig, notify_socket::NOTIFY_FILE, rootless, tty, utils};
+use crate::{
+    attach,
+    config::{self, YoukiConfig},
+    experimental::ExperimentalFeatures,
+    lsm,
+    notify_socket::NOTIFY_FILE,
+    progress::Progress,
+    rootless, selinux, tty, utils,
+};
 
 use super::{
-    builder::ContainerBuilder, builder_impl::ContainerBuilderImpl, Container, ContainerStatus,
+    builder::ContainerBuilder, builder_impl::ContainerBuilderImpl, runtime_metrics, Container,
+    ContainerStatus,
 };
 
 // Builder that can be used to configure the properties of a new container
@@ -18,6 +28,10 @@ pub struct InitContainerBuilder<'a> {
     base: ContainerBuilder<'a>,
     bundle: PathBuf,
     use_systemd: bool,
+    use_selinux_mcs: bool,
+    permissive_lsm: bool,
+    progress: bool,
+    attachable: bool,
 }
 
 impl<'a> InitContainerBuilder<'a> {
@@ -28,6 +42,10 @@ impl<'a> InitContainerBuilder<'a> {
             base: builder,
             bundle,
             use_systemd: true,
+            use_selinux_mcs: false,
+            permissive_lsm: false,
+            progress: false,
+            attachable: false,
         }
     }
 
@@ -37,18 +55,70 @@ impl<'a> InitContainerBuilder<'a> {
         self
     }
 
+    /// Sets if a unique SELinux MCS label should be generated for the container,
+    /// if SELinux is enabled and the spec does not specify any labels
+    pub fn with_selinux_mcs(mut self, should_use: bool) -> Self {
+        self.use_selinux_mcs = should_use;
+        self
+    }
+
+    /// Sets if the container should be created without the requested AppArmor
+    /// profile or SELinux label if they cannot be applied on this system. A warning
+    /// is logged instead of failing the creation.
+    pub fn with_permissive_lsm(mut self, permissive: bool) -> Self {
+        self.permissive_lsm = permissive;
+        self
+    }
+
+    /// Sets if the progress of the phases of the creation should be reported
+    /// as JSON records on stderr
+    pub fn with_progress(mut self, report: bool) -> Self {
+        self.progress = report;
+        self
+    }
+
+    /// Sets if the stdio of the container should be served on the attach
+    /// socket in the container directory, so that it can be attached to later
+    pub fn with_attachable(mut self, attachable: bool) -> Self {
+        self.attachable = attachable;
+        self
+    }
+
     /// Creates a new container
     pub fn build(self) -> Result<Container> {
-        let spec = self.load_spec()?;
-        let container_dir = self.create_container_dir()?;
+        let start = Instant::now();
+        runtime_metrics::discard();
+        let mut spec = self.load_spec()?;
+        let mcs_lock = if self.use_selinux_mcs {
+            self.assign_selinux_labels(&mut spec)
+                .context("failed to assign SELinux labels")?
+        } else {
+            None
+        };
+        self.validate_lsm(&mut spec)?;
+        let experimental = ExperimentalFeatures::from_annotations(spec.annotations().as_ref())
+            .context("invalid experimental feature annotations")?;
 
+        let container_dir = self.create_container_dir()?;
         let mut container = self.create_container_state(&container_dir)?;
+
         container
             .set_systemd(self.use_systemd)
-            .set_annotations(spec.annotations().clone());
+            .set_annotations(spec.annotations().clone())
+            .set_selinux_label(
+                spec.process()
+                    .as_ref()
+                    .and_then(|process| process.selinux_label().clone()),
+            );
+        if mcs_lock.is_some() {
+            // other creations see the allocated categories from now on
+            container.save()?;
+            drop(mcs_lock);
+        }
 
         let config = YoukiConfig::from_spec(&spec, container.id())?;
         config.save(&container_dir)?;
+        config::save_runtime_spec(&spec, &container_dir)?;
 
         unistd::chdir(&container_dir)?;
         let notify_path = container_dir.join(NOTIFY_FILE);
@@ -67,13 +137,42 @@ impl<'a> InitContainerBuilder<'a> {
             None
         };
 
+        let terminal = spec
+            .process()
+            .as_ref()
+            .and_then(|process| process.terminal())
+            .unwrap_or(false);
+        let (attach_stdio, attach_console) = match (self.attachable, terminal) {
+            (false, _) => (None, None),
+            (true, false) => (
+                Some(attach::spawn_relay(&container_dir).context("failed to start attach relay")?),
+                None,
+            ),
+            (true, true) => {
+                if csocketfd.is_some() {
+                    bail!("an attachable container cannot use a console socket");
+                }
+
+                let console = attach::spawn_terminal_relay(&container_dir)
+                    .context("failed to start attach relay")?;
+                (None, Some(console))
+            }
+        };
+
         let rootless = Rootless::new(&spec)?;
+        let progress = if self.progress {
+            Progress::to_stderr(3 + self.base.preserve_fds)?
+        } else {
+            Progress::default()
+        };
         let mut builder_impl = ContainerBuilderImpl {
             init: true,
             syscall: self.base.syscall,
             container_id: self.base.container_id,
+            root_path: self.base.root_path.clone(),
             pid_file: self.base.pid_file,
-            console_socket: csocketfd,
+            console_socket: csocketfd.or(attach_console),
+            attach_stdio,
             use_systemd: self.use_systemd,
             spec: &spec,
             rootfs,
@@ -81,16 +180,37 @@ impl<'a> InitContainerBuilder<'a> {
             notify_path,
             container: Some(container.clone()),
             preserve_fds: self.base.preserve_fds,
+            experimental,
+            progress,
         };
 
-        builder_impl.create()?;
+        let result = builder_impl.create();
+        progress.close();
+        // only the container holds the pipes or the console from now on, so
+        // that the relay sees when the container exits
+        if let Some(attach_stdio) = attach_stdio {
+            attach_stdio.close();
+        }
+        if let Some(console) = attach_console {
+            let _ = unistd::close(console);
+        }
+        result?;
         container.refresh_state()?;
 
+        runtime_metrics::observe("create", start.elapsed());
+        runtime_metrics::flush(&container_dir);
+
         Ok(container)
     }
 
     fn create_container_dir(&self) -> Result<PathBuf> {
         let container_dir = self.base.root_path.join(&self.base.container_id);
+        if !Container::is_container_dir(&container_dir) {
+            bail!(
+                "invalid container id {}, it must not start with a dot",
+                self.base.container_id
+            );
+        }
         log::debug!("container directory will be {:?}", container_dir);
 
         if container_dir.exists() {
@@ -118,21 +238,65 @@ impl<'a> InitContainerBuilder<'a> {
             );
         }
 
-        if let Some(process) = spec.process() {
-            if let Some(profile) = process.apparmor_profile() {
-                if !apparmor::is_enabled()? {
-                    bail!(
-                        "apparmor profile {} is specified in runtime spec, \
-                    but apparmor is not activated on this system",
-                        profile
-                    );
+        Ok(())
+    }
+
+    fn validate_lsm(&self, spec: &mut Spec) -> Result<()> {
+        let mut process = match spec.process() {
+            Some(process) => process.clone(),
+            None => return Ok(()),
+        };
+
+        if let Some(profile) = process.apparmor_profile() {
+            if let Err(err) = lsm::check_apparmor_profile(profile) {
+                if !self.permissive_lsm {
+                    return Err(err);
+                }
+
+                log::warn!("{}, continue without apparmor profile", err);
+                process.set_apparmor_profile(None);
+            }
+        }
+
+        if let Some(label) = process.selinux_label() {
+            if let Err(err) = lsm::check_selinux_label(label) {
+                if !self.permissive_lsm {
+                    return Err(err);
                 }
+
+                log::warn!("{}, continue without selinux label", err);
+                process.set_selinux_label(None);
             }
         }
 
+        spec.set_process(Some(process));
         Ok(())
     }
 
+    /// Allocates the SELinux labels of the container. Returns the lock on the
+    /// allocation, which is held until the label is saved in the state.
+    fn assign_selinux_labels(&self, spec: &mut Spec) -> Result<Option<fs::File>> {
+        if !selinux::is_enabled() {
+            log::debug!("SELinux is not enabled, skip label allocation");
+            return Ok(None);
+        }
+
+        let mut process = spec.process().clone().context("no process in spec")?;
+        let mut linux = spec.linux().clone().context("no linux in spec")?;
+        if process.selinux_label().is_some() || linux.mount_label().is_some() {
+            return Ok(None);
+        }
+
+        let lock = selinux::lock_allocation(&self.base.root_path)?;
+        let labels = selinux::allocate_labels(&self.base.root_path)?;
+        log::debug!("allocated SELinux labels {:?}", labels);
+        process.set_selinux_label(Some(labels.process_label));
+        linux.set_mount_label(Some(labels.mount_label));
+        spec.set_process(Some(process)).set_linux(Some(linux));
+
+        Ok(Some(lock))
+    }
+
     fn create_container_state(&self, container_dir: &Path) -> Result<Container> {
         let container = Container::new(
             &self.base.container_id,
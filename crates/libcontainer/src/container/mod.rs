@@ -8,14 +8,25 @@ pub mod builder;
 mod builder_impl;
 #[allow(clippy::module_inception)]
 mod container;
+mod container_checkpoint;
 mod container_delete;
 mod container_events;
+mod container_gc;
 mod container_kill;
 mod container_pause;
+mod container_restore;
 mod container_resume;
 mod container_start;
+mod container_stats;
+mod container_wait;
 pub mod init_builder;
+pub mod runtime_metrics;
 pub mod state;
 pub mod tenant_builder;
+mod tenant_user;
 pub use container::Container;
-pub use state::{ContainerProcessState, ContainerStatus, State};
+pub use container_checkpoint::CheckpointOptions;
+pub use container_events::Event;
+pub use container_gc::DEFAULT_ORPHAN_MIN_AGE;
+pub use container_restore::RestoreOptions;
+pub use state::{CheckpointState, ContainerProcessState, ContainerStatus, ContainerSummary, State};
@@ -0,0 +1,240 @@
+//! Metrics about the operations of the runtime itself, e.g. how long the
+//! creation of a container or the hooks took. Timings are collected in memory
+//! while an operation runs and are added to the metrics file in the container
+//! directory once it finished, so that regressions of the runtime can be
+//! observed with `youki events --runtime-metrics`.
+//!
+//! Only timings observed by the youki process are recorded. The
+//! createContainer and startContainer hooks run in the container process and
+//! are part of the create and start latency instead.
+use std::{
+    collections::BTreeMap,
+    fs,
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use nix::fcntl::{flock, FlockArg};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+const RUNTIME_METRICS_FILE: &str = "runtime_metrics.json";
+/// Serializes the updates of the metrics file by concurrent youki processes
+const RUNTIME_METRICS_LOCK: &str = "runtime_metrics.lock";
+
+/// Timings that have been observed by this process, but not yet saved
+static PENDING: Lazy<Mutex<Vec<(String, Duration)>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Aggregated durations of an operation in microseconds
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OperationMetrics {
+    /// Number of times the operation has been observed
+    pub count: u64,
+    /// Duration of the latest observation
+    pub last_us: u64,
+    /// Longest duration of all observations
+    pub max_us: u64,
+    /// Sum of the durations of all observations
+    pub total_us: u64,
+}
+
+impl OperationMetrics {
+    fn observe(&mut self, duration: Duration) {
+        let us = duration.as_micros() as u64;
+        self.count += 1;
+        self.last_us = us;
+        self.max_us = self.max_us.max(us);
+        self.total_us = self.total_us.saturating_add(us);
+    }
+
+    /// Mean duration of the operation in microseconds
+    pub fn mean_us(&self) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+
+        self.total_us / self.count
+    }
+}
+
+/// Metrics of the runtime operations on a container, keyed by operation, e.g.
+/// `create`, `start`, `exec`, `hook.prestart` or `sync.init_ready`
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuntimeMetrics {
+    pub operations: BTreeMap<String, OperationMetrics>,
+}
+
+impl RuntimeMetrics {
+    /// Loads the metrics of a container. A container for which no operation
+    /// has been recorded yet has empty metrics.
+    pub fn load(container_root: &Path) -> Result<Self> {
+        let path = container_root.join(RUNTIME_METRICS_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let file = fs::File::open(&path).with_context(|| format!("failed to open {:?}", path))?;
+        serde_json::from_reader(&file).with_context(|| format!("failed to parse {:?}", path))
+    }
+
+    fn save(&self, container_root: &Path) -> Result<()> {
+        // the metrics are replaced atomically, so that a concurrent reader
+        // never sees a partially written file
+        let tmp_path = container_root.join(format!(
+            "{}.{}.tmp",
+            RUNTIME_METRICS_FILE,
+            std::process::id()
+        ));
+        let result = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o644)
+            .open(&tmp_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| Ok(serde_json::to_writer(&file, self)?))
+            .and_then(|()| {
+                Ok(fs::rename(
+                    &tmp_path,
+                    container_root.join(RUNTIME_METRICS_FILE),
+                )?)
+            });
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        result.with_context(|| format!("failed to save runtime metrics to {:?}", container_root))
+    }
+
+    pub fn observe(&mut self, operation: &str, duration: Duration) {
+        self.operations
+            .entry(operation.to_owned())
+            .or_default()
+            .observe(duration);
+    }
+}
+
+/// Records the duration of an operation. It is saved with the next [`flush`].
+pub fn observe(operation: &str, duration: Duration) {
+    PENDING
+        .lock()
+        .unwrap()
+        .push((operation.to_owned(), duration));
+}
+
+/// Runs the function and records how long it took
+pub fn timed<T, F: FnOnce() -> T>(operation: &str, f: F) -> T {
+    let start = Instant::now();
+    let result = f();
+    observe(operation, start.elapsed());
+    result
+}
+
+/// Drops the timings left behind by an operation that failed before they
+/// could be saved, so that they are not attributed to another container
+pub(crate) fn discard() {
+    PENDING.lock().unwrap().clear();
+}
+
+/// Adds the pending timings to the metrics of the container. Metrics are
+/// purely informational, so failing to save them only logs a warning.
+pub fn flush(container_root: &Path) {
+    let pending: Vec<_> = PENDING.lock().unwrap().drain(..).collect();
+    if pending.is_empty() {
+        return;
+    }
+
+    if let Err(err) = add(container_root, pending) {
+        log::warn!(
+            "failed to save runtime metrics of {:?}: {:?}",
+            container_root,
+            err
+        );
+    }
+}
+
+/// Adds the timings to the metrics file of the container. The file is locked
+/// while it is updated, so that concurrent operations on the same container,
+/// e.g. several execs, do not lose each other's observations.
+fn add(container_root: &Path, timings: Vec<(String, Duration)>) -> Result<()> {
+    let lock_path = container_root.join(RUNTIME_METRICS_LOCK);
+    let lock = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .mode(0o600)
+        .open(&lock_path)
+        .with_context(|| format!("failed to open {:?}", lock_path))?;
+    // the lock is released when the file is closed
+    flock(lock.as_raw_fd(), FlockArg::LockExclusive)
+        .with_context(|| format!("failed to lock {:?}", lock_path))?;
+
+    let mut metrics = RuntimeMetrics::load(container_root)?;
+    for (operation, duration) in timings {
+        metrics.observe(&operation, duration);
+    }
+    metrics.save(container_root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+
+    #[test]
+    fn test_observe() {
+        let mut metrics = RuntimeMetrics::default();
+        metrics.observe("create", Duration::from_micros(300));
+        metrics.observe("create", Duration::from_micros(100));
+
+        let create = &metrics.operations["create"];
+        assert_eq!(create.count, 2);
+        assert_eq!(create.last_us, 100);
+        assert_eq!(create.max_us, 300);
+        assert_eq!(create.total_us, 400);
+        assert_eq!(create.mean_us(), 200);
+    }
+
+    #[test]
+    fn test_save_and_load() -> Result<()> {
+        let tmp = create_temp_dir("test_runtime_metrics_save_and_load")?;
+        assert_eq!(RuntimeMetrics::load(tmp.path())?, RuntimeMetrics::default());
+
+        let mut metrics = RuntimeMetrics::default();
+        metrics.observe("start", Duration::from_millis(2));
+        metrics.save(tmp.path())?;
+        assert_eq!(RuntimeMetrics::load(tmp.path())?, metrics);
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_add() -> Result<()> {
+        let tmp = create_temp_dir("test_runtime_metrics_concurrent_add")?;
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let root = tmp.path().to_owned();
+                std::thread::spawn(move || -> Result<()> {
+                    for _ in 0..20 {
+                        add(&root, vec![("exec".to_owned(), Duration::from_micros(10))])?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap()?;
+        }
+
+        // no observation is lost and no temporary file is left behind
+        assert_eq!(
+            RuntimeMetrics::load(tmp.path())?.operations["exec"].count,
+            160
+        );
+        let mut files: Vec<_> = fs::read_dir(tmp.path())?
+            .map(|entry| Ok(entry?.file_name()))
+            .collect::<Result<_>>()?;
+        files.sort();
+        assert_eq!(files, vec![RUNTIME_METRICS_FILE, RUNTIME_METRICS_LOCK]);
+        Ok(())
+    }
+}
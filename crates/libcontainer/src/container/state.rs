@@ -1,14 +1,20 @@
 //! Information about status and state of the container
 use std::collections::HashMap;
+use std::ffi::OsString;
 use std::fmt::Display;
 use std::fs;
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::{fs::File, path::Path};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use chrono::{DateTime, Utc};
+use nix::unistd::Pid;
 use serde::{Deserialize, Serialize};
 
+use super::container::current_status;
+use crate::syscall::syscall::create_syscall;
+
 /// Indicates status of the container
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
@@ -71,6 +77,21 @@ impl Display for ContainerStatus {
     }
 }
 
+impl FromStr for ContainerStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(status: &str) -> Result<Self> {
+        match status.to_ascii_lowercase().as_str() {
+            "creating" => Ok(Self::Creating),
+            "created" => Ok(Self::Created),
+            "running" => Ok(Self::Running),
+            "stopped" => Ok(Self::Stopped),
+            "paused" => Ok(Self::Paused),
+            _ => bail!("unknown container status {}", status),
+        }
+    }
+}
+
 /// Stores the state information of the container
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 #[serde(rename_all = "camelCase")]
@@ -97,6 +118,18 @@ pub struct State {
     pub creator: Option<u32>,
     // Specifies if systemd should be used to manage cgroups
     pub use_systemd: Option<bool>,
+    // SELinux label of the container process
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selinux_label: Option<String>,
+    // Latest checkpoint of the container
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checkpoint: Option<CheckpointState>,
+    // Paths of the cgroup of the container, keyed by subsystem or hierarchy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cgroup_paths: Option<HashMap<String, PathBuf>>,
+    // Whether a process of the container has been killed by the oom killer
+    #[serde(default)]
+    pub oom_killed: bool,
 }
 
 impl State {
@@ -118,6 +151,10 @@ impl State {
             created: None,
             creator: None,
             use_systemd: None,
+            selinux_label: None,
+            checkpoint: None,
+            cgroup_paths: None,
+            oom_killed: false,
         }
     }
 
@@ -132,6 +169,12 @@ impl State {
             .open(&state_file_path)
             .with_context(|| format!("failed to open {}", state_file_path.display()))?;
         serde_json::to_writer(&file, self)?;
+
+        // the state remains the source of truth, a missing or outdated
+        // summary only makes listing the container slower
+        if let Err(err) = ContainerSummary::from(self).save(container_root) {
+            log::warn!("failed to save summary of container {}: {:?}", self.id, err);
+        }
         Ok(())
     }
 
@@ -159,6 +202,105 @@ impl State {
     }
 }
 
+/// The part of the state of a container that is shown when listing
+/// containers. It is saved next to the state on every change of the state,
+/// so that listing many containers does not have to parse every full state.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainerSummary {
+    pub id: String,
+    pub status: ContainerStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<i32>,
+    pub bundle: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub creator: Option<u32>,
+}
+
+impl From<&State> for ContainerSummary {
+    fn from(state: &State) -> Self {
+        Self {
+            id: state.id.clone(),
+            status: state.status,
+            pid: state.pid,
+            bundle: state.bundle.clone(),
+            created: state.created,
+            creator: state.creator,
+        }
+    }
+}
+
+impl ContainerSummary {
+    const SUMMARY_FILE_PATH: &'static str = "summary.json";
+
+    fn save(&self, container_root: &Path) -> Result<()> {
+        // the summary is replaced atomically, so that a concurrent list never
+        // sees a partially written file
+        let tmp_path = container_root.join(format!("{}.tmp", Self::SUMMARY_FILE_PATH));
+        let file = File::create(&tmp_path)?;
+        serde_json::to_writer(&file, self)?;
+        fs::rename(&tmp_path, container_root.join(Self::SUMMARY_FILE_PATH))?;
+        Ok(())
+    }
+
+    /// Loads the summary of a container and refreshes its status. The state
+    /// is loaded instead if the summary is missing or older than the state,
+    /// e.g. because the state has been written by an older version.
+    pub fn load(container_root: &Path) -> Result<Self> {
+        let summary_path = container_root.join(Self::SUMMARY_FILE_PATH);
+        let mut summary = match Self::is_current(container_root) {
+            true => {
+                let file = File::open(&summary_path)
+                    .with_context(|| format!("failed to open {:?}", summary_path))?;
+                serde_json::from_reader(&file)
+                    .with_context(|| format!("failed to parse {:?}", summary_path))?
+            }
+            false => Self::from(&State::load(container_root)?),
+        };
+
+        summary.status = current_status(summary.pid.map(Pid::from_raw), summary.status)?;
+        Ok(summary)
+    }
+
+    /// Returns the name of the user that created the container
+    pub fn creator_name(&self) -> Option<OsString> {
+        let uid = self.creator?;
+        create_syscall()
+            .get_pwuid(uid)
+            .map(|name| (&*name).to_owned())
+    }
+
+    fn is_current(container_root: &Path) -> bool {
+        let modified = |path: PathBuf| fs::metadata(path).and_then(|m| m.modified()).ok();
+        match (
+            modified(container_root.join(Self::SUMMARY_FILE_PATH)),
+            modified(State::file_path(container_root)),
+        ) {
+            (Some(summary), Some(state)) => summary >= state,
+            _ => false,
+        }
+    }
+}
+
+/// Describes where the images of a checkpoint have been stored
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckpointState {
+    // Directory that contains the images
+    pub image_path: PathBuf,
+    // Directory that contains the logs of criu
+    pub work_path: PathBuf,
+    // Images of the previous dump, relative to the image path
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_path: Option<PathBuf>,
+    // Time the checkpoint has been taken
+    pub created: DateTime<Utc>,
+    // If the container kept running after the checkpoint
+    pub leave_running: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct ContainerProcessState {
@@ -229,4 +371,40 @@ mod tests {
         assert!(!cstatus.can_pause());
         assert!(cstatus.can_resume());
     }
+
+    #[test]
+    fn test_parse_status() {
+        assert_eq!(
+            "running".parse::<ContainerStatus>().unwrap(),
+            ContainerStatus::Running
+        );
+        assert_eq!(
+            "Stopped".parse::<ContainerStatus>().unwrap(),
+            ContainerStatus::Stopped
+        );
+        assert!("exited".parse::<ContainerStatus>().is_err());
+    }
+
+    #[test]
+    fn test_container_summary() -> Result<()> {
+        let tmp = crate::utils::create_temp_dir("test_container_summary")?;
+        let state = State::new(
+            "summary",
+            ContainerStatus::Created,
+            None,
+            PathBuf::from("/b"),
+        );
+        state.save(&tmp)?;
+
+        let summary = ContainerSummary::load(&tmp)?;
+        assert_eq!(summary.id, "summary");
+        assert_eq!(summary.bundle, PathBuf::from("/b"));
+        // without a process the container is stopped
+        assert_eq!(summary.status, ContainerStatus::Stopped);
+
+        // the state is used if there is no summary
+        fs::remove_file(tmp.join(ContainerSummary::SUMMARY_FILE_PATH))?;
+        assert_eq!(ContainerSummary::load(&tmp)?.id, "summary");
+        Ok(())
+    }
 }
@@ -4,7 +4,7 @@ use nix::unistd;
 use oci_spec::runtime::{
     Capabilities as SpecCapabilities, Capability as SpecCapability, LinuxBuilder,
     LinuxCapabilities, LinuxCapabilitiesBuilder, LinuxNamespace, LinuxNamespaceBuilder,
-    LinuxNamespaceType, Process, ProcessBuilder, Spec,
+    LinuxNamespaceType, Process, ProcessBuilder, Spec, User,
 };
 use procfs::process::Namespace;
 
@@ -15,14 +15,18 @@ use std::{
     os::unix::prelude::RawFd,
     path::{Path, PathBuf},
     str::FromStr,
+    time::Instant,
 };
 
-use crate::{capabilities::CapabilityExt, container::builder_impl::ContainerBuilderImpl};
+use crate::{
+    capabilities::CapabilityExt, config, container::builder_impl::ContainerBuilderImpl,
+    experimental::ExperimentalFeatures, progress::Progress,
+};
 use crate::{notify_socket::NotifySocket, rootless::Rootless, tty, utils};
 
-use super::{builder::ContainerBuilder, Container};
+use super::{builder::ContainerBuilder, runtime_metrics, tenant_user, Container};
 
-const NAMESPACE_TYPES: &[&str] = &["ipc", "uts", "net", "pid", "mnt", "cgroup"];
+const NAMESPACE_TYPES: &[&str] = &["user", "ipc", "uts", "net", "pid", "mnt", "cgroup"];
 const TENANT_NOTIFY: &str = "tenant-notify-";
 const TENANT_TTY: &str = "tenant-tty-";
 
@@ -36,6 +40,8 @@ pub struct TenantContainerBuilder<'a> {
     no_new_privs: Option<bool>,
     capabilities: Vec<String>,
     process: Option<PathBuf>,
+    user: Option<(u32, Option<u32>)>,
+    additional_gids: Vec<u32>,
 }
 
 impl<'a> TenantContainerBuilder<'a> {
@@ -51,6 +57,8 @@ impl<'a> TenantContainerBuilder<'a> {
             no_new_privs: None,
             capabilities: Vec::new(),
             process: None,
+            user: None,
+            additional_gids: Vec::new(),
         }
     }
 
@@ -87,8 +95,25 @@ impl<'a> TenantContainerBuilder<'a> {
         self
     }
 
+    /// Sets the user the process runs as. The ids are interpreted in the user
+    /// namespace of the container. If no gid is specified, the primary group of
+    /// the user in the container is used.
+    pub fn with_user(mut self, uid: u32, gid: Option<u32>) -> Self {
+        self.user = Some((uid, gid));
+        self
+    }
+
+    /// Sets supplementary groups of the process in addition to the groups the
+    /// user is a member of in the container
+    pub fn with_additional_gids(mut self, gids: Vec<u32>) -> Self {
+        self.additional_gids = gids;
+        self
+    }
+
     /// Joins an existing container
     pub fn build(self) -> Result<()> {
+        let start = Instant::now();
+        runtime_metrics::discard();
         let container_dir = self
             .lookup_container_dir()
             .context("failed to look up container dir")?;
@@ -114,13 +139,17 @@ impl<'a> TenantContainerBuilder<'a> {
 
         let use_systemd = self.should_use_systemd(&container);
         let rootless = Rootless::new(&spec)?;
+        let experimental = ExperimentalFeatures::from_annotations(spec.annotations().as_ref())
+            .context("invalid experimental feature annotations")?;
 
         let mut builder_impl = ContainerBuilderImpl {
             init: false,
             syscall: self.base.syscall,
             container_id: self.base.container_id,
+            root_path: self.base.root_path.clone(),
             pid_file: self.base.pid_file,
             console_socket: csocketfd,
+            attach_stdio: None,
             use_systemd,
             spec: &spec,
             rootfs,
@@ -128,12 +157,17 @@ impl<'a> TenantContainerBuilder<'a> {
             notify_path: notify_path.clone(),
             container: None,
             preserve_fds: self.base.preserve_fds,
+            experimental,
+            progress: Progress::default(),
         };
 
         builder_impl.create()?;
 
         let mut notify_socket = NotifySocket::new(notify_path);
         notify_socket.notify_container_start()?;
+
+        runtime_metrics::observe("exec", start.elapsed());
+        runtime_metrics::flush(&container_dir);
         Ok(())
     }
 
@@ -147,10 +181,7 @@ impl<'a> TenantContainerBuilder<'a> {
     }
 
     fn load_init_spec(&self, container_dir: &Path) -> Result<Spec> {
-        let spec_path = container_dir.join("config.json");
-
-        let spec = Spec::load(spec_path).context("failed to load spec")?;
-        Ok(spec)
+        config::load_runtime_spec(container_dir)
     }
 
     fn load_container_state(&self, container_dir: PathBuf) -> Result<Container> {
@@ -167,7 +198,13 @@ impl<'a> TenantContainerBuilder<'a> {
 
     fn adapt_spec_for_tenant(&self, spec: &mut Spec, container: &Container) -> Result<()> {
         let process = if let Some(process) = &self.process {
-            self.get_process(process)?
+            // capabilities requested in addition are granted on top of the
+            // capabilities of the process.json
+            let mut process = self.get_process(process)?;
+            if let Some(caps) = self.get_capabilities(process.capabilities().as_ref())? {
+                process.set_capabilities(Some(caps));
+            }
+            process
         } else {
             let mut process_builder = ProcessBuilder::default()
                 .args(self.get_args()?)
@@ -180,10 +217,24 @@ impl<'a> TenantContainerBuilder<'a> {
                 process_builder = process_builder.no_new_privileges(no_new_priv);
             }
 
-            if let Some(caps) = self.get_capabilities(spec)? {
+            let init_caps = spec
+                .process()
+                .as_ref()
+                .context("no process in spec")?
+                .capabilities()
+                .as_ref();
+            if let Some(caps) = self.get_capabilities(init_caps)? {
                 process_builder = process_builder.capabilities(caps);
             }
 
+            let init_uid = spec
+                .process()
+                .as_ref()
+                .map_or(0, |process| process.user().uid());
+            if let Some(user) = self.get_user(container, init_uid)? {
+                process_builder = process_builder.user(user);
+            }
+
             process_builder.build()?
         };
 
@@ -245,22 +296,20 @@ impl<'a> TenantContainerBuilder<'a> {
         self.no_new_privs
     }
 
-    fn get_capabilities(&self, spec: &Spec) -> Result<Option<LinuxCapabilities>> {
+    fn get_capabilities(
+        &self,
+        base_caps: Option<&LinuxCapabilities>,
+    ) -> Result<Option<LinuxCapabilities>> {
         if !self.capabilities.is_empty() {
-            let mut caps: Vec<Capability> = Vec::with_capacity(self.capabilities.len());
-            for cap in &self.capabilities {
-                caps.push(Capability::from_str(cap)?);
-            }
-
-            let caps: SpecCapabilities =
-                caps.iter().map(|c| SpecCapability::from_cap(*c)).collect();
-
-            if let Some(spec_caps) = spec
-                .process()
-                .as_ref()
-                .context("no process in spec")?
-                .capabilities()
-            {
+            // All capabilities are validated before any of them is granted, so
+            // that the process either gets all requested capabilities or none.
+            let caps: SpecCapabilities = self
+                .capabilities
+                .iter()
+                .map(|cap| parse_capability(cap))
+                .collect::<Result<_>>()?;
+
+            if let Some(spec_caps) = base_caps {
                 let mut capabilities_builder = LinuxCapabilitiesBuilder::default();
                 capabilities_builder = match spec_caps.ambient() {
                     Some(ambient) => {
@@ -317,11 +366,39 @@ impl<'a> TenantContainerBuilder<'a> {
         Ok(None)
     }
 
+    /// Resolves the user of the tenant process. Additional gids without a
+    /// user are added to the user of the init process.
+    fn get_user(&self, container: &Container, init_uid: u32) -> Result<Option<User>> {
+        let (uid, gid) = match self.user {
+            Some(user) => user,
+            None if self.additional_gids.is_empty() => return Ok(None),
+            None => (init_uid, None),
+        };
+
+        let init_pid = container
+            .pid()
+            .context("could not retrieve container init pid")?;
+        let user = tenant_user::resolve_user(init_pid.as_raw(), uid, gid, &self.additional_gids)
+            .with_context(|| format!("failed to resolve user {} in container", uid))?;
+        Ok(Some(user))
+    }
+
     fn get_namespaces(&self, init_namespaces: Vec<Namespace>) -> Result<Vec<LinuxNamespace>> {
         let mut tenant_namespaces = Vec::with_capacity(init_namespaces.len());
+        let own_namespaces = procfs::process::Process::myself()?.namespaces()?;
 
         for &ns_type in NAMESPACE_TYPES {
             if let Some(init_ns) = init_namespaces.iter().find(|n| n.ns_type == ns_type) {
+                // A user namespace cannot be re-entered, so it is only joined if
+                // the container has been created with its own user namespace.
+                if ns_type == "user"
+                    && own_namespaces
+                        .iter()
+                        .any(|n| n.ns_type == ns_type && n.identifier == init_ns.identifier)
+                {
+                    continue;
+                }
+
                 let tenant_ns = LinuxNamespaceType::try_from(ns_type)?;
                 tenant_namespaces.push(
                     LinuxNamespaceBuilder::default()
@@ -375,3 +452,31 @@ impl<'a> TenantContainerBuilder<'a> {
         }
     }
 }
+
+/// Parses a capability name. The CAP_ prefix is optional and the name is case
+/// insensitive, e.g. NET_ADMIN, cap_net_admin and CAP_NET_ADMIN are equivalent.
+fn parse_capability(cap: &str) -> Result<SpecCapability> {
+    let name = cap.to_uppercase();
+    let name = if name.starts_with("CAP_") {
+        name
+    } else {
+        format!("CAP_{}", name)
+    };
+
+    let cap = Capability::from_str(&name).with_context(|| format!("unknown capability {}", cap))?;
+    Ok(SpecCapability::from_cap(cap))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_capability() -> Result<()> {
+        for name in ["NET_ADMIN", "cap_net_admin", "CAP_NET_ADMIN", "net_admin"] {
+            assert_eq!(parse_capability(name)?, SpecCapability::NetAdmin);
+        }
+        assert!(parse_capability("CAP_DOES_NOT_EXIST").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,256 @@
+//! Resolves the identity of a process that joins an existing container, so
+//! that it matches the identity a process started by the container would have.
+use anyhow::{bail, Context, Result};
+use nix::errno::Errno;
+use oci_spec::runtime::{User, UserBuilder};
+use std::{
+    ffi::CString,
+    fs::{self, File},
+    io::Read,
+    mem,
+    os::unix::io::{AsRawFd, FromRawFd, RawFd},
+    path::PathBuf,
+};
+
+// Flags of openat2(2)
+const RESOLVE_NO_MAGICLINKS: u64 = 0x02;
+const RESOLVE_IN_ROOT: u64 = 0x10;
+
+/// Maximum number of bytes that are read from /etc/passwd and /etc/group of
+/// the container
+const MAX_ID_FILE_SIZE: u64 = 4 << 20;
+
+#[repr(C)]
+struct OpenHow {
+    flags: u64,
+    mode: u64,
+    resolve: u64,
+}
+
+/// Builds the user of a tenant process from the requested uid and gid. The
+/// primary gid and the supplementary groups are looked up from /etc/passwd and
+/// /etc/group of the container if they have not been specified. All ids are
+/// checked against the id mappings of the user namespace of the container.
+pub(super) fn resolve_user(
+    init_pid: i32,
+    uid: u32,
+    gid: Option<u32>,
+    additional_gids: &[u32],
+) -> Result<User> {
+    let proc_path = PathBuf::from(format!("/proc/{}", init_pid));
+    // The files are read through the root of the init process, so that they
+    // are resolved in the mount namespace of the container.
+    let root = File::open(proc_path.join("root"))
+        .with_context(|| format!("failed to open the root of {}", init_pid))?;
+    let passwd = read_container_file(&root, "/etc/passwd")?.unwrap_or_default();
+    let group = read_container_file(&root, "/etc/group")?.unwrap_or_default();
+
+    let user_entry = lookup_user(&passwd, uid);
+    let gid = gid
+        .or_else(|| user_entry.as_ref().map(|(_, gid)| *gid))
+        .unwrap_or(0);
+
+    let uid_map = fs::read_to_string(proc_path.join("uid_map"))
+        .with_context(|| format!("failed to read uid mappings of {}", init_pid))?;
+    let gid_map = fs::read_to_string(proc_path.join("gid_map"))
+        .with_context(|| format!("failed to read gid mappings of {}", init_pid))?;
+    if !is_mapped(&uid_map, uid) {
+        bail!(
+            "uid {} is not mapped in the user namespace of the container",
+            uid
+        );
+    }
+    if !is_mapped(&gid_map, gid) {
+        bail!(
+            "gid {} is not mapped in the user namespace of the container",
+            gid
+        );
+    }
+
+    let mut gids = Vec::new();
+    for &additional_gid in additional_gids {
+        if !is_mapped(&gid_map, additional_gid) {
+            bail!(
+                "additional gid {} is not mapped in the user namespace of the container",
+                additional_gid
+            );
+        }
+        gids.push(additional_gid);
+    }
+
+    // Supplementary groups can only be set if setgroups has not been disabled
+    // for the user namespace, which is the case for rootless containers.
+    let setgroups = fs::read_to_string(proc_path.join("setgroups")).unwrap_or_default();
+    if let Some((name, _)) = &user_entry {
+        let groups = lookup_groups(&group, name, gid);
+        if setgroups.trim() == "deny" {
+            if !groups.is_empty() {
+                log::warn!(
+                    "setgroups is disabled in the container, ignore supplementary groups {:?} of {}",
+                    groups,
+                    name
+                );
+            }
+        } else {
+            for group_gid in groups {
+                if is_mapped(&gid_map, group_gid) && !gids.contains(&group_gid) {
+                    gids.push(group_gid);
+                }
+            }
+        }
+    }
+
+    let mut builder = UserBuilder::default().uid(uid).gid(gid);
+    if !gids.is_empty() {
+        builder = builder.additional_gids(gids);
+    }
+    builder.build().context("failed to build user")
+}
+
+/// Reads a file of the container. The path is resolved with openat2 as if
+/// the root of the container was the root of the host, so that symbolic links
+/// of the container cannot lead to files of the host. Only regular files are
+/// read, up to `MAX_ID_FILE_SIZE` bytes. Returns None if the file does not
+/// exist.
+fn read_container_file(root: &File, path: &str) -> Result<Option<String>> {
+    let c_path = CString::new(path)?;
+    let how = OpenHow {
+        // a fifo must not block the open
+        flags: (libc::O_RDONLY | libc::O_CLOEXEC | libc::O_NOCTTY | libc::O_NONBLOCK) as u64,
+        mode: 0,
+        resolve: RESOLVE_IN_ROOT | RESOLVE_NO_MAGICLINKS,
+    };
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_openat2,
+            root.as_raw_fd(),
+            c_path.as_ptr(),
+            &how as *const OpenHow,
+            mem::size_of::<OpenHow>(),
+        )
+    };
+    let file = match Errno::result(res) {
+        Ok(fd) => unsafe { File::from_raw_fd(fd as RawFd) },
+        Err(Errno::ENOENT) => return Ok(None),
+        Err(err) => bail!("failed to open {} of the container: {}", path, err),
+    };
+
+    if !file.metadata()?.is_file() {
+        bail!("{} of the container is not a regular file", path);
+    }
+
+    let mut content = Vec::new();
+    file.take(MAX_ID_FILE_SIZE)
+        .read_to_end(&mut content)
+        .with_context(|| format!("failed to read {} of the container", path))?;
+    Ok(Some(String::from_utf8_lossy(&content).into_owned()))
+}
+
+/// Checks if the id lies in one of the ranges of an uid_map or gid_map file
+fn is_mapped(id_map: &str, id: u32) -> bool {
+    id_map.lines().any(|line| {
+        let fields: Vec<u64> = line
+            .split_whitespace()
+            .filter_map(|field| field.parse().ok())
+            .collect();
+        match fields[..] {
+            [inside, _, count] => (inside..inside + count).contains(&(id as u64)),
+            _ => false,
+        }
+    })
+}
+
+/// Returns the name and primary gid of the user with the uid
+fn lookup_user(passwd: &str, uid: u32) -> Option<(String, u32)> {
+    passwd.lines().find_map(|line| {
+        // name:password:uid:gid:gecos:home:shell
+        let fields: Vec<&str> = line.split(':').collect();
+        if fields.len() < 4 || fields[2].parse::<u32>() != Ok(uid) {
+            return None;
+        }
+
+        Some((fields[0].to_owned(), fields[3].parse().ok()?))
+    })
+}
+
+/// Returns the gids of all groups the user is a member of, except the primary group
+fn lookup_groups(group: &str, name: &str, primary_gid: u32) -> Vec<u32> {
+    group
+        .lines()
+        .filter_map(|line| {
+            // name:password:gid:member,member
+            let fields: Vec<&str> = line.split(':').collect();
+            if fields.len() < 4 || !fields[3].split(',').any(|member| member == name) {
+                return None;
+            }
+
+            fields[2].parse().ok()
+        })
+        .filter(|gid| *gid != primary_gid)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+
+    #[test]
+    fn test_is_mapped() {
+        let id_map = "         0       1000          1\n         1     100000      65536\n";
+        assert!(is_mapped(id_map, 0));
+        assert!(is_mapped(id_map, 1));
+        assert!(is_mapped(id_map, 65536));
+        assert!(!is_mapped(id_map, 65537));
+
+        // the initial user namespace maps the full range
+        let initial = "         0          0 4294967295\n";
+        assert!(is_mapped(initial, 4294967294));
+    }
+
+    #[test]
+    fn test_lookup_user() {
+        let passwd = "root:x:0:0:root:/root:/bin/sh\n\
+                      daemon:x:1:1:daemon:/usr/sbin:/usr/sbin/nologin\n\
+                      app:x:1000:1001::/home/app:/bin/sh\n";
+        assert_eq!(lookup_user(passwd, 0), Some(("root".to_owned(), 0)));
+        assert_eq!(lookup_user(passwd, 1000), Some(("app".to_owned(), 1001)));
+        assert_eq!(lookup_user(passwd, 1001), None);
+    }
+
+    #[test]
+    fn test_lookup_groups() {
+        let group = "root:x:0:\n\
+                     wheel:x:10:root,app\n\
+                     app:x:1001:app\n\
+                     video:x:44:app\n\
+                     audio:x:63:other\n";
+        assert_eq!(lookup_groups(group, "app", 1001), vec![10, 44]);
+        assert_eq!(lookup_groups(group, "root", 0), vec![10]);
+        assert!(lookup_groups(group, "nobody", 65534).is_empty());
+    }
+
+    #[test]
+    fn test_read_container_file() -> Result<()> {
+        let tmp = create_temp_dir("test_read_container_file")?;
+        let etc = tmp.path().join("etc");
+        fs::create_dir(&etc)?;
+        fs::write(etc.join("passwd"), "root:x:0:0:root:/root:/bin/sh\n")?;
+        // an absolute link is resolved in the root of the container
+        std::os::unix::fs::symlink("/../../../etc/passwd", etc.join("group"))?;
+        fs::create_dir(etc.join("shadow"))?;
+
+        let root = File::open(tmp.path())?;
+        assert_eq!(
+            read_container_file(&root, "/etc/passwd")?.as_deref(),
+            Some("root:x:0:0:root:/root:/bin/sh\n")
+        );
+        assert_eq!(
+            read_container_file(&root, "/etc/group")?,
+            read_container_file(&root, "/etc/passwd")?
+        );
+        assert!(read_container_file(&root, "/etc/shadow").is_err());
+        assert_eq!(read_container_file(&root, "/etc/gshadow")?, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+//! Detection of the version and the features of the installed criu binary,
+//! which is used to checkpoint and restore containers.
+use anyhow::{bail, Context, Result};
+use std::fmt::Display;
+use std::process::{Command, Stdio};
+
+pub const CRIU_BINARY: &str = "criu";
+
+/// Oldest version of criu that youki can work with
+pub const MIN_VERSION: CriuVersion = CriuVersion::new(3, 0, 0);
+
+/// Version of criu
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CriuVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub sublevel: u32,
+}
+
+impl CriuVersion {
+    pub const fn new(major: u32, minor: u32, sublevel: u32) -> Self {
+        Self {
+            major,
+            minor,
+            sublevel,
+        }
+    }
+
+    /// Parses the output of `criu --version`, which looks like
+    /// `Version: 3.16.1`, followed by the git id for development builds.
+    pub fn parse(output: &str) -> Result<Self> {
+        let version = output
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("Version:"))
+            .with_context(|| format!("no version in criu output {:?}", output))?
+            .trim();
+
+        let parts = version
+            .split('.')
+            .map(|part| part.parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("invalid criu version {}", version))?;
+        match parts[..] {
+            [major, minor] => Ok(Self::new(major, minor, 0)),
+            [major, minor, sublevel] => Ok(Self::new(major, minor, sublevel)),
+            _ => bail!("invalid criu version {}", version),
+        }
+    }
+}
+
+impl Display for CriuVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.sublevel)
+    }
+}
+
+/// Optional features of a checkpoint or restore, which depend on the version
+/// of criu and on the support of the kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CriuFeature {
+    /// Dumping only the memory pages that changed since a previous dump
+    PreDump,
+    /// Checkpointing established tcp connections
+    TcpEstablished,
+    /// Checkpointing containers in a cgroup v2 hierarchy
+    CgroupV2,
+    /// Restoring into a network namespace that is not part of the images
+    ExternalNetNs,
+    /// Restoring into a pid namespace that is not part of the images
+    ExternalPidNs,
+}
+
+impl CriuFeature {
+    /// Oldest version of criu that supports the feature
+    pub fn min_version(&self) -> CriuVersion {
+        match self {
+            Self::PreDump => CriuVersion::new(3, 0, 0),
+            Self::TcpEstablished => CriuVersion::new(3, 0, 0),
+            Self::CgroupV2 => CriuVersion::new(3, 14, 0),
+            Self::ExternalNetNs => CriuVersion::new(3, 11, 0),
+            Self::ExternalPidNs => CriuVersion::new(3, 15, 0),
+        }
+    }
+
+    /// Name of the feature in `criu check --feature`, if the feature also
+    /// needs support of the kernel
+    fn kernel_feature(&self) -> Option<&'static str> {
+        match self {
+            Self::PreDump => Some("mem_dirty_track"),
+            Self::ExternalNetNs => Some("external_net_ns"),
+            Self::TcpEstablished | Self::CgroupV2 | Self::ExternalPidNs => None,
+        }
+    }
+}
+
+impl Display for CriuFeature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let print = match *self {
+            Self::PreDump => "pre-dump",
+            Self::TcpEstablished => "tcp-established",
+            Self::CgroupV2 => "cgroup v2",
+            Self::ExternalNetNs => "external network namespaces",
+            Self::ExternalPidNs => "external pid namespaces",
+        };
+
+        write!(f, "{}", print)
+    }
+}
+
+/// Returns the version of the installed criu binary
+pub fn version() -> Result<CriuVersion> {
+    let output = Command::new(CRIU_BINARY)
+        .arg("--version")
+        .output()
+        .context("failed to execute criu, is it installed?")?;
+    if !output.status.success() {
+        bail!("criu --version failed with {}", output.status);
+    }
+
+    CriuVersion::parse(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Checks if the kernel provides what criu needs for the feature
+fn is_kernel_feature_supported(feature: &str) -> Result<bool> {
+    let status = Command::new(CRIU_BINARY)
+        .args(["check", "--feature", feature])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .context("failed to execute criu")?;
+    Ok(status.success())
+}
+
+/// Verifies that the installed criu can be used with all of the features,
+/// so that a checkpoint or restore fails before criu has been started
+/// instead of in the middle of it.
+pub fn check(features: &[CriuFeature]) -> Result<CriuVersion> {
+    let version = version()?;
+    check_version(version, features)?;
+
+    for feature in features {
+        if let Some(kernel_feature) = feature.kernel_feature() {
+            if !is_kernel_feature_supported(kernel_feature)? {
+                bail!(
+                    "{} is not supported by the kernel (criu check --feature {} failed)",
+                    feature,
+                    kernel_feature
+                );
+            }
+        }
+    }
+
+    Ok(version)
+}
+
+fn check_version(version: CriuVersion, features: &[CriuFeature]) -> Result<()> {
+    if version < MIN_VERSION {
+        bail!("requires CRIU >= {}, found {}", MIN_VERSION, version);
+    }
+
+    for feature in features {
+        if version < feature.min_version() {
+            bail!(
+                "requires CRIU >= {} for {}, found {}",
+                feature.min_version(),
+                feature,
+                version
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() -> Result<()> {
+        assert_eq!(
+            CriuVersion::parse("Version: 3.16.1\n")?,
+            CriuVersion::new(3, 16, 1)
+        );
+        assert_eq!(
+            CriuVersion::parse("Version: 3.15\nGitID: v3.15-12-g6a1e4f4\n")?,
+            CriuVersion::new(3, 15, 0)
+        );
+        assert!(CriuVersion::parse("criu 3.16").is_err());
+        assert!(CriuVersion::parse("Version: 3").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_version() {
+        let version = CriuVersion::new(3, 12, 0);
+        assert!(check_version(version, &[CriuFeature::PreDump]).is_ok());
+        assert!(check_version(version, &[CriuFeature::TcpEstablished]).is_ok());
+
+        let err = check_version(version, &[CriuFeature::CgroupV2]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "requires CRIU >= 3.14.0 for cgroup v2, found 3.12.0"
+        );
+
+        assert!(check_version(CriuVersion::new(2, 9, 0), &[]).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+//! Experimental behaviors that can be enabled per container with annotations
+//! in the `org.youki.experimental.` namespace, e.g.
+//! `"org.youki.experimental.mount-api": "true"`. This allows a feature to be
+//! trialed with selected workloads before it becomes the default.
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    str::FromStr,
+};
+
+use anyhow::{bail, Result};
+
+/// Prefix of the annotations that enable experimental features
+pub const ANNOTATION_PREFIX: &str = "org.youki.experimental.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Feature {
+    /// Creates bind mounts with open_tree(2), mount_setattr(2) and
+    /// move_mount(2), so that the mount is configured before it is attached
+    /// in the container instead of being remounted afterwards
+    MountApi,
+    /// Starts the intermediate process directly in the container cgroup with
+    /// clone3(2) and CLONE_INTO_CGROUP instead of moving it there after the
+    /// fork. Only cgroup v2 supports this, other setups fall back to fork
+    Clone3Cgroup,
+    /// Compiles the seccomp profile once and caches the resulting BPF program
+    /// in the root directory, so that containers with the same profile do
+    /// not compile it again. Profiles with notify actions are not cached, and
+    /// a cached program is only used if it is owned by the effective user
+    /// and its checksum matches
+    SeccompCache,
+}
+
+impl Feature {
+    pub const ALL: &'static [Feature] = &[
+        Feature::MountApi,
+        Feature::Clone3Cgroup,
+        Feature::SeccompCache,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Feature::MountApi => "mount-api",
+            Feature::Clone3Cgroup => "clone3-cgroup",
+            Feature::SeccompCache => "seccomp-cache",
+        }
+    }
+
+    /// Annotation that enables the feature
+    pub fn annotation(&self) -> String {
+        format!("{}{}", ANNOTATION_PREFIX, self.name())
+    }
+}
+
+impl Display for Feature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Feature {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match Feature::ALL.iter().find(|f| f.name() == name) {
+            Some(feature) => Ok(*feature),
+            None => bail!("unknown experimental feature {}", name),
+        }
+    }
+}
+
+/// The experimental features that are enabled for a container
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExperimentalFeatures {
+    enabled: HashSet<Feature>,
+}
+
+impl ExperimentalFeatures {
+    /// Collects the features that are enabled by the annotations of the spec.
+    /// Unknown features and values other than "true" or "false" are rejected,
+    /// so that a typo does not silently fall back to the default behavior.
+    pub fn from_annotations(annotations: Option<&HashMap<String, String>>) -> Result<Self> {
+        let mut features = Self::default();
+        for (key, value) in annotations.into_iter().flatten() {
+            let name = match key.strip_prefix(ANNOTATION_PREFIX) {
+                Some(name) => name,
+                None => continue,
+            };
+
+            let feature: Feature = name.parse()?;
+            match value.as_str() {
+                "true" => {
+                    log::debug!("experimental feature {} is enabled", feature);
+                    features.enabled.insert(feature);
+                }
+                "false" => {}
+                _ => bail!(
+                    "invalid value {:?} for {}, expected true or false",
+                    value,
+                    key
+                ),
+            }
+        }
+
+        Ok(features)
+    }
+
+    pub fn is_enabled(&self, feature: Feature) -> bool {
+        self.enabled.contains(&feature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn annotations(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_from_annotations() -> Result<()> {
+        let features = ExperimentalFeatures::from_annotations(None)?;
+        assert!(!features.is_enabled(Feature::MountApi));
+
+        let features = ExperimentalFeatures::from_annotations(Some(&annotations(&[
+            ("org.youki.experimental.mount-api", "true"),
+            ("org.example.unrelated", "value"),
+        ])))?;
+        assert!(features.is_enabled(Feature::MountApi));
+
+        let features = ExperimentalFeatures::from_annotations(Some(&annotations(&[(
+            "org.youki.experimental.mount-api",
+            "false",
+        )])))?;
+        assert!(!features.is_enabled(Feature::MountApi));
+
+        let features = ExperimentalFeatures::from_annotations(Some(&annotations(&[
+            ("org.youki.experimental.clone3-cgroup", "true"),
+            ("org.youki.experimental.seccomp-cache", "true"),
+        ])))?;
+        assert!(features.is_enabled(Feature::Clone3Cgroup));
+        assert!(features.is_enabled(Feature::SeccompCache));
+        assert!(!features.is_enabled(Feature::MountApi));
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_invalid_annotations() {
+        assert!(ExperimentalFeatures::from_annotations(Some(&annotations(&[(
+            "org.youki.experimental.unknown",
+            "true",
+        )])))
+        .is_err());
+        assert!(ExperimentalFeatures::from_annotations(Some(&annotations(&[(
+            "org.youki.experimental.mount-api",
+            "yes",
+        )])))
+        .is_err());
+    }
+}
@@ -0,0 +1,208 @@
+//! Support for Intel Resource Director Technology (RDT), which allows to
+//! restrict the L3 cache and memory bandwidth a container can use. The
+//! restrictions are configured through the resctrl filesystem. See
+//! https://www.kernel.org/doc/html/latest/x86/resctrl.html
+use anyhow::{bail, Context, Result};
+use nix::unistd::Pid;
+use oci_spec::runtime::LinuxIntelRdt;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::utils;
+
+const RESCTRL_FS_TYPE: &str = "resctrl";
+const SCHEMATA_FILE: &str = "schemata";
+const TASKS_FILE: &str = "tasks";
+
+const L3_CACHE_PREFIXES: &[&str] = &["L3:", "L3CODE:", "L3DATA:"];
+const MEM_BW_PREFIXES: &[&str] = &["MB:"];
+
+/// Finds the mount point of the resctrl filesystem
+pub fn find_resctrl_mount() -> Result<PathBuf> {
+    let mount_infos = libcgroups::mountinfo::mountinfo().context("failed to read mount info")?;
+    mount_infos
+        .into_iter()
+        .find(|m| m.fs_type == RESCTRL_FS_TYPE)
+        .map(|m| m.mount_point)
+        .context("resctrl filesystem is not mounted, intel rdt is not supported")
+}
+
+/// Checks that the schemata of the spec are well formed
+pub fn validate(intel_rdt: &LinuxIntelRdt) -> Result<()> {
+    if let Some(l3_cache_schema) = intel_rdt.l3_cache_schema() {
+        validate_schema(l3_cache_schema, L3_CACHE_PREFIXES).context("invalid l3 cache schema")?;
+    }
+
+    if let Some(mem_bw_schema) = intel_rdt.mem_bw_schema() {
+        validate_schema(mem_bw_schema, MEM_BW_PREFIXES)
+            .context("invalid memory bandwidth schema")?;
+    }
+
+    Ok(())
+}
+
+fn validate_schema(schema: &str, prefixes: &[&str]) -> Result<()> {
+    for line in schema.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if !prefixes.iter().any(|prefix| line.starts_with(prefix)) {
+            bail!("{} does not start with any of {:?}", line, prefixes);
+        }
+    }
+
+    Ok(())
+}
+
+/// Path of the resctrl group of the container. Containers that specify the
+/// same CLOS id share a group, otherwise the group is named after the container.
+pub fn group_path(resctrl_root: &Path, container_id: &str, intel_rdt: &LinuxIntelRdt) -> PathBuf {
+    match intel_rdt.clos_id() {
+        Some(clos_id) => resctrl_root.join(clos_id),
+        None => resctrl_root.join(container_id),
+    }
+}
+
+fn schemata(intel_rdt: &LinuxIntelRdt) -> String {
+    [intel_rdt.l3_cache_schema(), intel_rdt.mem_bw_schema()]
+        .iter()
+        .filter_map(|schema| schema.as_deref())
+        .flat_map(str::lines)
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+/// Creates the resctrl group of the container, writes the schemata and moves
+/// the given tasks into the group. Tasks forked afterwards inherit the group.
+/// Calling this for an existing group updates the schemata.
+pub fn apply(container_id: &str, intel_rdt: &LinuxIntelRdt, tasks: &[Pid]) -> Result<()> {
+    validate(intel_rdt)?;
+    let resctrl_root = find_resctrl_mount()?;
+    let group = group_path(&resctrl_root, container_id, intel_rdt);
+    utils::create_dir_all(&group)?;
+
+    let schemata = schemata(intel_rdt);
+    if !schemata.is_empty() {
+        utils::write_file(group.join(SCHEMATA_FILE), schemata)
+            .with_context(|| format!("failed to write schemata of {}", group.display()))?;
+    }
+
+    // resctrl groups contain threads, which have to be added one at a time
+    for task in tasks {
+        utils::write_file(group.join(TASKS_FILE), task.to_string())
+            .with_context(|| format!("failed to add {} to {}", task, group.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Lists the threads of the processes. Processes that exit while they are
+/// listed are skipped.
+pub fn threads(pids: &[Pid]) -> Result<Vec<Pid>> {
+    let mut threads = Vec::new();
+    for pid in pids {
+        let task_dir = PathBuf::from(format!("/proc/{}/task", pid));
+        let entries = match fs::read_dir(&task_dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to read {}", task_dir.display()))
+            }
+        };
+
+        for entry in entries {
+            if let Some(tid) = entry?.file_name().to_str().and_then(|tid| tid.parse().ok()) {
+                threads.push(Pid::from_raw(tid));
+            }
+        }
+    }
+
+    Ok(threads)
+}
+
+/// Removes the resctrl group of the container. Groups that have been selected
+/// through a CLOS id may be shared with other containers and are kept.
+pub fn remove(container_id: &str, intel_rdt: &LinuxIntelRdt) -> Result<()> {
+    if intel_rdt.clos_id().is_some() {
+        return Ok(());
+    }
+
+    let group = group_path(&find_resctrl_mount()?, container_id, intel_rdt);
+    if group.exists() {
+        fs::remove_dir(&group)
+            .with_context(|| format!("failed to remove resctrl group {}", group.display()))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oci_spec::runtime::LinuxIntelRdtBuilder;
+
+    #[test]
+    fn test_validate() -> Result<()> {
+        let valid = LinuxIntelRdtBuilder::default()
+            .l3_cache_schema("L3:0=7f0;1=1f")
+            .mem_bw_schema("MB:0=20;1=70")
+            .build()?;
+        assert!(validate(&valid).is_ok());
+
+        let cdp = LinuxIntelRdtBuilder::default()
+            .l3_cache_schema("L3CODE:0=ff\nL3DATA:0=f")
+            .build()?;
+        assert!(validate(&cdp).is_ok());
+
+        let swapped = LinuxIntelRdtBuilder::default()
+            .l3_cache_schema("MB:0=20")
+            .build()?;
+        assert!(validate(&swapped).is_err());
+
+        let invalid_mb = LinuxIntelRdtBuilder::default()
+            .mem_bw_schema("0=20")
+            .build()?;
+        assert!(validate(&invalid_mb).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_schemata() -> Result<()> {
+        let intel_rdt = LinuxIntelRdtBuilder::default()
+            .l3_cache_schema("L3:0=7f0;1=1f\n")
+            .mem_bw_schema("  MB:0=20;1=70")
+            .build()?;
+        assert_eq!(schemata(&intel_rdt), "L3:0=7f0;1=1f\nMB:0=20;1=70\n");
+        assert_eq!(schemata(&LinuxIntelRdt::default()), "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_threads() -> Result<()> {
+        let threads = threads(&[Pid::this()])?;
+        assert!(threads.contains(&Pid::this()));
+        assert_eq!(
+            threads.len(),
+            fs::read_dir("/proc/self/task")?.count(),
+            "all threads of the process are listed"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_path() -> Result<()> {
+        let root = Path::new("/sys/fs/resctrl");
+        assert_eq!(
+            group_path(root, "container", &LinuxIntelRdt::default()),
+            PathBuf::from("/sys/fs/resctrl/container")
+        );
+
+        let shared = LinuxIntelRdtBuilder::default().clos_id("shared").build()?;
+        assert_eq!(
+            group_path(root, "container", &shared),
+            PathBuf::from("/sys/fs/resctrl/shared")
+        );
+        Ok(())
+    }
+}
@@ -1,15 +1,23 @@
 #![cfg_attr(coverage, feature(no_coverage))]
 pub mod apparmor;
+pub mod attach;
 pub mod capabilities;
 pub mod config;
 pub mod container;
+pub mod criu;
+pub mod experimental;
 pub mod hooks;
+pub mod intel_rdt;
+pub mod lsm;
+pub mod migration;
 pub mod namespaces;
 pub mod notify_socket;
 pub mod process;
+pub mod progress;
 pub mod rootfs;
 pub mod rootless;
 pub mod seccomp;
+pub mod selinux;
 pub mod signal;
 pub mod syscall;
 pub mod tty;
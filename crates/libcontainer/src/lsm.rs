@@ -0,0 +1,257 @@
+//! Detection of the Linux security modules (LSM) that can be used to confine
+//! containers. Applying a profile or label fails with an opaque error when
+//! writing to /proc/self/attr, so the state of the LSM is checked upfront in
+//! order to tell the user what exactly is missing.
+use anyhow::{bail, Result};
+use std::{
+    fmt::Display,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::Path,
+};
+
+const APPARMOR_MODULE_PATH: &str = "/sys/module/apparmor";
+const APPARMOR_PROFILES_PATH: &str = "/sys/kernel/security/apparmor/profiles";
+const SELINUX_FS_PATH: &str = "/sys/fs/selinux";
+const FILESYSTEMS_PATH: &str = "/proc/filesystems";
+
+// Profile that does not confine the process at all and is therefore always available
+const APPARMOR_UNCONFINED: &str = "unconfined";
+
+/// State of a Linux security module on this system
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LsmStatus {
+    /// The kernel has been built without support for the module
+    NotSupported,
+    /// The kernel supports the module, but it has been disabled at boot
+    Disabled,
+    /// The module is active
+    Enabled,
+}
+
+impl Display for LsmStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let print = match *self {
+            Self::NotSupported => "not supported by the kernel",
+            Self::Disabled => "disabled",
+            Self::Enabled => "enabled",
+        };
+
+        write!(f, "{}", print)
+    }
+}
+
+/// Determines the state of AppArmor on this system
+pub fn apparmor_status() -> LsmStatus {
+    apparmor_status_at(Path::new(APPARMOR_MODULE_PATH))
+}
+
+fn apparmor_status_at(module_path: &Path) -> LsmStatus {
+    if !module_path.exists() {
+        return LsmStatus::NotSupported;
+    }
+
+    match fs::read_to_string(module_path.join("parameters/enabled")) {
+        Ok(enabled) if enabled.starts_with('Y') => LsmStatus::Enabled,
+        _ => LsmStatus::Disabled,
+    }
+}
+
+/// Determines the state of SELinux on this system
+pub fn selinux_status() -> LsmStatus {
+    selinux_status_at(Path::new(FILESYSTEMS_PATH), Path::new(SELINUX_FS_PATH))
+}
+
+fn selinux_status_at(filesystems_path: &Path, selinux_fs_path: &Path) -> LsmStatus {
+    let supported = fs::read_to_string(filesystems_path)
+        .map(|filesystems| {
+            filesystems
+                .lines()
+                .any(|line| line.split_whitespace().last() == Some("selinuxfs"))
+        })
+        .unwrap_or(false);
+    if !supported {
+        return LsmStatus::NotSupported;
+    }
+
+    if selinux_fs_path.join("enforce").exists() {
+        LsmStatus::Enabled
+    } else {
+        LsmStatus::Disabled
+    }
+}
+
+/// Checks if the AppArmor profile has been loaded into the kernel. Returns None
+/// if this cannot be determined, e.g. because securityfs is not mounted.
+pub fn is_apparmor_profile_loaded(profile: &str) -> Option<bool> {
+    is_apparmor_profile_loaded_at(Path::new(APPARMOR_PROFILES_PATH), profile)
+}
+
+fn is_apparmor_profile_loaded_at(profiles_path: &Path, profile: &str) -> Option<bool> {
+    if profile == APPARMOR_UNCONFINED {
+        return Some(true);
+    }
+
+    // each line has the form "<profile name> (<mode>)"
+    let profiles = fs::read_to_string(profiles_path).ok()?;
+    Some(profiles.lines().any(|line| {
+        line.rsplit_once(' ')
+            .map(|(name, _)| name == profile)
+            .unwrap_or(line == profile)
+    }))
+}
+
+/// Checks if the SELinux label is valid for the loaded policy. Returns None
+/// if this cannot be determined.
+pub fn is_selinux_label_valid(label: &str) -> Option<bool> {
+    // The kernel validates a context written to the context file of selinuxfs
+    // and rejects it with EINVAL if it is not defined by the policy.
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(Path::new(SELINUX_FS_PATH).join("context"))
+        .ok()?;
+    match file.write_all(label.as_bytes()) {
+        Ok(_) => Some(true),
+        Err(err) if err.kind() == ErrorKind::InvalidInput => Some(false),
+        Err(_) => None,
+    }
+}
+
+/// Checks that the AppArmor profile can be applied to the container
+pub fn check_apparmor_profile(profile: &str) -> Result<()> {
+    if profile.is_empty() {
+        return Ok(());
+    }
+
+    match apparmor_status() {
+        LsmStatus::NotSupported => bail!(
+            "apparmor profile {} is specified in runtime spec, \
+            but the kernel has been built without apparmor support",
+            profile
+        ),
+        LsmStatus::Disabled => bail!(
+            "apparmor profile {} is specified in runtime spec, \
+            but apparmor has been disabled at boot",
+            profile
+        ),
+        LsmStatus::Enabled => {}
+    }
+
+    if let Some(false) = is_apparmor_profile_loaded(profile) {
+        bail!(
+            "apparmor profile {} is specified in runtime spec, \
+            but it has not been loaded into the kernel",
+            profile
+        );
+    }
+
+    Ok(())
+}
+
+/// Checks that the SELinux label can be applied to the container
+pub fn check_selinux_label(label: &str) -> Result<()> {
+    if label.is_empty() {
+        return Ok(());
+    }
+
+    match selinux_status() {
+        LsmStatus::NotSupported => bail!(
+            "selinux label {} is specified in runtime spec, \
+            but the kernel has been built without selinux support",
+            label
+        ),
+        LsmStatus::Disabled => bail!(
+            "selinux label {} is specified in runtime spec, \
+            but selinux has been disabled at boot",
+            label
+        ),
+        LsmStatus::Enabled => {}
+    }
+
+    if let Some(false) = is_selinux_label_valid(label) {
+        bail!(
+            "selinux label {} is specified in runtime spec, \
+            but it is not defined by the loaded policy",
+            label
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+
+    #[test]
+    fn test_apparmor_status() -> Result<()> {
+        let tmp = create_temp_dir("test_apparmor_status")?;
+        let module_path = tmp.path().join("apparmor");
+        assert_eq!(apparmor_status_at(&module_path), LsmStatus::NotSupported);
+
+        fs::create_dir_all(module_path.join("parameters"))?;
+        fs::write(module_path.join("parameters/enabled"), "N\n")?;
+        assert_eq!(apparmor_status_at(&module_path), LsmStatus::Disabled);
+
+        fs::write(module_path.join("parameters/enabled"), "Y\n")?;
+        assert_eq!(apparmor_status_at(&module_path), LsmStatus::Enabled);
+        Ok(())
+    }
+
+    #[test]
+    fn test_selinux_status() -> Result<()> {
+        let tmp = create_temp_dir("test_selinux_status")?;
+        let filesystems = tmp.path().join("filesystems");
+        let selinux_fs = tmp.path().join("selinux");
+
+        fs::write(&filesystems, "nodev\tsysfs\nnodev\ttmpfs\n\text4\n")?;
+        assert_eq!(
+            selinux_status_at(&filesystems, &selinux_fs),
+            LsmStatus::NotSupported
+        );
+
+        fs::write(&filesystems, "nodev\tsysfs\nnodev\tselinuxfs\n\text4\n")?;
+        assert_eq!(
+            selinux_status_at(&filesystems, &selinux_fs),
+            LsmStatus::Disabled
+        );
+
+        fs::create_dir_all(&selinux_fs)?;
+        fs::write(selinux_fs.join("enforce"), "1")?;
+        assert_eq!(
+            selinux_status_at(&filesystems, &selinux_fs),
+            LsmStatus::Enabled
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_apparmor_profile_loaded() -> Result<()> {
+        let tmp = create_temp_dir("test_is_apparmor_profile_loaded")?;
+        let profiles = tmp.path().join("profiles");
+        assert_eq!(is_apparmor_profile_loaded_at(&profiles, "youki"), None);
+        assert_eq!(
+            is_apparmor_profile_loaded_at(&profiles, APPARMOR_UNCONFINED),
+            Some(true)
+        );
+
+        fs::write(
+            &profiles,
+            "docker-default (enforce)\n/usr/sbin/cups-browsed (enforce)\n",
+        )?;
+        assert_eq!(
+            is_apparmor_profile_loaded_at(&profiles, "docker-default"),
+            Some(true)
+        );
+        assert_eq!(
+            is_apparmor_profile_loaded_at(&profiles, "/usr/sbin/cups-browsed"),
+            Some(true)
+        );
+        assert_eq!(
+            is_apparmor_profile_loaded_at(&profiles, "docker"),
+            Some(false)
+        );
+        Ok(())
+    }
+}
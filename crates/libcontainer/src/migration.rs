@@ -0,0 +1,676 @@
+//! Live migration of a container. A migration takes a number of pre-dumps
+//! while the container keeps running, each of which only contains the memory
+//! pages that changed since the previous one, then a final dump that stops
+//! the container. The images and the state of the container are moved to the
+//! target by a transport, which finally restores the container there. If the
+//! transfer or the restore after the final dump fails, the container is
+//! restored from the final images on this host again.
+//!
+//! `CriuCheckpointer` migrates a container of this host with criu. Its
+//! rootfs and bundle have to be available under the same paths on the
+//! target, e.g. on shared storage.
+//!
+//! After a successful migration the container is left stopped on this host,
+//! together with its images in the `migration` directory of its state. It
+//! can be deleted once the container runs on the target.
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    container::{CheckpointOptions, Container, RestoreOptions},
+    rootfs::copy::copy_dir_contents,
+    utils,
+};
+
+/// Directory in the state directory of the container that holds the images
+/// of a migration
+const MIGRATION_DIR: &str = "migration";
+const FINAL_DUMP: &str = "final";
+
+/// Dumps and restores the container that is migrated
+pub trait Checkpointer {
+    /// Id of the container
+    fn id(&self) -> &str;
+
+    /// Directory of the state files of the container, which are transferred
+    /// after the final dump
+    fn state_dir(&self) -> &Path;
+
+    /// Dumps the container to `image_path`. A pre-dump leaves the container
+    /// running and tracks the memory changes for the next dump. The parent
+    /// path is the previous dump, relative to the image path.
+    fn dump(&mut self, image_path: &Path, parent_path: Option<&Path>, pre_dump: bool)
+        -> Result<()>;
+
+    /// Restores the container on this host from `image_path`
+    fn restore(&mut self, image_path: &Path) -> Result<()>;
+}
+
+/// Dumps and restores a container with criu. The pid of the container is
+/// cleared after the final dump, as it may belong to another process on the
+/// target.
+pub struct CriuCheckpointer<'a> {
+    container: &'a mut Container,
+    opts: CheckpointOptions,
+}
+
+impl<'a> CriuCheckpointer<'a> {
+    /// The image, work and parent paths, memory tracking and leave running
+    /// of `opts` are set by the migration
+    pub fn new(container: &'a mut Container, opts: CheckpointOptions) -> Self {
+        Self { container, opts }
+    }
+
+    /// Options of a restore with the criu options of the dumps
+    pub fn restore_options(&self) -> RestoreOptions {
+        RestoreOptions {
+            tcp_established: self.opts.tcp_established,
+            ext_unix_sk: self.opts.ext_unix_sk,
+            shell_job: self.opts.shell_job,
+            file_locks: self.opts.file_locks,
+            ..Default::default()
+        }
+    }
+}
+
+impl Checkpointer for CriuCheckpointer<'_> {
+    fn id(&self) -> &str {
+        self.container.id()
+    }
+
+    fn state_dir(&self) -> &Path {
+        &self.container.root
+    }
+
+    fn dump(
+        &mut self,
+        image_path: &Path,
+        parent_path: Option<&Path>,
+        pre_dump: bool,
+    ) -> Result<()> {
+        self.container.checkpoint(&CheckpointOptions {
+            image_path: Some(image_path.to_owned()),
+            work_path: None,
+            parent_path: parent_path.map(Path::to_owned),
+            track_mem: pre_dump,
+            leave_running: pre_dump,
+            ..self.opts.clone()
+        })?;
+        if !pre_dump {
+            self.container.state.pid = None;
+            self.container.save()?;
+        }
+
+        Ok(())
+    }
+
+    fn restore(&mut self, image_path: &Path) -> Result<()> {
+        self.container.restore(&RestoreOptions {
+            image_path: Some(image_path.to_owned()),
+            ..self.restore_options()
+        })
+    }
+}
+
+/// Moves the images and the state of a container to the target of a
+/// migration and restores the container there. Paths on the target are
+/// relative to the root path of youki on the target.
+pub trait MigrationTransport {
+    /// Transfers a file or a directory to the target, e.g. the images of a
+    /// dump to `<id>/migration/final`
+    fn transfer(&mut self, source: &Path, destination: &Path) -> Result<()>;
+
+    /// Restores the container on the target from the images in `image_path`
+    fn restore(&mut self, container_id: &str, image_path: &Path) -> Result<()>;
+}
+
+impl<T: MigrationTransport + ?Sized> MigrationTransport for Box<T> {
+    fn transfer(&mut self, source: &Path, destination: &Path) -> Result<()> {
+        (**self).transfer(source, destination)
+    }
+
+    fn restore(&mut self, container_id: &str, image_path: &Path) -> Result<()> {
+        (**self).restore(container_id, image_path)
+    }
+}
+
+/// Migrates a container to another root path on this host, e.g. on shared
+/// storage that is used by youki on another host as well
+pub struct LocalTransport {
+    root_path: PathBuf,
+    restore: RestoreOptions,
+}
+
+impl LocalTransport {
+    pub fn new<P: Into<PathBuf>>(root_path: P) -> Self {
+        Self {
+            root_path: root_path.into(),
+            restore: RestoreOptions::default(),
+        }
+    }
+
+    /// Options of the restore on the target, without the image path
+    pub fn with_restore_options(mut self, opts: RestoreOptions) -> Self {
+        self.restore = opts;
+        self
+    }
+}
+
+impl MigrationTransport for LocalTransport {
+    fn transfer(&mut self, source: &Path, destination: &Path) -> Result<()> {
+        let destination = self.root_path.join(destination);
+        if destination.exists() {
+            bail!("{} already exists", destination.display());
+        }
+        if let Some(parent) = destination.parent() {
+            utils::create_dir_all(parent)?;
+        }
+
+        if source.is_dir() {
+            utils::create_dir_all(&destination)?;
+            copy_dir_contents(source, &destination)
+        } else {
+            fs::copy(source, &destination)
+                .map(|_| ())
+                .with_context(|| format!("failed to copy {}", source.display()))
+        }
+    }
+
+    fn restore(&mut self, container_id: &str, image_path: &Path) -> Result<()> {
+        let mut container = Container::load(self.root_path.join(container_id))?;
+        container.restore(&RestoreOptions {
+            image_path: Some(self.root_path.join(image_path)),
+            ..self.restore.clone()
+        })
+    }
+}
+
+/// Migrates a container with shell commands, e.g. scp and ssh. The paths and
+/// the id are passed in environment variables instead of being substituted
+/// into the commands:
+///
+/// - transfer: `YOUKI_SOURCE` and `YOUKI_DESTINATION`
+/// - restore: `YOUKI_CONTAINER_ID` and `YOUKI_IMAGE_PATH`
+///
+/// The restore command has to pass the criu options of the migration, like
+/// `--tcp-established`, to the restore on the target.
+pub struct CommandTransport {
+    transfer_cmd: String,
+    restore_cmd: String,
+}
+
+impl CommandTransport {
+    pub fn new(transfer_cmd: String, restore_cmd: String) -> Self {
+        Self {
+            transfer_cmd,
+            restore_cmd,
+        }
+    }
+}
+
+fn run_command(cmd: &str, envs: &[(&str, &Path)]) -> Result<()> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .envs(envs.iter().map(|(key, value)| (key, value.as_os_str())))
+        .status()
+        .with_context(|| format!("failed to execute {:?}", cmd))?;
+    if !status.success() {
+        bail!("{:?} failed ({})", cmd, status);
+    }
+
+    Ok(())
+}
+
+impl MigrationTransport for CommandTransport {
+    fn transfer(&mut self, source: &Path, destination: &Path) -> Result<()> {
+        run_command(
+            &self.transfer_cmd,
+            &[("YOUKI_SOURCE", source), ("YOUKI_DESTINATION", destination)],
+        )
+    }
+
+    fn restore(&mut self, container_id: &str, image_path: &Path) -> Result<()> {
+        run_command(
+            &self.restore_cmd,
+            &[
+                ("YOUKI_CONTAINER_ID", Path::new(container_id)),
+                ("YOUKI_IMAGE_PATH", image_path),
+            ],
+        )
+    }
+}
+
+type TransferFn<'a> = Box<dyn FnMut(&Path, &Path) -> Result<()> + 'a>;
+type RestoreFn<'a> = Box<dyn FnMut(&str, &Path) -> Result<()> + 'a>;
+
+/// Migrates a container with callbacks, which have the signatures of the
+/// methods of `MigrationTransport`
+pub struct CallbackTransport<'a> {
+    transfer: TransferFn<'a>,
+    restore: RestoreFn<'a>,
+}
+
+impl<'a> CallbackTransport<'a> {
+    pub fn new(
+        transfer: impl FnMut(&Path, &Path) -> Result<()> + 'a,
+        restore: impl FnMut(&str, &Path) -> Result<()> + 'a,
+    ) -> Self {
+        Self {
+            transfer: Box::new(transfer),
+            restore: Box::new(restore),
+        }
+    }
+}
+
+impl MigrationTransport for CallbackTransport<'_> {
+    fn transfer(&mut self, source: &Path, destination: &Path) -> Result<()> {
+        (self.transfer)(source, destination)
+    }
+
+    fn restore(&mut self, container_id: &str, image_path: &Path) -> Result<()> {
+        (self.restore)(container_id, image_path)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MigrationPhase {
+    /// Pre-dump of the running container, counted from 1
+    PreDump(usize),
+    /// Final dump, which stops the container
+    Dump,
+    /// Transfer of a file or directory to the target
+    Transfer(PathBuf),
+    /// Restore of the container on the target
+    Restore,
+}
+
+impl Display for MigrationPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationPhase::PreDump(n) => write!(f, "pre-dump {}", n),
+            MigrationPhase::Dump => f.write_str("dump"),
+            MigrationPhase::Transfer(path) => write!(f, "transfer of {}", path.display()),
+            MigrationPhase::Restore => f.write_str("restore"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MigrationEvent {
+    Started(MigrationPhase),
+    Completed(MigrationPhase, Duration),
+    Failed(MigrationPhase),
+}
+
+impl Display for MigrationEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationEvent::Started(phase) => write!(f, "{} started", phase),
+            MigrationEvent::Completed(phase, elapsed) => {
+                write!(f, "{} completed in {:?}", phase, elapsed)
+            }
+            MigrationEvent::Failed(phase) => write!(f, "{} failed", phase),
+        }
+    }
+}
+
+type ProgressFn<'a> = Box<dyn FnMut(&MigrationEvent) + 'a>;
+
+/// Migrates a running container with a transport
+///
+/// # Example
+///
+/// ```no_run
+/// use libcontainer::container::{CheckpointOptions, Container};
+/// use libcontainer::migration::{CommandTransport, CriuCheckpointer, Migration};
+/// use std::path::PathBuf;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let mut container = Container::load(PathBuf::from("/run/youki/74f1a4cb3801"))?;
+/// let transport = CommandTransport::new(
+///     concat!(
+///         r#"ssh target mkdir -p "/run/youki/$(dirname "$YOUKI_DESTINATION")" && "#,
+///         r#"scp -rp "$YOUKI_SOURCE" "target:/run/youki/$YOUKI_DESTINATION""#
+///     )
+///     .to_owned(),
+///     r#"ssh target youki restore --image-path "/run/youki/$YOUKI_IMAGE_PATH" "$YOUKI_CONTAINER_ID""#.to_owned(),
+/// );
+/// Migration::new(transport)
+///     .with_pre_dumps(2)
+///     .with_progress(|event| println!("{}", event))
+///     .run(&mut CriuCheckpointer::new(&mut container, CheckpointOptions::default()))?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Migration<'a> {
+    transport: Box<dyn MigrationTransport + 'a>,
+    pre_dumps: usize,
+    progress: ProgressFn<'a>,
+}
+
+impl<'a> Migration<'a> {
+    pub fn new(transport: impl MigrationTransport + 'a) -> Self {
+        Self {
+            transport: Box::new(transport),
+            pre_dumps: 1,
+            progress: Box::new(|_| {}),
+        }
+    }
+
+    /// Number of pre-dumps that are taken before the final dump (default: 1)
+    pub fn with_pre_dumps(mut self, pre_dumps: usize) -> Self {
+        self.pre_dumps = pre_dumps;
+        self
+    }
+
+    /// Called when a phase of the migration has started, completed or failed
+    pub fn with_progress(mut self, progress: impl FnMut(&MigrationEvent) + 'a) -> Self {
+        self.progress = Box::new(progress);
+        self
+    }
+
+    pub fn run(&mut self, checkpointer: &mut dyn Checkpointer) -> Result<()> {
+        let images = checkpointer.state_dir().join(MIGRATION_DIR);
+        if images.exists() {
+            // left over from a failed migration
+            fs::remove_dir_all(&images)
+                .with_context(|| format!("failed to remove {}", images.display()))?;
+        }
+        let target_images = Path::new(checkpointer.id()).join(MIGRATION_DIR);
+
+        let mut parent_path: Option<PathBuf> = None;
+        for n in 1..=self.pre_dumps {
+            let name = format!("pre-{}", n);
+            self.phase(MigrationPhase::PreDump(n), |_| {
+                checkpointer.dump(&images.join(&name), parent_path.as_deref(), true)
+            })?;
+            self.transfer(&images.join(&name), &target_images.join(&name))?;
+            parent_path = Some(Path::new("..").join(&name));
+        }
+
+        let final_images = images.join(FINAL_DUMP);
+        self.phase(MigrationPhase::Dump, |_| {
+            checkpointer.dump(&final_images, parent_path.as_deref(), false)
+        })?;
+
+        if let Err(err) = self.finish(checkpointer, &final_images, &target_images.join(FINAL_DUMP))
+        {
+            log::warn!(
+                "failed to migrate container {}, restoring it on this host: {:?}",
+                checkpointer.id(),
+                err
+            );
+            checkpointer.restore(&final_images).with_context(|| {
+                format!(
+                    "failed to restore container {} after the failed migration: {:?}",
+                    checkpointer.id(),
+                    err
+                )
+            })?;
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    /// Transfers the final images and the state of the stopped container and
+    /// restores it on the target
+    fn finish(
+        &mut self,
+        checkpointer: &dyn Checkpointer,
+        final_images: &Path,
+        target_images: &Path,
+    ) -> Result<()> {
+        self.transfer(final_images, target_images)?;
+
+        // the state files, without the images and the sockets of the container
+        let state_dir = checkpointer.state_dir();
+        let mut files = Vec::new();
+        for entry in fs::read_dir(state_dir)
+            .with_context(|| format!("failed to read {}", state_dir.display()))?
+        {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                files.push(entry.file_name());
+            }
+        }
+        files.sort();
+        for file in files {
+            let destination = Path::new(checkpointer.id()).join(&file);
+            self.transfer(&state_dir.join(&file), &destination)?;
+        }
+
+        self.phase(MigrationPhase::Restore, |transport| {
+            transport.restore(checkpointer.id(), target_images)
+        })
+    }
+
+    fn transfer(&mut self, source: &Path, destination: &Path) -> Result<()> {
+        self.phase(
+            MigrationPhase::Transfer(destination.to_owned()),
+            |transport| transport.transfer(source, destination),
+        )
+    }
+
+    fn phase(
+        &mut self,
+        phase: MigrationPhase,
+        f: impl FnOnce(&mut dyn MigrationTransport) -> Result<()>,
+    ) -> Result<()> {
+        (self.progress)(&MigrationEvent::Started(phase.clone()));
+        let start = Instant::now();
+        match f(self.transport.as_mut()) {
+            Ok(()) => {
+                (self.progress)(&MigrationEvent::Completed(phase, start.elapsed()));
+                Ok(())
+            }
+            Err(err) => {
+                (self.progress)(&MigrationEvent::Failed(phase.clone()));
+                Err(err).with_context(|| format!("{} failed", phase))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{create_temp_dir, TempDir};
+    use std::cell::RefCell;
+
+    struct FakeCheckpointer {
+        state_dir: TempDir,
+        dumps: Vec<(PathBuf, Option<PathBuf>, bool)>,
+        restored: Option<PathBuf>,
+    }
+
+    impl FakeCheckpointer {
+        fn new(test_name: &str) -> Result<Self> {
+            let state_dir = create_temp_dir(test_name)?;
+            fs::write(state_dir.path().join("state.json"), "{}")?;
+            fs::write(state_dir.path().join("config.json"), "{}")?;
+            Ok(Self {
+                state_dir,
+                dumps: Vec::new(),
+                restored: None,
+            })
+        }
+    }
+
+    impl Checkpointer for FakeCheckpointer {
+        fn id(&self) -> &str {
+            "id"
+        }
+
+        fn state_dir(&self) -> &Path {
+            self.state_dir.path()
+        }
+
+        fn dump(
+            &mut self,
+            image_path: &Path,
+            parent_path: Option<&Path>,
+            pre_dump: bool,
+        ) -> Result<()> {
+            fs::create_dir_all(image_path)?;
+            fs::write(image_path.join("pages-1.img"), "pages")?;
+            self.dumps.push((
+                image_path.to_owned(),
+                parent_path.map(Path::to_owned),
+                pre_dump,
+            ));
+            Ok(())
+        }
+
+        fn restore(&mut self, image_path: &Path) -> Result<()> {
+            self.restored = Some(image_path.to_owned());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_migration_run() -> Result<()> {
+        let mut checkpointer = FakeCheckpointer::new("test_migration_run")?;
+        let images = checkpointer.state_dir().join(MIGRATION_DIR);
+        let transfers = RefCell::new(Vec::new());
+        let restores = RefCell::new(Vec::new());
+        let mut events = Vec::new();
+
+        Migration::new(CallbackTransport::new(
+            |source, destination| {
+                assert!(source.exists());
+                transfers.borrow_mut().push(destination.to_owned());
+                Ok(())
+            },
+            |id, image_path| {
+                restores
+                    .borrow_mut()
+                    .push((id.to_owned(), image_path.to_owned()));
+                Ok(())
+            },
+        ))
+        .with_pre_dumps(2)
+        .with_progress(|event| events.push(event.clone()))
+        .run(&mut checkpointer)?;
+
+        assert_eq!(
+            checkpointer.dumps,
+            vec![
+                (images.join("pre-1"), None, true),
+                (images.join("pre-2"), Some(PathBuf::from("../pre-1")), true),
+                (images.join("final"), Some(PathBuf::from("../pre-2")), false),
+            ]
+        );
+        assert_eq!(
+            transfers.into_inner(),
+            vec![
+                PathBuf::from("id/migration/pre-1"),
+                PathBuf::from("id/migration/pre-2"),
+                PathBuf::from("id/migration/final"),
+                PathBuf::from("id/config.json"),
+                PathBuf::from("id/state.json"),
+            ]
+        );
+        assert_eq!(
+            restores.into_inner(),
+            vec![("id".to_owned(), PathBuf::from("id/migration/final"))]
+        );
+        assert_eq!(checkpointer.restored, None);
+        assert_eq!(
+            events.first(),
+            Some(&MigrationEvent::Started(MigrationPhase::PreDump(1)))
+        );
+        assert!(matches!(
+            events.last(),
+            Some(MigrationEvent::Completed(MigrationPhase::Restore, _))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_migration_restores_locally_after_failed_transfer() -> Result<()> {
+        let mut checkpointer =
+            FakeCheckpointer::new("test_migration_restores_locally_after_failed_transfer")?;
+        let images = checkpointer.state_dir().join(MIGRATION_DIR);
+        let mut events = Vec::new();
+
+        let result = Migration::new(CallbackTransport::new(
+            |_, destination| {
+                if destination.ends_with("state.json") {
+                    bail!("connection lost");
+                }
+                Ok(())
+            },
+            |_, _| panic!("the container must not be restored on the target"),
+        ))
+        .with_pre_dumps(0)
+        .with_progress(|event| events.push(event.clone()))
+        .run(&mut checkpointer);
+
+        assert!(result.is_err());
+        assert_eq!(
+            checkpointer.dumps,
+            vec![(images.join("final"), None, false)]
+        );
+        assert_eq!(checkpointer.restored, Some(images.join("final")));
+        assert!(
+            events.contains(&MigrationEvent::Failed(MigrationPhase::Transfer(
+                PathBuf::from("id/state.json")
+            )))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_local_transport_transfer() -> Result<()> {
+        let tmp = create_temp_dir("test_local_transport_transfer")?;
+        let source = tmp.path().join("source");
+        fs::create_dir_all(source.join("images"))?;
+        fs::write(source.join("images/pages-1.img"), "pages")?;
+        fs::write(source.join("state.json"), "{}")?;
+
+        let mut transport = LocalTransport::new(tmp.path().join("target"));
+        transport.transfer(&source.join("images"), Path::new("id/migration/final"))?;
+        transport.transfer(&source.join("state.json"), Path::new("id/state.json"))?;
+        assert_eq!(
+            fs::read_to_string(tmp.path().join("target/id/migration/final/pages-1.img"))?,
+            "pages"
+        );
+        assert_eq!(
+            fs::read_to_string(tmp.path().join("target/id/state.json"))?,
+            "{}"
+        );
+
+        // images of another migration are never overwritten
+        assert!(transport
+            .transfer(&source.join("images"), Path::new("id/migration/final"))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_command_transport() -> Result<()> {
+        let mut transport = CommandTransport::new(
+            r#"test "$YOUKI_SOURCE" = "/source dir" && test "$YOUKI_DESTINATION" = id/final"#
+                .to_owned(),
+            r#"test "$YOUKI_CONTAINER_ID" = '$(id)' && test "$YOUKI_IMAGE_PATH" = id/final"#
+                .to_owned(),
+        );
+        transport.transfer(Path::new("/source dir"), Path::new("id/final"))?;
+        // the id is not substituted into the command
+        transport.restore("$(id)", Path::new("id/final"))?;
+
+        assert!(transport
+            .transfer(Path::new("/other"), Path::new("id/final"))
+            .is_err());
+        Ok(())
+    }
+}
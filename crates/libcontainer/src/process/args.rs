@@ -3,7 +3,11 @@ use oci_spec::runtime::Spec;
 use std::os::unix::prelude::RawFd;
 use std::path::PathBuf;
 
+use crate::attach::AttachStdio;
+use crate::experimental::ExperimentalFeatures;
+use crate::progress::Progress;
 use crate::rootless::Rootless;
+use crate::seccomp::BpfProgram;
 use crate::{container::Container, notify_socket::NotifyListener, syscall::Syscall};
 
 pub struct ContainerArgs<'a> {
@@ -17,6 +21,8 @@ pub struct ContainerArgs<'a> {
     pub rootfs: &'a PathBuf,
     /// Socket to communicate the file descriptor of the ptty
     pub console_socket: Option<RawFd>,
+    /// Pipes of the attach relay to use as stdio of the container
+    pub attach_stdio: Option<AttachStdio>,
     /// The Unix Domain Socket to communicate container start
     pub notify_socket: NotifyListener,
     /// File descriptos preserved/passed to the container init process.
@@ -27,4 +33,10 @@ pub struct ContainerArgs<'a> {
     pub rootless: &'a Option<Rootless<'a>>,
     /// Cgroup Manager
     pub cgroup_manager: Box<dyn CgroupManager>,
+    /// Experimental features enabled for the container
+    pub experimental: &'a ExperimentalFeatures,
+    /// Seccomp profile that has been compiled ahead of time
+    pub seccomp_program: Option<BpfProgram>,
+    /// Reports the progress of the phases of the creation
+    pub progress: Progress,
 }
@@ -61,6 +61,15 @@ impl MainSender {
         Ok(())
     }
 
+    // reports an error the init process detected before the point of no
+    // return, so that it can be surfaced by the main process instead of the
+    // container silently failing on exec
+    pub fn exec_failed(&mut self, err: String) -> Result<()> {
+        self.sender.send(Message::ExecFailed(err))?;
+
+        Ok(())
+    }
+
     pub fn close(&self) -> Result<()> {
         self.sender.close()
     }
@@ -116,6 +125,7 @@ impl MainReceiver {
                 };
                 Ok(fd)
             }
+            Message::ExecFailed(err) => bail!(err),
             msg => bail!(
                 "receive unexpected message {:?} waiting for seccomp request",
                 msg
@@ -132,6 +142,7 @@ impl MainReceiver {
             .context("failed to wait for init ready")?;
         match msg {
             Message::InitReady => Ok(()),
+            Message::ExecFailed(err) => bail!(err),
             msg => bail!(
                 "receive unexpected message {:?} waiting for init ready",
                 msg
@@ -531,6 +542,32 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    #[serial]
+    fn test_channel_exec_failed() -> Result<()> {
+        let (sender, receiver) = &mut main_channel()?;
+        match unsafe { unistd::fork()? } {
+            unistd::ForkResult::Parent { child } => {
+                wait::waitpid(child, None)?;
+                let ret = receiver.wait_for_init_ready();
+                assert_eq!(
+                    ret.unwrap_err().to_string(),
+                    "executable sh not found in container PATH"
+                );
+                receiver.close()?;
+            }
+            unistd::ForkResult::Child => {
+                sender
+                    .exec_failed("executable sh not found in container PATH".to_string())
+                    .with_context(|| "Failed to send exec failed")?;
+                sender.close()?;
+                std::process::exit(0);
+            }
+        };
+
+        Ok(())
+    }
+
     #[test]
     #[serial]
     fn test_channel_main_graceful_exit() -> Result<()> {
@@ -1,5 +1,7 @@
 use super::args::ContainerArgs;
 use crate::apparmor;
+use crate::progress::Phase;
+use crate::selinux;
 use crate::syscall::Syscall;
 use crate::{
     capabilities, hooks, namespaces::Namespaces, process::channel, rootfs::RootFS,
@@ -8,14 +10,16 @@ use crate::{
 use anyhow::{bail, Context, Result};
 use nix::mount::MsFlags;
 use nix::sched::CloneFlags;
+use nix::sys::statvfs::{statvfs, FsFlags};
 use nix::{
     fcntl,
     unistd::{self, Gid, Uid},
 };
-use oci_spec::runtime::{LinuxNamespaceType, Spec, User};
+use oci_spec::runtime::{LinuxNamespaceType, LinuxSeccomp, Spec, User};
 use std::collections::HashMap;
 use std::{
     env, fs,
+    os::unix::io::RawFd,
     path::{Path, PathBuf},
 };
 
@@ -71,7 +75,32 @@ fn cleanup_file_descriptors(preserve_fds: i32) -> Result<()> {
     Ok(())
 }
 
-fn sysctl(kernel_params: &HashMap<String, String>) -> Result<()> {
+/// Returns true if the kernel parameter is namespaced by the IPC namespace
+fn is_ipc_sysctl(kernel_param: &str) -> bool {
+    matches!(
+        kernel_param,
+        "kernel.msgmax"
+            | "kernel.msgmnb"
+            | "kernel.msgmni"
+            | "kernel.sem"
+            | "kernel.shmall"
+            | "kernel.shmmax"
+            | "kernel.shmmni"
+            | "kernel.shm_rmid_forced"
+    ) || kernel_param.starts_with("fs.mqueue.")
+}
+
+/// Splits the kernel parameters into the ones of the IPC namespace and the rest
+fn split_ipc_sysctl(
+    kernel_params: &HashMap<String, String>,
+) -> (HashMap<&str, &str>, HashMap<&str, &str>) {
+    kernel_params
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .partition(|(k, _)| is_ipc_sysctl(k))
+}
+
+fn sysctl(kernel_params: &HashMap<&str, &str>) -> Result<()> {
     let sys = PathBuf::from("/proc/sys");
     for (kernel_param, value) in kernel_params {
         let path = sys.join(kernel_param.replace(".", "/"));
@@ -125,6 +154,50 @@ fn readonly_path(path: &Path, syscall: &dyn Syscall) -> Result<()> {
     Ok(())
 }
 
+// Remounts the root filesystem of the container read-only and verifies that it
+// actually is. In a user namespace, flags such as nosuid that have been set by a
+// more privileged mount namespace are locked, and a remount that would clear them
+// fails with EPERM. In that case the remount is retried with the current flags.
+fn readonly_rootfs(syscall: &dyn Syscall) -> Result<()> {
+    let root = Path::new("/");
+    let flags = MsFlags::MS_RDONLY | MsFlags::MS_REMOUNT | MsFlags::MS_BIND;
+    if let Err(err) = syscall.mount(None, root, None, flags, None) {
+        if !matches!(err.downcast_ref(), Some(nix::errno::Errno::EPERM)) {
+            bail!(err)
+        }
+
+        let current = statvfs(root).context("failed to stat root filesystem")?;
+        let locked = locked_mount_flags(current.flags());
+        log::debug!("remount root filesystem with locked flags {:?}", locked);
+        syscall.mount(None, root, None, flags | locked, None)?;
+    }
+
+    let stat = statvfs(root).context("failed to stat root filesystem")?;
+    if !stat.flags().contains(FsFlags::ST_RDONLY) {
+        bail!("root filesystem is still writable after remounting it read-only");
+    }
+
+    log::debug!("root filesystem remounted read-only");
+    Ok(())
+}
+
+// Mount flags that cannot be cleared by a remount from an unprivileged user namespace
+fn locked_mount_flags(flags: FsFlags) -> MsFlags {
+    let mapping = [
+        (FsFlags::ST_NOSUID, MsFlags::MS_NOSUID),
+        (FsFlags::ST_NODEV, MsFlags::MS_NODEV),
+        (FsFlags::ST_NOEXEC, MsFlags::MS_NOEXEC),
+        (FsFlags::ST_NOATIME, MsFlags::MS_NOATIME),
+        (FsFlags::ST_NODIRATIME, MsFlags::MS_NODIRATIME),
+        (FsFlags::ST_RELATIME, MsFlags::MS_RELATIME),
+    ];
+
+    mapping
+        .iter()
+        .filter(|(fs_flag, _)| flags.contains(*fs_flag))
+        .fold(MsFlags::empty(), |acc, (_, ms_flag)| acc | *ms_flag)
+}
+
 // For files, bind mounts /dev/null over the top of the specified path.
 // For directories, mounts read-only tmpfs over the top of the specified path.
 fn masked_path(path: &Path, mount_label: &Option<String>, syscall: &dyn Syscall) -> Result<()> {
@@ -198,13 +271,41 @@ pub fn container_init_process(
     let hooks = spec.hooks().as_ref();
     let container = args.container.as_ref();
     let namespaces = Namespaces::from(linux.namespaces().as_ref());
+    let seccomp_audit = seccomp::is_audit(spec.annotations().as_ref())?;
 
     // set up tty if specified
     if let Some(csocketfd) = args.console_socket {
         tty::setup_console(&csocketfd).with_context(|| "Failed to set up tty")?;
     }
 
-    apply_rest_namespaces(&namespaces, spec, syscall)?;
+    if let Some(attach_stdio) = &args.attach_stdio {
+        attach_stdio
+            .connect()
+            .context("failed to connect stdio to the attach relay")?;
+    }
+
+    args.progress.phase(Phase::Namespaces, || {
+        apply_rest_namespaces(&namespaces, spec, syscall)
+    })?;
+
+    let (ipc_kernel_params, kernel_params) =
+        split_ipc_sysctl(linux.sysctl().as_ref().unwrap_or(&HashMap::new()));
+    if args.init && !ipc_kernel_params.is_empty() {
+        // Changing the IPC parameters of the host is not allowed
+        if namespaces.get(LinuxNamespaceType::Ipc).is_none() {
+            bail!(
+                "sysctls {:?} require an ipc namespace",
+                ipc_kernel_params.keys()
+            );
+        }
+
+        // The IPC parameters in /proc/sys belong to the IPC namespace of the
+        // writing process, so they are set right after entering the namespace.
+        // Unlike the other parameters, this does not depend on the /proc of
+        // the container, which may not be mounted or be read only.
+        sysctl(&ipc_kernel_params)
+            .with_context(|| format!("failed to sysctl: {:?}", ipc_kernel_params))?;
+    }
 
     if let Some(true) = proc.no_new_privileges() {
         let _ = prctl::set_no_new_privileges(true);
@@ -214,19 +315,25 @@ pub fn container_init_process(
         // create_container hook needs to be called after the namespace setup, but
         // before pivot_root is called. This runs in the container namespaces.
         if let Some(hooks) = hooks {
-            hooks::run_hooks(hooks.create_container().as_ref(), container)
+            args.progress
+                .phase(Phase::Hooks, || {
+                    hooks::run_hooks(hooks.create_container().as_ref(), container)
+                })
                 .context("Failed to run create container hooks")?;
         }
 
         let bind_service = namespaces.get(LinuxNamespaceType::User).is_some();
         let rootfs = RootFS::new();
-        rootfs
-            .prepare_rootfs(
-                spec,
-                rootfs_path,
-                bind_service,
-                namespaces.get(LinuxNamespaceType::Cgroup).is_some(),
-            )
+        args.progress
+            .phase(Phase::Rootfs, || {
+                rootfs.prepare_rootfs(
+                    spec,
+                    rootfs_path,
+                    bind_service,
+                    namespaces.get(LinuxNamespaceType::Cgroup).is_some(),
+                    args.experimental,
+                )
+            })
             .with_context(|| "Failed to prepare rootfs")?;
 
         // Entering into the rootfs jail. If mount namespace is specified, then
@@ -248,8 +355,8 @@ pub fn container_init_process(
             .adjust_root_mount_propagation(linux)
             .context("Failed to set propagation type of root mount")?;
 
-        if let Some(kernel_params) = linux.sysctl() {
-            sysctl(kernel_params)
+        if !kernel_params.is_empty() {
+            sysctl(&kernel_params)
                 .with_context(|| format!("Failed to sysctl: {:?}", kernel_params))?;
         }
     }
@@ -259,14 +366,9 @@ pub fn container_init_process(
             .with_context(|| format!("failed to apply apparmor profile {}", profile))?;
     }
 
-    if let Some(true) = spec.root().as_ref().map(|r| r.readonly().unwrap_or(false)) {
-        syscall.mount(
-            None,
-            Path::new("/"),
-            None,
-            MsFlags::MS_RDONLY | MsFlags::MS_REMOUNT | MsFlags::MS_BIND,
-            None,
-        )?
+    if let Some(label) = proc.selinux_label() {
+        selinux::apply_label(label)
+            .with_context(|| format!("failed to apply SELinux label {}", label))?;
     }
 
     if let Some(paths) = linux.readonly_paths() {
@@ -285,6 +387,12 @@ pub fn container_init_process(
         }
     }
 
+    // The root filesystem is remounted read-only after all other mounts, because
+    // mount points may still have to be created in it until then.
+    if let Some(true) = spec.root().as_ref().map(|r| r.readonly().unwrap_or(false)) {
+        readonly_rootfs(syscall).context("failed to make root filesystem read-only")?;
+    }
+
     let cwd = format!("{}", proc.cwd().display());
     let do_chdir = if cwd.is_empty() {
         false
@@ -314,10 +422,12 @@ pub fn container_init_process(
     // as close to exec as possible.
     if let Some(seccomp) = linux.seccomp() {
         if proc.no_new_privileges().is_none() {
-            let notify_fd =
-                seccomp::initialize_seccomp(seccomp).context("failed to execute seccomp")?;
-            sync_seccomp(notify_fd, main_sender, init_receiver)
-                .context("failed to sync seccomp")?;
+            args.progress.phase(Phase::Seccomp, || -> Result<()> {
+                let notify_fd = initialize_seccomp(args, seccomp, seccomp_audit)
+                    .context("failed to execute seccomp")?;
+                sync_seccomp(notify_fd, main_sender, init_receiver)
+                    .context("failed to sync seccomp")
+            })?;
         }
     }
 
@@ -381,15 +491,42 @@ pub fn container_init_process(
         .iter()
         .for_each(|(key, value)| env::set_var(key, value));
 
+    // Resolve the executable against the PATH of the container while errors
+    // can still be reported to the main process. Otherwise a missing
+    // executable only shows up as a bare ENOENT from exec after the container
+    // has been started. With no new privileges, the lookup happens before
+    // seccomp is initialized, so the profile cannot interfere with it. Without
+    // it, the profile has been loaded above already, and a profile that forbids
+    // the stat calls of the lookup makes it report the executable as missing.
+    let executable = match proc.args() {
+        Some(args) if !args.is_empty() => {
+            match utils::get_executable_path(&args[0], env::var("PATH").ok().as_deref()) {
+                Some(path) => path,
+                None => {
+                    let err = format!("executable {} not found in container PATH", args[0]);
+                    main_sender.exec_failed(err.clone())?;
+                    bail!(err);
+                }
+            }
+        }
+        _ => {
+            let err = "on non-Windows, at least one process arg entry is required".to_string();
+            main_sender.exec_failed(err.clone())?;
+            bail!(err);
+        }
+    };
+
     // Initialize seccomp profile right before we are ready to execute the
     // payload so as few syscalls will happen between here and payload exec. The
     // notify socket will still need network related syscalls.
     if let Some(seccomp) = linux.seccomp() {
         if proc.no_new_privileges().is_some() {
-            let notify_fd =
-                seccomp::initialize_seccomp(seccomp).context("failed to execute seccomp")?;
-            sync_seccomp(notify_fd, main_sender, init_receiver)
-                .context("failed to sync seccomp")?;
+            args.progress.phase(Phase::Seccomp, || -> Result<()> {
+                let notify_fd = initialize_seccomp(args, seccomp, seccomp_audit)
+                    .context("failed to execute seccomp")?;
+                sync_seccomp(notify_fd, main_sender, init_receiver)
+                    .context("failed to sync seccomp")
+            })?;
         }
     }
 
@@ -414,9 +551,7 @@ pub fn container_init_process(
     }
 
     if let Some(args) = proc.args() {
-        utils::do_exec(&args[0], args)?;
-    } else {
-        bail!("on non-Windows, at least one process arg entry is required")
+        utils::do_exec(&executable, args)?;
     }
 
     // After do_exec is called, the process is replaced with the container
@@ -490,6 +625,21 @@ fn set_supplementary_gids(
     Ok(())
 }
 
+/// Loads the seccomp profile, which may have been compiled ahead of time
+fn initialize_seccomp(
+    args: &ContainerArgs,
+    seccomp: &LinuxSeccomp,
+    audit: bool,
+) -> Result<Option<RawFd>> {
+    match &args.seccomp_program {
+        Some(program) => {
+            program.load()?;
+            Ok(None)
+        }
+        None => seccomp::initialize_seccomp(seccomp, audit),
+    }
+}
+
 fn sync_seccomp(
     fd: Option<i32>,
     main_sender: &mut channel::MainSender,
@@ -603,6 +753,42 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_locked_mount_flags() {
+        assert_eq!(locked_mount_flags(FsFlags::empty()), MsFlags::empty());
+        assert_eq!(
+            locked_mount_flags(
+                FsFlags::ST_RDONLY | FsFlags::ST_NOSUID | FsFlags::ST_NODEV | FsFlags::ST_RELATIME
+            ),
+            MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_RELATIME
+        );
+        assert_eq!(
+            locked_mount_flags(FsFlags::ST_NOEXEC | FsFlags::ST_NOATIME),
+            MsFlags::MS_NOEXEC | MsFlags::MS_NOATIME
+        );
+    }
+
+    #[test]
+    fn test_split_ipc_sysctl() {
+        let kernel_params: HashMap<String, String> = [
+            ("kernel.shmmax", "68719476736"),
+            ("fs.mqueue.msg_max", "100"),
+            ("net.ipv4.ip_forward", "1"),
+            ("kernel.domainname", "example.com"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        let (ipc, rest) = split_ipc_sysctl(&kernel_params);
+        let mut ipc: Vec<_> = ipc.keys().copied().collect();
+        ipc.sort();
+        let mut rest: Vec<_> = rest.keys().copied().collect();
+        rest.sort();
+        assert_eq!(ipc, vec!["fs.mqueue.msg_max", "kernel.shmmax"]);
+        assert_eq!(rest, vec!["kernel.domainname", "net.ipv4.ip_forward"]);
+    }
+
     #[test]
     fn test_apply_rest_namespaces() -> Result<()> {
         let syscall = create_syscall();
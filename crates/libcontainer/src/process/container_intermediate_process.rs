@@ -1,4 +1,4 @@
-use crate::{namespaces::Namespaces, process::channel, process::fork};
+use crate::{namespaces::Namespaces, process::channel, process::fork, progress::Phase};
 use anyhow::{Context, Error, Result};
 use libcgroups::common::CgroupManager;
 use nix::unistd::{Gid, Pid, Uid};
@@ -16,6 +16,7 @@ pub fn container_intermediate_process(
     init_sender: &mut channel::InitSender,
     init_receiver: &mut channel::InitReceiver,
     main_sender: &mut channel::MainSender,
+    in_cgroup: bool,
 ) -> Result<()> {
     let command = &args.syscall;
     let spec = &args.spec;
@@ -32,40 +33,48 @@ pub fn container_intermediate_process(
     // In addition this needs to be done before we enter the cgroup namespace as
     // the cgroup of the process will form the root of the cgroup hierarchy in
     // the cgroup namespace.
-    apply_cgroups(
-        args.cgroup_manager.as_ref(),
-        linux.resources().as_ref(),
-        args.init,
-    )
-    .context("failed to apply cgroups")?;
+    args.progress
+        .phase(Phase::Cgroups, || {
+            apply_cgroups(
+                args.cgroup_manager.as_ref(),
+                linux.resources().as_ref(),
+                args.init,
+                in_cgroup,
+            )
+        })
+        .context("failed to apply cgroups")?;
 
     // if new user is specified in specification, this will be true and new
     // namespace will be created, check
     // https://man7.org/linux/man-pages/man7/user_namespaces.7.html for more
     // information
     if let Some(user_namespace) = namespaces.get(LinuxNamespaceType::User) {
-        namespaces
-            .unshare_or_setns(user_namespace)
-            .with_context(|| format!("Failed to enter user namespace: {:?}", user_namespace))?;
-        if user_namespace.path().is_none() {
-            log::debug!("creating new user namespace");
-            // child needs to be dumpable, otherwise the non root parent is not
-            // allowed to write the uid/gid maps
-            prctl::set_dumpable(true).unwrap();
-            main_sender.identifier_mapping_request()?;
-            intermediate_receiver.wait_for_mapping_ack()?;
-            prctl::set_dumpable(false).unwrap();
-        }
-
-        // After UID and GID mapping is configured correctly in the Youki main
-        // process, We want to make sure continue as the root user inside the
-        // new user namespace. This is required because the process of
-        // configuring the container process will require root, even though the
-        // root in the user namespace likely is mapped to an non-priviliged user
-        // on the parent user namespace.
-        command.set_id(Uid::from_raw(0), Gid::from_raw(0)).context(
-            "failed to configure uid and gid root in the beginning of a new user namespace",
-        )?;
+        args.progress.phase(Phase::Namespaces, || -> Result<()> {
+            namespaces
+                .unshare_or_setns(user_namespace)
+                .with_context(|| format!("Failed to enter user namespace: {:?}", user_namespace))?;
+            if user_namespace.path().is_none() {
+                log::debug!("creating new user namespace");
+                // child needs to be dumpable, otherwise the non root parent is not
+                // allowed to write the uid/gid maps
+                prctl::set_dumpable(true).unwrap();
+                main_sender.identifier_mapping_request()?;
+                intermediate_receiver.wait_for_mapping_ack()?;
+                prctl::set_dumpable(false).unwrap();
+            }
+
+            // After UID and GID mapping is configured correctly in the Youki main
+            // process, We want to make sure continue as the root user inside the
+            // new user namespace. This is required because the process of
+            // configuring the container process will require root, even though the
+            // root in the user namespace likely is mapped to an non-priviliged user
+            // on the parent user namespace.
+            command.set_id(Uid::from_raw(0), Gid::from_raw(0)).context(
+                "failed to configure uid and gid root in the beginning of a new user namespace",
+            )?;
+
+            Ok(())
+        })?;
     }
 
     // set limits and namespaces to the process
@@ -122,25 +131,37 @@ fn apply_cgroups<C: CgroupManager + ?Sized>(
     cmanager: &C,
     resources: Option<&LinuxResources>,
     init: bool,
+    in_cgroup: bool,
 ) -> Result<(), Error> {
-    let pid = Pid::from_raw(Process::myself()?.pid());
-    cmanager
-        .add_task(pid)
-        .with_context(|| format!("failed to add task {} to cgroup manager", pid))?;
-
-    if let Some(resources) = resources {
-        if init {
-            let controller_opt = libcgroups::common::ControllerOpt {
-                resources,
-                freezer_state: None,
-                oom_score_adj: None,
-                disable_oom_killer: false,
-            };
+    // the process may have been started in the cgroup already
+    if !in_cgroup {
+        let pid = Pid::from_raw(Process::myself()?.pid());
+        cmanager
+            .add_task(pid)
+            .with_context(|| format!("failed to add task {} to cgroup manager", pid))?;
+    }
 
-            cmanager
-                .apply(&controller_opt)
-                .context("failed to apply resource limits to cgroup")?;
-        }
+    if init {
+        // the default device rules are applied also if the spec has no
+        // resources or no device rules
+        let default_resources = LinuxResources::default();
+        let resources =
+            libcgroups::common::with_device_rules(resources.unwrap_or(&default_resources));
+        let controller_opt = libcgroups::common::ControllerOpt {
+            resources: &resources,
+            freezer_state: None,
+            oom_score_adj: None,
+            disable_oom_killer: resources
+                .memory()
+                .as_ref()
+                .and_then(|memory| memory.disable_oom_killer())
+                .unwrap_or(false),
+        };
+
+        // the writes of all controllers are batched, so that invalid
+        // values are reported together
+        libcgroups::batch::batched(|| cmanager.apply(&controller_opt))
+            .context("failed to apply resource limits to cgroup")?;
     }
 
     Ok(())
@@ -162,7 +183,7 @@ mod tests {
         let resources = LinuxResources::default();
 
         // act
-        apply_cgroups(&cmanager, Some(&resources), true)?;
+        apply_cgroups(&cmanager, Some(&resources), true, false)?;
 
         // assert
         assert!(cmanager.get_add_task_args().len() == 1);
@@ -181,7 +202,7 @@ mod tests {
         let resources = LinuxResources::default();
 
         // act
-        apply_cgroups(&cmanager, Some(&resources), false)?;
+        apply_cgroups(&cmanager, Some(&resources), false, false)?;
 
         // assert
         assert_eq!(
@@ -198,13 +219,34 @@ mod tests {
         let cmanager = TestManager::default();
 
         // act
-        apply_cgroups(&cmanager, None, true)?;
+        apply_cgroups(&cmanager, None, true, false)?;
         // assert
         assert_eq!(
             cmanager.get_add_task_args()[0],
             Pid::from_raw(Process::myself()?.pid())
         );
-        assert!(!cmanager.apply_called());
+        // the default device rules are applied nevertheless
+        assert_eq!(
+            cmanager
+                .get_applied_resources()
+                .and_then(|resources| resources.devices().clone()),
+            Some(Vec::new())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn apply_cgroup_in_cgroup() -> Result<()> {
+        // arrange
+        let cmanager = TestManager::default();
+        let resources = LinuxResources::default();
+
+        // act
+        apply_cgroups(&cmanager, Some(&resources), true, true)?;
+
+        // assert
+        assert!(cmanager.get_add_task_args().is_empty());
+        assert!(cmanager.apply_called());
         Ok(())
     }
 }
@@ -1,5 +1,6 @@
 use crate::{
-    container::ContainerProcessState,
+    container::{runtime_metrics, ContainerProcessState},
+    experimental::Feature,
     process::{args::ContainerArgs, channel, container_intermediate_process, fork},
     rootless::Rootless,
     seccomp, utils,
@@ -10,7 +11,8 @@ use nix::{
     unistd::{self, Pid},
 };
 use oci_spec::runtime;
-use std::path::Path;
+use procfs::process::Process;
+use std::{fs::File, os::unix::io::AsRawFd, path::Path};
 
 pub fn container_main_process(container_args: &ContainerArgs) -> Result<Pid> {
     // We use a set of channels to communicate between parent and child process.
@@ -22,7 +24,7 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<Pid> {
     let (intermediate_sender, intermediate_receiver) = &mut channel::intermediate_channel()?;
     let (init_sender, init_receiver) = &mut channel::init_channel()?;
 
-    let intermediate_pid = fork::container_fork(|| {
+    let mut intermediate = |in_cgroup| {
         container_intermediate_process::container_intermediate_process(
             container_args,
             intermediate_sender,
@@ -30,8 +32,13 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<Pid> {
             init_sender,
             init_receiver,
             main_sender,
+            in_cgroup,
         )
-    })?;
+    };
+    let intermediate_pid = match clone_cgroup(container_args)? {
+        Some(cgroup) => fork::container_fork_into_cgroup(cgroup.as_raw_fd(), intermediate)?,
+        None => fork::container_fork(|| intermediate(false))?,
+    };
     // Close down unused fds. The corresponding fds are duplicated to the
     // child process during fork.
     main_sender
@@ -42,9 +49,11 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<Pid> {
     // the main process to set up uid and gid mapping, once the intermediate
     // process enters into a new user namespace.
     if let Some(rootless) = &container_args.rootless {
-        main_receiver.wait_for_mapping_request()?;
-        setup_mapping(rootless, intermediate_pid)?;
-        intermediate_sender.mapping_written()?;
+        runtime_metrics::timed("sync.mapping", || -> Result<()> {
+            main_receiver.wait_for_mapping_request()?;
+            setup_mapping(rootless, intermediate_pid)?;
+            intermediate_sender.mapping_written()
+        })?;
     }
 
     // At this point, we don't need to send any message to intermediate process anymore,
@@ -55,7 +64,9 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<Pid> {
 
     // The intermediate process will send the init pid once it forks the init
     // process.  The intermediate process should exit after this point.
-    let init_pid = main_receiver.wait_for_intermediate_ready()?;
+    let init_pid = runtime_metrics::timed("sync.intermediate_ready", || {
+        main_receiver.wait_for_intermediate_ready()
+    })?;
 
     if let Some(linux) = container_args.spec.linux() {
         if let Some(seccomp) = linux.seccomp() {
@@ -72,8 +83,11 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<Pid> {
                     .state
                     .clone(),
             };
-            sync_seccomp(seccomp, &state, init_sender, main_receiver)
-                .context("failed to sync seccomp with init")?;
+            let audit = seccomp::is_audit(container_args.spec.annotations().as_ref())?;
+            runtime_metrics::timed("sync.seccomp", || {
+                sync_seccomp(seccomp, audit, &state, init_sender, main_receiver)
+            })
+            .context("failed to sync seccomp with init")?;
         }
     }
 
@@ -83,22 +97,62 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<Pid> {
         .close()
         .context("failed to close unused init sender")?;
 
-    main_receiver
-        .wait_for_init_ready()
+    runtime_metrics::timed("sync.init_ready", || main_receiver.wait_for_init_ready())
         .context("failed to wait for init ready")?;
 
     log::debug!("init pid is {:?}", init_pid);
+    log_init_memory(init_pid);
 
     Ok(init_pid)
 }
 
+/// Logs the memory the init process holds while it waits for the start. This
+/// is what a created container costs until it is started, see the notes on
+/// memory in the developer documentation of youki.
+fn log_init_memory(init_pid: Pid) {
+    match Process::new(init_pid.as_raw()).and_then(|process| process.status()) {
+        Ok(status) => log::debug!(
+            "init process {} uses {} kB of memory, at most {} kB",
+            init_pid,
+            status.vmrss.unwrap_or_default(),
+            status.vmhwm.unwrap_or_default()
+        ),
+        Err(err) => log::debug!("failed to read the memory of the init process: {}", err),
+    }
+}
+
+/// Opens the cgroup to start the intermediate process in, if the experimental
+/// clone3-cgroup feature is enabled and the cgroup manager supports it
+fn clone_cgroup(container_args: &ContainerArgs) -> Result<Option<File>> {
+    if !container_args
+        .experimental
+        .is_enabled(Feature::Clone3Cgroup)
+    {
+        return Ok(None);
+    }
+
+    match container_args.cgroup_manager.prepare_cgroup()? {
+        Some(path) => {
+            let cgroup = File::open(&path)
+                .with_context(|| format!("failed to open cgroup {}", path.display()))?;
+            Ok(Some(cgroup))
+        }
+        None => {
+            log::debug!("cgroup manager does not support starting processes in the cgroup");
+            Ok(None)
+        }
+    }
+}
+
 fn sync_seccomp(
     seccomp: &runtime::LinuxSeccomp,
+    audit: bool,
     state: &ContainerProcessState,
     init_sender: &mut channel::InitSender,
     main_receiver: &mut channel::MainReceiver,
 ) -> Result<()> {
-    if seccomp::is_notify(seccomp) {
+    // notify actions are only logged in audit mode
+    if seccomp::is_notify(seccomp) && !audit {
         log::debug!("main process waiting for sync seccomp");
         let seccomp_fd = main_receiver.wait_for_seccomp_request()?;
         let listener_path = seccomp
@@ -316,6 +370,7 @@ mod tests {
                         .unwrap()])
                     .build()
                     .unwrap(),
+                false,
                 &state,
                 &mut init_sender,
                 &mut main_receiver,
@@ -1,6 +1,28 @@
 use anyhow::Result;
+use nix::errno::Errno;
 use nix::unistd;
-use nix::unistd::Pid;
+use nix::unistd::{ForkResult, Pid};
+use std::os::unix::io::RawFd;
+
+/// Flag of clone3(2) to start the child in the cgroup given by a file descriptor
+const CLONE_INTO_CGROUP: u64 = 0x200000000;
+
+/// Arguments of clone3(2), see struct clone_args in linux/sched.h
+#[repr(C)]
+#[derive(Default)]
+struct CloneArgs {
+    flags: u64,
+    pidfd: u64,
+    child_tid: u64,
+    parent_tid: u64,
+    exit_signal: u64,
+    stack: u64,
+    stack_size: u64,
+    tls: u64,
+    set_tid: u64,
+    set_tid_size: u64,
+    cgroup: u64,
+}
 
 // Execute the cb in another process. Make the fork works more like thread_spawn
 // or clone, so it is easier to reason. Compared to clone call, fork is easier
@@ -10,24 +32,99 @@ use nix::unistd::Pid;
 // lot of hassel to deal with every details.
 pub fn container_fork<F: FnOnce() -> Result<()>>(cb: F) -> Result<Pid> {
     match unsafe { unistd::fork()? } {
-        unistd::ForkResult::Parent { child } => Ok(child),
-        unistd::ForkResult::Child => {
-            let ret = if let Err(error) = cb() {
-                log::debug!("failed to run fork: {:?}", error);
-                -1
-            } else {
-                0
-            };
-            std::process::exit(ret);
+        ForkResult::Parent { child } => Ok(child),
+        ForkResult::Child => run_child(cb),
+    }
+}
+
+/// Like `container_fork`, but the child is started in the cgroup v2 cgroup
+/// the file descriptor refers to with clone3(2) and CLONE_INTO_CGROUP, so
+/// that it does not have to be moved into the cgroup afterwards. Kernels
+/// before 5.7 do not support this and a multithreaded process must not use
+/// it, in which case the child is forked as usual. The callback is told
+/// whether the child has been started in the cgroup.
+pub fn container_fork_into_cgroup<F: FnOnce(bool) -> Result<()>>(
+    cgroup_fd: RawFd,
+    cb: F,
+) -> Result<Pid> {
+    let cloned = if is_single_threaded() {
+        clone_into_cgroup(cgroup_fd)
+    } else {
+        Err(Errno::EAGAIN)
+    };
+    let (result, in_cgroup) = match cloned {
+        Ok(result) => (result, true),
+        Err(errno) => {
+            log::debug!(
+                "failed to clone into cgroup, falling back to fork: {}",
+                errno
+            );
+            (unsafe { unistd::fork()? }, false)
         }
+    };
+
+    match result {
+        ForkResult::Parent { child } => Ok(child),
+        ForkResult::Child => run_child(|| cb(in_cgroup)),
+    }
+}
+
+/// Checks if the calling process has no other threads
+fn is_single_threaded() -> bool {
+    std::fs::read_dir("/proc/self/task")
+        .map(|tasks| tasks.count() == 1)
+        .unwrap_or_default()
+}
+
+/// Calls clone3(2) directly, as libc has no wrapper for it that returns in
+/// the child like fork(2) does. This skips what fork(2) of libc does around
+/// the syscall: it runs the pthread_atfork handlers, which youki does not
+/// register, and resets the locks of the allocator and stdio that other
+/// threads may hold at the time of the fork. The latter only matters if
+/// there are other threads, so this must only be called by a single-threaded
+/// process. glibc no longer caches the pid since 2.25, so getpid(2) returns
+/// the pid of the child in the child.
+fn clone_into_cgroup(cgroup_fd: RawFd) -> nix::Result<ForkResult> {
+    let mut args = CloneArgs {
+        flags: CLONE_INTO_CGROUP,
+        exit_signal: libc::SIGCHLD as u64,
+        cgroup: cgroup_fd as u64,
+        ..Default::default()
+    };
+    // Without a stack, the child continues on a copy of the stack of the
+    // parent, just like with fork(2)
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_clone3,
+            &mut args as *mut CloneArgs,
+            std::mem::size_of::<CloneArgs>(),
+        )
+    };
+    match Errno::result(ret)? {
+        0 => Ok(ForkResult::Child),
+        child => Ok(ForkResult::Parent {
+            child: Pid::from_raw(child as i32),
+        }),
     }
 }
 
+fn run_child<F: FnOnce() -> Result<()>>(cb: F) -> ! {
+    let ret = if let Err(error) = cb() {
+        log::debug!("failed to run fork: {:?}", error);
+        -1
+    } else {
+        0
+    };
+    std::process::exit(ret);
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::utils::test_utils;
     use anyhow::{bail, Result};
     use nix::sys::wait::{waitpid, WaitStatus};
+    use std::os::unix::io::AsRawFd;
 
     #[test]
     fn test_container_fork() -> Result<()> {
@@ -42,6 +139,46 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_is_single_threaded() -> Result<()> {
+        // the test harness runs tests on threads, a forked child has none
+        test_utils::test_in_child_process(|| {
+            if !is_single_threaded() {
+                bail!("forked child should be single-threaded");
+            }
+            let (sender, receiver) = std::sync::mpsc::channel::<()>();
+            let thread = std::thread::spawn(move || receiver.recv());
+            let single_threaded = is_single_threaded();
+            drop(sender);
+            let _ = thread.join();
+            if single_threaded {
+                bail!("process with a thread should not be single-threaded");
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_container_fork_into_cgroup() -> Result<()> {
+        // a directory that is not a cgroup is rejected by clone3, so the
+        // child is forked as usual
+        let dir = std::fs::File::open(std::env::temp_dir())?;
+        let pid = container_fork_into_cgroup(dir.as_raw_fd(), |in_cgroup| {
+            if in_cgroup {
+                bail!("child has been cloned into a directory that is not a cgroup");
+            }
+            Ok(())
+        })?;
+        match waitpid(pid, None).expect("wait pid failed.") {
+            WaitStatus::Exited(p, status) => {
+                assert_eq!(pid, p);
+                assert_eq!(status, 0);
+                Ok(())
+            }
+            _ => bail!("test failed"),
+        }
+    }
+
     #[test]
     fn test_container_err_fork() -> Result<()> {
         let pid = container_fork(|| bail!(""))?;
@@ -0,0 +1,169 @@
+//! Machine readable progress records of the creation of a container. Each
+//! phase is reported as started and then as completed or failed, one JSON
+//! object per line, e.g.
+//!
+//! ```json
+//! {"time":"2022-01-20T10:00:00.000000Z","pid":4242,"phase":"rootfs","status":"started"}
+//! ```
+//!
+//! This allows orchestration tooling to show the status of a create and to
+//! pinpoint the phase a stuck create is in. Phases run in different processes
+//! and a phase like namespaces may be reported by more than one of them, which
+//! the pid of the record tells apart.
+use std::os::unix::io::RawFd;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use nix::{fcntl, unistd};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    Cgroups,
+    Namespaces,
+    Rootfs,
+    Seccomp,
+    Hooks,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Started,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+struct Record<'a> {
+    time: DateTime<Utc>,
+    pid: i32,
+    phase: Phase,
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+/// Reports the progress of the phases of a create. Reporting is disabled by
+/// default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Progress {
+    fd: Option<RawFd>,
+}
+
+impl Progress {
+    /// Reports the progress on the stderr of the runtime. The container
+    /// process replaces its stdio with the console or the pipes of the
+    /// container, so the records are written to a duplicate of stderr. The
+    /// duplicate is not inherited by the payload of the container and is
+    /// allocated at or above `min_fd`, so that it does not take the place of
+    /// file descriptors that are passed to the container.
+    pub fn to_stderr(min_fd: RawFd) -> Result<Self> {
+        let fd = fcntl::fcntl(libc::STDERR_FILENO, fcntl::F_DUPFD_CLOEXEC(min_fd))
+            .context("failed to duplicate stderr for progress records")?;
+        Ok(Self { fd: Some(fd) })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.fd.is_some()
+    }
+
+    /// Closes the duplicate of stderr of this process
+    pub fn close(&self) {
+        if let Some(fd) = self.fd {
+            let _ = unistd::close(fd);
+        }
+    }
+
+    /// Runs a phase and reports when it started and whether it completed or failed
+    pub fn phase<T, F: FnOnce() -> Result<T>>(&self, phase: Phase, f: F) -> Result<T> {
+        self.report(phase, Status::Started, None);
+        let result = f();
+        match &result {
+            Ok(_) => self.report(phase, Status::Completed, None),
+            Err(err) => self.report(phase, Status::Failed, Some(&format!("{:#}", err))),
+        }
+
+        result
+    }
+
+    fn report(&self, phase: Phase, status: Status, error: Option<&str>) {
+        let fd = match self.fd {
+            Some(fd) => fd,
+            None => return,
+        };
+
+        let record = Record {
+            time: Utc::now(),
+            pid: unistd::getpid().as_raw(),
+            phase,
+            status,
+            error,
+        };
+
+        // Progress is informational and must never fail the create. The
+        // record is written with a single write, so that the records of
+        // different processes do not interleave.
+        if let Ok(mut line) = serde_json::to_string(&record) {
+            line.push('\n');
+            if let Err(err) = unistd::write(fd, line.as_bytes()) {
+                log::debug!("failed to write progress record: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::bail;
+    use nix::unistd::{pipe, read};
+
+    fn read_records(fd: RawFd) -> Result<Vec<serde_json::Value>> {
+        let mut buf = [0u8; 4096];
+        let len = read(fd, &mut buf)?;
+        std::str::from_utf8(&buf[..len])?
+            .lines()
+            .map(|line| Ok(serde_json::from_str(line)?))
+            .collect()
+    }
+
+    #[test]
+    fn test_phase() -> Result<()> {
+        let (reader, writer) = pipe()?;
+        let progress = Progress { fd: Some(writer) };
+
+        assert_eq!(progress.phase(Phase::Rootfs, || Ok(42))?, 42);
+        assert!(progress
+            .phase(Phase::Seccomp, || -> Result<()> { bail!("denied") })
+            .is_err());
+        unistd::close(writer)?;
+
+        let records = read_records(reader)?;
+        let summary: Vec<(&str, &str)> = records
+            .iter()
+            .map(|r| (r["phase"].as_str().unwrap(), r["status"].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("rootfs", "started"),
+                ("rootfs", "completed"),
+                ("seccomp", "started"),
+                ("seccomp", "failed"),
+            ]
+        );
+        assert_eq!(records[3]["error"], "denied");
+        assert_eq!(records[0]["pid"], unistd::getpid().as_raw());
+        Ok(())
+    }
+
+    #[test]
+    fn test_disabled() -> Result<()> {
+        let progress = Progress::default();
+        assert!(!progress.is_enabled());
+        assert_eq!(progress.phase(Phase::Hooks, || Ok(1))?, 1);
+        Ok(())
+    }
+}
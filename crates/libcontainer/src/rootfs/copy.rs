@@ -0,0 +1,282 @@
+//! Copying of directory trees for the tmpcopyup mount option, which populates
+//! a tmpfs with the contents of the directory it is mounted over.
+use anyhow::{Context, Result};
+use nix::{
+    errno::Errno,
+    sys::stat::{mknod, Mode, SFlag},
+    unistd::{fchownat, FchownatFlags, Gid, Uid},
+};
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    fs::{self, Metadata},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{symlink, MetadataExt},
+    },
+    path::{Path, PathBuf},
+    ptr,
+};
+
+/// Copies the contents of a directory recursively, preserving the type,
+/// ownership, permissions and extended attributes of the entries. Like
+/// `cp -ax`, the copy stays on the filesystem of `src`: mount points are
+/// copied as empty directories and their contents are skipped. Hard links
+/// between the copied files are preserved.
+pub fn copy_dir_contents(src: &Path, dst: &Path) -> Result<()> {
+    let metadata = fs::metadata(src).with_context(|| format!("failed to stat {:?}", src))?;
+    TreeCopy {
+        dev: metadata.dev(),
+        links: HashMap::new(),
+    }
+    .copy_dir_contents(src, dst)
+}
+
+struct TreeCopy {
+    /// Device of the filesystem that is copied
+    dev: u64,
+    /// Copies of the files with several links, keyed by device and inode
+    links: HashMap<(u64, u64), PathBuf>,
+}
+
+impl TreeCopy {
+    fn copy_dir_contents(&mut self, src: &Path, dst: &Path) -> Result<()> {
+        for entry in fs::read_dir(src).with_context(|| format!("failed to read {:?}", src))? {
+            let from = entry?.path();
+            let to = dst.join(from.file_name().unwrap());
+            let metadata = fs::symlink_metadata(&from)
+                .with_context(|| format!("failed to stat {:?}", from))?;
+            self.copy_entry(&from, &to, &metadata)?;
+        }
+
+        Ok(())
+    }
+
+    fn copy_entry(&mut self, from: &Path, to: &Path, metadata: &Metadata) -> Result<()> {
+        let file_type = metadata.file_type();
+        if metadata.dev() != self.dev {
+            if !file_type.is_dir() {
+                log::debug!("skipping {:?} on another filesystem", from);
+                return Ok(());
+            }
+            // a mount point, which is kept so that the tree keeps its shape
+            fs::create_dir(to).with_context(|| format!("failed to create {:?}", to))?;
+            return copy_metadata(from, to, metadata);
+        }
+
+        if !file_type.is_dir() && metadata.nlink() > 1 {
+            let key = (metadata.dev(), metadata.ino());
+            if let Some(link) = self.links.get(&key) {
+                // the link shares the owner, mode and xattrs of the copy
+                return fs::hard_link(link, to)
+                    .with_context(|| format!("failed to link {:?} to {:?}", to, link));
+            }
+            self.links.insert(key, to.to_owned());
+        }
+
+        if file_type.is_dir() {
+            fs::create_dir(to).with_context(|| format!("failed to create {:?}", to))?;
+            self.copy_dir_contents(from, to)?;
+        } else if file_type.is_file() {
+            fs::copy(from, to).with_context(|| format!("failed to copy {:?}", from))?;
+        } else if file_type.is_symlink() {
+            symlink(fs::read_link(from)?, to)
+                .with_context(|| format!("failed to copy symlink {:?}", from))?;
+        } else {
+            // device nodes, fifos and sockets
+            mknod(
+                to,
+                SFlag::from_bits_truncate(metadata.mode()),
+                Mode::from_bits_truncate(metadata.mode()),
+                metadata.rdev(),
+            )
+            .with_context(|| format!("failed to copy node {:?}", from))?;
+        }
+
+        copy_metadata(from, to, metadata)
+    }
+}
+
+fn copy_metadata(from: &Path, to: &Path, metadata: &Metadata) -> Result<()> {
+    // The ids are those seen in the user namespace of the container. Files that
+    // are owned by an unmapped id cannot keep their owner and end up owned by
+    // the user of the container.
+    if let Err(errno) = fchownat(
+        None,
+        to,
+        Some(Uid::from_raw(metadata.uid())),
+        Some(Gid::from_raw(metadata.gid())),
+        FchownatFlags::NoFollowSymlink,
+    ) {
+        log::debug!("failed to copy ownership of {:?}: {}", from, errno);
+    }
+
+    // the mode has to be set after the owner, because chown clears setuid and setgid
+    if !metadata.file_type().is_symlink() {
+        fs::set_permissions(to, metadata.permissions())
+            .with_context(|| format!("failed to copy permissions of {:?}", from))?;
+    }
+
+    copy_xattrs(from, to)
+}
+
+/// Copies the extended attributes that can be set in the current namespaces.
+/// Attributes in privileged namespaces such as security.* may be rejected.
+fn copy_xattrs(from: &Path, to: &Path) -> Result<()> {
+    let from_c = CString::new(from.as_os_str().as_bytes())?;
+    let to_c = CString::new(to.as_os_str().as_bytes())?;
+
+    let size = unsafe { libc::llistxattr(from_c.as_ptr(), ptr::null_mut(), 0) };
+    if size < 0 {
+        return match Errno::last() {
+            Errno::ENOTSUP => Ok(()),
+            errno => Err(errno).with_context(|| format!("failed to list xattrs of {:?}", from)),
+        };
+    }
+    if size == 0 {
+        return Ok(());
+    }
+
+    let mut names = vec![0u8; size as usize];
+    let size = unsafe { libc::llistxattr(from_c.as_ptr(), names.as_mut_ptr().cast(), names.len()) };
+    if size < 0 {
+        return Err(Errno::last()).with_context(|| format!("failed to list xattrs of {:?}", from));
+    }
+
+    for name in names[..size as usize]
+        .split(|b| *b == 0)
+        .filter(|name| !name.is_empty())
+    {
+        let name = CString::new(name)?;
+        let len = unsafe { libc::lgetxattr(from_c.as_ptr(), name.as_ptr(), ptr::null_mut(), 0) };
+        if len < 0 {
+            log::debug!(
+                "failed to get xattr {:?} of {:?}: {}",
+                name,
+                from,
+                Errno::last()
+            );
+            continue;
+        }
+
+        let mut value = vec![0u8; len as usize];
+        let len = unsafe {
+            libc::lgetxattr(
+                from_c.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr().cast(),
+                value.len(),
+            )
+        };
+        if len < 0 {
+            log::debug!(
+                "failed to get xattr {:?} of {:?}: {}",
+                name,
+                from,
+                Errno::last()
+            );
+            continue;
+        }
+
+        let res = unsafe {
+            libc::lsetxattr(
+                to_c.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                len as usize,
+                0,
+            )
+        };
+        if res < 0 {
+            log::debug!(
+                "failed to copy xattr {:?} of {:?}: {}",
+                name,
+                from,
+                Errno::last()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_copy_dir_contents() -> Result<()> {
+        let src = create_temp_dir("test_copy_dir_contents_src")?;
+        let dst = create_temp_dir("test_copy_dir_contents_dst")?;
+
+        fs::create_dir(src.path().join("dir"))?;
+        fs::set_permissions(src.path().join("dir"), fs::Permissions::from_mode(0o750))?;
+        fs::write(src.path().join("dir/file"), "content")?;
+        fs::set_permissions(
+            src.path().join("dir/file"),
+            fs::Permissions::from_mode(0o640),
+        )?;
+        symlink("dir/file", src.path().join("link"))?;
+
+        copy_dir_contents(src.path(), dst.path())?;
+
+        let dir = fs::metadata(dst.path().join("dir"))?;
+        assert!(dir.is_dir());
+        assert_eq!(dir.permissions().mode() & 0o777, 0o750);
+        let file = fs::metadata(dst.path().join("dir/file"))?;
+        assert_eq!(file.permissions().mode() & 0o777, 0o640);
+        assert_eq!(fs::read_to_string(dst.path().join("dir/file"))?, "content");
+        assert_eq!(
+            fs::read_link(dst.path().join("link"))?,
+            Path::new("dir/file")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_dir_contents_preserves_hard_links() -> Result<()> {
+        let src = create_temp_dir("test_copy_dir_contents_hard_links_src")?;
+        let dst = create_temp_dir("test_copy_dir_contents_hard_links_dst")?;
+
+        fs::create_dir(src.path().join("dir"))?;
+        fs::write(src.path().join("file"), "content")?;
+        fs::hard_link(src.path().join("file"), src.path().join("dir/link"))?;
+        fs::write(src.path().join("other"), "content")?;
+
+        copy_dir_contents(src.path(), dst.path())?;
+
+        let file = fs::metadata(dst.path().join("file"))?;
+        let link = fs::metadata(dst.path().join("dir/link"))?;
+        let other = fs::metadata(dst.path().join("other"))?;
+        assert_eq!(file.ino(), link.ino());
+        assert_eq!(file.nlink(), 2);
+        assert_ne!(file.ino(), other.ino());
+        assert_eq!(fs::read_to_string(dst.path().join("dir/link"))?, "content");
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_dir_contents_stays_on_one_filesystem() -> Result<()> {
+        let src = create_temp_dir("test_copy_dir_contents_one_fs_src")?;
+        let dst = create_temp_dir("test_copy_dir_contents_one_fs_dst")?;
+
+        fs::create_dir(src.path().join("mnt"))?;
+        fs::write(src.path().join("mnt/file"), "content")?;
+        fs::write(src.path().join("file"), "content")?;
+
+        // pretend that the entries are on another filesystem than the copied one
+        let dev = fs::metadata(src.path())?.dev();
+        TreeCopy {
+            dev: dev.wrapping_add(1),
+            links: HashMap::new(),
+        }
+        .copy_dir_contents(src.path(), dst.path())?;
+
+        assert!(dst.path().join("mnt").is_dir());
+        assert_eq!(fs::read_dir(dst.path().join("mnt"))?.count(), 0);
+        assert!(!dst.path().join("file").exists());
+        Ok(())
+    }
+}
@@ -11,6 +11,8 @@ use nix::{
 use oci_spec::runtime::LinuxDevice;
 use std::path::{Path, PathBuf};
 
+const DEFAULT_FILE_MODE: u32 = 0o666;
+
 pub struct Device {
     syscall: Box<dyn Syscall>,
 }
@@ -84,17 +86,49 @@ impl Device {
         let full_container_path = create_container_dev_path(rootfs, dev)
             .with_context(|| format!("could not create container path for device {:?}", dev))?;
 
-        self.syscall.mknod(
-            &full_container_path,
-            to_sflag(dev.typ()),
-            Mode::from_bits_truncate(dev.file_mode().unwrap_or(0)),
-            makedev(dev.major(), dev.minor()),
-        )?;
-        self.syscall.chown(
-            &full_container_path,
-            dev.uid().map(Uid::from_raw),
-            dev.gid().map(Gid::from_raw),
-        )?;
+        // Only the permission bits of the file mode are relevant here, the
+        // file type is determined by the device type. Like runc, fall back to
+        // a world read- and writable node if no file mode has been specified.
+        let mode = Mode::from_bits_truncate(dev.file_mode().unwrap_or(DEFAULT_FILE_MODE) & 0o7777);
+        self.syscall
+            .mknod(
+                &full_container_path,
+                to_sflag(dev.typ()),
+                mode,
+                makedev(dev.major(), dev.minor()),
+            )
+            .with_context(|| format!("failed to create device node {:?}", full_container_path))?;
+
+        // The uid and gid are specified from the view of the container. As
+        // devices are created after the user namespace has been entered, the
+        // kernel translates them through the id mappings of the namespace.
+        self.syscall
+            .chown(
+                &full_container_path,
+                dev.uid().map(Uid::from_raw),
+                dev.gid().map(Gid::from_raw),
+            )
+            .with_context(|| {
+                format!(
+                    "failed to change owner of device {:?} to {:?}:{:?}, \
+                    the ids must be mapped in the user namespace",
+                    dev.path(),
+                    dev.uid(),
+                    dev.gid()
+                )
+            })?;
+
+        // mknod is subject to the umask and chown clears the setuid and setgid
+        // bits, so apply the requested mode explicitly at the end.
+        self.syscall
+            .chmod(&full_container_path, mode)
+            .with_context(|| {
+                format!(
+                    "failed to change mode of device {:?} to {:o}",
+                    dev.path(),
+                    mode.bits()
+                )
+            })?;
 
         Ok(())
     }
@@ -120,7 +154,7 @@ fn create_container_dev_path(rootfs: &Path, dev: &LinuxDevice) -> Result<PathBuf
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::syscall::test::{ChownArgs, MknodArgs, MountArgs, TestHelperSyscall};
+    use crate::syscall::test::{ChmodArgs, ChownArgs, MknodArgs, MountArgs, TestHelperSyscall};
     use crate::utils::TempDir;
     use nix::{
         sys::stat::SFlag,
@@ -205,6 +239,58 @@ mod tests {
             .unwrap()
             .get_chown_args()[0];
         assert_eq!(want_chown, *got_chown);
+
+        let want_chmod = ChmodArgs {
+            path: tmp_dir.path().join("null"),
+            mode: Mode::from_bits_truncate(0o644),
+        };
+        let got_chmod = &device
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_chmod_args()[0];
+        assert_eq!(want_chmod, *got_chmod);
+    }
+
+    #[test]
+    fn test_mknod_dev_default_mode() {
+        let tmp_dir = TempDir::new("/tmp/test_mknod_dev_default_mode").unwrap();
+        let device = Device::new();
+        assert!(device
+            .mknod_dev(
+                tmp_dir.path(),
+                &LinuxDeviceBuilder::default()
+                    .path(PathBuf::from("/kvm"))
+                    .major(10)
+                    .minor(232)
+                    .typ(LinuxDeviceType::C)
+                    .build()
+                    .unwrap(),
+            )
+            .is_ok());
+
+        let syscall = device
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        assert_eq!(
+            syscall.get_mknod_args()[0].perm,
+            Mode::from_bits_truncate(0o666)
+        );
+        assert_eq!(
+            syscall.get_chmod_args()[0].mode,
+            Mode::from_bits_truncate(0o666)
+        );
+        assert_eq!(
+            syscall.get_chown_args()[0],
+            ChownArgs {
+                path: tmp_dir.path().join("kvm"),
+                owner: None,
+                group: None,
+            }
+        );
     }
 
     #[test]
@@ -5,6 +5,7 @@
 pub(crate) mod rootfs;
 pub use rootfs::RootFS;
 
+pub(crate) mod copy;
 pub(super) mod device;
 pub(super) mod mount;
 pub(super) mod symlink;
@@ -1,25 +1,41 @@
 use super::{
+    copy::copy_dir_contents,
     symlink::Symlink,
-    utils::{find_parent_mount, parse_mount},
+    utils::{find_parent_mount, is_tmpcopyup, parse_mount, parse_recursive_attr},
 };
 use crate::utils::PathBufExt;
 use crate::{
-    syscall::{syscall::create_syscall, Syscall},
+    syscall::{
+        linux::{
+            MountAttr, AT_EMPTY_PATH, AT_RECURSIVE, MOUNT_ATTR_NOATIME, MOUNT_ATTR_NODEV,
+            MOUNT_ATTR_NODIRATIME, MOUNT_ATTR_NOEXEC, MOUNT_ATTR_NOSUID, MOUNT_ATTR_RDONLY,
+            MOUNT_ATTR_RELATIME, MOUNT_ATTR_STRICTATIME, MOUNT_ATTR__ATIME,
+            MOVE_MOUNT_F_EMPTY_PATH, OPEN_TREE_CLOEXEC, OPEN_TREE_CLONE,
+        },
+        syscall::create_syscall,
+        Syscall,
+    },
     utils,
 };
-use anyhow::{anyhow, bail, Context, Result};
+use anyhow::{anyhow, Context, Result};
 use libcgroups::common::{
     CgroupSetup::{Hybrid, Legacy, Unified},
     DEFAULT_CGROUP_ROOT,
 };
-use nix::{errno::Errno, mount::MsFlags};
+use nix::{
+    errno::Errno,
+    mount::{umount2, MntFlags, MsFlags},
+    unistd::{close, mkdtemp},
+};
 use oci_spec::runtime::{Mount as SpecMount, MountBuilder as SpecMountBuilder};
-use procfs::process::{MountOptFields, Process};
+use procfs::process::Process;
 use std::borrow::Cow;
+use std::os::unix::io::RawFd;
 use std::path::{Path, PathBuf};
 use std::{
     collections::HashMap,
-    fs::{canonicalize, create_dir_all, OpenOptions},
+    env,
+    fs::{self, canonicalize, create_dir_all, OpenOptions},
 };
 
 #[derive(Debug)]
@@ -27,6 +43,8 @@ pub struct MountOptions<'a> {
     pub root: &'a Path,
     pub label: Option<&'a str>,
     pub cgroup_ns: bool,
+    /// Create bind mounts with the new mount API
+    pub mount_api: bool,
 }
 
 pub struct Mount {
@@ -73,6 +91,9 @@ impl Mount {
                         options.label,
                     )
                     .with_context(|| format!("failed to mount /dev: {:?}", mount))?;
+                } else if options.mount_api && is_bind(mount, flags) {
+                    self.mount_bind_detached(mount, options.root, flags)
+                        .with_context(|| format!("failed to mount: {:?}", mount))?;
                 } else {
                     self.mount_into_container(mount, options.root, flags, &data, options.label)
                         .with_context(|| format!("failed to mount: {:?}", mount))?;
@@ -314,14 +335,14 @@ impl Mount {
     /// Make parent mount of rootfs private if it was shared, which is required by pivot_root.
     /// It also makes sure following bind mount does not propagate in other namespaces.
     pub fn make_parent_mount_private(&self, rootfs: &Path) -> Result<()> {
-        let mount_infos = Process::myself()?.mountinfo()?;
+        let mount_infos = libcgroups::mountinfo::mountinfo()?;
         let parent_mount = find_parent_mount(rootfs, &mount_infos)?;
 
         // check parent mount has 'shared' propagation type
         if parent_mount
-            .opt_fields
+            .optional_fields
             .iter()
-            .any(|field| matches!(field, MountOptFields::Shared(_)))
+            .any(|field| field.starts_with("shared:"))
         {
             self.syscall.mount(
                 None,
@@ -359,31 +380,15 @@ impl Mount {
             .with_context(|| format!("failed to join {:?} with {:?}", rootfs, m.destination()))?;
 
         let dest = Path::new(&dest_for_host);
+        // only an existing directory has contents that can be copied up
+        let tmpcopyup = is_tmpcopyup(m) && dest.is_dir();
         let source = m
             .source()
             .as_ref()
             .with_context(|| "no source in mount spec".to_string())?;
-        let src = if typ == Some("bind") {
-            let src = canonicalize(source)
-                .with_context(|| format!("failed to canonicalize: {:?}", source))?;
-            let dir = if src.is_file() {
-                Path::new(&dest).parent().unwrap()
-            } else {
-                Path::new(&dest)
-            };
-
-            create_dir_all(&dir)
-                .with_context(|| format!("failed to create dir for bind mount: {:?}", dir))?;
-
-            if src.is_file() {
-                OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .open(&dest)
-                    .with_context(|| format!("failed to create file for bind mount: {:?}", src))?;
-            }
-
-            src
+        let is_bind = is_bind(m, flags);
+        let src = if is_bind {
+            prepare_bind_target(source, dest)?
         } else {
             create_dir_all(&dest)
                 .with_context(|| format!("Failed to create device: {:?}", dest))?;
@@ -391,19 +396,44 @@ impl Mount {
             PathBuf::from(source)
         };
 
-        if let Err(err) = self.syscall.mount(Some(&*src), dest, typ, flags, Some(&*d)) {
-            if let Some(errno) = err.downcast_ref() {
-                if !matches!(errno, Errno::EINVAL) {
-                    bail!("mount of {:?} failed. {}", m.destination(), errno);
+        // With tmpcopyup, the tmpfs is mounted at a temporary location first, so
+        // that the contents of the destination can be copied into it before it
+        // is moved over the destination.
+        let mount_point = if tmpcopyup {
+            mkdtemp(&env::temp_dir().join("youki-tmpcopyup.XXXXXX"))
+                .context("failed to create temporary directory for tmpcopyup")?
+        } else {
+            dest.to_path_buf()
+        };
+
+        let mounted = match self
+            .syscall
+            .mount(Some(&*src), &mount_point, typ, flags, Some(&*d))
+        {
+            Ok(()) => Ok(()),
+            Err(err) => match err.downcast_ref() {
+                Some(errno) if !matches!(errno, Errno::EINVAL) => {
+                    Err(anyhow!("mount of {:?} failed. {}", m.destination(), errno))
                 }
+                _ => self
+                    .syscall
+                    .mount(Some(&*src), &mount_point, typ, flags, Some(data))
+                    .with_context(|| format!("failed to mount {:?} to {:?}", src, dest)),
+            },
+        };
+        if let Err(err) = mounted {
+            if tmpcopyup {
+                let _ = fs::remove_dir(&mount_point);
             }
+            return Err(err);
+        }
 
-            self.syscall
-                .mount(Some(&*src), dest, typ, flags, Some(data))
-                .with_context(|| format!("failed to mount {:?} to {:?}", src, dest))?;
+        if tmpcopyup {
+            self.copy_up(dest, &mount_point)
+                .with_context(|| format!("failed to copy up {:?}", m.destination()))?;
         }
 
-        if typ == Some("bind")
+        if is_bind
             && flags.intersects(
                 !(MsFlags::MS_REC
                     | MsFlags::MS_REMOUNT
@@ -418,18 +448,216 @@ impl Mount {
                 .with_context(|| format!("Failed to remount: {:?}", dest))?;
         }
 
+        // The remount above only changes the flags of the top mount. Recursive
+        // options like rro also apply to the submounts of a recursive bind mount.
+        if let Some(attr) = parse_recursive_attr(m) {
+            self.syscall
+                .mount_setattr(libc::AT_FDCWD, dest, AT_RECURSIVE, &attr)
+                .with_context(|| {
+                    format!("failed to set recursive mount attributes of {:?}", dest)
+                })?;
+        }
+
+        Ok(())
+    }
+
+    /// Creates a bind mount with the new mount API. The source is cloned into a
+    /// detached mount with open_tree(2), which is configured with
+    /// mount_setattr(2) before move_mount(2) attaches it in the container. Unlike
+    /// the bind mount followed by a remount, the mount never shows up with the
+    /// flags of the source.
+    fn mount_bind_detached(&self, m: &SpecMount, rootfs: &Path, flags: MsFlags) -> Result<()> {
+        let dest = utils::secure_join(rootfs, m.destination())
+            .with_context(|| format!("failed to join {:?} with {:?}", rootfs, m.destination()))?;
+        let source = m.source().as_ref().context("no source in mount spec")?;
+        let src = prepare_bind_target(source, &dest)?;
+
+        let mut open_flags = OPEN_TREE_CLONE | OPEN_TREE_CLOEXEC;
+        if flags.contains(MsFlags::MS_REC) {
+            open_flags |= AT_RECURSIVE;
+        }
+        let tree = self
+            .syscall
+            .open_tree(libc::AT_FDCWD, &src, open_flags)
+            .with_context(|| format!("failed to clone mount tree of {:?}", src))?;
+
+        let result = self.attach_tree(tree, m, &dest, flags);
+        let _ = close(tree);
+        result
+    }
+
+    fn attach_tree(&self, tree: RawFd, m: &SpecMount, dest: &Path, flags: MsFlags) -> Result<()> {
+        let empty = Path::new("");
+        let attr = mount_attr_from_flags(flags);
+        if attr != MountAttr::default() {
+            self.syscall
+                .mount_setattr(tree, empty, AT_EMPTY_PATH, &attr)
+                .context("failed to set mount attributes")?;
+        }
+
+        // As with the remount of a bind mount, recursive options are applied
+        // last and take precedence over the options of the top mount.
+        if let Some(attr) = parse_recursive_attr(m) {
+            self.syscall
+                .mount_setattr(tree, empty, AT_EMPTY_PATH | AT_RECURSIVE, &attr)
+                .context("failed to set recursive mount attributes")?;
+        }
+
+        self.syscall
+            .move_mount(tree, empty, libc::AT_FDCWD, dest, MOVE_MOUNT_F_EMPTY_PATH)
+            .with_context(|| format!("failed to attach mount to {:?}", dest))
+    }
+
+    /// Copies the contents of the directory into the tmpfs mounted at the
+    /// temporary mount point and moves the tmpfs over the directory
+    fn copy_up(&self, dir: &Path, tmp_mount: &Path) -> Result<()> {
+        if let Err(err) = copy_dir_contents(dir, tmp_mount) {
+            let _ = umount2(tmp_mount, MntFlags::MNT_DETACH);
+            let _ = fs::remove_dir(tmp_mount);
+            return Err(err);
+        }
+
+        if let Err(err) = self
+            .syscall
+            .mount(Some(tmp_mount), dir, None, MsFlags::MS_MOVE, None)
+        {
+            let _ = umount2(tmp_mount, MntFlags::MNT_DETACH);
+            let _ = fs::remove_dir(tmp_mount);
+            return Err(err.context(format!("failed to move tmpfs to {:?}", dir)));
+        }
+        if let Err(err) = fs::remove_dir(tmp_mount) {
+            log::warn!("failed to remove {:?}: {}", tmp_mount, err);
+        }
+
         Ok(())
     }
 }
 
+/// A bind mount may also be requested by the options only, e.g. with a type of none
+fn is_bind(m: &SpecMount, flags: MsFlags) -> bool {
+    m.typ().as_deref() == Some("bind") || flags.contains(MsFlags::MS_BIND)
+}
+
+/// Creates the file or directory the source of a bind mount is mounted on and
+/// returns the canonical source
+fn prepare_bind_target(source: &Path, dest: &Path) -> Result<PathBuf> {
+    let src =
+        canonicalize(source).with_context(|| format!("failed to canonicalize: {:?}", source))?;
+    let dir = if src.is_file() {
+        dest.parent().unwrap()
+    } else {
+        dest
+    };
+
+    create_dir_all(&dir)
+        .with_context(|| format!("failed to create dir for bind mount: {:?}", dir))?;
+
+    if src.is_file() {
+        OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(&dest)
+            .with_context(|| format!("failed to create file for bind mount: {:?}", src))?;
+    }
+
+    Ok(src)
+}
+
+/// Maps the flags of a bind mount to the attributes of the top mount. Like a
+/// remount, the access flags that are not requested are cleared.
+fn mount_attr_from_flags(flags: MsFlags) -> MountAttr {
+    let mut attr = MountAttr::default();
+    for (flag, mount_attr) in [
+        (MsFlags::MS_RDONLY, MOUNT_ATTR_RDONLY),
+        (MsFlags::MS_NOSUID, MOUNT_ATTR_NOSUID),
+        (MsFlags::MS_NODEV, MOUNT_ATTR_NODEV),
+        (MsFlags::MS_NOEXEC, MOUNT_ATTR_NOEXEC),
+        (MsFlags::MS_NODIRATIME, MOUNT_ATTR_NODIRATIME),
+    ] {
+        if flags.contains(flag) {
+            attr.attr_set |= mount_attr;
+        } else {
+            attr.attr_clr |= mount_attr;
+        }
+    }
+
+    let atime = if flags.contains(MsFlags::MS_NOATIME) {
+        Some(MOUNT_ATTR_NOATIME)
+    } else if flags.contains(MsFlags::MS_STRICTATIME) {
+        Some(MOUNT_ATTR_STRICTATIME)
+    } else if flags.contains(MsFlags::MS_RELATIME) {
+        Some(MOUNT_ATTR_RELATIME)
+    } else {
+        None
+    };
+    if let Some(atime) = atime {
+        attr.attr_set |= atime;
+        attr.attr_clr |= MOUNT_ATTR__ATIME;
+    }
+
+    for propagation in [
+        MsFlags::MS_PRIVATE,
+        MsFlags::MS_SHARED,
+        MsFlags::MS_SLAVE,
+        MsFlags::MS_UNBINDABLE,
+    ] {
+        if flags.contains(propagation) {
+            attr.propagation = propagation.bits() as u64;
+        }
+    }
+
+    attr
+}
+
 #[cfg(test)]
 mod tests {
-    use std::fs;
 
     use super::*;
-    use crate::syscall::test::{MountArgs, TestHelperSyscall};
+    use crate::syscall::test::{
+        ArgName, MountArgs, MountSetattrArgs, MoveMountArgs, OpenTreeArgs, TestHelperSyscall,
+    };
     use crate::utils::create_temp_dir;
-    use anyhow::Result;
+    use anyhow::{bail, Result};
+
+    #[test]
+    fn test_tmpcopyup_removes_temp_dir_on_failure() -> Result<()> {
+        let tmp_dir = create_temp_dir("test_tmpcopyup_removes_temp_dir_on_failure")?;
+        fs::create_dir_all(tmp_dir.path().join("run"))?;
+        let m = Mount::new();
+        m.syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .set_ret_err(ArgName::Mount, || bail!(nix::errno::Errno::EPERM));
+        let mount = SpecMountBuilder::default()
+            .destination(PathBuf::from("/run"))
+            .typ("tmpfs")
+            .source(PathBuf::from("tmpfs"))
+            .options(vec!["tmpcopyup".to_string()])
+            .build()?;
+        let (flags, data) = parse_mount(&mount);
+
+        let temp_dirs = || -> Result<Vec<PathBuf>> {
+            let mut dirs = Vec::new();
+            for entry in fs::read_dir(env::temp_dir())? {
+                let path = entry?.path();
+                let is_tmpcopyup = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .map_or(false, |name| name.starts_with("youki-tmpcopyup."));
+                if is_tmpcopyup {
+                    dirs.push(path);
+                }
+            }
+            Ok(dirs)
+        };
+        let before = temp_dirs()?;
+        assert!(m
+            .mount_into_container(&mount, tmp_dir.path(), flags, &data, None)
+            .is_err());
+        assert_eq!(temp_dirs()?, before);
+        Ok(())
+    }
 
     #[test]
     fn test_mount_to_container() {
@@ -520,6 +748,147 @@ mod tests {
             assert_eq!(want, *got);
             assert_eq!(got.len(), 2);
         }
+        {
+            let m = Mount::new();
+            fs::create_dir_all(tmp_dir.path().join("data")).unwrap();
+            let mount = &SpecMountBuilder::default()
+                .destination(PathBuf::from("/data"))
+                .typ("none")
+                .source(tmp_dir.path().join("data"))
+                .options(vec![
+                    "rbind".to_string(),
+                    "nosuid".to_string(),
+                    "rro".to_string(),
+                ])
+                .build()
+                .unwrap();
+            let (flags, data) = parse_mount(mount);
+
+            assert!(m
+                .mount_into_container(mount, tmp_dir.path(), flags, &data, None)
+                .is_ok());
+
+            let want = vec![
+                MountArgs {
+                    source: Some(tmp_dir.path().join("data")),
+                    target: tmp_dir.path().join("data"),
+                    fstype: Some("none".to_string()),
+                    flags: MsFlags::MS_BIND | MsFlags::MS_REC | MsFlags::MS_NOSUID,
+                    data: Some("".to_string()),
+                },
+                // remount with the flags of the top mount
+                MountArgs {
+                    source: Some(tmp_dir.path().join("data")),
+                    target: tmp_dir.path().join("data"),
+                    fstype: None,
+                    flags: MsFlags::MS_BIND
+                        | MsFlags::MS_REC
+                        | MsFlags::MS_NOSUID
+                        | MsFlags::MS_REMOUNT,
+                    data: None,
+                },
+            ];
+            let syscall = m
+                .syscall
+                .as_any()
+                .downcast_ref::<TestHelperSyscall>()
+                .unwrap();
+            assert_eq!(want, syscall.get_mount_args());
+
+            let want = vec![MountSetattrArgs {
+                dirfd: libc::AT_FDCWD,
+                path: tmp_dir.path().join("data"),
+                flags: AT_RECURSIVE,
+                mount_attr: MountAttr {
+                    attr_set: MOUNT_ATTR_RDONLY,
+                    ..Default::default()
+                },
+            }];
+            assert_eq!(want, syscall.get_mount_setattr_args());
+        }
+    }
+
+    #[test]
+    fn test_mount_bind_detached() -> Result<()> {
+        let tmp = create_temp_dir("test_mount_bind_detached")?;
+        let source = tmp.path().join("source");
+        fs::create_dir_all(&source)?;
+        let rootfs = tmp.path().join("rootfs");
+        fs::create_dir_all(&rootfs)?;
+
+        let mount = SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(&source)
+            .options(vec![
+                "rbind".to_string(),
+                "ro".to_string(),
+                "rnosuid".to_string(),
+            ])
+            .build()?;
+        let mount_opts = MountOptions {
+            root: &rootfs,
+            label: None,
+            cgroup_ns: false,
+            mount_api: true,
+        };
+
+        let m = Mount::new();
+        m.setup_mount(&mount, &mount_opts)?;
+
+        let syscall = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        assert!(syscall.get_mount_args().is_empty());
+        assert!(rootfs.join("data").is_dir());
+        assert_eq!(
+            syscall.get_open_tree_args(),
+            vec![OpenTreeArgs {
+                dirfd: libc::AT_FDCWD,
+                path: fs::canonicalize(&source)?,
+                flags: OPEN_TREE_CLONE | OPEN_TREE_CLOEXEC | AT_RECURSIVE,
+            }]
+        );
+        assert_eq!(
+            syscall.get_mount_setattr_args(),
+            vec![
+                MountSetattrArgs {
+                    dirfd: -1,
+                    path: PathBuf::new(),
+                    flags: AT_EMPTY_PATH,
+                    mount_attr: MountAttr {
+                        attr_set: MOUNT_ATTR_RDONLY,
+                        attr_clr: MOUNT_ATTR_NOSUID
+                            | MOUNT_ATTR_NODEV
+                            | MOUNT_ATTR_NOEXEC
+                            | MOUNT_ATTR_NODIRATIME,
+                        ..Default::default()
+                    },
+                },
+                MountSetattrArgs {
+                    dirfd: -1,
+                    path: PathBuf::new(),
+                    flags: AT_EMPTY_PATH | AT_RECURSIVE,
+                    mount_attr: MountAttr {
+                        attr_set: MOUNT_ATTR_NOSUID,
+                        ..Default::default()
+                    },
+                },
+            ]
+        );
+        assert_eq!(
+            syscall.get_move_mount_args(),
+            vec![MoveMountArgs {
+                from_dirfd: -1,
+                from_path: PathBuf::new(),
+                to_dirfd: libc::AT_FDCWD,
+                to_path: rootfs.join("data"),
+                flags: MOVE_MOUNT_F_EMPTY_PATH,
+            }]
+        );
+        Ok(())
     }
 
     #[test]
@@ -566,6 +935,7 @@ mod tests {
             root: tmp.path(),
             label: None,
             cgroup_ns: true,
+            mount_api: false,
         };
 
         let subsystem_name = "cpu";
@@ -617,6 +987,7 @@ mod tests {
             root: tmp.path(),
             label: None,
             cgroup_ns: false,
+            mount_api: false,
         };
 
         let subsystem_name = "cpu";
@@ -674,6 +1045,7 @@ mod tests {
             root: tmp.path(),
             label: None,
             cgroup_ns: true,
+            mount_api: false,
         };
 
         let mounter = Mount::new();
@@ -743,6 +1115,7 @@ mod tests {
             root: tmp.path(),
             label: None,
             cgroup_ns: true,
+            mount_api: false,
         };
 
         let mounter = Mount::new();
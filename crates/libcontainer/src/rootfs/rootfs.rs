@@ -2,8 +2,9 @@ use super::{
     device::Device,
     mount::{Mount, MountOptions},
     symlink::Symlink,
-    utils::default_devices,
+    utils::{default_devices, is_host_dev_bind},
 };
+use crate::experimental::{ExperimentalFeatures, Feature};
 use crate::syscall::{syscall::create_syscall, Syscall};
 use anyhow::{bail, Context, Result};
 use nix::mount::MsFlags;
@@ -34,6 +35,7 @@ impl RootFS {
         rootfs: &Path,
         bind_devices: bool,
         cgroup_ns: bool,
+        experimental: &ExperimentalFeatures,
     ) -> Result<()> {
         log::debug!("Prepare rootfs: {:?}", rootfs);
         let mut flags = MsFlags::MS_REC;
@@ -69,6 +71,7 @@ impl RootFS {
             root: rootfs,
             label: linux.mount_label().as_deref(),
             cgroup_ns,
+            mount_api: experimental.is_enabled(Feature::MountApi),
         };
 
         if let Some(mounts) = spec.mounts() {
@@ -83,6 +86,28 @@ impl RootFS {
         symlinker
             .setup_kcore_symlink(rootfs)
             .context("failed to  setup kcore symlink")?;
+
+        // If the host /dev has been bound into the container, the nodes and
+        // symlinks already exist. Creating or removing anything here would
+        // modify the /dev of the host.
+        let host_dev = spec
+            .mounts()
+            .as_ref()
+            .map(|mounts| mounts.iter().any(is_host_dev_bind))
+            .unwrap_or(false);
+        if host_dev {
+            log::debug!("host /dev is bound into the container, skip device creation");
+            if let Some(added_devices) = linux.devices() {
+                for dev in added_devices {
+                    if !rootfs.join(dev.path().strip_prefix("/")?).exists() {
+                        log::warn!("device {:?} does not exist in the host /dev", dev.path());
+                    }
+                }
+            }
+
+            return Ok(());
+        }
+
         symlinker
             .setup_default_symlinks(rootfs)
             .context("failed to setup default symlinks")?;
@@ -1,7 +1,12 @@
+use crate::syscall::linux::{
+    MountAttr, MOUNT_ATTR_NOATIME, MOUNT_ATTR_NODEV, MOUNT_ATTR_NODIRATIME, MOUNT_ATTR_NOEXEC,
+    MOUNT_ATTR_NOSUID, MOUNT_ATTR_NOSYMFOLLOW, MOUNT_ATTR_RDONLY, MOUNT_ATTR_RELATIME,
+    MOUNT_ATTR_STRICTATIME, MOUNT_ATTR__ATIME,
+};
 use anyhow::{anyhow, Result};
+use libcgroups::mountinfo::MountInfo;
 use nix::{mount::MsFlags, sys::stat::SFlag, NixPath};
 use oci_spec::runtime::{LinuxDevice, LinuxDeviceBuilder, LinuxDeviceType, Mount};
-use procfs::process::MountInfo;
 use std::path::{Path, PathBuf};
 
 pub fn default_devices() -> Vec<LinuxDevice> {
@@ -66,6 +71,73 @@ pub fn to_sflag(dev_type: LinuxDeviceType) -> SFlag {
     }
 }
 
+/// Mount option that copies the contents of the mount point into a tmpfs
+pub const TMPCOPYUP: &str = "tmpcopyup";
+
+/// Checks if the contents of the mount point should be copied into the tmpfs
+pub fn is_tmpcopyup(m: &Mount) -> bool {
+    m.typ().as_deref() == Some("tmpfs")
+        && m.options()
+            .as_ref()
+            .map(|options| options.iter().any(|o| o == TMPCOPYUP))
+            .unwrap_or_default()
+}
+
+/// Maps a recursive mount option to the mount_setattr(2) attribute it sets or
+/// clears. Unlike the non-recursive options, these also apply to submounts of
+/// recursive bind mounts.
+fn recursive_attr(option: &str) -> Option<(bool, u64)> {
+    match option {
+        "rro" => Some((false, MOUNT_ATTR_RDONLY)),
+        "rrw" => Some((true, MOUNT_ATTR_RDONLY)),
+        "rnosuid" => Some((false, MOUNT_ATTR_NOSUID)),
+        "rsuid" => Some((true, MOUNT_ATTR_NOSUID)),
+        "rnodev" => Some((false, MOUNT_ATTR_NODEV)),
+        "rdev" => Some((true, MOUNT_ATTR_NODEV)),
+        "rnoexec" => Some((false, MOUNT_ATTR_NOEXEC)),
+        "rexec" => Some((true, MOUNT_ATTR_NOEXEC)),
+        "rnodiratime" => Some((false, MOUNT_ATTR_NODIRATIME)),
+        "rdiratime" => Some((true, MOUNT_ATTR_NODIRATIME)),
+        // MOUNT_ATTR_RELATIME is 0, so rrelatime only takes effect through
+        // clearing the previous atime attribute, and rnorelatime has no effect
+        // at all, as in runc
+        "rrelatime" => Some((false, MOUNT_ATTR_RELATIME)),
+        "rnorelatime" => Some((true, MOUNT_ATTR_RELATIME)),
+        "rnoatime" => Some((false, MOUNT_ATTR_NOATIME)),
+        "ratime" => Some((true, MOUNT_ATTR_NOATIME)),
+        "rstrictatime" => Some((false, MOUNT_ATTR_STRICTATIME)),
+        "rnostrictatime" => Some((true, MOUNT_ATTR_STRICTATIME)),
+        "rnosymfollow" => Some((false, MOUNT_ATTR_NOSYMFOLLOW)),
+        "rsymfollow" => Some((true, MOUNT_ATTR_NOSYMFOLLOW)),
+        _ => None,
+    }
+}
+
+/// Collects the recursive mount options of the mount, which have to be applied
+/// with mount_setattr(2) after the mount has been created
+pub fn parse_recursive_attr(m: &Mount) -> Option<MountAttr> {
+    let mut attr = MountAttr::default();
+    let mut found = false;
+    for option in m.options().iter().flatten() {
+        if let Some((is_clear, flag)) = recursive_attr(option) {
+            found = true;
+            if is_clear {
+                attr.attr_clr |= flag;
+                attr.attr_set &= !flag;
+            } else if flag & MOUNT_ATTR__ATIME == flag {
+                // the atime attributes are an enum, the previous one has to be cleared
+                attr.attr_set = (attr.attr_set & !MOUNT_ATTR__ATIME) | flag;
+                attr.attr_clr |= MOUNT_ATTR__ATIME;
+            } else {
+                attr.attr_set |= flag;
+                attr.attr_clr &= !flag;
+            }
+        }
+    }
+
+    found.then(|| attr)
+}
+
 pub fn parse_mount(m: &Mount) -> (MsFlags, String) {
     let mut flags = MsFlags::empty();
     let mut data = Vec::new();
@@ -105,6 +177,9 @@ pub fn parse_mount(m: &Mount) -> (MsFlags, String) {
                 "norelatime" => Some((true, MsFlags::MS_RELATIME)),
                 "strictatime" => Some((true, MsFlags::MS_STRICTATIME)),
                 "nostrictatime" => Some((true, MsFlags::MS_STRICTATIME)),
+                // handled by the runtime, they must not be passed to the kernel
+                TMPCOPYUP => Some((false, MsFlags::empty())),
+                o if recursive_attr(o).is_some() => Some((false, MsFlags::empty())),
                 _ => None,
             } {
                 if is_clear {
@@ -120,6 +195,20 @@ pub fn parse_mount(m: &Mount) -> (MsFlags, String) {
     (flags, data.join(","))
 }
 
+/// Checks if the mount binds the host /dev into the container. In this case
+/// the container shares the device nodes of the host and they must not be
+/// created or modified by the runtime. Individual nodes can still be hidden
+/// from the container with `linux.maskedPaths`.
+pub fn is_host_dev_bind(m: &Mount) -> bool {
+    if m.destination() != Path::new("/dev") {
+        return false;
+    }
+
+    let (flags, _) = parse_mount(m);
+    let is_bind = m.typ().as_deref() == Some("bind") || flags.contains(MsFlags::MS_BIND);
+    is_bind && m.source().as_deref() == Some(Path::new("/dev"))
+}
+
 /// Find parent mount of rootfs in given mount infos
 pub fn find_parent_mount<'a>(rootfs: &Path, mount_infos: &'a [MountInfo]) -> Result<&'a MountInfo> {
     // find the longest mount point
@@ -141,34 +230,36 @@ mod tests {
     fn test_find_parent_mount() -> anyhow::Result<()> {
         let mount_infos = vec![
             MountInfo {
-                mnt_id: 11,
-                pid: 10,
-                majmin: "".to_string(),
-                root: "/".to_string(),
+                mount_id: 11,
+                parent_id: 10,
+                major: 0,
+                minor: 0,
+                root: PathBuf::from("/"),
                 mount_point: PathBuf::from("/"),
-                mount_options: Default::default(),
-                opt_fields: vec![],
+                mount_options: vec![],
+                optional_fields: vec![],
                 fs_type: "ext4".to_string(),
                 mount_source: Some("/dev/sda1".to_string()),
-                super_options: Default::default(),
+                super_options: vec![],
             },
             MountInfo {
-                mnt_id: 12,
-                pid: 11,
-                majmin: "".to_string(),
-                root: "/".to_string(),
+                mount_id: 12,
+                parent_id: 11,
+                major: 0,
+                minor: 0,
+                root: PathBuf::from("/"),
                 mount_point: PathBuf::from("/proc"),
-                mount_options: Default::default(),
-                opt_fields: vec![],
+                mount_options: vec![],
+                optional_fields: vec![],
                 fs_type: "proc".to_string(),
                 mount_source: Some("proc".to_string()),
-                super_options: Default::default(),
+                super_options: vec![],
             },
         ];
 
         let res = find_parent_mount(Path::new("/path/to/rootfs"), &mount_infos)
             .context("Failed to get parent mount")?;
-        assert_eq!(res.mnt_id, 11);
+        assert_eq!(res.mount_id, 11);
         Ok(())
     }
 
@@ -191,6 +282,156 @@ mod tests {
         assert_eq!(SFlag::S_IFIFO, to_sflag(LinuxDeviceType::P));
     }
 
+    #[test]
+    fn test_is_host_dev_bind() {
+        let host_dev = MountBuilder::default()
+            .destination(PathBuf::from("/dev"))
+            .typ("bind")
+            .source(PathBuf::from("/dev"))
+            .options(vec!["rbind".to_string(), "nosuid".to_string()])
+            .build()
+            .unwrap();
+        assert!(is_host_dev_bind(&host_dev));
+
+        let option_driven = MountBuilder::default()
+            .destination(PathBuf::from("/dev"))
+            .typ("none")
+            .source(PathBuf::from("/dev"))
+            .options(vec!["rbind".to_string()])
+            .build()
+            .unwrap();
+        assert!(is_host_dev_bind(&option_driven));
+
+        let tmpfs = MountBuilder::default()
+            .destination(PathBuf::from("/dev"))
+            .typ("tmpfs")
+            .source(PathBuf::from("tmpfs"))
+            .build()
+            .unwrap();
+        assert!(!is_host_dev_bind(&tmpfs));
+
+        let other_source = MountBuilder::default()
+            .destination(PathBuf::from("/dev"))
+            .typ("bind")
+            .source(PathBuf::from("/tmp/dev"))
+            .build()
+            .unwrap();
+        assert!(!is_host_dev_bind(&other_source));
+
+        let sub_dir = MountBuilder::default()
+            .destination(PathBuf::from("/dev/shm"))
+            .typ("bind")
+            .source(PathBuf::from("/dev"))
+            .build()
+            .unwrap();
+        assert!(!is_host_dev_bind(&sub_dir));
+    }
+
+    #[test]
+    fn test_tmpcopyup() {
+        let tmpfs = MountBuilder::default()
+            .destination(PathBuf::from("/run"))
+            .typ("tmpfs")
+            .source(PathBuf::from("tmpfs"))
+            .options(vec![
+                "nosuid".to_string(),
+                "tmpcopyup".to_string(),
+                "mode=755".to_string(),
+            ])
+            .build()
+            .unwrap();
+        assert!(is_tmpcopyup(&tmpfs));
+        assert_eq!(
+            parse_mount(&tmpfs),
+            (MsFlags::MS_NOSUID, "mode=755".to_string())
+        );
+
+        let bind = MountBuilder::default()
+            .destination(PathBuf::from("/run"))
+            .typ("bind")
+            .source(PathBuf::from("/run"))
+            .options(vec!["rbind".to_string(), "tmpcopyup".to_string()])
+            .build()
+            .unwrap();
+        assert!(!is_tmpcopyup(&bind));
+    }
+
+    #[test]
+    fn test_parse_recursive_attr() {
+        let rbind = MountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(PathBuf::from("/srv/data"))
+            .options(vec![
+                "rbind".to_string(),
+                "rro".to_string(),
+                "rnosuid".to_string(),
+                "rnoatime".to_string(),
+            ])
+            .build()
+            .unwrap();
+        assert_eq!(
+            parse_recursive_attr(&rbind),
+            Some(MountAttr {
+                attr_set: MOUNT_ATTR_RDONLY | MOUNT_ATTR_NOSUID | MOUNT_ATTR_NOATIME,
+                attr_clr: MOUNT_ATTR__ATIME,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse_mount(&rbind),
+            (MsFlags::MS_BIND | MsFlags::MS_REC, "".to_string())
+        );
+
+        let writable = MountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(PathBuf::from("/srv/data"))
+            .options(vec![
+                "rbind".to_string(),
+                "rro".to_string(),
+                "rrw".to_string(),
+            ])
+            .build()
+            .unwrap();
+        assert_eq!(
+            parse_recursive_attr(&writable),
+            Some(MountAttr {
+                attr_clr: MOUNT_ATTR_RDONLY,
+                ..Default::default()
+            })
+        );
+
+        let plain = MountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(PathBuf::from("/srv/data"))
+            .options(vec!["rbind".to_string(), "ro".to_string()])
+            .build()
+            .unwrap();
+        assert_eq!(parse_recursive_attr(&plain), None);
+
+        let relatime = MountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(PathBuf::from("/srv/data"))
+            .options(vec![
+                "rbind".to_string(),
+                "rnoatime".to_string(),
+                "rrelatime".to_string(),
+                "rnorelatime".to_string(),
+            ])
+            .build()
+            .unwrap();
+        assert_eq!(
+            parse_recursive_attr(&relatime),
+            Some(MountAttr {
+                attr_clr: MOUNT_ATTR__ATIME,
+                ..Default::default()
+            })
+        );
+    }
+
     #[test]
     fn test_parse_mount() {
         assert_eq!(
@@ -1,3 +1,5 @@
+pub mod notify;
+
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
@@ -8,8 +10,24 @@ use oci_spec::runtime::Arch;
 use oci_spec::runtime::LinuxSeccomp;
 use oci_spec::runtime::LinuxSeccompAction;
 use oci_spec::runtime::LinuxSeccompOperator;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
 use std::ffi::CString;
-use std::os::unix::io;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::os::unix::io::{self, AsRawFd};
+use std::path::Path;
+
+/// Annotation that loads the seccomp profile of a container in audit mode.
+/// All actions other than SCMP_ACT_ALLOW are replaced by SCMP_ACT_LOG, so the
+/// workload is not restricted, but every syscall that the profile would have
+/// denied is logged by the kernel. This allows to profile which syscalls a
+/// workload needs before a restrictive profile is enforced.
+pub const AUDIT_ANNOTATION: &str = "org.youki.seccomp.audit";
+
+const ACTIONS_LOGGED: &str = "/proc/sys/kernel/seccomp/actions_logged";
 
 #[derive(Debug)]
 struct Compare {
@@ -142,6 +160,16 @@ impl FilterContext {
         Ok(())
     }
 
+    /// Writes the filter compiled to BPF to the file descriptor
+    pub fn export_bpf(&self, fd: io::RawFd) -> Result<()> {
+        let res = unsafe { seccomp_export_bpf(self.ctx, fd) };
+        if res != 0 {
+            bail!("Failed to export seccomp profile: {}", res);
+        }
+
+        Ok(())
+    }
+
     pub fn notify_fd(&self) -> Result<Option<i32>> {
         let res = unsafe { seccomp_notify_fd(self.ctx) };
         if res > 0 {
@@ -261,7 +289,65 @@ fn check_seccomp(seccomp: &LinuxSeccomp) -> Result<()> {
     Ok(())
 }
 
-pub fn initialize_seccomp(seccomp: &LinuxSeccomp) -> Result<Option<io::RawFd>> {
+/// Returns true if the annotations of the spec request the seccomp profile to
+/// be loaded in audit mode. Values other than true and false are rejected.
+pub fn is_audit(annotations: Option<&HashMap<String, String>>) -> Result<bool> {
+    match annotations.and_then(|annotations| annotations.get(AUDIT_ANNOTATION)) {
+        None => Ok(false),
+        Some(value) => value.parse().with_context(|| {
+            format!(
+                "invalid value {:?} for {}, expected true or false",
+                value, AUDIT_ANNOTATION
+            )
+        }),
+    }
+}
+
+// In audit mode every action that would restrict the syscall is only logged
+fn audit_action(action: u32, audit: bool) -> u32 {
+    if audit && action != SCMP_ACT_ALLOW {
+        SCMP_ACT_LOG
+    } else {
+        action
+    }
+}
+
+// The kernel only logs SCMP_ACT_LOG actions if "log" is part of the actions
+// that are logged, which is the default.
+fn check_actions_logged() {
+    match fs::read_to_string(ACTIONS_LOGGED) {
+        Ok(actions) if !actions.split_whitespace().any(|action| action == "log") => {
+            log::warn!(
+                "seccomp audit mode is enabled, but {} does not contain log, so no records will be logged",
+                ACTIONS_LOGGED
+            );
+        }
+        Ok(_) => {}
+        Err(err) => log::debug!("failed to read {}: {}", ACTIONS_LOGGED, err),
+    }
+}
+
+pub fn initialize_seccomp(seccomp: &LinuxSeccomp, audit: bool) -> Result<Option<io::RawFd>> {
+    prepare_seccomp(seccomp, audit)?;
+    let ctx = build_filter(seccomp, audit)?;
+
+    // In order to use the SECCOMP_SET_MODE_FILTER operation, either the calling
+    // thread must have the CAP_SYS_ADMIN capability in its user namespace, or
+    // the thread must already have the no_new_privs bit set.
+    // Ref: https://man7.org/linux/man-pages/man2/seccomp.2.html
+    ctx.load().context("failed to load seccomp context")?;
+
+    // notify actions are only logged in audit mode, so there is no notify fd
+    let fd = if is_notify(seccomp) && !audit {
+        ctx.notify_fd().context("failed to get seccomp notify fd")?
+    } else {
+        None
+    };
+
+    Ok(fd)
+}
+
+fn prepare_seccomp(seccomp: &LinuxSeccomp, audit: bool) -> Result<()> {
     if seccomp.flags().is_some() {
         // runc did not support this, so let's skip it for now.
         bail!("seccomp flags are not yet supported");
@@ -269,7 +355,19 @@ pub fn initialize_seccomp(seccomp: &LinuxSeccomp) -> Result<Option<io::RawFd>> {
 
     check_seccomp(seccomp)?;
 
-    let default_action = translate_action(seccomp.default_action(), seccomp.default_errno_ret());
+    if audit {
+        log::info!("seccomp profile is loaded in audit mode, denied syscalls are only logged");
+        check_actions_logged();
+    }
+
+    Ok(())
+}
+
+fn build_filter(seccomp: &LinuxSeccomp, audit: bool) -> Result<FilterContext> {
+    let default_action = audit_action(
+        translate_action(seccomp.default_action(), seccomp.default_errno_ret()),
+        audit,
+    );
     let mut ctx = FilterContext::default(default_action)?;
 
     if let Some(architectures) = seccomp.architectures() {
@@ -297,7 +395,10 @@ pub fn initialize_seccomp(seccomp: &LinuxSeccomp) -> Result<Option<io::RawFd>> {
 
     if let Some(syscalls) = seccomp.syscalls() {
         for syscall in syscalls {
-            let action = translate_action(syscall.action(), syscall.errno_ret());
+            let action = audit_action(
+                translate_action(syscall.action(), syscall.errno_ret()),
+                audit,
+            );
             if action == default_action {
                 // When the action is the same as the default action, the rule is redundent. We can
                 // skip this here to avoid failing when we add the rules.
@@ -357,19 +458,241 @@ pub fn initialize_seccomp(seccomp: &LinuxSeccomp) -> Result<Option<io::RawFd>> {
         }
     }
 
-    // In order to use the SECCOMP_SET_MODE_FILTER operation, either the calling
-    // thread must have the CAP_SYS_ADMIN capability in its user namespace, or
-    // the thread must already have the no_new_privs bit set.
-    // Ref: https://man7.org/linux/man-pages/man2/seccomp.2.html
-    ctx.load().context("failed to load seccomp context")?;
+    Ok(ctx)
+}
 
-    let fd = if is_notify(seccomp) {
-        ctx.notify_fd().context("failed to get seccomp notify fd")?
-    } else {
-        None
+/// Filter compiled to BPF, which can be loaded without libseccomp
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BpfProgram {
+    instructions: Vec<SockFilter>,
+}
+
+/// An instruction of a BPF program, see struct sock_filter in linux/filter.h
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SockFilter {
+    code: u16,
+    jt: u8,
+    jf: u8,
+    k: u32,
+}
+
+/// See struct sock_fprog in linux/filter.h
+#[repr(C)]
+struct SockFprog {
+    len: libc::c_ushort,
+    filter: *const SockFilter,
+}
+
+const SECCOMP_SET_MODE_FILTER: libc::c_uint = 1;
+/// Maximum number of instructions of a program, see linux/bpf_common.h
+const BPF_MAXINSNS: usize = 4096;
+/// Class of the return instructions, see linux/bpf_common.h
+const BPF_RET: u16 = 0x06;
+
+impl BpfProgram {
+    fn parse(bytes: &[u8]) -> Result<Self> {
+        let size = std::mem::size_of::<SockFilter>();
+        if bytes.is_empty() || bytes.len() % size != 0 || bytes.len() / size > BPF_MAXINSNS {
+            bail!("invalid bpf program of {} bytes", bytes.len());
+        }
+
+        let instructions: Vec<SockFilter> = bytes
+            .chunks_exact(size)
+            .map(|i| SockFilter {
+                code: u16::from_ne_bytes([i[0], i[1]]),
+                jt: i[2],
+                jf: i[3],
+                k: u32::from_ne_bytes([i[4], i[5], i[6], i[7]]),
+            })
+            .collect();
+        // a program that does not end with a return is truncated
+        if instructions.last().map(|i| i.code & 0x07) != Some(BPF_RET) {
+            bail!("bpf program does not end with a return");
+        }
+        Ok(Self { instructions })
+    }
+
+    /// Loads the program as seccomp filter of the calling thread, the same
+    /// way libseccomp loads the filter it has been compiled from
+    pub fn load(&self) -> Result<()> {
+        let prog = SockFprog {
+            len: self
+                .instructions
+                .len()
+                .try_into()
+                .context("bpf program is too long")?,
+            filter: self.instructions.as_ptr(),
+        };
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_seccomp,
+                SECCOMP_SET_MODE_FILTER,
+                0,
+                &prog as *const SockFprog,
+            )
+        };
+        Errno::result(res).context("failed to load seccomp program")?;
+        Ok(())
+    }
+}
+
+/// Compiles the seccomp profile to a BPF program, which is cached in the
+/// directory, as compiling a large profile takes a noticeable part of the
+/// start of a container. The cache is keyed by the profile, the audit mode,
+/// the architecture and the versions of youki and libseccomp, which resolves
+/// the syscall names. A cached program is only used if the cache directory
+/// and the file are owned by the effective user and not writable by others,
+/// and if its key and checksum match, otherwise the profile is compiled
+/// again. Returns None for profiles with notify actions, whose notify fd is
+/// only available when libseccomp loads the filter itself.
+pub fn compile_cached(
+    seccomp: &LinuxSeccomp,
+    audit: bool,
+    cache_dir: &Path,
+) -> Result<Option<BpfProgram>> {
+    if is_notify(seccomp) && !audit {
+        return Ok(None);
+    }
+    prepare_seccomp(seccomp, audit)?;
+
+    let key = cache_key(seccomp, audit)?;
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    let path = cache_dir.join(format!("{:016x}.bpf", hasher.finish()));
+    match read_cached_program(&path, &key) {
+        Ok(Some(program)) => {
+            log::debug!("using cached seccomp program {}", path.display());
+            return Ok(Some(program));
+        }
+        Ok(None) => {}
+        Err(err) => log::warn!(
+            "ignoring invalid cached seccomp program {}: {:?}",
+            path.display(),
+            err
+        ),
+    }
+
+    let ctx = build_filter(seccomp, audit)?;
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(cache_dir)
+        .with_context(|| format!("failed to create {}", cache_dir.display()))?;
+    // the program is written to a temporary file first, so that concurrent
+    // starts never read a partially written program
+    let tmp_path = path.with_extension(format!("bpf.{}", std::process::id()));
+    let result = write_cached_program(&ctx, &key, &tmp_path, &path);
+    unsafe { seccomp_release(ctx.ctx) };
+    let content = match result {
+        Ok(content) => content,
+        Err(err) => {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(err)
+                .with_context(|| format!("failed to cache seccomp program {}", path.display()));
+        }
     };
 
-    Ok(fd)
+    let program =
+        parse_cached_program(&content, &key)?.context("cached program has another key")?;
+    log::debug!("cached seccomp program {}", path.display());
+    Ok(Some(program))
+}
+
+/// Size of the checksum that follows the program in the cache
+const CHECKSUM_SIZE: usize = std::mem::size_of::<u64>();
+
+fn checksum(bytes: &[u8]) -> [u8; CHECKSUM_SIZE] {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish().to_ne_bytes()
+}
+
+fn write_cached_program(
+    ctx: &FilterContext,
+    key: &str,
+    tmp_path: &Path,
+    path: &Path,
+) -> Result<Vec<u8>> {
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(tmp_path)?;
+    file.write_all(key.as_bytes())?;
+    file.write_all(&[0])?;
+    ctx.export_bpf(file.as_raw_fd())?;
+    let mut content = fs::read(tmp_path)?;
+    let sum = checksum(&content);
+    file.write_all(&sum)?;
+    content.extend_from_slice(&sum);
+    fs::rename(tmp_path, path)?;
+    Ok(content)
+}
+
+fn cache_key(seccomp: &LinuxSeccomp, audit: bool) -> Result<String> {
+    let version = unsafe { *seccomp_version() };
+    Ok(format!(
+        "youki {} libseccomp {}.{}.{} {} audit={} {}",
+        env!("CARGO_PKG_VERSION"),
+        version.major,
+        version.minor,
+        version.micro,
+        std::env::consts::ARCH,
+        audit,
+        serde_json::to_string(seccomp).context("failed to serialize seccomp profile")?
+    ))
+}
+
+/// Reads a cached program. Returns None if there is no program for the key.
+fn read_cached_program(path: &Path, key: &str) -> Result<Option<BpfProgram>> {
+    let mut file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    // whoever can write the cache could install any filter in a container
+    check_cache_owner(path.parent().context("cache file has no directory")?)?;
+    check_cache_owner(path)?;
+
+    let mut content = Vec::new();
+    file.read_to_end(&mut content)?;
+    parse_cached_program(&content, key)
+}
+
+fn check_cache_owner(path: &Path) -> Result<()> {
+    let metadata = fs::metadata(path)?;
+    if metadata.uid() != nix::unistd::geteuid().as_raw() || metadata.mode() & 0o022 != 0 {
+        bail!(
+            "{} is not owned by the effective user or writable by others",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Parses a cached program, which is preceded by its key and a nul byte and
+/// followed by the checksum of both. Returns None if the program has been
+/// cached for another key.
+fn parse_cached_program(content: &[u8], key: &str) -> Result<Option<BpfProgram>> {
+    if content.len() < CHECKSUM_SIZE {
+        bail!("cached program is truncated");
+    }
+    let (content, sum) = content.split_at(content.len() - CHECKSUM_SIZE);
+    if sum != checksum(content) {
+        bail!("checksum of the cached program does not match");
+    }
+
+    let separator = content
+        .iter()
+        .position(|&b| b == 0)
+        .context("missing key")?;
+    // the hash of another profile may collide
+    if &content[..separator] != key.as_bytes() {
+        return Ok(None);
+    }
+
+    BpfProgram::parse(&content[separator + 1..]).map(Some)
 }
 
 pub fn is_notify(seccomp: &LinuxSeccomp) -> bool {
@@ -383,11 +706,12 @@ pub fn is_notify(seccomp: &LinuxSeccomp) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::utils::test_utils;
+    use crate::utils::{create_temp_dir, test_utils};
     use anyhow::Result;
     use oci_spec::runtime::Arch;
     use oci_spec::runtime::{LinuxSeccompBuilder, LinuxSyscallBuilder};
     use serial_test::serial;
+    use std::os::unix::fs::PermissionsExt;
     use std::path;
 
     #[test]
@@ -418,7 +742,7 @@ mod tests {
 
         test_utils::test_in_child_process(|| {
             let _ = prctl::set_no_new_privileges(true);
-            initialize_seccomp(&seccomp_profile)?;
+            initialize_seccomp(&seccomp_profile, false)?;
             let ret = nix::unistd::getcwd();
             if ret.is_ok() {
                 bail!("getcwd didn't error out as seccomp profile specified");
@@ -438,6 +762,90 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    #[serial]
+    fn test_compile_cached() -> Result<()> {
+        let tmp = create_temp_dir("test_compile_cached")?;
+        let expect_error = libc::EAGAIN;
+        let syscall = LinuxSyscallBuilder::default()
+            .names(vec![String::from("getcwd")])
+            .action(LinuxSeccompAction::ScmpActErrno)
+            .errno_ret(expect_error as u32)
+            .build()?;
+        let seccomp_profile = LinuxSeccompBuilder::default()
+            .default_action(LinuxSeccompAction::ScmpActAllow)
+            .architectures(vec![Arch::ScmpArchNative])
+            .syscalls(vec![syscall])
+            .build()?;
+
+        let program = compile_cached(&seccomp_profile, false, tmp.path())?
+            .context("no program for profile")?;
+        assert_eq!(fs::read_dir(tmp.path())?.count(), 1);
+        let cached = compile_cached(&seccomp_profile, false, tmp.path())?;
+        assert_eq!(cached.as_ref(), Some(&program));
+        // the audit mode compiles to another program
+        compile_cached(&seccomp_profile, true, tmp.path())?;
+        assert_eq!(fs::read_dir(tmp.path())?.count(), 2);
+
+        test_utils::test_in_child_process(|| {
+            let _ = prctl::set_no_new_privileges(true);
+            program.load()?;
+            match nix::unistd::getcwd() {
+                Err(errno) if errno == nix::errno::from_i32(expect_error) => Ok(()),
+                ret => bail!(
+                    "getcwd returned {:?} instead of the error of the profile",
+                    ret
+                ),
+            }
+        })?;
+
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_compile_cached_rejects_invalid_cache() -> Result<()> {
+        let tmp = create_temp_dir("test_compile_cached_rejects_invalid_cache")?;
+        let seccomp_profile = LinuxSeccompBuilder::default()
+            .default_action(LinuxSeccompAction::ScmpActAllow)
+            .architectures(vec![Arch::ScmpArchNative])
+            .syscalls(vec![LinuxSyscallBuilder::default()
+                .names(vec![String::from("getcwd")])
+                .action(LinuxSeccompAction::ScmpActErrno)
+                .build()?])
+            .build()?;
+        let program = compile_cached(&seccomp_profile, false, tmp.path())?
+            .context("no program for profile")?;
+        let path = fs::read_dir(tmp.path())?
+            .next()
+            .context("no cached program")??
+            .path();
+        let key = cache_key(&seccomp_profile, false)?;
+        let content = fs::read(&path)?;
+        assert_eq!(parse_cached_program(&content, &key)?, Some(program.clone()));
+
+        // a modified program does not match its checksum
+        let mut tampered = content.clone();
+        tampered[key.len() + 1] ^= 0xff;
+        assert!(parse_cached_program(&tampered, &key).is_err());
+        // a truncated program is rejected
+        assert!(parse_cached_program(&content[..content.len() - 1], &key).is_err());
+        // a program of another profile is not used
+        assert_eq!(parse_cached_program(&content, "other")?, None);
+
+        // a cache that others can write is not trusted and is replaced
+        fs::write(&path, b"invalid")?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o666))?;
+        assert!(read_cached_program(&path, &key).is_err());
+        assert_eq!(
+            compile_cached(&seccomp_profile, false, tmp.path())?,
+            Some(program)
+        );
+        assert_eq!(fs::metadata(&path)?.mode() & 0o777, 0o600);
+
+        Ok(())
+    }
+
     #[test]
     #[serial]
     fn test_moby() -> Result<()> {
@@ -450,7 +858,7 @@ mod tests {
         let seccomp_profile = spec.linux().as_ref().unwrap().seccomp().as_ref().unwrap();
         test_utils::test_in_child_process(|| {
             let _ = prctl::set_no_new_privileges(true);
-            initialize_seccomp(seccomp_profile)?;
+            initialize_seccomp(seccomp_profile, false)?;
 
             Ok(())
         })?;
@@ -472,7 +880,7 @@ mod tests {
             .build()?;
         test_utils::test_in_child_process(|| {
             let _ = prctl::set_no_new_privileges(true);
-            let fd = initialize_seccomp(&seccomp_profile)?;
+            let fd = initialize_seccomp(&seccomp_profile, false)?;
             if fd.is_none() {
                 bail!("failed to get a seccomp notify fd with notify seccomp profile");
             }
@@ -482,4 +890,50 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_is_audit() {
+        assert!(!is_audit(None).unwrap());
+        let mut annotations = HashMap::new();
+        annotations.insert(AUDIT_ANNOTATION.to_owned(), "false".to_owned());
+        assert!(!is_audit(Some(&annotations)).unwrap());
+        annotations.insert(AUDIT_ANNOTATION.to_owned(), "true".to_owned());
+        assert!(is_audit(Some(&annotations)).unwrap());
+        annotations.insert(AUDIT_ANNOTATION.to_owned(), "yes".to_owned());
+        assert!(is_audit(Some(&annotations)).is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_audit() -> Result<()> {
+        let syscall = LinuxSyscallBuilder::default()
+            .names(vec![String::from("getcwd")])
+            .action(LinuxSeccompAction::ScmpActErrno)
+            .errno_ret(libc::EAGAIN as u32)
+            .build()?;
+        let notify = LinuxSyscallBuilder::default()
+            .names(vec![String::from("getpid")])
+            .action(LinuxSeccompAction::ScmpActNotify)
+            .build()?;
+        let seccomp_profile = LinuxSeccompBuilder::default()
+            .default_action(LinuxSeccompAction::ScmpActAllow)
+            .architectures(vec![Arch::ScmpArchNative])
+            .syscalls(vec![syscall, notify])
+            .build()?;
+        test_utils::test_in_child_process(|| {
+            let _ = prctl::set_no_new_privileges(true);
+            let fd = initialize_seccomp(&seccomp_profile, true)?;
+            if fd.is_some() {
+                bail!("got a seccomp notify fd in audit mode");
+            }
+
+            if let Err(errno) = nix::unistd::getcwd() {
+                bail!("getcwd failed in audit mode: {}", errno);
+            }
+
+            Ok(())
+        })?;
+
+        Ok(())
+    }
 }
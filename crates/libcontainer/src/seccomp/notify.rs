@@ -0,0 +1,267 @@
+//! Supervisor side of the seccomp user notification protocol.
+//!
+//! If a seccomp profile uses the `SCMP_ACT_NOTIFY` action, the runtime sends the
+//! container state together with the seccomp notify fd to the unix socket specified
+//! in `linux.seccomp.listenerPath`. A seccomp agent listening on this socket can use
+//! this module to receive the fd, read the notifications of the intercepted syscalls
+//! and respond to them. See seccomp_unotify(2) for details.
+use anyhow::{bail, Context, Result};
+use libseccomp::{seccomp_notif, seccomp_notif_resp};
+use nix::{
+    errno::Errno,
+    sys::{socket, uio},
+    unistd,
+};
+use std::{
+    mem,
+    os::unix::{
+        io::{AsRawFd, RawFd},
+        net::UnixStream,
+    },
+};
+
+use crate::container::ContainerProcessState;
+
+// Tells the kernel to execute the syscall as if no filter was installed
+const SECCOMP_USER_NOTIF_FLAG_CONTINUE: u32 = 1;
+// Installs the fd at the number specified in newfd
+const SECCOMP_ADDFD_FLAG_SETFD: u32 = 1;
+// Atomically injects the fd and uses its number as return value of the syscall
+const SECCOMP_ADDFD_FLAG_SEND: u32 = 2;
+// Upper bound of the size of the container state sent by the runtime
+const MAX_STATE_SIZE: usize = 64 * 1024;
+
+#[allow(non_camel_case_types)]
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+struct seccomp_notif_addfd {
+    id: u64,
+    flags: u32,
+    srcfd: u32,
+    newfd: u32,
+    newfd_flags: u32,
+}
+
+mod ioctl {
+    use super::{seccomp_notif, seccomp_notif_addfd, seccomp_notif_resp};
+
+    nix::ioctl_readwrite!(notif_recv, b'!', 0, seccomp_notif);
+    nix::ioctl_readwrite!(notif_send, b'!', 1, seccomp_notif_resp);
+    nix::ioctl_write_ptr!(notif_id_valid, b'!', 2, u64);
+    nix::ioctl_write_ptr!(notif_addfd, b'!', 3, seccomp_notif_addfd);
+}
+
+/// A syscall of the container that has been intercepted by the seccomp filter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Notification {
+    /// Cookie that identifies the notification, needed to respond to it
+    pub id: u64,
+    /// Pid of the process that made the syscall, as seen in the pid namespace of the agent
+    pub pid: u32,
+    /// Number of the syscall
+    pub syscall: i32,
+    /// Architecture the syscall has been made for (AUDIT_ARCH_*)
+    pub arch: u32,
+    /// Instruction pointer of the process at the time of the syscall
+    pub instruction_pointer: u64,
+    /// Arguments of the syscall
+    pub args: [u64; 6],
+}
+
+/// Answer to an intercepted syscall
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Response {
+    /// Let the kernel execute the syscall. Note that this is unsafe for syscalls that
+    /// dereference pointers, as the memory may have changed since it was inspected.
+    Continue,
+    /// Fail the syscall with the given errno
+    Errno(Errno),
+    /// Complete the syscall successfully with the given return value
+    Value(i64),
+}
+
+/// Options for injecting a fd of the agent into the process of a notification
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AddFd {
+    /// Number the fd should be installed at. If None, the lowest available
+    /// number is used.
+    pub target: Option<RawFd>,
+    /// Set close-on-exec on the injected fd
+    pub cloexec: bool,
+    /// Complete the syscall with the number of the injected fd as return value.
+    /// No additional response must be sent for the notification.
+    pub send: bool,
+}
+
+/// Seccomp notify fd received from the runtime
+#[derive(Debug)]
+pub struct NotifyFd {
+    fd: RawFd,
+}
+
+impl NotifyFd {
+    /// Wraps a raw seccomp notify fd. The fd is closed when the NotifyFd is dropped.
+    pub fn from_raw_fd(fd: RawFd) -> Self {
+        Self { fd }
+    }
+
+    /// Blocks until the next syscall has been intercepted
+    pub fn receive(&self) -> Result<Notification> {
+        // the kernel requires the buffer to be zeroed
+        let mut req: seccomp_notif = unsafe { mem::zeroed() };
+        loop {
+            match unsafe { ioctl::notif_recv(self.fd, &mut req) } {
+                Ok(_) => break,
+                Err(Errno::EINTR) => continue,
+                Err(err) => bail!("failed to receive seccomp notification: {}", err),
+            }
+        }
+
+        Ok(Notification {
+            id: req.id,
+            pid: req.pid,
+            syscall: req.data.nr,
+            arch: req.data.arch,
+            instruction_pointer: req.data.instruction_pointer,
+            args: req.data.args,
+        })
+    }
+
+    /// Responds to an intercepted syscall
+    pub fn respond(&self, id: u64, response: Response) -> Result<()> {
+        let mut resp = seccomp_notif_resp {
+            id,
+            val: 0,
+            error: 0,
+            flags: 0,
+        };
+        match response {
+            Response::Continue => resp.flags = SECCOMP_USER_NOTIF_FLAG_CONTINUE,
+            Response::Errno(errno) => resp.error = -(errno as i32),
+            Response::Value(val) => resp.val = val,
+        }
+
+        unsafe { ioctl::notif_send(self.fd, &mut resp) }
+            .map(|_| ())
+            .with_context(|| format!("failed to respond to seccomp notification {}", id))
+    }
+
+    /// Checks that the process of the notification is still waiting for a
+    /// response. This must be checked after reading the memory of the process,
+    /// as the process may have been killed and its pid reused in between.
+    pub fn is_id_valid(&self, id: u64) -> bool {
+        unsafe { ioctl::notif_id_valid(self.fd, &id) }.is_ok()
+    }
+
+    /// Injects a fd of the agent into the process of the notification and
+    /// returns its number in the target process.
+    pub fn add_fd(&self, id: u64, fd: RawFd, options: AddFd) -> Result<RawFd> {
+        let mut addfd = seccomp_notif_addfd {
+            id,
+            srcfd: fd as u32,
+            ..Default::default()
+        };
+        if let Some(target) = options.target {
+            addfd.flags |= SECCOMP_ADDFD_FLAG_SETFD;
+            addfd.newfd = target as u32;
+        }
+        if options.send {
+            addfd.flags |= SECCOMP_ADDFD_FLAG_SEND;
+        }
+        if options.cloexec {
+            addfd.newfd_flags = libc::O_CLOEXEC as u32;
+        }
+
+        unsafe { ioctl::notif_addfd(self.fd, &addfd) }
+            .with_context(|| format!("failed to add fd to seccomp notification {}", id))
+    }
+}
+
+impl AsRawFd for NotifyFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for NotifyFd {
+    fn drop(&mut self) {
+        let _ = unistd::close(self.fd);
+    }
+}
+
+/// Receives the container state and the seccomp notify fd that the runtime
+/// sends over a connection to the listener path.
+pub fn receive_listener_message(stream: &UnixStream) -> Result<(ContainerProcessState, NotifyFd)> {
+    let mut buf = vec![0u8; MAX_STATE_SIZE];
+    let mut cmsg_buf = nix::cmsg_space!([RawFd; 1]);
+    let iov = [uio::IoVec::from_mut_slice(&mut buf)];
+    let msg = socket::recvmsg(
+        stream.as_raw_fd(),
+        &iov,
+        Some(&mut cmsg_buf),
+        socket::MsgFlags::MSG_CMSG_CLOEXEC,
+    )
+    .context("failed to receive message from runtime")?;
+
+    let fd = msg
+        .cmsgs()
+        .find_map(|cmsg| match cmsg {
+            socket::ControlMessageOwned::ScmRights(fds) => fds.first().copied(),
+            _ => None,
+        })
+        .context("no seccomp notify fd in message from runtime")?;
+    let notify_fd = NotifyFd::from_raw_fd(fd);
+
+    let len = msg.bytes;
+    let state = serde_json::from_slice(&buf[..len])
+        .context("failed to parse container state from runtime")?;
+
+    Ok((state, notify_fd))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_struct_sizes() {
+        // sizes defined by the kernel ABI, which are encoded in the ioctl numbers
+        assert_eq!(mem::size_of::<seccomp_notif>(), 80);
+        assert_eq!(mem::size_of::<seccomp_notif_resp>(), 24);
+        assert_eq!(mem::size_of::<seccomp_notif_addfd>(), 24);
+    }
+
+    #[test]
+    fn test_receive_listener_message() -> Result<()> {
+        let (runtime, agent) = UnixStream::pair()?;
+        let state = ContainerProcessState {
+            oci_version: "1.0.2".to_string(),
+            fds: vec![String::from("seccompFd")],
+            pid: 1000,
+            ..Default::default()
+        };
+        let encoded = serde_json::to_vec(&state)?;
+        let (read_end, write_end) = unistd::pipe()?;
+        socket::sendmsg(
+            runtime.as_raw_fd(),
+            &[uio::IoVec::from_slice(&encoded)],
+            &[socket::ControlMessage::ScmRights(&[read_end])],
+            socket::MsgFlags::empty(),
+            None,
+        )?;
+        unistd::close(read_end)?;
+
+        let (received, notify_fd) = receive_listener_message(&agent)?;
+        assert_eq!(received.pid, 1000);
+        assert_eq!(received.fds, vec![String::from("seccompFd")]);
+
+        // the received fd refers to the same pipe
+        unistd::write(write_end, b"x")?;
+        let mut buf = [0u8; 1];
+        unistd::read(notify_fd.as_raw_fd(), &mut buf)?;
+        assert_eq!(&buf, b"x");
+        unistd::close(write_end)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,278 @@
+use anyhow::{bail, Context, Result};
+use nix::fcntl::{flock, FlockArg};
+use std::{
+    collections::HashSet,
+    fs,
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    container::{Container, State},
+    lsm::{self, LsmStatus},
+    utils,
+};
+
+const SELINUX_CONFIG_PATH: &str = "/etc/selinux/config";
+// Number of categories available to MCS. Category c0 to c1023 are defined
+// by the reference policies.
+const MCS_CATEGORIES: u32 = 1024;
+// Upper bound of attempts to find a category pair that is not used by any
+// other container yet
+const MCS_MAX_ATTEMPTS: usize = 1024;
+// Lock file in the root path that serializes the allocation of categories
+const MCS_LOCK_FILE: &str = ".selinux-mcs.lock";
+
+/// Checks if SELinux has been enabled on the system.
+pub fn is_enabled() -> bool {
+    lsm::selinux_status() == LsmStatus::Enabled
+}
+
+/// Applies a SELinux label to the container process. The label takes
+/// effect with the next exec.
+pub fn apply_label(label: &str) -> Result<()> {
+    if label.is_empty() {
+        return Ok(());
+    }
+
+    let path = Path::new("/proc/self/attr/exec");
+    utils::ensure_procfs(path)?;
+    utils::write_file(path, label)
+}
+
+/// Process and mount label of a container that has been assigned a unique
+/// MCS category pair.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct McsLabels {
+    pub process_label: String,
+    pub mount_label: String,
+}
+
+/// Takes an exclusive lock on the allocation of labels in the root path. The
+/// lock has to be held from the allocation until the label is saved in the
+/// state of the container, so that concurrent creations cannot pick the same
+/// categories. It is released when the returned file is dropped.
+pub fn lock_allocation(root_path: &Path) -> Result<fs::File> {
+    utils::create_dir_all(root_path)?;
+    let lock_path = root_path.join(MCS_LOCK_FILE);
+    let lock = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .mode(0o600)
+        .open(&lock_path)
+        .with_context(|| format!("failed to open {:?}", lock_path))?;
+    flock(lock.as_raw_fd(), FlockArg::LockExclusive)
+        .with_context(|| format!("failed to lock {:?}", lock_path))?;
+    Ok(lock)
+}
+
+/// Generates a process and mount label for a new container. The base contexts
+/// are taken from the lxc_contexts file of the active policy and extended with
+/// a category pair that is not used by any other container in the root path.
+/// The caller has to hold the lock of [`lock_allocation`].
+pub fn allocate_labels(root_path: &Path) -> Result<McsLabels> {
+    let (process_context, file_context) =
+        container_contexts().context("failed to read container contexts of SELinux policy")?;
+    let used = used_categories(root_path)?;
+    let categories = allocate_categories(&used)?;
+
+    Ok(McsLabels {
+        process_label: with_categories(&process_context, categories)?,
+        mount_label: with_categories(&file_context, categories)?,
+    })
+}
+
+/// Extracts the MCS categories from a label like
+/// `system_u:system_r:container_t:s0:c1,c2`.
+pub fn parse_categories(label: &str) -> Option<(u32, u32)> {
+    let categories = label.splitn(5, ':').nth(4)?;
+    let (first, second) = categories.split_once(',')?;
+    let first = first.strip_prefix('c')?.parse().ok()?;
+    let second = second.strip_prefix('c')?.parse().ok()?;
+    Some((first, second))
+}
+
+fn container_contexts() -> Result<(String, String)> {
+    let config = fs::read_to_string(SELINUX_CONFIG_PATH)
+        .with_context(|| format!("failed to read {}", SELINUX_CONFIG_PATH))?;
+    let policy = parse_key_values(&config, '=')
+        .into_iter()
+        .find(|(key, _)| key == "SELINUXTYPE")
+        .map(|(_, value)| value)
+        .with_context(|| format!("no SELINUXTYPE in {}", SELINUX_CONFIG_PATH))?;
+
+    let lxc_contexts_path = PathBuf::from("/etc/selinux")
+        .join(policy)
+        .join("contexts/lxc_contexts");
+    let lxc_contexts = fs::read_to_string(&lxc_contexts_path)
+        .with_context(|| format!("failed to read {}", lxc_contexts_path.display()))?;
+    parse_lxc_contexts(&lxc_contexts)
+}
+
+fn parse_lxc_contexts(content: &str) -> Result<(String, String)> {
+    let contexts = parse_key_values(content, '=');
+    let get = |name: &str| {
+        contexts
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+            .with_context(|| format!("no {} context in lxc_contexts", name))
+    };
+
+    Ok((get("process")?, get("file")?))
+}
+
+fn parse_key_values(content: &str, separator: char) -> Vec<(String, String)> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once(separator))
+        .map(|(key, value)| {
+            (
+                key.trim().to_owned(),
+                value.trim().trim_matches('"').to_owned(),
+            )
+        })
+        .collect()
+}
+
+fn used_categories(root_path: &Path) -> Result<HashSet<(u32, u32)>> {
+    let mut used = HashSet::new();
+    if !root_path.exists() {
+        return Ok(used);
+    }
+
+    for container_dir in fs::read_dir(root_path)? {
+        let container_dir = container_dir?.path();
+        if !Container::is_container_dir(&container_dir)
+            || !State::file_path(&container_dir).exists()
+        {
+            continue;
+        }
+
+        match Container::load(container_dir.clone()) {
+            Ok(container) => {
+                if let Some(categories) = container.selinux_label().and_then(parse_categories) {
+                    used.insert(categories);
+                }
+            }
+            Err(err) => log::debug!(
+                "failed to load container from {:?} while allocating MCS categories: {:?}",
+                container_dir,
+                err
+            ),
+        }
+    }
+
+    Ok(used)
+}
+
+fn allocate_categories(used: &HashSet<(u32, u32)>) -> Result<(u32, u32)> {
+    for _ in 0..MCS_MAX_ATTEMPTS {
+        let first = fastrand::u32(0..MCS_CATEGORIES);
+        let second = fastrand::u32(0..MCS_CATEGORIES);
+        if first == second {
+            continue;
+        }
+
+        let pair = (first.min(second), first.max(second));
+        if !used.contains(&pair) {
+            return Ok(pair);
+        }
+    }
+
+    bail!("failed to find an unused MCS category pair")
+}
+
+fn with_categories(context: &str, (first, second): (u32, u32)) -> Result<String> {
+    // a context consists of user:role:type:level, where the level may already
+    // contain categories. Those are replaced by the allocated ones.
+    let parts: Vec<&str> = context.splitn(5, ':').collect();
+    if parts.len() < 4 {
+        bail!("invalid SELinux context {}", context);
+    }
+
+    Ok(format!(
+        "{}:{}:{}:{}:c{},c{}",
+        parts[0], parts[1], parts[2], parts[3], first, second
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lxc_contexts() -> Result<()> {
+        let content = r#"
+process = "system_u:system_r:container_t:s0"
+content = "system_u:object_r:virt_var_lib_t:s0"
+file = "system_u:object_r:container_file_t:s0"
+ro_file="system_u:object_r:container_ro_file_t:s0"
+sandbox_kvm_process = "system_u:system_r:container_kvm_t:s0"
+"#;
+        let (process, file) = parse_lxc_contexts(content)?;
+        assert_eq!(process, "system_u:system_r:container_t:s0");
+        assert_eq!(file, "system_u:object_r:container_file_t:s0");
+
+        assert!(parse_lxc_contexts("process = \"system_u:system_r:container_t:s0\"").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_categories() -> Result<()> {
+        assert_eq!(
+            with_categories("system_u:system_r:container_t:s0", (1, 1023))?,
+            "system_u:system_r:container_t:s0:c1,c1023"
+        );
+        assert_eq!(
+            with_categories("system_u:object_r:container_file_t:s0:c5,c6", (7, 8))?,
+            "system_u:object_r:container_file_t:s0:c7,c8"
+        );
+        assert!(with_categories("container_t", (7, 8)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_categories() {
+        assert_eq!(
+            parse_categories("system_u:system_r:container_t:s0:c12,c345"),
+            Some((12, 345))
+        );
+        assert_eq!(parse_categories("system_u:system_r:container_t:s0"), None);
+        assert_eq!(
+            parse_categories("system_u:system_r:container_t:s0:c0.c1023"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_lock_allocation() -> Result<()> {
+        let tmp = crate::utils::create_temp_dir("test_selinux_lock_allocation")?;
+        let root_path = tmp.path().join("root");
+        let lock = lock_allocation(&root_path)?;
+
+        // a second lock, as taken by a concurrent creation, has to wait
+        let second = fs::File::open(root_path.join(MCS_LOCK_FILE))?;
+        assert_eq!(
+            flock(second.as_raw_fd(), FlockArg::LockExclusiveNonblock),
+            Err(nix::errno::Errno::EWOULDBLOCK)
+        );
+        drop(lock);
+        flock(second.as_raw_fd(), FlockArg::LockExclusiveNonblock)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_allocate_categories() -> Result<()> {
+        let mut used = HashSet::new();
+        for _ in 0..100 {
+            let (first, second) = allocate_categories(&used)?;
+            assert!(first < second);
+            assert!(second < MCS_CATEGORIES);
+            assert!(used.insert((first, second)));
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,9 @@
 //! Implements Command trait for Linux systems
 #[cfg_attr(coverage, no_coverage)]
-use std::ffi::{CStr, OsStr};
+use std::ffi::{CStr, CString, OsStr};
 use std::os::unix::ffi::OsStrExt;
 use std::os::unix::fs::symlink;
+use std::os::unix::io::RawFd;
 use std::sync::Arc;
 use std::{any::Any, mem, path::Path, ptr};
 
@@ -14,7 +15,7 @@ use nix::{
     fcntl::{open, OFlag},
     mount::{mount, umount2, MntFlags, MsFlags},
     sched::{unshare, CloneFlags},
-    sys::stat::{mknod, Mode, SFlag},
+    sys::stat::{fchmodat, mknod, FchmodatFlags, Mode, SFlag},
     unistd,
     unistd::{chown, fchdir, pivot_root, setgroups, sethostname, Gid, Uid},
 };
@@ -24,6 +25,38 @@ use oci_spec::runtime::LinuxRlimit;
 use super::Syscall;
 use crate::capabilities;
 
+// Flags of mount_setattr(2), see include/uapi/linux/mount.h
+pub const MOUNT_ATTR_RDONLY: u64 = 0x00000001;
+pub const MOUNT_ATTR_NOSUID: u64 = 0x00000002;
+pub const MOUNT_ATTR_NODEV: u64 = 0x00000004;
+pub const MOUNT_ATTR_NOEXEC: u64 = 0x00000008;
+pub const MOUNT_ATTR__ATIME: u64 = 0x00000070;
+pub const MOUNT_ATTR_RELATIME: u64 = 0x00000000;
+pub const MOUNT_ATTR_NOATIME: u64 = 0x00000010;
+pub const MOUNT_ATTR_STRICTATIME: u64 = 0x00000020;
+pub const MOUNT_ATTR_NODIRATIME: u64 = 0x00000080;
+pub const MOUNT_ATTR_NOSYMFOLLOW: u64 = 0x00200000;
+
+/// Applies the attributes to the whole mount tree below the path
+pub const AT_RECURSIVE: u32 = 0x8000;
+/// Operates on the file descriptor itself if the path is empty
+pub const AT_EMPTY_PATH: u32 = libc::AT_EMPTY_PATH as u32;
+
+// Flags of open_tree(2) and move_mount(2)
+pub const OPEN_TREE_CLONE: u32 = 0x1;
+pub const OPEN_TREE_CLOEXEC: u32 = libc::O_CLOEXEC as u32;
+pub const MOVE_MOUNT_F_EMPTY_PATH: u32 = 0x4;
+
+/// Attributes that are changed by mount_setattr(2)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MountAttr {
+    pub attr_set: u64,
+    pub attr_clr: u64,
+    pub propagation: u64,
+    pub userns_fd: u64,
+}
+
 /// Empty structure to implement Command trait for
 #[derive(Clone)]
 pub struct LinuxSyscall;
@@ -246,10 +279,81 @@ impl Syscall for LinuxSyscall {
         }
     }
 
+    fn chmod(&self, path: &Path, mode: Mode) -> Result<()> {
+        match fchmodat(None, path, mode, FchmodatFlags::FollowSymlink) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow!(e)),
+        }
+    }
+
     fn set_groups(&self, groups: &[Gid]) -> Result<()> {
         match setgroups(groups) {
             Ok(_) => Ok(()),
             Err(e) => Err(anyhow!(e)),
         }
     }
+
+    fn mount_setattr(
+        &self,
+        dirfd: RawFd,
+        path: &Path,
+        flags: u32,
+        mount_attr: &MountAttr,
+    ) -> Result<()> {
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_mount_setattr,
+                dirfd,
+                path.as_ptr(),
+                flags,
+                mount_attr as *const MountAttr,
+                mem::size_of::<MountAttr>(),
+            )
+        };
+
+        match Errno::result(res) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow!(e)),
+        }
+    }
+
+    /// Creates a detached copy of the mount at the path and returns a file
+    /// descriptor referring to it
+    fn open_tree(&self, dirfd: RawFd, path: &Path, flags: u32) -> Result<RawFd> {
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        let res = unsafe { libc::syscall(libc::SYS_open_tree, dirfd, path.as_ptr(), flags) };
+
+        match Errno::result(res) {
+            Ok(fd) => Ok(fd as RawFd),
+            Err(e) => Err(anyhow!(e)),
+        }
+    }
+
+    fn move_mount(
+        &self,
+        from_dirfd: RawFd,
+        from_path: &Path,
+        to_dirfd: RawFd,
+        to_path: &Path,
+        flags: u32,
+    ) -> Result<()> {
+        let from_path = CString::new(from_path.as_os_str().as_bytes())?;
+        let to_path = CString::new(to_path.as_os_str().as_bytes())?;
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_move_mount,
+                from_dirfd,
+                from_path.as_ptr(),
+                to_dirfd,
+                to_path.as_ptr(),
+                flags,
+            )
+        };
+
+        match Errno::result(res) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow!(e)),
+        }
+    }
 }
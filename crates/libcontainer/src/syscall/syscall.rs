@@ -1,7 +1,7 @@
 //! An interface trait so that rest of Youki can call
 //! necessary functions without having to worry about their
 //! implementation details
-use std::{any::Any, ffi::OsStr, path::Path, sync::Arc};
+use std::{any::Any, ffi::OsStr, os::unix::io::RawFd, path::Path, sync::Arc};
 
 use anyhow::Result;
 use caps::{CapSet, CapsHashSet};
@@ -14,7 +14,10 @@ use nix::{
 
 use oci_spec::runtime::LinuxRlimit;
 
-use crate::syscall::{linux::LinuxSyscall, test::TestHelperSyscall};
+use crate::syscall::{
+    linux::{LinuxSyscall, MountAttr},
+    test::TestHelperSyscall,
+};
 
 /// This specifies various kernel/other functionalities required for
 /// container management
@@ -40,7 +43,24 @@ pub trait Syscall {
     fn symlink(&self, original: &Path, link: &Path) -> Result<()>;
     fn mknod(&self, path: &Path, kind: SFlag, perm: Mode, dev: u64) -> Result<()>;
     fn chown(&self, path: &Path, owner: Option<Uid>, group: Option<Gid>) -> Result<()>;
+    fn chmod(&self, path: &Path, mode: Mode) -> Result<()>;
     fn set_groups(&self, groups: &[Gid]) -> Result<()>;
+    fn mount_setattr(
+        &self,
+        dirfd: RawFd,
+        path: &Path,
+        flags: u32,
+        mount_attr: &MountAttr,
+    ) -> Result<()>;
+    fn open_tree(&self, dirfd: RawFd, path: &Path, flags: u32) -> Result<RawFd>;
+    fn move_mount(
+        &self,
+        from_dirfd: RawFd,
+        from_path: &Path,
+        to_dirfd: RawFd,
+        to_path: &Path,
+        flags: u32,
+    ) -> Result<()>;
 }
 
 pub fn create_syscall() -> Box<dyn Syscall> {
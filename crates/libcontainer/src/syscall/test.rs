@@ -3,6 +3,7 @@ use std::{
     cell::{Ref, RefCell, RefMut},
     collections::HashMap,
     ffi::{OsStr, OsString},
+    os::unix::io::RawFd,
     path::{Path, PathBuf},
     sync::Arc,
 };
@@ -17,7 +18,7 @@ use nix::{
 
 use oci_spec::runtime::LinuxRlimit;
 
-use super::Syscall;
+use super::{linux::MountAttr, Syscall};
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct MountArgs {
@@ -28,6 +29,30 @@ pub struct MountArgs {
     pub data: Option<String>,
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MountSetattrArgs {
+    pub dirfd: RawFd,
+    pub path: PathBuf,
+    pub flags: u32,
+    pub mount_attr: MountAttr,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct OpenTreeArgs {
+    pub dirfd: RawFd,
+    pub path: PathBuf,
+    pub flags: u32,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MoveMountArgs {
+    pub from_dirfd: RawFd,
+    pub from_path: PathBuf,
+    pub to_dirfd: RawFd,
+    pub to_path: PathBuf,
+    pub flags: u32,
+}
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct MknodArgs {
     pub path: PathBuf,
@@ -43,6 +68,12 @@ pub struct ChownArgs {
     pub group: Option<Gid>,
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ChmodArgs {
+    pub path: PathBuf,
+    pub mode: Mode,
+}
+
 #[derive(Default)]
 struct Mock {
     values: Vec<Box<dyn Any>>,
@@ -55,9 +86,13 @@ pub enum ArgName {
     Namespace,
     Unshare,
     Mount,
+    MountSetattr,
+    OpenTree,
+    MoveMount,
     Symlink,
     Mknod,
     Chown,
+    Chmod,
     Hostname,
     Groups,
     Capability,
@@ -69,9 +104,13 @@ impl ArgName {
             ArgName::Namespace,
             ArgName::Unshare,
             ArgName::Mount,
+            ArgName::MountSetattr,
+            ArgName::OpenTree,
+            ArgName::MoveMount,
             ArgName::Symlink,
             ArgName::Mknod,
             ArgName::Chown,
+            ArgName::Chmod,
             ArgName::Hostname,
             ArgName::Groups,
             ArgName::Capability,
@@ -224,9 +263,70 @@ impl Syscall for TestHelperSyscall {
         )
     }
 
+    fn chmod(&self, path: &Path, mode: Mode) -> anyhow::Result<()> {
+        self.mocks.act(
+            ArgName::Chmod,
+            Box::new(ChmodArgs {
+                path: path.to_path_buf(),
+                mode,
+            }),
+        )
+    }
+
     fn set_groups(&self, groups: &[Gid]) -> anyhow::Result<()> {
         self.mocks.act(ArgName::Groups, Box::new(groups.to_vec()))
     }
+
+    fn mount_setattr(
+        &self,
+        dirfd: RawFd,
+        path: &Path,
+        flags: u32,
+        mount_attr: &MountAttr,
+    ) -> anyhow::Result<()> {
+        self.mocks.act(
+            ArgName::MountSetattr,
+            Box::new(MountSetattrArgs {
+                dirfd,
+                path: path.to_path_buf(),
+                flags,
+                mount_attr: *mount_attr,
+            }),
+        )
+    }
+
+    /// Returns an invalid file descriptor, so that closing it has no effect
+    fn open_tree(&self, dirfd: RawFd, path: &Path, flags: u32) -> anyhow::Result<RawFd> {
+        self.mocks.act(
+            ArgName::OpenTree,
+            Box::new(OpenTreeArgs {
+                dirfd,
+                path: path.to_path_buf(),
+                flags,
+            }),
+        )?;
+        Ok(-1)
+    }
+
+    fn move_mount(
+        &self,
+        from_dirfd: RawFd,
+        from_path: &Path,
+        to_dirfd: RawFd,
+        to_path: &Path,
+        flags: u32,
+    ) -> anyhow::Result<()> {
+        self.mocks.act(
+            ArgName::MoveMount,
+            Box::new(MoveMountArgs {
+                from_dirfd,
+                from_path: from_path.to_path_buf(),
+                to_dirfd,
+                to_path: to_path.to_path_buf(),
+                flags,
+            }),
+        )
+    }
 }
 
 impl TestHelperSyscall {
@@ -275,6 +375,33 @@ impl TestHelperSyscall {
             .collect::<Vec<MountArgs>>()
     }
 
+    pub fn get_mount_setattr_args(&self) -> Vec<MountSetattrArgs> {
+        self.mocks
+            .fetch(ArgName::MountSetattr)
+            .values
+            .iter()
+            .map(|x| x.downcast_ref::<MountSetattrArgs>().unwrap().clone())
+            .collect::<Vec<MountSetattrArgs>>()
+    }
+
+    pub fn get_open_tree_args(&self) -> Vec<OpenTreeArgs> {
+        self.mocks
+            .fetch(ArgName::OpenTree)
+            .values
+            .iter()
+            .map(|x| x.downcast_ref::<OpenTreeArgs>().unwrap().clone())
+            .collect::<Vec<OpenTreeArgs>>()
+    }
+
+    pub fn get_move_mount_args(&self) -> Vec<MoveMountArgs> {
+        self.mocks
+            .fetch(ArgName::MoveMount)
+            .values
+            .iter()
+            .map(|x| x.downcast_ref::<MoveMountArgs>().unwrap().clone())
+            .collect::<Vec<MoveMountArgs>>()
+    }
+
     pub fn get_symlink_args(&self) -> Vec<(PathBuf, PathBuf)> {
         self.mocks
             .fetch(ArgName::Symlink)
@@ -302,6 +429,15 @@ impl TestHelperSyscall {
             .collect::<Vec<ChownArgs>>()
     }
 
+    pub fn get_chmod_args(&self) -> Vec<ChmodArgs> {
+        self.mocks
+            .fetch(ArgName::Chmod)
+            .values
+            .iter()
+            .map(|x| x.downcast_ref::<ChmodArgs>().unwrap().clone())
+            .collect::<Vec<ChmodArgs>>()
+    }
+
     pub fn get_hostname_args(&self) -> Vec<String> {
         self.mocks
             .fetch(ArgName::Hostname)
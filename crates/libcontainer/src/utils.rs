@@ -67,6 +67,45 @@ pub fn do_exec(path: impl AsRef<Path>, args: &[String]) -> Result<()> {
     Ok(())
 }
 
+// Search path used by execvp if PATH is not set in the environment
+const DEFAULT_EXEC_PATH: &str = "/bin:/usr/bin";
+
+/// Resolves the executable the same way execvp would. Names containing a slash
+/// are used as is, otherwise each entry of `path_var` is searched in order. Returns
+/// None if no executable file could be found.
+pub fn get_executable_path(name: &str, path_var: Option<&str>) -> Option<PathBuf> {
+    if name.is_empty() {
+        return None;
+    }
+
+    if name.contains('/') {
+        let path = PathBuf::from(name);
+        return is_executable(&path).then(|| path);
+    }
+
+    path_var
+        .unwrap_or(DEFAULT_EXEC_PATH)
+        .split(':')
+        .map(|dir| {
+            // an empty entry refers to the current working directory
+            if dir.is_empty() {
+                PathBuf::from(name)
+            } else {
+                Path::new(dir).join(name)
+            }
+        })
+        .find(|path| is_executable(path))
+}
+
+fn is_executable(path: &Path) -> bool {
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() => {
+            unistd::access(path, unistd::AccessFlags::X_OK).is_ok()
+        }
+        _ => false,
+    }
+}
+
 /// If None, it will generate a default path for cgroups.
 pub fn get_cgroup_path(cgroups_path: &Option<PathBuf>, container_id: &str) -> PathBuf {
     match cgroups_path {
@@ -338,6 +377,43 @@ mod tests {
 
         Ok(())
     }
+    #[test]
+    fn test_get_executable_path() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = create_temp_dir("test_get_executable_path").unwrap();
+        let bin = tmp.path().join("bin");
+        fs::create_dir(&bin).unwrap();
+
+        let executable = bin.join("echo");
+        fs::write(&executable, "").unwrap();
+        fs::set_permissions(&executable, fs::Permissions::from_mode(0o755)).unwrap();
+        let non_executable = bin.join("data");
+        fs::write(&non_executable, "").unwrap();
+        fs::set_permissions(&non_executable, fs::Permissions::from_mode(0o644)).unwrap();
+
+        let path_var = format!("/does/not/exist:{}", bin.display());
+        assert_eq!(
+            get_executable_path("echo", Some(&path_var)),
+            Some(executable.clone())
+        );
+        assert_eq!(get_executable_path("data", Some(&path_var)), None);
+        assert_eq!(
+            get_executable_path("bin", Some(tmp.path().to_str().unwrap())),
+            None
+        );
+        assert_eq!(get_executable_path("echo", Some("/does/not/exist")), None);
+        assert_eq!(
+            get_executable_path(executable.to_str().unwrap(), None),
+            Some(executable.clone())
+        );
+        assert_eq!(
+            get_executable_path(non_executable.to_str().unwrap(), None),
+            None
+        );
+        assert_eq!(get_executable_path("", Some(&path_var)), None);
+    }
+
     #[test]
     fn test_secure_join() {
         assert_eq!(
@@ -0,0 +1,41 @@
+use clap::Parser;
+use std::path::PathBuf;
+
+/// Checkpoint a running container
+#[derive(Parser, Debug)]
+pub struct Checkpoint {
+    #[clap(forbid_empty_values = true, required = true)]
+    pub container_id: String,
+
+    /// Path for saving the criu image files
+    #[clap(long)]
+    pub image_path: Option<PathBuf>,
+
+    /// Path for saving the work files and logs of criu
+    #[clap(long)]
+    pub work_path: Option<PathBuf>,
+
+    /// Path for the images of a previous dump, relative to the image path
+    #[clap(long)]
+    pub parent_path: Option<PathBuf>,
+
+    /// Leave the process running after checkpointing
+    #[clap(long)]
+    pub leave_running: bool,
+
+    /// Allow open tcp connections
+    #[clap(long)]
+    pub tcp_established: bool,
+
+    /// Allow external unix sockets
+    #[clap(long)]
+    pub ext_unix_sk: bool,
+
+    /// Allow shell jobs
+    #[clap(long)]
+    pub shell_job: bool,
+
+    /// Handle file locks
+    #[clap(long)]
+    pub file_locks: bool,
+}
@@ -1,11 +1,15 @@
+use std::error::Error;
+use std::time::Duration;
+
 use clap::Parser;
 
 /// Show resource statistics for the container
 #[derive(Parser, Debug)]
 pub struct Events {
-    /// Sets the stats collection interval in seconds (default: 5s)
-    #[clap(long, default_value = "5")]
-    pub interval: u32,
+    /// Sets the stats collection interval, e.g. 500ms, 5s or 1m. Plain
+    /// numbers are taken as seconds (default: 5s)
+    #[clap(long, default_value = "5s", parse(try_from_str = parse_duration))]
+    pub interval: Duration,
     /// Display the container stats only once
     #[clap(long)]
     pub stats: bool,
@@ -13,3 +17,26 @@ pub struct Events {
     #[clap(forbid_empty_values = true, required = true)]
     pub container_id: String,
 }
+
+fn parse_duration(s: &str) -> Result<Duration, Box<dyn Error + Send + Sync + 'static>> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let value: u64 = s[..split].parse()?;
+    let secs = |multiplier: u64| {
+        value
+            .checked_mul(multiplier)
+            .map(Duration::from_secs)
+            .ok_or_else(|| format!("duration `{}` is too large", s))
+    };
+    let duration = match &s[split..] {
+        "ms" => Duration::from_millis(value),
+        "" | "s" => Duration::from_secs(value),
+        "m" => secs(60)?,
+        "h" => secs(60 * 60)?,
+        unit => return Err(format!("invalid unit `{}` in duration `{}`", unit, s).into()),
+    };
+    if duration.is_zero() {
+        return Err("the interval must be greater than zero".into());
+    }
+
+    Ok(duration)
+}
@@ -20,12 +20,21 @@ pub struct Exec {
     /// Environment variables that should be set in the container
     #[clap(short, long, parse(try_from_str = parse_key_val), number_of_values = 1)]
     pub env: Vec<(String, String)>,
+    /// Add a capability to the capability sets the process defines, or to all sets if it defines none
+    #[clap(short = 'c', long = "cap", number_of_values = 1)]
+    pub cap: Vec<String>,
     /// Prevent the process from gaining additional privileges
     #[clap(long)]
     pub no_new_privs: bool,
     /// Path to process.json
     #[clap(short, long)]
     pub process: Option<PathBuf>,
+    /// User the process runs as in the format UID[:GID]
+    #[clap(short, long, parse(try_from_str = parse_user))]
+    pub user: Option<(u32, Option<u32>)>,
+    /// Additional gids of the process
+    #[clap(short = 'g', long, number_of_values = 1)]
+    pub additional_gids: Vec<u32>,
     /// Detach from the container process
     #[clap(short, long)]
     pub detach: bool,
@@ -37,6 +46,14 @@ pub struct Exec {
     pub command: Vec<String>,
 }
 
+fn parse_user(s: &str) -> Result<(u32, Option<u32>), Box<dyn Error + Send + Sync + 'static>> {
+    match s.split_once(':') {
+        Some(("", _)) => Err(format!("invalid user `{}`: the uid is missing", s).into()),
+        Some((uid, gid)) => Ok((uid.parse()?, Some(gid.parse()?))),
+        None => Ok((s.parse()?, None)),
+    }
+}
+
 fn parse_key_val<T, U>(s: &str) -> Result<(T, U), Box<dyn Error + Send + Sync + 'static>>
 where
     T: std::str::FromStr,
@@ -6,4 +6,7 @@ pub struct Kill {
     #[clap(forbid_empty_values = true, required = true)]
     pub container_id: String,
     pub signal: String,
+    /// Send the signal to all processes in the container
+    #[clap(short, long)]
+    pub all: bool,
 }
@@ -2,4 +2,8 @@ use clap::Parser;
 
 /// List created containers
 #[derive(Parser, Debug)]
-pub struct List {}
+pub struct List {
+    /// format of the output: table, json, yaml or compact (default: "table")
+    #[clap(short, long, default_value = "table")]
+    pub format: String,
+}
@@ -1,4 +1,5 @@
 use clap::Parser;
+use std::path::PathBuf;
 
 /// Command generates a config.json
 #[derive(Parser, Debug)]
@@ -6,4 +7,8 @@ pub struct Spec {
     /// Generate a configuration for a rootless container
     #[clap(long)]
     pub rootless: bool,
+
+    /// Path to the root of the bundle the config.json is written to
+    #[clap(short, long)]
+    pub bundle: Option<PathBuf>,
 }
@@ -1,4 +1,5 @@
 use clap::Parser;
+use std::error::Error;
 use std::path::PathBuf;
 
 /// Update running container resource constraints
@@ -8,11 +9,91 @@ pub struct Update {
     pub container_id: String,
 
     /// Read the new resource limits from the given json file. Use - to read from stdin.
-    /// If this option is used, all other options are ignored.
+    /// If this option is used, all other resource options are ignored.
     #[clap(short, long)]
     pub resources: Option<PathBuf>,
 
     /// Set the maximum number of processes allowed in the container
     #[clap(long)]
     pub pids_limit: Option<i64>,
+
+    /// Memory limit in bytes, optionally with a suffix of k, m, g or t
+    #[clap(short, long, parse(try_from_str = parse_size), allow_hyphen_values = true)]
+    pub memory: Option<i64>,
+
+    /// Memory reservation or soft limit in bytes, optionally with a suffix of k, m, g or t
+    #[clap(long, parse(try_from_str = parse_size), allow_hyphen_values = true)]
+    pub memory_reservation: Option<i64>,
+
+    /// Total memory usage (memory + swap), set -1 to enable unlimited swap
+    #[clap(long, parse(try_from_str = parse_size), allow_hyphen_values = true)]
+    pub memory_swap: Option<i64>,
+
+    /// CPU shares (relative weight vs. other containers)
+    #[clap(long)]
+    pub cpu_shares: Option<u64>,
+
+    /// CPU CFS period to be used for hardcapping (in usecs)
+    #[clap(long)]
+    pub cpu_period: Option<u64>,
+
+    /// CPU CFS hardcap limit (in usecs), the allowed cpu time in a given period
+    #[clap(long, allow_hyphen_values = true)]
+    pub cpu_quota: Option<i64>,
+
+    /// CPU realtime period to be used for hardcapping (in usecs)
+    #[clap(long)]
+    pub cpu_rt_period: Option<u64>,
+
+    /// CPU realtime hardcap limit (in usecs), the allowed cpu time in a given period
+    #[clap(long, allow_hyphen_values = true)]
+    pub cpu_rt_runtime: Option<i64>,
+
+    /// CPU(s) to use, e.g. 0-3 or 0,1
+    #[clap(long)]
+    pub cpuset_cpus: Option<String>,
+
+    /// Memory node(s) to use, e.g. 0-3 or 0,1
+    #[clap(long)]
+    pub cpuset_mems: Option<String>,
+
+    /// Block io weight (relative weight), between 10 and 1000
+    #[clap(long)]
+    pub blkio_weight: Option<u16>,
+
+    /// The string of Intel RDT/CAT L3 cache schema
+    #[clap(long)]
+    pub l3_cache_schema: Option<String>,
+
+    /// The string of Intel RDT/MBA memory bandwidth schema
+    #[clap(long)]
+    pub mem_bw_schema: Option<String>,
+}
+
+/// Parses a size in bytes, which may have a suffix of k, m, g or t, optionally
+/// followed by b, as runc accepts them. -1 stands for unlimited.
+fn parse_size(s: &str) -> Result<i64, Box<dyn Error + Send + Sync + 'static>> {
+    let size = s.trim().to_ascii_lowercase();
+    if size == "-1" {
+        return Ok(-1);
+    }
+
+    let size = size.strip_suffix('b').unwrap_or(&size);
+    let (number, multiplier) = match size.char_indices().last() {
+        Some((i, 'k')) => (&size[..i], 1 << 10),
+        Some((i, 'm')) => (&size[..i], 1 << 20),
+        Some((i, 'g')) => (&size[..i], 1 << 30),
+        Some((i, 't')) => (&size[..i], 1 << 40),
+        _ => (size, 1),
+    };
+    let number: i64 = number
+        .parse()
+        .map_err(|_| format!("invalid size `{}`", s))?;
+    if number < 0 {
+        return Err(format!("invalid size `{}`, only -1 may be negative", s).into());
+    }
+
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size `{}` is too large", s).into())
 }
@@ -0,0 +1,89 @@
+//! Temporary OCI bundles
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use libcontainer::utils::{create_temp_dir, TempDir};
+use oci_spec::runtime::Spec;
+use tar::Archive;
+
+const CONFIG_FILE: &str = "config.json";
+const ROOTFS_DIR: &str = "rootfs";
+
+/// A bundle consisting of a root filesystem and a config.json in a temporary
+/// directory. The directory is removed when the bundle is dropped.
+pub struct Bundle {
+    dir: TempDir,
+}
+
+impl Bundle {
+    /// Creates a bundle with an empty root filesystem and the provided spec
+    pub fn new(name: &str, spec: &Spec) -> Result<Self> {
+        let dir = create_temp_dir(&format!("youki-bundle-{}", name))
+            .with_context(|| format!("failed to create bundle directory for {}", name))?;
+        let bundle = Self { dir };
+        fs::create_dir_all(bundle.rootfs())
+            .with_context(|| format!("failed to create rootfs for {}", name))?;
+        bundle.set_spec(spec)?;
+
+        Ok(bundle)
+    }
+
+    /// Creates a bundle whose root filesystem is unpacked from a gzip compressed tar archive
+    pub fn from_rootfs_archive<P: AsRef<Path>>(
+        name: &str,
+        archive: P,
+        spec: &Spec,
+    ) -> Result<Self> {
+        let archive = archive.as_ref();
+        let bundle = Self::new(name, spec)?;
+        let tar_gz =
+            File::open(archive).with_context(|| format!("failed to open {:?}", archive))?;
+        Archive::new(GzDecoder::new(tar_gz))
+            .unpack(bundle.rootfs())
+            .with_context(|| format!("failed to unpack {:?} to {:?}", archive, bundle.rootfs()))?;
+
+        Ok(bundle)
+    }
+
+    /// Path of the bundle directory
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Path of the root filesystem of the bundle
+    pub fn rootfs(&self) -> PathBuf {
+        self.path().join(ROOTFS_DIR)
+    }
+
+    /// Replaces the config.json of the bundle
+    pub fn set_spec(&self, spec: &Spec) -> Result<()> {
+        let config_path = self.path().join(CONFIG_FILE);
+        spec.save(&config_path)
+            .with_context(|| format!("failed to save spec to {:?}", config_path))?;
+        Ok(())
+    }
+
+    /// Copies a file from the host into the root filesystem, e.g. a statically linked binary.
+    /// The destination is interpreted relative to the root of the root filesystem.
+    pub fn copy_into_rootfs<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        source: P,
+        dest: Q,
+    ) -> Result<()> {
+        let source = source.as_ref();
+        let dest = dest.as_ref();
+        let target = self.rootfs().join(dest.strip_prefix("/").unwrap_or(dest));
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create directory {:?}", parent))?;
+        }
+
+        fs::copy(source, &target)
+            .with_context(|| format!("failed to copy {:?} to {:?}", source, target))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+//! Assertions on the cgroup state of a container
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use libcgroups::common::{self, CgroupSetup, DEFAULT_CGROUP_ROOT};
+
+/// Reads an interface file of a cgroup. The cgroup path is relative to the root of the
+/// cgroup hierarchy, e.g. /youki/container_id. On cgroup v1 systems the file is read from
+/// the hierarchy of the provided controller, on cgroup v2 systems the controller is ignored.
+pub fn read_cgroup_value(cgroup_path: &Path, controller: &str, file: &str) -> Result<String> {
+    let path = cgroup_file_path(cgroup_path, controller, file)?;
+    let value = common::read_cgroup_file(&path)?;
+    Ok(value.trim().to_owned())
+}
+
+/// Asserts that an interface file of a cgroup contains the expected value. Leading
+/// and trailing whitespace is ignored.
+pub fn assert_cgroup_value(
+    cgroup_path: &Path,
+    controller: &str,
+    file: &str,
+    expected: &str,
+) -> Result<()> {
+    let actual = read_cgroup_value(cgroup_path, controller, file)?;
+    if actual != expected.trim() {
+        bail!(
+            "expected {} of cgroup {:?} to be {:?}, but was {:?}",
+            file,
+            cgroup_path,
+            expected,
+            actual
+        );
+    }
+
+    Ok(())
+}
+
+fn cgroup_file_path(cgroup_path: &Path, controller: &str, file: &str) -> Result<PathBuf> {
+    let relative_path = cgroup_path.strip_prefix("/").unwrap_or(cgroup_path);
+    let root = match common::get_cgroup_setup()? {
+        CgroupSetup::Unified => PathBuf::from(DEFAULT_CGROUP_ROOT),
+        CgroupSetup::Legacy | CgroupSetup::Hybrid => controller_mount_point(controller)?,
+    };
+
+    Ok(root.join(relative_path).join(file))
+}
+
+fn controller_mount_point(controller: &str) -> Result<PathBuf> {
+    // controllers can be co-mounted, e.g. cpu,cpuacct
+    libcgroups::v1::util::list_subsystem_mount_points()?
+        .into_iter()
+        .find(|mount_point| {
+            mount_point
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map(|name| name.split(',').any(|c| c == controller))
+                .unwrap_or_default()
+        })
+        .with_context(|| format!("could not find mount point for {}", controller))
+}
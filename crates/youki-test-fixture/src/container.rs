@@ -0,0 +1,141 @@
+//! Containers that are cleaned up after the test
+use std::{
+    env,
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use libcontainer::{
+    container::{builder::ContainerBuilder, Container, ContainerStatus},
+    signal::Signal,
+    syscall::syscall::create_syscall,
+    utils::{create_temp_dir, TempDir},
+};
+use nix::sys::signal::Signal as NixSignal;
+
+use crate::bundle::Bundle;
+
+/// Interval in which the status of the container is polled while waiting
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Time to wait for a killed container to stop before it is deleted
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A container created through the library API. The state of the container is stored in
+/// a temporary root directory and the container is killed and deleted when dropped.
+pub struct TestContainer {
+    container: Container,
+    // dropped after the container has been deleted in drop
+    _state_root: TempDir,
+}
+
+impl TestContainer {
+    /// Creates the container from the bundle without starting it
+    pub fn create(id: &str, bundle: &Bundle) -> Result<Self> {
+        let state_root = create_temp_dir(&format!("youki-state-{}", id))
+            .with_context(|| format!("failed to create state directory for {}", id))?;
+        let syscall = create_syscall();
+        let container = ContainerBuilder::new(id.to_owned(), syscall.as_ref())
+            .with_root_path(state_root.path())
+            .as_init(bundle.path())
+            .with_systemd(false)
+            .build()
+            .with_context(|| format!("failed to create container {}", id))?;
+
+        Ok(Self {
+            container,
+            _state_root: state_root,
+        })
+    }
+
+    /// Creates the container from the bundle and starts it
+    pub fn run(id: &str, bundle: &Bundle) -> Result<Self> {
+        let mut container = Self::create(id, bundle)?;
+        container.start()?;
+        Ok(container)
+    }
+
+    /// Starts the created container
+    pub fn start(&mut self) -> Result<()> {
+        // starting a container changes the working directory of the
+        // process, which would affect the remaining test
+        let cwd = env::current_dir()?;
+        let result = self
+            .container
+            .start()
+            .with_context(|| format!("failed to start container {}", self.container.id()));
+        env::set_current_dir(cwd)?;
+        result
+    }
+
+    /// Sends a signal to the container init process
+    pub fn kill(&mut self, signal: NixSignal) -> Result<()> {
+        self.container.kill(Signal::from(signal))
+    }
+
+    /// Returns the current status of the container
+    pub fn status(&mut self) -> Result<ContainerStatus> {
+        self.container.refresh_status()?;
+        Ok(self.container.status())
+    }
+
+    /// Waits until the container reaches the expected status
+    pub fn wait_for_status(&mut self, expected: ContainerStatus, timeout: Duration) -> Result<()> {
+        let start = Instant::now();
+        loop {
+            let status = self.status()?;
+            if status == expected {
+                return Ok(());
+            }
+
+            if start.elapsed() > timeout {
+                bail!(
+                    "container {} did not reach status {} within {:?}, status is {}",
+                    self.container.id(),
+                    expected,
+                    timeout,
+                    status
+                );
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Path of the cgroup of the container relative to the root of the cgroup hierarchy
+    pub fn cgroup_path(&self) -> Result<PathBuf> {
+        Ok(self.container.spec()?.cgroup_path)
+    }
+
+    /// Provides access to the underlying container for operations that are not
+    /// covered by the fixture
+    pub fn container(&mut self) -> &mut Container {
+        &mut self.container
+    }
+}
+
+impl Drop for TestContainer {
+    fn drop(&mut self) {
+        if let Ok(status) = self.status() {
+            if status.can_kill() {
+                if let Err(err) = self.kill(NixSignal::SIGKILL) {
+                    log::warn!(
+                        "failed to kill container {}: {:?}",
+                        self.container.id(),
+                        err
+                    );
+                }
+            }
+        }
+
+        let _ = self.wait_for_status(ContainerStatus::Stopped, STOP_TIMEOUT);
+        if let Err(err) = self.container.delete(true) {
+            log::warn!(
+                "failed to delete container {}: {:?}",
+                self.container.id(),
+                err
+            );
+        }
+    }
+}
@@ -0,0 +1,12 @@
+//! Helpers for writing black-box tests against the container library. The fixtures
+//! provide temporary bundles, minimal specs, containers that are cleaned up when dropped
+//! and assertions on the cgroup state of a container.
+pub mod bundle;
+pub mod cgroups;
+pub mod container;
+pub mod spec;
+
+pub use bundle::Bundle;
+pub use cgroups::{assert_cgroup_value, read_cgroup_value};
+pub use container::TestContainer;
+pub use spec::{minimal_spec, rootless_spec};
@@ -0,0 +1,110 @@
+//! Generation of minimal runtime specs
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use oci_spec::runtime::{LinuxIdMappingBuilder, LinuxNamespaceBuilder, LinuxNamespaceType, Spec};
+
+/// Generates a spec which runs the provided command without a terminal
+pub fn minimal_spec(args: &[&str]) -> Result<Spec> {
+    let mut spec = Spec::default();
+    let mut process = spec
+        .process()
+        .clone()
+        .context("default spec has no process")?;
+    process
+        .set_args(Some(args.iter().map(|arg| arg.to_string()).collect()))
+        .set_terminal(Some(false));
+    spec.set_process(Some(process));
+
+    Ok(spec)
+}
+
+/// Generates a spec which runs the provided command without a terminal in a user
+/// namespace, mapping the current user and group to root inside the container
+pub fn rootless_spec(args: &[&str]) -> Result<Spec> {
+    let mut spec = minimal_spec(args)?;
+    let mut linux = spec.linux().clone().context("default spec has no linux")?;
+
+    let mut namespaces: Vec<_> = linux
+        .namespaces()
+        .clone()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|ns| {
+            ns.typ() != LinuxNamespaceType::Network && ns.typ() != LinuxNamespaceType::User
+        })
+        .collect();
+    namespaces.push(
+        LinuxNamespaceBuilder::default()
+            .typ(LinuxNamespaceType::User)
+            .build()?,
+    );
+
+    let uid = nix::unistd::geteuid().as_raw();
+    let gid = nix::unistd::getegid().as_raw();
+    linux
+        .set_namespaces(Some(namespaces))
+        .set_uid_mappings(Some(vec![LinuxIdMappingBuilder::default()
+            .host_id(uid)
+            .container_id(0_u32)
+            .size(1_u32)
+            .build()?]))
+        .set_gid_mappings(Some(vec![LinuxIdMappingBuilder::default()
+            .host_id(gid)
+            .container_id(0_u32)
+            .size(1_u32)
+            .build()?]));
+    spec.set_linux(Some(linux));
+
+    // sysfs cannot be mounted without a network namespace, so it is bind mounted instead
+    let mut mounts = spec.mounts().clone().unwrap_or_default();
+    for mount in &mut mounts {
+        if mount.destination().eq(Path::new("/sys")) {
+            mount
+                .set_source(Some(PathBuf::from("/sys")))
+                .set_typ(Some(String::from("none")))
+                .set_options(Some(vec![
+                    "rbind".to_string(),
+                    "nosuid".to_string(),
+                    "noexec".to_string(),
+                    "nodev".to_string(),
+                    "ro".to_string(),
+                ]));
+        }
+    }
+    spec.set_mounts(Some(mounts));
+
+    Ok(spec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_minimal_spec() -> Result<()> {
+        let spec = minimal_spec(&["sleep", "10"])?;
+        let process = spec.process().as_ref().unwrap();
+        assert_eq!(
+            process.args(),
+            &Some(vec!["sleep".to_owned(), "10".to_owned()])
+        );
+        assert_eq!(process.terminal(), Some(false));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rootless_spec() -> Result<()> {
+        let spec = rootless_spec(&["true"])?;
+        let linux = spec.linux().as_ref().unwrap();
+        let namespaces = linux.namespaces().as_ref().unwrap();
+        assert!(namespaces
+            .iter()
+            .any(|ns| ns.typ() == LinuxNamespaceType::User));
+        assert!(!namespaces
+            .iter()
+            .any(|ns| ns.typ() == LinuxNamespaceType::Network));
+        assert_eq!(linux.uid_mappings().as_ref().map(|m| m.len()), Some(1));
+        Ok(())
+    }
+}
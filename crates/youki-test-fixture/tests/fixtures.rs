@@ -0,0 +1,66 @@
+use std::{fs, path::Path, time::Duration};
+
+use anyhow::Result;
+use libcontainer::container::ContainerStatus;
+use nix::sys::signal::Signal;
+use oci_spec::runtime::{LinuxPidsBuilder, MountBuilder, Spec};
+use youki_test_fixture::{assert_cgroup_value, minimal_spec, Bundle, TestContainer};
+
+/// Host directories that are bind mounted into the container, so that it can
+/// run host binaries without a root filesystem archive
+const HOST_DIRS: &[&str] = &["/bin", "/lib", "/lib64", "/usr"];
+
+#[test]
+fn test_bundle() -> Result<()> {
+    let spec = minimal_spec(&["sleep", "10"])?;
+    let bundle = Bundle::new("fixture_bundle", &spec)?;
+    let path = bundle.path().to_owned();
+    assert!(bundle.rootfs().is_dir());
+    let saved = Spec::load(path.join("config.json"))?;
+    assert_eq!(
+        saved.process().as_ref().and_then(|process| process.args().clone()),
+        Some(vec!["sleep".to_owned(), "10".to_owned()])
+    );
+
+    bundle.copy_into_rootfs(path.join("config.json"), "/etc/copied.json")?;
+    assert!(bundle.rootfs().join("etc/copied.json").is_file());
+
+    drop(bundle);
+    assert!(!path.exists());
+    Ok(())
+}
+
+// Creates a real container and therefore needs to be run as root with
+// `cargo test -p youki-test-fixture -- --ignored`
+#[test]
+#[ignore]
+fn test_run_container() -> Result<()> {
+    let mut spec = minimal_spec(&["sleep", "30"])?;
+    let mut mounts = spec.mounts().clone().unwrap_or_default();
+    for dir in HOST_DIRS.iter().filter(|dir| Path::new(dir).exists()) {
+        mounts.push(
+            MountBuilder::default()
+                .destination(*dir)
+                .typ("bind")
+                .source(fs::canonicalize(dir)?)
+                .options(vec!["rbind".to_owned(), "ro".to_owned()])
+                .build()?,
+        );
+    }
+    spec.set_mounts(Some(mounts));
+
+    let mut linux = spec.linux().clone().unwrap_or_default();
+    let mut resources = linux.resources().clone().unwrap_or_default();
+    resources.set_pids(Some(LinuxPidsBuilder::default().limit(10).build()?));
+    linux.set_resources(Some(resources));
+    spec.set_linux(Some(linux));
+
+    let bundle = Bundle::new("fixture_run", &spec)?;
+    let mut container = TestContainer::run("fixture_run", &bundle)?;
+    container.wait_for_status(ContainerStatus::Running, Duration::from_secs(5))?;
+    assert_cgroup_value(&container.cgroup_path()?, "pids", "pids.max", "10")?;
+
+    container.kill(Signal::SIGKILL)?;
+    container.wait_for_status(ContainerStatus::Stopped, Duration::from_secs(5))?;
+    Ok(())
+}
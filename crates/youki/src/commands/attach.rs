@@ -0,0 +1,247 @@
+//! Contains functionality of the attach command
+use std::{
+    io::{self, Read, Write},
+    net::Shutdown,
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use nix::{
+    sys::{
+        signal::{SigSet, Signal},
+        termios::{self, SetArg, Termios},
+    },
+    unistd,
+};
+
+use libcontainer::attach::{self, Channel};
+
+use crate::commands::load_container;
+
+/// Attach to the stdio of a container that has been created with --attachable
+#[derive(Parser, Debug)]
+pub struct Attach {
+    /// Key sequence to detach from the container, e.g. ctrl-p,ctrl-q
+    #[clap(long, default_value = "ctrl-p,ctrl-q")]
+    pub detach_keys: String,
+    /// Do not forward stdin to the container
+    #[clap(long)]
+    pub no_stdin: bool,
+    #[clap(forbid_empty_values = true, required = true)]
+    pub container_id: String,
+}
+
+pub fn attach(args: Attach, root_path: PathBuf) -> Result<()> {
+    let detach_keys = DetachKeys::parse(&args.detach_keys)?;
+    let container = load_container(root_path, &args.container_id)?;
+    let socket_path = attach::socket_path(&container.root);
+    if !socket_path.exists() {
+        bail!(
+            "container {} is not attachable or has exited",
+            args.container_id
+        );
+    }
+
+    // the path of the socket may exceed the maximum length of a unix socket
+    // path, so it is connected relative to the container directory
+    unistd::chdir(&container.root)?;
+    let stream = UnixStream::connect(attach::ATTACH_SOCKET)
+        .with_context(|| format!("failed to connect to {:?}", socket_path))?;
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+
+    let terminal = container.spec()?.terminal && unistd::isatty(libc::STDIN_FILENO)?;
+    let _raw_mode = if terminal {
+        // SIGWINCH is blocked before any thread is spawned, so that it is
+        // only received by the thread that waits for it
+        let mut mask = SigSet::empty();
+        mask.add(Signal::SIGWINCH);
+        mask.thread_block()?;
+        send_resize(&writer);
+        let resize_writer = Arc::clone(&writer);
+        thread::spawn(move || loop {
+            if mask.wait().is_ok() {
+                send_resize(&resize_writer);
+            }
+        });
+
+        Some(RawMode::enable()?)
+    } else {
+        None
+    };
+
+    if !args.no_stdin {
+        let stdin_writer = Arc::clone(&writer);
+        thread::spawn(move || forward_stdin(stdin_writer, detach_keys, terminal));
+    }
+
+    forward_output(stream)
+}
+
+/// Writes the output of the container until the container exits or the
+/// caller detaches
+fn forward_output(mut stream: UnixStream) -> Result<()> {
+    let mut stdout = io::stdout();
+    let mut stderr = io::stderr();
+    // the connection is shut down on detach, which ends the stream
+    while let Ok(Some((channel, payload))) = attach::read_frame(&mut stream) {
+        match channel {
+            Channel::Stdout => {
+                stdout.write_all(&payload)?;
+                stdout.flush()?;
+            }
+            Channel::Stderr => {
+                stderr.write_all(&payload)?;
+                stderr.flush()?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn forward_stdin(writer: Arc<Mutex<UnixStream>>, mut detach_keys: DetachKeys, terminal: bool) {
+    let mut stdin = io::stdin();
+    let mut buf = [0u8; 4096];
+    loop {
+        let len = match stdin.read(&mut buf) {
+            Ok(0) => {
+                // a terminal stays open, otherwise the container gets EOF
+                if !terminal {
+                    let _ = attach::write_frame(&mut *writer.lock().unwrap(), Channel::Stdin, &[]);
+                }
+                return;
+            }
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => return,
+        };
+
+        let (input, detach) = detach_keys.feed(&buf[..len]);
+        let stream = writer.lock().unwrap();
+        if !input.is_empty() && attach::write_frame(&mut &*stream, Channel::Stdin, &input).is_err()
+        {
+            return;
+        }
+
+        if detach {
+            let _ = stream.shutdown(Shutdown::Both);
+            return;
+        }
+    }
+}
+
+fn send_resize(writer: &Mutex<UnixStream>) {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDIN_FILENO, libc::TIOCGWINSZ, &mut size) } < 0 {
+        return;
+    }
+
+    let payload = attach::resize_payload(size.ws_row, size.ws_col);
+    let _ = attach::write_frame(&mut *writer.lock().unwrap(), Channel::Resize, &payload);
+}
+
+/// Puts the terminal of stdin into raw mode and restores it when dropped
+struct RawMode {
+    original: Termios,
+}
+
+impl RawMode {
+    fn enable() -> Result<Self> {
+        let original = termios::tcgetattr(libc::STDIN_FILENO)?;
+        let mut raw = original.clone();
+        termios::cfmakeraw(&mut raw);
+        termios::tcsetattr(libc::STDIN_FILENO, SetArg::TCSANOW, &raw)?;
+        Ok(Self { original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = termios::tcsetattr(libc::STDIN_FILENO, SetArg::TCSANOW, &self.original);
+    }
+}
+
+/// Detects the detach key sequence in the input
+#[derive(Debug)]
+struct DetachKeys {
+    keys: Vec<u8>,
+    matched: usize,
+}
+
+impl DetachKeys {
+    /// Parses a comma separated list of keys. A key is either a single
+    /// character or ctrl-<character>, e.g. ctrl-p,ctrl-q.
+    fn parse(keys: &str) -> Result<Self> {
+        let keys = keys
+            .split(',')
+            .map(|key| {
+                let bytes = key.as_bytes();
+                match (key.strip_prefix("ctrl-"), bytes.len()) {
+                    (Some(c), _) if c.len() == 1 => {
+                        let c = c.as_bytes()[0].to_ascii_uppercase();
+                        if !(b'@'..=b'_').contains(&c) {
+                            bail!("invalid detach key {}", key);
+                        }
+                        Ok(c & 0x1f)
+                    }
+                    (None, 1) => Ok(bytes[0]),
+                    _ => bail!("invalid detach key {}", key),
+                }
+            })
+            .collect::<Result<Vec<u8>>>()?;
+
+        Ok(Self { keys, matched: 0 })
+    }
+
+    /// Returns the input that has to be forwarded to the container and
+    /// whether the detach sequence has been entered. Input that could be the
+    /// start of the sequence is held back until it is clear that it is not.
+    fn feed(&mut self, input: &[u8]) -> (Vec<u8>, bool) {
+        let mut forward = Vec::with_capacity(input.len());
+        for &b in input {
+            if b != self.keys[self.matched] {
+                forward.extend_from_slice(&self.keys[..self.matched]);
+                self.matched = 0;
+            }
+
+            if b == self.keys[self.matched] {
+                self.matched += 1;
+                if self.matched == self.keys.len() {
+                    self.matched = 0;
+                    return (forward, true);
+                }
+            } else {
+                forward.push(b);
+            }
+        }
+
+        (forward, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detach_keys() -> Result<()> {
+        let mut keys = DetachKeys::parse("ctrl-p,ctrl-q")?;
+        assert_eq!(keys.keys, vec![0x10, 0x11]);
+
+        assert_eq!(keys.feed(b"ls\n"), (b"ls\n".to_vec(), false));
+        assert_eq!(keys.feed(&[b'a', 0x10]), (b"a".to_vec(), false));
+        // the held back key is forwarded when the sequence is not completed
+        assert_eq!(keys.feed(&[b'b']), (vec![0x10, b'b'], false));
+        assert_eq!(keys.feed(&[0x10, 0x10, 0x11]), (vec![0x10], true));
+
+        assert!(DetachKeys::parse("ctrl-1").is_err());
+        assert!(DetachKeys::parse("ab").is_err());
+        assert_eq!(DetachKeys::parse("x")?.keys, b"x".to_vec());
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+//! Contains functionality of the check-spec command
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use oci_spec::runtime::Spec;
+
+use libcontainer::capabilities::{self, CapabilitySets};
+
+/// Check the configuration of a bundle without creating a container
+#[derive(Parser, Debug)]
+pub struct CheckSpec {
+    /// Path to the bundle directory, containing config.json
+    #[clap(long, short, default_value = ".")]
+    pub bundle: PathBuf,
+    /// Print the capability sets the container process ends up with
+    #[clap(long)]
+    pub show_caps: bool,
+}
+
+pub fn check_spec(args: CheckSpec) -> Result<()> {
+    let config_path = args.bundle.join("config.json");
+    let spec = Spec::load(&config_path)
+        .with_context(|| format!("failed to load spec from {:?}", config_path))?;
+    let process = spec.process().as_ref().context("no process in spec")?;
+    println!("{:?} is valid", config_path);
+
+    if args.show_caps {
+        let report = capabilities::compute_capabilities(process);
+        println!(
+            "{:<18}uid={} gid={}",
+            "User",
+            process.user().uid(),
+            process.user().gid()
+        );
+        println!(
+            "{:<18}{}",
+            "NoNewPrivileges",
+            process.no_new_privileges().unwrap_or_default()
+        );
+        print_sets("Before exec", &report.before_exec);
+        print_sets("After exec", &report.after_exec);
+        for warning in &report.warnings {
+            println!("warning: {}", warning);
+        }
+    }
+
+    Ok(())
+}
+
+fn print_sets(title: &str, sets: &CapabilitySets) {
+    println!("{}", title);
+    for (name, set) in [
+        ("Bounding", &sets.bounding),
+        ("Effective", &sets.effective),
+        ("Permitted", &sets.permitted),
+        ("Inheritable", &sets.inheritable),
+        ("Ambient", &sets.ambient),
+    ] {
+        println!("  {:<16}{}", name, capabilities::format_set(set));
+    }
+}
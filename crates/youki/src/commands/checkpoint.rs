@@ -0,0 +1,32 @@
+//! Contains functionality of checkpoint container command
+use crate::commands::load_container;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+use libcontainer::container::CheckpointOptions;
+use liboci_cli::Checkpoint;
+
+// Checkpointing dumps the state of all processes in the container to image
+// files with criu, from which the container can be restored later on.
+// For more information see:
+// https://criu.org/Checkpoint/Restore
+pub fn checkpoint(args: Checkpoint, root_path: PathBuf) -> Result<()> {
+    log::debug!("start checkpointing container {}", args.container_id);
+    let mut container = load_container(root_path, &args.container_id)?;
+    let opts = CheckpointOptions {
+        image_path: args.image_path,
+        work_path: args.work_path,
+        parent_path: args.parent_path,
+        track_mem: false,
+        leave_running: args.leave_running,
+        tcp_established: args.tcp_established,
+        ext_unix_sk: args.ext_unix_sk,
+        shell_job: args.shell_job,
+        file_locks: args.file_locks,
+    };
+
+    container
+        .checkpoint(&opts)
+        .with_context(|| format!("failed to checkpoint container {}", args.container_id))
+}
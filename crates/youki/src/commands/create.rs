@@ -1,9 +1,45 @@
 //! Handles the creation of a new container
-use anyhow::Result;
-use std::path::PathBuf;
+use anyhow::{Context, Result};
+use clap::Parser;
+use std::path::{Path, PathBuf};
 
-use libcontainer::{container::builder::ContainerBuilder, syscall::syscall::create_syscall};
-use liboci_cli::Create;
+use libcgroups::{common::ControllerOpt, dry_run};
+use libcontainer::{
+    container::builder::ContainerBuilder, rootless::rootless_required,
+    syscall::syscall::create_syscall, utils,
+};
+use oci_spec::runtime::Spec;
+
+use crate::commands::print_cgroup_writes;
+
+/// Create a container
+#[derive(Parser, Debug)]
+pub struct Create {
+    #[clap(flatten)]
+    pub base: liboci_cli::Create,
+    #[clap(flatten)]
+    pub opts: CreateOpts,
+}
+
+/// Options of youki for creating a container, which are accepted by create and run
+#[derive(Parser, Debug)]
+pub struct CreateOpts {
+    /// Generate a unique SELinux MCS label if SELinux is enabled and the spec does not specify one
+    #[clap(long)]
+    pub selinux_mcs: bool,
+    /// Warn instead of failing if the AppArmor profile or SELinux label of the spec cannot be applied
+    #[clap(long)]
+    pub permissive_lsm: bool,
+    /// Report the progress of the phases of the creation on stderr in the given format
+    #[clap(long, possible_values = &["json"])]
+    pub progress: Option<String>,
+    /// Print the cgroup files and values the resources of the spec would be applied with, without creating the container
+    #[clap(long)]
+    pub dry_run_cgroups: bool,
+    /// Serve the stdio of the container on a unix socket in the state directory, so it can be attached to later
+    #[clap(long)]
+    pub attachable: bool,
+}
 
 // One thing to note is that in the end, container is just another process in Linux
 // it has specific/different control group, namespace, using which program executing in it
@@ -11,15 +47,70 @@ use liboci_cli::Create;
 // it is running, it is just another process, and has attributes such as pid, file descriptors, etc.
 // associated with it like any other process.
 pub fn create(args: Create, root_path: PathBuf, systemd_cgroup: bool) -> Result<()> {
+    let (base, opts) = (args.base, args.opts);
+    if opts.dry_run_cgroups {
+        return dry_run_cgroups(&base.bundle, &base.container_id, systemd_cgroup);
+    }
+
     let syscall = create_syscall();
-    ContainerBuilder::new(args.container_id.clone(), syscall.as_ref())
-        .with_pid_file(args.pid_file.as_ref())
-        .with_console_socket(args.console_socket.as_ref())
+    ContainerBuilder::new(base.container_id.clone(), syscall.as_ref())
+        .with_pid_file(base.pid_file.as_ref())
+        .with_console_socket(base.console_socket.as_ref())
         .with_root_path(root_path)
-        .with_preserved_fds(args.preserve_fds)
-        .as_init(&args.bundle)
+        .with_preserved_fds(base.preserve_fds)
+        .as_init(&base.bundle)
         .with_systemd(systemd_cgroup)
+        .with_selinux_mcs(opts.selinux_mcs)
+        .with_permissive_lsm(opts.permissive_lsm)
+        .with_progress(opts.progress.is_some())
+        .with_attachable(opts.attachable)
         .build()?;
 
     Ok(())
 }
+
+/// Prints how the resources of the spec would be applied to the cgroup of the
+/// container, without creating the container
+pub(super) fn dry_run_cgroups(
+    bundle: &Path,
+    container_id: &str,
+    systemd_cgroup: bool,
+) -> Result<()> {
+    let config_path = bundle.join("config.json");
+    let spec = Spec::load(&config_path)
+        .with_context(|| format!("failed to load spec from {:?}", config_path))?;
+    let linux = spec.linux().as_ref().context("no linux in spec")?;
+    let resources = match linux.resources() {
+        Some(resources) => resources,
+        None => {
+            print_cgroup_writes(&[]);
+            return Ok(());
+        }
+    };
+
+    let cgroups_path = utils::get_cgroup_path(linux.cgroups_path(), container_id);
+    // rootless containers always use the systemd cgroup manager, see the
+    // container builder
+    let cmanager = libcgroups::common::create_cgroup_manager(
+        &cgroups_path,
+        systemd_cgroup || rootless_required(),
+        container_id,
+    )?;
+    // the same resources as for the creation of the container
+    let resources = libcgroups::common::with_device_rules(resources);
+    let writes = dry_run::dry_run(|| {
+        cmanager.apply(&ControllerOpt {
+            resources: &resources,
+            freezer_state: None,
+            oom_score_adj: None,
+            disable_oom_killer: resources
+                .memory()
+                .as_ref()
+                .and_then(|memory| memory.disable_oom_killer())
+                .unwrap_or(false),
+        })
+    })?;
+    print_cgroup_writes(&writes);
+
+    Ok(())
+}
@@ -2,11 +2,29 @@ use crate::commands::load_container;
 use anyhow::{Context, Result};
 use std::path::PathBuf;
 
+use libcontainer::container::{state::State, Container, DEFAULT_ORPHAN_MIN_AGE};
 use liboci_cli::Delete;
 
-pub fn delete(args: Delete, root_path: PathBuf) -> Result<()> {
+pub fn delete(args: Delete, root_path: PathBuf, systemd_cgroup: bool) -> Result<()> {
     log::debug!("start deleting {}", args.container_id);
-    let mut container = load_container(root_path, &args.container_id)?;
+    Container::validate_id(&args.container_id)?;
+    let container_root = root_path.join(&args.container_id);
+    let mut container = if container_root.is_dir() && !State::file_path(&container_root).exists() {
+        // the create of the container has been interrupted before the state was saved
+        log::warn!(
+            "container {} has no state, clean up the leftovers of its creation",
+            args.container_id
+        );
+        Container::load_orphan(
+            &root_path,
+            &args.container_id,
+            systemd_cgroup,
+            DEFAULT_ORPHAN_MIN_AGE,
+        )?
+    } else {
+        load_container(root_path, &args.container_id)?
+    };
+
     container
         .delete(args.force)
         .with_context(|| format!("failed to delete container {}", args.container_id))
@@ -1,14 +1,368 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
 use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::{thread, time::Duration};
 
 use anyhow::{Context, Result};
+use clap::Parser;
+use nix::sys::signal::{SigSet, Signal};
+use tabwriter::TabWriter;
 
-use liboci_cli::Events;
+use libcgroups::{
+    stats::{BlkioStats, Bytes, Nanoseconds, Stats},
+    stats_delta::{StatsDelta, StatsSampler},
+};
+use libcontainer::container::{runtime_metrics::RuntimeMetrics, Container, ContainerStatus};
 
-use crate::commands::load_container;
+use crate::commands::{load_container, print_formatted};
+
+/// Number of devices with the most transferred bytes that are shown in the summary
+const TOP_IO_DEVICES: usize = 3;
+
+/// Show resource statistics for the container
+#[derive(Parser, Debug)]
+pub struct Events {
+    #[clap(flatten)]
+    pub base: liboci_cli::Events,
+    #[clap(flatten)]
+    pub opts: EventsOpts,
+}
+
+/// Options of youki for showing the events of a container
+#[derive(Parser, Debug)]
+pub struct EventsOpts {
+    /// Display the container stats as a human readable summary instead of json
+    #[clap(long)]
+    pub human: bool,
+    /// format of the output: json, yaml or compact (default: "json")
+    #[clap(long, default_value = "json")]
+    pub format: String,
+    /// Display the latencies of the operations of the runtime on the container
+    /// instead of the resource statistics
+    #[clap(long)]
+    pub runtime_metrics: bool,
+    /// Wait until the container reaches the status, e.g. running or stopped,
+    /// instead of displaying stats
+    #[clap(long)]
+    pub wait: Option<String>,
+}
 
 pub fn events(args: Events, root_path: PathBuf) -> Result<()> {
-    let mut container = load_container(root_path, &args.container_id)?;
-    container
-        .events(args.interval, args.stats)
-        .with_context(|| format!("failed to get events from container {}", args.container_id))
+    let (base, opts) = (args.base, args.opts);
+    let mut container = load_container(root_path, &base.container_id)?;
+    if let Some(status) = &opts.wait {
+        let status: ContainerStatus = status.parse()?;
+        return container.wait_for_status(status, None).with_context(|| {
+            format!(
+                "failed to wait for container {} to be {}",
+                base.container_id, status
+            )
+        });
+    }
+
+    let shutdown = shutdown_signal()?;
+    if opts.runtime_metrics {
+        return runtime_metrics(&container, &base, &opts, &shutdown).with_context(|| {
+            format!(
+                "failed to get runtime metrics of container {}",
+                base.container_id
+            )
+        });
+    }
+
+    if !opts.human && opts.format == "json" {
+        return container
+            .events(base.interval, base.stats, &shutdown)
+            .with_context(|| format!("failed to get events from container {}", base.container_id));
+    }
+
+    formatted_events(&mut container, &base, &opts, &shutdown)
+        .with_context(|| format!("failed to get events from container {}", base.container_id))
+}
+
+/// Blocks SIGTERM and SIGINT and returns a receiver that gets a message once
+/// one of them arrives, so that the loops that print the stats end cleanly
+fn shutdown_signal() -> Result<Receiver<()>> {
+    // the signals are blocked before the thread is spawned, so that they are
+    // only received by the thread that waits for them
+    let mut mask = SigSet::empty();
+    mask.add(Signal::SIGTERM);
+    mask.add(Signal::SIGINT);
+    mask.thread_block()?;
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        if mask.wait().is_ok() {
+            let _ = sender.send(());
+        }
+    });
+    Ok(receiver)
+}
+
+/// Waits for the next interval and returns false if the loop should end
+fn wait_interval(shutdown: &Receiver<()>, interval: Duration) -> bool {
+    matches!(
+        shutdown.recv_timeout(interval),
+        Err(RecvTimeoutError::Timeout)
+    )
+}
+
+fn formatted_events(
+    container: &mut Container,
+    base: &liboci_cli::Events,
+    opts: &EventsOpts,
+    shutdown: &Receiver<()>,
+) -> Result<()> {
+    let mut sampler = StatsSampler::default();
+    loop {
+        let mut stats = container.stats()?;
+        if let Err(err) = stats.blkio.resolve_device_names() {
+            log::warn!("failed to resolve the names of block devices: {:?}", err);
+        }
+        if opts.human {
+            let delta = sampler.sample(&stats);
+            write_summary(io::stdout(), &stats, delta.as_ref())?;
+            println!();
+        } else {
+            print_formatted(&stats, &opts.format)?;
+        }
+
+        if base.stats || !wait_interval(shutdown, base.interval) {
+            return Ok(());
+        }
+
+        container.refresh_status()?;
+        if !matches!(
+            container.status(),
+            ContainerStatus::Running | ContainerStatus::Paused
+        ) {
+            return Ok(());
+        }
+    }
+}
+
+fn runtime_metrics(
+    container: &Container,
+    base: &liboci_cli::Events,
+    opts: &EventsOpts,
+    shutdown: &Receiver<()>,
+) -> Result<()> {
+    loop {
+        let metrics = container.runtime_metrics()?;
+        if opts.human {
+            write_runtime_metrics(io::stdout(), &metrics)?;
+            println!();
+        } else {
+            print_formatted(&metrics, &opts.format)?;
+        }
+
+        if base.stats || !wait_interval(shutdown, base.interval) {
+            return Ok(());
+        }
+    }
+}
+
+/// Renders the runtime metrics as a table with one operation per line
+fn write_runtime_metrics<W: Write>(writer: W, metrics: &RuntimeMetrics) -> Result<()> {
+    let mut tab_writer = TabWriter::new(writer);
+    writeln!(tab_writer, "OPERATION\tCOUNT\tLAST\tMEAN\tMAX")?;
+    for (operation, op_metrics) in &metrics.operations {
+        writeln!(
+            tab_writer,
+            "{}\t{}\t{}\t{}\t{}",
+            operation,
+            op_metrics.count,
+            Nanoseconds(op_metrics.last_us * 1000),
+            Nanoseconds(op_metrics.mean_us() * 1000),
+            Nanoseconds(op_metrics.max_us * 1000),
+        )?;
+    }
+
+    tab_writer.flush()?;
+    Ok(())
+}
+
+/// Renders a compact summary of the most important statistics, including the
+/// rates since the previous summary if there is one
+fn write_summary<W: Write>(writer: W, stats: &Stats, delta: Option<&StatsDelta>) -> Result<()> {
+    let mut tab_writer = TabWriter::new(writer);
+
+    let memory = &stats.memory.memory;
+    write!(tab_writer, "MEMORY\t{} / {}", memory.usage, memory.limit)?;
+    if memory.limit.0 != 0 && memory.limit.0 != u64::MAX {
+        let percent = memory.usage.0 as f64 / memory.limit.0 as f64 * 100.0;
+        write!(tab_writer, " ({:.1}%)", percent)?;
+    }
+    writeln!(tab_writer)?;
+
+    let usage = &stats.cpu.usage;
+    writeln!(
+        tab_writer,
+        "CPU\t{} (user {}, system {})",
+        usage.usage_total, usage.usage_user, usage.usage_kernel
+    )?;
+
+    if let Some(delta) = delta {
+        writeln!(
+            tab_writer,
+            "RATE\tcpu {:.1}%, io read {}/s, io write {}/s",
+            delta.cpu_percent,
+            Bytes(delta.io_read_bytes_per_second as u64),
+            Bytes(delta.io_write_bytes_per_second as u64)
+        )?;
+    }
+
+    let throttling = &stats.cpu.throttling;
+    let throttled_percent = if throttling.periods != 0 {
+        throttling.throttled_periods as f64 / throttling.periods as f64 * 100.0
+    } else {
+        0.0
+    };
+    writeln!(
+        tab_writer,
+        "THROTTLED\t{:.2}% ({} of {} periods, {})",
+        throttled_percent,
+        throttling.throttled_periods,
+        throttling.periods,
+        throttling.throttled_time
+    )?;
+
+    let pids = &stats.pids;
+    if pids.limit != 0 {
+        writeln!(tab_writer, "PIDS\t{} / {}", pids.current, pids.limit)?;
+    } else {
+        writeln!(tab_writer, "PIDS\t{}", pids.current)?;
+    }
+
+    for ((major, minor), (read, write)) in top_io_devices(&stats.blkio, TOP_IO_DEVICES) {
+        let name = stats
+            .blkio
+            .service_bytes
+            .iter()
+            .find(|stat| (stat.major, stat.minor) == (major, minor))
+            .and_then(|stat| stat.device_name.as_deref());
+        match name {
+            Some(name) => write!(tab_writer, "IO {} ({}:{})", name, major, minor)?,
+            None => write!(tab_writer, "IO {}:{}", major, minor)?,
+        }
+        writeln!(tab_writer, "\tread {} / write {}", read, write)?;
+    }
+
+    tab_writer.flush()?;
+    Ok(())
+}
+
+/// Returns the devices with the most transferred bytes together with the number of
+/// bytes that have been read from and written to them
+fn top_io_devices(blkio: &BlkioStats, count: usize) -> Vec<((u64, u64), (Bytes, Bytes))> {
+    let mut devices: BTreeMap<(u64, u64), (Bytes, Bytes)> = BTreeMap::new();
+    for stat in &blkio.service_bytes {
+        let (read, write) = devices.entry((stat.major, stat.minor)).or_default();
+        // cgroup v1 reports the operation types capitalized, cgroup v2 in lowercase
+        match stat.op_type.as_deref().map(str::to_lowercase).as_deref() {
+            Some("read") => read.0 += stat.value,
+            Some("write") => write.0 += stat.value,
+            _ => continue,
+        }
+    }
+
+    let mut devices: Vec<_> = devices.into_iter().collect();
+    devices.sort_by_key(|(_, (read, write))| std::cmp::Reverse(read.0 + write.0));
+    devices.truncate(count);
+    devices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libcgroups::stats::BlkioDeviceStat;
+
+    fn device_stat(major: u64, op_type: &str, value: u64) -> BlkioDeviceStat {
+        BlkioDeviceStat {
+            major,
+            minor: 0,
+            op_type: Some(op_type.to_owned().into()),
+            value,
+            device_name: None,
+        }
+    }
+
+    #[test]
+    fn test_top_io_devices() {
+        let blkio = BlkioStats {
+            service_bytes: vec![
+                device_stat(8, "Read", 100),
+                device_stat(8, "Write", 100),
+                device_stat(8, "Total", 200),
+                device_stat(9, "read", 1000),
+                device_stat(10, "write", 10),
+                device_stat(11, "write", 50),
+            ],
+            ..Default::default()
+        };
+
+        let devices = top_io_devices(&blkio, 3);
+        assert_eq!(
+            devices,
+            vec![
+                ((9, 0), (Bytes(1000), Bytes(0))),
+                ((8, 0), (Bytes(100), Bytes(100))),
+                ((11, 0), (Bytes(0), Bytes(50))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_runtime_metrics() -> Result<()> {
+        let mut metrics = RuntimeMetrics::default();
+        metrics.observe("create", Duration::from_millis(120));
+        metrics.observe("hook.prestart", Duration::from_millis(3));
+
+        let mut output = Vec::new();
+        write_runtime_metrics(&mut output, &metrics)?;
+        let output = String::from_utf8(output)?;
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("OPERATION"));
+        assert!(lines[1].starts_with("create"));
+        assert!(lines[1].contains("120.00ms"));
+        assert!(lines[2].starts_with("hook.prestart"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_summary() -> Result<()> {
+        let mut stats = Stats::default();
+        stats.memory.memory.usage = Bytes(512 * 1024 * 1024);
+        stats.memory.memory.limit = Bytes(2 * 1024 * 1024 * 1024);
+        stats.cpu.usage.usage_total = Nanoseconds(1_500_000_000);
+        stats.cpu.throttling.periods = 200;
+        stats.cpu.throttling.throttled_periods = 5;
+        stats.pids.current = 3;
+
+        let mut output = Vec::new();
+        write_summary(&mut output, &stats, None)?;
+        let output = String::from_utf8(output)?;
+        assert!(!output.contains("RATE"));
+
+        assert!(output.contains("512.00 MiB / 2.00 GiB (25.0%)"));
+        assert!(output.contains("1.50s"));
+        assert!(output.contains("2.50% (5 of 200 periods"));
+        assert!(output
+            .lines()
+            .any(|line| line.starts_with("PIDS") && line.ends_with(" 3")));
+
+        let delta = StatsDelta {
+            cpu_percent: 150.0,
+            io_read_bytes_per_second: 2048.0,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        write_summary(&mut output, &stats, Some(&delta))?;
+        let output = String::from_utf8(output)?;
+        assert!(output.contains("cpu 150.0%, io read 2.00 KiB/s, io write 0 B/s"));
+        Ok(())
+    }
 }
@@ -6,7 +6,7 @@ use liboci_cli::Exec;
 
 pub fn exec(args: Exec, root_path: PathBuf) -> Result<()> {
     let syscall = create_syscall();
-    ContainerBuilder::new(args.container_id.clone(), syscall.as_ref())
+    let mut builder = ContainerBuilder::new(args.container_id.clone(), syscall.as_ref())
         .with_root_path(root_path)
         .with_console_socket(args.console_socket.as_ref())
         .with_pid_file(args.pid_file.as_ref())
@@ -16,6 +16,12 @@ pub fn exec(args: Exec, root_path: PathBuf) -> Result<()> {
         .with_process(args.process.as_ref())
         .with_no_new_privs(args.no_new_privs)
         .with_process(args.process.as_ref())
-        .with_container_args(args.command.clone())
-        .build()
+        .with_capabilities(args.cap.clone())
+        .with_additional_gids(args.additional_gids.clone())
+        .with_container_args(args.command.clone());
+    if let Some((uid, gid)) = args.user {
+        builder = builder.with_user(uid, gid);
+    }
+
+    builder.build()
 }
@@ -0,0 +1,242 @@
+//! Contains functionality of the features command
+use std::collections::HashMap;
+
+use anyhow::Result;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+use libcontainer::experimental::Feature;
+
+use super::print_formatted;
+
+/// Show the features supported by youki, as described in the features
+/// document of the runtime spec
+#[derive(Parser, Debug)]
+pub struct Features {
+    /// format of the output: json, yaml or compact (default: "json")
+    #[clap(short, long, default_value = "json")]
+    pub format: String,
+}
+
+/// Features supported by the runtime
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct RuntimeFeatures {
+    oci_version_min: String,
+    oci_version_max: String,
+    hooks: Vec<String>,
+    mount_options: Vec<String>,
+    linux: LinuxFeatures,
+    annotations: HashMap<String, String>,
+}
+
+/// Linux specific features supported by the runtime
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct LinuxFeatures {
+    namespaces: Vec<String>,
+    cgroup: CgroupFeatures,
+    seccomp: SeccompFeatures,
+    apparmor: EnabledFeature,
+    selinux: EnabledFeature,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct CgroupFeatures {
+    v1: bool,
+    v2: bool,
+    systemd: bool,
+    systemd_user: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct SeccompFeatures {
+    enabled: bool,
+    actions: Vec<String>,
+    operators: Vec<String>,
+    archs: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct EnabledFeature {
+    enabled: bool,
+}
+
+// hooks that are run by youki
+const HOOKS: &[&str] = &[
+    "prestart",
+    "createRuntime",
+    "createContainer",
+    "startContainer",
+    "poststart",
+    "poststop",
+];
+
+// mount options that are understood by the parsing of the mounts in
+// libcontainer, all other options are passed to the file system as data
+const MOUNT_OPTIONS: &[&str] = &[
+    "async",
+    "atime",
+    "bind",
+    "defaults",
+    "dev",
+    "diratime",
+    "dirsync",
+    "exec",
+    "mand",
+    "noatime",
+    "nodev",
+    "nodiratime",
+    "noexec",
+    "nomand",
+    "norelatime",
+    "nostrictatime",
+    "nosuid",
+    "private",
+    "ratime",
+    "rbind",
+    "rdev",
+    "rdiratime",
+    "relatime",
+    "remount",
+    "rexec",
+    "rnoatime",
+    "rnodev",
+    "rnodiratime",
+    "rnoexec",
+    "rnorelatime",
+    "rnostrictatime",
+    "rnosuid",
+    "rnosymfollow",
+    "ro",
+    "rprivate",
+    "rrelatime",
+    "rro",
+    "rrw",
+    "rshared",
+    "rslave",
+    "rstrictatime",
+    "rsuid",
+    "rsymfollow",
+    "runbindable",
+    "rw",
+    "shared",
+    "slave",
+    "strictatime",
+    "suid",
+    "sync",
+    "tmpcopyup",
+    "unbindable",
+];
+
+const NAMESPACES: &[&str] = &["cgroup", "ipc", "mount", "network", "pid", "user", "uts"];
+
+const SECCOMP_ACTIONS: &[&str] = &[
+    "SCMP_ACT_ALLOW",
+    "SCMP_ACT_ERRNO",
+    "SCMP_ACT_KILL",
+    "SCMP_ACT_KILL_PROCESS",
+    "SCMP_ACT_LOG",
+    "SCMP_ACT_NOTIFY",
+    "SCMP_ACT_TRACE",
+    "SCMP_ACT_TRAP",
+];
+
+const SECCOMP_OPERATORS: &[&str] = &[
+    "SCMP_CMP_EQ",
+    "SCMP_CMP_GE",
+    "SCMP_CMP_GT",
+    "SCMP_CMP_LE",
+    "SCMP_CMP_LT",
+    "SCMP_CMP_MASKED_EQ",
+    "SCMP_CMP_NE",
+];
+
+const SECCOMP_ARCHS: &[&str] = &[
+    "SCMP_ARCH_AARCH64",
+    "SCMP_ARCH_ARM",
+    "SCMP_ARCH_MIPS",
+    "SCMP_ARCH_MIPS64",
+    "SCMP_ARCH_MIPS64N32",
+    "SCMP_ARCH_MIPSEL",
+    "SCMP_ARCH_MIPSEL64",
+    "SCMP_ARCH_MIPSEL64N32",
+    "SCMP_ARCH_PPC",
+    "SCMP_ARCH_PPC64",
+    "SCMP_ARCH_PPC64LE",
+    "SCMP_ARCH_S390",
+    "SCMP_ARCH_S390X",
+    "SCMP_ARCH_X32",
+    "SCMP_ARCH_X86",
+    "SCMP_ARCH_X86_64",
+];
+
+fn to_strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|v| v.to_string()).collect()
+}
+
+fn runtime_features() -> RuntimeFeatures {
+    let mut annotations = HashMap::new();
+    annotations.insert(
+        "org.youki.version".to_owned(),
+        env!("CARGO_PKG_VERSION").to_owned(),
+    );
+    // the experimental features that can be enabled per container
+    annotations.insert(
+        "org.youki.experimental".to_owned(),
+        Feature::ALL
+            .iter()
+            .map(|feature| feature.name())
+            .collect::<Vec<_>>()
+            .join(","),
+    );
+
+    RuntimeFeatures {
+        oci_version_min: "1.0.0".to_owned(),
+        oci_version_max: "1.0.2".to_owned(),
+        hooks: to_strings(HOOKS),
+        mount_options: to_strings(MOUNT_OPTIONS),
+        linux: LinuxFeatures {
+            namespaces: to_strings(NAMESPACES),
+            cgroup: CgroupFeatures {
+                v1: true,
+                v2: true,
+                systemd: true,
+                systemd_user: true,
+            },
+            seccomp: SeccompFeatures {
+                enabled: true,
+                actions: to_strings(SECCOMP_ACTIONS),
+                operators: to_strings(SECCOMP_OPERATORS),
+                archs: to_strings(SECCOMP_ARCHS),
+            },
+            apparmor: EnabledFeature { enabled: true },
+            selinux: EnabledFeature { enabled: true },
+        },
+        annotations,
+    }
+}
+
+pub fn features(args: Features) -> Result<()> {
+    print_formatted(&runtime_features(), &args.format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_features_formats() -> Result<()> {
+        let features = runtime_features();
+
+        let json = serde_json::to_string(&features)?;
+        assert!(json.contains("\"ociVersionMin\":\"1.0.0\""));
+        assert!(json.contains("\"systemdUser\":true"));
+        assert_eq!(serde_json::from_str::<RuntimeFeatures>(&json)?, features);
+
+        let yaml = serde_yaml::to_string(&features)?;
+        assert_eq!(serde_yaml::from_str::<RuntimeFeatures>(&yaml)?, features);
+        Ok(())
+    }
+}
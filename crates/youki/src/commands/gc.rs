@@ -0,0 +1,44 @@
+//! Contains functionality of the gc command
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+
+use libcontainer::container::Container;
+
+/// Clean up containers whose creation has been interrupted
+#[derive(Parser, Debug)]
+pub struct Gc {
+    /// Only clean up containers whose directory has not been modified for the given number of seconds
+    #[clap(long, default_value = "60")]
+    pub min_age: u64,
+    /// Print the containers that would be cleaned up without deleting them
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
+pub fn gc(args: Gc, root_path: PathBuf, systemd_cgroup: bool) -> Result<()> {
+    let orphans =
+        Container::find_orphans(root_path, Duration::from_secs(args.min_age), systemd_cgroup)
+            .context("failed to find orphaned containers")?;
+
+    let mut failed = 0;
+    for mut orphan in orphans {
+        println!("{}", orphan.id());
+        if args.dry_run {
+            continue;
+        }
+
+        if let Err(err) = orphan.delete(false) {
+            log::error!("failed to clean up container {}: {:?}", orphan.id(), err);
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        bail!("failed to clean up {} containers", failed);
+    }
+
+    Ok(())
+}
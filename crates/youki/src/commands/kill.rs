@@ -10,5 +10,9 @@ use liboci_cli::Kill;
 pub fn kill(args: Kill, root_path: PathBuf) -> Result<()> {
     let mut container = load_container(root_path, &args.container_id)?;
     let signal: Signal = args.signal.as_str().try_into()?;
-    container.kill(signal)
+    if args.all {
+        container.kill_all(signal)
+    } else {
+        container.kill(signal)
+    }
 }
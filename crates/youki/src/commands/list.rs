@@ -8,32 +8,42 @@ use anyhow::Result;
 use chrono::{DateTime, Local};
 use tabwriter::TabWriter;
 
-use libcontainer::container::{state::State, Container};
+use libcontainer::container::{state::State, Container, ContainerSummary};
 use liboci_cli::List;
 
+use crate::commands::print_formatted;
+
 /// lists all existing containers
-pub fn list(_: List, root_path: PathBuf) -> Result<()> {
+pub fn list(args: List, root_path: PathBuf) -> Result<()> {
     let root_path = fs::canonicalize(root_path)?;
     let mut content = String::new();
+    let mut states = Vec::new();
     // all containers' data is stored in their respective dir in root directory
     // so we iterate through each and print the various info
     for container_dir in fs::read_dir(root_path)? {
         let container_dir = container_dir?.path();
         let state_file = State::file_path(&container_dir);
-        if !state_file.exists() {
+        if !Container::is_container_dir(&container_dir) || !state_file.exists() {
+            continue;
+        }
+
+        if args.format != "table" {
+            states.push(Container::load(container_dir)?.state);
             continue;
         }
 
-        let container = Container::load(container_dir)?;
-        let pid = if let Some(pid) = container.pid() {
+        // the table only needs the summary of the container, which is much
+        // smaller than its state
+        let summary = ContainerSummary::load(&container_dir)?;
+        let pid = if let Some(pid) = summary.pid {
             pid.to_string()
         } else {
             "".to_owned()
         };
 
-        let user_name = container.creator().unwrap_or_default();
+        let user_name = summary.creator_name().unwrap_or_default();
 
-        let created = if let Some(utc) = container.created() {
+        let created = if let Some(utc) = summary.created {
             let local: DateTime<Local> = DateTime::from(utc);
             local.to_rfc3339_opts(chrono::SecondsFormat::Secs, false)
         } else {
@@ -42,15 +52,19 @@ pub fn list(_: List, root_path: PathBuf) -> Result<()> {
 
         content.push_str(&format!(
             "{}\t{}\t{}\t{}\t{}\t{}\n",
-            container.id(),
+            summary.id,
             pid,
-            container.status(),
-            container.bundle().display(),
+            summary.status,
+            summary.bundle.display(),
             created,
             user_name.to_string_lossy()
         ));
     }
 
+    if args.format != "table" {
+        return print_formatted(&states, &args.format);
+    }
+
     let mut tab_writer = TabWriter::new(io::stdout());
     writeln!(&mut tab_writer, "ID\tPID\tSTATUS\tBUNDLE\tCREATED\tCREATOR")?;
     write!(&mut tab_writer, "{}", content)?;
@@ -0,0 +1,368 @@
+//! Contains functionality of the metrics command, which exports the resource
+//! statistics of the containers, either served in the text format of
+//! Prometheus or pushed to an OpenTelemetry collector over OTLP
+mod otlp;
+mod prometheus;
+
+use std::{
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+
+use libcgroups::{
+    stats::{Bytes, Nanoseconds, Stats},
+    stats_cache::StatsCache,
+};
+use libcontainer::container::{state::State, Container};
+
+/// Export the resource statistics of all running and paused containers as
+/// metrics
+#[derive(Parser, Debug)]
+pub struct Metrics {
+    /// Address to listen on for Prometheus scrapes of /metrics
+    #[clap(long, default_value = "127.0.0.1:9100")]
+    pub listen: SocketAddr,
+    /// Push the metrics to the OTLP/HTTP endpoint of an OpenTelemetry
+    /// collector instead of serving them, e.g. http://127.0.0.1:4318
+    #[clap(long)]
+    pub otlp_endpoint: Option<String>,
+    /// Interval in seconds in which the metrics are pushed over OTLP
+    #[clap(long, default_value = "10")]
+    pub interval: u64,
+    /// Time in seconds for which the statistics are reused by further scrapes,
+    /// so that several Prometheus servers do not read them again (0 disables
+    /// it). Changes of the state of a container take effect immediately.
+    #[clap(long, default_value = "1")]
+    pub cache_ttl: u64,
+    /// Annotation of the containers that is added as label to their metrics,
+    /// e.g. io.kubernetes.pod.name. Can be given multiple times.
+    #[clap(long = "annotation", number_of_values = 1)]
+    pub annotations: Vec<String>,
+}
+
+pub fn metrics(args: Metrics, root_path: PathBuf) -> Result<()> {
+    let root_path = fs::canonicalize(&root_path)
+        .with_context(|| format!("failed to canonicalize {}", root_path.display()))?;
+
+    let endpoint = match &args.otlp_endpoint {
+        Some(endpoint) => otlp::Endpoint::parse(endpoint)?,
+        None => {
+            let cache = StatsCache::new(Duration::from_secs(args.cache_ttl));
+            return prometheus::serve(args.listen, &root_path, &args.annotations, &cache);
+        }
+    };
+
+    log::info!("pushing metrics to {} every {}s", endpoint, args.interval);
+    loop {
+        // a collector that is down is not fatal, the next push may succeed
+        let result = collect(&root_path, &args.annotations)
+            .and_then(|containers| endpoint.push(&otlp::export_request(&containers)));
+        if let Err(err) = result {
+            log::warn!("failed to push metrics to {}: {:?}", endpoint, err);
+        }
+
+        thread::sleep(Duration::from_secs(args.interval));
+    }
+}
+
+/// Statistics of a container together with the information its metrics are
+/// labeled with
+struct ContainerMetrics {
+    id: String,
+    /// Selected annotations of the container that it has set
+    annotations: Vec<(String, String)>,
+    created: Option<DateTime<Utc>>,
+    stats: Stats,
+}
+
+fn collect(root_path: &Path, annotations: &[String]) -> Result<Vec<ContainerMetrics>> {
+    // the server outlives changes of the cgroup mounts
+    libcgroups::v1::util::refresh_cgroup_info();
+    let mut containers: Vec<ContainerMetrics> = Container::stats_all(root_path)?
+        .into_iter()
+        .map(|(id, stats)| {
+            // the container may have been deleted since its stats were read
+            let state = State::load(&root_path.join(&id)).ok();
+            let created = state.as_ref().and_then(|state| state.created);
+            let container_annotations = state
+                .and_then(|state| state.annotations)
+                .unwrap_or_default();
+            let annotations = annotations
+                .iter()
+                .filter_map(|key| Some((key.clone(), container_annotations.get(key)?.clone())))
+                .collect();
+            ContainerMetrics {
+                id,
+                annotations,
+                created,
+                stats,
+            }
+        })
+        .collect();
+    containers.sort_by(|a, b| a.id.cmp(&b.id));
+
+    Ok(containers)
+}
+
+/// Returns the latest modification of the root path and of the states of the
+/// containers in it. It changes when a container is created or deleted, or
+/// when its state changes, e.g. because it is paused by another process.
+fn state_stamp(root_path: &Path) -> Option<SystemTime> {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let containers = fs::read_dir(root_path).ok()?;
+    containers
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| Container::is_container_dir(path))
+        .filter_map(|path| modified(&State::file_path(&path)))
+        .chain(modified(root_path))
+        .max()
+}
+
+/// Unit of the values of a metric
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unit {
+    Seconds,
+    Bytes,
+    Count,
+}
+
+/// Sample of a metric with the labels that are added to the ones of the container
+type Sample = (Vec<(&'static str, String)>, f64);
+
+/// Metric that is exported for every container. The exporters derive the names
+/// from `name` according to their conventions, e.g. cpu_usage becomes
+/// youki_container_cpu_usage_seconds_total for Prometheus and
+/// container.cpu.usage for OpenTelemetry.
+struct Family {
+    name: &'static str,
+    help: &'static str,
+    unit: Unit,
+    /// Counters only ever increase, all other metrics are gauges
+    counter: bool,
+    samples: fn(&Stats) -> Vec<Sample>,
+}
+
+fn families() -> Vec<Family> {
+    vec![
+        Family {
+            name: "cpu_usage",
+            help: "Cpu time consumed by the container",
+            unit: Unit::Seconds,
+            counter: true,
+            samples: |stats| single(seconds(stats.cpu.usage.usage_total)),
+        },
+        Family {
+            name: "cpu_user",
+            help: "Cpu time consumed by the container in user mode",
+            unit: Unit::Seconds,
+            counter: true,
+            samples: |stats| single(seconds(stats.cpu.usage.usage_user)),
+        },
+        Family {
+            name: "cpu_system",
+            help: "Cpu time consumed by the container in kernel mode",
+            unit: Unit::Seconds,
+            counter: true,
+            samples: |stats| single(seconds(stats.cpu.usage.usage_kernel)),
+        },
+        Family {
+            name: "cpu_periods",
+            help: "Enforcement periods of the cpu quota that have elapsed",
+            unit: Unit::Count,
+            counter: true,
+            samples: |stats| single(stats.cpu.throttling.periods as f64),
+        },
+        Family {
+            name: "cpu_throttled_periods",
+            help: "Enforcement periods in which the container has been throttled",
+            unit: Unit::Count,
+            counter: true,
+            samples: |stats| single(stats.cpu.throttling.throttled_periods as f64),
+        },
+        Family {
+            name: "cpu_throttled",
+            help: "Time the container has been throttled for",
+            unit: Unit::Seconds,
+            counter: true,
+            samples: |stats| single(seconds(stats.cpu.throttling.throttled_time)),
+        },
+        Family {
+            name: "cpu_bursts",
+            help: "Enforcement periods in which the container has used more than its quota by bursting",
+            unit: Unit::Count,
+            counter: true,
+            samples: |stats| single(stats.cpu.throttling.bursts as f64),
+        },
+        Family {
+            name: "cpu_burst",
+            help: "Time the container has run in excess of its quota by bursting",
+            unit: Unit::Seconds,
+            counter: true,
+            samples: |stats| single(seconds(stats.cpu.throttling.burst_time)),
+        },
+        Family {
+            name: "memory_usage",
+            help: "Memory used by the container",
+            unit: Unit::Bytes,
+            counter: false,
+            samples: |stats| single(bytes(stats.memory.memory.usage)),
+        },
+        Family {
+            name: "memory_limit",
+            help: "Memory limit of the container, left out if it is unlimited",
+            unit: Unit::Bytes,
+            counter: false,
+            samples: |stats| limited(stats.memory.memory.limit.0),
+        },
+        Family {
+            name: "memory_cache",
+            help: "Page cache used by the container",
+            unit: Unit::Bytes,
+            counter: false,
+            samples: |stats| single(bytes(stats.memory.cache)),
+        },
+        Family {
+            name: "memory_oom_kills",
+            help: "Processes of the container killed by the oom killer",
+            unit: Unit::Count,
+            counter: true,
+            samples: |stats| single(stats.memory.events.oom_kill as f64),
+        },
+        Family {
+            name: "pids_current",
+            help: "Number of processes and threads in the container",
+            unit: Unit::Count,
+            counter: false,
+            samples: |stats| single(stats.pids.current as f64),
+        },
+        Family {
+            name: "pids_limit",
+            help: "Maximum number of processes and threads, left out if it is unlimited",
+            unit: Unit::Count,
+            counter: false,
+            samples: |stats| limited(stats.pids.limit),
+        },
+        Family {
+            name: "blkio",
+            help: "Bytes read from and written to block devices",
+            unit: Unit::Bytes,
+            counter: true,
+            samples: |stats| {
+                stats
+                    .blkio
+                    .service_bytes
+                    .iter()
+                    .filter_map(|stat| {
+                        // cgroup v1 reports the operation types capitalized, cgroup v2 in lowercase
+                        let op = stat.op_type.as_deref()?.to_lowercase();
+                        if op != "read" && op != "write" {
+                            return None;
+                        }
+                        let device = format!("{}:{}", stat.major, stat.minor);
+                        Some((vec![("device", device), ("op", op)], stat.value as f64))
+                    })
+                    .collect()
+            },
+        },
+        Family {
+            name: "network_receive",
+            help: "Bytes received by the network interfaces of the container",
+            unit: Unit::Bytes,
+            counter: true,
+            samples: |stats| {
+                stats
+                    .network_interfaces
+                    .iter()
+                    .map(|iface| {
+                        (
+                            vec![("interface", iface.name.clone())],
+                            iface.rx_bytes as f64,
+                        )
+                    })
+                    .collect()
+            },
+        },
+        Family {
+            name: "network_transmit",
+            help: "Bytes transmitted by the network interfaces of the container",
+            unit: Unit::Bytes,
+            counter: true,
+            samples: |stats| {
+                stats
+                    .network_interfaces
+                    .iter()
+                    .map(|iface| {
+                        (
+                            vec![("interface", iface.name.clone())],
+                            iface.tx_bytes as f64,
+                        )
+                    })
+                    .collect()
+            },
+        },
+        Family {
+            name: "hugetlb_usage",
+            help: "Huge pages used by the container",
+            unit: Unit::Bytes,
+            counter: false,
+            samples: |stats| {
+                stats
+                    .hugetlb
+                    .iter()
+                    .map(|(size, hugetlb)| (vec![("pagesize", size.clone())], bytes(hugetlb.usage)))
+                    .collect()
+            },
+        },
+        // the names of extensions and their statistics are chosen by the
+        // embedder, so they are labels rather than part of the metric name,
+        // where they could collide with the metrics above
+        Family {
+            name: "extension_stat",
+            help: "Statistics of the registered cgroup controller extensions",
+            unit: Unit::Count,
+            counter: false,
+            samples: |stats| {
+                stats
+                    .extensions
+                    .iter()
+                    .flat_map(|(extension, values)| {
+                        values.iter().map(move |(stat, value)| {
+                            (
+                                vec![("extension", extension.clone()), ("stat", stat.clone())],
+                                *value as f64,
+                            )
+                        })
+                    })
+                    .collect()
+            },
+        },
+    ]
+}
+
+fn single(value: f64) -> Vec<Sample> {
+    vec![(vec![], value)]
+}
+
+fn seconds(nanos: Nanoseconds) -> f64 {
+    nanos.0 as f64 / 1e9
+}
+
+fn bytes(bytes: Bytes) -> f64 {
+    bytes.0 as f64
+}
+
+/// Returns the limit as sample, unless the kernel reports it as unlimited
+fn limited(limit: u64) -> Vec<Sample> {
+    if limit == 0 || limit == u64::MAX {
+        return vec![];
+    }
+
+    single(limit as f64)
+}
@@ -0,0 +1,262 @@
+//! Pushes the metrics to an OpenTelemetry collector with the JSON encoding of
+//! OTLP/HTTP, which needs no protobuf or gRPC support
+use std::{
+    fmt::Display,
+    io::{BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use serde_json::{json, Value};
+
+use super::{families, ContainerMetrics, Family, Unit};
+
+/// Path the collector receives metrics on, if the endpoint has none
+const METRICS_PATH: &str = "/v1/metrics";
+/// Time after which a collector that does not respond is given up on
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Cumulative aggregation temporality, as the counters of the kernel are never reset
+const AGGREGATION_TEMPORALITY_CUMULATIVE: u8 = 2;
+
+/// OTLP/HTTP endpoint of a collector. Only plain http is supported, a
+/// collector reached over the network should be fronted by a local agent.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct Endpoint {
+    /// Host and port
+    address: String,
+    path: String,
+}
+
+impl Endpoint {
+    pub fn parse(endpoint: &str) -> Result<Self> {
+        let rest = match endpoint.strip_prefix("http://") {
+            Some(rest) => rest,
+            None => bail!(
+                "unsupported otlp endpoint {}, only http:// is supported",
+                endpoint
+            ),
+        };
+
+        let (address, path) = match rest.find('/') {
+            Some(index) => rest.split_at(index),
+            None => (rest, ""),
+        };
+        if address.is_empty() {
+            bail!("otlp endpoint {} has no host", endpoint);
+        }
+        let address = if address.contains(':') {
+            address.to_owned()
+        } else {
+            format!("{}:80", address)
+        };
+        let path = match path.trim_end_matches('/') {
+            "" => METRICS_PATH.to_owned(),
+            path => path.to_owned(),
+        };
+
+        Ok(Self { address, path })
+    }
+
+    /// Connects to the first address of the collector that accepts the
+    /// connection within the timeout, so that an unreachable collector does
+    /// not block the pushes after it
+    fn connect(&self) -> Result<TcpStream> {
+        let mut last_err = None;
+        for addr in self.address.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, PUSH_TIMEOUT) {
+                Ok(stream) => return Ok(stream),
+                Err(err) => last_err = Some(err),
+            }
+        }
+
+        match last_err {
+            Some(err) => Err(err.into()),
+            None => bail!("{} resolves to no address", self.address),
+        }
+    }
+
+    /// Sends the export request to the collector
+    pub fn push(&self, request: &Value) -> Result<()> {
+        let body = serde_json::to_vec(request)?;
+        let mut stream = self
+            .connect()
+            .with_context(|| format!("failed to connect to {}", self.address))?;
+        stream.set_read_timeout(Some(PUSH_TIMEOUT))?;
+        stream.set_write_timeout(Some(PUSH_TIMEOUT))?;
+
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.path,
+            self.address,
+            body.len()
+        )?;
+        stream.write_all(&body)?;
+        stream.flush()?;
+
+        let mut status_line = String::new();
+        BufReader::new(&stream).read_line(&mut status_line)?;
+        match status_line.split_ascii_whitespace().nth(1) {
+            Some(status) if status.starts_with('2') => Ok(()),
+            _ => bail!("collector rejected the metrics: {}", status_line.trim()),
+        }
+    }
+}
+
+impl Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "http://{}{}", self.address, self.path)
+    }
+}
+
+/// Returns the name of the metric according to the semantic conventions of
+/// OpenTelemetry, e.g. container.cpu.usage
+fn metric_name(family: &Family) -> String {
+    format!("container.{}", family.name.replace('_', "."))
+}
+
+fn unit(unit: Unit) -> &'static str {
+    match unit {
+        Unit::Seconds => "s",
+        Unit::Bytes => "By",
+        Unit::Count => "1",
+    }
+}
+
+fn attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+/// Builds the request that exports the metrics of the containers, in the JSON
+/// encoding of ExportMetricsServiceRequest
+pub(super) fn export_request(containers: &[ContainerMetrics]) -> Value {
+    let now = Utc::now().timestamp_nanos().to_string();
+
+    let metrics: Vec<Value> = families()
+        .iter()
+        .map(|family| {
+            let mut data_points = Vec::new();
+            for container in containers {
+                let container_attributes: Vec<Value> =
+                    std::iter::once(attribute("container.id", &container.id))
+                        .chain(
+                            container
+                                .annotations
+                                .iter()
+                                .map(|(key, value)| attribute(key, value)),
+                        )
+                        .collect();
+
+                for (labels, value) in (family.samples)(&container.stats) {
+                    let mut attributes = container_attributes.clone();
+                    attributes.extend(labels.iter().map(|(key, value)| attribute(key, value)));
+
+                    let mut data_point = json!({
+                        "attributes": attributes,
+                        "timeUnixNano": now,
+                        "asDouble": value,
+                    });
+                    // counters of a container start when the container is created
+                    if let (true, Some(created)) = (family.counter, container.created) {
+                        data_point["startTimeUnixNano"] =
+                            json!(created.timestamp_nanos().to_string());
+                    }
+                    data_points.push(data_point);
+                }
+            }
+
+            let mut metric = json!({
+                "name": metric_name(family),
+                "description": family.help,
+                "unit": unit(family.unit),
+            });
+            if family.counter {
+                metric["sum"] = json!({
+                    "dataPoints": data_points,
+                    "aggregationTemporality": AGGREGATION_TEMPORALITY_CUMULATIVE,
+                    "isMonotonic": true,
+                });
+            } else {
+                metric["gauge"] = json!({ "dataPoints": data_points });
+            }
+            metric
+        })
+        .collect();
+
+    json!({
+        "resourceMetrics": [{
+            "resource": {
+                "attributes": [attribute("service.name", "youki")],
+            },
+            "scopeMetrics": [{
+                "scope": { "name": "youki", "version": env!("CARGO_PKG_VERSION") },
+                "metrics": metrics,
+            }],
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libcgroups::stats::{Bytes, Stats};
+
+    #[test]
+    fn test_parse_endpoint() -> Result<()> {
+        assert_eq!(
+            Endpoint::parse("http://127.0.0.1:4318")?,
+            Endpoint {
+                address: "127.0.0.1:4318".to_owned(),
+                path: METRICS_PATH.to_owned(),
+            }
+        );
+        assert_eq!(
+            Endpoint::parse("http://collector/otlp/v1/metrics/")?,
+            Endpoint {
+                address: "collector:80".to_owned(),
+                path: "/otlp/v1/metrics".to_owned(),
+            }
+        );
+        assert!(Endpoint::parse("https://collector:4318").is_err());
+        assert!(Endpoint::parse("http:///v1/metrics").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_request() {
+        let mut stats = Stats::default();
+        stats.memory.memory.usage = Bytes(4096);
+        let containers = vec![ContainerMetrics {
+            id: "74f1a4cb3801".to_owned(),
+            annotations: vec![("io.kubernetes.pod.name".to_owned(), "web-0".to_owned())],
+            created: Some(Utc::now()),
+            stats,
+        }];
+
+        let request = export_request(&containers);
+        let metrics = request["resourceMetrics"][0]["scopeMetrics"][0]["metrics"]
+            .as_array()
+            .unwrap();
+
+        let memory = metrics
+            .iter()
+            .find(|metric| metric["name"] == "container.memory.usage")
+            .unwrap();
+        assert_eq!(memory["unit"], "By");
+        let data_point = &memory["gauge"]["dataPoints"][0];
+        assert_eq!(data_point["asDouble"], 4096.0);
+        assert_eq!(
+            data_point["attributes"][1],
+            attribute("io.kubernetes.pod.name", "web-0")
+        );
+
+        let cpu = metrics
+            .iter()
+            .find(|metric| metric["name"] == "container.cpu.usage")
+            .unwrap();
+        assert_eq!(cpu["sum"]["isMonotonic"], true);
+        assert!(cpu["sum"]["dataPoints"][0]["startTimeUnixNano"].is_string());
+    }
+}
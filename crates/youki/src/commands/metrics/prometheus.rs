@@ -0,0 +1,286 @@
+//! Serves the metrics in the text format of Prometheus
+use std::{
+    fmt::Write as _,
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use libcgroups::stats_cache::StatsCache;
+
+use super::{collect, families, state_stamp, ContainerMetrics, Family, Unit};
+
+/// Prefix of the names of all metrics
+const PREFIX: &str = "youki_container";
+/// Time after which a scrape that has not sent its request is dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Maximum size of the request line and headers of a scrape
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+/// Time after which a scrape that does not read its response is dropped, so
+/// that a stalled client cannot block the scrapes after it
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(super) fn serve(
+    listen: SocketAddr,
+    root_path: &Path,
+    annotations: &[String],
+    cache: &StatsCache<Vec<ContainerMetrics>>,
+) -> Result<()> {
+    let listener =
+        TcpListener::bind(listen).with_context(|| format!("failed to listen on {}", listen))?;
+    log::info!("serving metrics on http://{}/metrics", listen);
+
+    // scrapes are rare and cheap, so they are handled one after the other
+    for stream in listener.incoming() {
+        let result = stream
+            .map_err(anyhow::Error::from)
+            .and_then(|stream| handle_scrape(stream, root_path, annotations, cache));
+        if let Err(err) = result {
+            log::warn!("failed to serve metrics: {:?}", err);
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_scrape(
+    mut stream: TcpStream,
+    root_path: &Path,
+    annotations: &[String],
+    cache: &StatsCache<Vec<ContainerMetrics>>,
+) -> Result<()> {
+    let request_line = read_request(&stream, REQUEST_TIMEOUT)?;
+    stream.set_write_timeout(Some(RESPONSE_TIMEOUT))?;
+
+    let mut parts = request_line.split_ascii_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let containers = cache.get(root_path, state_stamp(root_path), |root_path| {
+                collect(root_path, annotations)
+            });
+            match containers {
+                Ok(containers) => ("200 OK", render(&containers)),
+                // the scraper is told that the scrape failed, so that it does
+                // not wait for a response until it times out
+                Err(err) => {
+                    log::warn!("failed to collect metrics: {:?}", err);
+                    ("500 Internal Server Error", format!("{:#}\n", err))
+                }
+            }
+        }
+        _ => ("404 Not Found", String::new()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Reads the request up to the blank line that ends its headers and returns
+/// the request line. The response is only sent once the whole request has been
+/// read, as closing a socket with unread data resets the connection, which can
+/// discard the response. The whole request has to arrive within the timeout.
+fn read_request(mut stream: &TcpStream, timeout: Duration) -> Result<String> {
+    let deadline = Instant::now() + timeout;
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !has_headers(&request) {
+        if request.len() > MAX_REQUEST_SIZE {
+            bail!("request is larger than {} bytes", MAX_REQUEST_SIZE);
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            bail!("request has not been received within {:?}", timeout);
+        }
+
+        stream.set_read_timeout(Some(remaining))?;
+        let len = stream.read(&mut buf)?;
+        if len == 0 {
+            bail!("connection has been closed before the end of the request");
+        }
+        request.extend_from_slice(&buf[..len]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    Ok(request.lines().next().unwrap_or_default().to_owned())
+}
+
+/// Checks if the request contains the blank line after the headers
+fn has_headers(request: &[u8]) -> bool {
+    request.windows(4).any(|w| w == b"\r\n\r\n") || request.windows(2).any(|w| w == b"\n\n")
+}
+
+/// Returns the name of the metric, which ends with its unit and _total for
+/// counters, as recommended by Prometheus
+fn metric_name(family: &Family) -> String {
+    let unit = match family.unit {
+        Unit::Seconds => "_seconds",
+        Unit::Bytes => "_bytes",
+        Unit::Count => "",
+    };
+    let total = if family.counter { "_total" } else { "" };
+    format!("{}_{}{}{}", PREFIX, family.name, unit, total)
+}
+
+/// Returns the labels of the metrics of a container, which are its id and the
+/// selected annotations, named after the annotation with all characters that
+/// are invalid in label names replaced by underscores
+fn labels(container: &ContainerMetrics) -> Vec<(String, String)> {
+    let mut labels = vec![("id".to_owned(), container.id.clone())];
+    for (key, value) in &container.annotations {
+        let name: String = key
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        labels.push((format!("annotation_{}", name), value.clone()));
+    }
+
+    labels
+}
+
+fn render(containers: &[ContainerMetrics]) -> String {
+    let container_labels: Vec<_> = containers.iter().map(labels).collect();
+
+    let mut out = String::new();
+    for family in families() {
+        let name = metric_name(&family);
+        let kind = if family.counter { "counter" } else { "gauge" };
+        let _ = writeln!(out, "# HELP {} {}", name, family.help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for (container, labels) in containers.iter().zip(&container_labels) {
+            for (extra_labels, value) in (family.samples)(&container.stats) {
+                let labels: Vec<String> = labels
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_str()))
+                    .chain(
+                        extra_labels
+                            .iter()
+                            .map(|(name, value)| (*name, value.as_str())),
+                    )
+                    .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+                    .collect();
+                let _ = writeln!(out, "{}{{{}}} {}", name, labels.join(","), value);
+            }
+        }
+    }
+
+    out
+}
+
+/// Escapes a label value as required by the text format of Prometheus
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libcgroups::stats::{Bytes, Nanoseconds, Stats};
+    use std::{collections::HashMap, thread};
+
+    #[test]
+    fn test_read_request() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let client = thread::spawn(move || -> Result<TcpStream> {
+            let mut client = TcpStream::connect(addr)?;
+            client.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n")?;
+            thread::sleep(Duration::from_millis(50));
+            client.write_all(b"Accept: text/plain\r\n\r\n")?;
+            Ok(client)
+        });
+
+        let (stream, _) = listener.accept()?;
+        assert_eq!(
+            read_request(&stream, REQUEST_TIMEOUT)?,
+            "GET /metrics HTTP/1.1"
+        );
+        client.join().unwrap()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_incomplete_request() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let mut client = TcpStream::connect(listener.local_addr()?)?;
+        client.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n")?;
+
+        let (stream, _) = listener.accept()?;
+        let start = Instant::now();
+        assert!(read_request(&stream, Duration::from_millis(100)).is_err());
+        assert!(start.elapsed() < REQUEST_TIMEOUT);
+        Ok(())
+    }
+
+    #[test]
+    fn test_labels() {
+        let container = ContainerMetrics {
+            id: "74f1a4cb3801".to_owned(),
+            annotations: vec![("io.kubernetes.pod.name".to_owned(), "web-0".to_owned())],
+            created: None,
+            stats: Stats::default(),
+        };
+
+        assert_eq!(
+            labels(&container),
+            vec![
+                ("id".to_owned(), "74f1a4cb3801".to_owned()),
+                (
+                    "annotation_io_kubernetes_pod_name".to_owned(),
+                    "web-0".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let mut stats = Stats::default();
+        stats.cpu.usage.usage_total = Nanoseconds(1_500_000_000);
+        stats.memory.memory.limit = Bytes(u64::MAX);
+        stats.pids.current = 3;
+        let containers = vec![ContainerMetrics {
+            id: "a\"b".to_owned(),
+            annotations: vec![],
+            created: None,
+            stats,
+        }];
+
+        let output = render(&containers);
+        assert!(output.contains("# TYPE youki_container_cpu_usage_seconds_total counter\n"));
+        assert!(output.contains("youki_container_cpu_usage_seconds_total{id=\"a\\\"b\"} 1.5\n"));
+        assert!(output.contains("youki_container_pids_current{id=\"a\\\"b\"} 3\n"));
+        assert!(!output.contains("youki_container_memory_limit_bytes{"));
+    }
+
+    #[test]
+    fn test_render_extensions() {
+        let mut stats = Stats::default();
+        stats.extensions.insert(
+            "pids_current".to_owned(),
+            HashMap::from([("bytes_total\"".to_owned(), 7)]),
+        );
+        let containers = vec![ContainerMetrics {
+            id: "c1".to_owned(),
+            annotations: vec![],
+            created: None,
+            stats,
+        }];
+
+        let output = render(&containers);
+        assert!(output.contains(
+            "youki_container_extension_stat{id=\"c1\",extension=\"pids_current\",stat=\"bytes_total\\\"\"} 7\n"
+        ));
+    }
+}
@@ -0,0 +1,92 @@
+//! Contains functionality of the migrate command
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+
+use libcontainer::container::CheckpointOptions;
+use libcontainer::migration::{
+    CommandTransport, CriuCheckpointer, LocalTransport, Migration, MigrationTransport,
+};
+
+use super::load_container;
+
+/// Migrate a running container to another root path or host with criu (experimental)
+#[derive(Parser, Debug)]
+pub struct Migrate {
+    #[clap(forbid_empty_values = true, required = true)]
+    pub container_id: String,
+
+    /// Number of dumps that are taken while the container keeps running
+    #[clap(long, default_value = "1")]
+    pub pre_dumps: usize,
+
+    /// Root path of youki to migrate the container to, e.g. on shared storage
+    #[clap(long)]
+    pub target_root: Option<PathBuf>,
+
+    /// Shell command that copies $YOUKI_SOURCE to $YOUKI_DESTINATION, which is relative to the root path on the target
+    #[clap(long)]
+    pub transfer_cmd: Option<String>,
+
+    /// Shell command that restores $YOUKI_CONTAINER_ID on the target from the images in $YOUKI_IMAGE_PATH, which is relative to the root path on the target
+    #[clap(long)]
+    pub restore_cmd: Option<String>,
+
+    /// Allow open tcp connections
+    #[clap(long)]
+    pub tcp_established: bool,
+
+    /// Allow external unix sockets
+    #[clap(long)]
+    pub ext_unix_sk: bool,
+
+    /// Allow shell jobs
+    #[clap(long)]
+    pub shell_job: bool,
+
+    /// Handle file locks
+    #[clap(long)]
+    pub file_locks: bool,
+}
+
+pub fn migrate(args: Migrate, root_path: PathBuf) -> Result<()> {
+    let mut container = load_container(&root_path, &args.container_id)?;
+    let mut checkpointer = CriuCheckpointer::new(
+        &mut container,
+        CheckpointOptions {
+            tcp_established: args.tcp_established,
+            ext_unix_sk: args.ext_unix_sk,
+            shell_job: args.shell_job,
+            file_locks: args.file_locks,
+            ..Default::default()
+        },
+    );
+
+    let transport: Box<dyn MigrationTransport> =
+        match (args.target_root, args.transfer_cmd, args.restore_cmd) {
+            (Some(target_root), None, None) => {
+                let same_root = fs::canonicalize(&target_root)
+                    .map(|target| Some(target) == fs::canonicalize(&root_path).ok())
+                    .unwrap_or(false);
+                if same_root {
+                    bail!("the target root path is the root path of the container");
+                }
+                Box::new(
+                    LocalTransport::new(target_root)
+                        .with_restore_options(checkpointer.restore_options()),
+                )
+            }
+            (None, Some(transfer_cmd), Some(restore_cmd)) => {
+                Box::new(CommandTransport::new(transfer_cmd, restore_cmd))
+            }
+            _ => bail!("either --target-root or --transfer-cmd and --restore-cmd must be given"),
+        };
+
+    Migration::new(transport)
+        .with_pre_dumps(args.pre_dumps)
+        .with_progress(|event| eprintln!("{}", event))
+        .run(&mut checkpointer)
+        .with_context(|| format!("failed to migrate container {}", args.container_id))
+}
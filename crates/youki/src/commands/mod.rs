@@ -1,27 +1,39 @@
 use anyhow::{bail, Context, Result};
+use serde::Serialize;
 use std::{fs, path::Path};
 
-use libcgroups::common::CgroupManager;
+use libcgroups::{common::CgroupManager, dry_run::CgroupWrite};
 use libcontainer::container::Container;
 
+pub mod attach;
+pub mod check_spec;
+pub mod checkpoint;
 pub mod completion;
 pub mod create;
 pub mod delete;
 pub mod events;
 pub mod exec;
+pub mod features;
+pub mod gc;
 pub mod info;
 pub mod kill;
 pub mod list;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod migrate;
 pub mod pause;
 pub mod ps;
+pub mod restore;
 pub mod resume;
 pub mod run;
 pub mod spec_json;
 pub mod start;
 pub mod state;
+pub mod stats;
 pub mod update;
 
 fn load_container<P: AsRef<Path>>(root_path: P, container_id: &str) -> Result<Container> {
+    Container::validate_id(container_id)?;
     // resolves relative paths, symbolic links etc. and get complete path
     let root_path = fs::canonicalize(&root_path)
         .with_context(|| format!("failed to canonicalize {}", root_path.as_ref().display()))?;
@@ -35,6 +47,30 @@ fn load_container<P: AsRef<Path>>(root_path: P, container_id: &str) -> Result<Co
         .with_context(|| format!("could not load state for container {}", container_id))
 }
 
+/// Prints the value serialized in the requested output format. Supported formats are
+/// json, yaml and compact, which is json on a single line.
+fn print_formatted<T: Serialize>(value: &T, format: &str) -> Result<()> {
+    match format {
+        "json" => println!("{}", serde_json::to_string_pretty(value)?),
+        "compact" => println!("{}", serde_json::to_string(value)?),
+        "yaml" => print!("{}", serde_yaml::to_string(value)?),
+        _ => bail!("unsupported output format {}", format),
+    }
+
+    Ok(())
+}
+
+/// Prints the changes a cgroup dry run would have made
+fn print_cgroup_writes(writes: &[CgroupWrite]) {
+    if writes.is_empty() {
+        println!("no cgroup changes");
+    }
+
+    for write in writes {
+        println!("{}", write);
+    }
+}
+
 fn create_cgroup_manager<P: AsRef<Path>>(
     root_path: P,
     container_id: &str,
@@ -0,0 +1,67 @@
+//! Contains functionality of the restore command
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+
+use libcontainer::container::RestoreOptions;
+
+use super::load_container;
+
+/// Restore a stopped container from the images of a checkpoint
+#[derive(Parser, Debug)]
+pub struct Restore {
+    #[clap(forbid_empty_values = true, required = true)]
+    pub container_id: String,
+
+    /// Path of the criu image files (default: the image path of the latest checkpoint)
+    #[clap(long)]
+    pub image_path: Option<PathBuf>,
+
+    /// Path for the work files and logs of criu
+    #[clap(long)]
+    pub work_path: Option<PathBuf>,
+
+    /// Network namespace to restore the container into
+    #[clap(long)]
+    pub network_namespace: Option<PathBuf>,
+
+    /// Pid namespace to restore the container into
+    #[clap(long)]
+    pub pid_namespace: Option<PathBuf>,
+
+    /// Allow open tcp connections
+    #[clap(long)]
+    pub tcp_established: bool,
+
+    /// Allow external unix sockets
+    #[clap(long)]
+    pub ext_unix_sk: bool,
+
+    /// Allow shell jobs
+    #[clap(long)]
+    pub shell_job: bool,
+
+    /// Handle file locks
+    #[clap(long)]
+    pub file_locks: bool,
+}
+
+pub fn restore(args: Restore, root_path: PathBuf) -> Result<()> {
+    log::debug!("start restoring container {}", args.container_id);
+    let mut container = load_container(root_path, &args.container_id)?;
+    let opts = RestoreOptions {
+        image_path: args.image_path,
+        work_path: args.work_path,
+        network_namespace: args.network_namespace,
+        pid_namespace: args.pid_namespace,
+        tcp_established: args.tcp_established,
+        ext_unix_sk: args.ext_unix_sk,
+        shell_job: args.shell_job,
+        file_locks: args.file_locks,
+    };
+
+    container
+        .restore(&opts)
+        .with_context(|| format!("failed to restore container {}", args.container_id))
+}
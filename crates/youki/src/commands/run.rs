@@ -1,21 +1,41 @@
 use std::path::PathBuf;
 
 use anyhow::{Context, Result};
+use clap::Parser;
 use libcontainer::{container::builder::ContainerBuilder, syscall::syscall::create_syscall};
-use liboci_cli::Run;
+
+use crate::commands::create::{self, CreateOpts};
+
+/// Create a container and immediately start it
+#[derive(Parser, Debug)]
+pub struct Run {
+    #[clap(flatten)]
+    pub base: liboci_cli::Run,
+    #[clap(flatten)]
+    pub opts: CreateOpts,
+}
 
 pub fn run(args: Run, root_path: PathBuf, systemd_cgroup: bool) -> Result<()> {
+    let (base, opts) = (args.base, args.opts);
+    if opts.dry_run_cgroups {
+        return create::dry_run_cgroups(&base.bundle, &base.container_id, systemd_cgroup);
+    }
+
     let syscall = create_syscall();
-    let mut container = ContainerBuilder::new(args.container_id.clone(), syscall.as_ref())
-        .with_pid_file(args.pid_file.as_ref())
-        .with_console_socket(args.console_socket.as_ref())
+    let mut container = ContainerBuilder::new(base.container_id.clone(), syscall.as_ref())
+        .with_pid_file(base.pid_file.as_ref())
+        .with_console_socket(base.console_socket.as_ref())
         .with_root_path(root_path)
-        .with_preserved_fds(args.preserve_fds)
-        .as_init(&args.bundle)
+        .with_preserved_fds(base.preserve_fds)
+        .as_init(&base.bundle)
         .with_systemd(systemd_cgroup)
+        .with_selinux_mcs(opts.selinux_mcs)
+        .with_permissive_lsm(opts.permissive_lsm)
+        .with_progress(opts.progress.is_some())
+        .with_attachable(opts.attachable)
         .build()?;
 
     container
         .start()
-        .with_context(|| format!("failed to start container {}", args.container_id))
+        .with_context(|| format!("failed to start container {}", base.container_id))
 }
@@ -1,15 +1,51 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use clap::Parser;
 use nix;
-use oci_spec::runtime::Mount;
 use oci_spec::runtime::{
     LinuxBuilder, LinuxIdMappingBuilder, LinuxNamespace, LinuxNamespaceBuilder, LinuxNamespaceType,
     Spec,
 };
+use oci_spec::runtime::{Mount, MountBuilder};
 use serde_json::to_writer_pretty;
-use std::fs::File;
+use std::fs::{self, File};
 use std::path::Path;
 use std::path::PathBuf;
 
+/// Command generates a config.json
+#[derive(Parser, Debug)]
+pub struct SpecCmd {
+    #[clap(flatten)]
+    pub base: liboci_cli::Spec,
+    #[clap(flatten)]
+    pub opts: SpecOpts,
+}
+
+/// Options of youki for customizing the generated config.json
+#[derive(Parser, Debug, Default)]
+pub struct SpecOpts {
+    /// Arguments of the container process. All following values are taken as arguments,
+    /// so this has to be the last option.
+    #[clap(long, multiple_values = true, allow_hyphen_values = true)]
+    pub args: Vec<String>,
+
+    /// Add or replace an environment variable of the container process, e.g. TERM=xterm
+    #[clap(long, number_of_values = 1)]
+    pub env: Vec<String>,
+
+    /// Hostname of the container
+    #[clap(long)]
+    pub hostname: Option<String>,
+
+    /// Working directory of the container process, must be an absolute path
+    #[clap(long)]
+    pub cwd: Option<PathBuf>,
+
+    /// Bind mount a host path into the container, in the form src:dst[:opts] with comma
+    /// separated mount options. Mounts are recursive unless bind is given as option.
+    #[clap(long = "mount", number_of_values = 1)]
+    pub mounts: Vec<String>,
+}
+
 pub fn get_default() -> Result<Spec> {
     Ok(Spec::default())
 }
@@ -80,16 +116,111 @@ pub fn get_rootless() -> Result<Spec> {
     Ok(spec)
 }
 
+/// Applies the customizations given on the command line to the generated spec
+fn customize(spec: &mut Spec, args: &SpecOpts) -> Result<()> {
+    let mut process = spec.process().clone().context("no process in spec")?;
+    if !args.args.is_empty() {
+        process.set_args(Some(args.args.clone()));
+    }
+
+    if !args.env.is_empty() {
+        let mut env = process.env().clone().unwrap_or_default();
+        for var in &args.env {
+            let (key, _) = var.split_once('=').with_context(|| {
+                format!("invalid environment variable {}, expected KEY=value", var)
+            })?;
+            env.retain(|e| e.split_once('=').map(|(k, _)| k) != Some(key));
+            env.push(var.clone());
+        }
+        process.set_env(Some(env));
+    }
+
+    if let Some(cwd) = &args.cwd {
+        if !cwd.is_absolute() {
+            bail!("cwd {} must be an absolute path", cwd.display());
+        }
+        process.set_cwd(cwd.clone());
+    }
+    spec.set_process(Some(process));
+
+    if let Some(hostname) = &args.hostname {
+        spec.set_hostname(Some(hostname.clone()));
+    }
+
+    if !args.mounts.is_empty() {
+        let mut mounts = spec.mounts().clone().unwrap_or_default();
+        for mount in &args.mounts {
+            mounts.push(parse_mount(mount)?);
+        }
+        spec.set_mounts(Some(mounts));
+    }
+
+    Ok(())
+}
+
+/// Parses a bind mount in the form src:dst[:opts]
+fn parse_mount(value: &str) -> Result<Mount> {
+    let mut parts = value.splitn(3, ':');
+    let (source, destination) = match (parts.next(), parts.next()) {
+        (Some(source), Some(destination)) if !source.is_empty() && !destination.is_empty() => {
+            (source, destination)
+        }
+        _ => bail!("invalid mount {}, expected src:dst[:opts]", value),
+    };
+
+    let destination = PathBuf::from(destination);
+    if !destination.is_absolute() {
+        bail!(
+            "mount destination {} must be an absolute path",
+            destination.display()
+        );
+    }
+    let source =
+        fs::canonicalize(source).with_context(|| format!("invalid mount source {}", source))?;
+
+    let mut options: Vec<String> = parts
+        .next()
+        .map(|options| {
+            options
+                .split(',')
+                .filter(|o| !o.is_empty())
+                .map(|o| o.to_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    if !options.iter().any(|o| o == "bind" || o == "rbind") {
+        options.insert(0, "rbind".to_owned());
+    }
+
+    let mount = MountBuilder::default()
+        .typ("bind")
+        .source(source)
+        .destination(destination)
+        .options(options)
+        .build()?;
+    Ok(mount)
+}
+
 /// spec Cli command
-pub fn spec(args: liboci_cli::Spec) -> Result<()> {
-    let spec = if args.rootless {
+pub fn spec(args: SpecCmd) -> Result<()> {
+    let (base, opts) = (args.base, args.opts);
+    let mut spec = if base.rootless {
         get_rootless()?
     } else {
         get_default()?
     };
+    customize(&mut spec, &opts)?;
+
+    let path = match &base.bundle {
+        Some(bundle) => bundle.join("config.json"),
+        None => PathBuf::from("config.json"),
+    };
 
     // write data to config.json
-    to_writer_pretty(&File::create("config.json")?, &spec)?;
+    to_writer_pretty(
+        &File::create(&path).with_context(|| format!("failed to create {}", path.display()))?,
+        &spec,
+    )?;
     Ok(())
 }
 
@@ -109,4 +240,60 @@ mod tests {
         to_writer_pretty(&File::create(path)?, &spec)?;
         Ok(())
     }
+
+    #[test]
+    fn test_customize() -> Result<()> {
+        let mut spec = get_default()?;
+        let args = SpecOpts {
+            args: vec!["sleep".to_owned(), "10".to_owned()],
+            env: vec!["TERM=dumb".to_owned(), "FOO=bar".to_owned()],
+            hostname: Some("test".to_owned()),
+            cwd: Some(PathBuf::from("/tmp")),
+            mounts: vec!["/tmp:/data:ro".to_owned()],
+        };
+        customize(&mut spec, &args)?;
+
+        let process = spec.process().as_ref().unwrap();
+        assert_eq!(process.args().as_ref().unwrap(), &args.args);
+        let env = process.env().as_ref().unwrap();
+        assert!(env.contains(&"TERM=dumb".to_owned()));
+        assert!(env.contains(&"FOO=bar".to_owned()));
+        assert!(!env.contains(&"TERM=xterm".to_owned()));
+        assert_eq!(process.cwd(), &PathBuf::from("/tmp"));
+        assert_eq!(spec.hostname().as_deref(), Some("test"));
+
+        let mount = spec.mounts().as_ref().unwrap().last().unwrap();
+        assert_eq!(mount.destination(), &PathBuf::from("/data"));
+        assert_eq!(mount.typ().as_deref(), Some("bind"));
+        assert_eq!(
+            mount.options().as_ref().unwrap(),
+            &vec!["rbind".to_owned(), "ro".to_owned()]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_customize_invalid() -> Result<()> {
+        let mut spec = get_default()?;
+        let env = SpecOpts {
+            env: vec!["TERM".to_owned()],
+            ..Default::default()
+        };
+        assert!(customize(&mut spec, &env).is_err());
+
+        let cwd = SpecOpts {
+            cwd: Some(PathBuf::from("relative")),
+            ..Default::default()
+        };
+        assert!(customize(&mut spec, &cwd).is_err());
+
+        assert!(parse_mount("/tmp").is_err());
+        assert!(parse_mount("/tmp:relative").is_err());
+        assert!(parse_mount(":/data").is_err());
+        assert_eq!(
+            parse_mount("/tmp:/data:bind")?.options().as_ref().unwrap(),
+            &vec!["bind".to_owned()]
+        );
+        Ok(())
+    }
 }
@@ -2,14 +2,28 @@ use std::fs;
 use std::path::PathBuf;
 
 use anyhow::Result;
+use clap::Parser;
 
 use libcontainer::container::Container;
-use liboci_cli::State;
+
+use crate::commands::print_formatted;
+
+/// Show the container state
+#[derive(Parser, Debug)]
+pub struct State {
+    #[clap(flatten)]
+    pub base: liboci_cli::State,
+    /// format of the output: json, yaml or compact (default: "json")
+    #[clap(short, long, default_value = "json")]
+    pub format: String,
+}
 
 pub fn state(args: State, root_path: PathBuf) -> Result<()> {
+    let (base, format) = (args.base, args.format);
+    Container::validate_id(&base.container_id)?;
     let root_path = fs::canonicalize(root_path)?;
-    let container_root = root_path.join(&args.container_id);
+    let container_root = root_path.join(&base.container_id);
     let container = Container::load(container_root)?;
-    println!("{}", serde_json::to_string_pretty(&container.state)?);
+    print_formatted(&container.state, &format)?;
     std::process::exit(0);
 }
@@ -0,0 +1,38 @@
+//! Contains functionality of the stats command
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+
+use libcontainer::container::Container;
+
+use crate::commands::load_container;
+
+/// Display resource usage statistics of one or all containers
+#[derive(Parser, Debug)]
+pub struct Stats {
+    /// Collect statistics of all running and paused containers
+    #[clap(long)]
+    pub all: bool,
+    /// Name of the container instance
+    #[clap(forbid_empty_values = true, required_unless_present = "all")]
+    pub container_id: Option<String>,
+}
+
+pub fn stats(args: Stats, root_path: PathBuf) -> Result<()> {
+    if args.all {
+        let stats = Container::stats_all(root_path).context("failed to get stats")?;
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
+
+    // clap guarantees that the container id is present if --all has not been specified
+    let container_id = args.container_id.unwrap();
+    let mut container = load_container(root_path, &container_id)?;
+    let stats = container
+        .stats()
+        .with_context(|| format!("failed to get stats of container {}", container_id))?;
+    println!("{}", serde_json::to_string_pretty(&stats)?);
+
+    Ok(())
+}
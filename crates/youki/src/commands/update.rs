@@ -1,36 +1,203 @@
+use std::collections::HashMap;
 use std::fs;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use crate::commands::create_cgroup_manager;
-use anyhow::Result;
-use libcgroups::{self, common::ControllerOpt};
-use liboci_cli::Update;
-use oci_spec::runtime::{LinuxPidsBuilder, LinuxResources, LinuxResourcesBuilder};
+use crate::commands::{create_cgroup_manager, load_container, print_cgroup_writes};
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use libcgroups::{
+    self,
+    common::{CgroupSetup, ControllerOpt},
+    dry_run,
+};
+use libcontainer::{config::YoukiConfig, container::Container, intel_rdt};
+use oci_spec::runtime::{
+    LinuxBlockIoBuilder, LinuxCpuBuilder, LinuxIntelRdt, LinuxMemoryBuilder, LinuxPidsBuilder,
+    LinuxResources, LinuxResourcesBuilder,
+};
+
+/// Update running container resource constraints
+#[derive(Parser, Debug)]
+pub struct Update {
+    #[clap(flatten)]
+    pub base: liboci_cli::Update,
+    /// Set a cgroup v2 interface file of the container, e.g. memory.high=1G
+    #[clap(long, parse(try_from_str = parse_key_val), number_of_values = 1)]
+    pub unified: Vec<(String, String)>,
+    /// Print the cgroup files and values that would be written instead of applying them
+    #[clap(long)]
+    pub dry_run: bool,
+}
 
 pub fn update(args: Update, root_path: PathBuf) -> Result<()> {
-    let cmanager = create_cgroup_manager(root_path, &args.container_id)?;
+    let base = args.base;
+    let cmanager = create_cgroup_manager(&root_path, &base.container_id)?;
 
-    let linux_res: LinuxResources;
-    if let Some(resources_path) = args.resources {
+    let mut linux_res: LinuxResources;
+    if let Some(resources_path) = &base.resources {
         linux_res = if resources_path.to_string_lossy() == "-" {
             serde_json::from_reader(io::stdin())?
         } else {
             serde_json::from_reader(fs::File::open(resources_path)?)?
         };
     } else {
-        let mut builder = LinuxResourcesBuilder::default();
-        if let Some(new_pids_limit) = args.pids_limit {
-            builder = builder.pids(LinuxPidsBuilder::default().limit(new_pids_limit).build()?);
+        linux_res = resources_from_flags(&base)?;
+        if !args.unified.is_empty() {
+            linux_res.set_unified(Some(args.unified.into_iter().collect::<HashMap<_, _>>()));
         }
-        linux_res = builder.build()?;
     }
 
-    cmanager.apply(&ControllerOpt {
+    if linux_res.unified().is_some()
+        && !matches!(
+            libcgroups::common::get_cgroup_setup()?,
+            CgroupSetup::Unified
+        )
+    {
+        bail!("unified resources can only be updated on a cgroup v2 system");
+    }
+
+    let controller_opt = ControllerOpt {
         resources: &linux_res,
         disable_oom_killer: false,
         oom_score_adj: None,
         freezer_state: None,
-    })?;
+    };
+    // Everything is validated before anything is changed, so that an invalid
+    // update does not leave the container partially updated. The dry run
+    // validates the resources like a real update, but writes nothing.
+    let writes = dry_run::dry_run(|| cmanager.apply(&controller_opt))?;
+    let intel_rdt = if base.l3_cache_schema.is_some() || base.mem_bw_schema.is_some() {
+        Some(updated_intel_rdt(
+            &root_path,
+            &base.container_id,
+            base.l3_cache_schema,
+            base.mem_bw_schema,
+        )?)
+    } else {
+        None
+    };
+
+    if args.dry_run {
+        print_cgroup_writes(&writes);
+        if intel_rdt.is_some() {
+            println!("intel rdt schemata are not part of the dry run");
+        }
+        return Ok(());
+    }
+
+    cmanager.apply(&controller_opt)?;
+
+    if let Some((container, mut config, intel_rdt)) = intel_rdt {
+        // processes of the container that are not in the group yet, e.g. on
+        // the first update, are moved into it
+        let tasks = intel_rdt::threads(&cmanager.get_all_pids()?)?;
+        intel_rdt::apply(container.id(), &intel_rdt, &tasks)?;
+        config.intel_rdt = Some(intel_rdt);
+        config
+            .save(&container.root)
+            .context("failed to save updated intel rdt config")?;
+    }
+
+    // the state is saved again, so that its modification time changes and
+    // other processes that cache the statistics of the container, e.g.
+    // youki metrics, collect them with the new limits
+    load_container(&root_path, &base.container_id)?
+        .save()
+        .context("failed to save the state of the updated container")?;
+
     Ok(())
 }
+
+fn parse_key_val(s: &str) -> Result<(String, String)> {
+    let (key, value) = s
+        .split_once('=')
+        .with_context(|| format!("invalid KEY=value: no `=` found in `{}`", s))?;
+    Ok((key.to_owned(), value.to_owned()))
+}
+
+/// Builds the resources from the flags, leaving the resources that are not
+/// given unset, so that they are not changed
+fn resources_from_flags(args: &liboci_cli::Update) -> Result<LinuxResources> {
+    let mut builder = LinuxResourcesBuilder::default();
+    if let Some(new_pids_limit) = args.pids_limit {
+        builder = builder.pids(LinuxPidsBuilder::default().limit(new_pids_limit).build()?);
+    }
+
+    if args.memory.is_some() || args.memory_reservation.is_some() || args.memory_swap.is_some() {
+        let mut memory = LinuxMemoryBuilder::default();
+        if let Some(limit) = args.memory {
+            memory = memory.limit(limit);
+        }
+        if let Some(reservation) = args.memory_reservation {
+            memory = memory.reservation(reservation);
+        }
+        if let Some(swap) = args.memory_swap {
+            memory = memory.swap(swap);
+        }
+        builder = builder.memory(memory.build()?);
+    }
+
+    let mut cpu = LinuxCpuBuilder::default();
+    let mut has_cpu = false;
+    if let Some(shares) = args.cpu_shares {
+        cpu = cpu.shares(shares);
+        has_cpu = true;
+    }
+    if let Some(period) = args.cpu_period {
+        cpu = cpu.period(period);
+        has_cpu = true;
+    }
+    if let Some(quota) = args.cpu_quota {
+        cpu = cpu.quota(quota);
+        has_cpu = true;
+    }
+    if let Some(realtime_period) = args.cpu_rt_period {
+        cpu = cpu.realtime_period(realtime_period);
+        has_cpu = true;
+    }
+    if let Some(realtime_runtime) = args.cpu_rt_runtime {
+        cpu = cpu.realtime_runtime(realtime_runtime);
+        has_cpu = true;
+    }
+    if let Some(cpus) = &args.cpuset_cpus {
+        cpu = cpu.cpus(cpus.clone());
+        has_cpu = true;
+    }
+    if let Some(mems) = &args.cpuset_mems {
+        cpu = cpu.mems(mems.clone());
+        has_cpu = true;
+    }
+    if has_cpu {
+        builder = builder.cpu(cpu.build()?);
+    }
+
+    if let Some(weight) = args.blkio_weight {
+        builder = builder.block_io(LinuxBlockIoBuilder::default().weight(weight).build()?);
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Merges the schemata into the intel rdt restrictions of previous updates
+/// and validates them
+fn updated_intel_rdt(
+    root_path: &Path,
+    container_id: &str,
+    l3_cache_schema: Option<String>,
+    mem_bw_schema: Option<String>,
+) -> Result<(Container, YoukiConfig, LinuxIntelRdt)> {
+    let container = load_container(root_path, container_id)?;
+    let config = container.spec()?;
+    let mut intel_rdt = config.intel_rdt.clone().unwrap_or_default();
+    if l3_cache_schema.is_some() {
+        intel_rdt.set_l3_cache_schema(l3_cache_schema);
+    }
+    if mem_bw_schema.is_some() {
+        intel_rdt.set_mem_bw_schema(mem_bw_schema);
+    }
+
+    intel_rdt::validate(&intel_rdt)?;
+    intel_rdt::find_resctrl_mount()?;
+    Ok((container, config, intel_rdt))
+}
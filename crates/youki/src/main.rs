@@ -19,7 +19,7 @@ use libcontainer::utils::create_dir_all_with_mode;
 use nix::sys::stat::Mode;
 use nix::unistd::getuid;
 
-use liboci_cli::{CommonCmd, GlobalOpts, StandardCmd};
+use liboci_cli::GlobalOpts;
 
 // High-level commandline option definition
 // This takes global options as well as individual commands as specified in [OCI runtime-spec](https://github.com/opencontainers/runtime-spec/blob/master/runtime.md)
@@ -38,15 +38,38 @@ struct Opts {
 // Also for a short information, check [runc commandline documentation](https://github.com/opencontainers/runc/blob/master/man/runc.8.md)
 #[derive(Parser, Debug)]
 enum SubCommand {
-    // Standard and common commands handled by the liboci_cli crate
-    #[clap(flatten)]
-    Standard(liboci_cli::StandardCmd),
-    #[clap(flatten)]
-    Common(liboci_cli::CommonCmd),
+    // Standard and common commands handled by the liboci_cli crate. Some of them
+    // are extended with the options of youki, so they are listed one by one
+    // instead of flattening liboci_cli::StandardCmd and liboci_cli::CommonCmd.
+    Create(commands::create::Create),
+    Start(liboci_cli::Start),
+    State(commands::state::State),
+    Kill(liboci_cli::Kill),
+    Delete(liboci_cli::Delete),
+    Checkpoint(liboci_cli::Checkpoint),
+    Events(commands::events::Events),
+    Exec(liboci_cli::Exec),
+    List(liboci_cli::List),
+    Pause(liboci_cli::Pause),
+    #[clap(setting = clap::AppSettings::AllowLeadingHyphen)]
+    Ps(liboci_cli::Ps),
+    Resume(liboci_cli::Resume),
+    Run(commands::run::Run),
+    Update(commands::update::Update),
+    Spec(commands::spec_json::SpecCmd),
 
     // Youki specific extensions
+    Restore(commands::restore::Restore),
+    Migrate(commands::migrate::Migrate),
     Info(info::Info),
+    Features(commands::features::Features),
+    Stats(commands::stats::Stats),
+    Gc(commands::gc::Gc),
     Completion(commands::completion::Completion),
+    CheckSpec(commands::check_spec::CheckSpec),
+    Attach(commands::attach::Attach),
+    #[cfg(feature = "metrics")]
+    Metrics(commands::metrics::Metrics),
 }
 
 /// output Youki version in Moby compatible format
@@ -98,31 +121,37 @@ fn main() -> Result<()> {
     let systemd_cgroup = opts.global.systemd_cgroup;
 
     match opts.subcmd {
-        SubCommand::Standard(cmd) => match cmd {
-            StandardCmd::Create(create) => {
-                commands::create::create(create, root_path, systemd_cgroup)
-            }
-            StandardCmd::Start(start) => commands::start::start(start, root_path),
-            StandardCmd::Kill(kill) => commands::kill::kill(kill, root_path),
-            StandardCmd::Delete(delete) => commands::delete::delete(delete, root_path),
-            StandardCmd::State(state) => commands::state::state(state, root_path),
-        },
-        SubCommand::Common(cmd) => match cmd {
-            CommonCmd::Events(events) => commands::events::events(events, root_path),
-            CommonCmd::Exec(exec) => commands::exec::exec(exec, root_path),
-            CommonCmd::List(list) => commands::list::list(list, root_path),
-            CommonCmd::Pause(pause) => commands::pause::pause(pause, root_path),
-            CommonCmd::Ps(ps) => commands::ps::ps(ps, root_path),
-            CommonCmd::Resume(resume) => commands::resume::resume(resume, root_path),
-            CommonCmd::Run(run) => commands::run::run(run, root_path, systemd_cgroup),
-            CommonCmd::Spec(spec) => commands::spec_json::spec(spec),
-            CommonCmd::Update(update) => commands::update::update(update, root_path),
-        },
-
+        SubCommand::Create(create) => commands::create::create(create, root_path, systemd_cgroup),
+        SubCommand::Start(start) => commands::start::start(start, root_path),
+        SubCommand::Kill(kill) => commands::kill::kill(kill, root_path),
+        SubCommand::Delete(delete) => commands::delete::delete(delete, root_path, systemd_cgroup),
+        SubCommand::State(state) => commands::state::state(state, root_path),
+        SubCommand::Checkpoint(checkpoint) => {
+            commands::checkpoint::checkpoint(checkpoint, root_path)
+        }
+        SubCommand::Events(events) => commands::events::events(events, root_path),
+        SubCommand::Exec(exec) => commands::exec::exec(exec, root_path),
+        SubCommand::List(list) => commands::list::list(list, root_path),
+        SubCommand::Pause(pause) => commands::pause::pause(pause, root_path),
+        SubCommand::Ps(ps) => commands::ps::ps(ps, root_path),
+        SubCommand::Resume(resume) => commands::resume::resume(resume, root_path),
+        SubCommand::Run(run) => commands::run::run(run, root_path, systemd_cgroup),
+        SubCommand::Spec(spec) => commands::spec_json::spec(spec),
+        SubCommand::Update(update) => commands::update::update(update, root_path),
+
+        SubCommand::Restore(restore) => commands::restore::restore(restore, root_path),
+        SubCommand::Migrate(migrate) => commands::migrate::migrate(migrate, root_path),
         SubCommand::Info(info) => commands::info::info(info),
+        SubCommand::Features(features) => commands::features::features(features),
+        SubCommand::Stats(stats) => commands::stats::stats(stats, root_path),
+        SubCommand::Gc(gc) => commands::gc::gc(gc, root_path, systemd_cgroup),
         SubCommand::Completion(completion) => {
             commands::completion::completion(completion, &mut app)
         }
+        SubCommand::CheckSpec(check_spec) => commands::check_spec::check_spec(check_spec),
+        SubCommand::Attach(attach) => commands::attach::attach(attach, root_path),
+        #[cfg(feature = "metrics")]
+        SubCommand::Metrics(metrics) => commands::metrics::metrics(metrics, root_path),
     }
 }
 